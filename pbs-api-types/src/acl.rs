@@ -1,5 +1,6 @@
 use std::str::FromStr;
 
+use anyhow::{bail, Error};
 use serde::de::{value, IntoDeserializer};
 use serde::{Deserialize, Serialize};
 
@@ -240,6 +241,140 @@ impl FromStr for Role {
     }
 }
 
+pub fn split_acl_path(path: &str) -> Vec<&str> {
+    let items = path.split('/');
+
+    let mut components = vec![];
+
+    for name in items {
+        if name.is_empty() {
+            continue;
+        }
+        components.push(name);
+    }
+
+    components
+}
+
+/// Check whether a given ACL `path` conforms to the expected schema.
+///
+/// This checks the number of components for the various sub-trees and rejects unknown top-level
+/// objects, since ACL entries for such paths could never match anything.
+pub fn check_acl_path(path: &str) -> Result<(), Error> {
+    let components = split_acl_path(path);
+
+    let components_len = components.len();
+
+    if components_len == 0 {
+        return Ok(());
+    }
+    match components[0] {
+        "access" => {
+            if components_len == 1 {
+                return Ok(());
+            }
+            match components[1] {
+                "acl" | "users" | "domains" => {
+                    if components_len == 2 {
+                        return Ok(());
+                    }
+                }
+                // /access/openid/{endpoint}
+                "openid" => {
+                    if components_len <= 3 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        "datastore" => {
+            // /datastore/{store}
+            if components_len <= 2 {
+                return Ok(());
+            }
+            if components_len > 2 && components_len <= 2 + crate::MAX_NAMESPACE_DEPTH {
+                return Ok(());
+            }
+        }
+        "remote" => {
+            // /remote/{remote}/{store}
+            if components_len <= 3 {
+                return Ok(());
+            }
+        }
+        "system" => {
+            if components_len == 1 {
+                return Ok(());
+            }
+            match components[1] {
+                "certificates" | "disks" | "log" | "status" | "tasks" | "time" => {
+                    if components_len == 2 {
+                        return Ok(());
+                    }
+                }
+                "services" => {
+                    // /system/services/{service}
+                    if components_len <= 3 {
+                        return Ok(());
+                    }
+                }
+                "network" => {
+                    if components_len == 2 {
+                        return Ok(());
+                    }
+                    match components[2] {
+                        "dns" => {
+                            if components_len == 3 {
+                                return Ok(());
+                            }
+                        }
+                        "interfaces" => {
+                            // /system/network/interfaces/{iface}
+                            if components_len <= 4 {
+                                return Ok(());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        "tape" => {
+            if components_len == 1 {
+                return Ok(());
+            }
+            match components[1] {
+                "device" => {
+                    // /tape/device/{name}
+                    if components_len <= 3 {
+                        return Ok(());
+                    }
+                }
+                "pool" => {
+                    // /tape/pool/{name}
+                    if components_len <= 3 {
+                        return Ok(());
+                    }
+                }
+                "job" => {
+                    // /tape/job/{id}
+                    if components_len <= 3 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    bail!("invalid acl path '{}'.", path);
+}
+
+// only checks the syntax, [check_acl_path] is applied when adding entries, so that entries with
+// paths which became invalid can still be listed and deleted
 pub const ACL_PATH_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&ACL_PATH_REGEX);
 
 pub const ACL_PATH_SCHEMA: Schema = StringSchema::new("Access control path.")
@@ -289,3 +424,60 @@ pub struct AclListItem {
     pub propagate: bool,
     pub roleid: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_acl_path() {
+        for path in [
+            "/",
+            "/access/acl",
+            "/access/openid/myrealm",
+            "/datastore/store1",
+            "/datastore/store1/ns1/ns2",
+            "/remote/remote1/store1",
+            "/system/network/interfaces/eth0",
+            "/tape/pool/pool1",
+        ] {
+            assert!(
+                check_acl_path(path).is_ok(),
+                "path {} should be valid",
+                path
+            );
+        }
+
+        for path in [
+            "/foo",
+            "/access/acl/foo",
+            "/remote/remote1/store1/foo",
+            "/system/network/foo",
+            "/tape/job/job1/foo",
+        ] {
+            assert!(
+                check_acl_path(path).is_err(),
+                "path {} should be invalid",
+                path
+            );
+        }
+
+        let too_deep = format!(
+            "/datastore/store1{}",
+            "/ns".repeat(crate::MAX_NAMESPACE_DEPTH + 1)
+        );
+        assert!(check_acl_path(&too_deep).is_err());
+    }
+
+    #[test]
+    fn test_acl_path_schema() {
+        // paths with an invalid structure must still be accepted, for example to delete them
+        assert!(ACL_PATH_SCHEMA.parse_simple_value("/foo/bar").is_ok());
+        assert!(ACL_PATH_SCHEMA
+            .parse_simple_value("/access/acl/foo")
+            .is_ok());
+
+        assert!(ACL_PATH_SCHEMA.parse_simple_value("/foo bar").is_err());
+        assert!(ACL_PATH_SCHEMA.parse_simple_value("foo").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::{api, Schema, StringSchema, Updater};
+
+use crate::{
+    BackupNamespace, Userid, BACKUP_GROUP_SCHEMA, BACKUP_NAMESPACE_SCHEMA, DATASTORE_SCHEMA,
+    JOB_ID_SCHEMA, PROXMOX_SAFE_ID_FORMAT, SINGLE_LINE_COMMENT_SCHEMA,
+};
+
+pub const ALERT_RULE_ID_SCHEMA: Schema = StringSchema::new("Alert rule ID.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(3)
+    .max_length(32)
+    .schema();
+
+#[api()]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Condition checked by an alert rule
+pub enum AlertRuleType {
+    /// The usage of a datastore exceeds 'threshold' percent.
+    DatastoreUsage,
+    /// A job failed 'threshold' times in a row.
+    JobFailures,
+    /// A backup group got no new snapshot for 'threshold' days.
+    BackupAge,
+}
+
+#[api()]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Job type checked by a job failures alert rule
+pub enum AlertJobType {
+    /// Sync jobs
+    #[serde(rename = "syncjob")]
+    SyncJob,
+    /// Verification jobs
+    #[serde(rename = "verificationjob")]
+    VerificationJob,
+    /// Prune jobs
+    #[serde(rename = "prunejob")]
+    PruneJob,
+    /// Tape backup jobs
+    #[serde(rename = "tape-backup-job")]
+    TapeBackupJob,
+    /// Garbage collection of a datastore
+    #[serde(rename = "garbage_collection")]
+    GarbageCollection,
+}
+
+impl AlertJobType {
+    /// All job types, used if a rule does not restrict the job type.
+    pub const ALL: [AlertJobType; 5] = [
+        AlertJobType::SyncJob,
+        AlertJobType::VerificationJob,
+        AlertJobType::PruneJob,
+        AlertJobType::TapeBackupJob,
+        AlertJobType::GarbageCollection,
+    ];
+
+    /// The job type as used for the job state files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertJobType::SyncJob => "syncjob",
+            AlertJobType::VerificationJob => "verificationjob",
+            AlertJobType::PruneJob => "prunejob",
+            AlertJobType::TapeBackupJob => "tape-backup-job",
+            AlertJobType::GarbageCollection => "garbage_collection",
+        }
+    }
+}
+
+#[api(
+    properties: {
+        id: {
+            schema: ALERT_RULE_ID_SCHEMA,
+        },
+        "type": {
+            type: AlertRuleType,
+        },
+        threshold: {
+            type: Integer,
+            minimum: 1,
+        },
+        store: {
+            optional: true,
+            schema: DATASTORE_SCHEMA,
+        },
+        ns: {
+            optional: true,
+            schema: BACKUP_NAMESPACE_SCHEMA,
+        },
+        group: {
+            optional: true,
+            schema: BACKUP_GROUP_SCHEMA,
+        },
+        "job-type": {
+            optional: true,
+            type: AlertJobType,
+        },
+        "job-id": {
+            optional: true,
+            schema: JOB_ID_SCHEMA,
+        },
+        "notify-user": {
+            optional: true,
+            type: Userid,
+        },
+        disable: {
+            optional: true,
+            default: false,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// Alert rule, checked periodically by the proxy daemon
+pub struct AlertRule {
+    #[updater(skip)]
+    pub id: String,
+    #[serde(rename = "type")]
+    #[updater(skip)]
+    pub ty: AlertRuleType,
+    /// Usage in percent, number of consecutive failures or age in days, depending on the type
+    pub threshold: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Datastore to check (required for datastore-usage and backup-age rules)
+    pub store: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    /// Namespace to check for backup-age rules, the datastore root if not set
+    pub ns: Option<BackupNamespace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Only check this backup group for backup-age rules
+    pub group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Only check jobs of this type for job-failures rules
+    pub job_type: Option<AlertJobType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Only check the job with this ID for job-failures rules
+    pub job_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// User to notify, root@pam if not set
+    pub notify_user: Option<Userid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
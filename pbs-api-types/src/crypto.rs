@@ -1,6 +1,6 @@
 use std::fmt::{self, Display};
 
-use anyhow::Error;
+use anyhow::{bail, Error};
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::api;
@@ -18,6 +18,51 @@ pub enum CryptMode {
     SignOnly,
 }
 
+#[api(default: "aes-256-gcm")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+/// AEAD cipher used to encrypt chunks and blobs.
+pub enum CryptCipher {
+    /// AES-256 in Galois/Counter Mode, fast on hardware with AES instructions.
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, fast on hardware without AES instructions.
+    #[serde(rename = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+}
+
+impl Default for CryptCipher {
+    fn default() -> Self {
+        CryptCipher::Aes256Gcm
+    }
+}
+
+impl CryptCipher {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CryptCipher::Aes256Gcm => "aes-256-gcm",
+            CryptCipher::ChaCha20Poly1305 => "chacha20-poly1305",
+        }
+    }
+}
+
+impl Display for CryptCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for CryptCipher {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "aes-256-gcm" => Ok(CryptCipher::Aes256Gcm),
+            "chacha20-poly1305" => Ok(CryptCipher::ChaCha20Poly1305),
+            _ => bail!("unknown cipher '{}'", s),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
 #[serde(transparent)]
 /// 32-byte fingerprint, usually calculated with SHA256.
@@ -10,9 +10,10 @@ use proxmox_schema::{
 };
 
 use crate::{
-    Authid, CryptMode, Fingerprint, MaintenanceMode, Userid, DATASTORE_NOTIFY_STRING_SCHEMA,
-    GC_SCHEDULE_SCHEMA, PROXMOX_SAFE_ID_FORMAT, PRUNE_SCHEDULE_SCHEMA, SHA256_HEX_REGEX,
-    SINGLE_LINE_COMMENT_SCHEMA, UPID,
+    Authid, CryptMode, Fingerprint, HumanByte, MaintenanceMode, StorageStatus, Userid, ZpoolHealth,
+    DATASTORE_NOTIFY_STRING_SCHEMA, GC_SCHEDULE_SCHEMA, HTTP_URL_SCHEMA, PASSWORD_FORMAT,
+    PROXMOX_SAFE_ID_FORMAT, PRUNE_SCHEDULE_SCHEMA, SHA256_HEX_REGEX, SINGLE_LINE_COMMENT_SCHEMA,
+    UPID,
 };
 
 const_regex! {
@@ -35,6 +36,8 @@ const_regex! {
     pub GROUP_OR_SNAPSHOT_PATH_REGEX = concat!(r"^", GROUP_OR_SNAPSHOT_PATH_REGEX_STR!(), r"$");
 
     pub DATASTORE_MAP_REGEX = concat!(r"(:?", PROXMOX_SAFE_ID_REGEX_STR!(), r"=)?", PROXMOX_SAFE_ID_REGEX_STR!());
+
+    pub SNAPSHOT_LABEL_REGEX = r"^[A-Za-z0-9_][A-Za-z0-9_.\-]*=[^\x00-\x1F\x7F]*$";
 }
 
 pub const CHUNK_DIGEST_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&SHA256_HEX_REGEX);
@@ -48,6 +51,12 @@ pub const BACKUP_ARCHIVE_NAME_SCHEMA: Schema = StringSchema::new("Backup archive
     .format(&PROXMOX_SAFE_ID_FORMAT)
     .schema();
 
+pub const SNAPSHOT_LABEL_SCHEMA: Schema =
+    StringSchema::new("Snapshot label in the form 'key=value', an empty value removes it.")
+        .format(&ApiStringFormat::Pattern(&SNAPSHOT_LABEL_REGEX))
+        .max_length(256)
+        .schema();
+
 pub const BACKUP_ID_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&BACKUP_ID_REGEX);
 pub const BACKUP_GROUP_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&GROUP_PATH_REGEX);
 pub const BACKUP_NAMESPACE_FORMAT: ApiStringFormat =
@@ -157,6 +166,13 @@ pub const PRUNE_SCHEMA_KEEP_YEARLY: Schema =
         .minimum(1)
         .schema();
 
+pub const TRASH_RETENTION_SCHEMA: Schema = IntegerSchema::new(
+    "Number of days removed snapshots are kept in the trash before garbage collection purges \
+    them. If not set, snapshots are removed immediately.",
+)
+.minimum(1)
+.schema();
+
 #[api]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -199,12 +215,95 @@ pub enum DatastoreFSyncLevel {
     Filesystem,
 }
 
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Compression of the chunks uploaded by clients.
+///
+/// There is no lz4 mode, the blob format only knows zstd compressed chunks, and older clients
+/// could not read lz4 compressed ones.
+pub enum ChunkCompressionMode {
+    /// Chunks are not compressed.
+    None,
+    /// Chunks are compressed with zstd, if that makes them smaller.
+    #[default]
+    Zstd,
+}
+
+/// Zstd level used if no other level is requested
+pub const DEFAULT_ZSTD_LEVEL: i32 = 1;
+
+pub const ZSTD_LEVEL_SCHEMA: Schema = IntegerSchema::new(
+    "Zstd compression level of uploaded chunks, higher levels compress better but slower.",
+)
+.minimum(1)
+.maximum(19)
+.default(DEFAULT_ZSTD_LEVEL as isize)
+.schema();
+
+pub const DEFAULT_GC_THREADS: usize = 1;
+
+pub const GC_THREADS_SCHEMA: Schema = IntegerSchema::new(
+    "Number of threads marking the chunks used by index files during garbage collection.",
+)
+.minimum(1)
+.maximum(64)
+.default(DEFAULT_GC_THREADS as isize)
+.schema();
+
+pub const DEFAULT_VERIFY_THREADS: usize = 4;
+
+pub const VERIFY_THREADS_SCHEMA: Schema =
+    IntegerSchema::new("Number of threads decoding and hashing chunks during verification.")
+        .minimum(1)
+        .maximum(64)
+        .default(DEFAULT_VERIFY_THREADS as isize)
+        .schema();
+
+pub const DEFAULT_VERIFY_READ_THREADS: usize = 1;
+
+pub const VERIFY_READ_THREADS_SCHEMA: Schema = IntegerSchema::new(
+    "Number of threads reading chunks during verification, the number of concurrent reads.",
+)
+.minimum(1)
+.maximum(64)
+.default(DEFAULT_VERIFY_READ_THREADS as isize)
+.schema();
+
 #[api(
     properties: {
         "chunk-order": {
             type: ChunkOrder,
             optional: true,
         },
+        "gc-threads": {
+            schema: GC_THREADS_SCHEMA,
+            optional: true,
+        },
+        "gc-incremental": {
+            optional: true,
+            default: false,
+        },
+        compression: {
+            type: ChunkCompressionMode,
+            optional: true,
+        },
+        "zstd-level": {
+            schema: ZSTD_LEVEL_SCHEMA,
+            optional: true,
+        },
+        "chunk-cache-size": {
+            type: HumanByte,
+            optional: true,
+        },
+        "verify-threads": {
+            schema: VERIFY_THREADS_SCHEMA,
+            optional: true,
+        },
+        "verify-read-threads": {
+            schema: VERIFY_READ_THREADS_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Default)]
@@ -214,6 +313,17 @@ pub struct DatastoreTuning {
     /// Iterate chunks in this order
     pub chunk_order: Option<ChunkOrder>,
     pub sync_level: Option<DatastoreFSyncLevel>,
+    pub gc_threads: Option<usize>,
+    /// Skip touching the chunks of index files which did not change since the last garbage
+    /// collection.
+    pub gc_incremental: Option<bool>,
+    pub compression: Option<ChunkCompressionMode>,
+    pub zstd_level: Option<i32>,
+    /// Memory used for caching decoded chunks read by the server, for example for file
+    /// downloads from archives. Disabled by default.
+    pub chunk_cache_size: Option<HumanByte>,
+    pub verify_threads: Option<usize>,
+    pub verify_read_threads: Option<usize>,
 }
 
 pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore tuning options")
@@ -222,6 +332,97 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
     ))
     .schema();
 
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Storage backend of the chunks of a datastore.
+pub enum DatastoreBackendType {
+    /// Chunks are stored in the `.chunks` directory below the datastore path.
+    #[default]
+    Filesystem,
+    /// Chunks are stored in a bucket of an S3 compatible object storage.
+    S3,
+}
+
+const_regex! {
+    pub S3_BUCKET_NAME_REGEX = r"^[a-z0-9][a-z0-9.\-]{1,61}[a-z0-9]$";
+}
+
+pub const S3_BUCKET_NAME_SCHEMA: Schema = StringSchema::new("Name of the S3 bucket.")
+    .format(&ApiStringFormat::Pattern(&S3_BUCKET_NAME_REGEX))
+    .min_length(3)
+    .max_length(63)
+    .schema();
+
+pub const S3_REGION_SCHEMA: Schema = StringSchema::new("Region of the S3 bucket.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .max_length(64)
+    .schema();
+
+pub const S3_ACCESS_KEY_SCHEMA: Schema = StringSchema::new("S3 access key id.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .max_length(128)
+    .schema();
+
+pub const S3_SECRET_KEY_SCHEMA: Schema = StringSchema::new("S3 secret access key.")
+    .format(&PASSWORD_FORMAT)
+    .min_length(1)
+    .max_length(256)
+    .schema();
+
+#[api(
+    properties: {
+        "s3-endpoint": {
+            optional: true,
+            schema: HTTP_URL_SCHEMA,
+        },
+        "s3-region": {
+            optional: true,
+            schema: S3_REGION_SCHEMA,
+        },
+        "s3-bucket": {
+            optional: true,
+            schema: S3_BUCKET_NAME_SCHEMA,
+        },
+        "s3-access-key": {
+            optional: true,
+            schema: S3_ACCESS_KEY_SCHEMA,
+        },
+        "s3-secret-key": {
+            optional: true,
+            schema: S3_SECRET_KEY_SCHEMA,
+        },
+        "s3-path-style": {
+            optional: true,
+            default: false,
+        },
+    },
+)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Updater)]
+#[serde(rename_all = "kebab-case")]
+/// Settings of the S3 object storage backend of a datastore.
+pub struct S3BackendConfig {
+    /// Endpoint of the object storage, for example `https://s3.amazonaws.com`. Cannot be changed
+    /// once the datastore is created.
+    #[updater(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3_region: Option<String>,
+    /// Name of the S3 bucket, cannot be changed once the datastore is created.
+    #[updater(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3_bucket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3_access_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3_secret_key: Option<String>,
+    /// Address the bucket as part of the path instead of the host name, as required by most
+    /// self-hosted object storages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3_path_style: Option<bool>,
+}
+
 #[api(
     properties: {
         name: {
@@ -267,6 +468,21 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
             format: &ApiStringFormat::PropertyString(&MaintenanceMode::API_SCHEMA),
             type: String,
         },
+        "trash-retention": {
+            optional: true,
+            schema: TRASH_RETENTION_SCHEMA,
+        },
+        quota: {
+            optional: true,
+            type: HumanByte,
+        },
+        backend: {
+            optional: true,
+            type: DatastoreBackendType,
+        },
+        s3: {
+            type: S3BackendConfig,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Updater)]
@@ -310,6 +526,22 @@ pub struct DataStoreConfig {
     /// Maintenance mode, type is either 'offline' or 'read-only', message should be enclosed in "
     #[serde(skip_serializing_if = "Option::is_none")]
     pub maintenance_mode: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash_retention: Option<u64>,
+
+    /// Maximum space the chunks of the datastore may use. New backups are rejected once it is
+    /// exceeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<HumanByte>,
+
+    /// Storage backend of the chunks, cannot be changed once the datastore is created.
+    #[updater(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<DatastoreBackendType>,
+
+    #[serde(flatten)]
+    pub s3: S3BackendConfig,
 }
 
 impl DataStoreConfig {
@@ -326,14 +558,17 @@ impl DataStoreConfig {
             notify: None,
             tuning: None,
             maintenance_mode: None,
+            trash_retention: None,
+            quota: None,
+            backend: None,
+            s3: Default::default(),
         }
     }
 
     pub fn get_maintenance_mode(&self) -> Option<MaintenanceMode> {
         self.maintenance_mode
             .as_ref()
-            .and_then(|str| MaintenanceMode::API_SCHEMA.parse_property_string(str).ok())
-            .and_then(|value| MaintenanceMode::deserialize(value).ok())
+            .and_then(|str| str.parse().ok())
     }
 }
 
@@ -383,7 +618,7 @@ pub struct BackupContent {
     /// Info if file is encrypted, signed, or neither.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub crypt_mode: Option<CryptMode>,
-    /// Archive size (from backup manifest).
+    /// Archive size (from backup manifest), or the file size of the client log.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub size: Option<u64>,
 }
@@ -843,6 +1078,15 @@ impl BackupGroup {
         use crate::GroupFilter;
 
         match filter {
+            GroupFilter::Group(pattern) if pattern.contains(&['*', '?'][..]) => {
+                match pattern.split_once('/') {
+                    Some((ty, id)) => {
+                        ty.parse::<BackupType>().ok() == Some(self.ty)
+                            && wildcard_match(id.as_bytes(), self.id.as_bytes())
+                    }
+                    None => false, // shouldn't happen if value is schema-checked
+                }
+            }
             GroupFilter::Group(backup_group) => {
                 match backup_group.parse::<BackupGroup>() {
                     Ok(group) => *self == group,
@@ -855,6 +1099,41 @@ impl BackupGroup {
     }
 }
 
+// matches `text` against a `pattern` containing the wildcards '*' (any sequence) and '?' (any
+// single character)
+//
+// On a mismatch, only the last '*' needs to be retried with one more character, so this runs in
+// O(pattern * text) time at worst.
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // position of the last '*' in the pattern, and of the text it was tried at
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == b'?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    // let the last '*' match one more character
+                    backtrack = Some((star, star_t + 1));
+                    p = star + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
 impl AsRef<BackupGroup> for BackupGroup {
     #[inline]
     fn as_ref(&self) -> &Self {
@@ -1071,6 +1350,13 @@ impl std::str::FromStr for BackupPart {
             type: Authid,
             optional: true,
         },
+        labels: {
+            type: Array,
+            optional: true,
+            items: {
+                schema: SNAPSHOT_LABEL_SCHEMA,
+            },
+        },
     },
 )]
 #[derive(Serialize, Deserialize)]
@@ -1099,6 +1385,106 @@ pub struct SnapshotListItem {
     /// Protection from prunes
     #[serde(default)]
     pub protected: bool,
+    /// The labels from manifest "labels", as 'key=value'
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+}
+
+#[api()]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Verification state used to filter snapshot lists.
+pub enum SnapshotVerifyFilter {
+    /// Last verification was successful
+    Ok,
+    /// Last verification reported one or more errors
+    Failed,
+    /// Snapshot was never verified
+    None,
+}
+
+#[api(
+    properties: {
+        encrypted: {
+            type: bool,
+            optional: true,
+        },
+        "verify-state": {
+            type: SnapshotVerifyFilter,
+            optional: true,
+        },
+        owner: {
+            type: Authid,
+            optional: true,
+        },
+        since: {
+            schema: BACKUP_TIME_SCHEMA,
+            optional: true,
+        },
+        until: {
+            schema: BACKUP_TIME_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Filters for listing backup snapshots.
+pub struct SnapshotListFilter {
+    /// Only list snapshots with (true) or without (false) encrypted archives.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_state: Option<SnapshotVerifyFilter>,
+    /// Only list snapshots of groups owned by this user or token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Authid>,
+    /// Only list snapshots taken at or after this time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<i64>,
+    /// Only list snapshots taken at or before this time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+}
+
+impl SnapshotListFilter {
+    /// Returns true if the group owner passes the owner filter.
+    pub fn matches_owner(&self, owner: &Authid) -> bool {
+        self.owner.as_ref().map_or(true, |filter| filter == owner)
+    }
+
+    /// Returns true if the backup time lies within the configured time range.
+    pub fn matches_time(&self, backup_time: i64) -> bool {
+        self.since.map_or(true, |since| backup_time >= since)
+            && self.until.map_or(true, |until| backup_time <= until)
+    }
+
+    /// Returns true if the snapshot passes the encryption and verification filters.
+    pub fn matches_item(&self, item: &SnapshotListItem) -> bool {
+        if let Some(encrypted) = self.encrypted {
+            let has_encrypted = item
+                .files
+                .iter()
+                .any(|file| file.crypt_mode == Some(CryptMode::Encrypt));
+            if has_encrypted != encrypted {
+                return false;
+            }
+        }
+
+        if let Some(verify_state) = self.verify_state {
+            let state = item.verification.as_ref().map(|verify| verify.state);
+            let matches = match verify_state {
+                SnapshotVerifyFilter::Ok => state == Some(VerifyState::Ok),
+                SnapshotVerifyFilter::Failed => state == Some(VerifyState::Failed),
+                SnapshotVerifyFilter::None => state.is_none(),
+            };
+            if !matches {
+                return false;
+            }
+        }
+
+        true
+    }
 }
 
 #[api(
@@ -1155,6 +1541,31 @@ pub struct NamespaceListItem {
     pub comment: Option<String>,
 }
 
+#[api(
+    properties: {
+        ns: { type: BackupNamespace },
+        backup: { type: BackupDir },
+        owner: {
+            type: Authid,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A removed backup snapshot in the trash of a datastore.
+pub struct TrashListItem {
+    /// The namespace the snapshot was removed from
+    pub ns: BackupNamespace,
+    #[serde(flatten)]
+    pub backup: BackupDir,
+    /// Time the snapshot was moved into the trash (seconds since the Epoch)
+    pub removed: i64,
+    /// The owner of the group the snapshot was removed from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Authid>,
+}
+
 #[api(
     properties: {
         "backup": { type: BackupDir },
@@ -1214,12 +1625,51 @@ pub struct TypeCounts {
     pub snapshots: u64,
 }
 
+#[api]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Phase of a garbage collection.
+pub enum GarbageCollectionPhase {
+    /// Mark the chunks used by index files.
+    Mark,
+    /// Remove unused chunks.
+    Sweep,
+}
+
+#[api(
+    properties: {
+        phase: {
+            type: GarbageCollectionPhase,
+        },
+        eta: {
+            optional: true,
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Progress of a running garbage collection.
+pub struct GarbageCollectionProgress {
+    pub phase: GarbageCollectionPhase,
+    /// Progress of the current phase in percent.
+    pub percentage: u8,
+    /// Number of processed index files (mark phase) or chunks (sweep phase).
+    pub processed: u64,
+    /// Estimated remaining time of the current phase in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta: Option<u64>,
+}
+
 #[api(
     properties: {
         "upid": {
             optional: true,
             type: UPID,
         },
+        progress: {
+            type: GarbageCollectionProgress,
+            optional: true,
+        },
     },
 )]
 #[derive(Clone, Default, Serialize, Deserialize)]
@@ -1247,6 +1697,13 @@ pub struct GarbageCollectionStatus {
     pub removed_bad: usize,
     /// Number of chunks still marked as .bad after garbage collection.
     pub still_bad: usize,
+    /// Bytes used on disk by the chunks still marked as .bad, not included in 'disk-bytes'.
+    #[serde(default)]
+    pub still_bad_bytes: u64,
+    /// Progress of the currently running garbage collection, the other values are the ones of
+    /// the last finished run then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<GarbageCollectionProgress>,
 }
 
 #[api(
@@ -1259,6 +1716,14 @@ pub struct GarbageCollectionStatus {
             type: Counts,
             optional: true,
         },
+        storage: {
+            type: StorageStatus,
+            optional: true,
+        },
+        zpool: {
+            type: ZpoolHealth,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize)]
@@ -1277,6 +1742,12 @@ pub struct DataStoreStatus {
     /// Group/Snapshot counts
     #[serde(skip_serializing_if = "Option::is_none")]
     pub counts: Option<Counts>,
+    /// Detailed storage usage, including inode usage and a short usage history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<StorageStatus>,
+    /// Health of the ZFS pool the datastore is located on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zpool: Option<ZpoolHealth>,
 }
 
 #[api(
@@ -1292,6 +1763,10 @@ pub struct DataStoreStatus {
                 description: "The usage of a time in the past. Either null or between 0.0 and 1.0.",
             }
         },
+        zpool: {
+            type: ZpoolHealth,
+            optional: true,
+        },
      },
 )]
 #[derive(Serialize, Deserialize)]
@@ -1326,6 +1801,31 @@ pub struct DataStoreStatusListItem {
     /// Status of last GC
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gc_status: Option<GarbageCollectionStatus>,
+    /// Health of the ZFS pool the datastore is located on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zpool: Option<ZpoolHealth>,
+}
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Capacity forecast of a Datastore
+pub struct DataStoreForecast {
+    pub store: String,
+    /// Estimation of the UNIX epoch when the storage will be full, see the datastore usage
+    /// status for details.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_full_date: Option<i64>,
+    /// Estimated number of days until the storage is full. Missing if not enough data points are
+    /// available yet or if the usage is not growing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_until_full: Option<f64>,
 }
 
 impl DataStoreStatusListItem {
@@ -1341,6 +1841,7 @@ impl DataStoreStatusListItem {
             estimated_full_date: None,
             error: err,
             gc_status: None,
+            zpool: None,
         }
     }
 }
@@ -1381,6 +1882,15 @@ pub const ADMIN_DATASTORE_LIST_NAMESPACE_RETURN_TYPE: ReturnType = ReturnType {
     .schema(),
 };
 
+pub const ADMIN_DATASTORE_LIST_TRASH_RETURN_TYPE: ReturnType = ReturnType {
+    optional: false,
+    schema: &ArraySchema::new(
+        "Returns the list of removed snapshots in the trash.",
+        &TrashListItem::API_SCHEMA,
+    )
+    .schema(),
+};
+
 pub const ADMIN_DATASTORE_PRUNE_RETURN_TYPE: ReturnType = ReturnType {
     optional: false,
     schema: &ArraySchema::new(
@@ -1454,3 +1964,67 @@ pub fn print_store_and_ns(store: &str, ns: &BackupNamespace) -> String {
         format!("datastore '{}', namespace '{}'", store, ns)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        for (pattern, text) in [
+            ("", ""),
+            ("*", ""),
+            ("*", "100"),
+            ("1*", "100"),
+            ("*0", "100"),
+            ("1?0", "100"),
+            ("1*0*", "1000"),
+            ("*a*b", "xaybzab"),
+            ("?*?", "ab"),
+            ("**", "abc"),
+        ] {
+            assert!(
+                wildcard_match(pattern.as_bytes(), text.as_bytes()),
+                "'{pattern}' should match '{text}'"
+            );
+        }
+
+        for (pattern, text) in [
+            ("", "1"),
+            ("1", ""),
+            ("?", ""),
+            ("1*", "200"),
+            ("*1", "100"),
+            ("1?", "100"),
+            ("*a*b", "xaybza"),
+            ("?*?", "a"),
+        ] {
+            assert!(
+                !wildcard_match(pattern.as_bytes(), text.as_bytes()),
+                "'{pattern}' should not match '{text}'"
+            );
+        }
+
+        // would take exponential time when backtracking over every '*'
+        let text = "a".repeat(100);
+        assert!(!wildcard_match(
+            "*a*a*a*a*a*a*a*a*a*a*b".as_bytes(),
+            text.as_bytes()
+        ));
+    }
+
+    #[test]
+    fn test_group_filter_wildcards() {
+        let group = BackupGroup::new(BackupType::Ct, "100");
+
+        for filter in ["group:ct/1*", "group:ct/?00", "group:ct/*", "group:ct/100"] {
+            let filter: crate::GroupFilter = filter.parse().unwrap();
+            assert!(group.matches(&filter), "{filter} should match {group}");
+        }
+
+        for filter in ["group:vm/1*", "group:ct/2*", "group:ct/1?", "group:ct/10"] {
+            let filter: crate::GroupFilter = filter.parse().unwrap();
+            assert!(!group.matches(&filter), "{filter} should not match {group}");
+        }
+    }
+}
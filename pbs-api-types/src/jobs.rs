@@ -7,10 +7,10 @@ use serde::{Deserialize, Serialize};
 use proxmox_schema::*;
 
 use crate::{
-    Authid, BackupNamespace, BackupType, RateLimitConfig, Userid, BACKUP_GROUP_SCHEMA,
+    Authid, BackupGroup, BackupNamespace, BackupType, RateLimitConfig, Userid, BACKUP_GROUP_SCHEMA,
     BACKUP_NAMESPACE_SCHEMA, DATASTORE_SCHEMA, DRIVE_NAME_SCHEMA, MEDIA_POOL_NAME_SCHEMA,
     NS_MAX_DEPTH_REDUCED_SCHEMA, PROXMOX_SAFE_ID_FORMAT, REMOTE_ID_SCHEMA,
-    SINGLE_LINE_COMMENT_SCHEMA,
+    SINGLE_LINE_COMMENT_SCHEMA, UPID_SCHEMA,
 };
 
 const_regex! {
@@ -19,6 +19,8 @@ const_regex! {
     pub VERIFICATION_JOB_WORKER_ID_REGEX = concat!(r"^(", PROXMOX_SAFE_ID_REGEX_STR!(), r"):");
     /// Regex for sync jobs 'REMOTE:REMOTE_DATASTORE:LOCAL_DATASTORE:(?:LOCAL_NS_ANCHOR:)ACTUAL_JOB_ID'
     pub SYNC_JOB_WORKER_ID_REGEX = concat!(r"^(", PROXMOX_SAFE_ID_REGEX_STR!(), r"):(", PROXMOX_SAFE_ID_REGEX_STR!(), r"):(", PROXMOX_SAFE_ID_REGEX_STR!(), r")(?::(", BACKUP_NS_RE!(), r"))?:");
+    /// Regex for group filters with wildcards ('*' and '?') in the backup ID, like 'ct/1*'
+    pub GROUP_FILTER_PATTERN_REGEX = concat!(r"^", BACKUP_TYPE_RE!(), r"/[A-Za-z0-9_*?][A-Za-z0-9._\-*?]*$");
 }
 
 pub const JOB_ID_SCHEMA: Schema = StringSchema::new("Job ID.")
@@ -101,6 +103,89 @@ pub struct JobScheduleStatus {
     pub last_run_endtime: Option<i64>,
 }
 
+#[api(
+    properties: {
+        upid: {
+            schema: UPID_SCHEMA,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Result of a single job run
+pub struct JobRunResult {
+    pub upid: String,
+    /// Start time of the run (epoch)
+    pub starttime: i64,
+    /// End time of the run (epoch)
+    pub endtime: i64,
+    /// Task state of the run
+    pub state: String,
+    /// Whether the run finished without errors
+    pub success: bool,
+    /// Number of bytes transferred, if tracked by the job type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transferred_bytes: Option<u64>,
+    /// Number of snapshots processed, if tracked by the job type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_count: Option<u64>,
+}
+
+#[api(
+    properties: {
+        runs: {
+            type: Array,
+            items: {
+                type: JobRunResult,
+            },
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Recorded runs of a job together with aggregated statistics
+pub struct JobHistory {
+    /// Number of recorded runs
+    pub total: u64,
+    /// Number of runs which finished without errors
+    pub successful: u64,
+    /// Number of failed runs
+    pub failed: u64,
+    /// Fraction of successful runs, between 0 and 1
+    pub success_rate: f64,
+    /// Average run duration in seconds
+    pub average_duration: f64,
+    /// The recorded runs, newest first
+    pub runs: Vec<JobRunResult>,
+}
+
+impl JobHistory {
+    /// Create the history summary from a list of runs, ordered newest first.
+    pub fn from_runs(runs: Vec<JobRunResult>) -> Self {
+        let total = runs.len() as u64;
+        let successful = runs.iter().filter(|run| run.success).count() as u64;
+        let duration: i64 = runs.iter().map(|run| run.endtime - run.starttime).sum();
+
+        let (success_rate, average_duration) = if total > 0 {
+            (
+                successful as f64 / total as f64,
+                duration as f64 / total as f64,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        Self {
+            total,
+            successful,
+            failed: total - successful,
+            success_rate,
+            average_duration,
+            runs,
+        }
+    }
+}
+
 #[api()]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -114,6 +199,20 @@ pub enum Notify {
     Error,
 }
 
+impl Notify {
+    /// Returns true if a notification should be sent for a job with the given outcome.
+    ///
+    /// A job specific setting takes precedence over the datastore wide `fallback`. If neither is
+    /// set, notifications are always sent.
+    pub fn should_notify(setting: Option<Notify>, fallback: Option<Notify>, success: bool) -> bool {
+        match setting.or(fallback).unwrap_or(Notify::Always) {
+            Notify::Never => false,
+            Notify::Always => true,
+            Notify::Error => !success,
+        }
+    }
+}
+
 #[api(
     properties: {
         gc: {
@@ -132,6 +231,10 @@ pub enum Notify {
             type: Notify,
             optional: true,
         },
+        tape: {
+            type: Notify,
+            optional: true,
+        },
     },
 )]
 #[derive(Debug, Serialize, Deserialize)]
@@ -145,6 +248,8 @@ pub struct DatastoreNotify {
     pub sync: Option<Notify>,
     /// Prune job setting
     pub prune: Option<Notify>,
+    /// Tape backup job setting
+    pub tape: Option<Notify>,
 }
 
 pub const DATASTORE_NOTIFY_STRING_SCHEMA: Schema =
@@ -197,6 +302,10 @@ pub const VERIFICATION_OUTDATED_AFTER_SCHEMA: Schema =
             optional: true,
             schema: crate::NS_MAX_DEPTH_SCHEMA,
         },
+        notify: {
+            type: Notify,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Updater)]
@@ -227,6 +336,9 @@ pub struct VerificationJobConfig {
     /// how deep the verify should go from the `ns` level downwards. Passing 0 verifies only the
     /// snapshots on the same level as the passed `ns`, or the datastore root if none.
     pub max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// when to send email notifications, overrides the datastore's verify setting
+    pub notify: Option<Notify>,
 }
 
 impl VerificationJobConfig {
@@ -258,6 +370,31 @@ pub struct VerificationJobStatus {
     pub status: JobScheduleStatus,
 }
 
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        schedule: {
+            schema: GC_SCHEDULE_SCHEMA,
+            optional: true,
+        },
+        status: {
+            type: JobScheduleStatus,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Status of the garbage collection job of a datastore
+pub struct GarbageCollectionJobStatus {
+    pub store: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(flatten)]
+    pub status: JobScheduleStatus,
+}
+
 #[api(
     properties: {
         store: {
@@ -300,6 +437,10 @@ pub struct VerificationJobStatus {
             schema: crate::NS_MAX_DEPTH_SCHEMA,
             optional: true,
         },
+        notify: {
+            type: Notify,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, Updater)]
@@ -324,6 +465,9 @@ pub struct TapeBackupJobSetup {
     pub ns: Option<BackupNamespace>,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub max_depth: Option<usize>,
+    /// When to send job email notifications, overrides the datastore's tape setting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify: Option<Notify>,
 }
 
 #[api(
@@ -386,7 +530,8 @@ pub struct TapeBackupJobStatus {
 pub enum GroupFilter {
     /// BackupGroup type - either `vm`, `ct`, or `host`.
     BackupType(BackupType),
-    /// Full identifier of BackupGroup, including type
+    /// Full identifier of BackupGroup, including type. The backup ID may contain the wildcards
+    /// `*` and `?`.
     Group(String),
     /// A regular expression matched against the full identifier of the BackupGroup
     Regex(Regex),
@@ -397,6 +542,13 @@ impl std::str::FromStr for GroupFilter {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.split_once(':') {
+            Some(("group", value)) if value.contains(&['*', '?'][..]) => {
+                if GROUP_FILTER_PATTERN_REGEX.is_match(value) {
+                    Ok(GroupFilter::Group(value.to_string()))
+                } else {
+                    Err(format_err!("invalid group pattern"))
+                }
+            }
             Some(("group", value)) => BACKUP_GROUP_SCHEMA.parse_simple_value(value).map(|_| GroupFilter::Group(value.to_string())),
             Some(("type", value)) => Ok(GroupFilter::BackupType(value.parse()?)),
             Some(("regex", value)) => Ok(GroupFilter::Regex(Regex::new(value)?)),
@@ -425,14 +577,47 @@ fn verify_group_filter(input: &str) -> Result<(), anyhow::Error> {
 }
 
 pub const GROUP_FILTER_SCHEMA: Schema = StringSchema::new(
-    "Group filter based on group identifier ('group:GROUP'), group type ('type:<vm|ct|host>'), or regex ('regex:RE').")
+    "Group filter based on group identifier ('group:GROUP', may contain '*' and '?' wildcards in the backup ID), group type ('type:<vm|ct|host>'), or regex ('regex:RE').")
     .format(&ApiStringFormat::VerifyFn(verify_group_filter))
     .type_text("<type:<vm|ct|host>|group:GROUP|regex:RE>")
     .schema();
 
+#[api()]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Direction of a sync job
+pub enum SyncDirection {
+    /// Pull the backups from the remote into the local datastore
+    Pull,
+    /// Push the backups of the local datastore to the remote
+    Push,
+}
+
+impl Default for SyncDirection {
+    fn default() -> Self {
+        SyncDirection::Pull
+    }
+}
+
 pub const GROUP_FILTER_LIST_SCHEMA: Schema =
     ArraySchema::new("List of group filters.", &GROUP_FILTER_SCHEMA).schema();
 
+/// Default number of chunks a sync job downloads in parallel
+pub const DEFAULT_SYNC_PARALLEL_DOWNLOADS: usize = 20;
+
+pub const SYNC_PARALLEL_DOWNLOADS_SCHEMA: Schema =
+    IntegerSchema::new("Number of chunks downloaded in parallel.")
+        .minimum(1)
+        .maximum(256)
+        .default(DEFAULT_SYNC_PARALLEL_DOWNLOADS as isize)
+        .schema();
+
+pub const SYNC_VERIFY_SCHEMA: Schema = BooleanSchema::new(
+    "Verify newly synced snapshots, and remove them again if the verification fails.",
+)
+.default(false)
+.schema();
+
 #[api(
     properties: {
         id: {
@@ -482,6 +667,22 @@ pub const GROUP_FILTER_LIST_SCHEMA: Schema =
             schema: GROUP_FILTER_LIST_SCHEMA,
             optional: true,
         },
+        "parallel-downloads": {
+            schema: SYNC_PARALLEL_DOWNLOADS_SCHEMA,
+            optional: true,
+        },
+        "sync-direction": {
+            type: SyncDirection,
+            optional: true,
+        },
+        "verify-after-sync": {
+            schema: SYNC_VERIFY_SCHEMA,
+            optional: true,
+        },
+        notify: {
+            type: Notify,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, Updater)]
@@ -511,6 +712,15 @@ pub struct SyncJobConfig {
     pub group_filter: Option<Vec<GroupFilter>>,
     #[serde(flatten)]
     pub limit: RateLimitConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_downloads: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_direction: Option<SyncDirection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_after_sync: Option<bool>,
+    /// When to send email notifications, overrides the datastore's sync setting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify: Option<Notify>,
 }
 
 impl SyncJobConfig {
@@ -615,6 +825,10 @@ impl KeepOptions {
             schema: NS_MAX_DEPTH_REDUCED_SCHEMA,
             optional: true,
         },
+        "group-filter": {
+            schema: GROUP_FILTER_LIST_SCHEMA,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Default, Updater)]
@@ -630,6 +844,10 @@ pub struct PruneJobOptions {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ns: Option<BackupNamespace>,
+
+    /// Only prune the backup groups matching one of these filters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_filter: Option<Vec<GroupFilter>>,
 }
 
 impl PruneJobOptions {
@@ -637,6 +855,14 @@ impl PruneJobOptions {
         self.keep.keeps_something()
     }
 
+    /// Returns whether the backup group `group` is selected by the group filters, if any.
+    pub fn matches_group(&self, group: &BackupGroup) -> bool {
+        match &self.group_filter {
+            Some(filters) => filters.iter().any(|filter| group.matches(filter)),
+            None => true,
+        }
+    }
+
     pub fn acl_path<'a>(&'a self, store: &'a str) -> Vec<&'a str> {
         match &self.ns {
             Some(ns) => ns.acl_path(store),
@@ -669,6 +895,10 @@ impl PruneJobOptions {
         options: {
             type: PruneJobOptions,
         },
+        notify: {
+            type: Notify,
+            optional: true,
+        },
     },
 )]
 #[derive(Deserialize, Serialize, Updater)]
@@ -693,6 +923,10 @@ pub struct PruneJobConfig {
 
     #[serde(flatten)]
     pub options: PruneJobOptions,
+
+    /// When to send email notifications, overrides the datastore's prune setting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify: Option<Notify>,
 }
 
 impl PruneJobConfig {
@@ -724,3 +958,140 @@ pub struct PruneJobStatus {
     #[serde(flatten)]
     pub status: JobScheduleStatus,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_filter_pattern_regex() {
+        for pattern in [
+            "ct/1*",
+            "vm/?00",
+            "host/*",
+            "host/web-*.example.com",
+            "vm/*_?",
+        ] {
+            assert!(
+                GROUP_FILTER_PATTERN_REGEX.is_match(pattern),
+                "'{pattern}' should be a valid pattern"
+            );
+        }
+
+        for pattern in [
+            "ct/", "*/100", "foo/1*", "ct/-*", "ct/.*", "ct/1*/2", "ct/1 *", "ct1*",
+        ] {
+            assert!(
+                !GROUP_FILTER_PATTERN_REGEX.is_match(pattern),
+                "'{pattern}' should be an invalid pattern"
+            );
+        }
+    }
+
+    #[test]
+    fn test_group_filter_parse() {
+        assert!(matches!(
+            "group:ct/1*".parse::<GroupFilter>(),
+            Ok(GroupFilter::Group(pattern)) if pattern == "ct/1*"
+        ));
+        assert_eq!(
+            "group:ct/1*".parse::<GroupFilter>().unwrap().to_string(),
+            "group:ct/1*"
+        );
+
+        assert!("group:foo/1*".parse::<GroupFilter>().is_err());
+        assert!("group:ct/1*/2".parse::<GroupFilter>().is_err());
+        // wildcards are only allowed in the backup ID
+        assert!("group:*/100".parse::<GroupFilter>().is_err());
+    }
+
+    #[test]
+    fn test_job_history_from_runs() {
+        let run = |starttime: i64, duration: i64, success: bool| JobRunResult {
+            upid: String::new(),
+            starttime,
+            endtime: starttime + duration,
+            state: String::new(),
+            success,
+            transferred_bytes: None,
+            snapshot_count: None,
+        };
+
+        let history = JobHistory::from_runs(vec![
+            run(300, 20, true),
+            run(200, 40, false),
+            run(100, 30, true),
+            run(0, 10, true),
+        ]);
+        assert_eq!(history.total, 4);
+        assert_eq!(history.successful, 3);
+        assert_eq!(history.failed, 1);
+        assert_eq!(history.success_rate, 0.75);
+        assert_eq!(history.average_duration, 25.0);
+        assert_eq!(history.runs[0].starttime, 300);
+
+        let history = JobHistory::from_runs(Vec::new());
+        assert_eq!(history.total, 0);
+        assert_eq!(history.success_rate, 0.0);
+        assert_eq!(history.average_duration, 0.0);
+    }
+
+    #[test]
+    fn test_should_notify() {
+        use Notify::{Always, Never};
+
+        // (job setting, datastore setting, notify on success, notify on failure)
+        for (setting, fallback, success, failure) in [
+            // nothing configured, always notify
+            (None, None, true, true),
+            // the datastore setting applies if the job has none
+            (None, Some(Never), false, false),
+            (None, Some(Notify::Error), false, true),
+            (None, Some(Always), true, true),
+            // the job setting overrides the datastore setting
+            (Some(Always), Some(Never), true, true),
+            (Some(Never), Some(Always), false, false),
+            (Some(Notify::Error), Some(Always), false, true),
+            (Some(Notify::Error), None, false, true),
+        ] {
+            assert_eq!(Notify::should_notify(setting, fallback, true), success);
+            assert_eq!(Notify::should_notify(setting, fallback, false), failure);
+        }
+    }
+
+    #[test]
+    fn test_prune_job_group_filter() {
+        let group = |group: &str| group.parse::<BackupGroup>().unwrap();
+        let options = |filters: Option<&[&str]>| PruneJobOptions {
+            group_filter: filters.map(|filters| {
+                filters
+                    .iter()
+                    .map(|filter| filter.parse().unwrap())
+                    .collect()
+            }),
+            ..Default::default()
+        };
+
+        // without filters, every group is pruned
+        let unfiltered = options(None);
+        assert!(unfiltered.matches_group(&group("vm/100")));
+        assert!(unfiltered.matches_group(&group("host/foo")));
+
+        // an empty list matches nothing
+        assert!(!options(Some(&[])).matches_group(&group("vm/100")));
+
+        // the filters are combined, a group matching any of them is pruned
+        let filtered = options(Some(&["type:ct", "group:vm/1*", "regex:^host/db"]));
+        assert!(filtered.matches_group(&group("ct/200")));
+        assert!(filtered.matches_group(&group("vm/100")));
+        assert!(filtered.matches_group(&group("vm/1234")));
+        assert!(filtered.matches_group(&group("host/db-main")));
+        assert!(!filtered.matches_group(&group("vm/200")));
+        assert!(!filtered.matches_group(&group("host/web")));
+
+        let exact = options(Some(&["group:vm/100"]));
+        assert!(exact.matches_group(&group("vm/100")));
+        assert!(!exact.matches_group(&group("vm/1000")));
+        assert!(!exact.matches_group(&group("ct/100")));
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use proxmox_schema::api;
+use proxmox_schema::{api, IntegerSchema, Schema};
 
 use crate::CERT_FINGERPRINT_SHA256_SCHEMA;
 
@@ -15,6 +15,8 @@ pub enum Kdf {
     Scrypt,
     /// Encrtypt the Key with a password using PBKDF2
     PBKDF2,
+    /// Encrypt the key with a password using Argon2id.
+    Argon2id,
 }
 
 impl Default for Kdf {
@@ -24,6 +26,20 @@ impl Default for Kdf {
     }
 }
 
+pub const ARGON2_MEMORY_SCHEMA: Schema =
+    IntegerSchema::new("Memory used by the Argon2id key derivation (in KiB).")
+        .minimum(8 * 1024)
+        .maximum(4 * 1024 * 1024)
+        .default(64 * 1024)
+        .schema();
+
+pub const ARGON2_ITERATIONS_SCHEMA: Schema =
+    IntegerSchema::new("Number of iterations of the Argon2id key derivation.")
+        .minimum(1)
+        .maximum(64)
+        .default(3)
+        .schema();
+
 #[api(
     properties: {
         kdf: {
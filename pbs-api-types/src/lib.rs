@@ -6,7 +6,8 @@ pub mod common_regex;
 pub mod percent_encoding;
 
 use proxmox_schema::{
-    api, const_regex, ApiStringFormat, ApiType, ArraySchema, ReturnType, Schema, StringSchema,
+    api, const_regex, ApiStringFormat, ApiType, ArraySchema, IntegerSchema, ReturnType, Schema,
+    StringSchema,
 };
 use proxmox_time::parse_daily_duration;
 
@@ -67,6 +68,9 @@ macro_rules! GROUP_OR_SNAPSHOT_PATH_REGEX_STR {
 mod acl;
 pub use acl::*;
 
+mod alerts;
+pub use alerts::*;
+
 mod datastore;
 pub use datastore::*;
 
@@ -77,7 +81,7 @@ mod jobs;
 pub use jobs::*;
 
 mod key_derivation;
-pub use key_derivation::{Kdf, KeyInfo};
+pub use key_derivation::{Kdf, KeyInfo, ARGON2_ITERATIONS_SCHEMA, ARGON2_MEMORY_SCHEMA};
 
 mod maintenance;
 pub use maintenance::*;
@@ -101,7 +105,7 @@ pub use user::*;
 pub use proxmox_schema::upid::*;
 
 mod crypto;
-pub use crypto::{bytes_as_fingerprint, CryptMode, Fingerprint};
+pub use crypto::{bytes_as_fingerprint, CryptCipher, CryptMode, Fingerprint};
 
 pub mod file_restore;
 
@@ -123,6 +127,9 @@ pub use zfs::*;
 mod metrics;
 pub use metrics::*;
 
+mod port;
+pub use port::*;
+
 #[rustfmt::skip]
 #[macro_use]
 mod local_macros {
@@ -135,6 +142,7 @@ mod local_macros {
         () => (concat!(r"(?:(?:", DNS_ALIAS_LABEL!() , r"\.)*", DNS_ALIAS_LABEL!(), ")"))
     }
     macro_rules! PORT_REGEX_STR { () => (r"(?:[0-9]{1,4}|[1-5][0-9]{4}|6[0-4][0-9]{3}|65[0-4][0-9]{2}|655[0-2][0-9]|6553[0-5])") }
+    macro_rules! PORT_RANGE_REGEX_STR { () => (concat!(PORT_REGEX_STR!(), "(?:-", PORT_REGEX_STR!(), ")?")) }
 }
 
 const_regex! {
@@ -149,6 +157,8 @@ const_regex! {
     pub DNS_ALIAS_REGEX =  concat!(r"^", DNS_ALIAS_NAME!(), r"$");
     pub DNS_NAME_OR_IP_REGEX = concat!(r"^(?:", DNS_NAME!(), "|",  IPRE!(), r")$");
     pub HOST_PORT_REGEX = concat!(r"^(?:", DNS_NAME!(), "|", IPRE_BRACKET!(), "):", PORT_REGEX_STR!() ,"$");
+    pub PORT_REGEX = concat!(r"^", PORT_REGEX_STR!(), r"$");
+    pub PORT_RANGE_REGEX = concat!(r"^", PORT_RANGE_REGEX_STR!(), r"$");
     pub HTTP_URL_REGEX = concat!(r"^https?://(?:(?:(?:", DNS_NAME!(), "|", IPRE_BRACKET!(), ")(?::", PORT_REGEX_STR!() ,")?)|", IPV6RE!(),")(?:/[^\x00-\x1F\x7F]*)?$");
 
     pub SHA256_HEX_REGEX = r"^[a-f0-9]{64}$"; // fixme: define in common_regex ?
@@ -182,7 +192,7 @@ const_regex! {
         USER_ID_REGEX_STR!(), "|", APITOKEN_ID_REGEX_STR!(),
         ")@)?(",
         DNS_NAME!(), "|",  IPRE_BRACKET!(),
-        "):)?(?:([0-9]{1,5}):)?(", PROXMOX_SAFE_ID_REGEX_STR!(), r")$"
+        "):)?(?:(", PORT_REGEX_STR!(), "):)?(", PROXMOX_SAFE_ID_REGEX_STR!(), r")$"
     );
 
     pub BLOCKDEVICE_NAME_REGEX = r"^(:?(:?h|s|x?v)d[a-z]+)|(:?nvme\d+n\d+)$";
@@ -352,8 +362,20 @@ pub const BACKUP_REPO_URL: ApiStringFormat = ApiStringFormat::Pattern(&BACKUP_RE
 
 // Complex type definitions
 
-#[api()]
+#[api(
+    properties: {
+        history: {
+            type: Array,
+            optional: true,
+            items: {
+                type: Number,
+                description: "The usage of a time in the past. Either null or between 0.0 and 1.0.",
+            }
+        },
+    },
+)]
 #[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 /// Storage space usage information.
 pub struct StorageStatus {
     /// Total space (bytes).
@@ -362,6 +384,27 @@ pub struct StorageStatus {
     pub used: u64,
     /// Available space (bytes).
     pub avail: u64,
+    /// Total number of inodes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_inodes: Option<u64>,
+    /// Number of used inodes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_inodes: Option<u64>,
+    /// A short list of past space usages (last Day).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<Option<f64>>>,
+    /// History start time (epoch)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_start: Option<u64>,
+    /// History resolution (seconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_delta: Option<u64>,
+    /// Configured quota of the datastore (bytes).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+    /// Space counted against the quota of the datastore (bytes).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_used: Option<u64>,
 }
 
 pub const PASSWORD_HINT_SCHEMA: Schema = StringSchema::new("Password hint.")
@@ -411,6 +454,21 @@ pub enum NodePowerCommand {
     Shutdown,
 }
 
+#[api(default: "fail")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// How to handle device nodes which cannot be created due to missing privileges, for example
+/// when restoring as unprivileged user or inside an unprivileged container.
+pub enum DeviceNodeFallback {
+    /// Fail like for any other error.
+    #[default]
+    Fail,
+    /// Skip the device node with a warning.
+    Skip,
+    /// Create an empty regular file with the device node's metadata instead.
+    EmptyFile,
+}
+
 #[api()]
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -462,13 +520,14 @@ pub const NODE_TASKS_LIST_TASKS_RETURN_TYPE: ReturnType = ReturnType {
 };
 
 #[api()]
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 /// RRD consolidation mode
 pub enum RRDMode {
     /// Maximum
     Max,
     /// Average
+    #[default]
     Average,
 }
 
@@ -491,6 +550,16 @@ pub enum RRDTimeFrame {
     Decade,
 }
 
+pub const RRD_START_TIME_SCHEMA: Schema =
+    IntegerSchema::new("Start of a custom RRD time range (epoch), used instead of 'timeframe'.")
+        .minimum(0)
+        .schema();
+
+pub const RRD_END_TIME_SCHEMA: Schema =
+    IntegerSchema::new("End of a custom RRD time range (epoch), defaults to the current time.")
+        .minimum(0)
+        .schema();
+
 #[api]
 #[derive(Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
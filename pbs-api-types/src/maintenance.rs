@@ -1,8 +1,9 @@
 use anyhow::{bail, Error};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::fmt;
 
-use proxmox_schema::{api, const_regex, ApiStringFormat, Schema, StringSchema};
+use proxmox_schema::{api, const_regex, ApiStringFormat, ApiType, Schema, StringSchema};
 
 const_regex! {
     pub MAINTENANCE_MESSAGE_REGEX = r"^[[:^cntrl:]]*$";
@@ -33,7 +34,7 @@ pub enum Operation {
 }
 
 #[api]
-#[derive(Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 /// Maintenance type.
 pub enum MaintenanceType {
@@ -48,6 +49,9 @@ pub enum MaintenanceType {
     Offline,
 }
 
+serde_plain::derive_display_from_serialize!(MaintenanceType);
+serde_plain::derive_fromstr_from_deserialize!(MaintenanceType);
+
 #[api(
     properties: {
         type: {
@@ -60,7 +64,7 @@ pub enum MaintenanceType {
     },
     default_key: "type",
 )]
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 /// Maintenance mode
 pub struct MaintenanceMode {
     /// Type of maintenance ("read-only" or "offline").
@@ -73,20 +77,102 @@ pub struct MaintenanceMode {
 }
 
 impl MaintenanceMode {
-    pub fn check(&self, operation: Option<Operation>) -> Result<(), Error> {
-        let message = percent_encoding::percent_decode_str(self.message.as_deref().unwrap_or(""))
-            .decode_utf8()
-            .unwrap_or(Cow::Borrowed(""));
+    /// Create a new maintenance mode, the message gets percent-encoded for storage.
+    pub fn new(ty: MaintenanceType, message: Option<&str>) -> Self {
+        let message = message.map(|msg| {
+            percent_encoding::utf8_percent_encode(msg, percent_encoding::NON_ALPHANUMERIC)
+                .to_string()
+        });
+        Self { ty, message }
+    }
+
+    /// The type of this maintenance mode.
+    pub fn maintenance_type(&self) -> MaintenanceType {
+        self.ty
+    }
+
+    /// The decoded reason for the maintenance, if any.
+    pub fn message(&self) -> Option<Cow<str>> {
+        self.message.as_deref().map(|msg| {
+            percent_encoding::percent_decode_str(msg)
+                .decode_utf8()
+                .unwrap_or(Cow::Borrowed(""))
+        })
+    }
+
+    /// Returns true if `operation` is allowed in this maintenance mode.
+    pub fn is_allowed(&self, operation: Option<Operation>) -> bool {
+        match (self.ty, operation) {
+            (_, Some(Operation::Lookup)) => true,
+            (MaintenanceType::Offline, _) => false,
+            (MaintenanceType::ReadOnly, Some(Operation::Write)) => false,
+            (MaintenanceType::ReadOnly, _) => true,
+        }
+    }
 
-        if let Some(Operation::Lookup) = operation {
+    pub fn check(&self, operation: Option<Operation>) -> Result<(), Error> {
+        if self.is_allowed(operation) {
             return Ok(());
-        } else if self.ty == MaintenanceType::Offline {
-            bail!("offline maintenance mode: {}", message);
-        } else if self.ty == MaintenanceType::ReadOnly {
-            if let Some(Operation::Write) = operation {
-                bail!("read-only maintenance mode: {}", message);
-            }
+        }
+
+        let message = self.message().unwrap_or(Cow::Borrowed(""));
+
+        match self.ty {
+            MaintenanceType::Offline => bail!("offline maintenance mode: {}", message),
+            MaintenanceType::ReadOnly => bail!("read-only maintenance mode: {}", message),
+        }
+    }
+}
+
+impl std::str::FromStr for MaintenanceMode {
+    type Err = Error;
+
+    /// Parse a maintenance mode property string, e.g. `type=read-only,message=...`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = Self::API_SCHEMA.parse_property_string(s)?;
+        Ok(Self::deserialize(value)?)
+    }
+}
+
+impl fmt::Display for MaintenanceMode {
+    /// Format as property string, the inverse of `from_str`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "type={}", self.ty)?;
+        if let Some(message) = &self.message {
+            write!(f, ",message={}", message)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_maintenance_check() {
+        let mode = MaintenanceMode::new(MaintenanceType::Offline, Some("disk swap"));
+
+        assert!(mode.check(Some(Operation::Read)).is_err());
+        assert!(mode.check(Some(Operation::Write)).is_err());
+        assert!(mode.check(None).is_err());
+        // lookups only touch in-memory state and stay possible
+        assert!(mode.check(Some(Operation::Lookup)).is_ok());
+
+        let err = mode.check(Some(Operation::Read)).unwrap_err().to_string();
+        assert_eq!(err, "offline maintenance mode: disk swap");
+    }
+
+    #[test]
+    fn test_read_only_maintenance_check() {
+        let mode = MaintenanceMode::new(MaintenanceType::ReadOnly, None);
+
+        assert!(mode.check(Some(Operation::Read)).is_ok());
+        assert!(mode.check(Some(Operation::Write)).is_err());
+        assert!(mode.check(Some(Operation::Lookup)).is_ok());
+        assert!(mode.check(None).is_ok());
+
+        let err = mode.check(Some(Operation::Write)).unwrap_err().to_string();
+        assert_eq!(err, "read-only maintenance mode: ");
+    }
+}
@@ -0,0 +1,113 @@
+//! Port and port range types used by network related configuration.
+
+use std::fmt;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_schema::{ApiStringFormat, Schema, StringSchema};
+
+use crate::{PORT_RANGE_REGEX, PORT_REGEX};
+
+/// Parse a single port number.
+pub fn parse_port(s: &str) -> Result<u16, Error> {
+    if !PORT_REGEX.is_match(s) {
+        bail!("invalid port '{}'", s);
+    }
+    s.parse::<u16>()
+        .map_err(|err| format_err!("invalid port '{}' - {}", s, err))
+}
+
+/// An inclusive range of ports, e.g. `8000-8010`, or a single port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    /// Returns true if `port` lies inside this range.
+    pub fn contains(&self, port: u16) -> bool {
+        self.start <= port && port <= self.end
+    }
+}
+
+impl From<u16> for PortRange {
+    fn from(port: u16) -> Self {
+        Self {
+            start: port,
+            end: port,
+        }
+    }
+}
+
+impl std::str::FromStr for PortRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !PORT_RANGE_REGEX.is_match(s) {
+            bail!("invalid port range '{}'", s);
+        }
+
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (parse_port(start)?, parse_port(end)?),
+            None => {
+                let port = parse_port(s)?;
+                (port, port)
+            }
+        };
+
+        if start > end {
+            bail!("invalid port range '{}' - start is larger than end", s);
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+serde_plain::derive_deserialize_from_fromstr!(PortRange, "valid port or port range");
+serde_plain::derive_serialize_from_display!(PortRange);
+
+pub const PORT_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&PORT_REGEX);
+
+pub const PORT_RANGE_FORMAT: ApiStringFormat =
+    ApiStringFormat::VerifyFn(|s| s.parse::<PortRange>().map(drop));
+
+pub const PORT_SCHEMA: Schema = StringSchema::new("Port number.")
+    .format(&PORT_FORMAT)
+    .schema();
+
+pub const PORT_RANGE_SCHEMA: Schema =
+    StringSchema::new("Port or inclusive port range (for example '8000-8010').")
+        .format(&PORT_RANGE_FORMAT)
+        .schema();
+
+#[test]
+fn test_port_range() {
+    assert_eq!("8007".parse::<PortRange>().unwrap(), PortRange::from(8007));
+    assert_eq!(
+        "1000-2000".parse::<PortRange>().unwrap(),
+        PortRange {
+            start: 1000,
+            end: 2000
+        }
+    );
+    assert_eq!(
+        "0-65535".parse::<PortRange>().unwrap().to_string(),
+        "0-65535"
+    );
+
+    assert!("65536".parse::<PortRange>().is_err());
+    assert!("2000-1000".parse::<PortRange>().is_err());
+    assert!("1000-".parse::<PortRange>().is_err());
+    assert!("-1000".parse::<PortRange>().is_err());
+}
@@ -23,6 +23,26 @@ pub const REMOTE_ID_SCHEMA: Schema = StringSchema::new("Remote ID.")
     .max_length(32)
     .schema();
 
+pub const REMOTE_CA_CERT_SCHEMA: Schema = StringSchema::new(
+    "PEM encoded CA certificate(s) used to verify the remote's certificate, in addition to the \
+     system trust store.",
+)
+.min_length(1)
+.max_length(64 * 1024)
+.schema();
+
+#[api()]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Minimum TLS protocol version.
+pub enum TlsVersion {
+    /// TLS 1.2
+    #[serde(rename = "tls1.2")]
+    Tls1_2,
+    /// TLS 1.3
+    #[serde(rename = "tls1.3")]
+    Tls1_3,
+}
+
 #[api(
     properties: {
         comment: {
@@ -44,6 +64,15 @@ pub const REMOTE_ID_SCHEMA: Schema = StringSchema::new("Remote ID.")
             optional: true,
             schema: CERT_FINGERPRINT_SHA256_SCHEMA,
         },
+        "verify-hostname": {
+            type: bool,
+            optional: true,
+            default: true,
+        },
+        "min-tls-version": {
+            optional: true,
+            type: TlsVersion,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Updater)]
@@ -58,6 +87,42 @@ pub struct RemoteConfig {
     pub auth_id: Authid,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fingerprint: Option<String>,
+    /// Verify that the certificate of the remote matches its host name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_hostname: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_tls_version: Option<TlsVersion>,
+}
+
+#[api(
+    properties: {
+        fingerprint: {
+            optional: true,
+            schema: CERT_FINGERPRINT_SHA256_SCHEMA,
+        },
+        datastores: {
+            type: Array,
+            items: {
+                type: DataStoreListItem,
+            },
+        },
+    },
+)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Result of a successful connection test to a remote.
+pub struct RemoteTestResult {
+    /// Package version of the remote.
+    pub version: String,
+    /// Package release of the remote.
+    pub release: String,
+    /// Repository ID of the remote.
+    pub repoid: String,
+    /// The verified certificate fingerprint, if the certificate is not trusted otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// The datastores visible with the configured credentials.
+    pub datastores: Vec<DataStoreListItem>,
 }
 
 #[api(
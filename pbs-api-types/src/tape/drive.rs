@@ -6,7 +6,10 @@ use serde::{Deserialize, Serialize};
 
 use proxmox_schema::{api, IntegerSchema, Schema, StringSchema, Updater};
 
-use crate::{OptionalDeviceIdentification, CHANGER_NAME_SCHEMA, PROXMOX_SAFE_ID_FORMAT};
+use crate::{
+    OptionalDeviceIdentification, CHANGER_NAME_SCHEMA, MEDIA_LABEL_SCHEMA, MEDIA_POOL_NAME_SCHEMA,
+    PROXMOX_SAFE_ID_FORMAT,
+};
 
 pub const DRIVE_NAME_SCHEMA: Schema = StringSchema::new("Drive Identifier.")
     .format(&PROXMOX_SAFE_ID_FORMAT)
@@ -155,12 +158,39 @@ impl TryFrom<u8> for TapeDensity {
     }
 }
 
+#[api()]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Hardware encryption mode of a drive
+pub enum DriveEncryptionMode {
+    /// Encryption and decryption disabled
+    Off,
+    /// Encrypt written data, decrypt encrypted data only
+    On,
+    /// Encrypt written data, read encrypted and unencrypted data
+    Mixed,
+    /// Encrypt written data, return encrypted data without decrypting
+    RawRead,
+}
+
 #[api(
     properties: {
         density: {
             type: TapeDensity,
             optional: true,
         },
+        encryption: {
+            type: DriveEncryptionMode,
+            optional: true,
+        },
+        "label-text": {
+            schema: MEDIA_LABEL_SCHEMA,
+            optional: true,
+        },
+        pool: {
+            schema: MEDIA_POOL_NAME_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize)]
@@ -187,6 +217,15 @@ pub struct LtoDriveAndMediaStatus {
     /// Media is write protected
     #[serde(skip_serializing_if = "Option::is_none")]
     pub write_protect: Option<bool>,
+    /// Hardware encryption mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<DriveEncryptionMode>,
+    /// Label text (barcode) of the loaded media
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_text: Option<String>,
+    /// Media pool the loaded media is assigned to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
     /// Tape Alert Flags
     #[serde(skip_serializing_if = "Option::is_none")]
     pub alert_flags: Option<String>,
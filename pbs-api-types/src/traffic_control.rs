@@ -1,16 +1,189 @@
+use anyhow::{bail, format_err, Error};
 use serde::{Deserialize, Serialize};
 
-use proxmox_schema::{api, IntegerSchema, Schema, StringSchema, Updater};
+use proxmox_schema::{api, ApiStringFormat, IntegerSchema, Schema, StringSchema, Updater};
+use proxmox_time::{parse_daily_duration, WeekDays};
 
 use crate::{
-    HumanByte, CIDR_SCHEMA, DAILY_DURATION_FORMAT, PROXMOX_SAFE_ID_FORMAT,
+    Authid, HumanByte, CIDR_SCHEMA, DATASTORE_SCHEMA, PROXMOX_SAFE_ID_FORMAT,
     SINGLE_LINE_COMMENT_SCHEMA,
 };
 
-pub const TRAFFIC_CONTROL_TIMEFRAME_SCHEMA: Schema =
-    StringSchema::new("Timeframe to specify when the rule is actice.")
-        .format(&DAILY_DURATION_FORMAT)
-        .schema();
+pub const TRAFFIC_CONTROL_TIMEFRAME_FORMAT: ApiStringFormat =
+    ApiStringFormat::VerifyFn(|s| s.parse::<TrafficControlTimeframe>().map(drop));
+
+pub const TRAFFIC_CONTROL_TIMEFRAME_SCHEMA: Schema = StringSchema::new(
+    "Timeframe to specify when the rule is actice, in the form \
+    '[<weekdays>] <start>-<end> [utc|local|<+/-hh:mm>]'.",
+)
+.format(&TRAFFIC_CONTROL_TIMEFRAME_FORMAT)
+.schema();
+
+/// Timezone of a [TrafficControlTimeframe]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeframeTimezone {
+    /// The local timezone of the node
+    Local,
+    /// A fixed offset to UTC in seconds
+    Offset(i64),
+}
+
+/// A parsed traffic control timeframe
+///
+/// The format is `[<weekdays>] <start>-<end> [<timezone>]`, for example `mon..fri 8-17` or
+/// `fri 22:00-06:00 -05:00`. If the end time is before the start time, the timeframe extends
+/// over midnight into the following day. The timezone is either `local` (the default), `utc`
+/// or a fixed offset to UTC.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrafficControlTimeframe {
+    // bit N is set for weekday N, with sunday being 0 (like `tm_wday`)
+    days: u8,
+    // minutes since midnight
+    start: u32,
+    end: u32,
+    timezone: TimeframeTimezone,
+}
+
+// bit N for weekday N, with sunday being 0
+fn weekday_bits(days: WeekDays) -> u8 {
+    [
+        WeekDays::SUNDAY,
+        WeekDays::MONDAY,
+        WeekDays::TUESDAY,
+        WeekDays::WEDNESDAY,
+        WeekDays::THURSDAY,
+        WeekDays::FRIDAY,
+        WeekDays::SATURDAY,
+    ]
+    .iter()
+    .enumerate()
+    .filter(|(_, day)| days.contains(**day))
+    .fold(0, |bits, (pos, _)| bits | 1 << pos)
+}
+
+fn is_timezone(item: &str) -> bool {
+    item.eq_ignore_ascii_case("local")
+        || item.eq_ignore_ascii_case("utc")
+        || item.starts_with('+')
+        || item.starts_with('-')
+}
+
+fn parse_hm_time(time: &str) -> Result<u32, Error> {
+    let (hour, minute) = match time.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>()?, minute.parse::<u32>()?),
+        None => (time.parse::<u32>()?, 0),
+    };
+    if hour > 23 || minute > 59 {
+        bail!("invalid time '{}'", time);
+    }
+    Ok(hour * 60 + minute)
+}
+
+fn parse_timezone(tz: &str) -> Result<TimeframeTimezone, Error> {
+    let sign = match tz {
+        _ if tz.eq_ignore_ascii_case("local") => return Ok(TimeframeTimezone::Local),
+        _ if tz.eq_ignore_ascii_case("utc") => return Ok(TimeframeTimezone::Offset(0)),
+        _ if tz.starts_with('+') => 1,
+        _ if tz.starts_with('-') => -1,
+        _ => bail!("invalid timezone '{}'", tz),
+    };
+    let offset = parse_hm_time(&tz[1..]).map_err(|_| format_err!("invalid timezone '{}'", tz))?;
+    if offset > 14 * 60 {
+        bail!("timezone offset '{}' out of range", tz);
+    }
+    Ok(TimeframeTimezone::Offset(sign * (offset as i64) * 60))
+}
+
+impl std::str::FromStr for TrafficControlTimeframe {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let (duration, timezone) = match s.rsplit_once(char::is_whitespace) {
+            Some((duration, timezone)) if is_timezone(timezone) => {
+                (duration.trim_end(), parse_timezone(timezone)?)
+            }
+            _ => (s, TimeframeTimezone::Local),
+        };
+
+        // the weekdays and times are parsed like any other daily duration, which only knows
+        // ranges within a day - a range extending over midnight is parsed with swapped times
+        let duration = duration.to_lowercase();
+        let (days, range) = match duration.rsplit_once(char::is_whitespace) {
+            Some((days, range)) => (Some(days), range),
+            None => (None, duration.as_str()),
+        };
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format_err!("invalid time range '{}'", range))?;
+        let overnight = matches!(
+            (parse_hm_time(start), parse_hm_time(end)),
+            (Ok(start), Ok(end)) if end < start
+        );
+        let daily = if overnight {
+            let swapped = format!("{}-{}", end, start);
+            match days {
+                Some(days) => parse_daily_duration(&format!("{} {}", days, swapped))?,
+                None => parse_daily_duration(&swapped)?,
+            }
+        } else {
+            parse_daily_duration(&duration)?
+        };
+
+        let (mut start, mut end) = (
+            daily.start.hour * 60 + daily.start.minute,
+            daily.end.hour * 60 + daily.end.minute,
+        );
+        if overnight {
+            std::mem::swap(&mut start, &mut end);
+        }
+        if start == end {
+            bail!("empty time range '{}'", range);
+        }
+
+        Ok(Self {
+            days: weekday_bits(daily.days),
+            start,
+            end,
+            timezone,
+        })
+    }
+}
+
+impl TrafficControlTimeframe {
+    /// Returns the timezone the timeframe is specified in.
+    pub fn timezone(&self) -> TimeframeTimezone {
+        self.timezone
+    }
+
+    /// Check if the timeframe matches a time, given as weekday (0 is sunday) and minutes
+    /// since midnight in the timeframe's timezone.
+    pub fn time_match(&self, weekday: u32, minute: u32) -> bool {
+        let day_match = |day: u32| self.days & (1 << day) != 0;
+
+        if self.start < self.end {
+            day_match(weekday) && self.start <= minute && minute < self.end
+        } else {
+            // extends over midnight, the part after midnight belongs to the previous day
+            (day_match(weekday) && minute >= self.start)
+                || (day_match((weekday + 6) % 7) && minute < self.end)
+        }
+    }
+
+    /// Check if the timeframe matches the point in time `epoch`.
+    ///
+    /// `use_utc` makes timeframes in the local timezone use UTC instead.
+    pub fn epoch_match(&self, epoch: i64, use_utc: bool) -> Result<bool, Error> {
+        let tm = match self.timezone {
+            TimeframeTimezone::Local if use_utc => proxmox_time::gmtime(epoch)?,
+            TimeframeTimezone::Local => proxmox_time::localtime(epoch)?,
+            TimeframeTimezone::Offset(offset) => proxmox_time::gmtime(epoch + offset)?,
+        };
+
+        Ok(self.time_match(tm.tm_wday as u32, (tm.tm_hour * 60 + tm.tm_min) as u32))
+    }
+}
 
 pub const TRAFFIC_CONTROL_ID_SCHEMA: Schema = StringSchema::new("Rule ID.")
     .format(&PROXMOX_SAFE_ID_FORMAT)
@@ -98,6 +271,24 @@ impl RateLimitConfig {
             },
             optional: true,
         },
+        datastore: {
+            type: Array,
+            items: {
+                schema: DATASTORE_SCHEMA,
+            },
+            optional: true,
+        },
+        shared: {
+            optional: true,
+            default: true,
+        },
+        "auth-id": {
+            type: Array,
+            items: {
+                type: Authid,
+            },
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Updater)]
@@ -112,11 +303,45 @@ pub struct TrafficControlRule {
     pub network: Vec<String>,
     #[serde(flatten)]
     pub limit: RateLimitConfig,
-    // fixme: expose this?
-    //    /// Bandwidth is shared across all connections
-    //    #[serde(skip_serializing_if="Option::is_none")]
-    //    pub shared: Option<bool>,
+    /// Bandwidth is shared across all connections, otherwise the limit applies to each
+    /// connection separately.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared: Option<bool>,
+    /// Only apply the rule to backup and reader sessions of these users or API tokens. A user
+    /// also matches its API tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_id: Option<Vec<Authid>>,
     /// Enable the rule at specific times
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeframe: Option<Vec<String>>,
+    /// Only apply the rule to backup and reader sessions on these datastores
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datastore: Option<Vec<String>>,
+}
+
+#[api(
+    properties: {
+        "auth-id": {
+            type: Authid,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Accumulated backup and reader protocol traffic of a client
+pub struct ClientTransferStats {
+    pub auth_id: Authid,
+    /// Source IP address of the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    /// Bytes uploaded by backup sessions
+    pub uploaded: u64,
+    /// Bytes downloaded by reader sessions
+    pub downloaded: u64,
+    /// Number of backup sessions
+    pub backup_sessions: u64,
+    /// Number of reader sessions
+    pub reader_sessions: u64,
+    /// Time of the last finished session (epoch)
+    pub last_seen: i64,
 }
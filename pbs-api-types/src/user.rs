@@ -205,3 +205,18 @@ impl User {
         true
     }
 }
+
+#[api(
+    properties: {
+        authid: {
+            type: Authid,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize)]
+/// A user or API token which is about to expire.
+pub struct ExpiringAccount {
+    pub authid: Authid,
+    /// Account expiration date (seconds since epoch).
+    pub expire: i64,
+}
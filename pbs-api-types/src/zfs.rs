@@ -1,9 +1,11 @@
+use anyhow::{bail, Error};
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::*;
 
 const_regex! {
     pub ZPOOL_NAME_REGEX = r"^[a-zA-Z][a-z0-9A-Z\-_.:]+$";
+    pub ZFS_DATASET_REGEX = r"^[a-zA-Z][a-z0-9A-Z\-_.:]+(?:/[a-z0-9A-Z\-_.:]+)+$";
 }
 
 pub const ZFS_ASHIFT_SCHEMA: Schema = IntegerSchema::new("Pool sector size exponent.")
@@ -16,6 +18,24 @@ pub const ZPOOL_NAME_SCHEMA: Schema = StringSchema::new("ZFS Pool Name")
     .format(&ApiStringFormat::Pattern(&ZPOOL_NAME_REGEX))
     .schema();
 
+pub const ZFS_DATASET_SCHEMA: Schema =
+    StringSchema::new("ZFS dataset name, including the pool ('<pool>/<dataset>').")
+        .format(&ApiStringFormat::Pattern(&ZFS_DATASET_REGEX))
+        .schema();
+
+pub const ZFS_RECORDSIZE_SCHEMA: Schema =
+    IntegerSchema::new("Maximum block size of files, in bytes (power of two).")
+        .minimum(512)
+        .maximum(16 * 1024 * 1024)
+        .schema();
+
+pub const ZFS_SPECIAL_SMALL_BLOCKS_SCHEMA: Schema = IntegerSchema::new(
+    "Store blocks up to this size on the special allocation class, in bytes (0 or power of two).",
+)
+.minimum(0)
+.maximum(1024 * 1024)
+.schema();
+
 #[api(default: "On")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -37,6 +57,9 @@ pub enum ZfsCompressionType {
     Off,
 }
 
+serde_plain::derive_display_from_serialize!(ZfsCompressionType);
+serde_plain::derive_fromstr_from_deserialize!(ZfsCompressionType);
+
 #[api()]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -56,6 +79,73 @@ pub enum ZfsRaidLevel {
     RaidZ3,
 }
 
+#[api(
+    properties: {
+        compression: {
+            type: ZfsCompressionType,
+            optional: true,
+        },
+        recordsize: {
+            schema: ZFS_RECORDSIZE_SCHEMA,
+            optional: true,
+        },
+        "special-small-blocks": {
+            schema: ZFS_SPECIAL_SMALL_BLOCKS_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Properties of a ZFS dataset
+pub struct ZfsDatasetProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<ZfsCompressionType>,
+    /// Update the access time of files when they are read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub atime: Option<bool>,
+    /// Only update the access time if it is older than the modification time or than one day.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relatime: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recordsize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub special_small_blocks: Option<u64>,
+}
+
+impl ZfsDatasetProperties {
+    /// Returns the set properties as `<property>=<value>` list, as used by the `zfs` command.
+    pub fn to_zfs_options(&self) -> Result<Vec<String>, Error> {
+        let on_off = |value: bool| if value { "on" } else { "off" };
+
+        let mut options = Vec::new();
+
+        if let Some(compression) = self.compression {
+            options.push(format!("compression={}", compression));
+        }
+        if let Some(atime) = self.atime {
+            options.push(format!("atime={}", on_off(atime)));
+        }
+        if let Some(relatime) = self.relatime {
+            options.push(format!("relatime={}", on_off(relatime)));
+        }
+        if let Some(recordsize) = self.recordsize {
+            if !recordsize.is_power_of_two() {
+                bail!("recordsize {} is not a power of two", recordsize);
+            }
+            options.push(format!("recordsize={}", recordsize));
+        }
+        if let Some(size) = self.special_small_blocks {
+            if size != 0 && (size < 512 || !size.is_power_of_two()) {
+                bail!("special-small-blocks must be 0 or a power of two of at least 512");
+            }
+            options.push(format!("special_small_blocks={}", size));
+        }
+
+        Ok(options)
+    }
+}
+
 #[api()]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -76,3 +166,24 @@ pub struct ZpoolListItem {
     /// ZFS deduplication ratio
     pub dedup: f64,
 }
+
+#[api()]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Health of the ZFS pool a datastore is located on
+pub struct ZpoolHealth {
+    /// zpool name
+    pub pool: String,
+    /// Health (ONLINE, DEGRADED, FAULTED, ...)
+    pub health: String,
+    /// Description of the problem, as reported by 'zpool status'
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+impl ZpoolHealth {
+    /// Returns true if the pool is fully operational, with all redundancy available.
+    pub fn is_online(&self) -> bool {
+        self.health == "ONLINE"
+    }
+}
@@ -5,15 +5,19 @@ use std::os::unix::fs::OpenOptionsExt;
 use std::sync::Arc;
 
 use futures::future::AbortHandle;
+use http::header::HeaderValue;
 use serde_json::{json, Value};
 
-use pbs_api_types::{BackupDir, BackupNamespace};
+use pbs_api_types::{BackupDir, BackupNamespace, Fingerprint};
 use pbs_datastore::data_blob::DataBlob;
 use pbs_datastore::data_blob_reader::DataBlobReader;
 use pbs_datastore::dynamic_index::DynamicIndexReader;
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
-use pbs_datastore::manifest::MANIFEST_BLOB_NAME;
+use pbs_datastore::manifest::{PayloadCompression, MANIFEST_BLOB_NAME};
+use pbs_datastore::protocol::{
+    feature_offer, ChunkCompression, CHUNK_COMPRESSION_HEADER, PAYLOAD_COMPRESSION_HEADER,
+};
 use pbs_datastore::{BackupManifest, PROXMOX_BACKUP_READER_PROTOCOL_ID_V1};
 use pbs_tools::crypt_config::CryptConfig;
 use pbs_tools::sha::sha256;
@@ -51,6 +55,59 @@ impl BackupReader {
         backup: &BackupDir,
         debug: bool,
     ) -> Result<Arc<BackupReader>, Error> {
+        let (h2, abort) = Self::start_h2_connection(client, datastore, ns, backup, debug).await?;
+
+        Ok(BackupReader::new(h2, abort, crypt_config))
+    }
+
+    /// Create a new instance like [`start`](Self::start), but use `legacy_crypt_config` instead
+    /// of `crypt_config` if the snapshot's manifest was created with the legacy key.
+    ///
+    /// This allows to restore older snapshots after rotating the encryption key. Returns the
+    /// reader and the key it uses.
+    pub async fn start_with_legacy_key(
+        client: HttpClient,
+        crypt_config: Option<Arc<CryptConfig>>,
+        legacy_crypt_config: Option<Arc<CryptConfig>>,
+        datastore: &str,
+        ns: &BackupNamespace,
+        backup: &BackupDir,
+        debug: bool,
+    ) -> Result<(Arc<BackupReader>, Option<Arc<CryptConfig>>), Error> {
+        let (h2, abort) = Self::start_h2_connection(client, datastore, ns, backup, debug).await?;
+
+        let mut reader = Self {
+            h2,
+            abort,
+            crypt_config,
+        };
+
+        if let Some(legacy_crypt_config) = legacy_crypt_config {
+            // the key fingerprint is stored unprotected, so no key is needed to read it
+            let mut raw_data = Vec::with_capacity(64 * 1024);
+            reader.download(MANIFEST_BLOB_NAME, &mut raw_data).await?;
+            let blob = DataBlob::load_from_reader(&mut &raw_data[..])?;
+            let manifest = BackupManifest::from_data(&blob.decode(None, None)?, None)?;
+
+            let legacy_fingerprint = Fingerprint::new(legacy_crypt_config.fingerprint());
+            if manifest.fingerprint()?.as_ref() == Some(&legacy_fingerprint) {
+                log::info!("Using legacy encryption key {}", legacy_fingerprint);
+                reader.crypt_config = Some(legacy_crypt_config);
+            }
+        }
+
+        let crypt_config = reader.crypt_config.clone();
+
+        Ok((Arc::new(reader), crypt_config))
+    }
+
+    async fn start_h2_connection(
+        client: HttpClient,
+        datastore: &str,
+        ns: &BackupNamespace,
+        backup: &BackupDir,
+        debug: bool,
+    ) -> Result<(H2Client, AbortHandle), Error> {
         let mut param = json!({
             "backup-type": backup.ty(),
             "backup-id": backup.id(),
@@ -63,7 +120,7 @@ impl BackupReader {
             param["ns"] = serde_json::to_value(ns)?;
         }
 
-        let req = HttpClient::request_builder(
+        let mut req = HttpClient::request_builder(
             client.server(),
             client.port(),
             "GET",
@@ -72,11 +129,20 @@ impl BackupReader {
         )
         .unwrap();
 
-        let (h2, abort) = client
+        // let the server know which compressed chunks we are able to decode
+        req.headers_mut().insert(
+            CHUNK_COMPRESSION_HEADER,
+            HeaderValue::from_str(&feature_offer(ChunkCompression::ALL))?,
+        );
+        // and which archive payloads
+        req.headers_mut().insert(
+            PAYLOAD_COMPRESSION_HEADER,
+            HeaderValue::from_str(&feature_offer(PayloadCompression::ALL))?,
+        );
+
+        client
             .start_h2_connection(req, String::from(PROXMOX_BACKUP_READER_PROTOCOL_ID_V1!()))
-            .await?;
-
-        Ok(BackupReader::new(h2, abort, crypt_config))
+            .await
     }
 
     /// Execute a GET request
@@ -7,17 +7,27 @@ use std::sync::{Arc, Mutex};
 use anyhow::{bail, format_err, Error};
 use futures::future::{self, AbortHandle, Either, FutureExt, TryFutureExt};
 use futures::stream::{Stream, StreamExt, TryStreamExt};
+use http::header::HeaderValue;
 use serde_json::{json, Value};
 use tokio::io::AsyncReadExt;
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
 
-use pbs_api_types::{BackupDir, BackupNamespace, HumanByte};
-use pbs_datastore::data_blob::{ChunkInfo, DataBlob, DataChunkBuilder};
+use pbs_api_types::{BackupDir, BackupNamespace, CryptCipher, HumanByte, DEFAULT_ZSTD_LEVEL};
+use pbs_datastore::data_blob::{ChunkInfo, DataBlob};
 use pbs_datastore::dynamic_index::DynamicIndexReader;
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
-use pbs_datastore::manifest::{ArchiveType, BackupManifest, MANIFEST_BLOB_NAME};
+use pbs_datastore::manifest::{
+    ArchiveType, BackupManifest, PayloadCompression, MANIFEST_BLOB_NAME,
+};
+use pbs_datastore::protocol::{
+    decode_partial_index, decode_resume_chunks, feature_offer, negotiate_feature_set,
+    ChunkCompression, ChunkIntegrity, ClientLog, CHUNK_COMPRESSION_HEADER,
+    CHUNK_COMPRESSION_LEVEL_HEADER, CHUNK_INTEGRITY_HEADER, CIPHERS, CIPHER_HEADER,
+    CLIENT_LOG_HEADER, LEGACY_CIPHERS, PAYLOAD_COMPRESSION_HEADER,
+};
+use pbs_datastore::read_chunk::AsyncReadChunk;
 use pbs_datastore::{CATALOG_NAME, PROXMOX_BACKUP_PROTOCOL_ID_V1};
 use pbs_tools::crypt_config::CryptConfig;
 
@@ -25,10 +35,24 @@ use super::merge_known_chunks::{MergeKnownChunks, MergedChunkInfo};
 
 use super::{H2Client, HttpClient};
 
+/// Upper limit for the number of threads hashing and encoding chunks in parallel
+const MAX_CHUNK_WORKERS: usize = 8;
+
+/// Default number of chunk uploads kept in flight
+pub const DEFAULT_UPLOAD_WINDOW: usize = 4;
+
 pub struct BackupWriter {
     h2: H2Client,
     abort: AbortHandle,
     crypt_config: Option<Arc<CryptConfig>>,
+    chunk_integrity: Option<ChunkIntegrity>,
+    chunk_compression: Vec<ChunkCompression>,
+    compression_level: Option<i32>,
+    payload_compression: Vec<PayloadCompression>,
+    ciphers: Vec<CryptCipher>,
+    client_log: Option<ClientLog>,
+    resume: bool,
+    resume_chunks: HashSet<[u8; 32]>,
 }
 
 impl Drop for BackupWriter {
@@ -49,6 +73,8 @@ pub struct UploadOptions {
     pub compress: bool,
     pub encrypt: bool,
     pub fixed_size: Option<u64>,
+    /// Number of chunk uploads kept in flight, defaults to [`DEFAULT_UPLOAD_WINDOW`]
+    pub parallel: Option<usize>,
 }
 
 struct UploadStats {
@@ -65,11 +91,32 @@ type UploadQueueSender = mpsc::Sender<(MergedChunkInfo, Option<h2::client::Respo
 type UploadResultReceiver = oneshot::Receiver<Result<(), Error>>;
 
 impl BackupWriter {
-    fn new(h2: H2Client, abort: AbortHandle, crypt_config: Option<Arc<CryptConfig>>) -> Arc<Self> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        h2: H2Client,
+        abort: AbortHandle,
+        crypt_config: Option<Arc<CryptConfig>>,
+        chunk_integrity: Option<ChunkIntegrity>,
+        chunk_compression: Vec<ChunkCompression>,
+        compression_level: Option<i32>,
+        payload_compression: Vec<PayloadCompression>,
+        ciphers: Vec<CryptCipher>,
+        client_log: Option<ClientLog>,
+        resume: bool,
+        resume_chunks: HashSet<[u8; 32]>,
+    ) -> Arc<Self> {
         Arc::new(Self {
             h2,
             abort,
             crypt_config,
+            chunk_integrity,
+            chunk_compression,
+            compression_level,
+            payload_compression,
+            ciphers,
+            client_log,
+            resume,
+            resume_chunks,
         })
     }
 
@@ -83,6 +130,7 @@ impl BackupWriter {
         backup: &BackupDir,
         debug: bool,
         benchmark: bool,
+        resume: bool,
     ) -> Result<Arc<BackupWriter>, Error> {
         let mut param = json!({
             "backup-type": backup.ty(),
@@ -96,8 +144,11 @@ impl BackupWriter {
         if !ns.is_root() {
             param["ns"] = serde_json::to_value(ns)?;
         }
+        if resume {
+            param["resume"] = true.into();
+        }
 
-        let req = HttpClient::request_builder(
+        let mut req = HttpClient::request_builder(
             client.server(),
             client.port(),
             "GET",
@@ -106,11 +157,133 @@ impl BackupWriter {
         )
         .unwrap();
 
-        let (h2, abort) = client
-            .start_h2_connection(req, String::from(PROXMOX_BACKUP_PROTOCOL_ID_V1!()))
+        // chunks are always uploaded with a valid CRC, so offer to have it checked
+        req.headers_mut().insert(
+            CHUNK_INTEGRITY_HEADER,
+            HeaderValue::from_str(&feature_offer(ChunkIntegrity::ALL))?,
+        );
+        req.headers_mut().insert(
+            CHUNK_COMPRESSION_HEADER,
+            HeaderValue::from_str(&feature_offer(ChunkCompression::ALL))?,
+        );
+        req.headers_mut().insert(
+            PAYLOAD_COMPRESSION_HEADER,
+            HeaderValue::from_str(&feature_offer(PayloadCompression::ALL))?,
+        );
+        req.headers_mut().insert(
+            CIPHER_HEADER,
+            HeaderValue::from_str(&feature_offer(CIPHERS))?,
+        );
+        req.headers_mut().insert(
+            CLIENT_LOG_HEADER,
+            HeaderValue::from_str(&feature_offer(ClientLog::ALL))?,
+        );
+
+        let (h2, abort, headers) = client
+            .start_h2_connection_full(req, String::from(PROXMOX_BACKUP_PROTOCOL_ID_V1!()))
             .await?;
 
-        Ok(BackupWriter::new(h2, abort, crypt_config))
+        let chunk_integrity = match headers.get(CHUNK_INTEGRITY_HEADER) {
+            Some(value) => Some(value.to_str()?.parse::<ChunkIntegrity>()?),
+            None => None,
+        };
+        if let Some(chunk_integrity) = chunk_integrity {
+            log::debug!("server verifies chunk integrity with {}", chunk_integrity);
+        }
+
+        // older servers accept the legacy compression algorithms without negotiation
+        let chunk_compression = match headers.get(CHUNK_COMPRESSION_HEADER) {
+            Some(value) => negotiate_feature_set(value.to_str()?),
+            None => ChunkCompression::LEGACY.to_vec(),
+        };
+        let compression_level = match headers.get(CHUNK_COMPRESSION_LEVEL_HEADER) {
+            Some(value) => parse_compression_level(value.to_str()?)?,
+            None => Some(DEFAULT_ZSTD_LEVEL),
+        };
+        // older servers would hand out compressed payloads to clients unable to decode them
+        let payload_compression = match headers.get(PAYLOAD_COMPRESSION_HEADER) {
+            Some(value) => negotiate_feature_set(value.to_str()?),
+            None => Vec::new(),
+        };
+        // older servers cannot parse blobs encrypted with other ciphers
+        let ciphers = match headers.get(CIPHER_HEADER) {
+            Some(value) => negotiate_feature_set(value.to_str()?),
+            None => LEGACY_CIPHERS.to_vec(),
+        };
+        if let Some(crypt_config) = &crypt_config {
+            let cipher = crypt_config.crypt_cipher();
+            if !ciphers.contains(&cipher) {
+                bail!("server does not support the {} cipher", cipher);
+            }
+        }
+        // older servers refuse a log uploaded after the backup if there is one already
+        let client_log = match headers.get(CLIENT_LOG_HEADER) {
+            Some(value) => Some(value.to_str()?.parse::<ClientLog>()?),
+            None => None,
+        };
+
+        let mut resume_chunks = HashSet::new();
+        if resume {
+            let mut raw_data = Vec::new();
+            h2.download("resume_chunks", None, &mut raw_data).await?;
+            for (digest, _size) in decode_resume_chunks(&raw_data)? {
+                resume_chunks.insert(digest);
+            }
+            if !resume_chunks.is_empty() {
+                log::info!(
+                    "resuming interrupted backup, {} chunks already uploaded",
+                    resume_chunks.len()
+                );
+            }
+        }
+
+        Ok(BackupWriter::new(
+            h2,
+            abort,
+            crypt_config,
+            chunk_integrity,
+            chunk_compression,
+            compression_level,
+            payload_compression,
+            ciphers,
+            client_log,
+            resume,
+            resume_chunks,
+        ))
+    }
+
+    /// The chunk integrity check agreed on with the server, if any.
+    pub fn chunk_integrity(&self) -> Option<ChunkIntegrity> {
+        self.chunk_integrity
+    }
+
+    /// The compression algorithms the server accepts for chunks.
+    pub fn chunk_compression(&self) -> &[ChunkCompression] {
+        &self.chunk_compression
+    }
+
+    /// The archive payload compression formats the server protects from older readers.
+    pub fn payload_compression(&self) -> &[PayloadCompression] {
+        &self.payload_compression
+    }
+
+    /// The ciphers the server accepts for encrypted chunks and blobs.
+    pub fn ciphers(&self) -> &[CryptCipher] {
+        &self.ciphers
+    }
+
+    /// The client log the server accepts during the backup session, if any.
+    pub fn client_log(&self) -> Option<ClientLog> {
+        self.client_log
+    }
+
+    // only compress if the server accepts zstd compressed data, with the level it asks for
+    fn compression_level(&self, compress: bool) -> Option<i32> {
+        if compress && self.chunk_compression.contains(&ChunkCompression::Zstd) {
+            self.compression_level
+        } else {
+            None
+        }
     }
 
     pub async fn get(&self, path: &str, param: Option<Value>) -> Result<Value, Error> {
@@ -210,11 +383,12 @@ impl BackupWriter {
         file_name: &str,
         options: UploadOptions,
     ) -> Result<BackupStats, Error> {
+        let level = self.compression_level(options.compress);
         let blob = match (options.encrypt, &self.crypt_config) {
-            (false, _) => DataBlob::encode(&data, None, options.compress)?,
+            (false, _) => DataBlob::encode_with_level(&data, None, level)?,
             (true, None) => bail!("requested encryption without a crypt config"),
             (true, Some(crypt_config)) => {
-                DataBlob::encode(&data, Some(crypt_config), options.compress)?
+                DataBlob::encode_with_level(&data, Some(crypt_config), level)?
             }
         };
 
@@ -264,7 +438,8 @@ impl BackupWriter {
         stream: impl Stream<Item = Result<bytes::BytesMut, Error>>,
         options: UploadOptions,
     ) -> Result<BackupStats, Error> {
-        let known_chunks = Arc::new(Mutex::new(HashSet::new()));
+        // chunks uploaded by an interrupted backup are known to the server already
+        let known_chunks = Arc::new(Mutex::new(self.resume_chunks.clone()));
 
         let mut param = json!({ "archive-name": archive_name });
         let prefix = if let Some(size) = options.fixed_size {
@@ -319,18 +494,39 @@ impl BackupWriter {
             .as_u64()
             .unwrap();
 
+        // the server keeps the index entries written before the interruption, those chunks are
+        // skipped if the archive still starts with them
+        let resumed_entries = if self.resume {
+            let mut raw_data = Vec::new();
+            self.h2
+                .download("partial_index", Some(json!({ "wid": wid })), &mut raw_data)
+                .await?;
+            decode_partial_index(&raw_data)?
+        } else {
+            Vec::new()
+        };
+        if !resumed_entries.is_empty() {
+            log::info!(
+                "{}: resuming after {} chunks of the interrupted backup",
+                archive_name,
+                resumed_entries.len()
+            );
+        }
+
         let upload_stats = Self::upload_chunk_info_stream(
             self.h2.clone(),
             wid,
             stream,
             prefix,
+            resumed_entries,
             known_chunks.clone(),
             if options.encrypt {
                 self.crypt_config.clone()
             } else {
                 None
             },
-            options.compress,
+            self.compression_level(options.compress),
+            options.parallel.unwrap_or(DEFAULT_UPLOAD_WINDOW).max(1),
         )
         .await?;
 
@@ -402,6 +598,104 @@ impl BackupWriter {
         })
     }
 
+    /// Upload the archive `archive_name` from an existing `index`, reading its already encoded
+    /// chunks with `chunk_reader`.
+    ///
+    /// Chunks in `known_chunks` are only referenced, they need to be known to the server, for
+    /// example by downloading the index of the previous snapshot. This allows to copy a snapshot
+    /// to another server without decoding its chunks.
+    pub async fn upload_index<R: AsyncReadChunk + Sync>(
+        &self,
+        archive_name: &str,
+        index: &(dyn IndexFile + Send + Sync),
+        chunk_reader: &R,
+        known_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    ) -> Result<BackupStats, Error> {
+        let mut param = json!({ "archive-name": archive_name });
+        let prefix = match ArchiveType::from_path(archive_name)? {
+            ArchiveType::FixedIndex => {
+                param["size"] = index.index_bytes().into();
+                "fixed"
+            }
+            ArchiveType::DynamicIndex => "dynamic",
+            ArchiveType::Blob => bail!("'{}' is not an index archive", archive_name),
+        };
+
+        let index_path = format!("{}_index", prefix);
+        let chunk_path = format!("{}_chunk", prefix);
+        let close_path = format!("{}_close", prefix);
+
+        let wid = self
+            .h2
+            .post(&index_path, Some(param))
+            .await?
+            .as_u64()
+            .unwrap();
+
+        let mut digest_list = Vec::new();
+        let mut offset_list = Vec::new();
+
+        for pos in 0..index.index_count() {
+            let info = index.chunk_info(pos).unwrap();
+
+            let is_new = known_chunks.lock().unwrap().insert(info.digest);
+            if is_new {
+                let chunk_data = chunk_reader
+                    .read_raw_chunk(&info.digest)
+                    .await?
+                    .into_inner();
+                let param = json!({
+                    "wid": wid,
+                    "digest": hex::encode(&info.digest),
+                    "size": info.size(),
+                    "encoded-size": chunk_data.len(),
+                });
+                self.h2
+                    .upload(
+                        "POST",
+                        &chunk_path,
+                        Some(param),
+                        "application/octet-stream",
+                        chunk_data,
+                    )
+                    .await?;
+            }
+
+            digest_list.push(hex::encode(&info.digest));
+            offset_list.push(info.range.start);
+
+            if digest_list.len() >= 128 || pos + 1 == index.index_count() {
+                let param = json!({
+                    "wid": wid,
+                    "digest-list": std::mem::take(&mut digest_list),
+                    "offset-list": std::mem::take(&mut offset_list),
+                });
+                self.h2
+                    .upload(
+                        "PUT",
+                        &index_path,
+                        None,
+                        "application/json",
+                        param.to_string().into_bytes(),
+                    )
+                    .await?;
+            }
+        }
+
+        // same checksum as computed while uploading a stream
+        let (csum, size) = index.compute_csum();
+
+        let param = json!({
+            "wid": wid,
+            "chunk-count": index.index_count(),
+            "size": size,
+            "csum": hex::encode(&csum),
+        });
+        self.h2.post(&close_path, Some(param)).await?;
+
+        Ok(BackupStats { size, csum })
+    }
+
     fn response_queue() -> (
         mpsc::Sender<h2::client::ResponseFuture>,
         oneshot::Receiver<Result<(), Error>>,
@@ -624,10 +918,13 @@ impl BackupWriter {
         wid: u64,
         stream: impl Stream<Item = Result<bytes::BytesMut, Error>>,
         prefix: &str,
+        resumed_entries: Vec<(u64, [u8; 32])>,
         known_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
         crypt_config: Option<Arc<CryptConfig>>,
-        compress: bool,
+        compression_level: Option<i32>,
+        parallel: usize,
     ) -> impl Future<Output = Result<UploadStats, Error>> {
+        let resumed_count = resumed_entries.len();
         let total_chunks = Arc::new(AtomicUsize::new(0));
         let total_chunks2 = total_chunks.clone();
         let known_chunk_count = Arc::new(AtomicUsize::new(0));
@@ -652,21 +949,34 @@ impl BackupWriter {
         let index_csum = Arc::new(Mutex::new(Some(openssl::sha::Sha256::new())));
         let index_csum_2 = index_csum.clone();
 
+        // hashing and encoding chunks is CPU bound, so spread it over multiple threads while
+        // keeping the chunk order intact
+        let workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_CHUNK_WORKERS);
+        let digest_crypt_config = crypt_config.clone();
+
         stream
-            .and_then(move |data| {
+            .map_ok(move |data| {
+                let crypt_config = digest_crypt_config.clone();
+                tokio::task::spawn_blocking(move || {
+                    let digest = match crypt_config {
+                        Some(ref crypt_config) => crypt_config.compute_digest(&data),
+                        None => openssl::sha::sha256(&data),
+                    };
+                    (data, digest)
+                })
+                .map_err(Error::from)
+            })
+            .try_buffered(workers)
+            .map_ok(move |(data, digest)| {
                 let chunk_len = data.len();
 
-                total_chunks.fetch_add(1, Ordering::SeqCst);
+                let pos = total_chunks.fetch_add(1, Ordering::SeqCst);
                 let offset = stream_len.fetch_add(chunk_len, Ordering::SeqCst) as u64;
 
-                let mut chunk_builder = DataChunkBuilder::new(data.as_ref()).compress(compress);
-
-                if let Some(ref crypt_config) = crypt_config {
-                    chunk_builder = chunk_builder.crypt_config(crypt_config);
-                }
-
                 let mut known_chunks = known_chunks.lock().unwrap();
-                let digest = chunk_builder.digest();
 
                 let mut guard = index_csum.lock().unwrap();
                 let csum = guard.as_mut().unwrap();
@@ -676,31 +986,59 @@ impl BackupWriter {
                 if !is_fixed_chunk_size {
                     csum.update(&chunk_end.to_le_bytes());
                 }
-                csum.update(digest);
+                csum.update(&digest);
+
+                // already part of the index the server resumed
+                if let Some((end, resumed_digest)) = resumed_entries.get(pos) {
+                    if *end != chunk_end || *resumed_digest != digest {
+                        return Either::Left(future::err(format_err!(
+                            "archive changed since the interrupted backup, unable to resume it"
+                        )));
+                    }
+                    known_chunk_count.fetch_add(1, Ordering::SeqCst);
+                    reused_len.fetch_add(chunk_len, Ordering::SeqCst);
+                    return Either::Left(future::ok(None));
+                }
 
-                let chunk_is_known = known_chunks.contains(digest);
+                let chunk_is_known = known_chunks.contains(&digest);
                 if chunk_is_known {
                     known_chunk_count.fetch_add(1, Ordering::SeqCst);
                     reused_len.fetch_add(chunk_len, Ordering::SeqCst);
-                    future::ok(MergedChunkInfo::Known(vec![(offset, *digest)]))
+                    Either::Left(future::ok(Some(MergedChunkInfo::Known(vec![(
+                        offset, digest,
+                    )]))))
                 } else {
                     let compressed_stream_len2 = compressed_stream_len.clone();
-                    known_chunks.insert(*digest);
-                    future::ready(chunk_builder.build().map(move |(chunk, digest)| {
-                        compressed_stream_len2.fetch_add(chunk.raw_size(), Ordering::SeqCst);
-                        MergedChunkInfo::New(ChunkInfo {
-                            chunk,
-                            digest,
-                            chunk_len: chunk_len as u64,
-                            offset,
+                    let crypt_config = crypt_config.clone();
+                    known_chunks.insert(digest);
+                    Either::Right(
+                        tokio::task::spawn_blocking(move || {
+                            DataBlob::encode_with_level(
+                                &data,
+                                crypt_config.as_deref(),
+                                compression_level,
+                            )
                         })
-                    }))
+                        .map(
+                            move |result| -> Result<Option<MergedChunkInfo>, Error> {
+                                let chunk = result??;
+                                compressed_stream_len2
+                                    .fetch_add(chunk.raw_size(), Ordering::SeqCst);
+                                Ok(Some(MergedChunkInfo::New(ChunkInfo {
+                                    chunk,
+                                    digest,
+                                    chunk_len: chunk_len as u64,
+                                    offset,
+                                })))
+                            },
+                        ),
+                    )
                 }
             })
+            .try_buffered(workers)
+            .try_filter_map(future::ok)
             .merge_known_chunks()
-            .try_for_each(move |merged_chunk_info| {
-                let upload_queue = upload_queue.clone();
-
+            .map_ok(move |merged_chunk_info| {
                 if let MergedChunkInfo::New(chunk_info) = merged_chunk_info {
                     let offset = chunk_info.offset;
                     let digest = chunk_info.digest;
@@ -734,29 +1072,34 @@ impl BackupWriter {
 
                     let new_info = MergedChunkInfo::Known(vec![(offset, digest)]);
 
-                    Either::Left(h2.send_request(request, upload_data).and_then(
-                        move |response| async move {
-                            upload_queue
-                                .send((new_info, Some(response)))
-                                .await
-                                .map_err(|err| {
-                                    format_err!("failed to send to upload queue: {}", err)
-                                })
-                        },
-                    ))
+                    Either::Left(
+                        h2.send_request(request, upload_data)
+                            .map_ok(move |response| (new_info, Some(response))),
+                    )
                 } else {
-                    Either::Right(async move {
-                        upload_queue
-                            .send((merged_chunk_info, None))
-                            .await
-                            .map_err(|err| format_err!("failed to send to upload queue: {}", err))
-                    })
+                    Either::Right(future::ok((merged_chunk_info, None)))
+                }
+            })
+            // keep several uploads in flight, the responses are still queued in chunk order
+            .try_buffered(parallel)
+            .try_for_each(move |(merged_chunk_info, response)| {
+                let upload_queue = upload_queue.clone();
+                async move {
+                    upload_queue
+                        .send((merged_chunk_info, response))
+                        .await
+                        .map_err(|err| format_err!("failed to send to upload queue: {}", err))
                 }
             })
             .then(move |result| async move { upload_result.await?.and(result) }.boxed())
             .and_then(move |_| {
                 let duration = start_time.elapsed();
                 let chunk_count = total_chunks2.load(Ordering::SeqCst);
+                if chunk_count < resumed_count {
+                    return future::err(format_err!(
+                        "archive changed since the interrupted backup, unable to resume it"
+                    ));
+                }
                 let chunk_reused = known_chunk_count2.load(Ordering::SeqCst);
                 let size = stream_len2.load(Ordering::SeqCst);
                 let size_reused = reused_len2.load(Ordering::SeqCst);
@@ -831,3 +1174,24 @@ impl BackupWriter {
         Ok(speed)
     }
 }
+
+// the server sends 'none' if the datastore does not want compressed chunks
+fn parse_compression_level(value: &str) -> Result<Option<i32>, Error> {
+    match value {
+        "none" => Ok(None),
+        level => match level.parse() {
+            Ok(level) => Ok(Some(level)),
+            Err(err) => bail!("invalid chunk compression level '{level}' - {err}"),
+        },
+    }
+}
+
+#[test]
+fn test_parse_compression_level() {
+    assert_eq!(parse_compression_level("none").unwrap(), None);
+    assert_eq!(parse_compression_level("1").unwrap(), Some(1));
+    assert_eq!(parse_compression_level("19").unwrap(), Some(19));
+    assert!(parse_compression_level("").is_err());
+    assert!(parse_compression_level("zstd").is_err());
+    assert!(parse_compression_level("None").is_err());
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
@@ -6,6 +7,7 @@ use bytes::BytesMut;
 use futures::ready;
 use futures::stream::{Stream, TryStream};
 
+use pbs_datastore::seekable_zstd::SeekableZstdEncoder;
 use pbs_datastore::Chunker;
 
 /// Split input stream into dynamic sized chunks
@@ -132,3 +134,77 @@ where
         }
     }
 }
+
+/// Upper limit for the size of chunks produced by [SeekableZstdStream], as accepted by the server
+const MAX_COMPRESSED_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Split input stream into dynamic sized chunks and compress them into the seekable zstd format
+///
+/// The chunk boundaries are determined on the uncompressed data, and each chunk is compressed
+/// into frames of its own, so unchanged data still deduplicates. The seek table is returned as
+/// last chunk.
+pub struct SeekableZstdStream<S: Unpin> {
+    input: ChunkStream<S>,
+    encoder: Option<SeekableZstdEncoder>,
+    pending: VecDeque<BytesMut>,
+}
+
+impl<S: Unpin> SeekableZstdStream<S> {
+    pub fn new(input: S, chunk_size: Option<usize>) -> Self {
+        Self {
+            input: ChunkStream::new(input, chunk_size),
+            encoder: Some(SeekableZstdEncoder::new()),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<S: Unpin> Unpin for SeekableZstdStream<S> {}
+
+impl<S: Unpin> Stream for SeekableZstdStream<S>
+where
+    S: TryStream,
+    S::Ok: AsRef<[u8]>,
+    S::Error: Into<Error>,
+{
+    type Item = Result<BytesMut, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(chunk) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+
+            let encoder = match this.encoder.as_mut() {
+                Some(encoder) => encoder,
+                None => return Poll::Ready(None),
+            };
+
+            match ready!(Pin::new(&mut this.input).poll_next(cx)) {
+                Some(Err(err)) => {
+                    return Poll::Ready(Some(Err(err)));
+                }
+                None => {
+                    let encoder = this.encoder.take().unwrap();
+                    return Poll::Ready(Some(Ok(BytesMut::from(&encoder.finish()[..]))));
+                }
+                Some(Ok(data)) => {
+                    let frames = match encoder.write(&data) {
+                        Ok(frames) => frames,
+                        Err(err) => return Poll::Ready(Some(Err(err))),
+                    };
+                    // incompressible data grows slightly, so split it up if necessary
+                    let mut chunk = BytesMut::new();
+                    for frame in frames {
+                        if chunk.len() + frame.len() > MAX_COMPRESSED_CHUNK_SIZE {
+                            this.pending.push_back(chunk.split());
+                        }
+                        chunk.extend_from_slice(&frame);
+                    }
+                    this.pending.push_back(chunk);
+                }
+            }
+        }
+    }
+}
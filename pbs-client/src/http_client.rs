@@ -10,7 +10,7 @@ use http::{Request, Response};
 use hyper::client::{Client, HttpConnector};
 use hyper::Body;
 use openssl::{
-    ssl::{SslConnector, SslMethod},
+    ssl::{SslConnector, SslMethod, SslVersion},
     x509::X509StoreContextRef,
 };
 use percent_encoding::percent_encode;
@@ -33,6 +33,9 @@ use pbs_tools::ticket;
 use super::pipe_to_stream::PipeToSendStream;
 use super::PROXMOX_BACKUP_TCP_KEEPALIVE_TIME;
 
+// not exported by the openssl crate
+const X509_V_ERR_HOSTNAME_MISMATCH: i32 = 62;
+
 /// Timeout used for several HTTP operations that are expected to finish quickly but may block in
 /// certain error conditions. Keep it generous, to avoid false-positive under high load.
 const HTTP_TIMEOUT: Duration = Duration::from_secs(2 * 60);
@@ -52,6 +55,9 @@ pub struct HttpClientOptions {
     ticket_cache: bool,
     fingerprint_cache: bool,
     verify_cert: bool,
+    verify_hostname: bool,
+    ca_file: Option<String>,
+    min_tls_version: Option<SslVersion>,
     limit: RateLimitConfig,
 }
 
@@ -111,6 +117,22 @@ impl HttpClientOptions {
         self
     }
 
+    pub fn verify_hostname(mut self, verify_hostname: bool) -> Self {
+        self.verify_hostname = verify_hostname;
+        self
+    }
+
+    /// Trust the CA certificate(s) in `ca_file` in addition to the system trust store.
+    pub fn ca_file(mut self, ca_file: Option<String>) -> Self {
+        self.ca_file = ca_file;
+        self
+    }
+
+    pub fn min_tls_version(mut self, min_tls_version: Option<SslVersion>) -> Self {
+        self.min_tls_version = min_tls_version;
+        self
+    }
+
     pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
         self.limit = rate_limit;
         self
@@ -127,6 +149,9 @@ impl Default for HttpClientOptions {
             ticket_cache: false,
             fingerprint_cache: false,
             verify_cert: true,
+            verify_hostname: true,
+            ca_file: None,
+            min_tls_version: None,
             limit: RateLimitConfig::default(), // unlimited
         }
     }
@@ -327,10 +352,21 @@ impl HttpClient {
 
         let mut ssl_connector_builder = SslConnector::builder(SslMethod::tls()).unwrap();
 
+        if let Some(ca_file) = &options.ca_file {
+            ssl_connector_builder
+                .set_ca_file(ca_file)
+                .map_err(|err| format_err!("unable to load CA file '{}' - {}", ca_file, err))?;
+        }
+
+        if let Some(version) = options.min_tls_version {
+            ssl_connector_builder.set_min_proto_version(Some(version))?;
+        }
+
         if options.verify_cert {
             let server = server.to_string();
             let verified_fingerprint = verified_fingerprint.clone();
             let interactive = options.interactive;
+            let verify_hostname = options.verify_hostname;
             let fingerprint_cache = options.fingerprint_cache;
             let prefix = options.prefix.clone();
             ssl_connector_builder.set_verify_callback(
@@ -340,6 +376,7 @@ impl HttpClient {
                     ctx,
                     expected_fingerprint.as_ref(),
                     interactive,
+                    verify_hostname,
                 ) {
                     Ok(None) => true,
                     Ok(Some(fingerprint)) => {
@@ -554,11 +591,16 @@ impl HttpClient {
         ctx: &mut X509StoreContextRef,
         expected_fingerprint: Option<&String>,
         interactive: bool,
+        verify_hostname: bool,
     ) -> Result<Option<String>, Error> {
         if openssl_valid {
             return Ok(None);
         }
 
+        if !verify_hostname && ctx.error().as_raw() == X509_V_ERR_HOSTNAME_MISMATCH {
+            return Ok(None);
+        }
+
         let cert = match ctx.current_cert() {
             Some(cert) => cert,
             None => bail!("context lacks current certificate."),
@@ -728,9 +770,20 @@ impl HttpClient {
 
     pub async fn start_h2_connection(
         &self,
-        mut req: Request<Body>,
+        req: Request<Body>,
         protocol_name: String,
     ) -> Result<(H2Client, futures::future::AbortHandle), Error> {
+        let (h2, abort, _headers) = self.start_h2_connection_full(req, protocol_name).await?;
+        Ok((h2, abort))
+    }
+
+    /// Like [start_h2_connection](Self::start_h2_connection), but also returns the headers of
+    /// the upgrade response, which contain the negotiated protocol features.
+    pub async fn start_h2_connection_full(
+        &self,
+        mut req: Request<Body>,
+        protocol_name: String,
+    ) -> Result<(H2Client, futures::future::AbortHandle, http::HeaderMap), Error> {
         let client = self.client.clone();
         let auth = self.login().await?;
 
@@ -770,6 +823,8 @@ impl HttpClient {
             bail!("unknown error");
         }
 
+        let headers = resp.headers().clone();
+
         let upgraded = hyper::upgrade::on(resp).await?;
 
         let max_window_size = (1 << 31) - 2;
@@ -794,7 +849,7 @@ impl HttpClient {
 
         // Wait until the `SendRequest` handle has available capacity.
         let c = h2.ready().await?;
-        Ok((H2Client::new(c), abort))
+        Ok((H2Client::new(c), abort, headers))
     }
 
     async fn credentials(
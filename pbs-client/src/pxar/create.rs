@@ -14,6 +14,7 @@ use nix::dir::Dir;
 use nix::errno::Errno;
 use nix::fcntl::OFlag;
 use nix::sys::stat::{FileStat, Mode};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
 
 use pathpatterns::{MatchEntry, MatchFlag, MatchList, MatchType, PatternFlag};
 use pxar::encoder::{LinkOffset, SeqWrite};
@@ -41,6 +42,84 @@ pub struct PxarCreateOptions {
     pub entries_max: usize,
     /// Skip lost+found directory
     pub skip_lost_and_found: bool,
+    /// Record a (non-cryptographic) xxh3 content hash for regular files in the catalog
+    pub catalog_content_hash: bool,
+    /// Skip regular files larger than this many bytes
+    pub max_file_size: Option<u64>,
+    /// Skip regular files last modified before this epoch
+    pub min_mtime: Option<i64>,
+}
+
+/// Statistics about the entries processed while creating an archive.
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveStatistics {
+    /// Number of entries added to the archive
+    pub entries: u64,
+    /// Number of regular files, not counting additional hardlinks
+    pub files: u64,
+    /// Total size of all regular files in bytes
+    pub file_bytes: u64,
+}
+
+impl std::ops::AddAssign<&ArchiveStatistics> for ArchiveStatistics {
+    fn add_assign(&mut self, other: &ArchiveStatistics) {
+        self.entries += other.entries;
+        self.files += other.files;
+        self.file_bytes += other.file_bytes;
+    }
+}
+
+/// Detects holes in sparse files via `SEEK_DATA`/`SEEK_HOLE`, so we can avoid reading them.
+#[derive(Default)]
+struct HoleDetector {
+    unsupported: bool,
+    /// End of the last detected data region, no need to check for holes before this.
+    data_end: u64,
+}
+
+impl HoleDetector {
+    /// Returns the length of the hole starting at `pos`, or 0 if there is data at `pos`.
+    ///
+    /// The file offset is left at the end of the returned hole.
+    fn hole_at(&mut self, fd: RawFd, pos: u64, file_size: u64) -> u64 {
+        if self.unsupported || pos < self.data_end {
+            return 0;
+        }
+
+        let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        let hole = if data >= 0 {
+            (data as u64).min(file_size).saturating_sub(pos)
+        } else if Errno::last() == Errno::ENXIO {
+            // no more data after pos - unless the file shrunk, which the read path reports
+            let end = unsafe { libc::lseek(fd, 0, libc::SEEK_END) };
+            if end >= 0 && end as u64 >= file_size {
+                file_size - pos
+            } else {
+                0
+            }
+        } else {
+            // e.g. EINVAL on file systems without SEEK_DATA support
+            self.unsupported = true;
+            0
+        };
+
+        if hole == 0 {
+            let data_end = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_HOLE) };
+            self.data_end = if data_end > 0 {
+                data_end as u64
+            } else {
+                file_size
+            };
+        }
+
+        let offset = unsafe { libc::lseek(fd, (pos + hole) as libc::off_t, libc::SEEK_SET) };
+        if offset < 0 {
+            // we cannot continue reading reliably, rely on plain reads only
+            self.unsupported = true;
+        }
+
+        hole
+    }
 }
 
 fn detect_fs_type(fd: RawFd) -> Result<i64, Error> {
@@ -157,6 +236,10 @@ struct Archiver {
     errors: ErrorReporter,
     logger: Logger,
     file_copy_buffer: Vec<u8>,
+    content_hash: bool,
+    max_file_size: Option<u64>,
+    min_mtime: Option<i64>,
+    stats: ArchiveStatistics,
 }
 
 type Encoder<'a, T> = pxar::encoder::aio::Encoder<'a, T>;
@@ -168,7 +251,7 @@ pub async fn create_archive<T, F>(
     callback: F,
     catalog: Option<Arc<Mutex<dyn BackupCatalogWriter + Send>>>,
     options: PxarCreateOptions,
-) -> Result<(), Error>
+) -> Result<ArchiveStatistics, Error>
 where
     T: SeqWrite + Send,
     F: FnMut(&Path) -> Result<(), Error> + Send + 'static,
@@ -223,13 +306,17 @@ where
         errors: ErrorReporter,
         logger: Logger,
         file_copy_buffer: vec::undefined(4 * 1024 * 1024),
+        content_hash: options.catalog_content_hash,
+        max_file_size: options.max_file_size,
+        min_mtime: options.min_mtime,
+        stats: ArchiveStatistics::default(),
     };
 
     archiver
         .archive_dir_contents(&mut encoder, source_dir, true)
         .await?;
     encoder.finish().await?;
-    Ok(())
+    Ok(archiver.stats)
 }
 
 struct FileListEntry {
@@ -426,10 +513,11 @@ impl Archiver {
     ) -> Result<(), Error> {
         let content = generate_pxar_excludes_cli(&self.patterns[..patterns_count]);
         if let Some(ref catalog) = self.catalog {
+            let csum = self.content_hash.then(|| xxh3_64(&content));
             catalog
                 .lock()
                 .unwrap()
-                .add_file(file_name, content.len() as u64, 0)?;
+                .add_file(file_name, content.len() as u64, 0, 0, csum)?;
         }
 
         let mut metadata = Metadata::default();
@@ -488,6 +576,10 @@ impl Archiver {
                 continue;
             }
 
+            if self.excluded_by_size_or_mtime(&stat) || self.excluded_by_file_type(&stat) {
+                continue;
+            }
+
             self.entry_counter += 1;
             if self.entry_counter > self.entry_limit {
                 bail!(
@@ -508,6 +600,31 @@ impl Archiver {
         Ok(file_list)
     }
 
+    /// Check the size and mtime limits, these only apply to regular files.
+    fn excluded_by_size_or_mtime(&self, stat: &FileStat) -> bool {
+        if (stat.st_mode & libc::S_IFMT) != libc::S_IFREG {
+            return false;
+        }
+
+        if matches!(self.max_file_size, Some(max) if stat.st_size as u64 > max) {
+            return true;
+        }
+
+        matches!(self.min_mtime, Some(min) if stat.st_mtime < min)
+    }
+
+    /// Check whether device nodes, fifos or sockets were disabled via the feature flags.
+    fn excluded_by_file_type(&self, stat: &FileStat) -> bool {
+        let flag = match stat.st_mode & libc::S_IFMT {
+            libc::S_IFCHR | libc::S_IFBLK => Flags::WITH_DEVICE_NODES,
+            libc::S_IFIFO => Flags::WITH_FIFOS,
+            libc::S_IFSOCK => Flags::WITH_SOCKETS,
+            _ => return false,
+        };
+
+        !self.feature_flags.contains(flag)
+    }
+
     fn report_vanished_file(&mut self) -> Result<(), Error> {
         writeln!(
             self.errors,
@@ -580,6 +697,8 @@ impl Archiver {
             return Ok(());
         }
 
+        self.stats.entries += 1;
+
         let file_name: &Path = OsStr::from_bytes(c_file_name.to_bytes()).as_ref();
         match metadata.file_type() {
             mode::IFREG => {
@@ -601,17 +720,25 @@ impl Archiver {
                 }
 
                 let file_size = stat.st_size as u64;
-                if let Some(ref catalog) = self.catalog {
-                    catalog
-                        .lock()
-                        .unwrap()
-                        .add_file(c_file_name, file_size, stat.st_mtime)?;
-                }
+                self.stats.files += 1;
+                self.stats.file_bytes += file_size;
 
-                let offset: LinkOffset = self
+                let (offset, csum) = self
                     .add_regular_file(encoder, fd, file_name, &metadata, file_size)
                     .await?;
 
+                // the catalog only writes out entries at the end of the directory, so adding the
+                // file after encoding it lets us include the content hash
+                if let Some(ref catalog) = self.catalog {
+                    catalog.lock().unwrap().add_file(
+                        c_file_name,
+                        file_size,
+                        stat.st_mtime,
+                        stat.st_ctime,
+                        csum,
+                    )?;
+                }
+
                 if stat.st_nlink > 1 {
                     self.hardlinks
                         .insert(link_info, (self.path.clone(), offset));
@@ -727,11 +854,31 @@ impl Archiver {
         file_name: &Path,
         metadata: &Metadata,
         file_size: u64,
-    ) -> Result<LinkOffset, Error> {
+    ) -> Result<(LinkOffset, Option<u64>), Error> {
         let mut file = unsafe { std::fs::File::from_raw_fd(fd.into_raw_fd()) };
+        let mut hasher = self.content_hash.then(Xxh3::new);
         let mut remaining = file_size;
+        let mut holes = HoleDetector::default();
         let mut out = encoder.create_file(metadata, file_name, file_size).await?;
         while remaining != 0 {
+            let hole = holes.hole_at(file.as_raw_fd(), file_size - remaining, file_size);
+            if hole > 0 {
+                // holes read as zeroes anyway, so just skip reading them
+                let to_zero = hole.min(self.file_copy_buffer.len() as u64) as usize;
+                vec::clear(&mut self.file_copy_buffer[..to_zero]);
+                let mut left = hole;
+                while left != 0 {
+                    let fill = left.min(self.file_copy_buffer.len() as u64) as usize;
+                    if let Some(hasher) = hasher.as_mut() {
+                        hasher.update(&self.file_copy_buffer[..fill]);
+                    }
+                    out.write_all(&self.file_copy_buffer[..fill]).await?;
+                    left -= fill as u64;
+                }
+                remaining -= hole;
+                continue;
+            }
+
             let mut got = match file.read(&mut self.file_copy_buffer[..]) {
                 Ok(0) => break,
                 Ok(got) => got,
@@ -742,6 +889,9 @@ impl Archiver {
                 self.report_file_grew_while_reading()?;
                 got = remaining as usize;
             }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&self.file_copy_buffer[..got]);
+            }
             out.write_all(&self.file_copy_buffer[..got]).await?;
             remaining -= got as u64;
         }
@@ -751,12 +901,15 @@ impl Archiver {
             vec::clear(&mut self.file_copy_buffer[..to_zero]);
             while remaining != 0 {
                 let fill = remaining.min(self.file_copy_buffer.len() as u64) as usize;
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.update(&self.file_copy_buffer[..fill]);
+                }
                 out.write_all(&self.file_copy_buffer[..fill]).await?;
                 remaining -= fill as u64;
             }
         }
 
-        Ok(out.file_offset())
+        Ok((out.file_offset(), hasher.map(|hasher| hasher.digest())))
     }
 
     async fn add_symlink<T: SeqWrite + Send>(
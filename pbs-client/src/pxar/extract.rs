@@ -1,5 +1,6 @@
 //! Code for extraction of pxar contents onto the file system.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::ffi::{CStr, CString, OsStr, OsString};
@@ -26,7 +27,10 @@ use proxmox_sys::fs::{create_path, CreateOptions};
 
 use proxmox_compression::zip::{ZipEncoder, ZipEntry};
 
+use pbs_api_types::DeviceNodeFallback;
+
 use crate::pxar::dir_stack::PxarDirStack;
+use crate::pxar::idmap::IdMapping;
 use crate::pxar::metadata;
 use crate::pxar::Flags;
 
@@ -36,6 +40,10 @@ pub struct PxarExtractOptions<'a> {
     pub allow_existing_dirs: bool,
     pub overwrite: bool,
     pub on_error: Option<ErrorHandler>,
+    /// Remap user and group IDs of the extracted entries
+    pub id_mapping: Option<IdMapping>,
+    /// What to do with device nodes we are not allowed to create
+    pub device_node_fallback: DeviceNodeFallback,
 }
 
 pub type ErrorHandler = Box<dyn FnMut(Error) -> Result<(), Error> + Send>;
@@ -77,9 +85,18 @@ where
     )
     .map_err(|err| format_err!("unable to open target directory {:?}: {}", destination, err,))?;
 
+    let id_mapping = options.id_mapping.filter(|mapping| !mapping.is_identity());
+    let map_metadata = |metadata: &Metadata| -> Result<Metadata, Error> {
+        let mut metadata = metadata.clone();
+        if let Some(ref id_mapping) = id_mapping {
+            id_mapping.apply(&mut metadata)?;
+        }
+        Ok(metadata)
+    };
+
     let mut extractor = Extractor::new(
         dir,
-        root.metadata().clone(),
+        map_metadata(root.metadata())?,
         options.allow_existing_dirs,
         options.overwrite,
         feature_flags,
@@ -88,6 +105,7 @@ where
     if let Some(on_error) = options.on_error {
         extractor.on_error(on_error);
     }
+    extractor.set_device_node_fallback(options.device_node_fallback);
 
     let mut match_stack = Vec::new();
     let mut err_path_stack = vec![OsString::from("/")];
@@ -105,7 +123,12 @@ where
         let file_name = CString::new(file_name_os.as_bytes())
             .map_err(|_| format_err!("encountered file name with null-bytes"))?;
 
-        let metadata = entry.metadata();
+        let metadata = if id_mapping.is_some() {
+            Cow::Owned(map_metadata(entry.metadata())?)
+        } else {
+            Cow::Borrowed(entry.metadata())
+        };
+        let metadata = metadata.as_ref();
 
         extractor.set_path(entry.path().as_os_str().to_owned());
 
@@ -227,6 +250,8 @@ pub struct Extractor {
     /// Error callback. Includes `current_path` in the reformatted error, should return `Ok` to
     /// continue extracting or the passed error as `Err` to bail out.
     on_error: ErrorHandler,
+
+    device_node_fallback: DeviceNodeFallback,
 }
 
 impl Extractor {
@@ -245,9 +270,14 @@ impl Extractor {
             feature_flags,
             current_path: Arc::new(Mutex::new(OsString::new())),
             on_error: Box::new(Err),
+            device_node_fallback: DeviceNodeFallback::Fail,
         }
     }
 
+    pub fn set_device_node_fallback(&mut self, fallback: DeviceNodeFallback) {
+        self.device_node_fallback = fallback;
+    }
+
     /// We call this on errors. The error will be reformatted to include `current_path`. The
     /// callback should decide whether this error was fatal (simply return it) to bail out early,
     /// or log/remember/accumulate errors somewhere and return `Ok(())` in its place to continue
@@ -344,15 +374,22 @@ impl Extractor {
         let parent = self.parent_fd()?;
         let root = self.dir_stack.root_dir_fd()?;
         let target = CString::new(link.as_bytes())?;
-        nix::unistd::linkat(
+        match nix::unistd::linkat(
             Some(root.as_raw_fd()),
             target.as_c_str(),
             Some(parent),
             file_name,
             nix::unistd::LinkatFlags::NoSymlinkFollow,
-        )?;
-
-        Ok(())
+        ) {
+            Ok(()) => Ok(()),
+            // the first occurrence of a hardlinked file carries the content, if that one was not
+            // extracted (e.g. excluded by a pattern) we cannot recreate the link
+            Err(nix::errno::Errno::ENOENT) => bail!(
+                "hardlink target {:?} was not extracted, cannot recreate link",
+                link
+            ),
+            Err(err) => Err(err.into()),
+        }
     }
 
     pub fn extract_device(
@@ -361,7 +398,51 @@ impl Extractor {
         metadata: &Metadata,
         device: &Device,
     ) -> Result<(), Error> {
-        self.extract_special(file_name, metadata, device.to_dev_t())
+        let mode = special_file_mode(metadata)?;
+        let parent = self.parent_fd()?;
+        match unsafe {
+            c_result!(libc::mknodat(
+                parent,
+                file_name.as_ptr(),
+                mode,
+                device.to_dev_t()
+            ))
+        } {
+            Ok(_) => (),
+            Err(nix::errno::Errno::EPERM)
+                if self.device_node_fallback == DeviceNodeFallback::Skip =>
+            {
+                log::warn!(
+                    "skipping device node {:?}: not permitted",
+                    self.dir_stack
+                        .path()
+                        .join(OsStr::from_bytes(file_name.to_bytes()))
+                );
+                return Ok(());
+            }
+            Err(nix::errno::Errno::EPERM)
+                if self.device_node_fallback == DeviceNodeFallback::EmptyFile =>
+            {
+                nix::fcntl::openat(
+                    parent,
+                    file_name,
+                    OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_WRONLY | OFlag::O_CLOEXEC,
+                    Mode::from_bits_truncate(0o600),
+                )
+                .and_then(nix::unistd::close)
+                .map_err(|err| format_err!("failed to create placeholder file: {}", err))?;
+            }
+            Err(err) => bail!("failed to create device node: {}", err),
+        }
+
+        metadata::apply_at(
+            self.feature_flags,
+            metadata,
+            parent,
+            file_name,
+            self.dir_stack.path(),
+            &mut self.on_error,
+        )
     }
 
     pub fn extract_special(
@@ -370,14 +451,7 @@ impl Extractor {
         metadata: &Metadata,
         device: libc::dev_t,
     ) -> Result<(), Error> {
-        let mode = metadata.stat.mode;
-        let mode = u32::try_from(mode).map_err(|_| {
-            format_err!(
-                "device node's mode contains illegal bits: 0x{:x} (0o{:o})",
-                mode,
-                mode,
-            )
-        })?;
+        let mode = special_file_mode(metadata)?;
         let parent = self.parent_fd()?;
         unsafe { c_result!(libc::mknodat(parent, file_name.as_ptr(), mode, device)) }
             .map_err(|err| format_err!("failed to create device node: {}", err))?;
@@ -913,6 +987,17 @@ fn extract_special(
     Ok(())
 }
 
+fn special_file_mode(metadata: &Metadata) -> Result<u32, Error> {
+    let mode = metadata.stat.mode;
+    u32::try_from(mode).map_err(|_| {
+        format_err!(
+            "device node's mode contains illegal bits: 0x{:x} (0o{:o})",
+            mode,
+            mode,
+        )
+    })
+}
+
 fn get_filename(entry: &Entry) -> Result<(OsString, CString), Error> {
     let file_name_os = entry.file_name().to_owned();
 
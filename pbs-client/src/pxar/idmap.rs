@@ -0,0 +1,104 @@
+//! User and group ID remapping for extraction
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use anyhow::{bail, format_err, Error};
+
+use pxar::Metadata;
+
+/// Maps user and group IDs of archive entries to new values when extracting.
+///
+/// Explicit mappings take precedence, all other IDs are shifted by the respective offset. This is
+/// for example needed to restore the content of an unprivileged container into a different ID
+/// mapping range.
+#[derive(Clone, Debug, Default)]
+pub struct IdMapping {
+    pub uid_offset: u32,
+    pub gid_offset: u32,
+    pub users: HashMap<u32, u32>,
+    pub groups: HashMap<u32, u32>,
+}
+
+impl IdMapping {
+    /// Parse a single `old=new` ID mapping entry.
+    pub fn parse_entry(entry: &str) -> Result<(u32, u32), Error> {
+        let (old, new) = entry
+            .split_once('=')
+            .ok_or_else(|| format_err!("invalid id mapping '{}', expected 'old=new'", entry))?;
+        Ok((old.trim().parse()?, new.trim().parse()?))
+    }
+
+    /// Returns true if this mapping does not change any IDs.
+    pub fn is_identity(&self) -> bool {
+        self.uid_offset == 0
+            && self.gid_offset == 0
+            && self.users.is_empty()
+            && self.groups.is_empty()
+    }
+
+    pub fn map_uid(&self, uid: u32) -> Result<u32, Error> {
+        Self::map_id(uid, self.uid_offset, &self.users, "uid")
+    }
+
+    pub fn map_gid(&self, gid: u32) -> Result<u32, Error> {
+        Self::map_id(gid, self.gid_offset, &self.groups, "gid")
+    }
+
+    fn map_id(id: u32, offset: u32, map: &HashMap<u32, u32>, kind: &str) -> Result<u32, Error> {
+        if let Some(mapped) = map.get(&id) {
+            return Ok(*mapped);
+        }
+        match id.checked_add(offset) {
+            Some(mapped) => Ok(mapped),
+            None => bail!(
+                "{} {} out of range after adding offset {}",
+                kind,
+                id,
+                offset
+            ),
+        }
+    }
+
+    fn map_acl_id(id: u64, map: impl Fn(u32) -> Result<u32, Error>) -> Result<u64, Error> {
+        Ok(u64::from(map(u32::try_from(id)?)?))
+    }
+
+    /// Apply the mapping to the owner and ACL entries of `metadata`.
+    pub fn apply(&self, metadata: &mut Metadata) -> Result<(), Error> {
+        metadata.stat.uid = self.map_uid(metadata.stat.uid)?;
+        metadata.stat.gid = self.map_gid(metadata.stat.gid)?;
+
+        let acl = &mut metadata.acl;
+        for user in acl.users.iter_mut().chain(acl.default_users.iter_mut()) {
+            user.uid = Self::map_acl_id(user.uid, |uid| self.map_uid(uid))?;
+        }
+        for group in acl.groups.iter_mut().chain(acl.default_groups.iter_mut()) {
+            group.gid = Self::map_acl_id(group.gid, |gid| self.map_gid(gid))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_id_mapping() {
+    let mut mapping = IdMapping {
+        uid_offset: 100000,
+        gid_offset: 100000,
+        ..Default::default()
+    };
+    mapping.users.insert(0, 1000);
+    mapping
+        .groups
+        .extend([IdMapping::parse_entry("33 = 2000").unwrap()]);
+
+    assert_eq!(mapping.map_uid(0).unwrap(), 1000);
+    assert_eq!(mapping.map_uid(33).unwrap(), 100033);
+    assert_eq!(mapping.map_gid(33).unwrap(), 2000);
+    assert_eq!(mapping.map_gid(0).unwrap(), 100000);
+    assert!(mapping.map_uid(u32::MAX).is_err());
+    assert!(IdMapping::parse_entry("1000").is_err());
+    assert!(!mapping.is_identity());
+    assert!(IdMapping::default().is_identity());
+}
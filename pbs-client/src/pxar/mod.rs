@@ -51,17 +51,19 @@ pub(crate) mod create;
 pub(crate) mod dir_stack;
 pub(crate) mod extract;
 pub mod fuse;
+pub(crate) mod idmap;
 pub(crate) mod metadata;
 pub(crate) mod tools;
 
 mod flags;
 pub use flags::Flags;
 
-pub use create::{create_archive, PxarCreateOptions};
+pub use create::{create_archive, ArchiveStatistics, PxarCreateOptions};
 pub use extract::{
     create_tar, create_zip, extract_archive, extract_sub_dir, extract_sub_dir_seq, ErrorHandler,
     PxarExtractOptions,
 };
+pub use idmap::IdMapping;
 
 /// The format requires to build sorted directory lookup tables in
 /// memory, so we restrict the number of allowed entries to limit
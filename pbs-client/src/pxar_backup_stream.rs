@@ -17,6 +17,8 @@ use proxmox_io::StdChannelWriter;
 
 use pbs_datastore::catalog::CatalogWriter;
 
+use crate::pxar::ArchiveStatistics;
+
 /// Stream implementation to encode and upload .pxar archives.
 ///
 /// The hyper client needs an async Stream for file upload, so we
@@ -26,6 +28,7 @@ pub struct PxarBackupStream {
     rx: Option<std::sync::mpsc::Receiver<Result<Vec<u8>, Error>>>,
     handle: Option<AbortHandle>,
     error: Arc<Mutex<Option<String>>>,
+    stats: Arc<Mutex<Option<ArchiveStatistics>>>,
 }
 
 impl Drop for PxarBackupStream {
@@ -39,6 +42,7 @@ impl PxarBackupStream {
     pub fn new<W: Write + Send + 'static>(
         dir: Dir,
         catalog: Arc<Mutex<CatalogWriter<W>>>,
+        feature_flags: crate::pxar::Flags,
         options: crate::pxar::PxarCreateOptions,
     ) -> Result<Self, Error> {
         let (tx, rx) = std::sync::mpsc::sync_channel(10);
@@ -47,6 +51,8 @@ impl PxarBackupStream {
 
         let error = Arc::new(Mutex::new(None));
         let error2 = Arc::clone(&error);
+        let stats = Arc::new(Mutex::new(None));
+        let stats2 = Arc::clone(&stats);
         let handler = async move {
             let writer = TokioWriterAdapter::new(std::io::BufWriter::with_capacity(
                 buffer_size,
@@ -54,10 +60,10 @@ impl PxarBackupStream {
             ));
 
             let writer = pxar::encoder::sync::StandardWriter::new(writer);
-            if let Err(err) = crate::pxar::create_archive(
+            match crate::pxar::create_archive(
                 dir,
                 writer,
-                crate::pxar::Flags::DEFAULT,
+                feature_flags,
                 move |path| {
                     log::debug!("{:?}", path);
                     Ok(())
@@ -67,8 +73,11 @@ impl PxarBackupStream {
             )
             .await
             {
-                let mut error = error2.lock().unwrap();
-                *error = Some(err.to_string());
+                Ok(archive_stats) => *stats2.lock().unwrap() = Some(archive_stats),
+                Err(err) => {
+                    let mut error = error2.lock().unwrap();
+                    *error = Some(err.to_string());
+                }
             }
         };
 
@@ -80,17 +89,24 @@ impl PxarBackupStream {
             rx: Some(rx),
             handle: Some(handle),
             error,
+            stats,
         })
     }
 
+    /// Get a handle to the statistics of the archive, set once encoding finished successfully.
+    pub fn statistics(&self) -> Arc<Mutex<Option<ArchiveStatistics>>> {
+        Arc::clone(&self.stats)
+    }
+
     pub fn open<W: Write + Send + 'static>(
         dirname: &Path,
         catalog: Arc<Mutex<CatalogWriter<W>>>,
+        feature_flags: crate::pxar::Flags,
         options: crate::pxar::PxarCreateOptions,
     ) -> Result<Self, Error> {
         let dir = nix::dir::Dir::open(dirname, OFlag::O_DIRECTORY, Mode::empty())?;
 
-        Self::new(dir, catalog, options)
+        Self::new(dir, catalog, feature_flags, options)
     }
 }
 
@@ -1,18 +1,23 @@
-use std::collections::HashMap;
 use std::future::Future;
+use std::io::Read;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
+use futures::future::TryFutureExt;
+use futures::stream::{Stream, StreamExt};
 
 use proxmox_async::runtime::block_on;
 
 use pbs_api_types::CryptMode;
+use pbs_datastore::chunk_cache::ChunkCache;
 use pbs_datastore::data_blob::DataBlob;
 use pbs_datastore::read_chunk::AsyncReadChunk;
 use pbs_datastore::read_chunk::ReadChunk;
 use pbs_tools::crypt_config::CryptConfig;
 
+pub use pbs_datastore::chunk_cache::{ChunkCacheStats, DEFAULT_CHUNK_CACHE_SIZE};
+
 use super::BackupReader;
 
 /// Read chunks from remote host using ``BackupReader``
@@ -21,29 +26,33 @@ pub struct RemoteChunkReader {
     client: Arc<BackupReader>,
     crypt_config: Option<Arc<CryptConfig>>,
     crypt_mode: CryptMode,
-    cache_hint: Arc<HashMap<[u8; 32], usize>>,
-    cache: Arc<Mutex<HashMap<[u8; 32], Vec<u8>>>>,
+    cache: Arc<Mutex<ChunkCache>>,
 }
 
 impl RemoteChunkReader {
     /// Create a new instance.
     ///
-    /// Chunks listed in ``cache_hint`` are cached and kept in RAM.
+    /// Decoded chunks are kept in a LRU cache using up to `cache_size` bytes of RAM, use
+    /// [DEFAULT_CHUNK_CACHE_SIZE] if unsure. A `cache_size` of zero disables the cache.
     pub fn new(
         client: Arc<BackupReader>,
         crypt_config: Option<Arc<CryptConfig>>,
         crypt_mode: CryptMode,
-        cache_hint: HashMap<[u8; 32], usize>,
+        cache_size: usize,
     ) -> Self {
         Self {
             client,
             crypt_config,
             crypt_mode,
-            cache_hint: Arc::new(cache_hint),
-            cache: Arc::new(Mutex::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(ChunkCache::new(cache_size))),
         }
     }
 
+    /// Returns the hit statistics and current usage of the chunk cache.
+    pub fn cache_stats(&self) -> ChunkCacheStats {
+        self.cache.lock().unwrap().stats()
+    }
+
     /// Downloads raw chunk. This only verifies the (untrusted) CRC32, use
     /// DataBlob::verify_unencrypted or DataBlob::decode before storing/processing further.
     pub async fn read_raw_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
@@ -66,6 +75,73 @@ impl RemoteChunkReader {
             },
         }
     }
+
+    /// Returns a stream of the decoded chunks listed in `digests`, in that order.
+    ///
+    /// Up to `window` chunks are downloaded and decoded in parallel, to not be limited by the
+    /// latency of the single requests.
+    pub fn read_chunks_ordered(
+        &self,
+        digests: Vec<[u8; 32]>,
+        window: usize,
+    ) -> impl Stream<Item = Result<Vec<u8>, Error>> + Send + 'static {
+        let reader = self.clone();
+        futures::stream::iter(digests)
+            .map(move |digest| {
+                let reader = reader.clone();
+                tokio::spawn(async move { AsyncReadChunk::read_chunk(&reader, &digest).await })
+                    .map_err(|err| format_err!("chunk download task failed - {}", err))
+            })
+            .buffered(window.max(1))
+            .map(|result| result.and_then(|data| data))
+    }
+}
+
+/// Sequential reader over the data of a list of chunks, fetching up to `window` chunks in
+/// parallel.
+///
+/// Use this instead of a `BufferedDynamicReader` if the data is only read once from start to
+/// end, for example when restoring an archive.
+pub struct PrefetchingChunkReader {
+    stream: Pin<Box<dyn Stream<Item = Result<Vec<u8>, Error>> + Send>>,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl PrefetchingChunkReader {
+    pub fn new(reader: &RemoteChunkReader, digests: Vec<[u8; 32]>, window: usize) -> Self {
+        Self {
+            stream: Box::pin(reader.read_chunks_ordered(digests, window)),
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for PrefetchingChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.buffer.len() {
+            match block_on(self.stream.next()) {
+                Some(Ok(data)) => {
+                    self.buffer = data;
+                    self.pos = 0;
+                }
+                Some(Err(err)) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        err.to_string(),
+                    ))
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
 }
 
 impl ReadChunk for RemoteChunkReader {
@@ -74,18 +150,19 @@ impl ReadChunk for RemoteChunkReader {
     }
 
     fn read_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
-        if let Some(raw_data) = (*self.cache.lock().unwrap()).get(digest) {
-            return Ok(raw_data.to_vec());
+        // the cache is only used by this reader, so cached chunks have the checked crypt mode
+        if let Some((_crypt_mode, raw_data)) = self.cache.lock().unwrap().get(digest) {
+            return Ok(raw_data);
         }
 
         let chunk = ReadChunk::read_raw_chunk(self, digest)?;
 
         let raw_data = chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
 
-        let use_cache = self.cache_hint.contains_key(digest);
-        if use_cache {
-            (*self.cache.lock().unwrap()).insert(*digest, raw_data.to_vec());
-        }
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(digest, chunk.crypt_mode()?, &raw_data);
 
         Ok(raw_data)
     }
@@ -104,8 +181,8 @@ impl AsyncReadChunk for RemoteChunkReader {
         digest: &'a [u8; 32],
     ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + Send + 'a>> {
         Box::pin(async move {
-            if let Some(raw_data) = (*self.cache.lock().unwrap()).get(digest) {
-                return Ok(raw_data.to_vec());
+            if let Some((_crypt_mode, raw_data)) = self.cache.lock().unwrap().get(digest) {
+                return Ok(raw_data);
             }
 
             let chunk = Self::read_raw_chunk(self, digest).await?;
@@ -113,10 +190,10 @@ impl AsyncReadChunk for RemoteChunkReader {
             let raw_data =
                 chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
 
-            let use_cache = self.cache_hint.contains_key(digest);
-            if use_cache {
-                (*self.cache.lock().unwrap()).insert(*digest, raw_data.to_vec());
-            }
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(digest, chunk.crypt_mode()?, &raw_data);
 
             Ok(raw_data)
         })
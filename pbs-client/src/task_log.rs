@@ -24,6 +24,18 @@ pub async fn display_task_log(
     client: &HttpClient,
     upid_str: &str,
     strip_date: bool,
+) -> Result<(), Error> {
+    display_task_log_full(client, upid_str, strip_date, true).await
+}
+
+/// Display task log on console
+///
+/// Like [display_task_log], but only waits for new lines of a running task if `follow` is set.
+pub async fn display_task_log_full(
+    client: &HttpClient,
+    upid_str: &str,
+    strip_date: bool,
+    follow: bool,
 ) -> Result<(), Error> {
     let mut signal_stream = signal(SignalKind::interrupt())?;
     let abort_count = Arc::new(AtomicUsize::new(0));
@@ -85,7 +97,7 @@ pub async fn display_task_log(
             }
 
             if start > total {
-                if active {
+                if active && follow {
                     tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
                 } else {
                     break;
@@ -24,6 +24,11 @@ pub const KEYFD_SCHEMA: Schema =
         .minimum(0)
         .schema();
 
+pub const LEGACY_KEYFILE_SCHEMA: Schema = StringSchema::new(
+    "Path to a previous encryption key. Snapshots encrypted with it are read using this key.",
+)
+.schema();
+
 pub const MASTER_PUBKEY_FILE_SCHEMA: Schema = StringSchema::new(
     "Path to master public key. The encryption key used for a backup will be encrypted using this key and appended to the backup.")
     .schema();
@@ -359,6 +364,31 @@ pub fn get_encryption_key_password() -> Result<Vec<u8>, Error> {
     bail!("no password input mechanism available");
 }
 
+pub fn get_legacy_encryption_key_password() -> Result<Vec<u8>, Error> {
+    if let Some(password) = super::get_secret_from_env("PBS_LEGACY_ENCRYPTION_PASSWORD")? {
+        return Ok(password.as_bytes().to_vec());
+    }
+
+    // If we're on a TTY, query the user for a password
+    if tty::stdin_isatty() {
+        return tty::read_password("Legacy Encryption Key Password: ");
+    }
+
+    bail!("no password input mechanism available");
+}
+
+/// Returns the legacy encryption key passed with `--legacy-keyfile`, if any.
+pub fn legacy_key_parameter(param: &Value) -> Result<Option<KeyWithSource>, Error> {
+    match param.get("legacy-keyfile") {
+        Some(Value::String(keyfile)) => Ok(Some(KeyWithSource::from_path(
+            keyfile.clone(),
+            file_get_contents(keyfile)?,
+        ))),
+        Some(_) => bail!("bad --legacy-keyfile parameter type"),
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 fn create_testdir(name: &str) -> Result<String, Error> {
     // FIXME:
@@ -13,9 +13,11 @@ use xdg::BaseDirectories;
 use proxmox_http::uri::json_object_to_query;
 use proxmox_router::cli::{complete_file_name, shellword_split};
 use proxmox_schema::*;
-use proxmox_sys::fs::file_get_json;
+use proxmox_sys::fs::{file_get_json, replace_file, CreateOptions};
 
-use pbs_api_types::{Authid, BackupNamespace, RateLimitConfig, UserWithTokens, BACKUP_REPO_URL};
+use pbs_api_types::{
+    Authid, BackupNamespace, HumanByte, RateLimitConfig, UserWithTokens, BACKUP_REPO_URL,
+};
 
 use crate::{BackupRepository, HttpClient, HttpClientOptions};
 
@@ -23,18 +25,40 @@ pub mod key_source;
 
 const ENV_VAR_PBS_FINGERPRINT: &str = "PBS_FINGERPRINT";
 const ENV_VAR_PBS_PASSWORD: &str = "PBS_PASSWORD";
+const ENV_VAR_PBS_CHUNK_CACHE_SIZE: &str = "PBS_CHUNK_CACHE_SIZE";
 
 pub const REPO_URL_SCHEMA: Schema = StringSchema::new("Repository URL.")
     .format(&BACKUP_REPO_URL)
     .max_length(256)
     .schema();
 
+/// Time in seconds for which completion results queried from the server are reused.
+const COMPLETION_CACHE_TIMEOUT: i64 = 30;
+
 pub const CHUNK_SIZE_SCHEMA: Schema = IntegerSchema::new("Chunk size in KB. Must be a power of 2.")
     .minimum(64)
     .maximum(4096)
     .default(4096)
     .schema();
 
+/// Returns the memory budget for caching decoded chunks while reading from a remote.
+///
+/// Uses the size set in `PBS_CHUNK_CACHE_SIZE` (e.g. `256 MiB`, plain numbers are bytes), or
+/// [DEFAULT_CHUNK_CACHE_SIZE](crate::DEFAULT_CHUNK_CACHE_SIZE) if unset. Zero disables the
+/// cache.
+pub fn chunk_cache_size() -> Result<usize, Error> {
+    match std::env::var(ENV_VAR_PBS_CHUNK_CACHE_SIZE) {
+        Ok(size) => {
+            let size: HumanByte = size.trim().parse().map_err(|err| {
+                format_err!("invalid {ENV_VAR_PBS_CHUNK_CACHE_SIZE} '{size}' - {err}")
+            })?;
+            Ok(size.as_u64() as usize)
+        }
+        Err(NotPresent) => Ok(crate::DEFAULT_CHUNK_CACHE_SIZE),
+        Err(NotUnicode(_)) => bail!("{ENV_VAR_PBS_CHUNK_CACHE_SIZE} contains bad characters"),
+    }
+}
+
 /// Helper to read a secret through a environment variable (ENV).
 ///
 /// Tries the following variable names in order and returns the value
@@ -143,6 +167,20 @@ pub fn connect(repo: &BackupRepository) -> Result<HttpClient, Error> {
         .map_err(|err| format_err!("error building client for repository {} - {}", repo, err))
 }
 
+/// Build a traffic control configuration from the optional `rate` and `burst` parameters.
+pub fn rate_limit_from_param(param: &Value) -> Result<RateLimitConfig, Error> {
+    let rate = match param["rate"].as_str() {
+        Some(s) => Some(s.parse::<HumanByte>()?),
+        None => None,
+    };
+    let burst = match param["burst"].as_str() {
+        Some(s) => Some(s.parse::<HumanByte>()?),
+        None => None,
+    };
+
+    Ok(RateLimitConfig::with_same_inout(rate, burst))
+}
+
 pub fn connect_rate_limited(
     repo: &BackupRepository,
     rate_limit: RateLimitConfig,
@@ -191,6 +229,77 @@ pub async fn try_get(repo: &BackupRepository, url: &str) -> Value {
     Value::Null
 }
 
+/// like try_get, but reuse results of the same query for [COMPLETION_CACHE_TIMEOUT] seconds
+///
+/// Shell completion runs a new process for every key press, so this avoids querying the server
+/// over and over while the user is typing.
+pub async fn try_get_cached(repo: &BackupRepository, url: &str) -> Value {
+    let base = match BaseDirectories::with_prefix("proxmox-backup") {
+        Ok(v) => v,
+        _ => return try_get(repo, url).await,
+    };
+
+    // usually $HOME/.cache/proxmox-backup/completion-cache
+    let path = match base.place_cache_file("completion-cache") {
+        Ok(v) => v,
+        _ => return try_get(repo, url).await,
+    };
+
+    let now = proxmox_time::epoch_i64();
+    let key = format!("{} {}", repo, url);
+
+    let mut cache = file_get_json(&path, None).unwrap_or_else(|_| json!({}));
+    if let Some(map) = cache.as_object_mut() {
+        let expired: Vec<String> = map
+            .iter()
+            .filter(|(_, entry)| {
+                !matches!(entry["time"].as_i64(), Some(time) if time + COMPLETION_CACHE_TIMEOUT > now)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            map.remove(&key);
+        }
+        if let Some(entry) = map.get_mut(&key) {
+            return entry["data"].take();
+        }
+    } else {
+        cache = json!({});
+    }
+
+    let data = try_get(repo, url).await;
+    if data.is_null() {
+        return data;
+    }
+
+    cache[&key] = json!({ "time": now, "data": data });
+
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
+    let _ = replace_file(
+        path,
+        cache.to_string().as_bytes(),
+        CreateOptions::new().perm(mode),
+        false,
+    );
+
+    cache[&key]["data"].take()
+}
+
+// the namespace given on the command line, as API query parameters
+fn completion_ns_query(param: &HashMap<String, String>) -> Option<String> {
+    let ns: BackupNamespace = match param.get("ns") {
+        Some(ns) => ns.parse().ok()?,
+        None => return Some(String::new()),
+    };
+    if ns.is_root() {
+        return Some(String::new());
+    }
+    Some(format!(
+        "?{}",
+        json_object_to_query(json!({ "ns": ns })).ok()?
+    ))
+}
+
 pub fn complete_backup_group(_arg: &str, param: &HashMap<String, String>) -> Vec<String> {
     proxmox_async::runtime::main(async { complete_backup_group_do(param).await })
 }
@@ -203,9 +312,14 @@ pub async fn complete_backup_group_do(param: &HashMap<String, String>) -> Vec<St
         _ => return result,
     };
 
-    let path = format!("api2/json/admin/datastore/{}/groups", repo.store());
+    let query = match completion_ns_query(param) {
+        Some(query) => query,
+        None => return result,
+    };
 
-    let data = try_get(&repo, &path).await;
+    let path = format!("api2/json/admin/datastore/{}/groups{}", repo.store(), query);
+
+    let data = try_get_cached(&repo, &path).await;
 
     if let Some(list) = data.as_array() {
         for item in list {
@@ -253,9 +367,18 @@ pub async fn complete_backup_snapshot_do(param: &HashMap<String, String>) -> Vec
         _ => return result,
     };
 
-    let path = format!("api2/json/admin/datastore/{}/snapshots", repo.store());
+    let query = match completion_ns_query(param) {
+        Some(query) => query,
+        None => return result,
+    };
 
-    let data = try_get(&repo, &path).await;
+    let path = format!(
+        "api2/json/admin/datastore/{}/snapshots{}",
+        repo.store(),
+        query
+    );
+
+    let data = try_get_cached(&repo, &path).await;
 
     if let Value::Array(list) = data {
         for item in list {
@@ -9,9 +9,12 @@ use anyhow::{bail, Error};
 use lazy_static::lazy_static;
 
 use proxmox_schema::{ApiStringFormat, ApiType, Schema, StringSchema};
+use proxmox_section_config::SectionConfigData;
 
 use pbs_api_types::{Authid, Role, Userid, ROLE_NAME_NO_ACCESS};
 
+pub use pbs_api_types::{check_acl_path, split_acl_path};
+
 use crate::{open_backup_lockfile, replace_backup_config, BackupLockGuard};
 
 lazy_static! {
@@ -34,135 +37,53 @@ lazy_static! {
     };
 }
 
-pub fn split_acl_path(path: &str) -> Vec<&str> {
-    let items = path.split('/');
-
-    let mut components = vec![];
-
-    for name in items {
-        if name.is_empty() {
-            continue;
-        }
-        components.push(name);
-    }
-
-    components
-}
-
-/// Check whether a given ACL `path` conforms to the expected schema.
+/// Check whether the objects referenced by a given ACL `path` actually exist.
 ///
-/// Currently this just checks for the number of components for various sub-trees.
-pub fn check_acl_path(path: &str) -> Result<(), Error> {
+/// Paths for datastores, remotes, tape devices, media pools, tape jobs and OpenID realms are
+/// checked against the respective configuration, so that we do not silently create ACL entries
+/// that can never match. The path is expected to already pass [check_acl_path].
+pub fn check_acl_path_objects(path: &str) -> Result<(), Error> {
     let components = split_acl_path(path);
 
-    let components_len = components.len();
-
-    if components_len == 0 {
-        return Ok(());
-    }
-    match components[0] {
-        "access" => {
-            if components_len == 1 {
-                return Ok(());
-            }
-            match components[1] {
-                "acl" | "users" | "domains" => {
-                    if components_len == 2 {
-                        return Ok(());
-                    }
-                }
-                // /access/openid/{endpoint}
-                "openid" => {
-                    if components_len <= 3 {
-                        return Ok(());
-                    }
-                }
-                _ => {}
+    match components.as_slice() {
+        ["datastore", store, ..] => {
+            if !section_exists(crate::datastore::config()?.0, store) {
+                bail!("acl path '{}' - no such datastore '{}'", path, store);
             }
         }
-        "datastore" => {
-            // /datastore/{store}
-            if components_len <= 2 {
-                return Ok(());
-            }
-            if components_len > 2 && components_len <= 2 + pbs_api_types::MAX_NAMESPACE_DEPTH {
-                return Ok(());
+        ["remote", remote, ..] => {
+            if !section_exists(crate::remote::config()?.0, remote) {
+                bail!("acl path '{}' - no such remote '{}'", path, remote);
             }
         }
-        "remote" => {
-            // /remote/{remote}/{store}
-            if components_len <= 3 {
-                return Ok(());
+        ["tape", "device", name] => {
+            if !section_exists(crate::drive::config()?.0, name) {
+                bail!("acl path '{}' - no such tape device '{}'", path, name);
             }
         }
-        "system" => {
-            if components_len == 1 {
-                return Ok(());
-            }
-            match components[1] {
-                "certificates" | "disks" | "log" | "status" | "tasks" | "time" => {
-                    if components_len == 2 {
-                        return Ok(());
-                    }
-                }
-                "services" => {
-                    // /system/services/{service}
-                    if components_len <= 3 {
-                        return Ok(());
-                    }
-                }
-                "network" => {
-                    if components_len == 2 {
-                        return Ok(());
-                    }
-                    match components[2] {
-                        "dns" => {
-                            if components_len == 3 {
-                                return Ok(());
-                            }
-                        }
-                        "interfaces" => {
-                            // /system/network/interfaces/{iface}
-                            if components_len <= 4 {
-                                return Ok(());
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                _ => {}
+        ["tape", "pool", name] => {
+            if !section_exists(crate::media_pool::config()?.0, name) {
+                bail!("acl path '{}' - no such media pool '{}'", path, name);
             }
         }
-        "tape" => {
-            if components_len == 1 {
-                return Ok(());
+        ["tape", "job", id] => {
+            if !section_exists(crate::tape_job::config()?.0, id) {
+                bail!("acl path '{}' - no such tape backup job '{}'", path, id);
             }
-            match components[1] {
-                "device" => {
-                    // /tape/device/{name}
-                    if components_len <= 3 {
-                        return Ok(());
-                    }
-                }
-                "pool" => {
-                    // /tape/pool/{name}
-                    if components_len <= 3 {
-                        return Ok(());
-                    }
-                }
-                "job" => {
-                    // /tape/job/{id}
-                    if components_len <= 3 {
-                        return Ok(());
-                    }
-                }
-                _ => {}
+        }
+        ["access", "openid", realm] => {
+            if !section_exists(crate::domains::config()?.0, realm) {
+                bail!("acl path '{}' - no such realm '{}'", path, realm);
             }
         }
         _ => {}
     }
 
-    bail!("invalid acl path '{}'.", path);
+    Ok(())
+}
+
+fn section_exists(config: SectionConfigData, id: &str) -> bool {
+    config.sections.contains_key(id)
 }
 
 /// Tree representing a parsed acl.cfg
@@ -26,9 +26,68 @@ pub enum KeyDerivationConfig {
         #[serde(with = "proxmox_serde::bytes_as_base64")]
         salt: Vec<u8>,
     },
+    Argon2id {
+        /// memory cost in KiB
+        memory: u32,
+        iterations: u32,
+        parallelism: u32,
+        #[serde(with = "proxmox_serde::bytes_as_base64")]
+        salt: Vec<u8>,
+    },
 }
 
+/// Default Argon2id memory cost in KiB
+pub const DEFAULT_ARGON2_MEMORY: u32 = 64 * 1024;
+/// Default number of Argon2id iterations
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 3;
+
 impl KeyDerivationConfig {
+    /// Create a configuration using `kdf` with default parameters and a random salt.
+    pub fn new(kdf: Kdf) -> Result<Self, Error> {
+        let salt = proxmox_sys::linux::random_data(32)?;
+
+        Ok(match kdf {
+            Kdf::Scrypt => KeyDerivationConfig::Scrypt {
+                n: 65536,
+                r: 8,
+                p: 1,
+                salt,
+            },
+            Kdf::PBKDF2 => KeyDerivationConfig::PBKDF2 { iter: 65535, salt },
+            Kdf::Argon2id => KeyDerivationConfig::Argon2id {
+                memory: DEFAULT_ARGON2_MEMORY,
+                iterations: DEFAULT_ARGON2_ITERATIONS,
+                parallelism: 1,
+                salt,
+            },
+            Kdf::None => {
+                bail!("No key derivation function specified");
+            }
+        })
+    }
+
+    /// Create an Argon2id configuration with a random salt.
+    ///
+    /// `memory` is the memory cost in KiB.
+    pub fn argon2id(memory: u32, iterations: u32) -> Result<Self, Error> {
+        let salt = proxmox_sys::linux::random_data(32)?;
+
+        Ok(KeyDerivationConfig::Argon2id {
+            memory,
+            iterations,
+            parallelism: 1,
+            salt,
+        })
+    }
+
+    /// Returns the key derivation function used by this configuration.
+    pub fn kdf(&self) -> Kdf {
+        match self {
+            KeyDerivationConfig::Scrypt { .. } => Kdf::Scrypt,
+            KeyDerivationConfig::PBKDF2 { .. } => Kdf::PBKDF2,
+            KeyDerivationConfig::Argon2id { .. } => Kdf::Argon2id,
+        }
+    }
     /// Derive a key from provided passphrase
     pub fn derive_key(&self, passphrase: &[u8]) -> Result<[u8; 32], Error> {
         let mut key = [0u8; 32];
@@ -49,6 +108,21 @@ impl KeyDerivationConfig {
                     &mut key,
                 )?;
 
+                Ok(key)
+            }
+            KeyDerivationConfig::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+                salt,
+            } => {
+                let params = argon2::Params::new(*memory, *iterations, *parallelism, Some(32))
+                    .map_err(|err| format_err!("invalid argon2id parameters - {}", err))?;
+
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(passphrase, salt, &mut key)
+                    .map_err(|err| format_err!("argon2id key derivation failed - {}", err))?;
+
                 Ok(key)
             }
         }
@@ -82,8 +156,7 @@ impl From<&KeyConfig> for KeyInfo {
         Self {
             path: None,
             kdf: match key_config.kdf {
-                Some(KeyDerivationConfig::PBKDF2 { .. }) => Kdf::PBKDF2,
-                Some(KeyDerivationConfig::Scrypt { .. }) => Kdf::Scrypt,
+                Some(ref kdf) => kdf.kdf(),
                 None => Kdf::None,
             },
             created: key_config.created,
@@ -122,25 +195,20 @@ impl KeyConfig {
 
     /// Creates a new instance, protect raw_key with passphrase.
     pub fn with_key(raw_key: &[u8; 32], passphrase: &[u8], kdf: Kdf) -> Result<Self, Error> {
+        Self::with_kdf_config(raw_key, passphrase, KeyDerivationConfig::new(kdf)?)
+    }
+
+    /// Creates a new instance, protect raw_key with passphrase using the key derivation
+    /// configuration `kdf`.
+    pub fn with_kdf_config(
+        raw_key: &[u8; 32],
+        passphrase: &[u8],
+        kdf: KeyDerivationConfig,
+    ) -> Result<Self, Error> {
         if raw_key.len() != 32 {
             bail!("got strange key length ({} != 32)", raw_key.len())
         }
 
-        let salt = proxmox_sys::linux::random_data(32)?;
-
-        let kdf = match kdf {
-            Kdf::Scrypt => KeyDerivationConfig::Scrypt {
-                n: 65536,
-                r: 8,
-                p: 1,
-                salt,
-            },
-            Kdf::PBKDF2 => KeyDerivationConfig::PBKDF2 { iter: 65535, salt },
-            Kdf::None => {
-                bail!("No key derivation function specified");
-            }
-        };
-
         let derived_key = kdf.derive_key(passphrase)?;
 
         let cipher = openssl::symm::Cipher::aes_256_gcm();
@@ -393,3 +461,24 @@ fn fingerprint_checks() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn argon2id_key_test() -> Result<(), Error> {
+    let raw_key = [42u8; 32];
+    let kdf = KeyDerivationConfig::argon2id(8 * 1024, 1)?;
+
+    let key_config = KeyConfig::with_kdf_config(&raw_key, b"password", kdf)?;
+    assert!(matches!(
+        key_config.kdf,
+        Some(KeyDerivationConfig::Argon2id { .. })
+    ));
+
+    let passphrase = || -> Result<Vec<u8>, Error> { Ok(b"password".to_vec()) };
+    let (decrypted, _created, _fingerprint) = key_config.decrypt(&passphrase)?;
+    assert_eq!(decrypted, raw_key);
+
+    let wrong_passphrase = || -> Result<Vec<u8>, Error> { Ok(b"wrong password".to_vec()) };
+    assert!(key_config.decrypt(&wrong_passphrase).is_err());
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod acl;
+pub mod alerts;
 mod cached_user_info;
 pub use cached_user_info::CachedUserInfo;
 pub mod datastore;
@@ -10,6 +11,7 @@ pub mod metrics;
 pub mod network;
 pub mod prune;
 pub mod remote;
+pub mod s3_shadow;
 pub mod sync;
 pub mod tape_encryption_keys;
 pub mod tape_job;
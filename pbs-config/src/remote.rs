@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
-use anyhow::Error;
+use anyhow::{bail, Error};
 use lazy_static::lazy_static;
 
 use proxmox_schema::*;
@@ -30,6 +31,7 @@ fn init() -> SectionConfig {
 
 pub const REMOTE_CFG_FILENAME: &str = "/etc/proxmox-backup/remote.cfg";
 pub const REMOTE_CFG_LOCKFILE: &str = "/etc/proxmox-backup/.remote.lck";
+pub const REMOTE_CA_CERT_DIR: &str = "/etc/proxmox-backup/remote-ca";
 
 /// Get exclusive lock
 pub fn lock_config() -> Result<BackupLockGuard, Error> {
@@ -50,6 +52,61 @@ pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
     crate::replace_backup_config(REMOTE_CFG_FILENAME, raw.as_bytes())
 }
 
+/// Path of the PEM file with the CA certificate(s) trusted for the remote `name`.
+pub fn ca_cert_path(name: &str) -> PathBuf {
+    PathBuf::from(format!("{}/{}.pem", REMOTE_CA_CERT_DIR, name))
+}
+
+/// Save the CA certificate(s) trusted for the remote `name`, readable by the backup user.
+pub fn save_ca_cert(name: &str, pem: &str) -> Result<(), Error> {
+    let backup_user = crate::backup_user()?;
+    let options = proxmox_sys::fs::CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o750))
+        .owner(nix::unistd::ROOT)
+        .group(backup_user.gid);
+    proxmox_sys::fs::create_path(REMOTE_CA_CERT_DIR, None, Some(options))?;
+
+    crate::replace_backup_config(ca_cert_path(name), pem.as_bytes())
+}
+
+/// Read the CA certificates of all remotes which have one, by remote name.
+pub fn ca_certs() -> Result<HashMap<String, String>, Error> {
+    let mut certs = HashMap::new();
+
+    let entries = match std::fs::read_dir(REMOTE_CA_CERT_DIR) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(certs),
+        Err(err) => bail!("unable to read {} - {}", REMOTE_CA_CERT_DIR, err),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("pem") {
+            continue;
+        }
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        certs.insert(name, std::fs::read_to_string(&path)?);
+    }
+
+    Ok(certs)
+}
+
+/// Remove the CA certificate(s) trusted for the remote `name`, if any.
+pub fn remove_ca_cert(name: &str) -> Result<(), Error> {
+    match std::fs::remove_file(ca_cert_path(name)) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => bail!(
+            "unable to remove CA certificate of remote '{}' - {}",
+            name,
+            err
+        ),
+    }
+}
+
 // shell completion helper
 pub fn complete_remote_name(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
     match config() {
@@ -0,0 +1,69 @@
+//! Secret keys of the S3 backends of datastores
+//!
+//! They are stored separately from the datastore configuration, which is readable by any user
+//! of the backup group and gets returned by the API.
+
+use std::collections::HashMap;
+
+use anyhow::{format_err, Error};
+use serde_json::{from_value, Value};
+
+use proxmox_sys::fs::CreateOptions;
+
+use crate::{open_backup_lockfile, BackupLockGuard};
+
+const LOCK_FILE: &str = pbs_buildcfg::configdir!("/s3.shadow.lock");
+const CONF_FILE: &str = pbs_buildcfg::configdir!("/s3.shadow");
+
+// Get exclusive lock
+fn lock_config() -> Result<BackupLockGuard, Error> {
+    open_backup_lockfile(LOCK_FILE, None, true)
+}
+
+fn read_file() -> Result<HashMap<String, String>, Error> {
+    let json = proxmox_sys::fs::file_get_json(CONF_FILE, Some(Value::Null))?;
+
+    if json == Value::Null {
+        Ok(HashMap::new())
+    } else {
+        // swallow serde error which might contain sensitive data
+        from_value(json).map_err(|_err| format_err!("unable to parse '{}'", CONF_FILE))
+    }
+}
+
+fn write_file(data: HashMap<String, String>) -> Result<(), Error> {
+    let backup_user = crate::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0600))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    let json = serde_json::to_vec(&data)?;
+    proxmox_sys::fs::replace_file(CONF_FILE, &json, options, true)
+}
+
+/// Returns the S3 secret key of the datastore `store`, if one is set.
+pub fn get_secret_key(store: &str) -> Result<Option<String>, Error> {
+    Ok(read_file()?.remove(store))
+}
+
+/// Sets the S3 secret key of the datastore `store`.
+pub fn set_secret_key(store: &str, secret_key: &str) -> Result<(), Error> {
+    let _guard = lock_config()?;
+
+    let mut data = read_file()?;
+    data.insert(store.to_string(), secret_key.to_string());
+    write_file(data)
+}
+
+/// Removes the S3 secret key of the datastore `store`.
+pub fn delete_secret_key(store: &str) -> Result<(), Error> {
+    let _guard = lock_config()?;
+
+    let mut data = read_file()?;
+    if data.remove(store).is_some() {
+        write_file(data)?;
+    }
+
+    Ok(())
+}
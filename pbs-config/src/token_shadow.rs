@@ -91,3 +91,15 @@ pub fn delete_secret(tokenid: &Authid) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Returns all entries, with the secrets as salted hashes.
+pub fn hashed_secrets() -> Result<HashMap<Authid, String>, Error> {
+    read_file()
+}
+
+/// Replaces all entries with the given ones, the secrets need to be salted hashes already.
+pub fn replace_hashed_secrets(data: HashMap<Authid, String>) -> Result<(), Error> {
+    let _guard = lock_config()?;
+
+    write_file(data)
+}
@@ -14,7 +14,8 @@ use pbs_api_types::{
 use pbs_config::{open_backup_lockfile, BackupLockGuard};
 
 use crate::manifest::{
-    BackupManifest, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME, MANIFEST_LOCK_NAME,
+    BackupManifest, CLIENT_LOG_BLOB_NAME, CLIENT_LOG_STATISTICS_HEADER, MANIFEST_BLOB_NAME,
+    MANIFEST_LOCK_NAME,
 };
 use crate::{DataBlob, DataStore};
 
@@ -219,6 +220,13 @@ impl BackupGroup {
             std::fs::remove_dir_all(&path).map_err(|err| {
                 format_err!("removing group directory {:?} failed - {}", path, err)
             })?;
+            if let Err(err) = self.store.remove_from_backend(&path) {
+                log::warn!(
+                    "removing backup group {:?} from backend failed - {}",
+                    path,
+                    err
+                );
+            }
         }
 
         Ok(removed_all_snaps)
@@ -236,6 +244,11 @@ impl BackupGroup {
         self.store
             .set_owner(&self.ns, self.as_ref(), auth_id, force)
     }
+
+    /// Change the owner of the group, see [`DataStore::change_owner`].
+    pub fn change_owner(&self, new_owner: &Authid) -> Result<(), Error> {
+        self.store.change_owner(&self.ns, self.as_ref(), new_owner)
+    }
 }
 
 impl AsRef<pbs_api_types::BackupNamespace> for BackupGroup {
@@ -415,6 +428,29 @@ impl BackupDir {
         .map_err(|err| format_err!("unable to load blob '{:?}' - {}", path, err))
     }
 
+    /// Returns the client log if it only holds the archive statistics written by the backup
+    /// client, so that a log uploaded after the backup can still be merged into it.
+    pub fn client_log_statistics(&self) -> Result<Option<Vec<u8>>, Error> {
+        let mut path = self.full_path();
+        path.push(CLIENT_LOG_BLOB_NAME);
+
+        let blob = match std::fs::File::open(&path) {
+            Ok(mut file) => DataBlob::load_from_reader(&mut file)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("unable to open {path:?} - {err}"),
+        };
+        if blob.is_encrypted() {
+            return Ok(None);
+        }
+
+        let log = blob.decode(None, None)?;
+        if log.starts_with(CLIENT_LOG_STATISTICS_HEADER.as_bytes()) {
+            Ok(Some(log))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Returns the filename to lock a manifest
     ///
     /// Also creates the basedir. The lockfile is located in
@@ -441,6 +477,9 @@ impl BackupDir {
 
     /// Destroy the whole snapshot, bails if it's protected
     ///
+    /// If the datastore has the trash enabled, finished snapshots are moved into the trash
+    /// instead of being removed immediately.
+    ///
     /// Setting `force` to true skips locking and thus ignores if the backup is currently in use.
     pub fn destroy(&self, force: bool) -> Result<(), Error> {
         let full_path = self.full_path();
@@ -455,10 +494,24 @@ impl BackupDir {
             bail!("cannot remove protected snapshot"); // use special error type?
         }
 
-        log::info!("removing backup snapshot {:?}", full_path);
-        std::fs::remove_dir_all(&full_path).map_err(|err| {
-            format_err!("removing backup snapshot {:?} failed - {}", full_path, err,)
-        })?;
+        // incomplete snapshots are never worth restoring, skip the trash for them
+        if self.store.trash_retention().is_some() && full_path.join(MANIFEST_BLOB_NAME).exists() {
+            log::info!("moving backup snapshot {:?} to trash", full_path);
+            self.store.move_to_trash(self)?;
+        } else {
+            log::info!("removing backup snapshot {:?}", full_path);
+            std::fs::remove_dir_all(&full_path).map_err(|err| {
+                format_err!("removing backup snapshot {:?} failed - {}", full_path, err,)
+            })?;
+        }
+
+        if let Err(err) = self.store.remove_from_backend(&full_path) {
+            log::warn!(
+                "removing backup snapshot {:?} from backend failed - {}",
+                full_path,
+                err
+            );
+        }
 
         // the manifest doesn't exist anymore, no need to keep the lock (already done by guard?)
         if let Ok(path) = self.manifest_lock_path() {
@@ -514,7 +567,9 @@ impl BackupDir {
 
         // atomic replace invalidates flock - no other writes past this point!
         replace_file(&path, raw_data, CreateOptions::new(), false)?;
-        Ok(())
+
+        self.store
+            .store_snapshot_file_in_backend(&self.ns, &self.dir, MANIFEST_BLOB_NAME)
     }
 
     /// Cleans up the backup directory by removing any file not mentioned in the manifest.
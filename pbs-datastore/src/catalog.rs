@@ -12,7 +12,7 @@ use pathpatterns::{MatchList, MatchType};
 use proxmox_io::ReadExt;
 use proxmox_schema::api;
 
-use crate::file_formats::PROXMOX_CATALOG_FILE_MAGIC_1_0;
+use crate::file_formats::{PROXMOX_CATALOG_FILE_MAGIC_1_0, PROXMOX_CATALOG_FILE_MAGIC_2_0};
 
 /// Trait for writing file list catalogs.
 ///
@@ -21,7 +21,16 @@ use crate::file_formats::PROXMOX_CATALOG_FILE_MAGIC_1_0;
 pub trait BackupCatalogWriter {
     fn start_directory(&mut self, name: &CStr) -> Result<(), Error>;
     fn end_directory(&mut self) -> Result<(), Error>;
-    fn add_file(&mut self, name: &CStr, size: u64, mtime: i64) -> Result<(), Error>;
+    /// Add a regular file. `ctime` and `csum` (the 64 bit xxh3 hash of the file content) are only
+    /// stored in version 2 catalogs.
+    fn add_file(
+        &mut self,
+        name: &CStr,
+        size: u64,
+        mtime: i64,
+        ctime: i64,
+        csum: Option<u64>,
+    ) -> Result<(), Error>;
     fn add_symlink(&mut self, name: &CStr) -> Result<(), Error>;
     fn add_hardlink(&mut self, name: &CStr) -> Result<(), Error>;
     fn add_block_device(&mut self, name: &CStr) -> Result<(), Error>;
@@ -76,6 +85,32 @@ impl From<&DirEntryAttribute> for CatalogEntryType {
     }
 }
 
+/// Catalog file format version
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CatalogVersion {
+    /// File entries contain size and mtime
+    V1,
+    /// File entries additionally contain the ctime and an optional content hash
+    V2,
+}
+
+impl CatalogVersion {
+    fn magic(self) -> &'static [u8; 8] {
+        match self {
+            CatalogVersion::V1 => &PROXMOX_CATALOG_FILE_MAGIC_1_0,
+            CatalogVersion::V2 => &PROXMOX_CATALOG_FILE_MAGIC_2_0,
+        }
+    }
+
+    fn from_magic(magic: &[u8; 8]) -> Result<Self, Error> {
+        match *magic {
+            PROXMOX_CATALOG_FILE_MAGIC_1_0 => Ok(CatalogVersion::V1),
+            PROXMOX_CATALOG_FILE_MAGIC_2_0 => Ok(CatalogVersion::V2),
+            _ => bail!("got unexpected magic number for catalog"),
+        }
+    }
+}
+
 impl fmt::Display for CatalogEntryType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", char::from(*self as u8))
@@ -95,8 +130,17 @@ pub struct DirEntry {
 /// Used to specific additional attributes inside DirEntry
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DirEntryAttribute {
-    Directory { start: u64 },
-    File { size: u64, mtime: i64 },
+    Directory {
+        start: u64,
+    },
+    /// `ctime` and `csum` are only available in version 2 catalogs, `csum` only if content
+    /// hashing was enabled for the backup.
+    File {
+        size: u64,
+        mtime: i64,
+        ctime: Option<i64>,
+        csum: Option<u64>,
+    },
     Symlink,
     Hardlink,
     BlockDevice,
@@ -106,43 +150,6 @@ pub enum DirEntryAttribute {
 }
 
 impl DirEntry {
-    fn new(etype: CatalogEntryType, name: Vec<u8>, start: u64, size: u64, mtime: i64) -> Self {
-        match etype {
-            CatalogEntryType::Directory => DirEntry {
-                name,
-                attr: DirEntryAttribute::Directory { start },
-            },
-            CatalogEntryType::File => DirEntry {
-                name,
-                attr: DirEntryAttribute::File { size, mtime },
-            },
-            CatalogEntryType::Symlink => DirEntry {
-                name,
-                attr: DirEntryAttribute::Symlink,
-            },
-            CatalogEntryType::Hardlink => DirEntry {
-                name,
-                attr: DirEntryAttribute::Hardlink,
-            },
-            CatalogEntryType::BlockDevice => DirEntry {
-                name,
-                attr: DirEntryAttribute::BlockDevice,
-            },
-            CatalogEntryType::CharDevice => DirEntry {
-                name,
-                attr: DirEntryAttribute::CharDevice,
-            },
-            CatalogEntryType::Fifo => DirEntry {
-                name,
-                attr: DirEntryAttribute::Fifo,
-            },
-            CatalogEntryType::Socket => DirEntry {
-                name,
-                attr: DirEntryAttribute::Socket,
-            },
-        }
-    }
-
     /// Get file mode bits for this entry to be used with the `MatchList` api.
     pub fn get_file_mode(&self) -> Option<u32> {
         Some(match self.attr {
@@ -166,6 +173,104 @@ impl DirEntry {
     pub fn is_symlink(&self) -> bool {
         matches!(self.attr, DirEntryAttribute::Symlink { .. })
     }
+
+    /// Check if the file content might differ from another file entry.
+    ///
+    /// Content hashes are compared if both entries have one, otherwise this falls back to
+    /// comparing size, mtime and ctime (if both entries have one).
+    pub fn file_content_changed(&self, other: &DirEntry) -> Option<bool> {
+        match (&self.attr, &other.attr) {
+            (
+                DirEntryAttribute::File {
+                    size,
+                    mtime,
+                    ctime,
+                    csum,
+                },
+                DirEntryAttribute::File {
+                    size: other_size,
+                    mtime: other_mtime,
+                    ctime: other_ctime,
+                    csum: other_csum,
+                },
+            ) => Some(match (csum, other_csum) {
+                (Some(csum), Some(other_csum)) => csum != other_csum,
+                _ => {
+                    size != other_size
+                        || mtime != other_mtime
+                        || matches!((ctime, other_ctime), (Some(a), Some(b)) if a != b)
+                }
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Number of regular files of an archive compared with a previous catalog, see
+/// [`compare_catalog_files`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileChangeCounts {
+    /// Files whose content did not change, as far as the catalogs can tell
+    pub unchanged: u64,
+    /// Files whose content changed
+    pub changed: u64,
+    /// Files which were no regular file in the previous catalog
+    pub new: u64,
+}
+
+impl std::ops::AddAssign<&FileChangeCounts> for FileChangeCounts {
+    fn add_assign(&mut self, other: &FileChangeCounts) {
+        self.unchanged += other.unchanged;
+        self.changed += other.changed;
+        self.new += other.new;
+    }
+}
+
+/// Compare the regular files below the directory `path` of the `current` catalog with the ones
+/// at the same location in the `previous` catalog. A directory missing in the `previous` catalog
+/// only holds new files.
+pub fn compare_catalog_files<P: Read + Seek, C: Read + Seek>(
+    previous: &mut CatalogReader<P>,
+    current: &mut CatalogReader<C>,
+    path: &[u8],
+) -> Result<FileChangeCounts, Error> {
+    let mut counts = FileChangeCounts::default();
+
+    let previous_dir = previous
+        .lookup_recursive(path)
+        .ok()
+        .filter(DirEntry::is_directory);
+    let mut todo = vec![(current.lookup_recursive(path)?, previous_dir)];
+
+    while let Some((dir, previous_dir)) = todo.pop() {
+        let previous_entries = match previous_dir {
+            Some(previous_dir) => previous.read_dir(&previous_dir)?,
+            None => Vec::new(),
+        };
+        let previous_entries: std::collections::HashMap<&[u8], &DirEntry> = previous_entries
+            .iter()
+            .map(|entry| (entry.name.as_slice(), entry))
+            .collect();
+
+        for entry in current.read_dir(&dir)? {
+            let previous_entry = previous_entries.get(entry.name.as_slice());
+            if entry.is_directory() {
+                let previous_dir = previous_entry
+                    .filter(|previous| previous.is_directory())
+                    .map(|previous| (*previous).clone());
+                todo.push((entry, previous_dir));
+                continue;
+            }
+            match previous_entry.and_then(|previous| entry.file_content_changed(previous)) {
+                Some(false) => counts.unchanged += 1,
+                Some(true) => counts.changed += 1,
+                None if matches!(entry.attr, DirEntryAttribute::File { .. }) => counts.new += 1,
+                None => (),
+            }
+        }
+    }
+
+    Ok(counts)
 }
 
 struct DirInfo {
@@ -185,7 +290,12 @@ impl DirInfo {
         DirInfo::new(CString::new(b"/".to_vec()).unwrap())
     }
 
-    fn encode_entry<W: Write>(writer: &mut W, entry: &DirEntry, pos: u64) -> Result<(), Error> {
+    fn encode_entry<W: Write>(
+        writer: &mut W,
+        entry: &DirEntry,
+        pos: u64,
+        version: CatalogVersion,
+    ) -> Result<(), Error> {
         match entry {
             DirEntry {
                 name,
@@ -198,13 +308,30 @@ impl DirInfo {
             }
             DirEntry {
                 name,
-                attr: DirEntryAttribute::File { size, mtime },
+                attr:
+                    DirEntryAttribute::File {
+                        size,
+                        mtime,
+                        ctime,
+                        csum,
+                    },
             } => {
                 writer.write_all(&[CatalogEntryType::File as u8])?;
                 catalog_encode_u64(writer, name.len() as u64)?;
                 writer.write_all(name)?;
                 catalog_encode_u64(writer, *size)?;
                 catalog_encode_i64(writer, *mtime)?;
+                if version == CatalogVersion::V2 {
+                    catalog_encode_i64(writer, ctime.unwrap_or(0))?;
+                    match csum {
+                        Some(csum) => {
+                            let csum = csum.to_le_bytes();
+                            catalog_encode_u64(writer, csum.len() as u64)?;
+                            writer.write_all(&csum)?;
+                        }
+                        None => catalog_encode_u64(writer, 0)?,
+                    }
+                }
             }
             DirEntry {
                 name,
@@ -258,11 +385,11 @@ impl DirInfo {
         Ok(())
     }
 
-    fn encode(self, start: u64) -> Result<(CString, Vec<u8>), Error> {
+    fn encode(self, start: u64, version: CatalogVersion) -> Result<(CString, Vec<u8>), Error> {
         let mut table = Vec::new();
         catalog_encode_u64(&mut table, self.entries.len() as u64)?;
         for entry in self.entries {
-            Self::encode_entry(&mut table, &entry, start)?;
+            Self::encode_entry(&mut table, &entry, start, version)?;
         }
 
         let mut data = Vec::new();
@@ -272,8 +399,11 @@ impl DirInfo {
         Ok((self.name, data))
     }
 
-    fn parse<C: FnMut(CatalogEntryType, &[u8], u64, u64, i64) -> Result<bool, Error>>(
+    /// Parse a directory info block located at `start`, calling `callback` for each entry.
+    fn parse<C: FnMut(&[u8], DirEntryAttribute) -> Result<bool, Error>>(
         data: &[u8],
+        start: u64,
+        version: CatalogVersion,
         mut callback: C,
     ) -> Result<(), Error> {
         let mut cursor = data;
@@ -298,19 +428,50 @@ impl DirInfo {
             let name = &mut name_buf[0..name_len];
             cursor.read_exact(name)?;
 
-            let cont = match etype {
+            let attr = match etype {
                 CatalogEntryType::Directory => {
                     let offset = catalog_decode_u64(&mut cursor)?;
-                    callback(etype, name, offset, 0, 0)?
+                    if offset > start {
+                        bail!("got wrong directory offset ({} > {})", offset, start);
+                    }
+                    DirEntryAttribute::Directory {
+                        start: start - offset,
+                    }
                 }
                 CatalogEntryType::File => {
                     let size = catalog_decode_u64(&mut cursor)?;
                     let mtime = catalog_decode_i64(&mut cursor)?;
-                    callback(etype, name, 0, size, mtime)?
+                    let (ctime, csum) = match version {
+                        CatalogVersion::V1 => (None, None),
+                        CatalogVersion::V2 => {
+                            let ctime = catalog_decode_i64(&mut cursor)?;
+                            let csum = match catalog_decode_u64(&mut cursor)? {
+                                0 => None,
+                                8 => {
+                                    let mut csum = [0u8; 8];
+                                    cursor.read_exact(&mut csum)?;
+                                    Some(u64::from_le_bytes(csum))
+                                }
+                                len => bail!("unexpected catalog content hash length {}", len),
+                            };
+                            (Some(ctime), csum)
+                        }
+                    };
+                    DirEntryAttribute::File {
+                        size,
+                        mtime,
+                        ctime,
+                        csum,
+                    }
                 }
-                _ => callback(etype, name, 0, 0, 0)?,
+                CatalogEntryType::Symlink => DirEntryAttribute::Symlink,
+                CatalogEntryType::Hardlink => DirEntryAttribute::Hardlink,
+                CatalogEntryType::BlockDevice => DirEntryAttribute::BlockDevice,
+                CatalogEntryType::CharDevice => DirEntryAttribute::CharDevice,
+                CatalogEntryType::Fifo => DirEntryAttribute::Fifo,
+                CatalogEntryType::Socket => DirEntryAttribute::Socket,
             };
-            if !cont {
+            if !callback(name, attr)? {
                 return Ok(());
             }
         }
@@ -328,22 +489,30 @@ impl DirInfo {
 /// A Catalogs simply contains list of files and directories
 /// (directory tree). They are use to find content without having to
 /// search the real archive (which may be large). For files, they
-/// include the last modification time and file size.
+/// include the last modification time and file size, version 2
+/// catalogs also the ctime and an optional content hash.
 pub struct CatalogWriter<W> {
     writer: W,
     dirstack: Vec<DirInfo>,
     pos: u64,
+    version: CatalogVersion,
 }
 
 impl<W: Write> CatalogWriter<W> {
     /// Create a new  CatalogWriter instance
     pub fn new(writer: W) -> Result<Self, Error> {
+        Self::with_version(writer, CatalogVersion::V1)
+    }
+
+    /// Create a new CatalogWriter instance writing the specified format version
+    pub fn with_version(writer: W, version: CatalogVersion) -> Result<Self, Error> {
         let mut me = Self {
             writer,
             dirstack: vec![DirInfo::new_rootdir()],
             pos: 0,
+            version,
         };
-        me.write_all(&PROXMOX_CATALOG_FILE_MAGIC_1_0)?;
+        me.write_all(version.magic())?;
         Ok(me)
     }
 
@@ -364,7 +533,7 @@ impl<W: Write> CatalogWriter<W> {
         let dir = self.dirstack.pop().unwrap();
 
         let start = self.pos;
-        let (_, data) = dir.encode(start)?;
+        let (_, data) = dir.encode(start, self.version)?;
         self.write_all(&data)?;
 
         self.write_all(&start.to_le_bytes())?;
@@ -386,7 +555,7 @@ impl<W: Write> BackupCatalogWriter for CatalogWriter<W> {
         let (start, name) = match self.dirstack.pop() {
             Some(dir) => {
                 let start = self.pos;
-                let (name, data) = dir.encode(start)?;
+                let (name, data) = dir.encode(start, self.version)?;
                 self.write_all(&data)?;
                 (start, name)
             }
@@ -408,7 +577,14 @@ impl<W: Write> BackupCatalogWriter for CatalogWriter<W> {
         Ok(())
     }
 
-    fn add_file(&mut self, name: &CStr, size: u64, mtime: i64) -> Result<(), Error> {
+    fn add_file(
+        &mut self,
+        name: &CStr,
+        size: u64,
+        mtime: i64,
+        ctime: i64,
+        csum: Option<u64>,
+    ) -> Result<(), Error> {
         let dir = self
             .dirstack
             .last_mut()
@@ -416,7 +592,12 @@ impl<W: Write> BackupCatalogWriter for CatalogWriter<W> {
         let name = name.to_bytes().to_vec();
         dir.entries.push(DirEntry {
             name,
-            attr: DirEntryAttribute::File { size, mtime },
+            attr: DirEntryAttribute::File {
+                size,
+                mtime,
+                ctime: Some(ctime),
+                csum,
+            },
         });
         Ok(())
     }
@@ -503,12 +684,29 @@ impl<W: Write> BackupCatalogWriter for CatalogWriter<W> {
 /// Read Catalog files
 pub struct CatalogReader<R> {
     reader: R,
+    version: Option<CatalogVersion>,
 }
 
 impl<R: Read + Seek> CatalogReader<R> {
     /// Create a new CatalogReader instance
     pub fn new(reader: R) -> Self {
-        Self { reader }
+        Self {
+            reader,
+            version: None,
+        }
+    }
+
+    /// Get the format version of the catalog
+    pub fn version(&mut self) -> Result<CatalogVersion, Error> {
+        if let Some(version) = self.version {
+            return Ok(version);
+        }
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; 8];
+        self.reader.read_exact(&mut magic)?;
+        let version = CatalogVersion::from_magic(&magic)?;
+        self.version = Some(version);
+        Ok(version)
     }
 
     /// Print whole catalog to stdout
@@ -526,12 +724,7 @@ impl<R: Read + Seek> CatalogReader<R> {
     /// Get the root DirEntry
     pub fn root(&mut self) -> Result<DirEntry, Error> {
         // Root dir is special
-        self.reader.seek(SeekFrom::Start(0))?;
-        let mut magic = [0u8; 8];
-        self.reader.read_exact(&mut magic)?;
-        if magic != PROXMOX_CATALOG_FILE_MAGIC_1_0 {
-            bail!("got unexpected magic number for catalog");
-        }
+        self.version()?;
         self.reader.seek(SeekFrom::End(-8))?;
         let start = unsafe { self.reader.read_le_value::<u64>()? };
         Ok(DirEntry {
@@ -547,13 +740,16 @@ impl<R: Read + Seek> CatalogReader<R> {
             _ => bail!("parent is not a directory - internal error"),
         };
 
+        let version = self.version()?;
         let data = self.read_raw_dirinfo_block(start)?;
 
         let mut entry_list = Vec::new();
 
-        DirInfo::parse(&data, |etype, name, offset, size, mtime| {
-            let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime);
-            entry_list.push(entry);
+        DirInfo::parse(&data, start, version, |name, attr| {
+            entry_list.push(DirEntry {
+                name: name.to_vec(),
+                attr,
+            });
             Ok(true)
         })?;
 
@@ -598,16 +794,19 @@ impl<R: Read + Seek> CatalogReader<R> {
             _ => bail!("parent is not a directory - internal error"),
         };
 
+        let version = self.version()?;
         let data = self.read_raw_dirinfo_block(start)?;
 
         let mut item = None;
-        DirInfo::parse(&data, |etype, name, offset, size, mtime| {
+        DirInfo::parse(&data, start, version, |name, attr| {
             if name != filename {
                 return Ok(true);
             }
 
-            let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime);
-            item = Some(entry);
+            item = Some(DirEntry {
+                name: name.to_vec(),
+                attr,
+            });
             Ok(false) // stop parsing
         })?;
 
@@ -627,29 +826,39 @@ impl<R: Read + Seek> CatalogReader<R> {
 
     /// Print the content of a directory to stdout
     pub fn dump_dir(&mut self, prefix: &std::path::Path, start: u64) -> Result<(), Error> {
+        let version = self.version()?;
         let data = self.read_raw_dirinfo_block(start)?;
 
-        DirInfo::parse(&data, |etype, name, offset, size, mtime| {
+        DirInfo::parse(&data, start, version, |name, attr| {
             let mut path = std::path::PathBuf::from(prefix);
             let name: &OsStr = OsStrExt::from_bytes(name);
             path.push(name);
 
-            match etype {
-                CatalogEntryType::Directory => {
+            let etype = CatalogEntryType::from(&attr);
+            match attr {
+                DirEntryAttribute::Directory { start } => {
                     log::info!("{} {:?}", etype, path);
-                    if offset > start {
-                        bail!("got wrong directory offset ({} > {})", offset, start);
-                    }
-                    let pos = start - offset;
-                    self.dump_dir(&path, pos)?;
+                    self.dump_dir(&path, start)?;
                 }
-                CatalogEntryType::File => {
+                DirEntryAttribute::File {
+                    size, mtime, csum, ..
+                } => {
                     let mut mtime_string = mtime.to_string();
                     if let Ok(s) = proxmox_time::strftime_local("%FT%TZ", mtime as i64) {
                         mtime_string = s;
                     }
 
-                    log::info!("{} {:?} {} {}", etype, path, size, mtime_string,);
+                    match csum {
+                        Some(csum) => log::info!(
+                            "{} {:?} {} {} {:016x}",
+                            etype,
+                            path,
+                            size,
+                            mtime_string,
+                            csum
+                        ),
+                        None => log::info!("{} {:?} {} {}", etype, path, size, mtime_string,),
+                    }
                 }
                 _ => {
                     log::info!("{} {:?}", etype, path);
@@ -706,7 +915,7 @@ impl<R: Read + Seek> CatalogReader<R> {
             components.push(b'/');
             components.extend(&direntry.name);
             let mut entry = ArchiveEntry::new(&components, Some(&direntry.attr));
-            if let DirEntryAttribute::File { size, mtime } = direntry.attr {
+            if let DirEntryAttribute::File { size, mtime, .. } = direntry.attr {
                 entry.size = size.into();
                 entry.mtime = mtime.into();
             }
@@ -898,6 +1107,174 @@ fn test_catalog_i64_compatibility() {
     test_encode_decode(u64::MAX);
 }
 
+#[test]
+fn test_catalog_v2_file_entries() {
+    fn roundtrip(version: CatalogVersion) -> DirEntryAttribute {
+        let mut data = Vec::new();
+        let mut writer = CatalogWriter::with_version(&mut data, version).unwrap();
+        writer
+            .add_file(
+                CStr::from_bytes_with_nul(b"file\0").unwrap(),
+                42,
+                1000,
+                2000,
+                Some(0x0123_4567_89ab_cdef),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let mut reader = CatalogReader::new(std::io::Cursor::new(data));
+        assert_eq!(reader.version().unwrap(), version);
+        reader.lookup_recursive(b"/file").unwrap().attr
+    }
+
+    assert_eq!(
+        roundtrip(CatalogVersion::V1),
+        DirEntryAttribute::File {
+            size: 42,
+            mtime: 1000,
+            ctime: None,
+            csum: None,
+        }
+    );
+    assert_eq!(
+        roundtrip(CatalogVersion::V2),
+        DirEntryAttribute::File {
+            size: 42,
+            mtime: 1000,
+            ctime: Some(2000),
+            csum: Some(0x0123_4567_89ab_cdef),
+        }
+    );
+}
+
+#[test]
+fn test_file_content_changed() {
+    let file = |size, mtime, ctime, csum| DirEntry {
+        name: b"file".to_vec(),
+        attr: DirEntryAttribute::File {
+            size,
+            mtime,
+            ctime,
+            csum,
+        },
+    };
+
+    let entry = file(10, 100, Some(200), Some(0x1234));
+
+    // equal content hashes win over changed metadata, different ones over equal metadata
+    assert_eq!(
+        entry.file_content_changed(&file(10, 101, Some(201), Some(0x1234))),
+        Some(false)
+    );
+    assert_eq!(
+        entry.file_content_changed(&file(10, 100, Some(200), Some(0x4321))),
+        Some(true)
+    );
+
+    // without a hash on both sides, the metadata is compared
+    assert_eq!(
+        entry.file_content_changed(&file(10, 100, Some(200), None)),
+        Some(false)
+    );
+    assert_eq!(
+        entry.file_content_changed(&file(11, 100, Some(200), None)),
+        Some(true)
+    );
+    assert_eq!(
+        entry.file_content_changed(&file(10, 100, Some(201), None)),
+        Some(true)
+    );
+    // version 1 catalogs have no ctime
+    assert_eq!(
+        entry.file_content_changed(&file(10, 100, None, None)),
+        Some(false)
+    );
+
+    let dir = DirEntry {
+        name: b"file".to_vec(),
+        attr: DirEntryAttribute::Directory { start: 0 },
+    };
+    assert_eq!(entry.file_content_changed(&dir), None);
+}
+
+#[test]
+fn test_compare_catalog_files() {
+    let name = |name: &str| CString::new(name).unwrap();
+
+    // archive directory with the files (name, size, mtime) and subdirectories
+    let catalog = |version, files: &[(&str, u64, i64)], sub: &[(&str, u64, i64)]| {
+        let mut data = Vec::new();
+        let mut writer = CatalogWriter::with_version(&mut data, version).unwrap();
+        writer.start_directory(&name("root.pxar.didx")).unwrap();
+        for (file, size, mtime) in files {
+            writer
+                .add_file(&name(file), *size, *mtime, *mtime, None)
+                .unwrap();
+        }
+        writer.start_directory(&name("sub")).unwrap();
+        for (file, size, mtime) in sub {
+            writer
+                .add_file(&name(file), *size, *mtime, *mtime, None)
+                .unwrap();
+        }
+        writer.end_directory().unwrap();
+        writer.add_symlink(&name("link")).unwrap();
+        writer.end_directory().unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        CatalogReader::new(std::io::Cursor::new(data))
+    };
+
+    let mut previous = catalog(
+        CatalogVersion::V1,
+        &[("a", 1, 100), ("b", 2, 100)],
+        &[("c", 4, 100)],
+    );
+    let mut current = catalog(
+        CatalogVersion::V2,
+        &[("a", 1, 100), ("b", 2, 200), ("d", 5, 100)],
+        &[("c", 4, 100), ("e", 6, 100)],
+    );
+
+    // the ctime is only compared if both catalogs have it, symlinks are not counted
+    assert_eq!(
+        compare_catalog_files(&mut previous, &mut current, b"root.pxar.didx").unwrap(),
+        FileChangeCounts {
+            unchanged: 2,
+            changed: 1,
+            new: 2,
+        }
+    );
+
+    assert_eq!(
+        compare_catalog_files(&mut previous, &mut current, b"root.pxar.didx/sub").unwrap(),
+        FileChangeCounts {
+            unchanged: 1,
+            changed: 0,
+            new: 1,
+        }
+    );
+    // archives missing in the previous catalog only hold new files
+    let mut empty = CatalogReader::new(std::io::Cursor::new({
+        let mut data = Vec::new();
+        let mut writer = CatalogWriter::new(&mut data).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        data
+    }));
+    assert_eq!(
+        compare_catalog_files(&mut empty, &mut current, b"root.pxar.didx").unwrap(),
+        FileChangeCounts {
+            unchanged: 0,
+            changed: 0,
+            new: 5,
+        }
+    );
+    assert!(compare_catalog_files(&mut previous, &mut current, b"missing.pxar.didx").is_err());
+}
+
 /// An entry in a hierarchy of files for restore and listing.
 #[api]
 #[derive(Serialize, Deserialize)]
@@ -0,0 +1,155 @@
+//! Storage backends for the chunks of a datastore
+//!
+//! The index files and blobs of the snapshots are always stored below the datastore path, only
+//! the chunks can be stored elsewhere. The [`ChunkStore`] keeps them in the `.chunks` directory
+//! of the datastore, the [`S3ChunkBackend`](crate::s3::S3ChunkBackend) in a bucket of an S3
+//! compatible object storage. The latter also keeps a copy of the snapshot files, so that the
+//! snapshots can be restored from the bucket if the datastore path gets lost.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Error};
+
+use pbs_api_types::GarbageCollectionStatus;
+use proxmox_sys::WorkerTaskContext;
+
+use crate::chunk_bitmap::ChunkBitmap;
+use crate::chunk_store::ChunkStore;
+use crate::DataBlob;
+
+/// Storage backend of the chunks of a datastore
+pub trait ChunkBackend: Send + Sync {
+    /// Insert a chunk, returns whether it was already stored and its encoded size.
+    fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error>;
+
+    /// Replace a chunk atomically, even if it exists with the same size, returns its encoded
+    /// size.
+    fn replace_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<u64, Error>;
+
+    /// Mark a chunk as in use, so that garbage collection keeps it. Returns `false` if the chunk
+    /// does not exist, or fails if `assert_exists` is set.
+    fn cond_touch_chunk(&self, digest: &[u8; 32], assert_exists: bool) -> Result<bool, Error>;
+
+    /// Mark a chunk referenced by an index file during the first phase of garbage collection,
+    /// either by touching it or by setting it in `marks`. Returns `false` if the chunk is known
+    /// to not exist.
+    fn mark_used_chunk(&self, digest: &[u8; 32], _marks: &ChunkBitmap) -> Result<bool, Error> {
+        self.cond_touch_chunk(digest, false)
+    }
+
+    /// Load a chunk.
+    fn load_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error>;
+
+    /// Returns the encoded size of a chunk, fails if it does not exist.
+    fn chunk_size(&self, digest: &[u8; 32]) -> Result<u64, Error>;
+
+    /// Remove a chunk, for example because it is corrupt.
+    fn remove_chunk(&self, digest: &[u8; 32]) -> Result<(), Error>;
+
+    /// Remove all chunks which were not marked since the start of the first garbage collection
+    /// phase or of the oldest backup writer, the second phase of garbage collection. The chunks
+    /// set in `marks` are kept as well, these are the ones marked by
+    /// [`mark_used_chunk`](Self::mark_used_chunk) without touching them and the ones of index
+    /// files which did not change since the last run. Whenever the percentage of processed
+    /// chunks changes, it is passed to `progress` together with the number of chunks so far.
+    fn sweep_unused_chunks(
+        &self,
+        oldest_writer: i64,
+        phase1_start_time: i64,
+        marks: &ChunkBitmap,
+        status: &mut GarbageCollectionStatus,
+        progress: &mut dyn FnMut(usize, usize),
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(), Error>;
+
+    /// Returns `true` if the backend keeps a copy of the snapshot files.
+    fn stores_files(&self) -> bool {
+        false
+    }
+
+    /// Store a copy of a snapshot file, `path` is relative to the datastore base path.
+    fn store_file(&self, _path: &Path, _data: Vec<u8>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Load the stored copy of a snapshot file.
+    fn load_file(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        bail!(
+            "no stored copy of {:?}, the backend does not store files",
+            path
+        );
+    }
+
+    /// Remove the stored copy of a snapshot file, succeeds if there is none.
+    fn remove_file(&self, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// List the stored copies of the files below the directory `path`, recursively.
+    fn list_files(&self, _path: &Path) -> Result<Vec<PathBuf>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+impl ChunkBackend for ChunkStore {
+    fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error> {
+        ChunkStore::insert_chunk(self, chunk, digest)
+    }
+
+    fn replace_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<u64, Error> {
+        ChunkStore::replace_chunk(self, chunk, digest)
+    }
+
+    fn cond_touch_chunk(&self, digest: &[u8; 32], assert_exists: bool) -> Result<bool, Error> {
+        ChunkStore::cond_touch_chunk(self, digest, assert_exists)
+    }
+
+    fn load_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
+        let (chunk_path, _digest_str) = self.chunk_path(digest);
+        let mut file = std::fs::File::open(chunk_path)?;
+        DataBlob::load_from_reader(&mut file)
+    }
+
+    fn chunk_size(&self, digest: &[u8; 32]) -> Result<u64, Error> {
+        let (chunk_path, _digest_str) = self.chunk_path(digest);
+        let metadata = std::fs::metadata(chunk_path)?;
+        if !metadata.is_file() {
+            bail!("chunk is not a regular file");
+        }
+        Ok(metadata.len())
+    }
+
+    fn remove_chunk(&self, digest: &[u8; 32]) -> Result<(), Error> {
+        let (chunk_path, _digest_str) = self.chunk_path(digest);
+        remove_file_if_exists(&chunk_path)
+    }
+
+    fn sweep_unused_chunks(
+        &self,
+        oldest_writer: i64,
+        phase1_start_time: i64,
+        marks: &ChunkBitmap,
+        status: &mut GarbageCollectionStatus,
+        progress: &mut dyn FnMut(usize, usize),
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(), Error> {
+        // chunks are marked by touching them
+        ChunkStore::sweep_unused_chunks(
+            self,
+            oldest_writer,
+            phase1_start_time,
+            marks,
+            status,
+            progress,
+            worker,
+        )
+    }
+}
+
+fn remove_file_if_exists(path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
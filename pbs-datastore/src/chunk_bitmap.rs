@@ -0,0 +1,115 @@
+//! Fixed size bitmap of chunk digests used by garbage collection
+//!
+//! Garbage collection needs to remember chunks which are in use without relying on their access
+//! time, for example the chunks marked in an object storage. Keeping every digest in a set needs
+//! memory proportional to the number of chunks, which gets large on big datastores. The digests
+//! are SHA-256 hashes, so some of their bits address a bit of the map evenly instead.
+//!
+//! Different digests can share a bit, so a set bit only means that the chunk *may* be in use.
+//! Such a false positive only keeps an unused chunk for another garbage collection run. Every
+//! bitmap uses other bits of the digests, so the same unused chunk is not kept forever.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// bits of the map per expected chunk, gives about 3% false positives at the expected count
+const BITS_PER_CHUNK: u64 = 32;
+// 1 MiB
+const MIN_BITS: u64 = 1 << 23;
+// 1 GiB
+const MAX_BITS: u64 = 1 << 33;
+
+/// Bitmap of chunk digests, which can be set from several threads at once
+pub struct ChunkBitmap {
+    words: Vec<AtomicU64>,
+    // number of bits - 1, the number of bits is a power of two
+    mask: u64,
+    // offset of the digest bytes used as bit index
+    offset: usize,
+}
+
+impl ChunkBitmap {
+    /// Create an empty bitmap for about `expected_chunks` chunks.
+    pub fn new(expected_chunks: u64) -> Self {
+        let bits = expected_chunks
+            .saturating_mul(BITS_PER_CHUNK)
+            .clamp(MIN_BITS, MAX_BITS)
+            .next_power_of_two();
+        // use other bits of the digests than the last bitmap, most likely
+        let offset = (proxmox_time::epoch_i64() as usize) % (32 - 8 + 1);
+        Self::with_bits(bits, offset)
+    }
+
+    fn with_bits(bits: u64, offset: usize) -> Self {
+        let words = (0..bits / 64).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            words,
+            mask: bits - 1,
+            offset,
+        }
+    }
+
+    fn position(&self, digest: &[u8; 32]) -> (usize, u64) {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[self.offset..self.offset + 8]);
+        let bit = u64::from_le_bytes(bytes) & self.mask;
+        ((bit / 64) as usize, 1 << (bit % 64))
+    }
+
+    /// Mark the chunk `digest` as in use.
+    pub fn insert(&self, digest: &[u8; 32]) {
+        let (word, bit) = self.position(digest);
+        self.words[word].fetch_or(bit, Ordering::Relaxed);
+    }
+
+    /// Returns whether the chunk `digest` may be in use. Never `false` for a marked chunk.
+    pub fn contains(&self, digest: &[u8; 32]) -> bool {
+        let (word, bit) = self.position(digest);
+        self.words[word].load(Ordering::Relaxed) & bit != 0
+    }
+
+    /// Size of the bitmap in bytes.
+    pub fn size(&self) -> usize {
+        self.words.len() * 8
+    }
+}
+
+#[test]
+fn test_chunk_bitmap() {
+    let bitmap = ChunkBitmap::with_bits(1024, 3);
+
+    let digest = |n: u8| {
+        let mut digest = [0u8; 32];
+        digest[3] = n;
+        digest[31] = 0xff; // not used for the index
+        digest
+    };
+
+    assert!(!bitmap.contains(&digest(1)));
+    bitmap.insert(&digest(1));
+    bitmap.insert(&digest(200));
+    assert!(bitmap.contains(&digest(1)));
+    assert!(bitmap.contains(&digest(200)));
+    assert!(!bitmap.contains(&digest(2)));
+
+    // only the bits at the offset select the position, so such digests share a bit
+    let mut other = digest(1);
+    other[0] = 0x42;
+    assert!(bitmap.contains(&other));
+
+    // the index wraps at the size of the map
+    let mut wrapped = digest(2);
+    wrapped[4] = 0x04; // bit 1024 + 2
+    assert!(!bitmap.contains(&wrapped));
+    bitmap.insert(&wrapped);
+    assert!(bitmap.contains(&digest(2)));
+}
+
+#[test]
+fn test_chunk_bitmap_size() {
+    assert_eq!(ChunkBitmap::new(0).size(), (MIN_BITS / 8) as usize);
+    // rounded up to a power of two
+    assert_eq!(
+        ChunkBitmap::new(3 << 20).size(),
+        ((128u64 << 20) / 8) as usize
+    );
+}
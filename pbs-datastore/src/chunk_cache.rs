@@ -0,0 +1,175 @@
+//! LRU cache of decoded chunks, shared by the chunk readers
+//!
+//! Used by the [LocalChunkReader](crate::local_chunk_reader::LocalChunkReader) for restores on
+//! the server and by the remote chunk reader of the client, which is also used by the file
+//! restore. Verification does not use it, as it has to check the chunks stored on disk.
+
+use pbs_api_types::CryptMode;
+use pbs_tools::lru_cache::LruCache;
+
+/// Default memory budget for the decoded chunks cached by a chunk reader
+pub const DEFAULT_CHUNK_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+// upper bound for the number of cached chunks, assuming chunks are at least 64 KiB on average
+const CHUNK_CACHE_MIN_AVG_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Statistics of a [ChunkCache]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkCacheStats {
+    /// Number of chunks read from the cache
+    pub hits: u64,
+    /// Number of chunks which had to be loaded
+    pub misses: u64,
+    /// Number of currently cached chunks
+    pub entries: usize,
+    /// Size of the currently cached chunks in bytes
+    pub size: usize,
+}
+
+/// LRU cache of decoded chunks, limited by the size of the cached data
+pub struct ChunkCache {
+    cache: LruCache<[u8; 32], (CryptMode, Vec<u8>)>,
+    max_entries: usize,
+    max_size: usize,
+    stats: ChunkCacheStats,
+}
+
+impl ChunkCache {
+    /// Create a cache using up to `max_size` bytes of RAM, zero disables it.
+    pub fn new(max_size: usize) -> Self {
+        let max_entries = (max_size / CHUNK_CACHE_MIN_AVG_CHUNK_SIZE).max(1);
+        Self {
+            cache: LruCache::new(max_entries),
+            max_entries,
+            max_size,
+            stats: ChunkCacheStats::default(),
+        }
+    }
+
+    /// Returns the crypt mode of the chunk `digest` and a copy of its cached data.
+    ///
+    /// The cache may be shared by readers of indexes with different crypt modes, so callers have
+    /// to check the crypt mode like for chunks loaded from disk.
+    pub fn get(&mut self, digest: &[u8; 32]) -> Option<(CryptMode, Vec<u8>)> {
+        if self.max_size == 0 {
+            return None;
+        }
+        match self.cache.get_mut(*digest) {
+            Some((crypt_mode, data)) => {
+                self.stats.hits += 1;
+                Some((*crypt_mode, data.clone()))
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache the decoded `data` of the chunk `digest` with its `crypt_mode`, evicting the least
+    /// recently used chunks if necessary.
+    pub fn insert(&mut self, digest: &[u8; 32], crypt_mode: CryptMode, data: &[u8]) {
+        if self.max_size == 0 || data.len() > self.max_size {
+            return;
+        }
+
+        if let Some((_, old)) = self.cache.remove(*digest) {
+            self.stats.size -= old.len();
+        }
+
+        // evict ourselves, to keep track of the cached size
+        while self.stats.size + data.len() > self.max_size || self.cache.len() >= self.max_entries {
+            match self.cache.pop_lru() {
+                Some((_, (_, old))) => self.stats.size -= old.len(),
+                None => break,
+            }
+        }
+
+        self.cache.insert(*digest, (crypt_mode, data.to_vec()));
+        self.stats.size += data.len();
+    }
+
+    /// Returns the hit statistics and current usage.
+    pub fn stats(&self) -> ChunkCacheStats {
+        ChunkCacheStats {
+            entries: self.cache.len(),
+            ..self.stats
+        }
+    }
+}
+
+#[test]
+fn test_chunk_cache_size_accounting() {
+    let mut cache = ChunkCache::new(1024 * 1024);
+
+    cache.insert(&[1u8; 32], CryptMode::None, &[0u8; 1000]);
+    cache.insert(&[2u8; 32], CryptMode::None, &[0u8; 2000]);
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.size, 3000);
+
+    // replacing a chunk must not count its old data twice
+    cache.insert(&[1u8; 32], CryptMode::None, &[0u8; 500]);
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.size, 2500);
+
+    assert_eq!(cache.get(&[1u8; 32]).map(|(_, data)| data.len()), Some(500));
+    assert!(cache.get(&[3u8; 32]).is_none());
+    let stats = cache.stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 1);
+}
+
+#[test]
+fn test_chunk_cache_eviction() {
+    let chunk = vec![0u8; 400 * 1024];
+    let mut cache = ChunkCache::new(1024 * 1024);
+
+    cache.insert(&[1u8; 32], CryptMode::None, &chunk);
+    cache.insert(&[2u8; 32], CryptMode::None, &chunk);
+    // mark the first chunk as recently used, so the second one gets evicted
+    assert!(cache.get(&[1u8; 32]).is_some());
+    cache.insert(&[3u8; 32], CryptMode::None, &chunk);
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.size, 2 * chunk.len());
+    assert!(cache.get(&[1u8; 32]).is_some());
+    assert!(cache.get(&[2u8; 32]).is_none());
+    assert!(cache.get(&[3u8; 32]).is_some());
+
+    // chunks larger than the whole budget are not cached and evict nothing
+    cache.insert(&[4u8; 32], CryptMode::None, &vec![0u8; 2 * 1024 * 1024]);
+    assert!(cache.get(&[4u8; 32]).is_none());
+    assert_eq!(cache.stats().size, 2 * chunk.len());
+}
+
+#[test]
+fn test_chunk_cache_entry_limit() {
+    // 16 entries at most, even if the chunks are tiny
+    let mut cache = ChunkCache::new(16 * CHUNK_CACHE_MIN_AVG_CHUNK_SIZE);
+
+    for i in 0..20u8 {
+        cache.insert(&[i; 32], CryptMode::None, &[i; 16]);
+    }
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 16);
+    assert_eq!(stats.size, 16 * 16);
+    assert!(cache.get(&[3u8; 32]).is_none());
+    assert!(cache.get(&[4u8; 32]).is_some());
+}
+
+#[test]
+fn test_chunk_cache_disabled() {
+    let mut cache = ChunkCache::new(0);
+
+    cache.insert(&[1u8; 32], CryptMode::None, &[0u8; 16]);
+    assert!(cache.get(&[1u8; 32]).is_none());
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 0);
+    assert_eq!(stats.size, 0);
+    assert_eq!(stats.misses, 0);
+}
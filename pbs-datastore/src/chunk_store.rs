@@ -1,19 +1,39 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, format_err, Error};
+use hex::FromHex;
 
 use pbs_api_types::{DatastoreFSyncLevel, GarbageCollectionStatus};
-use proxmox_sys::fs::{create_dir, create_path, file_type_from_file_stat, CreateOptions};
+use proxmox_sys::fs::{
+    create_dir, create_path, file_read_optional_string, file_type_from_file_stat, make_tmp_file,
+    open_file_locked, CreateOptions,
+};
 use proxmox_sys::process_locker::{
     ProcessLockExclusiveGuard, ProcessLockSharedGuard, ProcessLocker,
 };
-use proxmox_sys::task_log;
 use proxmox_sys::WorkerTaskContext;
+use proxmox_sys::{task_log, task_warn};
 
+use crate::chunk_bitmap::ChunkBitmap;
 use crate::DataBlob;
 
+/// Name of the directory below the datastore path broken chunks are moved to
+pub const CORRUPT_DIR_NAME: &str = ".corrupt";
+
+/// Name of the file next to the garbage collection status, which counts the bytes of the chunks
+/// inserted since the last garbage collection
+pub const INSERTED_BYTES_FILE_NAME: &str = ".inserted-bytes";
+
+// the bytes inserted by a process are added to the counter file once they exceed this
+const INSERTED_BYTES_FLUSH_THRESHOLD: u64 = 64 * 1024 * 1024;
+
 /// File system based chunk store
 pub struct ChunkStore {
     name: String, // used for error reporting
@@ -22,10 +42,77 @@ pub struct ChunkStore {
     mutex: Mutex<()>,
     locker: Option<Arc<Mutex<ProcessLocker>>>,
     sync_level: DatastoreFSyncLevel,
+    // bytes of the chunks inserted by this process, not yet added to the counter file
+    inserted_bytes: AtomicU64,
+    // value of the counter file when this process last read or updated it
+    counted_inserted_bytes: AtomicU64,
+}
+
+/// Result of [`ChunkStore::check_chunks`]
+#[derive(Default)]
+pub struct ChunkCheckStatus {
+    /// Number of checked chunk files
+    pub checked_chunks: usize,
+    /// Digests of the empty or corrupt chunks
+    pub broken_chunks: Vec<[u8; 32]>,
+    /// Number of chunks stored in the wrong directory
+    pub misplaced_chunks: usize,
+    /// Number of files which are no chunks
+    pub unknown_files: usize,
+}
+
+/// Result of [`ChunkStore::recompress_chunks`]
+#[derive(Default)]
+pub struct ChunkRecompressStatus {
+    /// Number of rewritten chunks
+    pub rewritten_chunks: usize,
+    /// Number of chunks kept as they are, for example because they are encrypted
+    pub skipped_chunks: usize,
+    /// Number of chunks which could not be read or written
+    pub failed_chunks: usize,
+    /// Size of the rewritten chunks before rewriting them
+    pub old_bytes: u64,
+    /// Size of the rewritten chunks after rewriting them
+    pub new_bytes: u64,
 }
 
 // TODO: what about sysctl setting vm.vfs_cache_pressure (0 - 100) ?
 
+// FICLONE, from linux/fs.h
+nix::ioctl_write_int!(ficlone, 0x94, 9);
+
+/// Clone the content of `source` into the empty file `target`, sharing the data blocks if the file
+/// system supports reflinks, or copying them in the kernel otherwise.
+fn clone_file(source: &File, target: &File, size: u64) -> Result<(), Error> {
+    let cloned = unsafe {
+        ficlone(
+            target.as_raw_fd(),
+            source.as_raw_fd() as nix::sys::ioctl::ioctl_param_type,
+        )
+    };
+    if cloned.is_ok() {
+        return Ok(());
+    }
+
+    // not supported or across file systems
+    let mut remaining = size as usize;
+    while remaining > 0 {
+        let copied = nix::fcntl::copy_file_range(
+            source.as_raw_fd(),
+            None,
+            target.as_raw_fd(),
+            None,
+            remaining,
+        )?;
+        if copied == 0 {
+            bail!("unexpected end of file");
+        }
+        remaining -= copied;
+    }
+
+    Ok(())
+}
+
 pub fn verify_chunk_size(size: usize) -> Result<(), Error> {
     static SIZES: [usize; 7] = [
         64 * 1024,
@@ -69,6 +156,8 @@ impl ChunkStore {
             mutex: Mutex::new(()),
             locker: None,
             sync_level: Default::default(),
+            inserted_bytes: AtomicU64::new(0),
+            counted_inserted_bytes: AtomicU64::new(0),
         }
     }
 
@@ -181,6 +270,11 @@ impl ChunkStore {
 
         let locker = ProcessLocker::new(&lockfile_path)?;
 
+        let counted_inserted_bytes =
+            file_read_optional_string(base.join(INSERTED_BYTES_FILE_NAME))?
+                .and_then(|counter| counter.trim().parse().ok())
+                .unwrap_or(0);
+
         Ok(ChunkStore {
             name: name.to_owned(),
             base,
@@ -188,6 +282,8 @@ impl ChunkStore {
             locker: Some(locker),
             mutex: Mutex::new(()),
             sync_level,
+            inserted_bytes: AtomicU64::new(0),
+            counted_inserted_bytes: AtomicU64::new(counted_inserted_bytes),
         })
     }
 
@@ -345,7 +441,9 @@ impl ChunkStore {
         &self,
         oldest_writer: i64,
         phase1_start_time: i64,
+        marks: &ChunkBitmap,
         status: &mut GarbageCollectionStatus,
+        progress: &mut dyn FnMut(usize, usize),
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
         // unwrap: only `None` in unit tests
@@ -368,7 +466,7 @@ impl ChunkStore {
         for (entry, percentage, bad) in self.get_chunk_iterator()? {
             if last_percentage != percentage {
                 last_percentage = percentage;
-                task_log!(worker, "processed {}% ({} chunks)", percentage, chunk_count,);
+                progress(percentage, chunk_count);
             }
 
             worker.check_abort()?;
@@ -395,7 +493,9 @@ impl ChunkStore {
 
                 chunk_count += 1;
 
-                if stat.st_atime < min_atime {
+                let in_use = self.marked_in_use(filename.to_bytes(), bad, marks);
+
+                if stat.st_atime < min_atime && !in_use {
                     //let age = now - stat.st_atime;
                     //println!("UNLINK {}  {:?}", age/(3600*24), filename);
                     if let Err(err) = unlinkat(Some(dirfd), filename, UnlinkatFlags::NoRemoveDir) {
@@ -413,26 +513,400 @@ impl ChunkStore {
                         status.removed_chunks += 1;
                     }
                     status.removed_bytes += stat.st_size as u64;
-                } else if stat.st_atime < oldest_writer {
-                    if bad {
-                        status.still_bad += 1;
-                    } else {
-                        status.pending_chunks += 1;
-                    }
+                } else if bad {
+                    status.still_bad += 1;
+                    status.still_bad_bytes += stat.st_size as u64;
+                } else if stat.st_atime < oldest_writer && !in_use {
+                    status.pending_chunks += 1;
                     status.pending_bytes += stat.st_size as u64;
                 } else {
-                    if !bad {
-                        status.disk_chunks += 1;
-                    }
+                    status.disk_chunks += 1;
                     status.disk_bytes += stat.st_size as u64;
                 }
             }
             drop(lock);
         }
 
+        self.sweep_quarantined_chunks(oldest_writer, min_atime, marks, status, worker)
+    }
+
+    // chunks of unchanged index files do not get touched by incremental garbage collection, but
+    // set in `marks`. Their quarantined copies are kept while the chunk is missing, like touching
+    // them would.
+    fn marked_in_use(&self, file_name: &[u8], bad: bool, marks: &ChunkBitmap) -> bool {
+        // quarantined copies are named <digest>.<counter>.bad
+        let digest = match file_name.get(..64).map(<[u8; 32]>::from_hex) {
+            Some(Ok(digest)) => digest,
+            _ => return false,
+        };
+        if !marks.contains(&digest) {
+            return false;
+        }
+        !bad || !self.chunk_path(&digest).0.exists()
+    }
+
+    // quarantined chunks are kept as long as garbage collection touches them, which it does while
+    // an index file references the chunk and no correct copy of it got inserted again
+    fn sweep_quarantined_chunks(
+        &self,
+        oldest_writer: i64,
+        min_atime: i64,
+        marks: &ChunkBitmap,
+        status: &mut GarbageCollectionStatus,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(), Error> {
+        let corrupt_dir = self.corrupt_dir();
+        let entries = match std::fs::read_dir(&corrupt_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => bail!("unable to read {corrupt_dir:?} - {err}"),
+        };
+
+        for entry in entries {
+            worker.check_abort()?;
+
+            let path = entry?.path();
+            if !path.to_string_lossy().ends_with(".bad") {
+                continue;
+            }
+
+            let _lock = self.mutex.lock();
+
+            let metadata = match std::fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+
+            let in_use = path.file_name().map_or(false, |name| {
+                self.marked_in_use(name.as_bytes(), true, marks)
+            });
+
+            if metadata.atime() < min_atime && !in_use {
+                if let Err(err) = std::fs::remove_file(&path) {
+                    status.still_bad += 1;
+                    bail!(
+                        "unlinking chunk {path:?} failed on store '{}' - {err}",
+                        self.name,
+                    );
+                }
+                status.removed_bad += 1;
+                status.removed_bytes += metadata.size();
+            } else {
+                // kept quarantined chunks are no regular chunk data, so account them separately
+                status.still_bad += 1;
+                status.still_bad_bytes += metadata.size();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check all chunk files for zero length, a directory not matching their digest, a wrong
+    /// checksum and, for unencrypted chunks, a wrong digest.
+    ///
+    /// With `repair`, broken chunks are quarantined like verification does, so that
+    /// the next backup or sync can store them again, and misplaced chunks are moved to their
+    /// directory.
+    pub fn check_chunks(
+        &self,
+        repair: bool,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<ChunkCheckStatus, Error> {
+        // unwrap: only `None` in unit tests
+        assert!(self.locker.is_some());
+
+        let mut status = ChunkCheckStatus::default();
+        let mut last_percentage = 0;
+
+        for at in 0..0x10000 {
+            let percentage = at * 100 / 0x10000;
+            if percentage != last_percentage {
+                last_percentage = percentage;
+                task_log!(
+                    worker,
+                    "checked {}% ({} chunks)",
+                    percentage,
+                    status.checked_chunks
+                );
+            }
+
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let prefix = format!("{:04x}", at);
+            let entries = match std::fs::read_dir(self.chunk_dir.join(&prefix)) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => bail!("unable to read chunk dir '{prefix}' - {err}"),
+            };
+
+            for entry in entries {
+                let mut path = entry?.path();
+                let name = match path.file_name().and_then(|name| name.to_str()) {
+                    Some(name) => name.to_string(),
+                    None => {
+                        status.unknown_files += 1;
+                        task_warn!(worker, "unexpected file {path:?}");
+                        continue;
+                    }
+                };
+
+                // already found broken, or a chunk getting inserted
+                if name.ends_with(".bad") || name.contains(".tmp_") {
+                    continue;
+                }
+
+                let digest = match <[u8; 32]>::from_hex(&name) {
+                    Ok(digest) if hex::encode(digest) == name => digest,
+                    _ => {
+                        status.unknown_files += 1;
+                        task_warn!(worker, "unexpected file {path:?}");
+                        continue;
+                    }
+                };
+
+                status.checked_chunks += 1;
+
+                if !name.starts_with(&prefix) {
+                    status.misplaced_chunks += 1;
+                    task_warn!(
+                        worker,
+                        "chunk {name} is stored in wrong directory '{prefix}'"
+                    );
+                    if repair {
+                        path = match self.move_misplaced_chunk(&path, &digest) {
+                            Err(err) if is_not_found(&err) => continue,
+                            result => result?,
+                        };
+                        task_log!(worker, "moved chunk {name} to {path:?}");
+                    }
+                }
+
+                if let Err(err) = check_chunk_file(&path, &digest) {
+                    if is_not_found(&err) {
+                        // removed since it was listed, for example by verify or a repair
+                        continue;
+                    }
+                    task_warn!(worker, "chunk {name} is broken - {err}");
+                    status.broken_chunks.push(digest);
+                    if repair {
+                        match self.quarantine_chunk(&digest)? {
+                            Some(bad_path) => {
+                                task_log!(worker, "moved broken chunk to {bad_path:?}")
+                            }
+                            None => task_log!(worker, "chunk {name} got replaced in the meantime"),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(status)
+    }
+
+    // move a chunk found in the wrong directory to its path, if it is not already there,
+    // returns the path of the chunk
+    fn move_misplaced_chunk(&self, path: &Path, digest: &[u8; 32]) -> Result<PathBuf, Error> {
+        let (chunk_path, _digest_str) = self.chunk_path(digest);
+
+        let _lock = self.mutex.lock();
+
+        if chunk_path.exists() {
+            std::fs::remove_file(path)?;
+        } else {
+            std::fs::rename(path, &chunk_path)?;
+        }
+
+        Ok(chunk_path)
+    }
+
+    /// Returns the directory broken chunks are moved to by [ChunkStore::quarantine_chunk].
+    pub fn corrupt_dir(&self) -> PathBuf {
+        self.base.join(CORRUPT_DIR_NAME)
+    }
+
+    /// Move a broken chunk to a `.bad` file in the [corrupt directory](ChunkStore::corrupt_dir),
+    /// unless it got replaced by a correct one since it was checked. Returns the new path, or
+    /// `None` if the chunk is fine or does not exist.
+    pub fn quarantine_chunk(&self, digest: &[u8; 32]) -> Result<Option<PathBuf>, Error> {
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let _lock = self.mutex.lock();
+
+        if !chunk_path.exists() || check_chunk_file(&chunk_path, digest).is_ok() {
+            return Ok(None);
+        }
+
+        let corrupt_dir = self.corrupt_dir();
+        std::fs::create_dir_all(&corrupt_dir)
+            .map_err(|err| format_err!("unable to create {corrupt_dir:?} - {err}"))?;
+
+        // keep at most 10 bad copies
+        let mut counter = 0;
+        let mut bad_path;
+        loop {
+            bad_path = corrupt_dir.join(format!("{digest_str}.{counter}.bad"));
+            if bad_path.exists() && counter < 9 {
+                counter += 1;
+            } else {
+                break;
+            }
+        }
+
+        std::fs::rename(&chunk_path, &bad_path).map_err(|err| {
+            format_err!(
+                "renaming broken chunk {digest_str} on store '{}' failed - {err}",
+                self.name
+            )
+        })?;
+
+        Ok(Some(bad_path))
+    }
+
+    /// Update the atime of the quarantined copies of a missing chunk, so that garbage collection
+    /// keeps them while the chunk is referenced. This includes `.bad` files next to the chunk
+    /// path, where older versions renamed broken chunks to.
+    pub fn touch_quarantined_chunk(&self, digest: &[u8; 32]) -> Result<(), Error> {
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+        let corrupt_dir = self.corrupt_dir();
+
+        for counter in 0..=9 {
+            let name = format!("{digest_str}.{counter}.bad");
+            self.cond_touch_path(&chunk_path.with_file_name(&name), false)?;
+            self.cond_touch_path(&corrupt_dir.join(&name), false)?;
+        }
         Ok(())
     }
 
+    /// Rewrite all unencrypted chunks compressed with the zstd `level`, or uncompressed if it is
+    /// `None`. Chunks are only replaced if that makes them smaller, or to store them
+    /// uncompressed. The digests do not change, so index files stay untouched.
+    ///
+    /// Encrypted chunks are skipped, as they cannot be decrypted without the client's key.
+    ///
+    /// The size difference of the rewritten chunks is counted like inserted chunks, so shrunk
+    /// chunks free quota right away.
+    pub fn recompress_chunks(
+        &self,
+        level: Option<i32>,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<ChunkRecompressStatus, Error> {
+        // unwrap: only `None` in unit tests
+        assert!(self.locker.is_some());
+
+        let mut status = ChunkRecompressStatus::default();
+
+        let result = self.recompress_chunk_dirs(level, worker, &mut status);
+
+        // also account the chunks rewritten before an abort
+        if status.new_bytes > status.old_bytes {
+            self.add_inserted_bytes(status.new_bytes - status.old_bytes);
+        } else if status.new_bytes < status.old_bytes {
+            self.account_inserted_bytes(status.old_bytes - status.new_bytes)?;
+        }
+
+        result.map(|()| status)
+    }
+
+    fn recompress_chunk_dirs(
+        &self,
+        level: Option<i32>,
+        worker: &dyn WorkerTaskContext,
+        status: &mut ChunkRecompressStatus,
+    ) -> Result<(), Error> {
+        let mut last_percentage = 0;
+
+        for at in 0..0x10000 {
+            let percentage = at * 100 / 0x10000;
+            if percentage != last_percentage {
+                last_percentage = percentage;
+                task_log!(
+                    worker,
+                    "processed {}% ({} chunks rewritten)",
+                    percentage,
+                    status.rewritten_chunks
+                );
+            }
+
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let prefix = format!("{:04x}", at);
+            let entries = match std::fs::read_dir(self.chunk_dir.join(&prefix)) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => bail!("unable to read chunk dir '{prefix}' - {err}"),
+            };
+
+            for entry in entries {
+                let name = entry?.file_name();
+                let digest = match name.to_str().map(<[u8; 32]>::from_hex) {
+                    Some(Ok(digest)) => digest,
+                    _ => continue, // bad chunks, temporary and unknown files
+                };
+
+                match self.recompress_chunk(&digest, level) {
+                    Ok(Some((old_size, new_size))) => {
+                        status.rewritten_chunks += 1;
+                        status.old_bytes += old_size;
+                        status.new_bytes += new_size;
+                    }
+                    Ok(None) => status.skipped_chunks += 1,
+                    Err(err) => {
+                        status.failed_chunks += 1;
+                        task_warn!(
+                            worker,
+                            "could not recompress chunk {} - {}",
+                            hex::encode(digest),
+                            err
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // returns the old and new size of the chunk, or `None` if it was kept
+    fn recompress_chunk(
+        &self,
+        digest: &[u8; 32],
+        level: Option<i32>,
+    ) -> Result<Option<(u64, u64)>, Error> {
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let mut file = match File::open(&chunk_path) {
+            Ok(file) => file,
+            // removed by garbage collection in the meantime
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let blob = DataBlob::load_from_reader(&mut file)?;
+        drop(file);
+
+        if blob.is_encrypted() {
+            return Ok(None);
+        }
+
+        let data = blob.decode(None, Some(digest))?;
+        let new_blob = DataBlob::encode_with_level(&data, None, level)?;
+
+        let old_size = blob.raw_size();
+        let new_size = new_blob.raw_size();
+        let decompress = level.is_none() && blob.magic() != new_blob.magic();
+        if new_size >= old_size && !decompress {
+            return Ok(None);
+        }
+
+        let _lock = self.mutex.lock();
+
+        // the new file gets a current atime, so garbage collection keeps it
+        self.write_chunk_file(&chunk_path, &digest_str, new_blob.raw_data())?;
+
+        Ok(Some((old_size, new_size)))
+    }
+
     pub fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error> {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -468,12 +942,56 @@ impl ChunkStore {
             }
         }
 
+        self.write_chunk_file(&chunk_path, &digest_str, raw_data)?;
+
+        drop(lock);
+
+        self.add_inserted_bytes(encoded_size);
+
+        Ok((false, encoded_size))
+    }
+
+    /// Replace a chunk atomically, even if a chunk of the same size exists, for example to
+    /// replace a corrupt chunk with a correct copy. Returns the encoded size.
+    pub fn replace_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<u64, Error> {
+        // unwrap: only `None` in unit tests
+        assert!(self.locker.is_some());
+
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let lock = self.mutex.lock();
+
+        let raw_data = chunk.raw_data();
+        let encoded_size = raw_data.len() as u64;
+
+        let existed = chunk_path.exists();
+
+        self.write_chunk_file(&chunk_path, &digest_str, raw_data)?;
+
+        drop(lock);
+
+        if !existed {
+            self.add_inserted_bytes(encoded_size);
+        }
+
+        Ok(encoded_size)
+    }
+
+    // atomically write the chunk file, with the chunk store mutex held
+    fn write_chunk_file(
+        &self,
+        chunk_path: &Path,
+        digest_str: &str,
+        raw_data: &[u8],
+    ) -> Result<(), Error> {
+        let name = &self.name;
+
         let chunk_dir_path = chunk_path
             .parent()
             .ok_or_else(|| format_err!("unable to get chunk dir"))?;
 
         proxmox_sys::fs::replace_file(
-            &chunk_path,
+            chunk_path,
             raw_data,
             CreateOptions::new(),
             self.sync_level == DatastoreFSyncLevel::File,
@@ -489,11 +1007,155 @@ impl ChunkStore {
                 .map_err(|err| format_err!("fsync failed: {err}"))?;
         }
 
+        Ok(())
+    }
+
+    /// Insert a chunk by cloning the chunk file `source`, usually from another chunk store on the
+    /// same file system.
+    ///
+    /// File systems supporting reflinks, like btrfs or XFS, share the data blocks of both files
+    /// instead of duplicating them. Others copy the data in the kernel.
+    pub fn insert_chunk_file(
+        &self,
+        source: &Path,
+        digest: &[u8; 32],
+    ) -> Result<(bool, u64), Error> {
+        // unwrap: only `None` in unit tests
+        assert!(self.locker.is_some());
+
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let source_file = std::fs::File::open(source)?;
+        let encoded_size = source_file.metadata()?.len();
+
+        let lock = self.mutex.lock();
+
+        let name = &self.name;
+
+        if let Ok(metadata) = std::fs::metadata(&chunk_path) {
+            if !metadata.is_file() {
+                bail!("got unexpected file type on store '{name}' for chunk {digest_str}");
+            }
+            if metadata.len() == encoded_size {
+                self.touch_chunk(digest)?;
+                return Ok((true, encoded_size));
+            }
+        }
+
+        let chunk_dir_path = chunk_path
+            .parent()
+            .ok_or_else(|| format_err!("unable to get chunk dir"))?;
+
+        let (tmp_file, tmp_path) = make_tmp_file(&chunk_path, CreateOptions::new())?;
+
+        let result: Result<(), Error> = proxmox_lang::try_block!({
+            clone_file(&source_file, &tmp_file, encoded_size)?;
+            if self.sync_level == DatastoreFSyncLevel::File {
+                nix::unistd::fsync(tmp_file.as_raw_fd())?;
+            }
+            std::fs::rename(&tmp_path, &chunk_path)?;
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            let _ = std::fs::remove_file(&tmp_path);
+            bail!("inserting chunk on store '{name}' failed for {digest_str} - {err}");
+        }
+
+        if self.sync_level == DatastoreFSyncLevel::File {
+            // fsync dir handle to persist the tmp rename
+            let dir = std::fs::File::open(chunk_dir_path)?;
+            nix::unistd::fsync(dir.as_raw_fd())
+                .map_err(|err| format_err!("fsync failed: {err}"))?;
+        }
+
         drop(lock);
 
+        self.add_inserted_bytes(encoded_size);
+
         Ok((false, encoded_size))
     }
 
+    /// Counts `bytes` of newly inserted chunks, for chunks stored by another backend.
+    pub(crate) fn add_inserted_bytes(&self, bytes: u64) {
+        let pending = self.inserted_bytes.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        if pending >= INSERTED_BYTES_FLUSH_THRESHOLD {
+            if let Err(err) = self.update_inserted_bytes_file(0) {
+                log::warn!(
+                    "unable to count inserted chunks of store '{}' - {err}",
+                    self.name
+                );
+            }
+        }
+    }
+
+    /// Returns the size of the chunks inserted since the last garbage collection, which are not
+    /// yet accounted in its status.
+    ///
+    /// Chunks inserted by other processes are only included up to the last time this process
+    /// read the counter file, see [`ChunkStore::refresh_inserted_bytes`].
+    pub fn inserted_bytes(&self) -> u64 {
+        self.counted_inserted_bytes.load(Ordering::SeqCst)
+            + self.inserted_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Adds the chunks inserted by this process to the counter file and returns the size of all
+    /// chunks inserted since the last garbage collection.
+    pub fn refresh_inserted_bytes(&self) -> Result<u64, Error> {
+        self.update_inserted_bytes_file(0)
+    }
+
+    /// Removes `bytes` from the counter of inserted bytes, once they got accounted by a garbage
+    /// collection.
+    pub(crate) fn account_inserted_bytes(&self, bytes: u64) -> Result<(), Error> {
+        self.update_inserted_bytes_file(bytes)?;
+        Ok(())
+    }
+
+    // adds the bytes inserted by this process to the counter file and subtracts `accounted`
+    // bytes from it, returns the new value
+    fn update_inserted_bytes_file(&self, accounted: u64) -> Result<u64, Error> {
+        let path = self.base.join(INSERTED_BYTES_FILE_NAME);
+
+        // owned like the datastore directory, as any process may create it
+        let stat = std::fs::metadata(&self.base)?;
+        let options = CreateOptions::new()
+            .perm(nix::sys::stat::Mode::from_bits_truncate(0o644))
+            .owner(nix::unistd::Uid::from_raw(stat.uid()))
+            .group(nix::unistd::Gid::from_raw(stat.gid()));
+
+        let timeout = std::time::Duration::new(10, 0);
+        let mut file = open_file_locked(&path, timeout, true, options)
+            .map_err(|err| format_err!("unable to lock {path:?} - {err}"))?;
+
+        let pending = self.inserted_bytes.swap(0, Ordering::SeqCst);
+
+        let result: Result<u64, Error> = proxmox_lang::try_block!({
+            let mut counter = String::new();
+            file.read_to_string(&mut counter)?;
+            // a broken counter only underestimates the usage until the next garbage collection
+            let counted: u64 = counter.trim().parse().unwrap_or(0);
+
+            let counted = (counted + pending).saturating_sub(accounted);
+
+            file.set_len(0)?;
+            file.write_all_at(counted.to_string().as_bytes(), 0)?;
+
+            Ok(counted)
+        });
+
+        match result {
+            Ok(counted) => {
+                self.counted_inserted_bytes.store(counted, Ordering::SeqCst);
+                Ok(counted)
+            }
+            Err(err) => {
+                self.inserted_bytes.fetch_add(pending, Ordering::SeqCst);
+                bail!("unable to update {path:?} - {err}");
+            }
+        }
+    }
+
     pub fn chunk_path(&self, digest: &[u8; 32]) -> (PathBuf, String) {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -537,6 +1199,46 @@ impl ChunkStore {
     }
 }
 
+impl Drop for ChunkStore {
+    fn drop(&mut self) {
+        // keep the count of the chunks inserted by this process
+        if self.locker.is_some() && self.inserted_bytes.load(Ordering::SeqCst) > 0 {
+            if let Err(err) = self.update_inserted_bytes_file(0) {
+                log::warn!(
+                    "unable to count inserted chunks of store '{}' - {err}",
+                    self.name
+                );
+            }
+        }
+    }
+}
+
+fn is_not_found(err: &Error) -> bool {
+    matches!(
+        err.downcast_ref::<std::io::Error>(),
+        Some(err) if err.kind() == std::io::ErrorKind::NotFound
+    )
+}
+
+// check a chunk file for zero length, its checksum and, if it is not encrypted, its digest
+fn check_chunk_file(path: &Path, digest: &[u8; 32]) -> Result<(), Error> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_file() {
+        bail!("not a regular file");
+    }
+    if metadata.len() == 0 {
+        bail!("zero-length chunk");
+    }
+
+    let mut file = File::open(path)?;
+    let chunk = DataBlob::load_from_reader(&mut file)?;
+    if !chunk.is_encrypted() {
+        chunk.decode(None, Some(digest))?;
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_chunk_store1() {
     let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
@@ -582,3 +1284,195 @@ fn test_chunk_store1() {
 
     if let Err(_e) = std::fs::remove_dir_all(".testdir") { /* ignore */ }
 }
+
+// create an empty chunk store in `.testdir-<name>`, owned by the current user
+#[cfg(test)]
+pub(crate) fn create_test_chunk_store(name: &str) -> (ChunkStore, PathBuf) {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(format!(".testdir-{name}"));
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .unwrap()
+        .unwrap();
+    let chunk_store = ChunkStore::create(
+        "test",
+        &path,
+        user.uid,
+        user.gid,
+        None,
+        DatastoreFSyncLevel::None,
+    )
+    .unwrap();
+
+    (chunk_store, path)
+}
+
+#[test]
+fn test_chunk_store_inserted_bytes() {
+    let (chunk_store, path) = create_test_chunk_store("inserted-bytes");
+
+    let insert = |chunk_store: &ChunkStore, data: &[u8]| {
+        let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(data)
+            .build()
+            .unwrap();
+        chunk_store.insert_chunk(&chunk, &digest).unwrap()
+    };
+
+    let (_exists, size1) = insert(&chunk_store, b"chunk1");
+    assert_eq!(chunk_store.inserted_bytes(), size1);
+    // already stored chunks are not counted again
+    insert(&chunk_store, b"chunk1");
+    assert_eq!(chunk_store.inserted_bytes(), size1);
+
+    // the counter persists across processes, simulated by dropping the chunk store
+    assert_eq!(chunk_store.refresh_inserted_bytes().unwrap(), size1);
+    let (_exists, size2) = insert(&chunk_store, b"chunk2");
+    drop(chunk_store);
+    let chunk_store = ChunkStore::open("test", &path, DatastoreFSyncLevel::None).unwrap();
+    assert_eq!(chunk_store.inserted_bytes(), size1 + size2);
+
+    // garbage collection only accounts the chunks counted when its sweep phase started, chunks
+    // inserted in the meantime stay counted
+    let accounted = chunk_store.refresh_inserted_bytes().unwrap();
+    let (_exists, size3) = insert(&chunk_store, b"chunk3");
+    chunk_store.account_inserted_bytes(accounted).unwrap();
+    assert_eq!(chunk_store.inserted_bytes(), size3);
+    assert_eq!(chunk_store.refresh_inserted_bytes().unwrap(), size3);
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
+
+#[test]
+fn test_chunk_store_replace_chunk() {
+    let (chunk_store, path) = create_test_chunk_store("replace-chunk");
+
+    let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(b"chunk")
+        .build()
+        .unwrap();
+    chunk_store.insert_chunk(&chunk, &digest).unwrap();
+
+    // corrupt the chunk, keeping its size
+    let (chunk_path, _digest_str) = chunk_store.chunk_path(&digest);
+    let mut raw_data = chunk.raw_data().to_vec();
+    let last = raw_data.len() - 1;
+    raw_data[last] ^= 0xff;
+    std::fs::write(&chunk_path, &raw_data).unwrap();
+    assert!(check_chunk_file(&chunk_path, &digest).is_err());
+
+    // inserting keeps a chunk of the same size, replacing does not
+    let (exists, _) = chunk_store.insert_chunk(&chunk, &digest).unwrap();
+    assert!(exists);
+    assert!(check_chunk_file(&chunk_path, &digest).is_err());
+
+    chunk_store.replace_chunk(&chunk, &digest).unwrap();
+    check_chunk_file(&chunk_path, &digest).unwrap();
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
+
+#[test]
+fn test_chunk_store_recompress_chunks() {
+    use crate::data_blob::DataChunkBuilder;
+
+    let (chunk_store, path) = create_test_chunk_store("recompress");
+
+    let data = vec![0x55u8; 64 * 1024];
+    let (chunk, digest) = DataChunkBuilder::new(&data)
+        .compress(false)
+        .build()
+        .unwrap();
+    chunk_store.insert_chunk(&chunk, &digest).unwrap();
+
+    let crypt_config = pbs_tools::crypt_config::CryptConfig::new([1u8; 32]).unwrap();
+    let encrypted_data = vec![0xaau8; 64 * 1024];
+    let (encrypted, encrypted_digest) = DataChunkBuilder::new(&encrypted_data)
+        .compress(false)
+        .crypt_config(&crypt_config)
+        .build()
+        .unwrap();
+    chunk_store
+        .insert_chunk(&encrypted, &encrypted_digest)
+        .unwrap();
+    let inserted = chunk_store.refresh_inserted_bytes().unwrap();
+
+    let status = chunk_store
+        .recompress_chunks(Some(3), &crate::datastore::TestWorker)
+        .unwrap();
+    assert_eq!(status.rewritten_chunks, 1);
+    assert_eq!(status.skipped_chunks, 1);
+    assert_eq!(status.failed_chunks, 0);
+    assert_eq!(status.old_bytes, chunk.raw_size());
+    assert!(status.new_bytes < status.old_bytes);
+
+    // the rewritten chunk decodes to the same data, the encrypted one is untouched
+    let (chunk_path, _digest_str) = chunk_store.chunk_path(&digest);
+    check_chunk_file(&chunk_path, &digest).unwrap();
+    let blob = DataBlob::load_from_reader(&mut File::open(&chunk_path).unwrap()).unwrap();
+    assert_eq!(
+        blob.magic(),
+        &crate::file_formats::COMPRESSED_BLOB_MAGIC_1_0
+    );
+    assert_eq!(blob.decode(None, Some(&digest)).unwrap(), data);
+    let (encrypted_path, _digest_str) = chunk_store.chunk_path(&encrypted_digest);
+    assert_eq!(std::fs::read(encrypted_path).unwrap(), encrypted.raw_data());
+
+    // the saved space is no longer counted against the quota
+    assert_eq!(
+        chunk_store.refresh_inserted_bytes().unwrap(),
+        inserted - (status.old_bytes - status.new_bytes)
+    );
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
+
+#[test]
+fn test_chunk_store_sweep_quarantined_chunks() {
+    let (chunk_store, path) = create_test_chunk_store("sweep-quarantined");
+
+    let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(b"chunk")
+        .build()
+        .unwrap();
+    chunk_store.insert_chunk(&chunk, &digest).unwrap();
+
+    let (chunk_path, _digest_str) = chunk_store.chunk_path(&digest);
+    std::fs::write(&chunk_path, b"corrupt").unwrap();
+    let bad_path = chunk_store.quarantine_chunk(&digest).unwrap().unwrap();
+    let bad_size = std::fs::metadata(&bad_path).unwrap().len();
+
+    let worker = crate::datastore::TestWorker;
+    let marks = ChunkBitmap::new(0);
+
+    // recently touched quarantined chunks are kept, but not counted as regular chunk data
+    let mut status = GarbageCollectionStatus::default();
+    chunk_store
+        .sweep_quarantined_chunks(0, 0, &marks, &mut status, &worker)
+        .unwrap();
+    assert_eq!(status.still_bad, 1);
+    assert_eq!(status.still_bad_bytes, bad_size);
+    assert_eq!(status.disk_bytes, 0);
+    assert_eq!(status.pending_bytes, 0);
+    assert!(bad_path.exists());
+
+    // the chunks of unchanged index files are only marked, their copies are kept while missing
+    let unchanged = ChunkBitmap::new(0);
+    unchanged.insert(&digest);
+    let mut status = GarbageCollectionStatus::default();
+    chunk_store
+        .sweep_quarantined_chunks(i64::MAX, i64::MAX, &unchanged, &mut status, &worker)
+        .unwrap();
+    assert_eq!(status.still_bad, 1);
+    assert!(bad_path.exists());
+
+    let mut status = GarbageCollectionStatus::default();
+    chunk_store
+        .sweep_quarantined_chunks(i64::MAX, i64::MAX, &marks, &mut status, &worker)
+        .unwrap();
+    assert_eq!(status.removed_bad, 1);
+    assert_eq!(status.removed_bytes, bad_size);
+    assert_eq!(status.still_bad, 0);
+    assert!(!bad_path.exists());
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
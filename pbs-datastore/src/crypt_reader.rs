@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use anyhow::{bail, Error};
 
+use pbs_api_types::CryptCipher;
 use pbs_tools::crypt_config::CryptConfig;
 
 pub struct CryptReader<R> {
@@ -18,13 +19,14 @@ impl<R: BufRead> CryptReader<R> {
         reader: R,
         iv: [u8; 16],
         tag: [u8; 16],
+        cipher: CryptCipher,
         config: Arc<CryptConfig>,
     ) -> Result<Self, Error> {
-        let block_size = config.cipher().block_size(); // Note: block size is normally 1 byte for stream ciphers
+        let block_size = CryptConfig::openssl_cipher(cipher).block_size(); // Note: block size is normally 1 byte for stream ciphers
         if block_size.count_ones() != 1 || block_size > 512 {
             bail!("unexpected Cipher block size {}", block_size);
         }
-        let mut crypter = config.data_crypter(&iv, openssl::symm::Mode::Decrypt)?;
+        let mut crypter = config.data_crypter_for(cipher, &iv, openssl::symm::Mode::Decrypt)?;
         crypter.set_tag(&tag)?;
 
         Ok(Self {
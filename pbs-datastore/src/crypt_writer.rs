@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use anyhow::Error;
 
+use pbs_api_types::CryptCipher;
 use pbs_tools::crypt_config::CryptConfig;
 
 pub struct CryptWriter<W> {
@@ -10,6 +11,7 @@ pub struct CryptWriter<W> {
     block_size: usize,
     encr_buf: Box<[u8; 64 * 1024]>,
     iv: [u8; 16],
+    cipher: CryptCipher,
     crypter: openssl::symm::Crypter,
 }
 
@@ -24,12 +26,18 @@ impl<W: Write> CryptWriter<W> {
         Ok(Self {
             writer,
             iv,
+            cipher: config.crypt_cipher(),
             crypter,
             block_size,
             encr_buf: Box::new([0u8; 64 * 1024]),
         })
     }
 
+    /// The cipher used to encrypt the data
+    pub fn cipher(&self) -> CryptCipher {
+        self.cipher
+    }
+
     pub fn finish(mut self) -> Result<(W, [u8; 16], [u8; 16]), Error> {
         let rest = self.crypter.finalize(self.encr_buf.as_mut())?;
         if rest > 0 {
@@ -1,4 +1,5 @@
 use std::convert::TryInto;
+use std::fmt;
 use std::io::Write;
 
 use anyhow::{bail, Error};
@@ -6,13 +7,37 @@ use openssl::symm::{decrypt_aead, Mode};
 
 use proxmox_io::{ReadExt, WriteExt};
 
-use pbs_api_types::CryptMode;
+use pbs_api_types::{CryptCipher, CryptMode, DEFAULT_ZSTD_LEVEL};
 use pbs_tools::crypt_config::CryptConfig;
 
 use super::file_formats::*;
 
 const MAX_BLOB_SIZE: usize = 128 * 1024 * 1024;
 
+/// Error returned when decoded chunk data does not match the expected digest
+///
+/// Callers can detect corrupt chunks by downcasting the returned [Error] to this type.
+#[derive(Debug)]
+pub struct ChunkDigestMismatch {
+    /// The digest the chunk is referenced with
+    pub expected: [u8; 32],
+    /// The digest computed from the decoded data
+    pub computed: [u8; 32],
+}
+
+impl std::error::Error for ChunkDigestMismatch {}
+
+impl fmt::Display for ChunkDigestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "detected chunk with wrong digest (expected {}, got {}).",
+            hex::encode(self.expected),
+            hex::encode(self.computed),
+        )
+    }
+}
+
 /// Encoded data chunk with digest and positional information
 pub struct ChunkInfo {
     pub chunk: DataBlob,
@@ -89,6 +114,18 @@ impl DataBlob {
         data: &[u8],
         config: Option<&CryptConfig>,
         compress: bool,
+    ) -> Result<Self, Error> {
+        Self::encode_with_level(data, config, compress.then_some(DEFAULT_ZSTD_LEVEL))
+    }
+
+    /// Create a DataBlob, optionally encrypted and compressed with the zstd `compression_level`.
+    ///
+    /// The level is not recorded, as it does not matter for decoding, the blob magic only tells
+    /// whether the data is compressed.
+    pub fn encode_with_level(
+        data: &[u8],
+        config: Option<&CryptConfig>,
+        compression_level: Option<i32>,
     ) -> Result<Self, Error> {
         if data.len() > MAX_BLOB_SIZE {
             bail!("data blob too large ({} bytes).", data.len());
@@ -96,17 +133,18 @@ impl DataBlob {
 
         let mut blob = if let Some(config) = config {
             let compr_data;
-            let (_compress, data, magic) = if compress {
-                compr_data = zstd::block::compress(data, 1)?;
+            let (compress, data) = if let Some(level) = compression_level {
+                compr_data = zstd::block::compress(data, level)?;
                 // Note: We only use compression if result is shorter
                 if compr_data.len() < data.len() {
-                    (true, &compr_data[..], ENCR_COMPR_BLOB_MAGIC_1_0)
+                    (true, &compr_data[..])
                 } else {
-                    (false, data, ENCRYPTED_BLOB_MAGIC_1_0)
+                    (false, data)
                 }
             } else {
-                (false, data, ENCRYPTED_BLOB_MAGIC_1_0)
+                (false, data)
             };
+            let magic = encrypted_blob_magic(config.crypt_cipher(), compress);
 
             let header_len = std::mem::size_of::<EncryptedDataBlobHeader>();
             let mut raw_data = Vec::with_capacity(data.len() + header_len);
@@ -138,7 +176,7 @@ impl DataBlob {
             DataBlob { raw_data }
         } else {
             let max_data_len = data.len() + std::mem::size_of::<DataBlobHeader>();
-            if compress {
+            if let Some(level) = compression_level {
                 let mut comp_data = Vec::with_capacity(max_data_len);
 
                 let head = DataBlobHeader {
@@ -149,7 +187,7 @@ impl DataBlob {
                     comp_data.write_le_value(head)?;
                 }
 
-                zstd::stream::copy_encode(data, &mut comp_data, 1)?;
+                zstd::stream::copy_encode(data, &mut comp_data, level)?;
 
                 if comp_data.len() < max_data_len {
                     let mut blob = DataBlob {
@@ -186,7 +224,7 @@ impl DataBlob {
         Ok(
             if magic == &UNCOMPRESSED_BLOB_MAGIC_1_0 || magic == &COMPRESSED_BLOB_MAGIC_1_0 {
                 CryptMode::None
-            } else if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0 {
+            } else if encrypted_blob_info(magic).is_some() {
                 CryptMode::Encrypt
             } else {
                 bail!("Invalid blob magic number.");
@@ -219,16 +257,17 @@ impl DataBlob {
                 Self::verify_digest(&data, None, digest)?;
             }
             Ok(data)
-        } else if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0 {
+        } else if let Some((cipher, compressed)) = encrypted_blob_info(magic) {
             let header_len = std::mem::size_of::<EncryptedDataBlobHeader>();
             let head = unsafe {
                 (&self.raw_data[..header_len]).read_le_value::<EncryptedDataBlobHeader>()?
             };
 
             if let Some(config) = config {
-                let data = if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 {
+                let data = if compressed {
                     Self::decode_compressed_chunk(
                         config,
+                        cipher,
                         &self.raw_data[header_len..],
                         &head.iv,
                         &head.tag,
@@ -236,6 +275,7 @@ impl DataBlob {
                 } else {
                     Self::decode_uncompressed_chunk(
                         config,
+                        cipher,
                         &self.raw_data[header_len..],
                         &head.iv,
                         &head.tag,
@@ -271,9 +311,9 @@ impl DataBlob {
             bail!("blob too small ({} bytes).", data.len());
         }
 
-        let magic = &data[0..8];
+        let magic: &[u8; 8] = data[0..8].try_into().unwrap();
 
-        if magic == ENCR_COMPR_BLOB_MAGIC_1_0 || magic == ENCRYPTED_BLOB_MAGIC_1_0 {
+        if encrypted_blob_info(magic).is_some() {
             if data.len() < std::mem::size_of::<EncryptedDataBlobHeader>() {
                 bail!("encrypted blob too small ({} bytes).", data.len());
             }
@@ -281,7 +321,7 @@ impl DataBlob {
             let blob = DataBlob { raw_data: data };
 
             Ok(blob)
-        } else if magic == COMPRESSED_BLOB_MAGIC_1_0 || magic == UNCOMPRESSED_BLOB_MAGIC_1_0 {
+        } else if magic == &COMPRESSED_BLOB_MAGIC_1_0 || magic == &UNCOMPRESSED_BLOB_MAGIC_1_0 {
             let blob = DataBlob { raw_data: data };
 
             Ok(blob)
@@ -292,8 +332,7 @@ impl DataBlob {
 
     /// Returns if chunk is encrypted
     pub fn is_encrypted(&self) -> bool {
-        let magic = self.magic();
-        magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0
+        encrypted_blob_info(self.magic()).is_some()
     }
 
     /// Verify digest and data length for unencrypted chunks.
@@ -307,9 +346,7 @@ impl DataBlob {
         expected_chunk_size: usize,
         expected_digest: &[u8; 32],
     ) -> Result<(), Error> {
-        let magic = self.magic();
-
-        if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0 {
+        if self.is_encrypted() {
             return Ok(());
         }
 
@@ -337,7 +374,11 @@ impl DataBlob {
             None => openssl::sha::sha256(data),
         };
         if &digest != expected_digest {
-            bail!("detected chunk with wrong digest.");
+            return Err(ChunkDigestMismatch {
+                expected: *expected_digest,
+                computed: digest,
+            }
+            .into());
         }
 
         Ok(())
@@ -353,7 +394,7 @@ impl DataBlob {
         Ok(())
     }
 
-    // Encrypt data using a random 16 byte IV.
+    // Encrypt data using a random 16 byte IV (of which CHACHA20_POLY1305 only uses 12 bytes).
     //
     // Writes encrypted data to ``output``, Return the used IV and computed MAC.
     fn encrypt_to<W: Write>(
@@ -403,6 +444,7 @@ impl DataBlob {
     // Decompress and decrypt data, verify MAC.
     fn decode_compressed_chunk(
         config: &CryptConfig,
+        cipher: CryptCipher,
         data: &[u8],
         iv: &[u8; 16],
         tag: &[u8; 16],
@@ -411,12 +453,12 @@ impl DataBlob {
 
         let mut decompressor = zstd::stream::write::Decoder::new(dec)?;
 
-        let mut c = config.data_crypter(iv, Mode::Decrypt)?;
+        let mut c = config.data_crypter_for(cipher, iv, Mode::Decrypt)?;
 
         const BUFFER_SIZE: usize = 32 * 1024;
 
         let mut decr_buf = [0u8; BUFFER_SIZE];
-        let max_decoder_input = BUFFER_SIZE - config.cipher().block_size();
+        let max_decoder_input = BUFFER_SIZE - CryptConfig::openssl_cipher(cipher).block_size();
 
         let mut start = 0;
         loop {
@@ -447,14 +489,15 @@ impl DataBlob {
     // Decrypt data, verify tag.
     fn decode_uncompressed_chunk(
         config: &CryptConfig,
+        cipher: CryptCipher,
         data: &[u8],
         iv: &[u8; 16],
         tag: &[u8; 16],
     ) -> Result<Vec<u8>, Error> {
         let decr_data = decrypt_aead(
-            *config.cipher(),
+            CryptConfig::openssl_cipher(cipher),
             config.enc_key(),
-            Some(iv),
+            Some(CryptConfig::cipher_iv(cipher, iv)),
             b"", //??
             data,
             tag,
@@ -475,6 +518,7 @@ pub struct DataChunkBuilder<'a, 'b> {
     digest_computed: bool,
     digest: [u8; 32],
     compress: bool,
+    compression_level: i32,
 }
 
 impl<'a, 'b> DataChunkBuilder<'a, 'b> {
@@ -486,17 +530,24 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
             digest_computed: false,
             digest: [0u8; 32],
             compress: true,
+            compression_level: DEFAULT_ZSTD_LEVEL,
         }
     }
 
     /// Set compression flag.
     ///
-    /// If true, chunk data is compressed using zstd (level 1).
+    /// If true, chunk data is compressed using zstd (level 1 by default).
     pub fn compress(mut self, value: bool) -> Self {
         self.compress = value;
         self
     }
 
+    /// Set the zstd level used if compression is enabled.
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
     /// Set encryption Configuration
     ///
     /// If set, chunks are encrypted
@@ -538,7 +589,11 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
             self.compute_digest();
         }
 
-        let chunk = DataBlob::encode(self.orig_data, self.config, self.compress)?;
+        let chunk = DataBlob::encode_with_level(
+            self.orig_data,
+            self.config,
+            self.compress.then_some(self.compression_level),
+        )?;
         Ok((chunk, self.digest))
     }
 
@@ -557,3 +612,48 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
         chunk_builder.build()
     }
 }
+
+#[test]
+fn test_encode_with_level() {
+    let crypt_config = CryptConfig::new([1u8; 32]).unwrap();
+
+    let data = b"compressible ".repeat(1000);
+    // deterministic, but does not compress
+    let mut noise = Vec::with_capacity(4096);
+    let mut state = 0x2545f4914f6cdd1du64;
+    while noise.len() < 4096 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        noise.extend_from_slice(&state.to_le_bytes());
+    }
+
+    for level in [1, 19] {
+        let blob = DataBlob::encode_with_level(&data, None, Some(level)).unwrap();
+        assert_eq!(blob.magic(), &COMPRESSED_BLOB_MAGIC_1_0);
+        assert!(blob.raw_size() < data.len() as u64);
+        assert_eq!(blob.decode(None, None).unwrap(), data);
+
+        let blob = DataBlob::encode_with_level(&data, Some(&crypt_config), Some(level)).unwrap();
+        assert_eq!(blob.magic(), &ENCR_COMPR_BLOB_MAGIC_1_0);
+        assert_eq!(blob.decode(Some(&crypt_config), None).unwrap(), data);
+
+        // only compressed if that makes the blob smaller
+        let blob = DataBlob::encode_with_level(&noise, None, Some(level)).unwrap();
+        assert_eq!(blob.magic(), &UNCOMPRESSED_BLOB_MAGIC_1_0);
+        assert_eq!(blob.decode(None, None).unwrap(), noise);
+    }
+
+    let blob = DataBlob::encode_with_level(&data, None, None).unwrap();
+    assert_eq!(blob.magic(), &UNCOMPRESSED_BLOB_MAGIC_1_0);
+    assert_eq!(blob.decode(None, None).unwrap(), data);
+
+    let blob = DataBlob::encode_with_level(&data, Some(&crypt_config), None).unwrap();
+    assert_eq!(blob.magic(), &ENCRYPTED_BLOB_MAGIC_1_0);
+    assert_eq!(blob.decode(Some(&crypt_config), None).unwrap(), data);
+
+    // the plain encoding uses the default level
+    let blob = DataBlob::encode(&data, None, true).unwrap();
+    let leveled = DataBlob::encode_with_level(&data, None, Some(DEFAULT_ZSTD_LEVEL)).unwrap();
+    assert_eq!(blob.raw_data(), leveled.raw_data());
+}
@@ -68,7 +68,9 @@ impl<R: Read> DataBlobReader<'_, R> {
                     },
                 })
             }
-            file_formats::ENCRYPTED_BLOB_MAGIC_1_0 => {
+            magic => {
+                let (cipher, compressed) = file_formats::encrypted_blob_info(&magic)
+                    .ok_or_else(|| format_err!("got wrong magic number {:?}", magic))?;
                 let config = config
                     .ok_or_else(|| format_err!("unable to read encrypted blob without key"))?;
                 let expected_crc = u32::from_le_bytes(head.crc);
@@ -81,39 +83,26 @@ impl<R: Read> DataBlobReader<'_, R> {
                     BufReader::with_capacity(64 * 1024, csum_reader),
                     iv,
                     expected_tag,
+                    cipher,
                     config,
                 )?;
-                Ok(Self {
-                    state: BlobReaderState::Encrypted {
-                        expected_crc,
-                        decrypt_reader,
-                    },
-                })
-            }
-            file_formats::ENCR_COMPR_BLOB_MAGIC_1_0 => {
-                let config = config
-                    .ok_or_else(|| format_err!("unable to read encrypted blob without key"))?;
-                let expected_crc = u32::from_le_bytes(head.crc);
-                let mut iv = [0u8; 16];
-                let mut expected_tag = [0u8; 16];
-                reader.read_exact(&mut iv)?;
-                reader.read_exact(&mut expected_tag)?;
-                let csum_reader = ChecksumReader::new(reader, None);
-                let decrypt_reader = CryptReader::new(
-                    BufReader::with_capacity(64 * 1024, csum_reader),
-                    iv,
-                    expected_tag,
-                    config,
-                )?;
-                let decompr = zstd::stream::read::Decoder::new(decrypt_reader)?;
-                Ok(Self {
-                    state: BlobReaderState::EncryptedCompressed {
-                        expected_crc,
-                        decompr,
-                    },
-                })
+                if compressed {
+                    let decompr = zstd::stream::read::Decoder::new(decrypt_reader)?;
+                    Ok(Self {
+                        state: BlobReaderState::EncryptedCompressed {
+                            expected_crc,
+                            decompr,
+                        },
+                    })
+                } else {
+                    Ok(Self {
+                        state: BlobReaderState::Encrypted {
+                            expected_crc,
+                            decrypt_reader,
+                        },
+                    })
+                }
             }
-            _ => bail!("got wrong magic number {:?}", head.magic),
         }
     }
 
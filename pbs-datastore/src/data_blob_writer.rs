@@ -67,7 +67,7 @@ impl<W: Write + Seek> DataBlobWriter<'_, W> {
         writer.seek(SeekFrom::Start(0))?;
         let head = EncryptedDataBlobHeader {
             head: DataBlobHeader {
-                magic: file_formats::ENCRYPTED_BLOB_MAGIC_1_0,
+                magic: file_formats::encrypted_blob_magic(config.crypt_cipher(), false),
                 crc: [0; 4],
             },
             iv: [0u8; 16],
@@ -91,7 +91,7 @@ impl<W: Write + Seek> DataBlobWriter<'_, W> {
         writer.seek(SeekFrom::Start(0))?;
         let head = EncryptedDataBlobHeader {
             head: DataBlobHeader {
-                magic: file_formats::ENCR_COMPR_BLOB_MAGIC_1_0,
+                magic: file_formats::encrypted_blob_magic(config.crypt_cipher(), true),
                 crc: [0; 4],
             },
             iv: [0u8; 16],
@@ -143,12 +143,13 @@ impl<W: Write + Seek> DataBlobWriter<'_, W> {
                 Ok(writer)
             }
             BlobWriterState::Encrypted { crypt_writer } => {
+                let magic = file_formats::encrypted_blob_magic(crypt_writer.cipher(), false);
                 let (csum_writer, iv, tag) = crypt_writer.finish()?;
                 let (mut writer, crc, _) = csum_writer.finish()?;
 
                 let head = EncryptedDataBlobHeader {
                     head: DataBlobHeader {
-                        magic: file_formats::ENCRYPTED_BLOB_MAGIC_1_0,
+                        magic,
                         crc: crc.to_le_bytes(),
                     },
                     iv,
@@ -162,12 +163,13 @@ impl<W: Write + Seek> DataBlobWriter<'_, W> {
             }
             BlobWriterState::EncryptedCompressed { compr } => {
                 let crypt_writer = compr.finish()?;
+                let magic = file_formats::encrypted_blob_magic(crypt_writer.cipher(), true);
                 let (csum_writer, iv, tag) = crypt_writer.finish()?;
                 let (mut writer, crc, _) = csum_writer.finish()?;
 
                 let head = EncryptedDataBlobHeader {
                     head: DataBlobHeader {
-                        magic: file_formats::ENCR_COMPR_BLOB_MAGIC_1_0,
+                        magic,
                         crc: crc.to_le_bytes(),
                     },
                     iv,
@@ -1,13 +1,17 @@
 use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Error};
 use lazy_static::lazy_static;
 use nix::unistd::{unlinkat, UnlinkatFlags};
+use serde::{Deserialize, Serialize};
 
 use proxmox_schema::ApiType;
 
@@ -16,22 +20,130 @@ use proxmox_sys::fs::{lock_dir_noblock, DirLockGuard};
 use proxmox_sys::process_locker::ProcessLockSharedGuard;
 use proxmox_sys::WorkerTaskContext;
 use proxmox_sys::{task_log, task_warn};
+use proxmox_time::TimeSpan;
 
 use pbs_api_types::{
-    Authid, BackupNamespace, BackupType, ChunkOrder, DataStoreConfig, DatastoreFSyncLevel,
-    DatastoreTuning, GarbageCollectionStatus, HumanByte, Operation, UPID,
+    print_ns_and_snapshot, Authid, BackupNamespace, BackupType, ChunkCompressionMode, ChunkOrder,
+    DataStoreConfig, DatastoreBackendType, DatastoreFSyncLevel, DatastoreTuning,
+    GarbageCollectionPhase, GarbageCollectionProgress, GarbageCollectionStatus, HumanByte,
+    Operation, S3BackendConfig, TrashListItem, DEFAULT_GC_THREADS, DEFAULT_VERIFY_READ_THREADS,
+    DEFAULT_VERIFY_THREADS, DEFAULT_ZSTD_LEVEL, UPID,
 };
 
 use crate::backup_info::{BackupDir, BackupGroup};
-use crate::chunk_store::ChunkStore;
+use crate::chunk_backend::ChunkBackend;
+use crate::chunk_bitmap::ChunkBitmap;
+use crate::chunk_cache::ChunkCache;
+use crate::chunk_store::{
+    ChunkCheckStatus, ChunkRecompressStatus, ChunkStore, CORRUPT_DIR_NAME, INSERTED_BYTES_FILE_NAME,
+};
 use crate::dynamic_index::{DynamicIndexReader, DynamicIndexWriter};
 use crate::fixed_index::{FixedIndexReader, FixedIndexWriter};
 use crate::hierarchy::{ListGroups, ListGroupsType, ListNamespaces, ListNamespacesRecursive};
 use crate::index::IndexFile;
-use crate::manifest::{archive_type, ArchiveType};
+use crate::manifest::{archive_type, ArchiveType, MANIFEST_BLOB_NAME};
+use crate::s3::S3ChunkBackend;
+use crate::snapshot_reader::SnapshotReader;
 use crate::task_tracking::update_active_operations;
 use crate::DataBlob;
 
+/// Directory below the datastore base holding removed snapshots until they get purged
+const TRASH_DIR_NAME: &str = ".trash";
+
+/// File below the datastore base recording the index files seen by the last incremental garbage
+/// collection
+const GC_INDEX_STATE_FILE_NAME: &str = ".gc-index-state";
+
+// identifies the version of an index file, to detect changes between garbage collection runs
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexFileState {
+    inode: u64,
+    // in nanoseconds
+    mtime: i64,
+    size: u64,
+}
+
+impl IndexFileState {
+    fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            inode: metadata.ino(),
+            mtime: metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec(),
+            size: metadata.size(),
+        }
+    }
+}
+
+// index files seen by incremental garbage collection, by their path relative to the datastore
+type GcIndexState = HashMap<String, IndexFileState>;
+
+// progress of a garbage collection phase, shared with the status API
+struct GcPhaseProgress<'a> {
+    progress: &'a Mutex<Option<GarbageCollectionProgress>>,
+    start: Instant,
+}
+
+impl<'a> GcPhaseProgress<'a> {
+    fn start(
+        progress: &'a Mutex<Option<GarbageCollectionProgress>>,
+        phase: GarbageCollectionPhase,
+    ) -> Self {
+        *progress.lock().unwrap() = Some(GarbageCollectionProgress {
+            phase,
+            percentage: 0,
+            processed: 0,
+            eta: None,
+        });
+        Self {
+            progress,
+            start: Instant::now(),
+        }
+    }
+
+    // record the progress, and log it together with the estimated remaining time of the phase
+    // whenever the percentage increases
+    fn update(
+        &self,
+        percentage: usize,
+        processed: usize,
+        worker: &dyn WorkerTaskContext,
+        describe: impl FnOnce() -> String,
+    ) {
+        let mut progress = self.progress.lock().unwrap();
+        let progress = match progress.as_mut() {
+            Some(progress) => progress,
+            None => return,
+        };
+
+        progress.processed = processed as u64;
+        let percentage = percentage.min(100) as u8;
+        if percentage <= progress.percentage {
+            return;
+        }
+        progress.percentage = percentage;
+
+        if percentage < 100 {
+            let elapsed = self.start.elapsed().as_secs();
+            let eta = elapsed * (100 - percentage as u64) / percentage as u64;
+            progress.eta = Some(eta);
+            let remaining = TimeSpan::from(Duration::from_secs(eta));
+            task_log!(worker, "{}, about {} remaining", describe(), remaining);
+        } else {
+            progress.eta = Some(0);
+            task_log!(worker, "{}", describe());
+        }
+    }
+}
+
+// per thread result of the mark phase of garbage collection
+#[derive(Default)]
+struct GcMarkResult {
+    status: GarbageCollectionStatus,
+    index_state: GcIndexState,
+    unchanged_count: usize,
+}
+/// File inside a removed snapshot recording the time it was moved into the trash
+const TRASH_REMOVED_FILE_NAME: &str = ".removed";
+
 lazy_static! {
     static ref DATASTORE_MAP: Mutex<HashMap<String, Arc<DataStoreImpl>>> =
         Mutex::new(HashMap::new());
@@ -54,26 +166,51 @@ pub fn check_backup_owner(owner: &Authid, auth_id: &Authid) -> Result<(), Error>
 /// management interface for backup.
 pub struct DataStoreImpl {
     chunk_store: Arc<ChunkStore>,
+    backend: Arc<dyn ChunkBackend>,
+    backend_type: DatastoreBackendType,
+    s3_config: S3BackendConfig,
     gc_mutex: Mutex<()>,
     last_gc_status: Mutex<GarbageCollectionStatus>,
+    gc_progress: Mutex<Option<GarbageCollectionProgress>>,
     verify_new: bool,
     chunk_order: ChunkOrder,
+    gc_threads: usize,
+    gc_incremental: bool,
+    compression_level: Option<i32>,
+    chunk_cache: Option<Arc<Mutex<ChunkCache>>>,
+    verify_threads: usize,
+    verify_read_threads: usize,
     last_digest: Option<[u8; 32]>,
     sync_level: DatastoreFSyncLevel,
+    trash_retention: Option<u64>,
+    quota: Option<u64>,
 }
 
 impl DataStoreImpl {
     // This one just panics on everything
     #[doc(hidden)]
     pub(crate) unsafe fn new_test() -> Arc<Self> {
+        let chunk_store = Arc::new(unsafe { ChunkStore::panic_store() });
         Arc::new(Self {
-            chunk_store: Arc::new(unsafe { ChunkStore::panic_store() }),
+            backend: chunk_store.clone(),
+            chunk_store,
+            backend_type: DatastoreBackendType::Filesystem,
+            s3_config: Default::default(),
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(GarbageCollectionStatus::default()),
+            gc_progress: Mutex::new(None),
             verify_new: false,
             chunk_order: ChunkOrder::None,
+            gc_threads: DEFAULT_GC_THREADS,
+            gc_incremental: false,
+            compression_level: Some(DEFAULT_ZSTD_LEVEL),
+            chunk_cache: None,
+            verify_threads: DEFAULT_VERIFY_THREADS,
+            verify_read_threads: DEFAULT_VERIFY_READ_THREADS,
             last_digest: None,
             sync_level: Default::default(),
+            trash_retention: None,
+            quota: None,
         })
     }
 }
@@ -127,7 +264,21 @@ impl DataStore {
         // we could use the ConfigVersionCache's generation for staleness detection, but  we load
         // the config anyway -> just use digest, additional benefit: manual changes get detected
         let (config, digest) = pbs_config::datastore::config()?;
-        let config: DataStoreConfig = config.lookup("datastore", name)?;
+        let mut config: DataStoreConfig = config.lookup("datastore", name)?;
+
+        // the S3 secret key is stored separately, a changed one needs a new client too
+        let digest = match config.backend.unwrap_or_default() {
+            DatastoreBackendType::Filesystem => digest,
+            DatastoreBackendType::S3 => {
+                if config.s3.s3_secret_key.is_none() {
+                    config.s3.s3_secret_key = pbs_config::s3_shadow::get_secret_key(name)?;
+                }
+                let mut hasher = openssl::sha::Sha256::new();
+                hasher.update(&digest);
+                hasher.update(config.s3.s3_secret_key.as_deref().unwrap_or("").as_bytes());
+                hasher.finish()
+            }
+        };
 
         if let Some(maintenance_mode) = config.get_maintenance_mode() {
             if let Err(error) = maintenance_mode.check(operation) {
@@ -143,6 +294,7 @@ impl DataStore {
         let entry = datastore_cache.get(name);
 
         // reuse chunk store so that we keep using the same process locker instance!
+        let mut backend = None;
         let chunk_store = if let Some(datastore) = &entry {
             let last_digest = datastore.last_digest.as_ref();
             if let Some(true) = last_digest.map(|last_digest| last_digest == &digest) {
@@ -151,6 +303,12 @@ impl DataStore {
                     operation,
                 }));
             }
+            // also keep the chunks marked as in use, if the backend did not change
+            if datastore.backend_type == config.backend.unwrap_or_default()
+                && datastore.s3_config == config.s3
+            {
+                backend = Some(Arc::clone(&datastore.backend));
+            }
             Arc::clone(&datastore.chunk_store)
         } else {
             let tuning: DatastoreTuning = serde_json::from_value(
@@ -164,7 +322,8 @@ impl DataStore {
             )?)
         };
 
-        let datastore = DataStore::with_store_and_config(chunk_store, config, Some(digest))?;
+        let datastore =
+            DataStore::with_store_and_config(chunk_store, backend, config, Some(digest))?;
 
         let datastore = Arc::new(datastore);
         datastore_cache.insert(name.to_string(), datastore.clone());
@@ -185,6 +344,83 @@ impl DataStore {
         Ok(())
     }
 
+    /// Destroy all data of a datastore which is not configured anymore.
+    ///
+    /// Removes the chunk store, all namespaces, backup groups and snapshots (including the ones in
+    /// the trash), quarantined chunks and finally the datastore directory itself, if nothing else is left in it.
+    ///
+    /// Only handles the local directory, so the data of datastores on an object storage would stay
+    /// in their bucket. Callers have to refuse them.
+    pub fn destroy_data(path: &Path, worker: &dyn WorkerTaskContext) -> Result<(), Error> {
+        let chunk_dir = path.join(".chunks");
+        if !chunk_dir.is_dir() {
+            bail!("no chunk store found at {path:?}, refusing to remove data");
+        }
+
+        task_log!(worker, "removing backup snapshots");
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if name == "ns"
+                || name == TRASH_DIR_NAME
+                || name == CORRUPT_DIR_NAME
+                || name.parse::<BackupType>().is_ok()
+            {
+                let entry_path = entry.path();
+                std::fs::remove_dir_all(&entry_path)
+                    .map_err(|err| format_err!("removing {entry_path:?} failed - {err}"))?;
+            }
+        }
+
+        task_log!(worker, "removing chunks");
+        let mut last_percentage = 0;
+        for i in 0..0x10000 {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let subdir = chunk_dir.join(format!("{:04x}", i));
+            match std::fs::remove_dir_all(&subdir) {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => bail!("removing chunk directory {subdir:?} failed - {err}"),
+            }
+
+            let percentage = (i + 1) * 100 / 0x10000;
+            if percentage != last_percentage {
+                task_log!(worker, "removed {}% of the chunk directories", percentage);
+                last_percentage = percentage;
+            }
+        }
+        std::fs::remove_dir_all(&chunk_dir)
+            .map_err(|err| format_err!("removing chunk store {chunk_dir:?} failed - {err}"))?;
+
+        for name in [
+            ".gc-status",
+            ".lock",
+            INSERTED_BYTES_FILE_NAME,
+            GC_INDEX_STATE_FILE_NAME,
+        ]
+        .iter()
+        {
+            match std::fs::remove_file(path.join(name)) {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => bail!("removing {name} failed - {err}"),
+            }
+        }
+
+        // may still contain unrelated files, e.g. a lost+found directory of the file system
+        if let Err(err) = std::fs::remove_dir(path) {
+            task_warn!(worker, "not removing datastore directory {path:?} - {err}");
+        }
+
+        Ok(())
+    }
+
     /// Open a raw database given a name and a path.
     ///
     /// # Safety
@@ -225,6 +461,7 @@ impl DataStore {
             ChunkStore::open(&name, &config.path, tuning.sync_level.unwrap_or_default())?;
         let inner = Arc::new(Self::with_store_and_config(
             Arc::new(chunk_store),
+            None,
             config,
             None,
         )?);
@@ -238,6 +475,7 @@ impl DataStore {
 
     fn with_store_and_config(
         chunk_store: Arc<ChunkStore>,
+        backend: Option<Arc<dyn ChunkBackend>>,
         config: DataStoreConfig,
         last_digest: Option<[u8; 32]>,
     ) -> Result<DataStoreImpl, Error> {
@@ -260,16 +498,53 @@ impl DataStore {
             DatastoreTuning::API_SCHEMA
                 .parse_property_string(config.tuning.as_deref().unwrap_or(""))?,
         )?;
-        let chunk_order = tuning.chunk_order.unwrap_or(ChunkOrder::Inode);
+        let backend_type = config.backend.unwrap_or_default();
+
+        let backend: Arc<dyn ChunkBackend> = match (backend, backend_type) {
+            (Some(backend), _) => backend,
+            (None, DatastoreBackendType::Filesystem) => chunk_store.clone(),
+            (None, DatastoreBackendType::S3) => {
+                Arc::new(S3ChunkBackend::new(chunk_store.clone(), &config.s3)?)
+            }
+        };
+
+        // objects have no inode, and their order does not matter for the object storage anyway
+        let chunk_order = match backend_type {
+            DatastoreBackendType::Filesystem => tuning.chunk_order.unwrap_or(ChunkOrder::Inode),
+            DatastoreBackendType::S3 => ChunkOrder::None,
+        };
 
         Ok(DataStoreImpl {
             chunk_store,
+            backend,
+            backend_type,
+            s3_config: config.s3,
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(gc_status),
+            gc_progress: Mutex::new(None),
             verify_new: config.verify_new.unwrap_or(false),
             chunk_order,
+            gc_threads: tuning.gc_threads.unwrap_or(DEFAULT_GC_THREADS),
+            // marking chunks on the S3 backend does not cause any IO anyway
+            gc_incremental: backend_type == DatastoreBackendType::Filesystem
+                && tuning.gc_incremental.unwrap_or(false),
+            compression_level: match tuning.compression.unwrap_or_default() {
+                ChunkCompressionMode::None => None,
+                ChunkCompressionMode::Zstd => Some(tuning.zstd_level.unwrap_or(DEFAULT_ZSTD_LEVEL)),
+            },
+            chunk_cache: tuning
+                .chunk_cache_size
+                .map(|size| size.as_u64() as usize)
+                .filter(|size| *size > 0)
+                .map(|size| Arc::new(Mutex::new(ChunkCache::new(size)))),
+            verify_threads: tuning.verify_threads.unwrap_or(DEFAULT_VERIFY_THREADS),
+            verify_read_threads: tuning
+                .verify_read_threads
+                .unwrap_or(DEFAULT_VERIFY_READ_THREADS),
             last_digest,
             sync_level: tuning.sync_level.unwrap_or_default(),
+            trash_retention: config.trash_retention,
+            quota: config.quota.map(|quota| quota.as_u64()),
         })
     }
 
@@ -354,9 +629,9 @@ impl DataStore {
                 continue;
             }
 
-            self.stat_chunk(&info.digest).map_err(|err| {
+            self.inner.backend.chunk_size(&info.digest).map_err(|err| {
                 format_err!(
-                    "fast_index_verification error, stat_chunk {} failed - {}",
+                    "fast_index_verification error, checking chunk {} failed - {}",
                     hex::encode(&info.digest),
                     err,
                 )
@@ -561,6 +836,383 @@ impl DataStore {
         backup_dir.destroy(force)
     }
 
+    /// Returns the path of the trash, which holds removed snapshots until they get purged.
+    pub fn trash_path(&self) -> PathBuf {
+        let mut path = self.base_path();
+        path.push(TRASH_DIR_NAME);
+        path
+    }
+
+    /// Returns the number of days removed snapshots are kept in the trash.
+    ///
+    /// If `None`, the trash is disabled and snapshots are removed immediately.
+    pub fn trash_retention(&self) -> Option<u64> {
+        self.inner.trash_retention
+    }
+
+    /// Returns the cache of decoded chunks shared by the chunk readers of this datastore, if one
+    /// is configured.
+    pub fn chunk_cache(&self) -> Option<Arc<Mutex<ChunkCache>>> {
+        self.inner.chunk_cache.clone()
+    }
+
+    /// Returns the number of threads decoding and hashing chunks during verification.
+    pub fn verify_threads(&self) -> usize {
+        self.inner.verify_threads
+    }
+
+    /// Returns the number of threads loading chunks concurrently during verification.
+    pub fn verify_read_threads(&self) -> usize {
+        self.inner.verify_read_threads
+    }
+
+    /// Returns the zstd level clients should compress chunks with.
+    ///
+    /// If `None`, chunks should not be compressed.
+    pub fn chunk_compression_level(&self) -> Option<i32> {
+        self.inner.compression_level
+    }
+
+    /// Returns the quota of the datastore in bytes, if one is configured.
+    pub fn quota(&self) -> Option<u64> {
+        self.inner.quota
+    }
+
+    /// Returns the space counted against the quota.
+    ///
+    /// This is the on-disk usage determined by the last garbage collection, plus the chunks
+    /// inserted since then. Chunks removed since then are only accounted by the next garbage
+    /// collection.
+    pub fn quota_used(&self) -> u64 {
+        let disk_bytes = self.inner.last_gc_status.lock().unwrap().disk_bytes;
+        disk_bytes + self.inner.chunk_store.inserted_bytes()
+    }
+
+    /// Fails if the datastore exceeds its quota, including the chunks inserted by other
+    /// processes so far.
+    pub fn check_quota(&self) -> Result<(), Error> {
+        if self.inner.quota.is_some() {
+            if let Err(err) = self.inner.chunk_store.refresh_inserted_bytes() {
+                log::warn!("{err}");
+            }
+        }
+        self.check_counted_quota()
+    }
+
+    // like check_quota, but only with the chunks of other processes counted so far
+    fn check_counted_quota(&self) -> Result<(), Error> {
+        if let Some(quota) = self.inner.quota {
+            let used = self.quota_used();
+            if used >= quota {
+                bail!(
+                    "datastore '{}' exceeds its quota ({} of {} used)",
+                    self.name(),
+                    HumanByte::from(used),
+                    HumanByte::from(quota),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // chunks which are already stored do not need additional space
+    fn check_chunk_quota(&self, digest: &[u8; 32]) -> Result<(), Error> {
+        if let Err(err) = self.check_counted_quota() {
+            if !self.cond_touch_chunk(digest, false)? {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn trash_namespace_path(&self, ns: &BackupNamespace) -> PathBuf {
+        let mut path = self.trash_path();
+        for part in ns.components() {
+            path.push("ns");
+            path.push(part);
+        }
+        path
+    }
+
+    fn trash_group_path(
+        &self,
+        ns: &BackupNamespace,
+        backup_group: &pbs_api_types::BackupGroup,
+    ) -> PathBuf {
+        let mut path = self.trash_namespace_path(ns);
+        path.push(backup_group.to_string());
+        path
+    }
+
+    fn trash_snapshot_path(
+        &self,
+        ns: &BackupNamespace,
+        backup_dir: &pbs_api_types::BackupDir,
+    ) -> PathBuf {
+        let mut path = self.trash_namespace_path(ns);
+        path.push(backup_dir.to_string());
+        path
+    }
+
+    /// Move a snapshot into the trash, the caller needs to hold the snapshot locks.
+    pub(crate) fn move_to_trash(&self, snapshot: &BackupDir) -> Result<(), Error> {
+        let ns = snapshot.backup_ns();
+        let full_path = snapshot.full_path();
+
+        let group_path = self.trash_group_path(ns, snapshot.group());
+        std::fs::create_dir_all(&group_path)?;
+
+        // keep the owner around, the group may be gone once the snapshot gets restored
+        if let Ok(owner) = self.get_owner(ns, snapshot.group()) {
+            replace_file(
+                group_path.join("owner"),
+                format!("{owner}\n").as_bytes(),
+                CreateOptions::new(),
+                false,
+            )?;
+        }
+
+        let trash_path = self.trash_snapshot_path(ns, snapshot.dir());
+        if trash_path.exists() {
+            // an older copy of the same snapshot, superseded by the one removed now
+            std::fs::remove_dir_all(&trash_path).map_err(|err| {
+                format_err!("removing old trash entry {trash_path:?} failed - {err}")
+            })?;
+        }
+
+        let removed_path = full_path.join(TRASH_REMOVED_FILE_NAME);
+        let now = proxmox_time::epoch_i64();
+        replace_file(
+            &removed_path,
+            now.to_string().as_bytes(),
+            CreateOptions::new(),
+            false,
+        )?;
+
+        if let Err(err) = std::fs::rename(&full_path, &trash_path) {
+            let _ = std::fs::remove_file(&removed_path);
+            bail!("moving backup snapshot {full_path:?} to trash failed - {err}");
+        }
+
+        Ok(())
+    }
+
+    /// List all removed snapshots in the trash.
+    pub fn list_trash(&self) -> Result<Vec<TrashListItem>, Error> {
+        let mut list = Vec::new();
+        self.list_trash_ns(BackupNamespace::root(), self.trash_path(), &mut list)?;
+        Ok(list)
+    }
+
+    fn list_trash_ns(
+        &self,
+        ns: BackupNamespace,
+        path: PathBuf,
+        list: &mut Vec<TrashListItem>,
+    ) -> Result<(), Error> {
+        let entries = match std::fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => bail!("unable to read trash directory {path:?} - {err}"),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+
+            if name == "ns" {
+                for child in std::fs::read_dir(entry.path())? {
+                    let child = child?;
+                    if let Ok(child_name) = child.file_name().into_string() {
+                        let mut child_ns = ns.clone();
+                        if let Err(err) = child_ns.push(child_name) {
+                            log::warn!("skipping trash directory {:?} - {err}", child.path());
+                            continue;
+                        }
+                        self.list_trash_ns(child_ns, child.path(), list)?;
+                    }
+                }
+            } else if let Ok(ty) = name.parse::<BackupType>() {
+                for group in std::fs::read_dir(entry.path())? {
+                    let group = group?;
+                    let id = match group.file_name().into_string() {
+                        Ok(id) => id,
+                        Err(_) => continue,
+                    };
+                    let group_path = group.path();
+                    let owner = proxmox_sys::fs::file_read_firstline(group_path.join("owner"))
+                        .ok()
+                        .and_then(|owner| owner.trim_end().parse().ok());
+
+                    for snapshot in std::fs::read_dir(&group_path)? {
+                        let snapshot = snapshot?;
+                        if !snapshot.file_type()?.is_dir() {
+                            continue;
+                        }
+                        let time = match snapshot
+                            .file_name()
+                            .to_str()
+                            .and_then(|time| proxmox_time::parse_rfc3339(time).ok())
+                        {
+                            Some(time) => time,
+                            None => continue,
+                        };
+                        let removed = proxmox_sys::fs::file_read_firstline(
+                            snapshot.path().join(TRASH_REMOVED_FILE_NAME),
+                        )
+                        .ok()
+                        .and_then(|removed| removed.trim_end().parse().ok())
+                        .unwrap_or(0);
+
+                        list.push(TrashListItem {
+                            ns: ns.clone(),
+                            backup: (ty, id.clone(), time).into(),
+                            removed,
+                            owner: owner.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restore a removed snapshot from the trash.
+    ///
+    /// The backup group gets re-created with its original owner if it was removed as well.
+    pub fn restore_from_trash(
+        &self,
+        ns: &BackupNamespace,
+        backup_dir: &pbs_api_types::BackupDir,
+    ) -> Result<(), Error> {
+        let trash_path = self.trash_snapshot_path(ns, backup_dir);
+        if !trash_path.exists() {
+            bail!(
+                "snapshot {} not found in trash",
+                print_ns_and_snapshot(ns, backup_dir)
+            );
+        }
+        if !self.namespace_exists(ns) {
+            bail!("cannot restore snapshot, namespace {ns} does not exist anymore");
+        }
+
+        let owner_path = self.trash_group_path(ns, &backup_dir.group).join("owner");
+        let owner: Authid = proxmox_sys::fs::file_read_firstline(&owner_path)
+            .map_err(|err| format_err!("unable to read owner of removed snapshot - {err}"))?
+            .trim_end()
+            .parse()?;
+
+        let (_owner, _group_guard) =
+            self.create_locked_backup_group(ns, &backup_dir.group, &owner)?;
+
+        let full_path = self.snapshot_path(ns, backup_dir);
+        if full_path.exists() {
+            bail!(
+                "cannot restore snapshot, {} already exists",
+                print_ns_and_snapshot(ns, backup_dir)
+            );
+        }
+
+        std::fs::rename(&trash_path, &full_path).map_err(|err| {
+            format_err!("restoring snapshot {full_path:?} from trash failed - {err}")
+        })?;
+        let _ = std::fs::remove_file(full_path.join(TRASH_REMOVED_FILE_NAME));
+
+        self.store_snapshot_in_backend(ns, backup_dir)?;
+
+        self.cleanup_trash_group(ns, &backup_dir.group);
+
+        Ok(())
+    }
+
+    /// Permanently remove a snapshot from the trash.
+    pub fn purge_from_trash(
+        &self,
+        ns: &BackupNamespace,
+        backup_dir: &pbs_api_types::BackupDir,
+    ) -> Result<(), Error> {
+        let trash_path = self.trash_snapshot_path(ns, backup_dir);
+        if !trash_path.exists() {
+            bail!(
+                "snapshot {} not found in trash",
+                print_ns_and_snapshot(ns, backup_dir)
+            );
+        }
+
+        log::info!("purging backup snapshot {:?} from trash", trash_path);
+        std::fs::remove_dir_all(&trash_path)
+            .map_err(|err| format_err!("purging backup snapshot {trash_path:?} failed - {err}"))?;
+
+        self.cleanup_trash_group(ns, &backup_dir.group);
+
+        Ok(())
+    }
+
+    /// Purge all snapshots which are in the trash for longer than the configured retention.
+    ///
+    /// If the trash is disabled, all remaining snapshots in the trash get purged. Snapshots which
+    /// cannot be purged are logged and skipped.
+    pub fn purge_expired_trash(&self, worker: &dyn WorkerTaskContext) -> Result<(), Error> {
+        let limit = match self.inner.trash_retention {
+            Some(days) => proxmox_time::epoch_i64() - (days as i64) * 24 * 3600,
+            None => i64::MAX,
+        };
+
+        for item in self.list_trash()? {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            if item.removed > limit {
+                continue;
+            }
+            let snapshot = print_ns_and_snapshot(&item.ns, &item.backup);
+            task_log!(worker, "purging snapshot {snapshot} from trash");
+            if let Err(err) = self.purge_from_trash(&item.ns, &item.backup) {
+                task_warn!(
+                    worker,
+                    "purging snapshot {snapshot} from trash failed - {err}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // remove the trash directories of a group (and its parents) once its last snapshot is gone
+    fn cleanup_trash_group(&self, ns: &BackupNamespace, backup_group: &pbs_api_types::BackupGroup) {
+        let group_path = self.trash_group_path(ns, backup_group);
+
+        let has_snapshots = match std::fs::read_dir(&group_path) {
+            Ok(mut entries) => entries.any(|entry| {
+                entry
+                    .and_then(|entry| entry.file_type())
+                    .map(|file_type| file_type.is_dir())
+                    .unwrap_or(true)
+            }),
+            Err(_) => return,
+        };
+        if has_snapshots || std::fs::remove_dir_all(&group_path).is_err() {
+            return;
+        }
+
+        // remove empty parents, stops at the first non-empty one
+        let trash_path = self.trash_path();
+        let mut path = group_path.as_path();
+        while let Some(parent) = path.parent() {
+            if parent == trash_path || std::fs::remove_dir(parent).is_err() {
+                break;
+            }
+            path = parent;
+        }
+    }
+
     /// Returns the time of the last successful backup
     ///
     /// Or None if there is no backup in the group (or the group dir does not exist).
@@ -639,6 +1291,31 @@ impl DataStore {
         Ok(())
     }
 
+    /// Change the owner of an existing backup group.
+    ///
+    /// The group gets locked, so that its owner does not change during a running backup, and the
+    /// owner file is replaced atomically.
+    pub fn change_owner(
+        &self,
+        ns: &BackupNamespace,
+        backup_group: &pbs_api_types::BackupGroup,
+        new_owner: &Authid,
+    ) -> Result<(), Error> {
+        let full_path = self.group_path(ns, backup_group);
+        let _guard = lock_dir_noblock(&full_path, "backup group", "possibly running backup")?;
+
+        let path = self.owner_path(ns, backup_group);
+        replace_file(
+            &path,
+            format!("{new_owner}\n").as_bytes(),
+            CreateOptions::new(),
+            false,
+        )
+        .map_err(|err| format_err!("unable to replace owner file {:?} - {}", path, err))?;
+
+        self.store_owner_in_backend(ns, backup_group)
+    }
+
     /// Create (if it does not already exists) and lock a backup group
     ///
     /// And set the owner to 'userid'. If the group already exists, it returns the
@@ -849,8 +1526,6 @@ impl DataStore {
 
         use walkdir::WalkDir;
 
-        let walker = WalkDir::new(&base).into_iter();
-
         // make sure we skip .chunks (and other hidden files to keep it simple)
         fn is_hidden(entry: &walkdir::DirEntry) -> bool {
             entry
@@ -881,19 +1556,30 @@ impl DataStore {
             }
             Ok(())
         };
-        for entry in walker.filter_entry(|e| !is_hidden(e)) {
-            let path = match entry {
-                Ok(entry) => entry.into_path(),
-                Err(err) => {
-                    handle_entry_err(err)?;
-                    continue;
-                }
-            };
-            if let Ok(archive_type) = archive_type(&path) {
-                if archive_type == ArchiveType::FixedIndex
-                    || archive_type == ArchiveType::DynamicIndex
-                {
-                    list.push(path);
+
+        let mut roots = vec![base];
+        // removed snapshots keep their chunks alive until they get purged from the trash
+        let trash_path = self.trash_path();
+        if trash_path.exists() {
+            roots.push(trash_path);
+        }
+
+        for root in roots {
+            let walker = WalkDir::new(&root).into_iter();
+            for entry in walker.filter_entry(|e| e.depth() == 0 || !is_hidden(e)) {
+                let path = match entry {
+                    Ok(entry) => entry.into_path(),
+                    Err(err) => {
+                        handle_entry_err(err)?;
+                        continue;
+                    }
+                };
+                if let Ok(archive_type) = archive_type(&path) {
+                    if archive_type == ArchiveType::FixedIndex
+                        || archive_type == ArchiveType::DynamicIndex
+                    {
+                        list.push(path);
+                    }
                 }
             }
         }
@@ -907,6 +1593,8 @@ impl DataStore {
         index: I,
         file_name: &Path, // only used for error reporting
         status: &mut GarbageCollectionStatus,
+        marks: &ChunkBitmap,
+        unchanged: bool,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
         status.index_file_count += 1;
@@ -916,7 +1604,13 @@ impl DataStore {
             worker.check_abort()?;
             worker.fail_on_shutdown()?;
             let digest = index.index_digest(pos).unwrap();
-            if !self.inner.chunk_store.cond_touch_chunk(digest, false)? {
+            if unchanged {
+                // kept by the sweep phase without touching them, together with their quarantined
+                // copies while they are missing
+                marks.insert(digest);
+                continue;
+            }
+            if !self.inner.backend.mark_used_chunk(digest, marks)? {
                 let hex = hex::encode(digest);
                 task_warn!(
                     worker,
@@ -926,76 +1620,194 @@ impl DataStore {
                 // touch any corresponding .bad files to keep them around, meaning if a chunk is
                 // rewritten correctly they will be removed automatically, as well as if no index
                 // file requires the chunk anymore (won't get to this loop then)
-                for i in 0..=9 {
-                    let bad_ext = format!("{}.bad", i);
-                    let mut bad_path = PathBuf::new();
-                    bad_path.push(self.chunk_path(digest).0);
-                    bad_path.set_extension(bad_ext);
-                    self.inner.chunk_store.cond_touch_path(&bad_path, false)?;
-                }
+                self.inner.chunk_store.touch_quarantined_chunk(digest)?;
             }
         }
         Ok(())
     }
 
-    fn mark_used_chunks(
+    // open an index file, or its new location if its snapshot got moved into or out of the trash
+    // since the index files were listed
+    fn open_index_file(&self, path: &Path) -> io::Result<std::fs::File> {
+        match std::fs::File::open(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let base = self.base_path();
+                let trash = self.trash_path();
+                let moved = match path.strip_prefix(&trash) {
+                    Ok(relative) => base.join(relative),
+                    Err(_) => match path.strip_prefix(&base) {
+                        Ok(relative) => trash.join(relative),
+                        Err(_) => return Err(err),
+                    },
+                };
+                std::fs::File::open(moved)
+            }
+            result => result,
+        }
+    }
+
+    // mark the chunks used by the index file `img`, returns whether it is outside of the
+    // expected directory scheme
+    //
+    // with `last_index_state`, the chunks of index files which did not change since the last
+    // garbage collection are only set in `marks` instead of touched - they were kept by every run
+    // since, so they still exist
+    fn mark_index_file_chunks(
         &self,
-        status: &mut GarbageCollectionStatus,
+        img: &Path,
+        trash_path: &Path,
+        last_index_state: Option<&GcIndexState>,
+        marks: &ChunkBitmap,
+        result: &mut GcMarkResult,
         worker: &dyn WorkerTaskContext,
-    ) -> Result<(), Error> {
-        let image_list = self.list_images()?;
-        let image_count = image_list.len();
-
-        let mut last_percentage: usize = 0;
-
-        let mut strange_paths_count: u64 = 0;
-
-        for (i, img) in image_list.into_iter().enumerate() {
-            worker.check_abort()?;
-            worker.fail_on_shutdown()?;
-
-            if let Some(backup_dir_path) = img.parent() {
-                let backup_dir_path = backup_dir_path.strip_prefix(self.base_path())?;
-                if let Some(backup_dir_str) = backup_dir_path.to_str() {
-                    if pbs_api_types::BackupDir::from_str(backup_dir_str).is_err() {
-                        strange_paths_count += 1;
-                    }
+    ) -> Result<bool, Error> {
+        let mut strange_path = false;
+
+        if let Some(backup_dir_path) = img.parent() {
+            let backup_dir_path = backup_dir_path
+                .strip_prefix(trash_path)
+                .or_else(|_| backup_dir_path.strip_prefix(self.base_path()))?;
+            if let Some(backup_dir_str) = backup_dir_path.to_str() {
+                if pbs_api_types::BackupDir::from_str(backup_dir_str).is_err() {
+                    strange_path = true;
                 }
             }
+        }
 
-            match std::fs::File::open(&img) {
-                Ok(file) => {
-                    if let Ok(archive_type) = archive_type(&img) {
-                        if archive_type == ArchiveType::FixedIndex {
-                            let index = FixedIndexReader::new(file).map_err(|e| {
-                                format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
-                            })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
-                        } else if archive_type == ArchiveType::DynamicIndex {
-                            let index = DynamicIndexReader::new(file).map_err(|e| {
-                                format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
-                            })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
-                        }
+        match self.open_index_file(img) {
+            Ok(file) => {
+                let mut unchanged = false;
+                if let Some(last_index_state) = last_index_state {
+                    let rel_path = img.strip_prefix(self.base_path())?.to_string_lossy();
+                    let state = IndexFileState::from_metadata(&file.metadata()?);
+                    unchanged = last_index_state.get(rel_path.as_ref()) == Some(&state);
+                    result.index_state.insert(rel_path.into_owned(), state);
+                }
+                if unchanged {
+                    result.unchanged_count += 1;
+                }
+
+                let status = &mut result.status;
+                if let Ok(archive_type) = archive_type(img) {
+                    if archive_type == ArchiveType::FixedIndex {
+                        let index = FixedIndexReader::new(file).map_err(|e| {
+                            format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
+                        })?;
+                        self.index_mark_used_chunks(index, img, status, marks, unchanged, worker)?;
+                    } else if archive_type == ArchiveType::DynamicIndex {
+                        let index = DynamicIndexReader::new(file).map_err(|e| {
+                            format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
+                        })?;
+                        self.index_mark_used_chunks(index, img, status, marks, unchanged, worker)?;
                     }
                 }
-                Err(err) if err.kind() == io::ErrorKind::NotFound => (), // ignore vanished files
-                Err(err) => bail!("can't open index {} - {}", img.to_string_lossy(), err),
             }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (), // ignore vanished files
+            Err(err) => bail!("can't open index {} - {}", img.to_string_lossy(), err),
+        }
+
+        Ok(strange_path)
+    }
+
+    // returns the state of the index files for incremental garbage collection
+    fn mark_used_chunks(
+        &self,
+        status: &mut GarbageCollectionStatus,
+        last_index_state: Option<&GcIndexState>,
+        marks: &ChunkBitmap,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<GcIndexState, Error> {
+        let image_list = self.list_images()?;
+        let image_count = image_list.len();
 
-            let percentage = (i + 1) * 100 / image_count;
-            if percentage > last_percentage {
-                task_log!(
+        let trash_path = self.trash_path();
+
+        // the index files are handed out one by one, so that a thread done with small ones does
+        // not idle while another one still works through large ones
+        let next_image = AtomicUsize::new(0);
+        let marked_images = AtomicUsize::new(0);
+        let progress =
+            GcPhaseProgress::start(&self.inner.gc_progress, GarbageCollectionPhase::Mark);
+        let strange_paths_count = AtomicU64::new(0);
+        let failed = AtomicBool::new(false);
+
+        let mark_images = || -> Result<GcMarkResult, Error> {
+            let mut thread_result = GcMarkResult::default();
+
+            // stop early if another thread failed
+            while !failed.load(Ordering::SeqCst) {
+                let img = match image_list.get(next_image.fetch_add(1, Ordering::SeqCst)) {
+                    Some(img) => img,
+                    None => break,
+                };
+
+                worker.check_abort()?;
+                worker.fail_on_shutdown()?;
+
+                if self.mark_index_file_chunks(
+                    img,
+                    &trash_path,
+                    last_index_state,
+                    marks,
+                    &mut thread_result,
                     worker,
-                    "marked {}% ({} of {} index files)",
-                    percentage,
-                    i + 1,
-                    image_count,
-                );
-                last_percentage = percentage;
+                )? {
+                    strange_paths_count.fetch_add(1, Ordering::SeqCst);
+                }
+
+                let marked = marked_images.fetch_add(1, Ordering::SeqCst) + 1;
+                let percentage = marked * 100 / image_count;
+                progress.update(percentage, marked, worker, || {
+                    format!("marked {percentage}% ({marked} of {image_count} index files)")
+                });
             }
+
+            Ok(thread_result)
+        };
+
+        let results: Vec<Result<GcMarkResult, Error>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..self.inner.gc_threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let result = mark_images();
+                        if result.is_err() {
+                            failed.store(true, Ordering::SeqCst);
+                        }
+                        result
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(format_err!("marking thread panicked")))
+                })
+                .collect()
+        });
+
+        let mut index_state = GcIndexState::new();
+        let mut unchanged_count = 0;
+
+        for result in results {
+            let thread_result = result?;
+            status.index_file_count += thread_result.status.index_file_count;
+            status.index_data_bytes += thread_result.status.index_data_bytes;
+            index_state.extend(thread_result.index_state);
+            unchanged_count += thread_result.unchanged_count;
         }
 
+        if unchanged_count > 0 {
+            task_log!(
+                worker,
+                "skipped touching the chunks of {} unchanged index files",
+                unchanged_count,
+            );
+        }
+
+        let strange_paths_count = strange_paths_count.into_inner();
         if strange_paths_count > 0 {
             task_log!(
                 worker,
@@ -1004,11 +1816,43 @@ impl DataStore {
             );
         }
 
-        Ok(())
+        Ok(index_state)
+    }
+
+    // an unreadable state only means that all chunks get touched again
+    fn load_gc_index_state(&self, worker: &dyn WorkerTaskContext) -> GcIndexState {
+        let path = self.base_path().join(GC_INDEX_STATE_FILE_NAME);
+        match file_read_optional_string(&path) {
+            Ok(Some(data)) => match serde_json::from_str(&data) {
+                Ok(state) => return state,
+                Err(err) => task_warn!(worker, "could not parse {path:?} - {err}"),
+            },
+            Ok(None) => (),
+            Err(err) => task_warn!(worker, "could not read {path:?} - {err}"),
+        }
+        GcIndexState::new()
+    }
+
+    fn save_gc_index_state(&self, state: &GcIndexState) -> Result<(), Error> {
+        let path = self.base_path().join(GC_INDEX_STATE_FILE_NAME);
+        let data = serde_json::to_vec(state)?;
+
+        let backup_user = pbs_config::backup_user()?;
+        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+        let options = CreateOptions::new()
+            .perm(mode)
+            .owner(backup_user.uid)
+            .group(backup_user.gid);
+
+        replace_file(path, &data, options, false)
     }
 
+    /// Returns the status of the last finished garbage collection, and the progress of the
+    /// running one.
     pub fn last_gc_status(&self) -> GarbageCollectionStatus {
-        self.inner.last_gc_status.lock().unwrap().clone()
+        let mut status = self.inner.last_gc_status.lock().unwrap().clone();
+        status.progress = self.inner.gc_progress.lock().unwrap().clone();
+        status
     }
 
     pub fn garbage_collection_running(&self) -> bool {
@@ -1021,109 +1865,180 @@ impl DataStore {
         upid: &UPID,
     ) -> Result<(), Error> {
         if let Ok(ref mut _mutex) = self.inner.gc_mutex.try_lock() {
-            // avoids that we run GC if an old daemon process has still a
-            // running backup writer, which is not save as we have no "oldest
-            // writer" information and thus no safe atime cutoff
-            let _exclusive_lock = self.inner.chunk_store.try_exclusive_lock()?;
-
-            let phase1_start_time = proxmox_time::epoch_i64();
-            let oldest_writer = self
-                .inner
-                .chunk_store
-                .oldest_writer()
-                .unwrap_or(phase1_start_time);
-
-            let mut gc_status = GarbageCollectionStatus {
-                upid: Some(upid.to_string()),
-                ..Default::default()
-            };
+            let result = self.locked_garbage_collection(worker, upid);
+            // the progress is only reported while running
+            *self.inner.gc_progress.lock().unwrap() = None;
+            result
+        } else {
+            bail!("Start GC failed - (already running/locked)");
+        }
+    }
 
-            task_log!(worker, "Start GC phase1 (mark used chunks)");
+    // the actual garbage collection, with the GC mutex held
+    fn locked_garbage_collection(
+        &self,
+        worker: &dyn WorkerTaskContext,
+        upid: &UPID,
+    ) -> Result<(), Error> {
+        // avoids that we run GC if an old daemon process has still a
+        // running backup writer, which is not save as we have no "oldest
+        // writer" information and thus no safe atime cutoff
+        let _exclusive_lock = self.inner.chunk_store.try_exclusive_lock()?;
+
+        let phase1_start_time = proxmox_time::epoch_i64();
+        let oldest_writer = self
+            .inner
+            .chunk_store
+            .oldest_writer()
+            .unwrap_or(phase1_start_time);
 
-            self.mark_used_chunks(&mut gc_status, worker)?;
+        let mut gc_status = GarbageCollectionStatus {
+            upid: Some(upid.to_string()),
+            ..Default::default()
+        };
 
-            task_log!(worker, "Start GC phase2 (sweep unused chunks)");
-            self.inner.chunk_store.sweep_unused_chunks(
-                oldest_writer,
-                phase1_start_time,
-                &mut gc_status,
+        // removed snapshots which cannot be purged just keep their chunks alive for now
+        if let Err(err) = self.purge_expired_trash(worker) {
+            worker.check_abort()?;
+            task_warn!(
                 worker,
-            )?;
+                "purging expired snapshots from trash failed - {err}"
+            );
+        }
+
+        task_log!(worker, "Start GC phase1 (mark used chunks)");
+
+        let last_index_state = self
+            .inner
+            .gc_incremental
+            .then(|| self.load_gc_index_state(worker));
 
+        // sized for the chunks seen by the last run, only filled by incremental garbage collection
+        // and backends which do not mark chunks by touching them
+        let marks = if last_index_state.is_some()
+            || self.inner.backend_type != DatastoreBackendType::Filesystem
+        {
+            let last_status = self.inner.last_gc_status.lock().unwrap();
+            ChunkBitmap::new(last_status.disk_chunks as u64 + last_status.pending_chunks as u64)
+        } else {
+            ChunkBitmap::new(0)
+        };
+
+        let index_state =
+            self.mark_used_chunks(&mut gc_status, last_index_state.as_ref(), &marks, worker)?;
+
+        // accounted by the sweep phase, which sees all chunks inserted until now, including the
+        // ones inserted during phase1. Chunks inserted during the sweep may be counted twice until
+        // the next run, which only overestimates the usage.
+        let inserted_bytes = self.inner.chunk_store.refresh_inserted_bytes()?;
+
+        task_log!(worker, "Start GC phase2 (sweep unused chunks)");
+        let progress =
+            GcPhaseProgress::start(&self.inner.gc_progress, GarbageCollectionPhase::Sweep);
+        self.inner.backend.sweep_unused_chunks(
+            oldest_writer,
+            phase1_start_time,
+            &marks,
+            &mut gc_status,
+            &mut |percentage, chunk_count| {
+                progress.update(percentage, chunk_count, worker, || {
+                    format!("processed {percentage}% ({chunk_count} chunks)")
+                })
+            },
+            worker,
+        )?;
+
+        if self.inner.gc_incremental {
+            if let Err(err) = self.save_gc_index_state(&index_state) {
+                task_warn!(worker, "could not save index file state - {err}");
+            }
+        } else {
+            // not needed anymore, a full run touched all chunks
+            let _ = std::fs::remove_file(self.base_path().join(GC_INDEX_STATE_FILE_NAME));
+        }
+
+        task_log!(
+            worker,
+            "Removed garbage: {}",
+            HumanByte::from(gc_status.removed_bytes),
+        );
+        task_log!(worker, "Removed chunks: {}", gc_status.removed_chunks);
+        if gc_status.pending_bytes > 0 {
             task_log!(
                 worker,
-                "Removed garbage: {}",
-                HumanByte::from(gc_status.removed_bytes),
+                "Pending removals: {} (in {} chunks)",
+                HumanByte::from(gc_status.pending_bytes),
+                gc_status.pending_chunks,
             );
-            task_log!(worker, "Removed chunks: {}", gc_status.removed_chunks);
-            if gc_status.pending_bytes > 0 {
-                task_log!(
-                    worker,
-                    "Pending removals: {} (in {} chunks)",
-                    HumanByte::from(gc_status.pending_bytes),
-                    gc_status.pending_chunks,
-                );
-            }
-            if gc_status.removed_bad > 0 {
-                task_log!(worker, "Removed bad chunks: {}", gc_status.removed_bad);
-            }
-
-            if gc_status.still_bad > 0 {
-                task_log!(worker, "Leftover bad chunks: {}", gc_status.still_bad);
-            }
+        }
+        if gc_status.removed_bad > 0 {
+            task_log!(worker, "Removed bad chunks: {}", gc_status.removed_bad);
+        }
 
+        if gc_status.still_bad > 0 {
             task_log!(
                 worker,
-                "Original data usage: {}",
-                HumanByte::from(gc_status.index_data_bytes),
+                "Leftover bad chunks: {} ({})",
+                gc_status.still_bad,
+                HumanByte::from(gc_status.still_bad_bytes),
             );
+        }
 
-            if gc_status.index_data_bytes > 0 {
-                let comp_per =
-                    (gc_status.disk_bytes as f64 * 100.) / gc_status.index_data_bytes as f64;
-                task_log!(
-                    worker,
-                    "On-Disk usage: {} ({:.2}%)",
-                    HumanByte::from(gc_status.disk_bytes),
-                    comp_per,
-                );
-            }
+        task_log!(
+            worker,
+            "Original data usage: {}",
+            HumanByte::from(gc_status.index_data_bytes),
+        );
 
-            task_log!(worker, "On-Disk chunks: {}", gc_status.disk_chunks);
+        if gc_status.index_data_bytes > 0 {
+            let comp_per = (gc_status.disk_bytes as f64 * 100.) / gc_status.index_data_bytes as f64;
+            task_log!(
+                worker,
+                "On-Disk usage: {} ({:.2}%)",
+                HumanByte::from(gc_status.disk_bytes),
+                comp_per,
+            );
+        }
 
-            let deduplication_factor = if gc_status.disk_bytes > 0 {
-                (gc_status.index_data_bytes as f64) / (gc_status.disk_bytes as f64)
-            } else {
-                1.0
-            };
+        task_log!(worker, "On-Disk chunks: {}", gc_status.disk_chunks);
 
-            task_log!(worker, "Deduplication factor: {:.2}", deduplication_factor);
+        let deduplication_factor = if gc_status.disk_bytes > 0 {
+            (gc_status.index_data_bytes as f64) / (gc_status.disk_bytes as f64)
+        } else {
+            1.0
+        };
 
-            if gc_status.disk_chunks > 0 {
-                let avg_chunk = gc_status.disk_bytes / (gc_status.disk_chunks as u64);
-                task_log!(worker, "Average chunk size: {}", HumanByte::from(avg_chunk));
-            }
+        task_log!(worker, "Deduplication factor: {:.2}", deduplication_factor);
 
-            if let Ok(serialized) = serde_json::to_string(&gc_status) {
-                let mut path = self.base_path();
-                path.push(".gc-status");
-
-                let backup_user = pbs_config::backup_user()?;
-                let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
-                // set the correct owner/group/permissions while saving file
-                // owner(rw) = backup, group(r)= backup
-                let options = CreateOptions::new()
-                    .perm(mode)
-                    .owner(backup_user.uid)
-                    .group(backup_user.gid);
-
-                // ignore errors
-                let _ = replace_file(path, serialized.as_bytes(), options, false);
-            }
+        if gc_status.disk_chunks > 0 {
+            let avg_chunk = gc_status.disk_bytes / (gc_status.disk_chunks as u64);
+            task_log!(worker, "Average chunk size: {}", HumanByte::from(avg_chunk));
+        }
 
-            *self.inner.last_gc_status.lock().unwrap() = gc_status;
-        } else {
-            bail!("Start GC failed - (already running/locked)");
+        if let Ok(serialized) = serde_json::to_string(&gc_status) {
+            let mut path = self.base_path();
+            path.push(".gc-status");
+
+            let backup_user = pbs_config::backup_user()?;
+            let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+            // set the correct owner/group/permissions while saving file
+            // owner(rw) = backup, group(r)= backup
+            let options = CreateOptions::new()
+                .perm(mode)
+                .owner(backup_user.uid)
+                .group(backup_user.gid);
+
+            // ignore errors
+            let _ = replace_file(path, serialized.as_bytes(), options, false);
+        }
+
+        *self.inner.last_gc_status.lock().unwrap() = gc_status;
+        if let Err(err) = self
+            .inner
+            .chunk_store
+            .account_inserted_bytes(inserted_bytes)
+        {
+            task_warn!(worker, "{err}");
         }
 
         Ok(())
@@ -1137,14 +2052,86 @@ impl DataStore {
         self.inner.chunk_store.chunk_path(digest)
     }
 
+    /// Returns the storage backend of the chunks.
+    pub fn backend_type(&self) -> DatastoreBackendType {
+        self.inner.backend_type
+    }
+
     pub fn cond_touch_chunk(&self, digest: &[u8; 32], assert_exists: bool) -> Result<bool, Error> {
-        self.inner
-            .chunk_store
-            .cond_touch_chunk(digest, assert_exists)
+        self.inner.backend.cond_touch_chunk(digest, assert_exists)
     }
 
     pub fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error> {
-        self.inner.chunk_store.insert_chunk(chunk, digest)
+        self.check_chunk_quota(digest)?;
+        self.inner.backend.insert_chunk(chunk, digest)
+    }
+
+    /// Insert a chunk by cloning the chunk file `source`, see [ChunkStore::insert_chunk_file].
+    ///
+    /// The source file is verified like a downloaded chunk first, so corruption does not spread
+    /// to this datastore. `size` is the expected size of the decoded chunk.
+    pub fn insert_chunk_file(
+        &self,
+        source: &Path,
+        digest: &[u8; 32],
+        size: u64,
+    ) -> Result<(bool, u64), Error> {
+        if self.inner.backend_type != DatastoreBackendType::Filesystem {
+            bail!("cloning chunk files is only supported by the filesystem backend");
+        }
+
+        let chunk = DataBlob::load_from_reader(&mut std::fs::File::open(source)?)
+            .map_err(|err| format_err!("unable to load chunk {:?} - {}", source, err))?;
+        chunk
+            .verify_unencrypted(size as usize, digest)
+            .map_err(|err| format_err!("invalid chunk {:?} - {}", source, err))?;
+
+        self.check_chunk_quota(digest)?;
+        self.inner.chunk_store.insert_chunk_file(source, digest)
+    }
+
+    /// Replace a corrupt chunk with a correct copy, without a window in which it is missing.
+    pub fn replace_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<u64, Error> {
+        self.inner.backend.replace_chunk(chunk, digest)
+    }
+
+    /// Remove a chunk, for example because it is corrupt.
+    pub fn remove_chunk(&self, digest: &[u8; 32]) -> Result<(), Error> {
+        self.inner.backend.remove_chunk(digest)
+    }
+
+    /// Move a corrupt chunk to the `.corrupt` directory, see [`ChunkStore::quarantine_chunk`].
+    pub fn quarantine_chunk(&self, digest: &[u8; 32]) -> Result<Option<PathBuf>, Error> {
+        self.inner.chunk_store.quarantine_chunk(digest)
+    }
+
+    /// Check the chunk files of the chunk store for zero length, misplaced and corrupt chunks,
+    /// see [`ChunkStore::check_chunks`].
+    pub fn check_chunkstore(
+        &self,
+        repair: bool,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<ChunkCheckStatus, Error> {
+        if self.inner.backend_type != DatastoreBackendType::Filesystem {
+            bail!("checking the chunks is only supported for datastores on the filesystem");
+        }
+        // keep garbage collection from removing the chunks while they are checked
+        let _shared_lock = self.inner.chunk_store.try_shared_lock()?;
+        self.inner.chunk_store.check_chunks(repair, worker)
+    }
+
+    /// Rewrite the unencrypted chunks with the compression the datastore is tuned to, see
+    /// [`ChunkStore::recompress_chunks`].
+    pub fn recompress_chunks(
+        &self,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<ChunkRecompressStatus, Error> {
+        if self.inner.backend_type != DatastoreBackendType::Filesystem {
+            bail!("recompressing the chunks is only supported for datastores on the filesystem");
+        }
+        self.inner
+            .chunk_store
+            .recompress_chunks(self.inner.compression_level, worker)
     }
 
     pub fn stat_chunk(&self, digest: &[u8; 32]) -> Result<std::fs::Metadata, Error> {
@@ -1153,22 +2140,342 @@ impl DataStore {
     }
 
     pub fn load_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
-        let (chunk_path, digest_str) = self.inner.chunk_store.chunk_path(digest);
-
-        proxmox_lang::try_block!({
-            let mut file = std::fs::File::open(&chunk_path)?;
-            DataBlob::load_from_reader(&mut file)
-        })
-        .map_err(|err| {
+        self.inner.backend.load_chunk(digest).map_err(|err| {
             format_err!(
                 "store '{}', unable to load chunk '{}' - {}",
                 self.name(),
-                digest_str,
+                hex::encode(digest),
                 err,
             )
         })
     }
 
+    /// Load the encoded chunk `digest` without blocking the async executor.
+    ///
+    /// Chunk files of the filesystem backend are read with tokio file IO, other backends fall
+    /// back to [DataStore::load_chunk] in a blocking section.
+    pub async fn load_chunk_async(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
+        match self.inner.backend_type {
+            DatastoreBackendType::Filesystem => {
+                let (path, digest_str) = self.chunk_path(digest);
+                let raw_data = tokio::fs::read(&path).await.map_err(|err| {
+                    format_err!(
+                        "store '{}', unable to load chunk '{}' - {}",
+                        self.name(),
+                        digest_str,
+                        err,
+                    )
+                })?;
+                DataBlob::load_from_reader(&mut &raw_data[..])
+            }
+            DatastoreBackendType::S3 => {
+                proxmox_async::runtime::block_in_place(|| self.load_chunk(digest))
+            }
+        }
+    }
+
+    /// Copy the snapshot `backup_dir` in namespace `ns` to the namespace `target_ns` of the
+    /// `target` datastore.
+    ///
+    /// Only the chunks missing on the target get copied, by cloning the chunk files if both
+    /// datastores use the filesystem backend. A newly created backup group on the target is
+    /// owned by `owner`, an existing one must already be. Returns the number of copied chunks.
+    pub fn copy_snapshot(
+        self: &Arc<Self>,
+        target: &Arc<DataStore>,
+        ns: &BackupNamespace,
+        backup_dir: &pbs_api_types::BackupDir,
+        target_ns: &BackupNamespace,
+        owner: &Authid,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<usize, Error> {
+        if Arc::ptr_eq(&self.inner, &target.inner) && ns == target_ns {
+            bail!("source and target of the snapshot copy are the same");
+        }
+        if !target.namespace_exists(target_ns) {
+            bail!(
+                "namespace '{target_ns}' does not exist on datastore '{}'",
+                target.name()
+            );
+        }
+
+        let reader = SnapshotReader::new(Arc::clone(self), ns.clone(), backup_dir.clone())?;
+
+        // keeps a garbage collection on the target from removing the copied chunks before the
+        // index files referencing them are in place
+        let _shared_store_lock = target.try_shared_chunk_store_lock()?;
+
+        let (group_owner, _group_guard) =
+            target.create_locked_backup_group(target_ns, &backup_dir.group, owner)?;
+        check_backup_owner(&group_owner, owner)?;
+
+        let (relative_path, is_new, _snapshot_guard) =
+            target.create_locked_backup_dir(target_ns, backup_dir)?;
+        if !is_new {
+            bail!(
+                "snapshot '{backup_dir}' already exists on datastore '{}'",
+                target.name()
+            );
+        }
+        let target_path = target.base_path().join(relative_path);
+
+        let result = self
+            .copy_snapshot_files(&reader, target, &target_path, worker)
+            .and_then(|copied_chunks| {
+                target.store_snapshot_in_backend(target_ns, backup_dir)?;
+                Ok(copied_chunks)
+            });
+        if result.is_err() {
+            if let Err(err) = std::fs::remove_dir_all(&target_path) {
+                task_warn!(
+                    worker,
+                    "could not remove incomplete copy {target_path:?} - {err}"
+                );
+            }
+        }
+
+        result
+    }
+
+    // copy the chunks and files of the snapshot, the manifest last, as it marks the copy as
+    // complete
+    fn copy_snapshot_files(
+        &self,
+        reader: &SnapshotReader,
+        target: &DataStore,
+        target_path: &Path,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<usize, Error> {
+        let clone_files = self.inner.backend_type == DatastoreBackendType::Filesystem
+            && target.inner.backend_type == DatastoreBackendType::Filesystem;
+
+        let mut copied_chunks = HashSet::new();
+
+        for file in reader.file_list() {
+            if file == MANIFEST_BLOB_NAME {
+                continue;
+            }
+
+            match archive_type(file)? {
+                ArchiveType::FixedIndex => {
+                    let index = FixedIndexReader::new(reader.open_file(file)?)?;
+                    self.copy_index_chunks(
+                        &index,
+                        target,
+                        clone_files,
+                        &mut copied_chunks,
+                        worker,
+                    )?;
+                }
+                ArchiveType::DynamicIndex => {
+                    let index = DynamicIndexReader::new(reader.open_file(file)?)?;
+                    self.copy_index_chunks(
+                        &index,
+                        target,
+                        clone_files,
+                        &mut copied_chunks,
+                        worker,
+                    )?;
+                }
+                ArchiveType::Blob => (),
+            }
+
+            copy_snapshot_file(reader, file, target_path)?;
+        }
+
+        copy_snapshot_file(reader, MANIFEST_BLOB_NAME, target_path)?;
+        target.try_ensure_sync_level()?;
+
+        Ok(copied_chunks.len())
+    }
+
+    fn copy_index_chunks(
+        &self,
+        index: &dyn IndexFile,
+        target: &DataStore,
+        clone_files: bool,
+        copied_chunks: &mut HashSet<[u8; 32]>,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(), Error> {
+        for pos in 0..index.index_count() {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let digest = index.index_digest(pos).unwrap();
+            if copied_chunks.contains(digest) || target.cond_touch_chunk(digest, false)? {
+                continue;
+            }
+
+            let size = index.chunk_info(pos).unwrap().size();
+            if clone_files {
+                let (chunk_path, _digest_str) = self.chunk_path(digest);
+                target.insert_chunk_file(&chunk_path, digest, size)?;
+            } else {
+                let chunk = self.load_chunk(digest)?;
+                chunk
+                    .verify_unencrypted(size as usize, digest)
+                    .map_err(|err| {
+                        format_err!("invalid chunk {} - {}", hex::encode(digest), err)
+                    })?;
+                target.insert_chunk(&chunk, digest)?;
+            }
+            copied_chunks.insert(*digest);
+        }
+
+        Ok(())
+    }
+
+    // relative to the base path, as used for the copies of the files stored by the backend
+    fn relative_path(&self, path: &Path) -> Result<PathBuf, Error> {
+        Ok(path
+            .strip_prefix(self.base_path())
+            .map_err(|_| format_err!("{:?} is not below the datastore path", path))?
+            .to_path_buf())
+    }
+
+    /// Stores a copy of the files of a snapshot and the owner of its group in the backend, if it
+    /// keeps one, and removes the copies of files which do not exist anymore.
+    pub fn store_snapshot_in_backend(
+        &self,
+        ns: &BackupNamespace,
+        backup_dir: &pbs_api_types::BackupDir,
+    ) -> Result<(), Error> {
+        let backend = &self.inner.backend;
+        if !backend.stores_files() {
+            return Ok(());
+        }
+
+        let full_path = self.snapshot_path(ns, backup_dir);
+        let relative_path = self.relative_path(&full_path)?;
+
+        let mut stored = HashSet::new();
+        for entry in std::fs::read_dir(&full_path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            // skip temporary files, but keep the protection marker
+            if name.ends_with(".tmp") || (name.starts_with('.') && name != ".protected") {
+                continue;
+            }
+            let path = relative_path.join(&name);
+            backend.store_file(&path, std::fs::read(entry.path())?)?;
+            stored.insert(path);
+        }
+
+        for path in backend.list_files(&relative_path)? {
+            if !stored.contains(&path) {
+                backend.remove_file(&path)?;
+            }
+        }
+
+        self.store_owner_in_backend(ns, &backup_dir.group)
+    }
+
+    // the owner is needed to restore a group from the backend
+    fn store_owner_in_backend(
+        &self,
+        ns: &BackupNamespace,
+        backup_group: &pbs_api_types::BackupGroup,
+    ) -> Result<(), Error> {
+        let backend = &self.inner.backend;
+        if !backend.stores_files() {
+            return Ok(());
+        }
+
+        let owner_path = self.owner_path(ns, backup_group);
+        backend.store_file(
+            &self.relative_path(&owner_path)?,
+            std::fs::read(&owner_path)?,
+        )
+    }
+
+    /// Stores a copy of a single file of a snapshot in the backend, if it keeps one.
+    pub fn store_snapshot_file_in_backend(
+        &self,
+        ns: &BackupNamespace,
+        backup_dir: &pbs_api_types::BackupDir,
+        file_name: &str,
+    ) -> Result<(), Error> {
+        let backend = &self.inner.backend;
+        if !backend.stores_files() {
+            return Ok(());
+        }
+
+        let full_path = self.snapshot_path(ns, backup_dir).join(file_name);
+        backend.store_file(&self.relative_path(&full_path)?, std::fs::read(&full_path)?)
+    }
+
+    /// Removes the copies of the files below a snapshot or group directory from the backend.
+    pub fn remove_from_backend(&self, full_path: &Path) -> Result<(), Error> {
+        let backend = &self.inner.backend;
+        if !backend.stores_files() {
+            return Ok(());
+        }
+
+        for path in backend.list_files(&self.relative_path(full_path)?)? {
+            backend.remove_file(&path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores the snapshot files stored in the backend which are missing below the datastore
+    /// path, for example after the disk holding it got replaced. Returns the number of restored
+    /// files.
+    pub fn restore_snapshots_from_backend(
+        &self,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<usize, Error> {
+        let backend = &self.inner.backend;
+        if !backend.stores_files() {
+            bail!(
+                "the backend of datastore '{}' does not store the snapshots",
+                self.name()
+            );
+        }
+
+        let base_path = self.base_path();
+
+        let mut files = backend.list_files(Path::new(""))?;
+        // restore the manifests last, as they mark a snapshot as complete
+        files.sort_by_key(|path| path.ends_with(MANIFEST_BLOB_NAME));
+
+        let mut restored = 0;
+        for path in files {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            if path
+                .components()
+                .any(|component| !matches!(component, std::path::Component::Normal(_)))
+            {
+                task_warn!(worker, "skipping invalid path {:?}", path);
+                continue;
+            }
+
+            let full_path = base_path.join(&path);
+            if full_path.exists() {
+                continue;
+            }
+
+            if let Some(parent) = full_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let data = backend.load_file(&path)?;
+            replace_file(&full_path, &data, CreateOptions::new(), false)?;
+
+            task_log!(worker, "restored {:?}", path);
+            restored += 1;
+        }
+
+        Ok(restored)
+    }
+
     /// Updates the protection status of the specified snapshot.
     pub fn update_protection(&self, backup_dir: &BackupDir, protection: bool) -> Result<(), Error> {
         let full_path = backup_dir.full_path();
@@ -1186,7 +2493,7 @@ impl DataStore {
             }
         }
 
-        Ok(())
+        self.store_snapshot_in_backend(backup_dir.backup_ns(), backup_dir.as_ref())
     }
 
     pub fn verify_new(&self) -> bool {
@@ -1326,3 +2633,264 @@ impl DataStore {
         Ok(())
     }
 }
+
+// copy the file `name` of the snapshot locked by `reader` into the directory `target_path`
+fn copy_snapshot_file(
+    reader: &SnapshotReader,
+    name: &str,
+    target_path: &Path,
+) -> Result<(), Error> {
+    let mut data = Vec::new();
+    std::io::Read::read_to_end(&mut reader.open_file(name)?, &mut data)
+        .map_err(|err| format_err!("unable to read {name} - {err}"))?;
+    replace_file(target_path.join(name), &data, CreateOptions::new(), false)
+        .map_err(|err| format_err!("unable to write {name} - {err}"))
+}
+
+#[cfg(test)]
+pub(crate) struct TestWorker;
+
+#[cfg(test)]
+impl WorkerTaskContext for TestWorker {
+    fn abort_requested(&self) -> bool {
+        false
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        false
+    }
+
+    fn log(&self, _level: log::Level, _message: &std::fmt::Arguments) {}
+}
+
+#[cfg(test)]
+pub(crate) fn create_test_datastore(path: &Path, trash_retention: Option<u64>) -> Arc<DataStore> {
+    let _ = std::fs::remove_dir_all(path);
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .unwrap()
+        .unwrap();
+    let chunk_store = ChunkStore::create(
+        "test",
+        path,
+        user.uid,
+        user.gid,
+        None,
+        DatastoreFSyncLevel::None,
+    )
+    .unwrap();
+
+    let mut config = DataStoreConfig::new("test".to_string(), path.to_string_lossy().into());
+    config.trash_retention = trash_retention;
+
+    let inner =
+        DataStore::with_store_and_config(Arc::new(chunk_store), None, config, None).unwrap();
+    Arc::new(DataStore {
+        inner: Arc::new(inner),
+        operation: None,
+    })
+}
+
+#[cfg(test)]
+fn create_test_snapshot(
+    store: &Arc<DataStore>,
+    ns: &BackupNamespace,
+    dir: &pbs_api_types::BackupDir,
+    owner: &Authid,
+) {
+    let (_owner, _guard) = store
+        .create_locked_backup_group(ns, &dir.group, owner)
+        .unwrap();
+    std::fs::create_dir(store.snapshot_path(ns, dir)).unwrap();
+}
+
+#[test]
+fn test_trash_restore() {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-trash-restore");
+    let store = create_test_datastore(&path, Some(1));
+
+    let ns = BackupNamespace::root();
+    let dir: pbs_api_types::BackupDir = (BackupType::Vm, "100".to_string(), 1_600_000_000).into();
+    let owner: Authid = "root@pam".parse().unwrap();
+    create_test_snapshot(&store, &ns, &dir, &owner);
+
+    let snapshot = store.backup_dir(ns.clone(), dir.clone()).unwrap();
+    store.move_to_trash(&snapshot).unwrap();
+    assert!(!snapshot.full_path().exists());
+
+    let list = store.list_trash().unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].backup, dir);
+    assert_eq!(list[0].owner, Some(owner.clone()));
+    assert!(list[0].removed > 0);
+
+    // the group gets re-created with its owner
+    std::fs::remove_dir_all(store.group_path(&ns, &dir.group)).unwrap();
+    store.restore_from_trash(&ns, &dir).unwrap();
+    assert!(snapshot.full_path().exists());
+    assert!(!snapshot.full_path().join(TRASH_REMOVED_FILE_NAME).exists());
+    assert_eq!(store.get_owner(&ns, &dir.group).unwrap(), owner);
+
+    // the trash directories of the group are gone with its last snapshot
+    assert!(store.list_trash().unwrap().is_empty());
+    assert!(!store.trash_group_path(&ns, &dir.group).exists());
+    assert!(store.restore_from_trash(&ns, &dir).is_err());
+
+    let _ = std::fs::remove_dir_all(&path);
+}
+
+#[test]
+fn test_destroy_data() {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-destroy-data");
+    let store = create_test_datastore(&path, None);
+
+    let ns = BackupNamespace::root();
+    let dir: pbs_api_types::BackupDir = (BackupType::Vm, "100".to_string(), 1_600_000_000).into();
+    let owner: Authid = "root@pam".parse().unwrap();
+    create_test_snapshot(&store, &ns, &dir, &owner);
+
+    let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(b"chunk")
+        .build()
+        .unwrap();
+    store.insert_chunk(&chunk, &digest).unwrap();
+    store.inner.chunk_store.refresh_inserted_bytes().unwrap();
+    std::fs::write(path.join(GC_INDEX_STATE_FILE_NAME), b"{}").unwrap();
+    drop(store);
+
+    DataStore::destroy_data(&path, &TestWorker).unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_trash_purge() {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-trash-purge");
+    let store = create_test_datastore(&path, Some(1));
+
+    let ns = BackupNamespace::root();
+    let owner: Authid = "root@pam".parse().unwrap();
+    let expired: pbs_api_types::BackupDir =
+        (BackupType::Vm, "100".to_string(), 1_600_000_000).into();
+    let recent: pbs_api_types::BackupDir =
+        (BackupType::Vm, "100".to_string(), 1_600_000_100).into();
+
+    for dir in [&expired, &recent] {
+        create_test_snapshot(&store, &ns, dir, &owner);
+        let snapshot = store.backup_dir(ns.clone(), dir.clone()).unwrap();
+        store.move_to_trash(&snapshot).unwrap();
+    }
+
+    // pretend the first snapshot got removed two days ago
+    let removed = proxmox_time::epoch_i64() - 2 * 24 * 3600;
+    replace_file(
+        store
+            .trash_snapshot_path(&ns, &expired)
+            .join(TRASH_REMOVED_FILE_NAME),
+        removed.to_string().as_bytes(),
+        CreateOptions::new(),
+        false,
+    )
+    .unwrap();
+
+    // directories which are no valid namespace are skipped
+    std::fs::create_dir_all(store.trash_path().join("ns").join("not valid")).unwrap();
+
+    store.purge_expired_trash(&TestWorker).unwrap();
+    let list = store.list_trash().unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].backup, recent);
+
+    store.purge_from_trash(&ns, &recent).unwrap();
+    assert!(store.list_trash().unwrap().is_empty());
+    assert!(!store.trash_group_path(&ns, &recent.group).exists());
+    assert!(store.purge_from_trash(&ns, &recent).is_err());
+
+    let _ = std::fs::remove_dir_all(&path);
+}
+
+#[test]
+fn test_incremental_gc() {
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-incremental-gc");
+    let store = create_test_datastore(&path, None);
+
+    let ns = BackupNamespace::root();
+    let dir: pbs_api_types::BackupDir = (BackupType::Vm, "100".to_string(), 1_600_000_000).into();
+    let owner: Authid = "root@pam".parse().unwrap();
+    create_test_snapshot(&store, &ns, &dir, &owner);
+    let snapshot_path = store.snapshot_path(&ns, &dir);
+
+    let insert_chunk = |data: &[u8]| {
+        let (chunk, digest) = crate::data_blob::DataChunkBuilder::new(data)
+            .build()
+            .unwrap();
+        store.insert_chunk(&chunk, &digest).unwrap();
+        digest
+    };
+    let digests: Vec<_> = [&b"kept"[..], b"removed index", b"changed index", b"unused"]
+        .into_iter()
+        .map(insert_chunk)
+        .collect();
+
+    let write_index = |name: &str, digest: &[u8; 32]| {
+        let mut writer = store
+            .create_dynamic_writer(snapshot_path.join(name))
+            .unwrap();
+        writer.add_chunk(4, digest).unwrap();
+        writer.close().unwrap();
+    };
+    write_index("kept.img.didx", &digests[0]);
+    write_index("removed.img.didx", &digests[1]);
+    write_index("changed.img.didx", &digests[2]);
+
+    // pretend the chunks were last touched long ago
+    let age_chunks = |digests: &[[u8; 32]]| {
+        let epoch = nix::sys::time::TimeVal::new(0, 0);
+        for digest in digests {
+            nix::sys::stat::utimes(&store.chunk_path(digest).0, &epoch, &epoch).unwrap();
+        }
+    };
+
+    let gc = |last_index_state: Option<&GcIndexState>| {
+        let marks = ChunkBitmap::new(0);
+        let mut status = GarbageCollectionStatus::default();
+        let index_state = store
+            .mark_used_chunks(&mut status, last_index_state, &marks, &TestWorker)
+            .unwrap();
+        let now = proxmox_time::epoch_i64();
+        store
+            .inner
+            .backend
+            .sweep_unused_chunks(now, now, &marks, &mut status, &mut |_, _| (), &TestWorker)
+            .unwrap();
+        index_state
+    };
+    let exists = |digest: &[u8; 32]| store.chunk_path(digest).0.exists();
+
+    let index_state = gc(None);
+    assert_eq!(index_state.len(), 3);
+
+    // chunks referenced only by unchanged index files are not touched, but survive the sweep
+    age_chunks(&digests);
+    let index_state = gc(Some(&index_state));
+    assert!(digests[..3].iter().all(exists));
+    assert!(!exists(&digests[3]));
+
+    // a removed or changed index file does not keep its old chunks anymore
+    let new = insert_chunk(b"new");
+    age_chunks(&digests[..3]);
+    age_chunks(&[new]);
+    std::fs::remove_file(snapshot_path.join("removed.img.didx")).unwrap();
+    write_index("changed.img.didx", &new);
+    let index_state = gc(Some(&index_state));
+    assert_eq!(index_state.len(), 2);
+    assert!(exists(&digests[0]));
+    assert!(!exists(&digests[1]));
+    assert!(!exists(&digests[2]));
+    // touched again, as its index file changed
+    assert!(exists(&new));
+
+    let _ = std::fs::remove_dir_all(&path);
+}
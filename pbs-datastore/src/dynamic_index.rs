@@ -22,7 +22,9 @@ use crate::chunk_store::ChunkStore;
 use crate::data_blob::{DataBlob, DataChunkBuilder};
 use crate::file_formats;
 use crate::index::{ChunkReadInfo, IndexFile};
+use crate::manifest::PayloadCompression;
 use crate::read_chunk::ReadChunk;
+use crate::seekable_zstd::{SeekableZstdReadAt, SeekableZstdReader};
 use crate::Chunker;
 
 /// Header format definition for dynamic index files (`.dixd`)
@@ -732,3 +734,23 @@ impl<R: ReadChunk> ReadAt for LocalDynamicReadAt<R> {
         panic!("LocalDynamicReadAt::start_read_at returned Pending");
     }
 }
+
+/// Prepare random access to a pxar archive, decompressing its payload if necessary.
+///
+/// Returns the reader along with the size of the (decompressed) archive.
+pub fn pxar_read_at<R: ReadChunk + Send + 'static>(
+    reader: BufferedDynamicReader<R>,
+    payload_compression: Option<PayloadCompression>,
+) -> Result<(Arc<dyn ReadAt + Send + Sync>, u64), Error> {
+    Ok(match payload_compression {
+        Some(PayloadCompression::SeekableZstd) => {
+            let reader = SeekableZstdReader::new(reader)?;
+            let archive_size = reader.decompressed_size();
+            (Arc::new(SeekableZstdReadAt::new(reader)), archive_size)
+        }
+        None => {
+            let archive_size = reader.archive_size();
+            (Arc::new(LocalDynamicReadAt::new(reader)), archive_size)
+        }
+    })
+}
@@ -1,10 +1,15 @@
 use endian_trait::Endian;
 
+use pbs_api_types::CryptCipher;
+
 // WARNING: PLEASE DO NOT MODIFY THOSE MAGIC VALUES
 
 // openssl::sha::sha256(b"Proxmox Backup Catalog file v1.0")[0..8]
 pub const PROXMOX_CATALOG_FILE_MAGIC_1_0: [u8; 8] = [145, 253, 96, 249, 196, 103, 88, 213];
 
+// openssl::sha::sha256(b"Proxmox Backup Catalog file v2.0")[0..8]
+pub const PROXMOX_CATALOG_FILE_MAGIC_2_0: [u8; 8] = [204, 223, 24, 211, 187, 125, 183, 226];
+
 // openssl::sha::sha256(b"Proxmox Backup uncompressed blob v1.0")[0..8]
 pub const UNCOMPRESSED_BLOB_MAGIC_1_0: [u8; 8] = [66, 171, 56, 7, 190, 131, 112, 161];
 
@@ -17,6 +22,12 @@ pub const ENCRYPTED_BLOB_MAGIC_1_0: [u8; 8] = [123, 103, 133, 190, 34, 45, 76, 2
 // openssl::sha::sha256(b"Proxmox Backup zstd compressed encrypted blob v1.0")[0..8]
 pub const ENCR_COMPR_BLOB_MAGIC_1_0: [u8; 8] = [230, 89, 27, 191, 11, 191, 216, 11];
 
+// openssl::sha::sha256(b"Proxmox Backup chacha20-poly1305 encrypted blob v1.0")[0..8]
+pub const ENCRYPTED_CHACHA_BLOB_MAGIC_1_0: [u8; 8] = [224, 118, 48, 183, 55, 29, 7, 248];
+
+// openssl::sha::sha256(b"Proxmox Backup zstd compressed chacha20-poly1305 encrypted blob v1.0")[0..8]
+pub const ENCR_COMPR_CHACHA_BLOB_MAGIC_1_0: [u8; 8] = [42, 33, 130, 83, 60, 82, 205, 78];
+
 // openssl::sha::sha256(b"Proxmox Backup fixed sized chunk index v1.0")[0..8]
 pub const FIXED_SIZED_CHUNK_INDEX_1_0: [u8; 8] = [47, 127, 65, 237, 145, 253, 15, 205];
 
@@ -51,6 +62,9 @@ pub struct DataBlobHeader {
 /// tag, followed by the encrypted data:
 ///
 /// (MAGIC || CRC32 || IV || TAG || EncryptedData).
+///
+/// The magic number also identifies the cipher. CHACHA20_POLY1305 only
+/// uses the first 12 bytes of the IV as nonce.
 #[derive(Endian)]
 #[repr(C, packed)]
 pub struct EncryptedDataBlobHeader {
@@ -68,6 +82,30 @@ pub fn header_size(magic: &[u8; 8]) -> usize {
         COMPRESSED_BLOB_MAGIC_1_0 => std::mem::size_of::<DataBlobHeader>(),
         ENCRYPTED_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
         ENCR_COMPR_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
+        ENCRYPTED_CHACHA_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
+        ENCR_COMPR_CHACHA_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
         _ => panic!("unknown blob magic"),
     }
 }
+
+/// Returns the cipher of an encrypted blob and whether it is compressed, or `None` if the
+/// `magic` does not belong to an encrypted blob.
+pub fn encrypted_blob_info(magic: &[u8; 8]) -> Option<(CryptCipher, bool)> {
+    match *magic {
+        ENCRYPTED_BLOB_MAGIC_1_0 => Some((CryptCipher::Aes256Gcm, false)),
+        ENCR_COMPR_BLOB_MAGIC_1_0 => Some((CryptCipher::Aes256Gcm, true)),
+        ENCRYPTED_CHACHA_BLOB_MAGIC_1_0 => Some((CryptCipher::ChaCha20Poly1305, false)),
+        ENCR_COMPR_CHACHA_BLOB_MAGIC_1_0 => Some((CryptCipher::ChaCha20Poly1305, true)),
+        _ => None,
+    }
+}
+
+/// Returns the magic number of blobs encrypted with `cipher`.
+pub fn encrypted_blob_magic(cipher: CryptCipher, compressed: bool) -> [u8; 8] {
+    match (cipher, compressed) {
+        (CryptCipher::Aes256Gcm, false) => ENCRYPTED_BLOB_MAGIC_1_0,
+        (CryptCipher::Aes256Gcm, true) => ENCR_COMPR_BLOB_MAGIC_1_0,
+        (CryptCipher::ChaCha20Poly1305, false) => ENCRYPTED_CHACHA_BLOB_MAGIC_1_0,
+        (CryptCipher::ChaCha20Poly1305, true) => ENCR_COMPR_CHACHA_BLOB_MAGIC_1_0,
+    }
+}
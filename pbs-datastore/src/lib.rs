@@ -172,6 +172,9 @@ pub mod cached_chunk_reader;
 pub mod catalog;
 pub mod checksum_reader;
 pub mod checksum_writer;
+pub mod chunk_backend;
+pub mod chunk_bitmap;
+pub mod chunk_cache;
 pub mod chunk_stat;
 pub mod chunk_store;
 pub mod chunker;
@@ -184,8 +187,11 @@ pub mod file_formats;
 pub mod index;
 pub mod manifest;
 pub mod paperkey;
+pub mod protocol;
 pub mod prune;
 pub mod read_chunk;
+pub mod s3;
+pub mod seekable_zstd;
 pub mod store_progress;
 pub mod task_tracking;
 
@@ -199,7 +205,7 @@ pub use chunk_store::ChunkStore;
 pub use chunker::Chunker;
 pub use crypt_reader::CryptReader;
 pub use crypt_writer::CryptWriter;
-pub use data_blob::DataBlob;
+pub use data_blob::{ChunkDigestMismatch, DataBlob};
 pub use data_blob_reader::DataBlobReader;
 pub use data_blob_writer::DataBlobWriter;
 pub use manifest::BackupManifest;
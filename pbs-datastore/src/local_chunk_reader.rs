@@ -1,12 +1,13 @@
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Error};
 
 use pbs_api_types::CryptMode;
 use pbs_tools::crypt_config::CryptConfig;
 
+use crate::chunk_cache::{ChunkCache, ChunkCacheStats};
 use crate::data_blob::DataBlob;
 use crate::read_chunk::{AsyncReadChunk, ReadChunk};
 use crate::DataStore;
@@ -16,21 +17,92 @@ pub struct LocalChunkReader {
     store: Arc<DataStore>,
     crypt_config: Option<Arc<CryptConfig>>,
     crypt_mode: CryptMode,
+    cache: Option<Arc<Mutex<ChunkCache>>>,
+    verify_digest: bool,
 }
 
 impl LocalChunkReader {
+    /// Create a new instance, caching the decoded chunks in the chunk cache of the datastore if
+    /// one is configured.
     pub fn new(
         store: Arc<DataStore>,
         crypt_config: Option<Arc<CryptConfig>>,
         crypt_mode: CryptMode,
     ) -> Self {
+        let cache = store.chunk_cache();
         Self {
             store,
             crypt_config,
             crypt_mode,
+            cache,
+            verify_digest: true,
         }
     }
 
+    /// Enable or disable checking the digest of decoded chunks, enabled by default.
+    ///
+    /// A chunk with a digest mismatch fails to read with a
+    /// [ChunkDigestMismatch](crate::data_blob::ChunkDigestMismatch) error. Only
+    /// disable this if the data gets verified otherwise, since it skips hashing every chunk.
+    /// Chunks read without the check are not added to the chunk cache, which is shared with
+    /// readers relying on it.
+    pub fn verify_digest(mut self, verify: bool) -> Self {
+        self.verify_digest = verify;
+        self
+    }
+
+    fn decode_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        let digest = if self.verify_digest {
+            Some(digest)
+        } else {
+            None
+        };
+        chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), digest)
+    }
+
+    /// Use `cache` for the decoded chunks instead of the chunk cache of the datastore, for
+    /// example to share it between the readers of a single task.
+    pub fn with_cache(mut self, cache: Arc<Mutex<ChunkCache>>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Returns the statistics of the used chunk cache, if any.
+    pub fn cache_stats(&self) -> Option<ChunkCacheStats> {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().stats())
+    }
+
+    // the cache is shared with readers of other indexes, so check the crypt mode on hits too
+    fn cached_chunk(&self, digest: &[u8; 32]) -> Result<Option<Vec<u8>>, Error> {
+        let cached = match &self.cache {
+            Some(cache) => cache.lock().unwrap().get(digest),
+            None => None,
+        };
+        match cached {
+            Some((crypt_mode, data)) => {
+                self.ensure_crypt_mode(crypt_mode)?;
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn cache_chunk(&self, digest: &[u8; 32], chunk: &DataBlob, data: &[u8]) -> Result<(), Error> {
+        // the cache is shared with other readers, which rely on the digest being checked
+        if !self.verify_digest {
+            return Ok(());
+        }
+        if let Some(cache) = &self.cache {
+            cache
+                .lock()
+                .unwrap()
+                .insert(digest, chunk.crypt_mode()?, data);
+        }
+        Ok(())
+    }
+
     fn ensure_crypt_mode(&self, chunk_mode: CryptMode) -> Result<(), Error> {
         match self.crypt_mode {
             CryptMode::Encrypt => match chunk_mode {
@@ -55,9 +127,15 @@ impl ReadChunk for LocalChunkReader {
     }
 
     fn read_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        if let Some(raw_data) = self.cached_chunk(digest)? {
+            return Ok(raw_data);
+        }
+
         let chunk = ReadChunk::read_raw_chunk(self, digest)?;
 
-        let raw_data = chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
+        let raw_data = self.decode_chunk(&chunk, digest)?;
+
+        self.cache_chunk(digest, &chunk, &raw_data)?;
 
         Ok(raw_data)
     }
@@ -69,11 +147,7 @@ impl AsyncReadChunk for LocalChunkReader {
         digest: &'a [u8; 32],
     ) -> Pin<Box<dyn Future<Output = Result<DataBlob, Error>> + Send + 'a>> {
         Box::pin(async move {
-            let (path, _) = self.store.chunk_path(digest);
-
-            let raw_data = tokio::fs::read(&path).await?;
-
-            let chunk = DataBlob::load_from_reader(&mut &raw_data[..])?;
+            let chunk = self.store.load_chunk_async(digest).await?;
             self.ensure_crypt_mode(chunk.crypt_mode()?)?;
 
             Ok(chunk)
@@ -85,14 +159,87 @@ impl AsyncReadChunk for LocalChunkReader {
         digest: &'a [u8; 32],
     ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + Send + 'a>> {
         Box::pin(async move {
+            if let Some(raw_data) = self.cached_chunk(digest)? {
+                return Ok(raw_data);
+            }
+
             let chunk = AsyncReadChunk::read_raw_chunk(self, digest).await?;
 
-            let raw_data =
-                chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
+            let raw_data = self.decode_chunk(&chunk, digest)?;
 
-            // fixme: verify digest?
+            self.cache_chunk(digest, &chunk, &raw_data)?;
 
             Ok(raw_data)
         })
     }
 }
+
+#[test]
+fn test_unverified_chunks_are_not_cached() {
+    use crate::data_blob::{ChunkDigestMismatch, DataChunkBuilder};
+
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-local-chunk-reader");
+    let store = crate::datastore::create_test_datastore(&path, None);
+    let cache = Arc::new(Mutex::new(ChunkCache::new(1024 * 1024)));
+
+    // a chunk stored under a digest which does not match its data
+    let digest = [1u8; 32];
+    let chunk = DataBlob::encode(b"corrupt data", None, true).unwrap();
+    store.insert_chunk(&chunk, &digest).unwrap();
+
+    let unverified = LocalChunkReader::new(store.clone(), None, CryptMode::None)
+        .with_cache(Arc::clone(&cache))
+        .verify_digest(false);
+    let data = ReadChunk::read_chunk(&unverified, &digest).unwrap();
+    assert_eq!(data, b"corrupt data");
+    assert!(cache.lock().unwrap().get(&digest).is_none());
+
+    let verified =
+        LocalChunkReader::new(store.clone(), None, CryptMode::None).with_cache(Arc::clone(&cache));
+    let err = ReadChunk::read_chunk(&verified, &digest).unwrap_err();
+    assert!(err.downcast_ref::<ChunkDigestMismatch>().is_some());
+
+    // verified chunks are shared with all readers
+    let (chunk, digest) = DataChunkBuilder::new(b"valid data").build().unwrap();
+    store.insert_chunk(&chunk, &digest).unwrap();
+    ReadChunk::read_chunk(&verified, &digest).unwrap();
+    assert_eq!(
+        cache.lock().unwrap().get(&digest).unwrap(),
+        (CryptMode::None, b"valid data".to_vec())
+    );
+    assert_eq!(
+        ReadChunk::read_chunk(&unverified, &digest).unwrap(),
+        b"valid data"
+    );
+
+    let _ = std::fs::remove_dir_all(&path);
+}
+
+#[test]
+fn test_cached_chunk_crypt_mode() {
+    use crate::data_blob::DataChunkBuilder;
+
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-local-chunk-reader-crypt-mode");
+    let store = crate::datastore::create_test_datastore(&path, None);
+    let cache = Arc::new(Mutex::new(ChunkCache::new(1024 * 1024)));
+
+    let (chunk, digest) = DataChunkBuilder::new(b"plain data").build().unwrap();
+    store.insert_chunk(&chunk, &digest).unwrap();
+
+    let plain =
+        LocalChunkReader::new(store.clone(), None, CryptMode::None).with_cache(Arc::clone(&cache));
+    assert_eq!(
+        ReadChunk::read_chunk(&plain, &digest).unwrap(),
+        b"plain data"
+    );
+    assert!(cache.lock().unwrap().get(&digest).is_some());
+
+    // a reader of an encrypted index must not get the unencrypted chunk from the cache
+    let encrypted = LocalChunkReader::new(store.clone(), None, CryptMode::Encrypt)
+        .with_cache(Arc::clone(&cache));
+    assert!(ReadChunk::read_chunk(&encrypted, &digest).is_err());
+
+    let _ = std::fs::remove_dir_all(&path);
+}
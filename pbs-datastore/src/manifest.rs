@@ -12,6 +12,8 @@ use pbs_tools::crypt_config::CryptConfig;
 pub const MANIFEST_BLOB_NAME: &str = "index.json.blob";
 pub const MANIFEST_LOCK_NAME: &str = ".index.json.lck";
 pub const CLIENT_LOG_BLOB_NAME: &str = "client.log.blob";
+/// First line of the client log the backup client writes with the archive statistics
+pub const CLIENT_LOG_STATISTICS_HEADER: &str = "backup client archive statistics:";
 pub const ENCRYPTED_KEY_BLOB_NAME: &str = "rsa-encrypted.key.blob";
 
 fn crypt_mode_none() -> CryptMode {
@@ -21,6 +23,42 @@ fn empty_value() -> Value {
     json!({})
 }
 
+/// Compression applied to the archive payload before chunking
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PayloadCompression {
+    /// Independently compressed zstd frames with a seek table, see [crate::seekable_zstd]
+    SeekableZstd,
+}
+
+impl PayloadCompression {
+    /// All supported variants, in order of preference
+    pub const ALL: &'static [PayloadCompression] = &[PayloadCompression::SeekableZstd];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadCompression::SeekableZstd => "seekable-zstd",
+        }
+    }
+}
+
+impl std::fmt::Display for PayloadCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for PayloadCompression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "seekable-zstd" => Ok(PayloadCompression::SeekableZstd),
+            _ => bail!("unknown payload compression '{}'", s),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct FileInfo {
@@ -30,6 +68,8 @@ pub struct FileInfo {
     pub size: u64,
     #[serde(with = "hex::serde")]
     pub csum: [u8; 32],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_compression: Option<PayloadCompression>,
 }
 
 impl FileInfo {
@@ -107,10 +147,24 @@ impl BackupManifest {
             size,
             csum,
             crypt_mode,
+            payload_compression: None,
         });
         Ok(())
     }
 
+    /// Mark the payload of an already added file as compressed.
+    pub fn set_payload_compression(
+        &mut self,
+        name: &str,
+        compression: PayloadCompression,
+    ) -> Result<(), Error> {
+        match self.files.iter_mut().find(|item| item.filename == name) {
+            Some(info) => info.payload_compression = Some(compression),
+            None => bail!("manifest does not contain file '{}'", name),
+        }
+        Ok(())
+    }
+
     pub fn files(&self) -> &[FileInfo] {
         &self.files[..]
     }
@@ -0,0 +1,427 @@
+//! Backup and reader protocol feature negotiation
+//!
+//! Optional protocol features are negotiated with HTTP headers on the protocol upgrade request.
+//! The client lists the variants it supports, in order of preference, as comma separated list.
+//! The server selects the first one it supports and returns it in the same header of its
+//! `101 Switching Protocols` response. Older servers ignore the request header, and older
+//! clients do not send it, so a missing header means the feature is not in use.
+//!
+//! For features where several variants can be in use at the same time, like chunk compression,
+//! the server returns all offered variants it supports instead.
+//!
+//! Resuming an interrupted backup is requested with the `resume` parameter instead, as the server
+//! needs to keep state for it.
+
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Error};
+
+use pbs_api_types::CryptCipher;
+
+use crate::file_formats::{encrypted_blob_info, COMPRESSED_BLOB_MAGIC_1_0};
+
+/// Header used to negotiate an end-to-end integrity check for uploaded chunks
+pub const CHUNK_INTEGRITY_HEADER: &str = "proxmox-backup-chunk-integrity";
+
+/// Integrity check of uploaded chunks, in addition to the transport encryption
+///
+/// This allows to detect chunks which got corrupted in-flight, for example by a TLS terminating
+/// proxy, even if they are encrypted and so cannot be checked against their digest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkIntegrity {
+    /// Verify the CRC32 checksum in the blob header of each chunk
+    Crc32,
+}
+
+impl ChunkIntegrity {
+    /// All supported variants, in order of preference
+    pub const ALL: &'static [ChunkIntegrity] = &[ChunkIntegrity::Crc32];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkIntegrity::Crc32 => "crc32",
+        }
+    }
+}
+
+impl fmt::Display for ChunkIntegrity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ChunkIntegrity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "crc32" => Ok(ChunkIntegrity::Crc32),
+            _ => bail!("unknown chunk integrity check '{}'", s),
+        }
+    }
+}
+
+/// Header used to negotiate the compression algorithms usable for chunks
+pub const CHUNK_COMPRESSION_HEADER: &str = "proxmox-backup-chunk-compression";
+
+/// Header used by the server to tell the zstd level chunks should be compressed with, or `none` if
+/// chunks should not be compressed at all. Only sent along with an accepted
+/// [`CHUNK_COMPRESSION_HEADER`] offer which includes zstd, which means that zstd compressed chunks
+/// are accepted in any case.
+pub const CHUNK_COMPRESSION_LEVEL_HEADER: &str = "proxmox-backup-chunk-compression-level";
+
+/// Compression algorithm of chunks (and blobs)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkCompression {
+    /// Zstandard compression
+    Zstd,
+}
+
+impl ChunkCompression {
+    /// All supported variants, in order of preference
+    pub const ALL: &'static [ChunkCompression] = &[ChunkCompression::Zstd];
+
+    /// Variants assumed if the peer did not take part in the negotiation
+    pub const LEGACY: &'static [ChunkCompression] = &[ChunkCompression::Zstd];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkCompression::Zstd => "zstd",
+        }
+    }
+
+    /// Returns the compression of an encoded blob by its `magic`, or `None` if the blob is not
+    /// compressed.
+    pub fn from_magic(magic: &[u8]) -> Option<Self> {
+        let magic: &[u8; 8] = match magic.try_into() {
+            Ok(magic) => magic,
+            Err(_) => return None,
+        };
+        let compressed = match encrypted_blob_info(magic) {
+            Some((_cipher, compressed)) => compressed,
+            None => magic == &COMPRESSED_BLOB_MAGIC_1_0,
+        };
+        if compressed {
+            Some(ChunkCompression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ChunkCompression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ChunkCompression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "zstd" => Ok(ChunkCompression::Zstd),
+            _ => bail!("unknown chunk compression '{}'", s),
+        }
+    }
+}
+
+/// Header used to negotiate the payload compression formats of archives, see
+/// [`PayloadCompression`](crate::manifest::PayloadCompression).
+///
+/// Readers only get access to archives with a compressed payload if they offered its format, so
+/// older clients cannot misinterpret them. Writers should only create such archives if the server
+/// accepted the format, as older servers would hand them out to any reader.
+pub const PAYLOAD_COMPRESSION_HEADER: &str = "proxmox-backup-payload-compression";
+
+/// Header used to negotiate the client log uploaded during the backup session, see [`ClientLog`].
+pub const CLIENT_LOG_HEADER: &str = "proxmox-backup-client-log";
+
+/// Content of a client log uploaded during the backup session
+///
+/// A log uploaded after the backup, for example the task log of Proxmox VE, gets refused by older
+/// servers if the snapshot already contains a log. Clients only upload a log during the session if
+/// the server accepted it, as it then merges the later upload into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientLog {
+    /// Archive statistics, starting with
+    /// [`CLIENT_LOG_STATISTICS_HEADER`](crate::manifest::CLIENT_LOG_STATISTICS_HEADER)
+    Statistics,
+}
+
+impl ClientLog {
+    /// All supported variants, in order of preference
+    pub const ALL: &'static [ClientLog] = &[ClientLog::Statistics];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientLog::Statistics => "statistics",
+        }
+    }
+}
+
+impl fmt::Display for ClientLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ClientLog {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "statistics" => Ok(ClientLog::Statistics),
+            _ => bail!("unknown client log '{}'", s),
+        }
+    }
+}
+
+/// Header used to negotiate the ciphers usable for encrypted chunks and blobs
+///
+/// Older servers cannot parse blobs encrypted with other ciphers than AES-256-GCM, so writers only
+/// use another cipher if the server accepted it.
+pub const CIPHER_HEADER: &str = "proxmox-backup-cipher";
+
+/// All supported ciphers, in order of preference
+pub const CIPHERS: &[CryptCipher] = &[CryptCipher::Aes256Gcm, CryptCipher::ChaCha20Poly1305];
+
+/// Ciphers assumed if the peer did not take part in the negotiation
+pub const LEGACY_CIPHERS: &[CryptCipher] = &[CryptCipher::Aes256Gcm];
+
+/// Name of the file in an unfinished snapshot directory listing the chunks uploaded so far, which
+/// allows to resume the backup.
+pub const RESUME_CHUNKS_NAME: &str = ".resume-chunks";
+
+/// Size of a record in [`RESUME_CHUNKS_NAME`]: the digest followed by the chunk size (u32, little
+/// endian).
+pub const RESUME_CHUNK_RECORD_SIZE: usize = 32 + 4;
+
+/// Encode a list of chunk digests and sizes as stored in [`RESUME_CHUNKS_NAME`].
+pub fn encode_resume_chunks<'a, I>(chunks: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = (&'a [u8; 32], &'a u32)>,
+{
+    let mut data = Vec::new();
+    for (digest, size) in chunks {
+        data.extend_from_slice(digest);
+        data.extend_from_slice(&size.to_le_bytes());
+    }
+    data
+}
+
+/// Decode a list of chunk digests and sizes as stored in [`RESUME_CHUNKS_NAME`].
+pub fn decode_resume_chunks(data: &[u8]) -> Result<Vec<([u8; 32], u32)>, Error> {
+    if data.len() % RESUME_CHUNK_RECORD_SIZE != 0 {
+        bail!("invalid resume chunk list length {}", data.len());
+    }
+
+    Ok(data
+        .chunks_exact(RESUME_CHUNK_RECORD_SIZE)
+        .map(|record| {
+            let digest: [u8; 32] = record[..32].try_into().unwrap();
+            let size = u32::from_le_bytes(record[32..].try_into().unwrap());
+            (digest, size)
+        })
+        .collect())
+}
+
+/// Extension of the partial index of an archive in an unfinished snapshot directory, which lists
+/// the end offsets and digests of the chunks appended so far.
+pub const PARTIAL_INDEX_EXTENSION: &str = ".partial";
+
+/// Size of a partial index record: the end offset of the chunk (u64, little endian) followed by its
+/// digest.
+pub const PARTIAL_INDEX_RECORD_SIZE: usize = 8 + 32;
+
+/// Encode the entries of a partial index, see [`PARTIAL_INDEX_EXTENSION`].
+pub fn encode_partial_index(entries: &[(u64, [u8; 32])]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(entries.len() * PARTIAL_INDEX_RECORD_SIZE);
+    for (end, digest) in entries {
+        data.extend_from_slice(&end.to_le_bytes());
+        data.extend_from_slice(digest);
+    }
+    data
+}
+
+/// Decode the entries of a partial index, see [`PARTIAL_INDEX_EXTENSION`].
+pub fn decode_partial_index(data: &[u8]) -> Result<Vec<(u64, [u8; 32])>, Error> {
+    if data.len() % PARTIAL_INDEX_RECORD_SIZE != 0 {
+        bail!("invalid partial index length {}", data.len());
+    }
+
+    Ok(data
+        .chunks_exact(PARTIAL_INDEX_RECORD_SIZE)
+        .map(|record| {
+            let end = u64::from_le_bytes(record[..8].try_into().unwrap());
+            let digest: [u8; 32] = record[8..].try_into().unwrap();
+            (end, digest)
+        })
+        .collect())
+}
+
+/// Strip the incomplete last record of a list with fixed size records, which is left behind if
+/// appending to it got interrupted.
+pub fn strip_incomplete_record(data: &[u8], record_size: usize) -> &[u8] {
+    &data[..data.len() - data.len() % record_size]
+}
+
+/// Returns the leading `entries` of a partial index which a resumed backup can append to.
+///
+/// The chunks of a dynamic index need increasing end offsets. Those of a fixed index, with
+/// `chunk_size` set, are ordered by their offsets, and only full chunks without gaps are kept.
+pub fn partial_index_prefix(
+    mut entries: Vec<(u64, [u8; 32])>,
+    chunk_size: Option<u64>,
+) -> Vec<(u64, [u8; 32])> {
+    let len = match chunk_size {
+        Some(chunk_size) => {
+            entries.sort_by_key(|(end, _digest)| *end);
+            entries.dedup_by_key(|(end, _digest)| *end);
+            entries
+                .iter()
+                .zip(1..)
+                .take_while(|((end, _digest), pos)| *end == pos * chunk_size)
+                .count()
+        }
+        None => {
+            let mut last_end = 0;
+            entries
+                .iter()
+                .take_while(|(end, _digest)| {
+                    let increasing = *end > last_end;
+                    last_end = *end;
+                    increasing
+                })
+                .count()
+        }
+    };
+    entries.truncate(len);
+    entries
+}
+
+/// Format the supported `variants` as header value offered by the client.
+pub fn feature_offer<T: fmt::Display>(variants: &[T]) -> String {
+    variants
+        .iter()
+        .map(|variant| variant.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Select the first variant of the client's `offer` which is supported.
+pub fn negotiate_feature<T: FromStr>(offer: &str) -> Option<T> {
+    offer
+        .split(',')
+        .find_map(|variant| variant.trim().parse().ok())
+}
+
+/// Select all variants of the client's `offer` which are supported.
+pub fn negotiate_feature_set<T: FromStr>(offer: &str) -> Vec<T> {
+    offer
+        .split(',')
+        .filter_map(|variant| variant.trim().parse().ok())
+        .collect()
+}
+
+#[test]
+fn test_feature_negotiation() {
+    assert_eq!(feature_offer(ChunkIntegrity::ALL), "crc32");
+    assert_eq!(
+        feature_offer(&[ClientLog::Statistics, ClientLog::Statistics]),
+        "statistics,statistics"
+    );
+    assert_eq!(feature_offer::<ChunkCompression>(&[]), "");
+
+    // the first supported variant of the offer wins, unknown ones are skipped
+    assert_eq!(
+        negotiate_feature::<ChunkIntegrity>("sha256, crc32,md5"),
+        Some(ChunkIntegrity::Crc32)
+    );
+    assert_eq!(negotiate_feature::<ChunkIntegrity>("sha256"), None);
+    assert_eq!(negotiate_feature::<ChunkIntegrity>(""), None);
+    assert_eq!(
+        negotiate_feature::<ClientLog>(&feature_offer(ClientLog::ALL)),
+        Some(ClientLog::Statistics)
+    );
+    assert_eq!(
+        negotiate_feature::<CryptCipher>("chacha20-poly1305,aes-256-gcm"),
+        Some(CryptCipher::ChaCha20Poly1305)
+    );
+
+    // sets keep the order of the offer
+    assert_eq!(
+        negotiate_feature_set::<CryptCipher>("aes-256-gcm,chacha20-poly1305"),
+        vec![CryptCipher::Aes256Gcm, CryptCipher::ChaCha20Poly1305]
+    );
+    assert_eq!(
+        negotiate_feature_set::<ChunkCompression>("lz4,zstd"),
+        vec![ChunkCompression::Zstd]
+    );
+    assert!(negotiate_feature_set::<ChunkCompression>("").is_empty());
+    assert!(negotiate_feature_set::<ChunkCompression>("lz4, ,").is_empty());
+}
+
+#[test]
+fn test_cipher_negotiation() {
+    assert_eq!(
+        negotiate_feature_set::<CryptCipher>(&feature_offer(CIPHERS)),
+        CIPHERS
+    );
+    assert_eq!(
+        negotiate_feature_set::<CryptCipher>("chacha20-poly1305, aes-512-foo,aes-256-gcm"),
+        &[CryptCipher::ChaCha20Poly1305, CryptCipher::Aes256Gcm],
+    );
+    assert!(negotiate_feature_set::<CryptCipher>("aes-512-foo").is_empty());
+}
+
+#[test]
+fn test_resume_chunks() -> Result<(), Error> {
+    let chunks = vec![([1u8; 32], 4096u32), ([2u8; 32], 4 * 1024 * 1024)];
+
+    let data = encode_resume_chunks(chunks.iter().map(|(digest, size)| (digest, size)));
+    assert_eq!(decode_resume_chunks(&data)?, chunks);
+
+    assert!(decode_resume_chunks(&data[1..]).is_err());
+    assert_eq!(
+        decode_resume_chunks(strip_incomplete_record(
+            &data[..50],
+            RESUME_CHUNK_RECORD_SIZE
+        ))?,
+        &chunks[..1],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_partial_index() -> Result<(), Error> {
+    let entries = vec![(4096u64, [1u8; 32]), (12288, [2u8; 32]), (12289, [3u8; 32])];
+
+    let data = encode_partial_index(&entries);
+    assert_eq!(decode_partial_index(&data)?, entries);
+    assert!(decode_partial_index(&data[1..]).is_err());
+
+    // dynamic indexes keep the entries with increasing offsets
+    assert_eq!(partial_index_prefix(entries.clone(), None), entries);
+    let mut broken = entries.clone();
+    broken.insert(2, (8192, [4u8; 32]));
+    assert_eq!(partial_index_prefix(broken, None), &entries[..2]);
+
+    // fixed indexes keep full chunks without gaps, in order
+    let fixed = vec![
+        (8192u64, [2u8; 32]),
+        (4096, [1u8; 32]),
+        (8192, [2u8; 32]),
+        (16384, [4u8; 32]),
+    ];
+    assert_eq!(
+        partial_index_prefix(fixed, Some(4096)),
+        vec![(4096, [1u8; 32]), (8192, [2u8; 32])],
+    );
+    assert!(partial_index_prefix(vec![(2048, [1u8; 32])], Some(4096)).is_empty());
+
+    Ok(())
+}
@@ -14,6 +14,8 @@ pub trait ReadChunk {
     fn read_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error>;
 }
 
+/// The AsyncReadChunk trait allows reading backup data chunks (local or remote) from async
+/// contexts, without blocking the executor
 pub trait AsyncReadChunk: Send {
     /// Returns the encoded chunk data
     fn read_raw_chunk<'a>(
@@ -0,0 +1,361 @@
+//! Seekable zstd format for archive payloads.
+//!
+//! The payload is split into frames of at most [FRAME_SIZE] uncompressed bytes, which are
+//! compressed independently of each other. A seek table is appended as zstd skippable frame,
+//! following the zstd "seekable format" specification (without per-frame checksums), so that
+//! readers can map uncompressed offsets to frames and only decompress what is actually needed.
+//!
+//! Writers are expected to start a new frame at every chunk boundary of the uncompressed data and
+//! to never let a frame span two chunks, so unchanged data results in the same chunks across
+//! backups and deduplicates just like an uncompressed archive.
+//!
+//! Since the result is a valid zstd stream, it can also be decompressed sequentially with the
+//! standard `zstd` tool.
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Context;
+
+use anyhow::{bail, format_err, Error};
+use pxar::accessor::{MaybeReady, ReadAt, ReadAtOperation};
+
+/// Maximum uncompressed size of a single frame.
+pub const FRAME_SIZE: usize = 1024 * 1024;
+
+const COMPRESSION_LEVEL: i32 = 1;
+
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+const SEEK_TABLE_FOOTER_SIZE: usize = 9;
+const SEEK_TABLE_ENTRY_SIZE: usize = 8;
+
+/// Compresses data into independent frames and builds the seek table.
+pub struct SeekableZstdEncoder {
+    // (compressed, decompressed) size of each written frame
+    frames: Vec<(u32, u32)>,
+}
+
+impl Default for SeekableZstdEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeekableZstdEncoder {
+    pub fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+
+    /// Compress `data` as a single frame.
+    pub fn write_frame(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if data.is_empty() || data.len() > FRAME_SIZE {
+            bail!("invalid seekable zstd frame size {}", data.len());
+        }
+        let frame = zstd::stream::encode_all(data, COMPRESSION_LEVEL)
+            .map_err(|err| format_err!("zstd frame compression failed - {}", err))?;
+        self.frames.push((frame.len() as u32, data.len() as u32));
+        Ok(frame)
+    }
+
+    /// Compress `data` as frames of [FRAME_SIZE] bytes, the last one may be shorter.
+    pub fn write(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        data.chunks(FRAME_SIZE)
+            .map(|frame| self.write_frame(frame))
+            .collect()
+    }
+
+    /// Finish the written frames by returning the seek table.
+    pub fn finish(self) -> Vec<u8> {
+        let table_size = self.frames.len() * SEEK_TABLE_ENTRY_SIZE + SEEK_TABLE_FOOTER_SIZE;
+        let mut output = Vec::with_capacity(table_size + 8);
+        output.extend_from_slice(&SKIPPABLE_FRAME_MAGIC.to_le_bytes());
+        output.extend_from_slice(&(table_size as u32).to_le_bytes());
+        for (compressed, decompressed) in self.frames.iter() {
+            output.extend_from_slice(&compressed.to_le_bytes());
+            output.extend_from_slice(&decompressed.to_le_bytes());
+        }
+        output.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        output.push(0); // descriptor: no checksums
+        output.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+
+        output
+    }
+}
+
+struct FrameInfo {
+    compressed_offset: u64,
+    compressed_size: u32,
+    decompressed_offset: u64,
+    decompressed_size: u32,
+}
+
+/// Random access reader for data in seekable zstd format.
+pub struct SeekableZstdReader<R> {
+    inner: R,
+    frames: Vec<FrameInfo>,
+    size: u64,
+    position: u64,
+    // index and decompressed data of the last used frame
+    current: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> SeekableZstdReader<R> {
+    /// Open a reader, this reads the seek table from the end of `inner`.
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let total = inner.seek(SeekFrom::End(0))?;
+        if total < SEEK_TABLE_FOOTER_SIZE as u64 {
+            bail!("seekable zstd data too small");
+        }
+
+        let mut footer = [0u8; SEEK_TABLE_FOOTER_SIZE];
+        inner.seek(SeekFrom::Start(total - SEEK_TABLE_FOOTER_SIZE as u64))?;
+        inner.read_exact(&mut footer)?;
+
+        if u32::from_le_bytes(footer[5..9].try_into().unwrap()) != SEEKABLE_MAGIC {
+            bail!("missing seekable zstd seek table");
+        }
+        if footer[4] & 0x80 != 0 {
+            bail!("seekable zstd seek tables with checksums are not supported");
+        }
+        let frame_count = u32::from_le_bytes(footer[0..4].try_into().unwrap()) as u64;
+
+        let table_size = frame_count * SEEK_TABLE_ENTRY_SIZE as u64;
+        let table_start = total
+            .checked_sub(SEEK_TABLE_FOOTER_SIZE as u64 + table_size + 8)
+            .ok_or_else(|| format_err!("invalid seekable zstd seek table size"))?;
+
+        let mut table = vec![0u8; table_size as usize + 8];
+        inner.seek(SeekFrom::Start(table_start))?;
+        inner.read_exact(&mut table)?;
+
+        if u32::from_le_bytes(table[0..4].try_into().unwrap()) != SKIPPABLE_FRAME_MAGIC {
+            bail!("invalid seekable zstd seek table header");
+        }
+
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        let mut compressed_offset = 0;
+        let mut decompressed_offset = 0;
+        for entry in table[8..].chunks_exact(SEEK_TABLE_ENTRY_SIZE) {
+            let compressed_size = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let decompressed_size = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+            frames.push(FrameInfo {
+                compressed_offset,
+                compressed_size,
+                decompressed_offset,
+                decompressed_size,
+            });
+            compressed_offset += compressed_size as u64;
+            decompressed_offset += decompressed_size as u64;
+        }
+
+        if compressed_offset != table_start {
+            bail!("seekable zstd seek table does not match data size");
+        }
+
+        Ok(Self {
+            inner,
+            frames,
+            size: decompressed_offset,
+            position: 0,
+            current: None,
+        })
+    }
+
+    /// Size of the decompressed data.
+    pub fn decompressed_size(&self) -> u64 {
+        self.size
+    }
+
+    fn load_frame(&mut self, index: usize) -> Result<&[u8], Error> {
+        if !matches!(self.current, Some((current, _)) if current == index) {
+            let frame = &self.frames[index];
+            let mut compressed = vec![0u8; frame.compressed_size as usize];
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            self.inner.read_exact(&mut compressed)?;
+            let data = zstd::stream::decode_all(&compressed[..])?;
+            if data.len() != frame.decompressed_size as usize {
+                bail!("seekable zstd frame {} has wrong decompressed size", index);
+            }
+            self.current = Some((index, data));
+        }
+        Ok(&self.current.as_ref().unwrap().1)
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let position = self.position;
+        let index = match self
+            .frames
+            .binary_search_by(|frame| frame.decompressed_offset.cmp(&position))
+        {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        let frame_offset = (position - self.frames[index].decompressed_offset) as usize;
+
+        let data = self
+            .load_frame(index)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+
+        let len = (data.len() - frame_offset).min(buf.len());
+        buf[..len].copy_from_slice(&data[frame_offset..(frame_offset + len)]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableZstdReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+
+        if new_pos < 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek before start of seekable zstd data",
+            ));
+        }
+
+        self.position = new_pos as u64;
+        Ok(self.position)
+    }
+}
+
+/// Random access to seekable zstd data for the pxar accessor.
+///
+/// Like [LocalDynamicReadAt](crate::dynamic_index::LocalDynamicReadAt), this simply blocks on the
+/// underlying synchronous reader.
+pub struct SeekableZstdReadAt<R> {
+    inner: Mutex<SeekableZstdReader<R>>,
+}
+
+impl<R: Read + Seek> SeekableZstdReadAt<R> {
+    pub fn new(inner: SeekableZstdReader<R>) -> Self {
+        Self {
+            inner: Mutex::new(inner),
+        }
+    }
+}
+
+impl<R: Read + Seek + Send> ReadAt for SeekableZstdReadAt<R> {
+    fn start_read_at<'a>(
+        self: Pin<&'a Self>,
+        _cx: &mut Context,
+        buf: &'a mut [u8],
+        offset: u64,
+    ) -> MaybeReady<std::io::Result<usize>, ReadAtOperation<'a>> {
+        MaybeReady::Ready(tokio::task::block_in_place(move || {
+            let mut reader = self.inner.lock().unwrap();
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read(buf)
+        }))
+    }
+
+    fn poll_complete<'a>(
+        self: Pin<&'a Self>,
+        _op: ReadAtOperation<'a>,
+    ) -> MaybeReady<std::io::Result<usize>, ReadAtOperation<'a>> {
+        panic!("SeekableZstdReadAt::start_read_at returned Pending");
+    }
+}
+
+#[cfg(test)]
+fn test_data(len: usize) -> Vec<u8> {
+    // compressible, but not trivially so
+    (0..len)
+        .map(|i| ((i * 7) % 251) as u8 ^ (i >> 13) as u8)
+        .collect()
+}
+
+#[cfg(test)]
+fn encode(chunks: &[&[u8]]) -> Result<Vec<u8>, Error> {
+    let mut encoder = SeekableZstdEncoder::new();
+    let mut output = Vec::new();
+    for chunk in chunks {
+        for frame in encoder.write(chunk)? {
+            output.extend_from_slice(&frame);
+        }
+    }
+    output.extend_from_slice(&encoder.finish());
+    Ok(output)
+}
+
+#[test]
+fn test_seekable_zstd_roundtrip() -> Result<(), Error> {
+    let data = test_data(3 * FRAME_SIZE + 12345);
+    let (first, second) = data.split_at(FRAME_SIZE + 17);
+    let encoded = encode(&[first, second])?;
+
+    // a valid zstd stream for sequential decoders
+    assert_eq!(zstd::stream::decode_all(&encoded[..])?, data);
+
+    let mut reader = SeekableZstdReader::new(std::io::Cursor::new(encoded))?;
+    assert_eq!(reader.decompressed_size(), data.len() as u64);
+
+    let mut decoded = Vec::new();
+    reader.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, data);
+
+    Ok(())
+}
+
+#[test]
+fn test_seekable_zstd_seek() -> Result<(), Error> {
+    let data = test_data(2 * FRAME_SIZE + 100);
+    let encoded = encode(&[&data[..100], &data[100..]])?;
+    let mut reader = SeekableZstdReader::new(std::io::Cursor::new(encoded))?;
+
+    // reads across frame boundaries, in both directions
+    for offset in [FRAME_SIZE + 90, 50, 2 * FRAME_SIZE + 99, 0, FRAME_SIZE - 10] {
+        reader.seek(SeekFrom::Start(offset as u64))?;
+        let mut buf = vec![0u8; 200];
+        let len = (data.len() - offset).min(buf.len());
+        reader.read_exact(&mut buf[..len])?;
+        assert_eq!(&buf[..len], &data[offset..offset + len]);
+    }
+
+    reader.seek(SeekFrom::End(0))?;
+    let mut buf = [0u8; 16];
+    assert_eq!(reader.read(&mut buf)?, 0);
+
+    assert!(reader
+        .seek(SeekFrom::Current(-(data.len() as i64) - 1))
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_seekable_zstd_frames_follow_chunks() -> Result<(), Error> {
+    let data = test_data(FRAME_SIZE + 5000);
+
+    // the frames of a chunk do not depend on the data in front of it
+    let mut encoder = SeekableZstdEncoder::new();
+    let unchanged = encoder.write(&data)?;
+    let mut encoder = SeekableZstdEncoder::new();
+    encoder.write(b"some prepended data")?;
+    assert_eq!(encoder.write(&data)?, unchanged);
+
+    Ok(())
+}
+
+#[test]
+fn test_seekable_zstd_invalid() {
+    assert!(SeekableZstdReader::new(std::io::Cursor::new(vec![0u8; 4])).is_err());
+
+    let mut encoded = encode(&[&test_data(1000)]).unwrap();
+    let len = encoded.len();
+    encoded[len - 1] ^= 0xff; // break the seekable magic
+    assert!(SeekableZstdReader::new(std::io::Cursor::new(encoded)).is_err());
+}
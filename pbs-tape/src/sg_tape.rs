@@ -892,6 +892,9 @@ impl SgTape {
             density: drive_status.density_code.try_into()?,
             alert_flags,
             write_protect: None,
+            encryption: None,
+            label_text: None,
+            pool: None,
             file_number: None,
             block_number: None,
             manufactured: None,
@@ -912,7 +915,12 @@ impl SgTape {
             status.file_number = Some(position.logical_file_id);
             status.block_number = Some(position.logical_object_number);
 
+            // drives without hardware encryption do not support the status page
+            status.encryption = drive_get_encryption(&mut self.file).ok();
+
             if let Ok(mam) = self.cartridge_memory() {
+                status.label_text = mam_extract_barcode(&mam);
+
                 let usage = mam_extract_media_usage(&mam)?;
 
                 status.manufactured = Some(usage.manufactured);
@@ -6,6 +6,8 @@ use endian_trait::Endian;
 
 use proxmox_io::{ReadExt, WriteExt};
 
+use pbs_api_types::DriveEncryptionMode;
+
 use crate::sgutils2::{alloc_page_aligned_buffer, SgRaw};
 
 /// Test if drive supports hardware encryption
@@ -41,12 +43,12 @@ pub fn set_encryption<F: AsRawFd>(file: &mut F, key: Option<[u8; 32]>) -> Result
     let status = decode_spin_data_encryption_status(&data)?;
 
     match status.mode {
-        DataEncryptionMode::Off => {
+        DriveEncryptionMode::Off => {
             if key.is_none() {
                 return Ok(());
             }
         }
-        DataEncryptionMode::Mixed => {
+        DriveEncryptionMode::Mixed => {
             if key.is_some() {
                 return Ok(());
             }
@@ -57,6 +59,15 @@ pub fn set_encryption<F: AsRawFd>(file: &mut F, key: Option<[u8; 32]>) -> Result
     bail!("got unexpected encryption mode {:?}", status.mode);
 }
 
+/// Read the current encryption mode of the drive
+///
+/// Note: This fails if there is no media loaded.
+pub fn drive_get_encryption<F: AsRawFd>(file: &mut F) -> Result<DriveEncryptionMode, Error> {
+    let data = sg_spin_data_encryption_status(file)?;
+    let status = decode_spin_data_encryption_status(&data)?;
+    Ok(status.mode)
+}
+
 #[derive(Endian)]
 #[repr(C, packed)]
 struct SspSetDataEncryptionPage {
@@ -114,7 +125,7 @@ fn sg_spout_set_encryption<F: AsRawFd>(
     }
 
     let mut cmd = Vec::new();
-    cmd.push(0xB5); // SECURITY PROTOCOL IN (SPOUT)
+    cmd.push(0xB5); // SECURITY PROTOCOL OUT (SPOUT)
     cmd.push(0x20); // Tape Data Encryption Page
     cmd.push(0);
     cmd.push(0x10); // Set Data Encryption page
@@ -187,17 +198,9 @@ fn sg_spin_data_encryption_caps<F: AsRawFd>(file: &mut F) -> Result<Vec<u8>, Err
         .map(|v| v.to_vec())
 }
 
-#[derive(Debug)]
-enum DataEncryptionMode {
-    On,
-    Mixed,
-    RawRead,
-    Off,
-}
-
 #[derive(Debug)]
 struct DataEncryptionStatus {
-    mode: DataEncryptionMode,
+    mode: DriveEncryptionMode,
 }
 
 #[derive(Endian)]
@@ -288,10 +291,10 @@ fn decode_spin_data_encryption_status(data: &[u8]) -> Result<DataEncryptionStatu
         }
 
         let mode = match (page.encryption_mode, page.decryption_mode) {
-            (0, 0) => DataEncryptionMode::Off,
-            (2, 1) => DataEncryptionMode::RawRead,
-            (2, 2) => DataEncryptionMode::On,
-            (2, 3) => DataEncryptionMode::Mixed,
+            (0, 0) => DriveEncryptionMode::Off,
+            (2, 1) => DriveEncryptionMode::RawRead,
+            (2, 2) => DriveEncryptionMode::On,
+            (2, 3) => DriveEncryptionMode::Mixed,
             _ => bail!("unknown encryption mode"),
         };
 
@@ -280,6 +280,20 @@ fn decode_mam_attributes(data: &[u8]) -> Result<Vec<MamAttribute>, Error> {
     Ok(list)
 }
 
+/// Extract the Barcode from Cartridge Memory
+///
+/// Not all libraries write this attribute, so this returns None if it is unset or empty.
+pub fn mam_extract_barcode(mam: &[MamAttribute]) -> Option<String> {
+    mam.iter()
+        .find(|v| v.id == 0x08_06)
+        .map(|v| {
+            v.value
+                .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        })
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+}
+
 /// Media Usage Information from Cartridge Memory
 pub struct MediaUsageInfo {
     pub manufactured: i64,
@@ -54,6 +54,14 @@ impl CertInfo {
         Ok(Self { x509 })
     }
 
+    /// Parse all certificates contained in a PEM file, for example a certificate chain.
+    pub fn stack_from_pem(pem: &[u8]) -> Result<Vec<Self>, Error> {
+        Ok(X509::stack_from_pem(pem)?
+            .into_iter()
+            .map(|x509| Self { x509 })
+            .collect())
+    }
+
     pub fn subject_alt_names(&self) -> Option<Stack<GeneralName>> {
         self.x509.subject_alt_names()
     }
@@ -1,7 +1,9 @@
 //! Wrappers for OpenSSL crypto functions
 //!
-//! We use this to encrypt and decrypt data chunks. Cipher is
-//! AES_256_GCM, which is fast and provides authenticated encryption.
+//! We use this to encrypt and decrypt data chunks. The default cipher
+//! is AES_256_GCM, which is fast and provides authenticated encryption.
+//! CHACHA20_POLY1305 is available as alternative for hardware without
+//! AES instructions.
 //!
 //! See the Wikipedia Artikel for [Authenticated
 //! encryption](https://en.wikipedia.org/wiki/Authenticated_encryption)
@@ -12,6 +14,8 @@ use openssl::hash::MessageDigest;
 use openssl::pkcs5::pbkdf2_hmac;
 use openssl::symm::{Cipher, Crypter, Mode};
 
+use pbs_api_types::CryptCipher;
+
 // openssl::sha::sha256(b"Proxmox Backup Encryption Key Fingerprint")
 /// This constant is used to compute fingerprints.
 const FINGERPRINT_INPUT: [u8; 32] = [
@@ -24,8 +28,8 @@ const FINGERPRINT_INPUT: [u8; 32] = [
 /// This structure stores the secret key and provides helpers for
 /// authenticated encryption.
 pub struct CryptConfig {
-    // the Cipher
-    cipher: Cipher,
+    // the Cipher used for encryption
+    cipher: CryptCipher,
     // A secrect key use to provide the chunk digest name space.
     id_key: [u8; 32],
     // Openssl hmac PKey of id_key
@@ -56,13 +60,44 @@ impl CryptConfig {
             id_key,
             id_pkey,
             enc_key,
-            cipher: Cipher::aes_256_gcm(),
+            cipher: CryptCipher::Aes256Gcm,
         })
     }
 
-    /// Expose Cipher (AES_256_GCM)
-    pub fn cipher(&self) -> &Cipher {
-        &self.cipher
+    /// Use `cipher` to encrypt data (default is AES_256_GCM).
+    ///
+    /// Decryption always uses the cipher the data was encrypted with.
+    pub fn with_cipher(mut self, cipher: CryptCipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Expose the cipher used for encryption
+    pub fn crypt_cipher(&self) -> CryptCipher {
+        self.cipher
+    }
+
+    /// Expose openssl Cipher used for encryption
+    pub fn cipher(&self) -> Cipher {
+        Self::openssl_cipher(self.cipher)
+    }
+
+    /// Returns the openssl Cipher for `cipher`
+    pub fn openssl_cipher(cipher: CryptCipher) -> Cipher {
+        match cipher {
+            CryptCipher::Aes256Gcm => Cipher::aes_256_gcm(),
+            CryptCipher::ChaCha20Poly1305 => Cipher::chacha20_poly1305(),
+        }
+    }
+
+    /// Returns the part of the 16 byte `iv` used as nonce by `cipher`.
+    ///
+    /// CHACHA20_POLY1305 only supports nonces up to 12 bytes.
+    pub fn cipher_iv(cipher: CryptCipher, iv: &[u8; 16]) -> &[u8] {
+        match cipher {
+            CryptCipher::Aes256Gcm => &iv[..],
+            CryptCipher::ChaCha20Poly1305 => &iv[..12],
+        }
     }
 
     /// Expose encryption key
@@ -108,9 +143,24 @@ impl CryptConfig {
         self.compute_digest(&FINGERPRINT_INPUT)
     }
 
-    /// Returns an openssl Crypter using AES_256_GCM,
+    /// Returns an openssl Crypter using the cipher used for encryption
     pub fn data_crypter(&self, iv: &[u8; 16], mode: Mode) -> Result<Crypter, Error> {
-        let mut crypter = openssl::symm::Crypter::new(self.cipher, mode, &self.enc_key, Some(iv))?;
+        self.data_crypter_for(self.cipher, iv, mode)
+    }
+
+    /// Returns an openssl Crypter using `cipher`
+    pub fn data_crypter_for(
+        &self,
+        cipher: CryptCipher,
+        iv: &[u8; 16],
+        mode: Mode,
+    ) -> Result<Crypter, Error> {
+        let mut crypter = openssl::symm::Crypter::new(
+            Self::openssl_cipher(cipher),
+            mode,
+            &self.enc_key,
+            Some(Self::cipher_iv(cipher, iv)),
+        )?;
         crypter.aad_update(b"")?; //??
         Ok(crypter)
     }
@@ -171,6 +171,16 @@ impl<K: std::cmp::Eq + std::hash::Hash + Copy, V> LruCache<K, V> {
         Some(node.value)
     }
 
+    /// Remove the least recently used entry from the cache and return it.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        if self.list.tail.is_null() {
+            return None;
+        }
+        let node = self.list.remove(self.list.tail);
+        self.map.remove(&node.key);
+        Some((node.key, node.value))
+    }
+
     /// Remove the least recently used node from the cache.
     fn pop_tail(&mut self) {
         if let Some(old_tail) = self.list.pop_tail() {
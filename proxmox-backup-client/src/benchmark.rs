@@ -14,7 +14,7 @@ use proxmox_router::{
 };
 use proxmox_schema::{api, ApiType, ReturnType};
 
-use pbs_api_types::{BackupNamespace, BackupType};
+use pbs_api_types::{BackupNamespace, BackupType, CryptCipher};
 use pbs_client::tools::key_source::get_encryption_key_password;
 use pbs_client::{BackupRepository, BackupWriter};
 use pbs_config::key_config::{load_and_decrypt_key, KeyDerivationConfig};
@@ -53,6 +53,9 @@ struct Speed {
         "aes256_gcm": {
             type: Speed,
         },
+        "chacha20_poly1305": {
+            type: Speed,
+        },
         "verify": {
             type: Speed,
         },
@@ -71,6 +74,8 @@ struct BenchmarkResult {
     decompress: Speed,
     /// AES256 GCM encryption speed
     aes256_gcm: Speed,
+    /// ChaCha20 Poly1305 encryption speed
+    chacha20_poly1305: Speed,
     /// Verify speed
     verify: Speed,
 }
@@ -96,6 +101,10 @@ static BENCHMARK_RESULT_2020_TOP: BenchmarkResult = BenchmarkResult {
         speed: None,
         top: 1_000_000.0 * 3645.0, // AMD Ryzen 7 2700X
     },
+    chacha20_poly1305: Speed {
+        speed: None,
+        top: 1_000_000.0 * 1800.0, // AMD Ryzen 7 2700X (approximate)
+    },
     verify: Speed {
         speed: None,
         top: 1_000_000.0 * 758.0, // AMD Ryzen 7 2700X
@@ -210,6 +219,12 @@ fn render_result(output_format: &str, benchmark_result: &BenchmarkResult) -> Res
                 .header("AES256 GCM encryption speed")
                 .right_align(false)
                 .renderer(render_speed),
+        )
+        .column(
+            ColumnConfig::new("chacha20_poly1305")
+                .header("ChaCha20 Poly1305 encryption speed")
+                .right_align(false)
+                .renderer(render_speed),
         );
 
     format_and_print_result_full(&mut data, &return_type, output_format, &options);
@@ -236,6 +251,7 @@ async fn test_upload_speed(
         &(BackupType::Host, "benchmark".to_string(), backup_time).into(),
         false,
         true,
+        false,
     )
     .await?;
 
@@ -344,6 +360,24 @@ fn test_crypt_speed(benchmark_result: &mut BenchmarkResult) -> Result<(), Error>
 
     log::info!("AES256/GCM speed: {:.2} MB/s", speed / 1_000_000.0);
 
+    let crypt_config = crypt_config.with_cipher(CryptCipher::ChaCha20Poly1305);
+
+    let start_time = std::time::Instant::now();
+
+    let mut bytes = 0;
+    loop {
+        let mut out = Vec::new();
+        DataBlob::encrypt_benchmark(&crypt_config, &random_data, &mut out)?;
+        bytes += random_data.len();
+        if start_time.elapsed().as_micros() > 1_000_000 {
+            break;
+        }
+    }
+    let speed = (bytes as f64) / start_time.elapsed().as_secs_f64();
+    benchmark_result.chacha20_poly1305.speed = Some(speed);
+
+    log::info!("ChaCha20/Poly1305 speed: {:.2} MB/s", speed / 1_000_000.0);
+
     let start_time = std::time::Instant::now();
 
     let (chunk, digest) = DataChunkBuilder::new(&random_data).compress(true).build()?;
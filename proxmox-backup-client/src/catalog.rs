@@ -1,5 +1,8 @@
-use std::io::{Seek, SeekFrom};
+use std::ffi::OsStr;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
@@ -9,17 +12,23 @@ use proxmox_router::cli::*;
 use proxmox_schema::api;
 
 use pbs_api_types::BackupNamespace;
+use pbs_client::tools::chunk_cache_size;
 use pbs_client::tools::key_source::get_encryption_key_password;
-use pbs_client::{BackupReader, RemoteChunkReader};
+use pbs_client::{BackupReader, BackupRepository, RemoteChunkReader};
+use pbs_datastore::catalog::{CatalogEntryType, DirEntry, DirEntryAttribute};
+use pbs_datastore::dynamic_index::pxar_read_at;
+use pbs_datastore::manifest::{BackupManifest, PayloadCompression};
+use pbs_datastore::seekable_zstd::SeekableZstdReader;
 use pbs_tools::crypt_config::CryptConfig;
 use pbs_tools::json::required_string_param;
 
 use crate::{
     complete_backup_snapshot, complete_group_or_snapshot, complete_namespace,
-    complete_pxar_archive_name, complete_repository, connect, crypto_parameters, decrypt_key,
-    dir_or_last_from_group, extract_repository_from_value, format_key_source, optional_ns_param,
-    record_repository, BackupDir, BufferedDynamicReadAt, BufferedDynamicReader, CatalogReader,
-    DynamicIndexReader, IndexFile, Shell, CATALOG_NAME, KEYFD_SCHEMA, REPO_URL_SCHEMA,
+    complete_pxar_archive_name, complete_repository, connect_rate_limited, crypto_parameters,
+    decrypt_key, dir_or_last_from_group, extract_repository_from_value, format_key_source,
+    legacy_crypt_config, optional_ns_param, rate_limit_from_param, record_repository, BackupDir,
+    BufferedDynamicReader, CatalogReader, Shell, CATALOG_NAME, KEYFD_SCHEMA, LEGACY_KEYFILE_SCHEMA,
+    REPO_URL_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA, TRAFFIC_CONTROL_RATE_SCHEMA,
 };
 
 #[api(
@@ -46,6 +55,18 @@ use crate::{
                 schema: KEYFD_SCHEMA,
                 optional: true,
             },
+            "legacy-keyfile": {
+                schema: LEGACY_KEYFILE_SCHEMA,
+                optional: true,
+            },
+            rate: {
+                schema: TRAFFIC_CONTROL_RATE_SCHEMA,
+                optional: true,
+            },
+            burst: {
+                schema: TRAFFIC_CONTROL_BURST_SCHEMA,
+                optional: true,
+            },
         }
    }
 )]
@@ -72,11 +93,14 @@ async fn dump_catalog(param: Value) -> Result<Value, Error> {
         }
     };
 
-    let client = connect(&repo)?;
+    let client = connect_rate_limited(&repo, rate_limit_from_param(&param)?)?;
+
+    let legacy_crypt_config = legacy_crypt_config(&param)?;
 
-    let client = BackupReader::start(
+    let (client, crypt_config) = BackupReader::start_with_legacy_key(
         client,
-        crypt_config.clone(),
+        crypt_config,
+        legacy_crypt_config,
         repo.store(),
         &backup_ns,
         &snapshot,
@@ -87,19 +111,32 @@ async fn dump_catalog(param: Value) -> Result<Value, Error> {
     let (manifest, _) = client.download_manifest().await?;
     manifest.check_fingerprint(crypt_config.as_ref().map(Arc::as_ref))?;
 
+    let mut catalog_reader = download_catalog_file(client, crypt_config, &manifest).await?;
+
+    catalog_reader.dump()?;
+
+    record_repository(&repo);
+
+    Ok(Value::Null)
+}
+
+/// Downloads the catalog of the snapshot opened by `client` into a temporary file.
+pub(crate) async fn download_catalog_file(
+    client: Arc<BackupReader>,
+    crypt_config: Option<Arc<CryptConfig>>,
+    manifest: &BackupManifest,
+) -> Result<CatalogReader<std::fs::File>, Error> {
     let index = client
-        .download_dynamic_index(&manifest, CATALOG_NAME)
+        .download_dynamic_index(manifest, CATALOG_NAME)
         .await?;
 
-    let most_used = index.find_most_used_chunks(8);
-
     let file_info = manifest.lookup_file_info(CATALOG_NAME)?;
 
     let chunk_reader = RemoteChunkReader::new(
-        client.clone(),
+        client,
         crypt_config,
         file_info.chunk_crypt_mode(),
-        most_used,
+        chunk_cache_size()?,
     );
 
     let mut reader = BufferedDynamicReader::new(index, chunk_reader);
@@ -115,13 +152,7 @@ async fn dump_catalog(param: Value) -> Result<Value, Error> {
 
     catalogfile.seek(SeekFrom::Start(0))?;
 
-    let mut catalog_reader = CatalogReader::new(catalogfile);
-
-    catalog_reader.dump()?;
-
-    record_repository(&repo);
-
-    Ok(Value::Null)
+    Ok(CatalogReader::new(catalogfile))
 }
 
 #[api(
@@ -152,113 +183,390 @@ async fn dump_catalog(param: Value) -> Result<Value, Error> {
                 schema: KEYFD_SCHEMA,
                 optional: true,
             },
+            "legacy-keyfile": {
+                schema: LEGACY_KEYFILE_SCHEMA,
+                optional: true,
+            },
+            rate: {
+                schema: TRAFFIC_CONTROL_RATE_SCHEMA,
+                optional: true,
+            },
+            burst: {
+                schema: TRAFFIC_CONTROL_BURST_SCHEMA,
+                optional: true,
+            },
          },
     },
 )]
 /// Shell to interactively inspect and restore snapshots.
 async fn catalog_shell(param: Value) -> Result<(), Error> {
-    let repo = extract_repository_from_value(&param)?;
-    let client = connect(&repo)?;
-    let backup_ns = optional_ns_param(&param)?;
-    let path = required_string_param(&param, "snapshot")?;
-    let archive_name = required_string_param(&param, "archive-name")?;
+    let session = PxarCatalogSession::open(&param).await?;
 
-    let backup_dir = dir_or_last_from_group(&client, &repo, &backup_ns, path).await?;
+    let (reader, payload_compression) = session.archive_reader().await?;
+    let (reader, archive_size) = pxar_read_at(reader, payload_compression)?;
+    let decoder = pbs_client::pxar::fuse::Accessor::new(reader, archive_size).await?;
 
-    let crypto = crypto_parameters(&param)?;
+    let catalog_reader = session.download_catalog().await?;
+    let state = Shell::new(catalog_reader, &session.archive_name, decoder).await?;
 
-    let crypt_config = match crypto.enc_key {
-        None => None,
-        Some(key) => {
-            let (key, _created, _fingerprint) = decrypt_key(&key.key, &get_encryption_key_password)
-                .map_err(|err| {
-                    log::error!("{}", format_key_source(&key.source, "encryption"));
-                    err
-                })?;
-            let crypt_config = CryptConfig::new(key)?;
-            Some(Arc::new(crypt_config))
-        }
-    };
+    log::info!("Starting interactive shell");
+    state.shell().await?;
+
+    record_repository(&session.repo);
 
-    let server_archive_name = if archive_name.ends_with(".pxar") {
-        format!("{}.didx", archive_name)
-    } else {
-        bail!("Can only mount pxar archives.");
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            "snapshot": {
+                type: String,
+                description: "Group/Snapshot path.",
+            },
+            "archive-name": {
+                type: String,
+                description: "Backup archive name.",
+            },
+            "repository": {
+                optional: true,
+                schema: REPO_URL_SCHEMA,
+            },
+            "keyfile": {
+                optional: true,
+                type: String,
+                description: "Path to encryption key.",
+            },
+            "keyfd": {
+                schema: KEYFD_SCHEMA,
+                optional: true,
+            },
+            "legacy-keyfile": {
+                schema: LEGACY_KEYFILE_SCHEMA,
+                optional: true,
+            },
+            rate: {
+                schema: TRAFFIC_CONTROL_RATE_SCHEMA,
+                optional: true,
+            },
+            burst: {
+                schema: TRAFFIC_CONTROL_BURST_SCHEMA,
+                optional: true,
+            },
+         },
+    },
+)]
+/// Read a whole pxar archive without extracting it and check that it agrees with the catalog.
+async fn verify_archive(param: Value) -> Result<(), Error> {
+    let session = PxarCatalogSession::open(&param).await?;
+    let archive_name = &session.archive_name;
+
+    let mut catalog = session.download_catalog().await?;
+    let catalog_root = catalog.root()?;
+    let archive_root = catalog
+        .lookup(&catalog_root, archive_name.as_bytes())?
+        .ok_or_else(|| format_err!("archive not found in catalog"))?;
+
+    let (reader, payload_compression) = session.archive_reader().await?;
+    let reader: Box<dyn Read + Send> = match payload_compression {
+        Some(PayloadCompression::SeekableZstd) => Box::new(SeekableZstdReader::new(reader)?),
+        None => Box::new(reader),
     };
 
-    let client = BackupReader::start(
-        client,
-        crypt_config.clone(),
-        repo.store(),
-        &backup_ns,
-        &backup_dir,
-        true,
-    )
-    .await?;
+    let mut decoder = pxar::decoder::Decoder::from_std(reader)?;
+    decoder.enable_goodbye_entries(true);
 
-    let mut tmpfile = std::fs::OpenOptions::new()
-        .write(true)
-        .read(true)
-        .custom_flags(libc::O_TMPFILE)
-        .open("/tmp")?;
+    let mut checker = CatalogChecker::new(&mut catalog, archive_root);
+    let mut entries = 0;
 
-    let (manifest, _) = client.download_manifest().await?;
-    manifest.check_fingerprint(crypt_config.as_ref().map(Arc::as_ref))?;
+    while let Some(entry) = decoder.next() {
+        let entry = entry.map_err(|err| format_err!("error reading pxar archive: {}", err))?;
 
-    let index = client
-        .download_dynamic_index(&manifest, &server_archive_name)
-        .await?;
-    let most_used = index.find_most_used_chunks(8);
+        if let pxar::EntryKind::GoodbyeTable = entry.kind() {
+            continue;
+        }
 
-    let file_info = manifest.lookup_file_info(&server_archive_name)?;
-    let chunk_reader = RemoteChunkReader::new(
-        client.clone(),
-        crypt_config.clone(),
-        file_info.chunk_crypt_mode(),
-        most_used,
+        if let Some(mut contents) = decoder.contents() {
+            std::io::copy(&mut contents, &mut std::io::sink()).map_err(|err| {
+                format_err!("error reading contents of {:?}: {}", entry.path(), err)
+            })?;
+        }
+
+        if entry.path() != Path::new("/") {
+            entries += 1;
+            checker.check(&entry)?;
+        }
+    }
+
+    let catalog_entries = checker.count_catalog_entries()?;
+    if catalog_entries != entries {
+        log::warn!(
+            "catalog contains {} entries, but archive contains {}",
+            catalog_entries,
+            entries
+        );
+        checker.errors += 1;
+    }
+
+    record_repository(&session.repo);
+
+    if checker.errors > 0 {
+        bail!(
+            "archive '{}' has {} inconsistencies with the catalog",
+            archive_name,
+            checker.errors
+        );
+    }
+
+    log::info!(
+        "archive '{}' is consistent ({} entries)",
+        archive_name,
+        entries
     );
-    let reader = BufferedDynamicReader::new(index, chunk_reader);
-    let archive_size = reader.archive_size();
-    let reader: pbs_client::pxar::fuse::Reader = Arc::new(BufferedDynamicReadAt::new(reader));
-    let decoder = pbs_client::pxar::fuse::Accessor::new(reader, archive_size).await?;
 
-    client.download(CATALOG_NAME, &mut tmpfile).await?;
-    let index = DynamicIndexReader::new(tmpfile)
-        .map_err(|err| format_err!("unable to read catalog index - {}", err))?;
+    Ok(())
+}
 
-    // Note: do not use values stored in index (not trusted) - instead, computed them again
-    let (csum, size) = index.compute_csum();
-    manifest.verify_file(CATALOG_NAME, &csum, size)?;
+/// Reader session on a snapshot for commands which work on a pxar archive and its catalog.
+struct PxarCatalogSession {
+    repo: BackupRepository,
+    client: Arc<BackupReader>,
+    crypt_config: Option<Arc<CryptConfig>>,
+    manifest: BackupManifest,
+    /// server side name of the archive, including the '.didx' extension
+    archive_name: String,
+}
 
-    let most_used = index.find_most_used_chunks(8);
+impl PxarCatalogSession {
+    /// Connects to the snapshot and archive given by the 'snapshot' and 'archive-name'
+    /// parameters and loads the manifest.
+    async fn open(param: &Value) -> Result<Self, Error> {
+        let repo = extract_repository_from_value(param)?;
+        let client = connect_rate_limited(&repo, rate_limit_from_param(param)?)?;
+        let backup_ns = optional_ns_param(param)?;
+        let path = required_string_param(param, "snapshot")?;
+        let archive_name = required_string_param(param, "archive-name")?;
+
+        let backup_dir = dir_or_last_from_group(&client, &repo, &backup_ns, path).await?;
+
+        let crypto = crypto_parameters(param)?;
+
+        let crypt_config = match crypto.enc_key {
+            None => None,
+            Some(key) => {
+                let (key, _created, _fingerprint) =
+                    decrypt_key(&key.key, &get_encryption_key_password).map_err(|err| {
+                        log::error!("{}", format_key_source(&key.source, "encryption"));
+                        err
+                    })?;
+                let crypt_config = CryptConfig::new(key)?;
+                Some(Arc::new(crypt_config))
+            }
+        };
+
+        let archive_name = if archive_name.ends_with(".pxar") {
+            format!("{}.didx", archive_name)
+        } else if archive_name.ends_with(".pxar.didx") {
+            archive_name.to_string()
+        } else {
+            bail!("Can only open pxar archives.");
+        };
+
+        let legacy_crypt_config = legacy_crypt_config(param)?;
+
+        let (client, crypt_config) = BackupReader::start_with_legacy_key(
+            client,
+            crypt_config,
+            legacy_crypt_config,
+            repo.store(),
+            &backup_ns,
+            &backup_dir,
+            true,
+        )
+        .await?;
 
-    let file_info = manifest.lookup_file_info(CATALOG_NAME)?;
-    let chunk_reader = RemoteChunkReader::new(
-        client.clone(),
-        crypt_config,
-        file_info.chunk_crypt_mode(),
-        most_used,
-    );
-    let mut reader = BufferedDynamicReader::new(index, chunk_reader);
-    let mut catalogfile = std::fs::OpenOptions::new()
-        .write(true)
-        .read(true)
-        .custom_flags(libc::O_TMPFILE)
-        .open("/tmp")?;
+        let (manifest, _) = client.download_manifest().await?;
+        manifest.check_fingerprint(crypt_config.as_ref().map(Arc::as_ref))?;
+
+        Ok(Self {
+            repo,
+            client,
+            crypt_config,
+            manifest,
+            archive_name,
+        })
+    }
+
+    async fn chunk_reader(
+        &self,
+        name: &str,
+    ) -> Result<BufferedDynamicReader<RemoteChunkReader>, Error> {
+        let index = self
+            .client
+            .download_dynamic_index(&self.manifest, name)
+            .await?;
+        let file_info = self.manifest.lookup_file_info(name)?;
+        let chunk_reader = RemoteChunkReader::new(
+            self.client.clone(),
+            self.crypt_config.clone(),
+            file_info.chunk_crypt_mode(),
+            chunk_cache_size()?,
+        );
+        Ok(BufferedDynamicReader::new(index, chunk_reader))
+    }
+
+    /// Downloads the catalog of the snapshot into a temporary file.
+    async fn download_catalog(&self) -> Result<CatalogReader<std::fs::File>, Error> {
+        download_catalog_file(
+            self.client.clone(),
+            self.crypt_config.clone(),
+            &self.manifest,
+        )
+        .await
+    }
+
+    /// Returns a reader for the archive together with the compression of its payload.
+    async fn archive_reader(
+        &self,
+    ) -> Result<
+        (
+            BufferedDynamicReader<RemoteChunkReader>,
+            Option<PayloadCompression>,
+        ),
+        Error,
+    > {
+        let reader = self.chunk_reader(&self.archive_name).await?;
+        let file_info = self.manifest.lookup_file_info(&self.archive_name)?;
+        Ok((reader, file_info.payload_compression))
+    }
+}
 
-    std::io::copy(&mut reader, &mut catalogfile)
-        .map_err(|err| format_err!("unable to download catalog - {}", err))?;
+/// Compares pxar entries with their catalog counterparts.
+struct CatalogChecker<'a, R> {
+    catalog: &'a mut CatalogReader<R>,
+    root: DirEntry,
+    // parent directory and its content of the last checked entry
+    current_dir: Option<(PathBuf, Vec<DirEntry>)>,
+    errors: usize,
+}
 
-    catalogfile.seek(SeekFrom::Start(0))?;
-    let catalog_reader = CatalogReader::new(catalogfile);
-    let state = Shell::new(catalog_reader, &server_archive_name, decoder).await?;
+impl<'a, R: Read + Seek> CatalogChecker<'a, R> {
+    fn new(catalog: &'a mut CatalogReader<R>, root: DirEntry) -> Self {
+        Self {
+            catalog,
+            root,
+            current_dir: None,
+            errors: 0,
+        }
+    }
+
+    fn lookup_dir(&mut self, path: &Path) -> Result<Option<DirEntry>, Error> {
+        let mut dir = self.root.clone();
+        for component in path.iter().filter(|c| *c != OsStr::new("/")) {
+            match self.catalog.lookup(&dir, component.as_bytes())? {
+                Some(entry) if entry.is_directory() => dir = entry,
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(dir))
+    }
+
+    fn check(&mut self, entry: &pxar::Entry) -> Result<(), Error> {
+        let path = entry.path();
+        let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+
+        if !matches!(self.current_dir, Some((ref current, _)) if current == parent) {
+            let content = match self.lookup_dir(parent)? {
+                Some(dir) => self.catalog.read_dir(&dir)?,
+                None => Vec::new(),
+            };
+            self.current_dir = Some((parent.to_owned(), content));
+        }
 
-    log::info!("Starting interactive shell");
-    state.shell().await?;
+        let name = entry.file_name().as_bytes();
+        let catalog_entry = self
+            .current_dir
+            .as_ref()
+            .and_then(|(_, content)| content.iter().find(|e| e.name == name));
+
+        let catalog_entry = match catalog_entry {
+            Some(catalog_entry) => catalog_entry,
+            None => {
+                log::warn!("{:?}: missing in catalog", path);
+                self.errors += 1;
+                return Ok(());
+            }
+        };
+
+        let metadata = entry.metadata();
+        let expected = match entry.kind() {
+            pxar::EntryKind::Directory => CatalogEntryType::Directory,
+            pxar::EntryKind::File { .. } => CatalogEntryType::File,
+            pxar::EntryKind::Symlink(_) => CatalogEntryType::Symlink,
+            pxar::EntryKind::Hardlink(_) => CatalogEntryType::Hardlink,
+            pxar::EntryKind::Device(_) if metadata.file_type() == pxar::mode::IFBLK => {
+                CatalogEntryType::BlockDevice
+            }
+            pxar::EntryKind::Device(_) => CatalogEntryType::CharDevice,
+            pxar::EntryKind::Fifo => CatalogEntryType::Fifo,
+            pxar::EntryKind::Socket => CatalogEntryType::Socket,
+            pxar::EntryKind::GoodbyeTable => return Ok(()),
+        };
+
+        let found = CatalogEntryType::from(&catalog_entry.attr);
+        if found != expected {
+            log::warn!(
+                "{:?}: type mismatch, archive: '{}', catalog: '{}'",
+                path,
+                expected,
+                found
+            );
+            self.errors += 1;
+            return Ok(());
+        }
 
-    record_repository(&repo);
+        if let (
+            pxar::EntryKind::File { size, .. },
+            DirEntryAttribute::File {
+                size: catalog_size,
+                mtime,
+                ..
+            },
+        ) = (entry.kind(), &catalog_entry.attr)
+        {
+            if size != catalog_size || metadata.stat.mtime.secs != *mtime {
+                log::warn!(
+                    "{:?}: size/mtime mismatch, archive: {}/{}, catalog: {}/{}",
+                    path,
+                    size,
+                    metadata.stat.mtime.secs,
+                    catalog_size,
+                    mtime
+                );
+                self.errors += 1;
+            }
+        }
 
-    Ok(())
+        Ok(())
+    }
+
+    fn count_catalog_entries(&mut self) -> Result<usize, Error> {
+        let mut count = 0;
+        let mut todo = vec![self.root.clone()];
+        while let Some(dir) = todo.pop() {
+            for entry in self.catalog.read_dir(&dir)? {
+                count += 1;
+                if entry.is_directory() {
+                    todo.push(entry);
+                }
+            }
+        }
+        Ok(count)
+    }
 }
 
 pub fn catalog_mgmt_cli() -> CliCommandMap {
@@ -275,7 +583,15 @@ pub fn catalog_mgmt_cli() -> CliCommandMap {
         .completion_cb("ns", complete_namespace)
         .completion_cb("snapshot", complete_backup_snapshot);
 
+    let catalog_verify_cmd_def = CliCommand::new(&API_METHOD_VERIFY_ARCHIVE)
+        .arg_param(&["snapshot", "archive-name"])
+        .completion_cb("repository", complete_repository)
+        .completion_cb("ns", complete_namespace)
+        .completion_cb("archive-name", complete_pxar_archive_name)
+        .completion_cb("snapshot", complete_group_or_snapshot);
+
     CliCommandMap::new()
         .insert("dump", catalog_dump_cmd_def)
         .insert("shell", catalog_shell_cmd_def)
+        .insert("verify", catalog_verify_cmd_def)
 }
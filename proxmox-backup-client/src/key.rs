@@ -13,12 +13,17 @@ use proxmox_schema::{api, ApiType, ReturnType};
 use proxmox_sys::fs::{file_get_contents, replace_file, CreateOptions};
 use proxmox_sys::linux::tty;
 
-use pbs_api_types::{Kdf, KeyInfo, PASSWORD_HINT_SCHEMA};
+use pbs_api_types::{
+    Kdf, KeyInfo, ARGON2_ITERATIONS_SCHEMA, ARGON2_MEMORY_SCHEMA, PASSWORD_HINT_SCHEMA,
+};
 use pbs_client::tools::key_source::{
     find_default_encryption_key, find_default_master_pubkey, get_encryption_key_password,
     place_default_encryption_key, place_default_master_pubkey,
 };
-use pbs_config::key_config::{rsa_decrypt_key_config, KeyConfig};
+use pbs_config::key_config::{
+    rsa_decrypt_key_config, rsa_encrypt_key_config, KeyConfig, KeyDerivationConfig,
+    DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_MEMORY,
+};
 use pbs_datastore::paperkey::{generate_paper_key, PaperkeyFormat};
 
 #[api]
@@ -54,6 +59,26 @@ impl std::convert::TryFrom<openssl::rsa::Rsa<openssl::pkey::Public>> for RsaPubK
     }
 }
 
+// Key derivation configuration for `kdf`, Argon2id parameters are only allowed for Argon2id.
+fn kdf_config(
+    kdf: Kdf,
+    argon2_memory: Option<u32>,
+    argon2_iterations: Option<u32>,
+) -> Result<KeyDerivationConfig, Error> {
+    match kdf {
+        Kdf::Argon2id => KeyDerivationConfig::argon2id(
+            argon2_memory.unwrap_or(DEFAULT_ARGON2_MEMORY),
+            argon2_iterations.unwrap_or(DEFAULT_ARGON2_ITERATIONS),
+        ),
+        kdf => {
+            if argon2_memory.is_some() || argon2_iterations.is_some() {
+                bail!("argon2 parameters are only allowed with kdf 'argon2id'");
+            }
+            KeyDerivationConfig::new(kdf)
+        }
+    }
+}
+
 #[api(
     input: {
         properties: {
@@ -61,6 +86,14 @@ impl std::convert::TryFrom<openssl::rsa::Rsa<openssl::pkey::Public>> for RsaPubK
                 type: Kdf,
                 optional: true,
             },
+            "argon2-memory": {
+                schema: ARGON2_MEMORY_SCHEMA,
+                optional: true,
+            },
+            "argon2-iterations": {
+                schema: ARGON2_ITERATIONS_SCHEMA,
+                optional: true,
+            },
             path: {
                 description:
                     "Output file. Without this the key will become the new default encryption key.",
@@ -74,7 +107,13 @@ impl std::convert::TryFrom<openssl::rsa::Rsa<openssl::pkey::Public>> for RsaPubK
     },
 )]
 /// Create a new encryption key.
-fn create(kdf: Option<Kdf>, path: Option<String>, hint: Option<String>) -> Result<(), Error> {
+fn create(
+    kdf: Option<Kdf>,
+    argon2_memory: Option<u32>,
+    argon2_iterations: Option<u32>,
+    path: Option<String>,
+    hint: Option<String>,
+) -> Result<(), Error> {
     let path = match path {
         Some(path) => PathBuf::from(path),
         None => {
@@ -99,7 +138,9 @@ fn create(kdf: Option<Kdf>, path: Option<String>, hint: Option<String>) -> Resul
 
             key_config.store(path, false)?;
         }
-        Kdf::Scrypt | Kdf::PBKDF2 => {
+        Kdf::Scrypt | Kdf::PBKDF2 | Kdf::Argon2id => {
+            let kdf = kdf_config(kdf, argon2_memory, argon2_iterations)?;
+
             // always read passphrase from tty
             if !tty::stdin_isatty() {
                 bail!("unable to read passphrase - no tty");
@@ -107,7 +148,7 @@ fn create(kdf: Option<Kdf>, path: Option<String>, hint: Option<String>) -> Resul
 
             let password = tty::read_and_verify_password("Encryption Key Password: ")?;
 
-            let mut key_config = KeyConfig::with_key(&key, &password, kdf)?;
+            let mut key_config = KeyConfig::with_kdf_config(&key, &password, kdf)?;
             key_config.hint = hint;
 
             key_config.store(&path, false)?;
@@ -186,7 +227,7 @@ async fn import_with_master_key(
 
             key_config.store(path, true)?;
         }
-        Kdf::Scrypt | Kdf::PBKDF2 => {
+        Kdf::Scrypt | Kdf::PBKDF2 | Kdf::Argon2id => {
             let password = tty::read_and_verify_password("New Password: ")?;
 
             let mut new_key_config = KeyConfig::with_key(&key, &password, kdf)?;
@@ -207,6 +248,14 @@ async fn import_with_master_key(
                 type: Kdf,
                 optional: true,
             },
+            "argon2-memory": {
+                schema: ARGON2_MEMORY_SCHEMA,
+                optional: true,
+            },
+            "argon2-iterations": {
+                schema: ARGON2_ITERATIONS_SCHEMA,
+                optional: true,
+            },
             path: {
                 description: "Key file. Without this the default key's password will be changed.",
                 optional: true,
@@ -221,6 +270,8 @@ async fn import_with_master_key(
 /// Change the encryption key's password.
 fn change_passphrase(
     kdf: Option<Kdf>,
+    argon2_memory: Option<u32>,
+    argon2_iterations: Option<u32>,
     path: Option<String>,
     hint: Option<String>,
 ) -> Result<(), Error> {
@@ -255,10 +306,12 @@ fn change_passphrase(
 
             key_config.store(&path, true)?;
         }
-        Kdf::Scrypt | Kdf::PBKDF2 => {
+        Kdf::Scrypt | Kdf::PBKDF2 | Kdf::Argon2id => {
+            let kdf = kdf_config(kdf, argon2_memory, argon2_iterations)?;
+
             let password = tty::read_and_verify_password("New Password: ")?;
 
-            let mut new_key_config = KeyConfig::with_key(&key, &password, kdf)?;
+            let mut new_key_config = KeyConfig::with_kdf_config(&key, &password, kdf)?;
             new_key_config.created = created; // keep original value
             new_key_config.hint = hint;
 
@@ -269,6 +322,63 @@ fn change_passphrase(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            kdf: {
+                type: Kdf,
+                optional: true,
+            },
+            "argon2-memory": {
+                schema: ARGON2_MEMORY_SCHEMA,
+                optional: true,
+            },
+            "argon2-iterations": {
+                schema: ARGON2_ITERATIONS_SCHEMA,
+                optional: true,
+            },
+            path: {
+                description: "Key file. Without this the default key will be re-wrapped.",
+                optional: true,
+            },
+        },
+    },
+)]
+/// Re-encrypt the encryption key using another key derivation function (default: argon2id),
+/// keeping its password.
+fn rewrap(
+    kdf: Option<Kdf>,
+    argon2_memory: Option<u32>,
+    argon2_iterations: Option<u32>,
+    path: Option<String>,
+) -> Result<(), Error> {
+    let path = key_path_or_default(path)?;
+
+    let kdf = kdf_config(
+        kdf.unwrap_or(Kdf::Argon2id),
+        argon2_memory,
+        argon2_iterations,
+    )?;
+
+    let key_config = KeyConfig::load(&path)?;
+    if key_config.kdf.is_none() {
+        bail!("key is not password protected, use 'change-passphrase' to set a password");
+    }
+
+    let password = get_encryption_key_password()?;
+    let (key, created, _fingerprint) = key_config.decrypt(&|| Ok(password.clone()))?;
+
+    let mut new_key_config = KeyConfig::with_kdf_config(&key, &password, kdf)?;
+    new_key_config.created = created; // keep original value
+    new_key_config.hint = key_config.hint;
+
+    new_key_config.store(&path, true)?;
+
+    log::info!("re-wrapped key {:?}", path);
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -318,6 +428,191 @@ fn show_key(path: Option<String>, param: Value) -> Result<(), Error> {
     Ok(())
 }
 
+fn key_path_or_default(path: Option<String>) -> Result<PathBuf, Error> {
+    match path {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => find_default_encryption_key()?
+            .ok_or_else(|| format_err!("no encryption file provided and no default file found")),
+    }
+}
+
+// Parse a key in JSON format, or as contained in the text output of 'paperkey'.
+fn parse_key_data(data: &str) -> Result<KeyConfig, Error> {
+    if let Ok(key_config) = serde_json::from_str::<KeyConfig>(data) {
+        return Ok(key_config);
+    }
+
+    const BEGIN_MARKER: &str = "-----BEGIN PROXMOX BACKUP KEY-----";
+    const END_MARKER: &str = "-----END PROXMOX BACKUP KEY-----";
+
+    let start = data
+        .find(BEGIN_MARKER)
+        .ok_or_else(|| format_err!("data is neither a JSON key nor in paper-key format"))?
+        + BEGIN_MARKER.len();
+    let data = &data[start..];
+    let end = data
+        .find(END_MARKER)
+        .ok_or_else(|| format_err!("cannot find key end marker below start marker"))?;
+
+    serde_json::from_str(&data[..end])
+        .map_err(|err| format_err!("unable to parse paper-key data - {}", err))
+}
+
+#[api(
+    input: {
+        properties: {
+            path: {
+                description: "Key file. Without this the default key's fingerprint will be shown.",
+                optional: true,
+            },
+        },
+    },
+)]
+/// Print the encryption key's fingerprint.
+///
+/// Older key files do not store the fingerprint, which requires to decrypt the key.
+fn fingerprint(path: Option<String>) -> Result<(), Error> {
+    let key_config = KeyConfig::load(key_path_or_default(path)?)?;
+
+    let fingerprint = match key_config.fingerprint {
+        Some(ref fingerprint) => fingerprint.clone(),
+        None => key_config.decrypt(&get_encryption_key_password)?.2,
+    };
+
+    println!("{}", fingerprint);
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            path: {
+                description: "Key file. Without this the default key will be exported.",
+                optional: true,
+            },
+        },
+    },
+)]
+/// Print the encryption key in JSON format, for example to import it on another host.
+///
+/// The exported key stays protected by its password, if it has one.
+fn export(path: Option<String>) -> Result<(), Error> {
+    let key_config = KeyConfig::load(key_path_or_default(path)?)?;
+
+    println!("{}", serde_json::to_string_pretty(&key_config)?);
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            file: {
+                description: "Key to import, in JSON or paper-key text format.",
+            },
+            path: {
+                description:
+                    "Output file. Without this the key will become the new default encryption key.",
+                optional: true,
+            },
+        },
+    },
+)]
+/// Import an exported encryption key.
+///
+/// The key is decrypted once to verify the password and fingerprint before it is stored.
+fn import(file: String, path: Option<String>) -> Result<(), Error> {
+    let data = String::from_utf8(file_get_contents(&file)?)?;
+    let key_config = parse_key_data(&data)?;
+
+    let (_key, _created, fingerprint) = key_config.decrypt(&get_encryption_key_password)?;
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = place_default_encryption_key()?;
+            if path.exists() {
+                bail!("Please remove default encryption key at {:?} before importing to default location (or choose a non-default one).", path);
+            }
+            path
+        }
+    };
+
+    key_config.store(&path, false)?;
+
+    log::info!(
+        "Imported key with fingerprint {} to {:?}",
+        fingerprint,
+        path
+    );
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            path: {
+                description: "Key file. Without this the default key will be used.",
+                optional: true,
+            },
+            "master-pubkey-file": {
+                description: "Path to the PEM formatted RSA public master key. Without this the default master key will be used.",
+                optional: true,
+            },
+            output: {
+                description: "Output file for the RSA-encrypted key.",
+            },
+        },
+    },
+)]
+/// Encrypt the encryption key with a (public) master key.
+///
+/// This creates the same RSA-encrypted key file which is uploaded along with each backup if a
+/// master key is configured. It can be restored with 'import-with-master-key'.
+fn export_with_master_key(
+    path: Option<String>,
+    master_pubkey_file: Option<String>,
+    output: String,
+) -> Result<(), Error> {
+    let master_pubkey_file = match master_pubkey_file {
+        Some(file) => PathBuf::from(file),
+        None => find_default_master_pubkey()?.ok_or_else(|| {
+            format_err!("No master key file specified and no default master key available.")
+        })?,
+    };
+
+    let pem_data = file_get_contents(&master_pubkey_file)?;
+    let rsa = openssl::rsa::Rsa::public_key_from_pem(&pem_data)
+        .map_err(|err| format_err!("Unable to decode PEM data - {}", err))?;
+
+    let key_config = KeyConfig::load(key_path_or_default(path)?)?;
+    let (key, created, fingerprint) = key_config.decrypt(&get_encryption_key_password)?;
+
+    let mut key_config = KeyConfig::without_password(key)?;
+    key_config.created = created; // keep original value
+
+    let encrypted_key = rsa_encrypt_key_config(rsa, &key_config)?;
+
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
+    replace_file(
+        &output,
+        &encrypted_key,
+        CreateOptions::new().perm(mode),
+        true,
+    )?;
+
+    log::info!(
+        "Wrote key with fingerprint {} encrypted with master key {:?} to {:?}",
+        fingerprint,
+        master_pubkey_file,
+        output
+    );
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -503,6 +798,10 @@ pub fn cli() -> CliCommandMap {
         .arg_param(&["path"])
         .completion_cb("path", complete_file_name);
 
+    let key_rewrap_cmd_def = CliCommand::new(&API_METHOD_REWRAP)
+        .arg_param(&["path"])
+        .completion_cb("path", complete_file_name);
+
     let key_create_master_key_cmd_def = CliCommand::new(&API_METHOD_CREATE_MASTER_KEY);
     let key_import_master_pubkey_cmd_def = CliCommand::new(&API_METHOD_IMPORT_MASTER_PUBKEY)
         .arg_param(&["path"])
@@ -519,13 +818,37 @@ pub fn cli() -> CliCommandMap {
         .arg_param(&["path"])
         .completion_cb("path", complete_file_name);
 
+    let key_fingerprint_cmd_def = CliCommand::new(&API_METHOD_FINGERPRINT)
+        .arg_param(&["path"])
+        .completion_cb("path", complete_file_name);
+
+    let key_export_cmd_def = CliCommand::new(&API_METHOD_EXPORT)
+        .arg_param(&["path"])
+        .completion_cb("path", complete_file_name);
+
+    let key_import_cmd_def = CliCommand::new(&API_METHOD_IMPORT)
+        .arg_param(&["file", "path"])
+        .completion_cb("file", complete_file_name)
+        .completion_cb("path", complete_file_name);
+
+    let key_export_with_master_key_cmd_def = CliCommand::new(&API_METHOD_EXPORT_WITH_MASTER_KEY)
+        .arg_param(&["path"])
+        .completion_cb("path", complete_file_name)
+        .completion_cb("master-pubkey-file", complete_file_name)
+        .completion_cb("output", complete_file_name);
+
     CliCommandMap::new()
         .insert("create", key_create_cmd_def)
         .insert("import-with-master-key", key_import_with_master_key_cmd_def)
         .insert("create-master-key", key_create_master_key_cmd_def)
         .insert("import-master-pubkey", key_import_master_pubkey_cmd_def)
         .insert("change-passphrase", key_change_passphrase_cmd_def)
+        .insert("rewrap", key_rewrap_cmd_def)
         .insert("show", key_show_cmd_def)
         .insert("show-master-pubkey", key_show_master_pubkey_cmd_def)
         .insert("paperkey", paper_key_cmd_def)
+        .insert("fingerprint", key_fingerprint_cmd_def)
+        .insert("export", key_export_cmd_def)
+        .insert("import", key_import_cmd_def)
+        .insert("export-with-master-key", key_export_with_master_key_cmd_def)
 }
@@ -1,11 +1,12 @@
 use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
-use std::pin::Pin;
 use std::sync::{Arc, Mutex};
-use std::task::Context;
 
 use anyhow::{bail, format_err, Error};
+use futures::future::Either;
 use futures::stream::{StreamExt, TryStreamExt};
 use serde::Deserialize;
 use serde_json::{json, Value};
@@ -20,42 +21,50 @@ use proxmox_router::{cli::*, ApiMethod, RpcEnvironment};
 use proxmox_schema::api;
 use proxmox_sys::fs::{file_get_json, image_size, replace_file, CreateOptions};
 use proxmox_time::{epoch_i64, strftime_local};
-use pxar::accessor::{MaybeReady, ReadAt, ReadAtOperation};
 
 use pbs_api_types::{
-    Authid, BackupDir, BackupGroup, BackupNamespace, BackupPart, BackupType, CryptMode,
-    Fingerprint, GroupListItem, HumanByte, PruneJobOptions, PruneListItem, RateLimitConfig,
-    SnapshotListItem, StorageStatus, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
-    BACKUP_TYPE_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA, TRAFFIC_CONTROL_RATE_SCHEMA,
+    Authid, BackupDir, BackupGroup, BackupNamespace, BackupPart, BackupType, CryptCipher,
+    CryptMode, DeviceNodeFallback, Fingerprint, GroupListItem, HumanByte, PruneJobOptions,
+    PruneListItem, SnapshotListFilter, SnapshotListItem, StorageStatus, BACKUP_ID_SCHEMA,
+    BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA,
+    TRAFFIC_CONTROL_RATE_SCHEMA,
 };
 use pbs_client::catalog_shell::Shell;
+use pbs_client::pxar::ArchiveStatistics;
 use pbs_client::tools::{
-    complete_archive_name, complete_auth_id, complete_backup_group, complete_backup_snapshot,
-    complete_backup_source, complete_chunk_size, complete_group_or_snapshot,
-    complete_img_archive_name, complete_namespace, complete_pxar_archive_name, complete_repository,
-    connect, connect_rate_limited, extract_repository_from_value,
+    chunk_cache_size, complete_archive_name, complete_auth_id, complete_backup_group,
+    complete_backup_snapshot, complete_backup_source, complete_chunk_size,
+    complete_group_or_snapshot, complete_img_archive_name, complete_namespace,
+    complete_pxar_archive_name, complete_repository, connect, connect_rate_limited,
+    extract_repository_from_value,
     key_source::{
-        crypto_parameters, format_key_source, get_encryption_key_password, KEYFD_SCHEMA,
-        KEYFILE_SCHEMA, MASTER_PUBKEY_FD_SCHEMA, MASTER_PUBKEY_FILE_SCHEMA,
+        crypto_parameters, format_key_source, get_encryption_key_password,
+        get_legacy_encryption_key_password, legacy_key_parameter, KEYFD_SCHEMA, KEYFILE_SCHEMA,
+        LEGACY_KEYFILE_SCHEMA, MASTER_PUBKEY_FD_SCHEMA, MASTER_PUBKEY_FILE_SCHEMA,
     },
-    CHUNK_SIZE_SCHEMA, REPO_URL_SCHEMA,
+    rate_limit_from_param, CHUNK_SIZE_SCHEMA, REPO_URL_SCHEMA,
 };
 use pbs_client::{
     delete_ticket_info, parse_backup_specification, view_task_result, BackupReader,
     BackupRepository, BackupSpecificationType, BackupStats, BackupWriter, ChunkStream,
-    FixedChunkStream, HttpClient, PxarBackupStream, RemoteChunkReader, UploadOptions,
-    BACKUP_SOURCE_SCHEMA,
+    FixedChunkStream, HttpClient, PrefetchingChunkReader, PxarBackupStream, RemoteChunkReader,
+    SeekableZstdStream, UploadOptions, BACKUP_SOURCE_SCHEMA,
 };
 use pbs_config::key_config::{decrypt_key, rsa_encrypt_key_config, KeyConfig};
-use pbs_datastore::catalog::{BackupCatalogWriter, CatalogReader, CatalogWriter};
+use pbs_datastore::catalog::{
+    compare_catalog_files, BackupCatalogWriter, CatalogReader, CatalogVersion, CatalogWriter,
+    FileChangeCounts,
+};
 use pbs_datastore::chunk_store::verify_chunk_size;
 use pbs_datastore::dynamic_index::{BufferedDynamicReader, DynamicIndexReader};
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
 use pbs_datastore::manifest::{
-    archive_type, ArchiveType, BackupManifest, ENCRYPTED_KEY_BLOB_NAME, MANIFEST_BLOB_NAME,
+    archive_type, ArchiveType, BackupManifest, PayloadCompression, CLIENT_LOG_BLOB_NAME,
+    CLIENT_LOG_STATISTICS_HEADER, ENCRYPTED_KEY_BLOB_NAME, MANIFEST_BLOB_NAME,
 };
-use pbs_datastore::read_chunk::AsyncReadChunk;
+use pbs_datastore::protocol::ClientLog;
+use pbs_datastore::seekable_zstd::SeekableZstdReader;
 use pbs_datastore::CATALOG_NAME;
 use pbs_tools::crypt_config::CryptConfig;
 use pbs_tools::json;
@@ -133,6 +142,7 @@ async fn api_datastore_list_snapshots(
     store: &str,
     ns: &BackupNamespace,
     group: Option<&BackupGroup>,
+    filter: Option<&SnapshotListFilter>,
 ) -> Result<Value, Error> {
     let path = format!("api2/json/admin/datastore/{}/snapshots", store);
 
@@ -143,6 +153,11 @@ async fn api_datastore_list_snapshots(
     if !ns.is_root() {
         args["ns"] = serde_json::to_value(ns)?;
     }
+    if let Some(filter) = filter {
+        if let Value::Object(filter) = serde_json::to_value(filter)? {
+            args.as_object_mut().unwrap().extend(filter);
+        }
+    }
 
     let mut result = client.get(&path, Some(args)).await?;
 
@@ -155,7 +170,7 @@ pub async fn api_datastore_latest_snapshot(
     ns: &BackupNamespace,
     group: BackupGroup,
 ) -> Result<BackupDir, Error> {
-    let list = api_datastore_list_snapshots(client, store, ns, Some(&group)).await?;
+    let list = api_datastore_list_snapshots(client, store, ns, Some(&group), None).await?;
     let mut list: Vec<SnapshotListItem> = serde_json::from_value(list)?;
 
     if list.is_empty() {
@@ -181,17 +196,30 @@ pub async fn dir_or_last_from_group(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn backup_directory<P: AsRef<Path>>(
     client: &BackupWriter,
     dir_path: P,
     archive_name: &str,
     chunk_size: Option<usize>,
-    catalog: Arc<Mutex<CatalogWriter<TokioWriterAdapter<StdChannelWriter<Error>>>>>,
+    catalog: Arc<Mutex<CatalogWriter<CatalogUploadWriter>>>,
+    feature_flags: pbs_client::pxar::Flags,
     pxar_create_options: pbs_client::pxar::PxarCreateOptions,
     upload_options: UploadOptions,
-) -> Result<BackupStats, Error> {
-    let pxar_stream = PxarBackupStream::open(dir_path.as_ref(), catalog, pxar_create_options)?;
-    let mut chunk_stream = ChunkStream::new(pxar_stream, chunk_size);
+    seekable_zstd: bool,
+) -> Result<(BackupStats, Option<ArchiveStatistics>), Error> {
+    let pxar_stream = PxarBackupStream::open(
+        dir_path.as_ref(),
+        catalog,
+        feature_flags,
+        pxar_create_options,
+    )?;
+    let archive_stats = pxar_stream.statistics();
+    let mut chunk_stream = if seekable_zstd {
+        Either::Left(SeekableZstdStream::new(pxar_stream, chunk_size))
+    } else {
+        Either::Right(ChunkStream::new(pxar_stream, chunk_size))
+    };
 
     let (tx, rx) = mpsc::channel(10); // allow to buffer 10 chunks
 
@@ -212,7 +240,73 @@ async fn backup_directory<P: AsRef<Path>>(
         .upload_stream(archive_name, stream, upload_options)
         .await?;
 
-    Ok(stats)
+    let archive_stats = archive_stats.lock().unwrap().take();
+
+    Ok((stats, archive_stats))
+}
+
+/// Logs the statistics of an archive and appends them to the statistics log.
+fn log_archive_statistics(stats_log: &mut String, name: &str, stats: &ArchiveStatistics) {
+    let line = format!(
+        "{}: {} entries, {} regular files ({})",
+        name,
+        stats.entries,
+        stats.files,
+        HumanByte::from(stats.file_bytes),
+    );
+    log::info!("{}", line);
+    stats_log.push_str(&line);
+    stats_log.push('\n');
+}
+
+/// Logs the number of unchanged, changed and new regular files of the pxar `archives` compared
+/// with the previous snapshot, and appends them to the statistics log.
+fn log_file_changes<P: Read + Seek, C: Read + Seek>(
+    stats_log: &mut String,
+    previous: &mut CatalogReader<P>,
+    current: &mut CatalogReader<C>,
+    archives: &[String],
+) -> Result<(), Error> {
+    let mut log = |name: &str, changes: &FileChangeCounts| {
+        let line = format!(
+            "{}: {} unchanged, {} changed, {} new regular files",
+            name, changes.unchanged, changes.changed, changes.new,
+        );
+        log::info!("{}", line);
+        stats_log.push_str(&line);
+        stats_log.push('\n');
+    };
+
+    let mut total = FileChangeCounts::default();
+    for archive in archives {
+        let changes = compare_catalog_files(previous, current, archive.as_bytes())?;
+        log(archive, &changes);
+        total += &changes;
+    }
+    log("Total", &total);
+
+    Ok(())
+}
+
+/// Downloads the catalog of the previous snapshot.
+async fn download_previous_catalog(
+    repo: &BackupRepository,
+    crypt_config: Option<Arc<CryptConfig>>,
+    ns: &BackupNamespace,
+    snapshot: &BackupDir,
+    manifest: &BackupManifest,
+) -> Result<CatalogReader<std::fs::File>, Error> {
+    let client = connect(repo)?;
+    let client = BackupReader::start(
+        client,
+        crypt_config.clone(),
+        repo.store(),
+        ns,
+        snapshot,
+        true,
+    )
+    .await?;
+    catalog::download_catalog_file(client, crypt_config, manifest).await
 }
 
 async fn backup_image<P: AsRef<Path>>(
@@ -250,6 +344,23 @@ pub fn optional_ns_param(param: &Value) -> Result<BackupNamespace, Error> {
     })
 }
 
+/// Load the legacy encryption key passed with `--legacy-keyfile`, if any.
+pub fn legacy_crypt_config(param: &Value) -> Result<Option<Arc<CryptConfig>>, Error> {
+    let key = match legacy_key_parameter(param)? {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+
+    let (key, _created, fingerprint) = decrypt_key(&key.key, &get_legacy_encryption_key_password)
+        .map_err(|err| {
+        log::error!("{}", format_key_source(&key.source, "legacy encryption"));
+        err
+    })?;
+    log::info!("Legacy encryption key fingerprint: {}", fingerprint);
+
+    Ok(Some(Arc::new(CryptConfig::new(key)?)))
+}
+
 #[api(
    input: {
         properties: {
@@ -515,23 +626,67 @@ async fn start_garbage_collection(param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
+/// Writes the catalog into the upload stream, and optionally a copy into a local file.
+struct CatalogUploadWriter {
+    upload: TokioWriterAdapter<StdChannelWriter<Error>>,
+    copy: Option<std::fs::File>,
+}
+
+impl Write for CatalogUploadWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.upload.write(buf)?;
+        if let Some(copy) = &mut self.copy {
+            copy.write_all(&buf[..written])?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.upload.flush()?;
+        if let Some(copy) = &mut self.copy {
+            copy.flush()?;
+        }
+        Ok(())
+    }
+}
+
 struct CatalogUploadResult {
-    catalog_writer: Arc<Mutex<CatalogWriter<TokioWriterAdapter<StdChannelWriter<Error>>>>>,
+    catalog_writer: Arc<Mutex<CatalogWriter<CatalogUploadWriter>>>,
+    // the local copy of the catalog, if requested
+    copy: Option<std::fs::File>,
     result: tokio::sync::oneshot::Receiver<Result<BackupStats, Error>>,
 }
 
 fn spawn_catalog_upload(
     client: Arc<BackupWriter>,
     encrypt: bool,
+    version: CatalogVersion,
+    keep_copy: bool,
 ) -> Result<CatalogUploadResult, Error> {
     let (catalog_tx, catalog_rx) = std::sync::mpsc::sync_channel(10); // allow to buffer 10 writes
     let catalog_stream = proxmox_async::blocking::StdChannelStream(catalog_rx);
     let catalog_chunk_size = 512 * 1024;
     let catalog_chunk_stream = ChunkStream::new(catalog_stream, Some(catalog_chunk_size));
 
-    let catalog_writer = Arc::new(Mutex::new(CatalogWriter::new(TokioWriterAdapter::new(
-        StdChannelWriter::new(catalog_tx),
-    ))?));
+    let copy = if keep_copy {
+        Some(
+            std::fs::OpenOptions::new()
+                .write(true)
+                .read(true)
+                .custom_flags(libc::O_TMPFILE)
+                .open("/tmp")?,
+        )
+    } else {
+        None
+    };
+
+    let catalog_writer = Arc::new(Mutex::new(CatalogWriter::with_version(
+        CatalogUploadWriter {
+            upload: TokioWriterAdapter::new(StdChannelWriter::new(catalog_tx)),
+            copy: copy.as_ref().map(std::fs::File::try_clone).transpose()?,
+        },
+        version,
+    )?));
 
     let (catalog_result_tx, catalog_result_rx) = tokio::sync::oneshot::channel();
 
@@ -556,6 +711,7 @@ fn spawn_catalog_upload(
 
     Ok(CatalogUploadResult {
         catalog_writer,
+        copy,
         result: catalog_result_rx,
     })
 }
@@ -608,12 +764,34 @@ fn spawn_catalog_upload(
                type: CryptMode,
                optional: true,
            },
+           cipher: {
+               type: CryptCipher,
+               optional: true,
+           },
            "skip-lost-and-found": {
                type: Boolean,
                description: "Skip lost+found directory.",
                optional: true,
                default: false,
            },
+           "no-device-nodes": {
+               type: Boolean,
+               description: "Do not include device nodes in pxar archives.",
+               optional: true,
+               default: false,
+           },
+           "no-fifos": {
+               type: Boolean,
+               description: "Do not include fifos in pxar archives.",
+               optional: true,
+               default: false,
+           },
+           "no-sockets": {
+               type: Boolean,
+               description: "Do not include sockets in pxar archives.",
+               optional: true,
+               default: false,
+           },
            "ns": {
                schema: BACKUP_NAMESPACE_SCHEMA,
                optional: true,
@@ -651,18 +829,57 @@ fn spawn_catalog_upload(
                    description: "Path or match pattern.",
                 }
            },
+           "exclude-larger-than": {
+               type: String,
+               description: "Skip regular files larger than this size (e.g. '10 GiB').",
+               optional: true,
+           },
+           "exclude-older-than": {
+               type: String,
+               description: "Skip regular files not modified within this time span (e.g. '2 years').",
+               optional: true,
+           },
            "entries-max": {
                type: Integer,
                description: "Max number of entries to hold in memory.",
                optional: true,
                default: pbs_client::pxar::ENCODER_MAX_ENTRIES as isize,
            },
+           "upload-window": {
+               type: Integer,
+               description: "Number of chunks uploaded in parallel.",
+               optional: true,
+               minimum: 1,
+               maximum: 64,
+               default: pbs_client::DEFAULT_UPLOAD_WINDOW as isize,
+           },
+           "resume": {
+               type: Boolean,
+               description: "Keep the uploaded chunks if the backup fails, and resume an \
+                   interrupted backup with the same backup time. Requires 'backup-time'.",
+               optional: true,
+               default: false,
+           },
            "dry-run": {
                type: Boolean,
                description: "Just show what backup would do, but do not upload anything.",
                optional: true,
                default: false,
            },
+           "seekable-zstd": {
+               type: Boolean,
+               description: "Compress pxar archives in independent zstd frames per chunk. \
+                   Keeps random access possible, but older clients cannot restore such archives.",
+               optional: true,
+               default: false,
+           },
+           "catalog-content-hash": {
+               type: Boolean,
+               description: "Record ctime and a fast (xxh3) content hash of regular files in the \
+                   catalog (catalog format v2), to allow detecting changed files between snapshots.",
+               optional: true,
+               default: false,
+           },
        }
    }
 )]
@@ -672,6 +889,8 @@ async fn create_backup(
     all_file_systems: bool,
     skip_lost_and_found: bool,
     dry_run: bool,
+    seekable_zstd: bool,
+    catalog_content_hash: bool,
     _info: &ApiMethod,
     _rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Value, Error> {
@@ -681,25 +900,27 @@ async fn create_backup(
 
     let backup_time_opt = param["backup-time"].as_i64();
 
+    let catalog_version = if catalog_content_hash {
+        CatalogVersion::V2
+    } else {
+        CatalogVersion::V1
+    };
+
     let chunk_size_opt = param["chunk-size"].as_u64().map(|v| (v * 1024) as usize);
 
     if let Some(size) = chunk_size_opt {
         verify_chunk_size(size)?;
     }
 
-    let rate = match param["rate"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-    let burst = match param["burst"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-
-    let rate_limit = RateLimitConfig::with_same_inout(rate, burst);
+    let rate_limit = rate_limit_from_param(&param)?;
 
     let crypto = crypto_parameters(&param)?;
 
+    let cipher: CryptCipher = match param.get("cipher") {
+        Some(cipher) => serde::Deserialize::deserialize(cipher)?,
+        None => CryptCipher::default(),
+    };
+
     let backup_id = param["backup-id"]
         .as_str()
         .unwrap_or_else(|| proxmox_sys::nodename());
@@ -714,6 +935,13 @@ async fn create_backup(
         .as_u64()
         .unwrap_or(pbs_client::pxar::ENCODER_MAX_ENTRIES as u64);
 
+    let upload_window = param["upload-window"].as_u64().map(|n| n as usize);
+
+    let resume = param["resume"].as_bool().unwrap_or(false);
+    if resume && backup_time_opt.is_none() {
+        bail!("resuming a backup requires to specify its 'backup-time'");
+    }
+
     let empty = Vec::new();
     let exclude_args = param["exclude"].as_array().unwrap_or(&empty);
 
@@ -728,6 +956,40 @@ async fn create_backup(
         );
     }
 
+    let max_file_size = match param["exclude-larger-than"].as_str() {
+        Some(s) => Some(
+            s.parse::<HumanByte>()
+                .map_err(|err| format_err!("invalid size for 'exclude-larger-than': {}", err))?
+                .as_u64(),
+        ),
+        None => None,
+    };
+
+    let min_mtime = match param["exclude-older-than"].as_str() {
+        Some(s) => {
+            let span: proxmox_time::TimeSpan = s.parse().map_err(|err| {
+                format_err!("invalid time span for 'exclude-older-than': {}", err)
+            })?;
+            let reference = backup_time_opt.unwrap_or_else(proxmox_time::epoch_i64);
+            Some(reference - f64::from(span) as i64)
+        }
+        None => None,
+    };
+
+    let mut feature_flags = pbs_client::pxar::Flags::DEFAULT;
+    for (name, flag) in [
+        (
+            "no-device-nodes",
+            pbs_client::pxar::Flags::WITH_DEVICE_NODES,
+        ),
+        ("no-fifos", pbs_client::pxar::Flags::WITH_FIFOS),
+        ("no-sockets", pbs_client::pxar::Flags::WITH_SOCKETS),
+    ] {
+        if param[name].as_bool().unwrap_or(false) {
+            feature_flags.remove(flag);
+        }
+    }
+
     let mut devices = if all_file_systems {
         None
     } else {
@@ -856,7 +1118,7 @@ async fn create_backup(
                 decrypt_key(&key_with_source.key, &get_encryption_key_password)?;
             log::info!("Encryption key fingerprint: {}", fingerprint);
 
-            let crypt_config = CryptConfig::new(key)?;
+            let crypt_config = CryptConfig::new(key)?.with_cipher(cipher);
 
             match crypto.master_pubkey {
                 Some(pem_with_source) => {
@@ -884,10 +1146,20 @@ async fn create_backup(
         &snapshot,
         true,
         false,
+        resume,
     )
     .await?;
 
-    let download_previous_manifest = match client.previous_backup_time().await {
+    if seekable_zstd
+        && !client
+            .payload_compression()
+            .contains(&PayloadCompression::SeekableZstd)
+    {
+        bail!("the server does not support seekable zstd compressed archives");
+    }
+
+    let previous_backup_time = client.previous_backup_time().await;
+    let download_previous_manifest = match previous_backup_time {
         Ok(Some(backup_time)) => {
             log::info!(
                 "Downloading previous manifest ({})",
@@ -925,10 +1197,43 @@ async fn create_backup(
         None
     };
 
+    // the catalog of the previous snapshot tells which files changed since
+    let has_pxar = upload_list
+        .iter()
+        .any(|(ty, ..)| matches!(ty, BackupSpecificationType::PXAR));
+    let mut previous_catalog = match (&previous_manifest, previous_backup_time) {
+        (Some(previous_manifest), Ok(Some(previous_time)))
+            if has_pxar && !dry_run && previous_manifest.lookup_file_info(CATALOG_NAME).is_ok() =>
+        {
+            let previous_snapshot =
+                BackupDir::from((backup_type, backup_id.to_owned(), previous_time));
+            match download_previous_catalog(
+                &repo,
+                crypt_config.clone(),
+                &backup_ns,
+                &previous_snapshot,
+                previous_manifest,
+            )
+            .await
+            {
+                Ok(previous_catalog) => Some(previous_catalog),
+                Err(err) => {
+                    log::warn!("Couldn't download previous catalog - {}", err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
     let mut manifest = BackupManifest::new(snapshot);
+    let mut file_stats = ArchiveStatistics::default();
+    let mut stats_log = format!("{}\n", CLIENT_LOG_STATISTICS_HEADER);
 
     let mut catalog = None;
+    let mut catalog_copy = None;
     let mut catalog_result_rx = None;
+    let mut pxar_archives = Vec::new();
 
     let log_file = |desc: &str, file: &str, target: &str| {
         let what = if dry_run { "Would upload" } else { "Upload" };
@@ -973,9 +1278,14 @@ async fn create_backup(
             (BackupSpecificationType::PXAR, false) => {
                 // start catalog upload on first use
                 if catalog.is_none() {
-                    let catalog_upload_res =
-                        spawn_catalog_upload(client.clone(), crypto.mode == CryptMode::Encrypt)?;
+                    let catalog_upload_res = spawn_catalog_upload(
+                        client.clone(),
+                        crypto.mode == CryptMode::Encrypt,
+                        catalog_version,
+                        previous_catalog.is_some(),
+                    )?;
                     catalog = Some(catalog_upload_res.catalog_writer);
+                    catalog_copy = catalog_upload_res.copy;
                     catalog_result_rx = Some(catalog_upload_res.result);
                 }
                 let catalog = catalog.as_ref().unwrap();
@@ -991,27 +1301,42 @@ async fn create_backup(
                     patterns: pattern_list.clone(),
                     entries_max: entries_max as usize,
                     skip_lost_and_found,
+                    catalog_content_hash,
+                    max_file_size,
+                    min_mtime,
                 };
 
                 let upload_options = UploadOptions {
                     previous_manifest: previous_manifest.clone(),
-                    compress: true,
+                    // no need to compress chunks again if the payload is compressed already
+                    compress: !seekable_zstd,
                     encrypt: crypto.mode == CryptMode::Encrypt,
+                    parallel: upload_window,
                     ..UploadOptions::default()
                 };
 
-                let stats = backup_directory(
+                let (stats, archive_stats) = backup_directory(
                     &client,
                     &filename,
                     &target,
                     chunk_size_opt,
                     catalog.clone(),
+                    feature_flags,
                     pxar_options,
                     upload_options,
+                    seekable_zstd,
                 )
                 .await?;
-                manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
+                if let Some(archive_stats) = archive_stats {
+                    log_archive_statistics(&mut stats_log, &target, &archive_stats);
+                    file_stats += &archive_stats;
+                }
+                manifest.add_file(target.clone(), stats.size, stats.csum, crypto.mode)?;
+                if seekable_zstd {
+                    manifest.set_payload_compression(&target, PayloadCompression::SeekableZstd)?;
+                }
                 catalog.lock().unwrap().end_directory()?;
+                pxar_archives.push(target);
             }
             (BackupSpecificationType::IMAGE, false) => {
                 log_file("image", &filename, &target);
@@ -1021,6 +1346,7 @@ async fn create_backup(
                     fixed_size: Some(size),
                     compress: true,
                     encrypt: crypto.mode == CryptMode::Encrypt,
+                    parallel: upload_window,
                 };
 
                 let stats =
@@ -1052,6 +1378,40 @@ async fn create_backup(
         }
     }
 
+    if file_stats.entries > 0 {
+        log_archive_statistics(&mut stats_log, "Total", &file_stats);
+
+        if let (Some(previous_catalog), Some(mut catalog_copy)) =
+            (previous_catalog.as_mut(), catalog_copy)
+        {
+            catalog_copy.seek(SeekFrom::Start(0))?;
+            let mut current_catalog = CatalogReader::new(catalog_copy);
+            if let Err(err) = log_file_changes(
+                &mut stats_log,
+                previous_catalog,
+                &mut current_catalog,
+                &pxar_archives,
+            ) {
+                log::warn!("Couldn't compare catalog with previous snapshot - {}", err);
+            }
+        }
+
+        // The server merges a log uploaded after the backup into this one. It cannot do that
+        // for encrypted logs, so encrypted backups only log the statistics locally. Older
+        // servers refuse the log uploaded after the backup instead.
+        if crypto.mode != CryptMode::Encrypt && client.client_log() == Some(ClientLog::Statistics) {
+            log::debug!("Upload archive statistics to '{}'", repo);
+            let options = UploadOptions {
+                compress: true,
+                encrypt: false,
+                ..UploadOptions::default()
+            };
+            client
+                .upload_blob_from_data(stats_log.into_bytes(), CLIENT_LOG_BLOB_NAME, options)
+                .await?;
+        }
+    }
+
     if let Some(rsa_encrypted_key) = rsa_encrypted_key {
         let target = ENCRYPTED_KEY_BLOB_NAME;
         log::info!("Upload RSA encoded key to '{:?}' as {}", repo, target);
@@ -1091,16 +1451,23 @@ async fn create_backup(
     Ok(Value::Null)
 }
 
+/// Default number of chunks downloaded in parallel on restore
+const DEFAULT_FETCH_WINDOW: usize = 8;
+
 async fn dump_image<W: Write>(
     client: Arc<BackupReader>,
     crypt_config: Option<Arc<CryptConfig>>,
     crypt_mode: CryptMode,
     index: FixedIndexReader,
     mut writer: W,
+    fetch_window: usize,
 ) -> Result<(), Error> {
-    let most_used = index.find_most_used_chunks(8);
-
-    let chunk_reader = RemoteChunkReader::new(client.clone(), crypt_config, crypt_mode, most_used);
+    let chunk_reader = RemoteChunkReader::new(
+        client.clone(),
+        crypt_config,
+        crypt_mode,
+        chunk_cache_size()?,
+    );
 
     // Note: we avoid using BufferedFixedReader, because that add an additional buffer/copy
     // and thus slows down reading. Instead, directly use RemoteChunkReader
@@ -1108,9 +1475,13 @@ async fn dump_image<W: Write>(
     let mut bytes = 0;
     let start_time = std::time::Instant::now();
 
-    for pos in 0..index.index_count() {
-        let digest = index.index_digest(pos).unwrap();
-        let raw_data = chunk_reader.read_chunk(digest).await?;
+    let digests = (0..index.index_count())
+        .map(|pos| *index.index_digest(pos).unwrap())
+        .collect();
+    let mut chunks = chunk_reader.read_chunks_ordered(digests, fetch_window);
+
+    let mut pos = 0;
+    while let Some(raw_data) = chunks.try_next().await? {
         writer.write_all(&raw_data)?;
         bytes += raw_data.len();
         let next_per = ((pos + 1) * 100) / index.index_count();
@@ -1123,6 +1494,7 @@ async fn dump_image<W: Write>(
             );
             per = next_per;
         }
+        pos += 1;
     }
 
     let end_time = std::time::Instant::now();
@@ -1134,6 +1506,13 @@ async fn dump_image<W: Write>(
         bytes as f64 / (1024.0 * 1024.0 * elapsed.as_secs_f64())
     );
 
+    let cache_stats = chunk_reader.cache_stats();
+    log::debug!(
+        "chunk cache: {} hits, {} misses",
+        cache_stats.hits,
+        cache_stats.misses
+    );
+
     Ok(())
 }
 
@@ -1198,6 +1577,10 @@ We do not extract '.pxar' archives when writing to standard output.
                 schema: KEYFD_SCHEMA,
                 optional: true,
             },
+            "legacy-keyfile": {
+                schema: LEGACY_KEYFILE_SCHEMA,
+                optional: true,
+            },
             "crypt-mode": {
                 type: CryptMode,
                 optional: true,
@@ -1226,16 +1609,118 @@ We do not extract '.pxar' archives when writing to standard output.
                 optional: true,
                 default: false,
             },
+            "ignore-fcaps": {
+                type: Boolean,
+                description: "ignore file capabilities",
+                optional: true,
+                default: false,
+            },
+            "ignore-chattr": {
+                type: Boolean,
+                description: "ignore file attribute flags (no chattr)",
+                optional: true,
+                default: false,
+            },
+            "ignore-device-nodes": {
+                type: Boolean,
+                description: "do not restore device nodes",
+                optional: true,
+                default: false,
+            },
+            "ignore-fifos": {
+                type: Boolean,
+                description: "do not restore fifos",
+                optional: true,
+                default: false,
+            },
+            "ignore-sockets": {
+                type: Boolean,
+                description: "do not restore sockets",
+                optional: true,
+                default: false,
+            },
+            "device-node-fallback": {
+                type: DeviceNodeFallback,
+                optional: true,
+            },
+            "include": {
+                type: Array,
+                description: "List of paths or match patterns for files to restore, \
+                    restores everything if not given.",
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Path or match pattern.",
+                },
+            },
+            "exclude": {
+                type: Array,
+                description: "List of paths or match patterns for files to skip on restore, \
+                    takes precedence over 'include'.",
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Path or match pattern.",
+                },
+            },
+            "uid-offset": {
+                type: Integer,
+                description: "Add this offset to the user IDs of restored files.",
+                optional: true,
+                minimum: 0,
+            },
+            "gid-offset": {
+                type: Integer,
+                description: "Add this offset to the group IDs of restored files.",
+                optional: true,
+                minimum: 0,
+            },
+            "map-user": {
+                type: Array,
+                description: "Restore files of a user ID with a different one, takes precedence \
+                    over 'uid-offset'.",
+                optional: true,
+                items: {
+                    type: String,
+                    description: "User ID mapping in the form 'old=new'.",
+                },
+            },
+            "map-group": {
+                type: Array,
+                description: "Restore files of a group ID with a different one, takes precedence \
+                    over 'gid-offset'.",
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Group ID mapping in the form 'old=new'.",
+                },
+            },
+            "ignore-metadata-errors": {
+                type: Boolean,
+                description: "only warn about metadata (owner, permissions, xattrs, ...) which \
+                    could not be restored, instead of failing",
+                optional: true,
+                default: false,
+            },
             "overwrite": {
                 type: Boolean,
                 description: "overwrite already existing files",
                 optional: true,
                 default: false,
             },
+            "fetch-window": {
+                type: Integer,
+                description: "Number of chunks downloaded in parallel.",
+                optional: true,
+                minimum: 1,
+                maximum: 64,
+                default: DEFAULT_FETCH_WINDOW as isize,
+            },
         }
     }
 )]
 /// Restore backup repository.
+#[allow(clippy::too_many_arguments)]
 async fn restore(
     param: Value,
     allow_existing_dirs: bool,
@@ -1243,22 +1728,19 @@ async fn restore(
     ignore_xattrs: bool,
     ignore_ownership: bool,
     ignore_permissions: bool,
+    ignore_fcaps: bool,
+    ignore_chattr: bool,
+    ignore_metadata_errors: bool,
     overwrite: bool,
+    fetch_window: Option<usize>,
 ) -> Result<Value, Error> {
     let repo = extract_repository_from_value(&param)?;
 
-    let archive_name = json::required_string_param(&param, "archive-name")?;
+    let fetch_window = fetch_window.unwrap_or(DEFAULT_FETCH_WINDOW);
 
-    let rate = match param["rate"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-    let burst = match param["burst"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
+    let archive_name = json::required_string_param(&param, "archive-name")?;
 
-    let rate_limit = RateLimitConfig::with_same_inout(rate, burst);
+    let rate_limit = rate_limit_from_param(&param)?;
 
     let client = connect_rate_limited(&repo, rate_limit)?;
     record_repository(&repo);
@@ -1285,9 +1767,12 @@ async fn restore(
         }
     };
 
-    let client = BackupReader::start(
+    let legacy_crypt_config = legacy_crypt_config(&param)?;
+
+    let (client, crypt_config) = BackupReader::start_with_legacy_key(
         client,
-        crypt_config.clone(),
+        crypt_config,
+        legacy_crypt_config,
         repo.store(),
         &ns,
         &backup_dir,
@@ -1353,23 +1838,94 @@ async fn restore(
             .download_dynamic_index(&manifest, &archive_name)
             .await?;
 
-        let most_used = index.find_most_used_chunks(8);
-
         let chunk_reader = RemoteChunkReader::new(
             client.clone(),
             crypt_config,
             file_info.chunk_crypt_mode(),
-            most_used,
+            chunk_cache_size()?,
         );
 
-        let mut reader = BufferedDynamicReader::new(index, chunk_reader);
+        let mut reader: Box<dyn Read + Send> = match file_info.payload_compression {
+            Some(PayloadCompression::SeekableZstd) => {
+                // needs to seek, so read the chunks on demand
+                let reader = BufferedDynamicReader::new(index, chunk_reader);
+                Box::new(SeekableZstdReader::new(reader)?)
+            }
+            None => {
+                let digests = (0..index.index_count())
+                    .map(|pos| *index.index_digest(pos).unwrap())
+                    .collect();
+                Box::new(PrefetchingChunkReader::new(
+                    &chunk_reader,
+                    digests,
+                    fetch_window,
+                ))
+            }
+        };
+
+        let on_error = if ignore_metadata_errors {
+            Some(Box::new(|err| {
+                log::warn!("warning: {}", err);
+                Ok(())
+            }) as pbs_client::pxar::ErrorHandler)
+        } else {
+            None
+        };
+
+        let mut match_list = Vec::new();
+        for (name, match_type) in [
+            ("include", MatchType::Include),
+            ("exclude", MatchType::Exclude),
+        ] {
+            if let Some(patterns) = param[name].as_array() {
+                for entry in patterns {
+                    let entry = entry
+                        .as_str()
+                        .ok_or_else(|| format_err!("Invalid pattern string slice"))?;
+                    match_list.push(
+                        MatchEntry::parse_pattern(entry, PatternFlag::PATH_NAME, match_type)
+                            .map_err(|err| {
+                                format_err!("invalid {} pattern entry: {}", name, err)
+                            })?,
+                    );
+                }
+            }
+        }
+
+        // restore everything unless there are explicit include patterns
+        let extract_match_default = param["include"].as_array().map_or(true, Vec::is_empty);
+
+        let mut id_mapping = pbs_client::pxar::IdMapping {
+            uid_offset: u32::try_from(param["uid-offset"].as_u64().unwrap_or(0))?,
+            gid_offset: u32::try_from(param["gid-offset"].as_u64().unwrap_or(0))?,
+            ..Default::default()
+        };
+        for (name, map) in [
+            ("map-user", &mut id_mapping.users),
+            ("map-group", &mut id_mapping.groups),
+        ] {
+            if let Some(entries) = param[name].as_array() {
+                for entry in entries {
+                    let entry = entry
+                        .as_str()
+                        .ok_or_else(|| format_err!("invalid {} entry", name))?;
+                    let (old, new) = pbs_client::pxar::IdMapping::parse_entry(entry)?;
+                    map.insert(old, new);
+                }
+            }
+        }
 
         let options = pbs_client::pxar::PxarExtractOptions {
-            match_list: &[],
-            extract_match_default: true,
+            match_list: &match_list,
+            extract_match_default,
             allow_existing_dirs,
             overwrite,
-            on_error: None,
+            on_error,
+            id_mapping: Some(id_mapping),
+            device_node_fallback: match param.get("device-node-fallback") {
+                Some(fallback) => serde::Deserialize::deserialize(fallback)?,
+                None => DeviceNodeFallback::default(),
+            },
         };
 
         let mut feature_flags = pbs_client::pxar::Flags::DEFAULT;
@@ -1386,6 +1942,24 @@ async fn restore(
         if ignore_permissions {
             feature_flags.remove(pbs_client::pxar::Flags::WITH_PERMISSIONS);
         }
+        if ignore_fcaps {
+            feature_flags.remove(pbs_client::pxar::Flags::WITH_FCAPS);
+        }
+        if ignore_chattr {
+            feature_flags.remove(pbs_client::pxar::Flags::WITH_CHATTR);
+        }
+        for (name, flag) in [
+            (
+                "ignore-device-nodes",
+                pbs_client::pxar::Flags::WITH_DEVICE_NODES,
+            ),
+            ("ignore-fifos", pbs_client::pxar::Flags::WITH_FIFOS),
+            ("ignore-sockets", pbs_client::pxar::Flags::WITH_SOCKETS),
+        ] {
+            if param[name].as_bool().unwrap_or(false) {
+                feature_flags.remove(flag);
+            }
+        }
 
         if let Some(target) = target {
             pbs_client::pxar::extract_archive(
@@ -1432,6 +2006,7 @@ async fn restore(
             file_info.chunk_crypt_mode(),
             index,
             &mut writer,
+            fetch_window,
         )
         .await?;
     }
@@ -1608,46 +2183,6 @@ async fn status(param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
-/// This is a workaround until we have cleaned up the chunk/reader/... infrastructure for better
-/// async use!
-///
-/// Ideally BufferedDynamicReader gets replaced so the LruCache maps to `BroadcastFuture<Chunk>`,
-/// so that we can properly access it from multiple threads simultaneously while not issuing
-/// duplicate simultaneous reads over http.
-pub struct BufferedDynamicReadAt {
-    inner: Mutex<BufferedDynamicReader<RemoteChunkReader>>,
-}
-
-impl BufferedDynamicReadAt {
-    fn new(inner: BufferedDynamicReader<RemoteChunkReader>) -> Self {
-        Self {
-            inner: Mutex::new(inner),
-        }
-    }
-}
-
-impl ReadAt for BufferedDynamicReadAt {
-    fn start_read_at<'a>(
-        self: Pin<&'a Self>,
-        _cx: &mut Context,
-        buf: &'a mut [u8],
-        offset: u64,
-    ) -> MaybeReady<io::Result<usize>, ReadAtOperation<'a>> {
-        MaybeReady::Ready(tokio::task::block_in_place(move || {
-            let mut reader = self.inner.lock().unwrap();
-            reader.seek(SeekFrom::Start(offset))?;
-            reader.read(buf)
-        }))
-    }
-
-    fn poll_complete<'a>(
-        self: Pin<&'a Self>,
-        _op: ReadAtOperation<'a>,
-    ) -> MaybeReady<io::Result<usize>, ReadAtOperation<'a>> {
-        panic!("BufferedDynamicReadAt::start_read_at returned Pending");
-    }
-}
-
 fn main() {
     pbs_tools::setup_libc_malloc_opts();
     init_cli_logger("PBS_LOG", "info");
@@ -18,20 +18,22 @@ use proxmox_schema::*;
 use proxmox_sys::sortable;
 
 use pbs_api_types::BackupNamespace;
+use pbs_client::tools::chunk_cache_size;
 use pbs_client::tools::key_source::get_encryption_key_password;
 use pbs_client::{BackupReader, RemoteChunkReader};
 use pbs_config::key_config::load_and_decrypt_key;
 use pbs_datastore::cached_chunk_reader::CachedChunkReader;
-use pbs_datastore::dynamic_index::BufferedDynamicReader;
+use pbs_datastore::dynamic_index::{pxar_read_at, BufferedDynamicReader};
 use pbs_datastore::index::IndexFile;
 use pbs_tools::crypt_config::CryptConfig;
 use pbs_tools::json::required_string_param;
 
 use crate::{
     complete_group_or_snapshot, complete_img_archive_name, complete_namespace,
-    complete_pxar_archive_name, complete_repository, connect, dir_or_last_from_group,
-    extract_repository_from_value, optional_ns_param, record_repository, BufferedDynamicReadAt,
-    REPO_URL_SCHEMA,
+    complete_pxar_archive_name, complete_repository, connect_rate_limited, dir_or_last_from_group,
+    extract_repository_from_value, legacy_crypt_config, optional_ns_param, rate_limit_from_param,
+    record_repository, LEGACY_KEYFILE_SCHEMA, REPO_URL_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA,
+    TRAFFIC_CONTROL_RATE_SCHEMA,
 };
 
 #[sortable]
@@ -51,17 +53,20 @@ const API_METHOD_MOUNT: ApiMethod = ApiMethod::new(
                 false,
                 &StringSchema::new("Backup archive name.").schema()
             ),
+            ("burst", true, &TRAFFIC_CONTROL_BURST_SCHEMA),
             (
                 "target",
                 false,
                 &StringSchema::new("Target directory path.").schema()
             ),
+            ("rate", true, &TRAFFIC_CONTROL_RATE_SCHEMA),
             ("repository", true, &REPO_URL_SCHEMA),
             (
                 "keyfile",
                 true,
                 &StringSchema::new("Path to encryption key.").schema()
             ),
+            ("legacy-keyfile", true, &LEGACY_KEYFILE_SCHEMA),
             (
                 "verbose",
                 true,
@@ -91,12 +96,15 @@ WARNING: Only do this with *trusted* backups!",
                 false,
                 &StringSchema::new("Backup archive name.").schema()
             ),
+            ("burst", true, &TRAFFIC_CONTROL_BURST_SCHEMA),
+            ("rate", true, &TRAFFIC_CONTROL_RATE_SCHEMA),
             ("repository", true, &REPO_URL_SCHEMA),
             (
                 "keyfile",
                 true,
                 &StringSchema::new("Path to encryption key.").schema()
             ),
+            ("legacy-keyfile", true, &LEGACY_KEYFILE_SCHEMA),
             (
                 "verbose",
                 true,
@@ -198,7 +206,7 @@ fn mount(
 async fn mount_do(param: Value, pipe: Option<OwnedFd>) -> Result<Value, Error> {
     let repo = extract_repository_from_value(&param)?;
     let archive_name = required_string_param(&param, "archive-name")?;
-    let client = connect(&repo)?;
+    let client = connect_rate_limited(&repo, rate_limit_from_param(&param)?)?;
 
     let target = param["target"].as_str();
 
@@ -233,9 +241,12 @@ async fn mount_do(param: Value, pipe: Option<OwnedFd>) -> Result<Value, Error> {
         bail!("Can only mount/map pxar archives and drive images.");
     };
 
-    let client = BackupReader::start(
+    let legacy_crypt_config = legacy_crypt_config(&param)?;
+
+    let (client, crypt_config) = BackupReader::start_with_legacy_key(
         client,
-        crypt_config.clone(),
+        crypt_config,
+        legacy_crypt_config,
         repo.store(),
         &backup_ns,
         &backup_dir,
@@ -286,16 +297,14 @@ async fn mount_do(param: Value, pipe: Option<OwnedFd>) -> Result<Value, Error> {
         let index = client
             .download_dynamic_index(&manifest, &server_archive_name)
             .await?;
-        let most_used = index.find_most_used_chunks(8);
         let chunk_reader = RemoteChunkReader::new(
             client.clone(),
             crypt_config,
             file_info.chunk_crypt_mode(),
-            most_used,
+            chunk_cache_size()?,
         );
         let reader = BufferedDynamicReader::new(index, chunk_reader);
-        let archive_size = reader.archive_size();
-        let reader: pbs_client::pxar::fuse::Reader = Arc::new(BufferedDynamicReadAt::new(reader));
+        let (reader, archive_size) = pxar_read_at(reader, file_info.payload_compression)?;
         let decoder = pbs_client::pxar::fuse::Accessor::new(reader, archive_size).await?;
 
         let session = pbs_client::pxar::fuse::Session::mount(
@@ -323,7 +332,7 @@ async fn mount_do(param: Value, pipe: Option<OwnedFd>) -> Result<Value, Error> {
             client.clone(),
             crypt_config,
             file_info.chunk_crypt_mode(),
-            HashMap::new(),
+            chunk_cache_size()?,
         );
         let reader = CachedChunkReader::new(chunk_reader, index, 8).seekable();
 
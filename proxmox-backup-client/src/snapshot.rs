@@ -7,7 +7,10 @@ use proxmox_router::cli::*;
 use proxmox_schema::api;
 use proxmox_sys::fs::file_get_contents;
 
-use pbs_api_types::{BackupGroup, BackupNamespace, CryptMode, SnapshotListItem};
+use pbs_api_types::{
+    BackupGroup, BackupNamespace, CryptMode, SnapshotListFilter, SnapshotListItem,
+    SNAPSHOT_LABEL_SCHEMA,
+};
 use pbs_client::tools::key_source::get_encryption_key_password;
 use pbs_config::key_config::decrypt_key;
 use pbs_datastore::DataBlob;
@@ -45,6 +48,10 @@ fn snapshot_args(ns: &BackupNamespace, snapshot: &BackupDir) -> Result<Value, Er
                 description: "Backup group.",
                 optional: true,
             },
+            filter: {
+                type: SnapshotListFilter,
+                flatten: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -67,8 +74,16 @@ async fn list_snapshots(param: Value) -> Result<Value, Error> {
 
     let backup_ns = optional_ns_param(&param)?;
 
-    let mut data =
-        api_datastore_list_snapshots(&client, repo.store(), &backup_ns, group.as_ref()).await?;
+    let filter: SnapshotListFilter = serde_json::from_value(param.clone())?;
+
+    let mut data = api_datastore_list_snapshots(
+        &client,
+        repo.store(),
+        &backup_ns,
+        group.as_ref(),
+        Some(&filter),
+    )
+    .await?;
 
     record_repository(&repo);
 
@@ -377,6 +392,50 @@ async fn update_notes(param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            repository: {
+                schema: REPO_URL_SCHEMA,
+                optional: true,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            snapshot: {
+                type: String,
+                description: "Snapshot path.",
+            },
+            label: {
+                type: Array,
+                description: "Labels to set, replacing the values of existing keys.",
+                items: {
+                    schema: SNAPSHOT_LABEL_SCHEMA,
+                },
+            },
+        }
+    }
+)]
+/// Update the labels of a snapshot, an empty value removes a label
+async fn update_labels(label: Vec<String>, param: Value) -> Result<Value, Error> {
+    let repo = extract_repository_from_value(&param)?;
+    let path = required_string_param(&param, "snapshot")?;
+
+    let backup_ns = optional_ns_param(&param)?;
+    let snapshot: BackupDir = path.parse()?;
+    let client = connect(&repo)?;
+
+    let path = format!("api2/json/admin/datastore/{}/notes", repo.store());
+
+    let mut args = snapshot_args(&backup_ns, &snapshot)?;
+    args["labels"] = json!(label);
+
+    client.put(&path, Some(args)).await?;
+
+    Ok(Value::Null)
+}
+
 #[api(
     input: {
         properties: {
@@ -509,6 +568,13 @@ fn notes_cli() -> CliCommandMap {
                 .completion_cb("ns", complete_namespace)
                 .completion_cb("snapshot", complete_backup_snapshot),
         )
+        .insert(
+            "labels",
+            CliCommand::new(&API_METHOD_UPDATE_LABELS)
+                .arg_param(&["snapshot"])
+                .completion_cb("ns", complete_namespace)
+                .completion_cb("snapshot", complete_backup_snapshot),
+        )
 }
 
 pub fn snapshot_mgtm_cli() -> CliCommandMap {
@@ -5,7 +5,7 @@ use proxmox_router::cli::*;
 use proxmox_schema::api;
 
 use pbs_api_types::percent_encoding::percent_encode_component;
-use pbs_client::display_task_log;
+use pbs_client::display_task_log_full;
 use pbs_tools::json::required_string_param;
 
 use pbs_api_types::UPID;
@@ -94,6 +94,12 @@ async fn task_list(param: Value) -> Result<Value, Error> {
             upid: {
                 type: UPID,
             },
+            "no-follow": {
+                description: "Do not wait for new lines of a running task.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
         }
     }
 )]
@@ -101,10 +107,11 @@ async fn task_list(param: Value) -> Result<Value, Error> {
 async fn task_log(param: Value) -> Result<Value, Error> {
     let repo = extract_repository_from_value(&param)?;
     let upid = required_string_param(&param, "upid")?;
+    let follow = !param["no-follow"].as_bool().unwrap_or(false);
 
     let client = connect(&repo)?;
 
-    display_task_log(&client, upid, true).await?;
+    display_task_log_full(&client, upid, true, follow).await?;
 
     Ok(Value::Null)
 }
@@ -20,21 +20,26 @@ use proxmox_sys::fs::{create_path, CreateOptions};
 use pxar::accessor::aio::Accessor;
 use pxar::decoder::aio::Decoder;
 
-use pbs_api_types::{file_restore::FileRestoreFormat, BackupDir, BackupNamespace, CryptMode};
+use pbs_api_types::{
+    file_restore::FileRestoreFormat, BackupDir, BackupNamespace, CryptMode,
+    TRAFFIC_CONTROL_BURST_SCHEMA, TRAFFIC_CONTROL_RATE_SCHEMA,
+};
 use pbs_client::pxar::{create_tar, create_zip, extract_sub_dir, extract_sub_dir_seq};
 use pbs_client::tools::{
-    complete_group_or_snapshot, complete_repository, connect, extract_repository_from_value,
+    chunk_cache_size, complete_group_or_snapshot, complete_repository, connect,
+    connect_rate_limited, extract_repository_from_value,
     key_source::{
-        crypto_parameters_keep_fd, format_key_source, get_encryption_key_password, KEYFD_SCHEMA,
-        KEYFILE_SCHEMA,
+        crypto_parameters_keep_fd, format_key_source, get_encryption_key_password,
+        get_legacy_encryption_key_password, legacy_key_parameter, KEYFD_SCHEMA, KEYFILE_SCHEMA,
+        LEGACY_KEYFILE_SCHEMA,
     },
-    REPO_URL_SCHEMA,
+    rate_limit_from_param, REPO_URL_SCHEMA,
 };
-use pbs_client::{BackupReader, BackupRepository, RemoteChunkReader};
+use pbs_client::{BackupReader, BackupRepository, HttpClient, RemoteChunkReader};
 use pbs_config::key_config::decrypt_key;
+use pbs_datastore::cached_chunk_reader::CachedChunkReader;
 use pbs_datastore::catalog::{ArchiveEntry, CatalogReader, DirEntryAttribute};
-use pbs_datastore::dynamic_index::{BufferedDynamicReader, LocalDynamicReadAt};
-use pbs_datastore::index::IndexFile;
+use pbs_datastore::dynamic_index::{pxar_read_at, BufferedDynamicReader};
 use pbs_datastore::CATALOG_NAME;
 use pbs_tools::crypt_config::CryptConfig;
 
@@ -96,6 +101,59 @@ fn keyfile_path(param: &Value) -> Option<String> {
     None
 }
 
+/// Load the key passed with `--legacy-keyfile`, together with its path for the restore VM.
+fn legacy_key(param: &Value) -> Result<Option<(Arc<CryptConfig>, String)>, Error> {
+    let key = match legacy_key_parameter(param)? {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+    // checked by legacy_key_parameter
+    let path = param["legacy-keyfile"].as_str().unwrap().to_owned();
+
+    let (key, _, _) =
+        decrypt_key(&key.key, &get_legacy_encryption_key_password).map_err(|err| {
+            log::error!("{}", format_key_source(&key.source, "legacy encryption"));
+            err
+        })?;
+
+    Ok(Some((Arc::new(CryptConfig::new(key)?), path)))
+}
+
+/// Start a reader, using the legacy key if the snapshot was encrypted with it. Returns the
+/// reader, the selected key and the path of the selected key file.
+async fn start_reader(
+    client: HttpClient,
+    repo: &BackupRepository,
+    namespace: &BackupNamespace,
+    snapshot: &BackupDir,
+    crypt_config: Option<Arc<CryptConfig>>,
+    keyfile: Option<String>,
+    legacy_key: Option<(Arc<CryptConfig>, String)>,
+) -> Result<(Arc<BackupReader>, Option<Arc<CryptConfig>>, Option<String>), Error> {
+    let (client, crypt_config) = BackupReader::start_with_legacy_key(
+        client,
+        crypt_config,
+        legacy_key.as_ref().map(|(config, _)| Arc::clone(config)),
+        repo.store(),
+        namespace,
+        snapshot,
+        true,
+    )
+    .await?;
+
+    let keyfile = match (&crypt_config, legacy_key) {
+        (Some(config), Some((legacy_config, legacy_keyfile)))
+            if Arc::ptr_eq(config, &legacy_config) =>
+        {
+            Some(legacy_keyfile)
+        }
+        _ => keyfile,
+    };
+
+    Ok((client, crypt_config, keyfile))
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn list_files(
     repo: BackupRepository,
     namespace: BackupNamespace,
@@ -103,16 +161,18 @@ async fn list_files(
     path: ExtractPath,
     crypt_config: Option<Arc<CryptConfig>>,
     keyfile: Option<String>,
+    legacy_key: Option<(Arc<CryptConfig>, String)>,
     driver: Option<BlockDriverType>,
 ) -> Result<Vec<ArchiveEntry>, Error> {
     let client = connect(&repo)?;
-    let client = BackupReader::start(
+    let (client, crypt_config, keyfile) = start_reader(
         client,
-        crypt_config.clone(),
-        repo.store(),
+        &repo,
         &namespace,
         &snapshot,
-        true,
+        crypt_config,
+        keyfile,
+        legacy_key,
     )
     .await?;
 
@@ -146,13 +206,12 @@ async fn list_files(
             let index = client
                 .download_dynamic_index(&manifest, CATALOG_NAME)
                 .await?;
-            let most_used = index.find_most_used_chunks(8);
             let file_info = manifest.lookup_file_info(CATALOG_NAME)?;
             let chunk_reader = RemoteChunkReader::new(
                 client.clone(),
                 crypt_config,
                 file_info.chunk_crypt_mode(),
-                most_used,
+                chunk_cache_size()?,
             );
             let reader = BufferedDynamicReader::new(index, chunk_reader);
             let mut catalog_reader = CatalogReader::new(reader);
@@ -208,6 +267,10 @@ async fn list_files(
                 schema: KEYFD_SCHEMA,
                 optional: true,
             },
+            "legacy-keyfile": {
+                schema: LEGACY_KEYFILE_SCHEMA,
+                optional: true,
+            },
             "crypt-mode": {
                 type: CryptMode,
                 optional: true,
@@ -263,6 +326,7 @@ async fn list(
             Some(Arc::new(CryptConfig::new(key)?))
         }
     };
+    let legacy_key = legacy_key(&param)?;
 
     let driver: Option<BlockDriverType> = match param.get("driver") {
         Some(drv) => Some(serde::Deserialize::deserialize(drv)?),
@@ -272,7 +336,16 @@ async fn list(
     let result = if let Some(timeout) = timeout {
         match tokio::time::timeout(
             std::time::Duration::from_secs(timeout),
-            list_files(repo, ns, snapshot, path, crypt_config, keyfile, driver),
+            list_files(
+                repo,
+                ns,
+                snapshot,
+                path,
+                crypt_config,
+                keyfile,
+                legacy_key,
+                driver,
+            ),
         )
         .await
         {
@@ -280,7 +353,17 @@ async fn list(
             Err(_) => Err(http_err!(SERVICE_UNAVAILABLE, "list not finished in time")),
         }
     } else {
-        list_files(repo, ns, snapshot, path, crypt_config, keyfile, driver).await
+        list_files(
+            repo,
+            ns,
+            snapshot,
+            path,
+            crypt_config,
+            keyfile,
+            legacy_key,
+            driver,
+        )
+        .await
     };
 
     let output_format = get_output_format(&param);
@@ -341,7 +424,8 @@ async fn list(
                 description: "Group/Snapshot path.",
             },
             "path": {
-                description: "Path to restore. Directories will be restored as archive files if extracted to stdout.",
+                description: "Path to restore. Directories will be restored as archive files if extracted to stdout. \
+                    A drive image without sub-path and with format 'plain' is exported as raw block device image.",
                 type: String,
             },
             "format": {
@@ -373,6 +457,10 @@ async fn list(
                 schema: KEYFD_SCHEMA,
                 optional: true,
             },
+            "legacy-keyfile": {
+                schema: LEGACY_KEYFILE_SCHEMA,
+                optional: true,
+            },
             "crypt-mode": {
                 type: CryptMode,
                 optional: true,
@@ -387,6 +475,14 @@ async fn list(
                 type: BlockDriverType,
                 optional: true,
             },
+            rate: {
+                schema: TRAFFIC_CONTROL_RATE_SCHEMA,
+                optional: true,
+            },
+            burst: {
+                schema: TRAFFIC_CONTROL_BURST_SCHEMA,
+                optional: true,
+            },
         }
     }
 )]
@@ -427,15 +523,18 @@ async fn extract(
             Some(Arc::new(CryptConfig::new(key)?))
         }
     };
+    let legacy_key = legacy_key(&param)?;
 
-    let client = connect(&repo)?;
-    let client = BackupReader::start(
+    // note: the rate limit does not apply to the restore VM, which uses its own connection
+    let client = connect_rate_limited(&repo, rate_limit_from_param(&param)?)?;
+    let (client, crypt_config, keyfile) = start_reader(
         client,
-        crypt_config.clone(),
-        repo.store(),
+        &repo,
         &namespace,
         &snapshot,
-        true,
+        crypt_config,
+        keyfile,
+        legacy_key,
     )
     .await?;
     let (manifest, _) = client.download_manifest().await?;
@@ -446,20 +545,32 @@ async fn extract(
             let index = client
                 .download_dynamic_index(&manifest, &archive_name)
                 .await?;
-            let most_used = index.find_most_used_chunks(8);
             let chunk_reader = RemoteChunkReader::new(
                 client.clone(),
                 crypt_config,
                 file_info.chunk_crypt_mode(),
-                most_used,
+                chunk_cache_size()?,
             );
             let reader = BufferedDynamicReader::new(index, chunk_reader);
 
-            let archive_size = reader.archive_size();
-            let reader = LocalDynamicReadAt::new(reader);
+            let (reader, archive_size) = pxar_read_at(reader, file_info.payload_compression)?;
             let decoder = Accessor::new(reader, archive_size).await?;
             extract_to_target(decoder, &path, target, format, zstd).await?;
         }
+        ExtractPath::VM(file, path)
+            if path.is_empty() && format == Some(FileRestoreFormat::Plain) =>
+        {
+            let file_info = manifest.lookup_file_info(&file)?;
+            let index = client.download_fixed_index(&manifest, &file).await?;
+            let chunk_reader = RemoteChunkReader::new(
+                client.clone(),
+                crypt_config,
+                file_info.chunk_crypt_mode(),
+                chunk_cache_size()?,
+            );
+            let reader = CachedChunkReader::new(chunk_reader, index, 8).seekable();
+            export_image(reader, &file, target, zstd).await?;
+        }
         ExtractPath::VM(file, path) => {
             let details = SnapRestoreDetails {
                 manifest,
@@ -506,6 +617,45 @@ async fn extract(
     Ok(())
 }
 
+/// Write the raw content of a drive image to a file in `target` or, if `None`, to stdout.
+async fn export_image<R>(
+    mut reader: R,
+    file: &str,
+    target: Option<PathBuf>,
+    zstd: bool,
+) -> Result<(), Error>
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+{
+    let mut writer: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = match target {
+        Some(mut target) => {
+            if target.is_dir() {
+                target.push(file.strip_suffix(".fidx").unwrap_or(file));
+            }
+            let file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&target)
+                .await
+                .map_err(|err| format_err!("unable to create target file {target:?} - {err}"))?;
+            Box::new(file)
+        }
+        None => Box::new(tokio::io::stdout()),
+    };
+
+    if zstd {
+        let mut zstdstream = ZstdEncoder::new(tokio_util::io::ReaderStream::new(reader))?;
+        while let Some(buf) = zstdstream.next().await {
+            writer.write_all(&buf?).await?;
+        }
+    } else {
+        tokio::io::copy(&mut reader, &mut writer).await?;
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
 async fn extract_to_target<T>(
     decoder: Accessor<T>,
     path: &[u8],
@@ -109,6 +109,8 @@ fn get_dir_entry(path: &Path) -> Result<DirEntryAttribute, Error> {
         libc::S_IFREG => DirEntryAttribute::File {
             size: stat.st_size as u64,
             mtime: stat.st_mtime,
+            ctime: Some(stat.st_ctime),
+            csum: None,
         },
         libc::S_IFDIR => DirEntryAttribute::Directory { start: 0 },
         _ => bail!("unsupported file type: {}", stat.st_mode),
@@ -352,6 +354,9 @@ fn extract(
                         device_set: None,
                         patterns,
                         skip_lost_and_found: false,
+                        catalog_content_hash: false,
+                        max_file_size: None,
+                        min_mtime: None,
                     };
 
                     let pxar_writer = TokioWriter::new(writer);
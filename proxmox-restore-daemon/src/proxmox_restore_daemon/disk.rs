@@ -17,8 +17,9 @@ use pbs_api_types::BLOCKDEVICE_NAME_REGEX;
 
 const_regex! {
     VIRTIO_PART_REGEX = r"^vd[a-z]+(\d+)$";
+    MD_DEVICE_REGEX = r"^md\d+$";
     ZPOOL_POOL_NAME_REGEX = r"^ {3}pool: (.*)$";
-    ZPOOL_IMPORT_DISK_REGEX = r"^\t {2,4}(vd[a-z]+(?:\d+)?)\s+ONLINE$";
+    ZPOOL_IMPORT_DISK_REGEX = r"^\t {2,4}(vd[a-z]+(?:\d+)?|md\d+)\s+ONLINE$";
 }
 
 lazy_static! {
@@ -37,6 +38,11 @@ lazy_static! {
         m.insert("ufs", "ufstype=ufs2");
 
         m.insert("ntfs", "utf8");
+        // guest snapshots are usually crash consistent, so the volume is likely marked dirty
+        m.insert("ntfs3", "iocharset=utf8,force");
+
+        // mount the top-level subvolume so all subvolumes are reachable, and don't replay the log
+        m.insert("btrfs", "subvolid=5,rescue=nologreplay");
 
         m
     };
@@ -63,6 +69,14 @@ struct ZFSBucketData {
     size: Option<u64>,
 }
 
+#[derive(Clone)]
+struct MdRaidBucketData {
+    name: String,
+    dev_node: String,
+    mountpoint: Option<PathBuf>,
+    size: u64,
+}
+
 #[derive(Clone)]
 struct LVMBucketData {
     vg_name: String,
@@ -88,6 +102,7 @@ enum Bucket {
     RawFs(PartitionBucketData),
     ZPool(ZFSBucketData),
     LVM(LVMBucketData),
+    MdRaid(MdRaidBucketData),
 }
 
 impl Bucket {
@@ -120,6 +135,13 @@ impl Bucket {
                     false
                 }
             }
+            Bucket::MdRaid(data) => {
+                if let Some(ref comp) = comp.get(0) {
+                    ty == "md" && comp.as_ref() == data.name
+                } else {
+                    false
+                }
+            }
         })
     }
 
@@ -129,6 +151,7 @@ impl Bucket {
             Bucket::RawFs(_) => "raw",
             Bucket::ZPool(_) => "zpool",
             Bucket::LVM(_) => "lvm",
+            Bucket::MdRaid(_) => "md",
         }
     }
 
@@ -153,6 +176,7 @@ impl Bucket {
                     data.lv_name.clone()
                 }
             }
+            Bucket::MdRaid(data) => data.name.clone(),
         })
     }
 
@@ -162,6 +186,7 @@ impl Bucket {
             "raw" => 0,
             "zpool" => 1,
             "lvm" => 2,
+            "md" => 1,
             _ => bail!("invalid bucket type for component depth: {}", type_string),
         })
     }
@@ -177,6 +202,7 @@ impl Bucket {
                     None
                 }
             }
+            Bucket::MdRaid(data) => Some(data.size),
         }
     }
 }
@@ -317,6 +343,18 @@ impl Filesystems {
                 );
                 self.try_mount(&mapper_path, &mntpath)?;
 
+                let mp = PathBuf::from(mntpath);
+                data.mountpoint = Some(mp.clone());
+                Ok(mp)
+            }
+            Bucket::MdRaid(data) => {
+                if let Some(mp) = &data.mountpoint {
+                    return Ok(mp.clone());
+                }
+
+                let mntpath = format!("/mnt/md/{}", &data.name);
+                self.try_mount(&data.dev_node, &mntpath)?;
+
                 let mp = PathBuf::from(mntpath);
                 data.mountpoint = Some(mp.clone());
                 Ok(mp)
@@ -448,6 +486,9 @@ impl DiskState {
             disk_map.insert(fidx, parts);
         }
 
+        // assemble software RAIDs first, they may contain zpools or LVM PVs too
+        Self::scan_mdraid(&mut disk_map, &mut drive_info)?;
+
         // After the above, every valid disk should have a device node in /dev, so we can query all
         // of them for zpools
         let mut cmd = Command::new("/sbin/zpool");
@@ -480,6 +521,80 @@ impl DiskState {
         })
     }
 
+    /// assemble mdraid arrays (read-only) and create device nodes for them
+    fn scan_mdraid(
+        disk_map: &mut HashMap<String, Vec<Bucket>>,
+        drive_info: &mut HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let mut cmd = Command::new("/sbin/mdadm");
+        cmd.args(["--assemble", "--scan", "--readonly"].iter());
+        if let Err(err) = run_command(cmd, None) {
+            // also fails if no arrays are found at all, so not critical
+            info!("mdraid: 'mdadm --assemble --scan' failed: {}", err);
+        }
+
+        for entry in proxmox_sys::fs::scan_subdir(libc::AT_FDCWD, "/sys/block", &MD_DEVICE_REGEX)?
+            .filter_map(Result::ok)
+        {
+            let name = unsafe { entry.file_name_utf8_unchecked() }.to_owned();
+            let sys_path = format!("/sys/block/{}", name);
+            let dev_node = format!("/dev/{}", name);
+            // mdadm may have created the node already, recreate it to be sure it is correct
+            let _ = std::fs::remove_file(&dev_node);
+            let size = match Self::make_dev_node(&dev_node, &sys_path) {
+                Ok(size) => size,
+                Err(err) => {
+                    warn!(
+                        "mdraid: could not create device node for '{}' - {}",
+                        name, err
+                    );
+                    continue;
+                }
+            };
+
+            let bucket = Bucket::MdRaid(MdRaidBucketData {
+                name: name.clone(),
+                dev_node,
+                mountpoint: None,
+                size,
+            });
+
+            // map the array to the disks of all its members via the 'slaves' directory
+            let mut fidx_list: Vec<String> = Vec::new();
+            for member in std::fs::read_dir(format!("{}/slaves", sys_path))? {
+                let member = member?.file_name();
+                if let Some(fidx) = drive_info.get(member.to_string_lossy().as_ref()) {
+                    if !fidx_list.contains(fidx) {
+                        fidx_list.push(fidx.clone());
+                    }
+                }
+            }
+
+            info!(
+                "mdraid: found array '{}' ({}B) on {}",
+                name,
+                size,
+                fidx_list.join(", ")
+            );
+
+            for fidx in fidx_list.iter() {
+                match disk_map.get_mut(fidx) {
+                    Some(v) => v.push(bucket.clone()),
+                    None => {
+                        disk_map.insert(fidx.to_owned(), vec![bucket.clone()]);
+                    }
+                }
+            }
+
+            // allow finding zpools and LVM PVs on top of the array
+            if let Some(fidx) = fidx_list.into_iter().next() {
+                drive_info.insert(name, fidx);
+            }
+        }
+
+        Ok(())
+    }
+
     /// scan for LVM volumes and create device nodes for them to later mount on demand
     fn scan_lvm(
         disk_map: &mut HashMap<String, Vec<Bucket>>,
@@ -12,8 +12,9 @@ use futures::select;
 use tokio::signal::unix::{signal, SignalKind};
 
 use pathpatterns::{MatchEntry, MatchType, PatternFlag};
+use pbs_api_types::DeviceNodeFallback;
 use pbs_client::pxar::{
-    format_single_line_entry, fuse, Flags, PxarExtractOptions, ENCODER_MAX_ENTRIES,
+    format_single_line_entry, fuse, Flags, IdMapping, PxarExtractOptions, ENCODER_MAX_ENTRIES,
 };
 
 use proxmox_router::cli::*;
@@ -70,6 +71,16 @@ fn extract_archive_from_reader<R: std::io::Read>(
                 optional: true,
                 default: false,
             },
+            "no-chattr": {
+                description: "Ignore file attribute flags.",
+                optional: true,
+                default: false,
+            },
+            "no-ownership": {
+                description: "Do not restore file ownership.",
+                optional: true,
+                default: false,
+            },
             "allow-existing-dirs": {
                 description: "Allows directories to already exist on restore.",
                 optional: true,
@@ -99,11 +110,43 @@ fn extract_archive_from_reader<R: std::io::Read>(
                 optional: true,
                 default: false,
             },
+            "device-node-fallback": {
+                type: DeviceNodeFallback,
+                optional: true,
+            },
             strict: {
                 description: "Stop on errors. Otherwise most errors will simply warn.",
                 optional: true,
                 default: false,
             },
+            "uid-offset": {
+                description: "Add this offset to the user IDs of extracted files.",
+                optional: true,
+                minimum: 0,
+            },
+            "gid-offset": {
+                description: "Add this offset to the group IDs of extracted files.",
+                optional: true,
+                minimum: 0,
+            },
+            "map-user": {
+                description: "List of user ID mappings, takes precedence over 'uid-offset'.",
+                type: Array,
+                items: {
+                    type: String,
+                    description: "User ID mapping in the form 'old=new'.",
+                },
+                optional: true,
+            },
+            "map-group": {
+                description: "List of group ID mappings, takes precedence over 'gid-offset'.",
+                type: Array,
+                items: {
+                    type: String,
+                    description: "Group ID mapping in the form 'old=new'.",
+                },
+                optional: true,
+            },
         },
     },
 )]
@@ -116,13 +159,20 @@ fn extract_archive(
     no_xattrs: bool,
     no_fcaps: bool,
     no_acls: bool,
+    no_chattr: bool,
+    no_ownership: bool,
     allow_existing_dirs: bool,
     overwrite: bool,
     files_from: Option<String>,
     no_device_nodes: bool,
     no_fifos: bool,
     no_sockets: bool,
+    device_node_fallback: Option<DeviceNodeFallback>,
     strict: bool,
+    uid_offset: Option<u32>,
+    gid_offset: Option<u32>,
+    map_user: Option<Vec<String>>,
+    map_group: Option<Vec<String>>,
 ) -> Result<(), Error> {
     let mut feature_flags = Flags::DEFAULT;
     if no_xattrs {
@@ -134,6 +184,12 @@ fn extract_archive(
     if no_acls {
         feature_flags.remove(Flags::WITH_ACL);
     }
+    if no_chattr {
+        feature_flags.remove(Flags::WITH_CHATTR);
+    }
+    if no_ownership {
+        feature_flags.remove(Flags::WITH_OWNER);
+    }
     if no_device_nodes {
         feature_flags.remove(Flags::WITH_DEVICE_NODES);
     }
@@ -182,12 +238,28 @@ fn extract_archive(
             as Box<dyn FnMut(Error) -> Result<(), Error> + Send>)
     };
 
+    let mut id_mapping = IdMapping {
+        uid_offset: uid_offset.unwrap_or(0),
+        gid_offset: gid_offset.unwrap_or(0),
+        ..Default::default()
+    };
+    for entry in map_user.unwrap_or_default() {
+        let (old, new) = IdMapping::parse_entry(&entry)?;
+        id_mapping.users.insert(old, new);
+    }
+    for entry in map_group.unwrap_or_default() {
+        let (old, new) = IdMapping::parse_entry(&entry)?;
+        id_mapping.groups.insert(old, new);
+    }
+
     let options = PxarExtractOptions {
         match_list: &match_list,
         allow_existing_dirs,
         overwrite,
         extract_match_default,
         on_error,
+        id_mapping: Some(id_mapping),
+        device_node_fallback: device_node_fallback.unwrap_or_default(),
     };
 
     if archive == "-" {
@@ -309,6 +381,9 @@ async fn create_archive(
         device_set,
         patterns,
         skip_lost_and_found: false,
+        catalog_content_hash: false,
+        max_file_size: None,
+        min_mtime: None,
     };
 
     let source = PathBuf::from(source);
@@ -251,6 +251,7 @@ pub fn update_acl(
     if !delete {
         // Note: we allow to delete entries with invalid path
         pbs_config::acl::check_acl_path(&path)?;
+        pbs_config::acl::check_acl_path_objects(&path)?;
     }
 
     if let Some(auth_id) = auth_id {
@@ -428,6 +428,10 @@ const SUBDIRS: SubdirMap = &sorted!([
     ("ticket", &Router::new().post(&API_METHOD_CREATE_TICKET)),
     ("openid", &openid::ROUTER),
     ("domains", &domain::ROUTER),
+    (
+        "expiring",
+        &Router::new().get(&user::API_METHOD_LIST_EXPIRING)
+    ),
     ("roles", &role::ROUTER),
     ("users", &user::ROUTER),
     ("tfa", &tfa::ROUTER),
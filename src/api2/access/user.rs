@@ -10,9 +10,9 @@ use proxmox_router::{ApiMethod, Permission, Router, RpcEnvironment, SubdirMap};
 use proxmox_schema::api;
 
 use pbs_api_types::{
-    ApiToken, Authid, Tokenname, User, UserUpdater, UserWithTokens, Userid, ENABLE_USER_SCHEMA,
-    EXPIRE_USER_SCHEMA, PBS_PASSWORD_SCHEMA, PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT,
-    PROXMOX_CONFIG_DIGEST_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
+    ApiToken, Authid, ExpiringAccount, Tokenname, User, UserUpdater, UserWithTokens, Userid,
+    ENABLE_USER_SCHEMA, EXPIRE_USER_SCHEMA, PBS_PASSWORD_SCHEMA, PRIV_PERMISSIONS_MODIFY,
+    PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
 };
 use pbs_config::token_shadow;
 
@@ -727,6 +727,62 @@ pub fn list_tokens(
     Ok(res)
 }
 
+#[api(
+    input: {
+        properties: {
+            days: {
+                type: Integer,
+                description: "Number of days to look ahead. Defaults to the 'user-expiry-warning' \
+                    node setting.",
+                minimum: 1,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "List of users and API tokens which expire soon.",
+        type: Array,
+        items: { type: ExpiringAccount },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Returns all or just the logged-in user's (/API token owner's) accounts, \
+            depending on privileges.",
+    },
+)]
+/// List the users and API tokens which expire within the next days.
+pub fn list_expiring(
+    days: Option<u64>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<ExpiringAccount>, Error> {
+    let auth_id: Authid = rpcenv
+        .get_auth_id()
+        .ok_or_else(|| format_err!("no authid available"))?
+        .parse()?;
+
+    let days = match days {
+        Some(days) => days,
+        None => {
+            let (config, _digest) = crate::config::node::config()?;
+            config
+                .user_expiry_warning
+                .unwrap_or(crate::config::node::DEFAULT_USER_EXPIRY_WARNING)
+        }
+    };
+
+    let user_info = CachedUserInfo::new()?;
+
+    let top_level_privs = user_info.lookup_privs(&auth_id, &["access", "users"]);
+    let top_level_allowed = (top_level_privs & PRIV_SYS_AUDIT) != 0;
+
+    let list = crate::server::expiring_accounts(days)?
+        .into_iter()
+        .filter(|account| top_level_allowed || account.authid.user() == auth_id.user())
+        .collect();
+
+    Ok(list)
+}
+
 const TOKEN_ITEM_ROUTER: Router = Router::new()
     .get(&API_METHOD_READ_TOKEN)
     .put(&API_METHOD_UPDATE_TOKEN)
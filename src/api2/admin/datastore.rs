@@ -29,16 +29,19 @@ use proxmox_sys::sortable;
 use proxmox_sys::{task_log, task_warn};
 
 use pxar::accessor::aio::Accessor;
+use pxar::accessor::ReadAt;
 use pxar::EntryKind;
 
 use pbs_api_types::{
     print_ns_and_snapshot, print_store_and_ns, Authid, BackupContent, BackupNamespace, BackupType,
     Counts, CryptMode, DataStoreListItem, DataStoreStatus, GarbageCollectionStatus, GroupListItem,
-    KeepOptions, Operation, PruneJobOptions, RRDMode, RRDTimeFrame, SnapshotListItem,
-    SnapshotVerifyState, BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA,
-    BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, DATASTORE_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA,
+    HumanByte, KeepOptions, Operation, PruneJobOptions, RRDMode, RRDTimeFrame, SnapshotListFilter,
+    SnapshotListItem, SnapshotVerifyState, StorageStatus, SyncJobConfig,
+    BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
+    BACKUP_TYPE_SCHEMA, DATASTORE_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA, JOB_ID_SCHEMA,
     MAX_NAMESPACE_DEPTH, NS_MAX_DEPTH_SCHEMA, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP,
     PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_READ, PRIV_DATASTORE_VERIFY,
+    PRIV_REMOTE_READ, RRD_END_TIME_SCHEMA, RRD_START_TIME_SCHEMA, SNAPSHOT_LABEL_SCHEMA,
     UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
 };
 use pbs_client::pxar::{create_tar, create_zip};
@@ -48,7 +51,7 @@ use pbs_datastore::cached_chunk_reader::CachedChunkReader;
 use pbs_datastore::catalog::{ArchiveEntry, CatalogReader};
 use pbs_datastore::data_blob::DataBlob;
 use pbs_datastore::data_blob_reader::DataBlobReader;
-use pbs_datastore::dynamic_index::{BufferedDynamicReader, DynamicIndexReader, LocalDynamicReadAt};
+use pbs_datastore::dynamic_index::{pxar_read_at, BufferedDynamicReader, DynamicIndexReader};
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
 use pbs_datastore::manifest::{BackupManifest, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME};
@@ -63,9 +66,10 @@ use proxmox_rest_server::{formatter, WorkerTask};
 use crate::api2::backup::optional_ns_param;
 use crate::api2::node::rrd::create_value_from_rrd;
 use crate::backup::{
-    check_ns_privs_full, verify_all_backups, verify_backup_dir, verify_backup_group, verify_filter,
-    ListAccessibleBackupGroups, NS_PRIVS_OK,
+    check_ns_privs, check_ns_privs_full, verify_all_backups, verify_backup_dir,
+    verify_backup_group, verify_filter, ListAccessibleBackupGroups, NS_PRIVS_OK,
 };
+use crate::rrd_cache::{extract_rrd_data, RRDTimeRange};
 
 use crate::server::jobstate::Job;
 
@@ -146,9 +150,17 @@ fn get_all_snapshot_files(
         if file_set.contains(file) {
             continue;
         }
+        // not part of the manifest, its size tells syncs whether it changed
+        let size = if file == CLIENT_LOG_BLOB_NAME {
+            std::fs::metadata(info.backup_dir.full_path().join(file))
+                .ok()
+                .map(|metadata| metadata.len())
+        } else {
+            None
+        };
         files.push(BackupContent {
             filename: file.to_string(),
-            size: None,
+            size,
             crypt_mode: None,
         });
     }
@@ -410,6 +422,130 @@ pub async fn delete_snapshot(
     .await?
 }
 
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+            "target-store": { schema: DATASTORE_SCHEMA },
+            "target-ns": {
+                type: BackupNamespace,
+                optional: true,
+            },
+            "remove-source": {
+                description: "Remove the snapshot from the source datastore after copying it.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_READ for any \
+            or DATASTORE_BACKUP and being the owner of the group, with remove-source additionally \
+            either DATASTORE_MODIFY or DATASTORE_PRUNE and being the owner of the group. \
+            Requires DATASTORE_BACKUP on /datastore/{target-store}[/{target-namespace}].",
+    },
+)]
+/// Copy a backup snapshot to another local datastore or namespace. Only the chunks missing on
+/// the target datastore get copied. The copy is owned by the calling user.
+pub fn copy_snapshot(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    target_store: String,
+    target_ns: Option<BackupNamespace>,
+    remove_source: bool,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+    let target_ns = target_ns.unwrap_or_default();
+
+    let operation = if remove_source {
+        Operation::Write
+    } else {
+        Operation::Read
+    };
+    let source = check_privs_and_load_store(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_READ,
+        PRIV_DATASTORE_BACKUP,
+        Some(operation),
+        &backup_dir.group,
+    )?;
+    if remove_source {
+        check_privs_and_load_store(
+            &store,
+            &ns,
+            &auth_id,
+            PRIV_DATASTORE_MODIFY,
+            PRIV_DATASTORE_PRUNE,
+            None,
+            &backup_dir.group,
+        )?;
+    }
+
+    check_ns_privs(&target_store, &target_ns, &auth_id, PRIV_DATASTORE_BACKUP)?;
+    let target = DataStore::lookup_datastore(&target_store, Some(Operation::Write))?;
+
+    let snapshot = source.backup_dir(ns.clone(), backup_dir.clone())?;
+    // fail early instead of leaving a copy behind
+    if remove_source && snapshot.is_protected() {
+        bail!("cannot move protected snapshot {backup_dir}");
+    }
+
+    let worker_id = format!("{}:{}/{}", store, ns.display_as_path(), backup_dir);
+    let worker_type = if remove_source {
+        "move-snapshot"
+    } else {
+        "copy-snapshot"
+    };
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        worker_type,
+        Some(worker_id),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            task_log!(
+                worker,
+                "copying snapshot {} to {}",
+                print_ns_and_snapshot(&ns, &backup_dir),
+                print_store_and_ns(&target_store, &target_ns),
+            );
+
+            let copied_chunks =
+                source.copy_snapshot(&target, &ns, &backup_dir, &target_ns, &auth_id, &*worker)?;
+            task_log!(worker, "copied {} chunks", copied_chunks);
+
+            if remove_source {
+                task_log!(worker, "removing source snapshot");
+                snapshot.destroy(false)?;
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
 #[api(
     streaming: true,
     input: {
@@ -427,6 +563,10 @@ pub async fn delete_snapshot(
                 optional: true,
                 schema: BACKUP_ID_SCHEMA,
             },
+            filter: {
+                type: SnapshotListFilter,
+                flatten: true,
+            },
         },
     },
     returns: pbs_api_types::ADMIN_DATASTORE_LIST_SNAPSHOTS_RETURN_TYPE,
@@ -442,6 +582,7 @@ pub async fn list_snapshots(
     ns: Option<BackupNamespace>,
     backup_type: Option<BackupType>,
     backup_id: Option<String>,
+    filter: SnapshotListFilter,
     _param: Value,
     _info: &ApiMethod,
     rpcenv: &mut dyn RpcEnvironment,
@@ -449,7 +590,7 @@ pub async fn list_snapshots(
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
 
     tokio::task::spawn_blocking(move || unsafe {
-        list_snapshots_blocking(store, ns, backup_type, backup_id, auth_id)
+        list_snapshots_blocking(store, ns, backup_type, backup_id, filter, auth_id)
     })
     .await
     .map_err(|err| format_err!("failed to await blocking task: {err}"))?
@@ -461,6 +602,7 @@ unsafe fn list_snapshots_blocking(
     ns: Option<BackupNamespace>,
     backup_type: Option<BackupType>,
     backup_id: Option<String>,
+    filter: SnapshotListFilter,
     auth_id: Authid,
 ) -> Result<Vec<SnapshotListItem>, Error> {
     let ns = ns.unwrap_or_default();
@@ -532,6 +674,13 @@ unsafe fn list_snapshots_blocking(
 
                 let size = Some(files.iter().map(|x| x.size.unwrap_or(0)).sum());
 
+                let labels = manifest.unprotected["labels"].as_object().map(|labels| {
+                    labels
+                        .iter()
+                        .filter_map(|(key, value)| Some(format!("{key}={}", value.as_str()?)))
+                        .collect()
+                });
+
                 SnapshotListItem {
                     backup,
                     comment,
@@ -541,6 +690,7 @@ unsafe fn list_snapshots_blocking(
                     size,
                     owner,
                     protected,
+                    labels,
                 }
             }
             Err(err) => {
@@ -564,6 +714,7 @@ unsafe fn list_snapshots_blocking(
                     size: None,
                     owner,
                     protected,
+                    labels: None,
                 }
             }
         }
@@ -587,12 +738,18 @@ unsafe fn list_snapshots_blocking(
             return Ok(snapshots);
         }
 
+        if !filter.matches_owner(&owner) {
+            return Ok(snapshots);
+        }
+
         let group_backups = group.list_backups()?;
 
         snapshots.extend(
             group_backups
                 .into_iter()
-                .map(|info| info_to_snapshot_list_item(group, Some(owner.clone()), info)),
+                .filter(|info| filter.matches_time(info.backup_dir.backup_time()))
+                .map(|info| info_to_snapshot_list_item(group, Some(owner.clone()), info))
+                .filter(|item| filter.matches_item(item)),
         );
 
         Ok(snapshots)
@@ -711,12 +868,36 @@ pub async fn status(
 
     Ok(if store_stats {
         let storage = crate::tools::fs::fs_info(datastore.base_path()).await?;
+        let storage_status = if verbose {
+            let (total_inodes, used_inodes) =
+                crate::tools::fs::fs_inode_info(datastore.base_path()).await?;
+            let mut storage_status = StorageStatus {
+                total: storage.total,
+                used: storage.used,
+                avail: storage.available,
+                total_inodes: Some(total_inodes),
+                used_inodes: Some(used_inodes),
+                quota: datastore.quota(),
+                quota_used: datastore.quota().map(|_| datastore.quota_used()),
+                ..Default::default()
+            };
+            if let Some((start, delta, history)) = get_usage_history(&store)? {
+                storage_status.history_start = Some(start);
+                storage_status.history_delta = Some(delta);
+                storage_status.history = Some(history);
+            }
+            Some(storage_status)
+        } else {
+            None
+        };
         DataStoreStatus {
             total: storage.total,
             used: storage.used,
             avail: storage.available,
             gc_status,
             counts,
+            storage: storage_status,
+            zpool: crate::api2::status::datastore_zpool_health(datastore.base_path()).await,
         }
     } else {
         DataStoreStatus {
@@ -725,10 +906,38 @@ pub async fn status(
             avail: 0,
             gc_status,
             counts,
+            storage: None,
+            zpool: None,
         }
     })
 }
 
+/// Returns the space usage of the last day as (start, resolution, usage list).
+fn get_usage_history(store: &str) -> Result<Option<(u64, u64, Vec<Option<f64>>)>, Error> {
+    let rrd_dir = format!("datastore/{}", store);
+    let get_rrd =
+        |what: &str| extract_rrd_data(&rrd_dir, what, RRDTimeFrame::Day, RRDMode::Average);
+
+    let (total, used) = match (get_rrd("total")?, get_rrd("used")?) {
+        (Some(total), Some(used)) => (total, used),
+        _ => return Ok(None),
+    };
+
+    let history = used
+        .data
+        .iter()
+        .enumerate()
+        .map(
+            |(idx, used)| match (total.data.get(idx).copied().flatten(), used) {
+                (Some(total), Some(used)) if total != 0.0 => Some(used / total),
+                _ => None,
+            },
+        )
+        .collect();
+
+    Ok(Some((total.start, total.resolution, history)))
+}
+
 #[api(
     input: {
         properties: {
@@ -906,6 +1115,7 @@ pub fn verify(
                     Some(&move |manifest| verify_filter(ignore_verified, outdated_after, manifest)),
                 )?
             };
+            verify_worker.log_chunk_summary();
             if !failed_dirs.is_empty() {
                 task_log!(worker, "Failed to verify the following snapshots/groups:");
                 for dir in failed_dirs {
@@ -1123,6 +1333,7 @@ pub fn prune_datastore(
         to_stdout,
         move |worker| {
             crate::server::prune_datastore(worker, auth_id, prune_options, datastore, dry_run)
+                .map(|_| ())
         },
     )?;
 
@@ -1199,6 +1410,169 @@ pub fn garbage_collection_status(
     Ok(status)
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            repair: {
+                description: "Move broken chunks to the '.corrupt' directory and misplaced chunks to their directory.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "sync-job": {
+                schema: JOB_ID_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+        description: "With a sync job, requires additionally Remote.Read on its remote datastore.",
+    },
+)]
+/// Check the chunk store for empty, misplaced and corrupt chunks. The broken chunks are
+/// downloaded again from the remote of the given sync job, which has to sync this datastore.
+pub fn check_chunkstore(
+    store: String,
+    repair: bool,
+    sync_job: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let sync_job = match sync_job {
+        Some(id) => {
+            let (config, _digest) = pbs_config::sync::config()?;
+            let job: SyncJobConfig = config.lookup("sync", &id)?;
+            if job.store != store {
+                bail!("sync job '{id}' does not sync datastore '{store}'");
+            }
+            let user_info = CachedUserInfo::new()?;
+            user_info.check_privs(
+                &auth_id,
+                &["remote", &job.remote, &job.remote_store],
+                PRIV_REMOTE_READ,
+                false,
+            )?;
+            Some(job)
+        }
+        None => None,
+    };
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str =
+        crate::server::do_chunkstore_check(datastore, repair, sync_job, &auth_id, to_stdout)?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Rewrite the unencrypted chunks of the datastore with its configured compression.
+pub fn recompress_chunks(store: String, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "recompress-chunks",
+        Some(store.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            match datastore.chunk_compression_level() {
+                Some(level) => task_log!(worker, "compressing chunks with zstd level {}", level),
+                None => task_log!(worker, "storing chunks uncompressed"),
+            }
+
+            let status = datastore.recompress_chunks(&*worker)?;
+
+            task_log!(
+                worker,
+                "rewrote {} chunks, from {} to {}",
+                status.rewritten_chunks,
+                HumanByte::from(status.old_bytes),
+                HumanByte::from(status.new_bytes),
+            );
+            task_log!(worker, "kept {} chunks", status.skipped_chunks);
+
+            if status.failed_chunks > 0 {
+                bail!("failed to recompress {} chunks", status.failed_chunks);
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Restore the snapshot files missing below the datastore path from the copies kept by its
+/// storage backend.
+pub fn restore_snapshots_from_backend(
+    store: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "restore-backend",
+        Some(store.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            let restored = datastore.restore_snapshots_from_backend(&*worker)?;
+            task_log!(worker, "restored {} files", restored);
+            Ok(())
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
 #[api(
     returns: {
         description: "List the accessible datastores.",
@@ -1473,7 +1847,8 @@ pub fn download_file_decoded(
 pub const API_METHOD_UPLOAD_BACKUP_LOG: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&upload_backup_log),
     &ObjectSchema::new(
-        "Upload the client backup log file into a backup snapshot ('client.log.blob').",
+        "Upload the client backup log file into a backup snapshot ('client.log.blob'). \
+         The archive statistics written by the backup client are appended to it.",
         &sorted!([
             ("store", false, &DATASTORE_SCHEMA),
             ("ns", true, &BACKUP_NAMESPACE_SCHEMA),
@@ -1518,9 +1893,11 @@ pub fn upload_backup_log(
         let mut path = backup_dir.full_path();
         path.push(&file_name);
 
-        if path.exists() {
-            bail!("backup already contains a log.");
-        }
+        let statistics = match backup_dir.client_log_statistics()? {
+            Some(statistics) => Some(statistics),
+            None if path.exists() => bail!("backup already contains a log."),
+            None => None,
+        };
 
         println!(
             "Upload backup log to {} {backup_dir_api}/{file_name}",
@@ -1538,6 +1915,23 @@ pub fn upload_backup_log(
         // always verify blob/CRC at server side
         let blob = DataBlob::load_from_reader(&mut &data[..])?;
 
+        let blob = match statistics {
+            Some(statistics) if !blob.is_encrypted() => {
+                // append the archive statistics written by the backup client
+                let mut log = blob.decode(None, None)?;
+                if !log.is_empty() && !log.ends_with(b"\n") {
+                    log.push(b'\n');
+                }
+                log.extend_from_slice(&statistics);
+                DataBlob::encode(&log, None, true)?
+            }
+            Some(_) => {
+                println!("cannot merge encrypted log with archive statistics, replacing them");
+                blob
+            }
+            None => blob,
+        };
+
         replace_file(&path, blob.raw_data(), CreateOptions::new(), false)?;
 
         // fixme: use correct formatter
@@ -1630,6 +2024,38 @@ pub async fn catalog(
     .await?
 }
 
+/// Open an unencrypted pxar archive of a snapshot for random access.
+async fn open_pxar_accessor(
+    datastore: Arc<DataStore>,
+    backup_dir: &BackupDir,
+    pxar_name: &str,
+) -> Result<Accessor<Arc<dyn ReadAt + Send + Sync>>, Error> {
+    let (manifest, files) = read_backup_index(backup_dir)?;
+    for file in files {
+        if file.filename == pxar_name && file.crypt_mode == Some(CryptMode::Encrypt) {
+            bail!("cannot decode '{}' - is encrypted", pxar_name);
+        }
+    }
+
+    let payload_compression = manifest.lookup_file_info(pxar_name)?.payload_compression;
+
+    let mut path = datastore.base_path();
+    path.push(backup_dir.relative_path());
+    path.push(pxar_name);
+
+    let index = DynamicIndexReader::open(&path)
+        .map_err(|err| format_err!("unable to read dynamic index '{:?}' - {}", &path, err))?;
+
+    let (csum, size) = index.compute_csum();
+    manifest.verify_file(pxar_name, &csum, size)?;
+
+    let chunk_reader = LocalChunkReader::new(datastore, None, CryptMode::None);
+    let reader = BufferedDynamicReader::new(index, chunk_reader);
+    let (reader, archive_size) = pxar_read_at(reader, payload_compression)?;
+
+    Ok(Accessor::new(reader, archive_size).await?)
+}
+
 #[sortable]
 pub const API_METHOD_PXAR_FILE_DOWNLOAD: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&pxar_file_download),
@@ -1642,7 +2068,14 @@ pub const API_METHOD_PXAR_FILE_DOWNLOAD: ApiMethod = ApiMethod::new(
             ("backup-id", false,  &BACKUP_ID_SCHEMA),
             ("backup-time", false, &BACKUP_TIME_SCHEMA),
             ("filepath", false, &StringSchema::new("Base64 encoded path").schema()),
-            ("tar", true, &BooleanSchema::new("Download as .tar.zst").schema()),
+            ("tar", true, &BooleanSchema::new("Download directories as .tar.zst").schema()),
+            (
+                "zstd",
+                true,
+                &BooleanSchema::new("Compress tar downloads with zstd.")
+                    .default(true)
+                    .schema()
+            ),
         ]),
     )
 ).access(
@@ -1681,6 +2114,7 @@ pub fn pxar_file_download(
         let filepath = required_string_param(&param, "filepath")?.to_owned();
 
         let tar = param["tar"].as_bool().unwrap_or(false);
+        let zstd = param["zstd"].as_bool().unwrap_or(true);
 
         let mut components = base64::decode(&filepath)?;
         if !components.is_empty() && components[0] == b'/' {
@@ -1690,29 +2124,8 @@ pub fn pxar_file_download(
         let mut split = components.splitn(2, |c| *c == b'/');
         let pxar_name = std::str::from_utf8(split.next().unwrap())?;
         let file_path = split.next().unwrap_or(b"/");
-        let (manifest, files) = read_backup_index(&backup_dir)?;
-        for file in files {
-            if file.filename == pxar_name && file.crypt_mode == Some(CryptMode::Encrypt) {
-                bail!("cannot decode '{}' - is encrypted", pxar_name);
-            }
-        }
 
-        let mut path = datastore.base_path();
-        path.push(backup_dir.relative_path());
-        path.push(pxar_name);
-
-        let index = DynamicIndexReader::open(&path)
-            .map_err(|err| format_err!("unable to read dynamic index '{:?}' - {}", &path, err))?;
-
-        let (csum, size) = index.compute_csum();
-        manifest.verify_file(pxar_name, &csum, size)?;
-
-        let chunk_reader = LocalChunkReader::new(datastore, None, CryptMode::None);
-        let reader = BufferedDynamicReader::new(index, chunk_reader);
-        let archive_size = reader.archive_size();
-        let reader = LocalDynamicReadAt::new(reader);
-
-        let decoder = Accessor::new(reader, archive_size).await?;
+        let decoder = open_pxar_accessor(datastore, &backup_dir, pxar_name).await?;
         let root = decoder.open_root().await?;
         let path = OsStr::from_bytes(file_path).to_os_string();
         let file = root
@@ -1720,6 +2133,15 @@ pub fn pxar_file_download(
             .await?
             .ok_or_else(|| format_err!("error opening '{:?}'", path))?;
 
+        let mut download_name = match file_path.rsplit(|c| *c == b'/').find(|c| !c.is_empty()) {
+            Some(name) => String::from_utf8_lossy(name).into_owned(),
+            None => pxar_name
+                .strip_suffix(".pxar.didx")
+                .unwrap_or(pxar_name)
+                .to_string(),
+        };
+
+        let mut content_type = "application/octet-stream";
         let body = match file.kind() {
             EntryKind::File { .. } => Body::wrap_stream(
                 AsyncReaderStream::new(file.contents().await?).map_err(move |err| {
@@ -1737,7 +2159,9 @@ pub fn pxar_file_download(
             EntryKind::Directory => {
                 let (sender, receiver) = tokio::sync::mpsc::channel::<Result<_, Error>>(100);
                 let channelwriter = AsyncChannelWriter::new(sender, 1024 * 1024);
-                if tar {
+                if tar && zstd {
+                    download_name.push_str(".tar.zst");
+                    content_type = "application/zstd";
                     proxmox_rest_server::spawn_internal_task(create_tar(
                         channelwriter,
                         decoder,
@@ -1748,7 +2172,21 @@ pub fn pxar_file_download(
                         log::error!("error during streaming of tar.zst '{:?}' - {}", path, err);
                         err
                     }))
+                } else if tar {
+                    download_name.push_str(".tar");
+                    content_type = "application/x-tar";
+                    proxmox_rest_server::spawn_internal_task(create_tar(
+                        channelwriter,
+                        decoder,
+                        path.clone(),
+                    ));
+                    Body::wrap_stream(ReceiverStream::new(receiver).map_err(move |err| {
+                        log::error!("error during streaming of tar '{:?}' - {}", path, err);
+                        err
+                    }))
                 } else {
+                    download_name.push_str(".zip");
+                    content_type = "application/zip";
                     proxmox_rest_server::spawn_internal_task(create_zip(
                         channelwriter,
                         decoder,
@@ -1763,10 +2201,16 @@ pub fn pxar_file_download(
             other => bail!("cannot download file of type {:?}", other),
         };
 
-        // fixme: set other headers ?
+        // the name must not end the quoted filename parameter early
+        let download_name = download_name.replace(|c: char| c == '"' || c.is_control(), "_");
+
         Ok(Response::builder()
             .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_TYPE, content_type)
+            .header(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", download_name),
+            )
             .body(body)
             .unwrap())
     }
@@ -1781,9 +2225,19 @@ pub fn pxar_file_download(
             },
             timeframe: {
                 type: RRDTimeFrame,
+                optional: true,
+            },
+            start: {
+                schema: RRD_START_TIME_SCHEMA,
+                optional: true,
+            },
+            end: {
+                schema: RRD_END_TIME_SCHEMA,
+                optional: true,
             },
             cf: {
                 type: RRDMode,
+                optional: true,
             },
         },
     },
@@ -1792,13 +2246,16 @@ pub fn pxar_file_download(
             &["datastore", "{store}"], PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_BACKUP, true),
     },
 )]
-/// Read datastore stats
+/// Read datastore stats, either for a fixed time frame or a custom start/end range
 pub fn get_rrd_stats(
     store: String,
-    timeframe: RRDTimeFrame,
-    cf: RRDMode,
+    timeframe: Option<RRDTimeFrame>,
+    start: Option<u64>,
+    end: Option<u64>,
+    cf: Option<RRDMode>,
     _param: Value,
 ) -> Result<Value, Error> {
+    let range = RRDTimeRange::from_params(timeframe, start, end)?;
     let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
     let disk_manager = crate::tools::disks::DiskManage::new();
 
@@ -1811,13 +2268,35 @@ pub fn get_rrd_stats(
         "write_bytes",
     ];
 
-    // we do not have io_ticks for zpools, so don't include them
+    // we do not have io_ticks and per direction wait times for zpools, so don't include them
     match disk_manager.find_mounted_device(&datastore.base_path()) {
         Ok(Some((fs_type, _, _))) if fs_type.as_str() == "zfs" => {}
-        _ => rrd_fields.push("io_ticks"),
+        _ => rrd_fields.extend(["io_ticks", "read_ticks", "write_ticks"]),
     };
 
-    create_value_from_rrd(&format!("datastore/{}", store), &rrd_fields, timeframe, cf)
+    let mut data = create_value_from_rrd(
+        &format!("datastore/{}", store),
+        &rrd_fields,
+        range,
+        cf.unwrap_or_default(),
+    )?;
+
+    if let Some(list) = data.as_array_mut() {
+        for entry in list {
+            add_rrd_latency(entry, "read_ios", "read_ticks", "read_latency");
+            add_rrd_latency(entry, "write_ios", "write_ticks", "write_latency");
+        }
+    }
+
+    Ok(data)
+}
+
+/// Calculate the average latency in milliseconds per operation of an RRD entry.
+fn add_rrd_latency(entry: &mut Value, ios: &str, ticks: &str, name: &str) {
+    if let (Some(ios), Some(ticks)) = (entry[ios].as_f64(), entry[ticks].as_f64()) {
+        let latency = if ios > 0.0 { ticks / ios } else { 0.0 };
+        entry[name] = latency.into();
+    }
 }
 
 #[api(
@@ -1998,6 +2477,15 @@ pub fn get_notes(
             },
             notes: {
                 description: "A multiline text.",
+                optional: true,
+            },
+            labels: {
+                description: "Labels to set, replacing the values of existing keys.",
+                type: Array,
+                optional: true,
+                items: {
+                    schema: SNAPSHOT_LABEL_SCHEMA,
+                },
             },
         },
     },
@@ -2007,17 +2495,22 @@ pub fn get_notes(
             or DATASTORE_BACKUP and being the owner of the group",
     },
 )]
-/// Set "notes" for a specific backup
+/// Set "notes" and labels for a specific backup
 pub fn set_notes(
     store: String,
     ns: Option<BackupNamespace>,
     backup_dir: pbs_api_types::BackupDir,
-    notes: String,
+    notes: Option<String>,
+    labels: Option<Vec<String>>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<(), Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let ns = ns.unwrap_or_default();
 
+    if notes.is_none() && labels.is_none() {
+        bail!("neither notes nor labels given");
+    }
+
     let datastore = check_privs_and_load_store(
         &store,
         &ns,
@@ -2032,13 +2525,44 @@ pub fn set_notes(
 
     backup_dir
         .update_manifest(|manifest| {
-            manifest.unprotected["notes"] = notes.into();
+            if let Some(notes) = notes {
+                manifest.unprotected["notes"] = notes.into();
+            }
+            if let Some(labels) = labels {
+                update_snapshot_labels(&mut manifest.unprotected, labels);
+            }
         })
         .map_err(|err| format_err!("unable to update manifest blob - {}", err))?;
 
     Ok(())
 }
 
+// merge the `key=value` labels into the manifest labels, an empty value removes a label
+fn update_snapshot_labels(unprotected: &mut Value, labels: Vec<String>) {
+    let mut current = unprotected["labels"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+
+    for label in labels {
+        // the schema guarantees the separator
+        let (key, value) = label.split_once('=').unwrap();
+        if value.is_empty() {
+            current.remove(key);
+        } else {
+            current.insert(key.to_string(), value.into());
+        }
+    }
+
+    if current.is_empty() {
+        if let Some(unprotected) = unprotected.as_object_mut() {
+            unprotected.remove("labels");
+        }
+    } else {
+        unprotected["labels"] = Value::Object(current);
+    }
+}
+
 #[api(
     input: {
         properties: {
@@ -2231,7 +2755,7 @@ pub async fn set_backup_owner(
             );
         }
 
-        backup_group.set_owner(&new_owner, true)?;
+        backup_group.change_owner(&new_owner)?;
 
         Ok(())
     })
@@ -2247,7 +2771,17 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
     ("catalog", &Router::new().get(&API_METHOD_CATALOG)),
     (
         "change-owner",
-        &Router::new().post(&API_METHOD_SET_BACKUP_OWNER),
+        &Router::new()
+            .post(&API_METHOD_SET_BACKUP_OWNER)
+            .put(&API_METHOD_SET_BACKUP_OWNER),
+    ),
+    (
+        "check-chunks",
+        &Router::new().post(&API_METHOD_CHECK_CHUNKSTORE),
+    ),
+    (
+        "copy-snapshot",
+        &Router::new().post(&API_METHOD_COPY_SNAPSHOT),
     ),
     (
         "download",
@@ -2302,6 +2836,14 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         "pxar-file-download",
         &Router::new().download(&API_METHOD_PXAR_FILE_DOWNLOAD),
     ),
+    (
+        "recompress-chunks",
+        &Router::new().post(&API_METHOD_RECOMPRESS_CHUNKS),
+    ),
+    (
+        "restore-backend",
+        &Router::new().post(&API_METHOD_RESTORE_SNAPSHOTS_FROM_BACKEND),
+    ),
     ("rrd", &Router::new().get(&API_METHOD_GET_RRD_STATS)),
     (
         "snapshots",
@@ -2310,6 +2852,7 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
             .delete(&API_METHOD_DELETE_SNAPSHOT),
     ),
     ("status", &Router::new().get(&API_METHOD_STATUS)),
+    ("trash", &crate::api2::admin::trash::ROUTER),
     (
         "upload-backup-log",
         &Router::new().upload(&API_METHOD_UPLOAD_BACKUP_LOG),
@@ -2324,3 +2867,49 @@ const DATASTORE_INFO_ROUTER: Router = Router::new()
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_GET_DATASTORE_LIST)
     .match_all("store", &DATASTORE_INFO_ROUTER);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn labels(list: &[&str]) -> Vec<String> {
+        list.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn test_update_snapshot_labels() {
+        let mut unprotected = json!({ "verify_state": { "state": "ok" } });
+
+        // add
+        update_snapshot_labels(&mut unprotected, labels(&["os=debian", "tier=gold"]));
+        assert_eq!(
+            unprotected,
+            json!({
+                "verify_state": { "state": "ok" },
+                "labels": { "os": "debian", "tier": "gold" },
+            })
+        );
+
+        // a duplicate key replaces the value, the last one given wins
+        update_snapshot_labels(&mut unprotected, labels(&["os=ubuntu", "os=alpine"]));
+        assert_eq!(
+            unprotected["labels"],
+            json!({ "os": "alpine", "tier": "gold" })
+        );
+
+        // remove, also of labels which are not set
+        update_snapshot_labels(&mut unprotected, labels(&["tier=", "unset="]));
+        assert_eq!(unprotected["labels"], json!({ "os": "alpine" }));
+
+        // removing the last label removes the whole object
+        update_snapshot_labels(&mut unprotected, labels(&["os="]));
+        assert_eq!(unprotected, json!({ "verify_state": { "state": "ok" } }));
+
+        // manifests without unprotected data
+        let mut unprotected = Value::Null;
+        update_snapshot_labels(&mut unprotected, labels(&["unset="]));
+        assert_eq!(unprotected, Value::Null);
+        update_snapshot_labels(&mut unprotected, labels(&["os=debian"]));
+        assert_eq!(unprotected, json!({ "labels": { "os": "debian" } }));
+    }
+}
@@ -0,0 +1,113 @@
+//! Garbage Collection Job Management
+
+use anyhow::{format_err, Error};
+use serde_json::Value;
+
+use proxmox_router::{list_subdirs_api_method, Permission, Router, RpcEnvironment, SubdirMap};
+use proxmox_schema::api;
+use proxmox_sys::sortable;
+
+use pbs_api_types::{
+    Authid, DataStoreConfig, GarbageCollectionJobStatus, JobHistory, DATASTORE_SCHEMA,
+    PRIV_DATASTORE_AUDIT,
+};
+use pbs_config::CachedUserInfo;
+
+use crate::server::jobstate::{compute_schedule_status, read_job_history, JobState};
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "List the garbage collection jobs of the datastores and their status (filtered by access)",
+        type: Array,
+        items: { type: GarbageCollectionJobStatus },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit on datastore.",
+    },
+)]
+/// List the garbage collection jobs of all datastores, with their next scheduled run
+pub fn list_gc_jobs(
+    store: Option<String>,
+    _param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<GarbageCollectionJobStatus>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, digest) = pbs_config::datastore::config()?;
+
+    let mut list = Vec::new();
+
+    for datastore in config.convert_to_typed_array::<DataStoreConfig>("datastore")? {
+        if let Some(store) = &store {
+            if &datastore.name != store {
+                continue;
+            }
+        }
+
+        let privs = user_info.lookup_privs(&auth_id, &["datastore", &datastore.name]);
+        if privs & PRIV_DATASTORE_AUDIT == 0 {
+            continue;
+        }
+
+        let last_state = JobState::load("garbage_collection", &datastore.name).map_err(|err| {
+            format_err!("could not open statefile for {}: {}", &datastore.name, err)
+        })?;
+
+        let status = compute_schedule_status(&last_state, datastore.gc_schedule.as_deref())?;
+
+        list.push(GarbageCollectionJobStatus {
+            store: datastore.name,
+            schedule: datastore.gc_schedule,
+            status,
+        });
+    }
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        type: JobHistory,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+    },
+)]
+/// Returns the recorded garbage collection runs of a datastore with aggregated statistics.
+pub fn gc_job_history(store: String) -> Result<JobHistory, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let _store: DataStoreConfig = config.lookup("datastore", &store)?;
+
+    read_job_history("garbage_collection", &store)
+}
+
+#[sortable]
+const GC_JOB_SUBDIRS: SubdirMap =
+    &sorted!([("history", &Router::new().get(&API_METHOD_GC_JOB_HISTORY)),]);
+
+const GC_JOB_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(GC_JOB_SUBDIRS))
+    .subdirs(GC_JOB_SUBDIRS);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_GC_JOBS)
+    .match_all("store", &GC_JOB_ROUTER);
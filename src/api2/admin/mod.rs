@@ -5,20 +5,25 @@ use proxmox_router::{Router, SubdirMap};
 use proxmox_sys::sortable;
 
 pub mod datastore;
+pub mod gc;
 pub mod metrics;
 pub mod namespace;
 pub mod prune;
 pub mod sync;
 pub mod traffic_control;
+pub mod transfer_accounting;
+pub mod trash;
 pub mod verify;
 
 #[sortable]
 const SUBDIRS: SubdirMap = &sorted!([
     ("datastore", &datastore::ROUTER),
+    ("gc", &gc::ROUTER),
     ("metrics", &metrics::ROUTER),
     ("prune", &prune::ROUTER),
     ("sync", &sync::ROUTER),
     ("traffic-control", &traffic_control::ROUTER),
+    ("transfer-accounting", &transfer_accounting::ROUTER),
     ("verify", &verify::ROUTER),
 ]);
 
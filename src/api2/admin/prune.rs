@@ -10,15 +10,15 @@ use proxmox_schema::api;
 use proxmox_sys::sortable;
 
 use pbs_api_types::{
-    Authid, PruneJobConfig, PruneJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_MODIFY,
+    Authid, JobHistory, PruneJobConfig, PruneJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA,
+    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY,
 };
 use pbs_config::prune;
 use pbs_config::CachedUserInfo;
 
 use crate::server::{
     do_prune_job,
-    jobstate::{compute_schedule_status, Job, JobState},
+    jobstate::{compute_schedule_status, read_job_history, Job, JobState},
 };
 
 #[api(
@@ -121,13 +121,57 @@ pub fn run_prune_job(
 
     let job = Job::new("prunejob", &id)?;
 
-    let upid_str = do_prune_job(job, prune_job.options, prune_job.store, &auth_id, None)?;
+    let upid_str = do_prune_job(
+        job,
+        prune_job.options,
+        prune_job.store,
+        prune_job.notify,
+        &auth_id,
+        None,
+    )?;
 
     Ok(upid_str)
 }
 
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            }
+        }
+    },
+    returns: {
+        type: JobHistory,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit or Datastore.Modify on job's datastore.",
+    },
+)]
+/// Returns the recorded runs of a prune job with aggregated statistics.
+pub fn prune_job_history(id: String, rpcenv: &mut dyn RpcEnvironment) -> Result<JobHistory, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, _digest) = prune::config()?;
+    let prune_job: PruneJobConfig = config.lookup("prune", &id)?;
+
+    user_info.check_privs(
+        &auth_id,
+        &prune_job.acl_path(),
+        PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_MODIFY,
+        true,
+    )?;
+
+    read_job_history("prunejob", &id)
+}
+
 #[sortable]
-const PRUNE_INFO_SUBDIRS: SubdirMap = &[("run", &Router::new().post(&API_METHOD_RUN_PRUNE_JOB))];
+const PRUNE_INFO_SUBDIRS: SubdirMap = &sorted!([
+    ("history", &Router::new().get(&API_METHOD_PRUNE_JOB_HISTORY)),
+    ("run", &Router::new().post(&API_METHOD_RUN_PRUNE_JOB)),
+]);
 
 const PRUNE_INFO_ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(PRUNE_INFO_SUBDIRS))
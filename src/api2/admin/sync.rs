@@ -10,7 +10,9 @@ use proxmox_router::{
 use proxmox_schema::api;
 use proxmox_sys::sortable;
 
-use pbs_api_types::{Authid, SyncJobConfig, SyncJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA};
+use pbs_api_types::{
+    Authid, JobHistory, SyncJobConfig, SyncJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA,
+};
 use pbs_config::sync;
 use pbs_config::CachedUserInfo;
 
@@ -19,7 +21,7 @@ use crate::{
         config::sync::{check_sync_job_modify_access, check_sync_job_read_access},
         pull::do_sync_job,
     },
-    server::jobstate::{compute_schedule_status, Job, JobState},
+    server::jobstate::{compute_schedule_status, read_job_history, Job, JobState},
 };
 
 #[api(
@@ -92,7 +94,7 @@ pub fn list_sync_jobs(
         }
     },
     access: {
-        description: "User needs Datastore.Backup on target datastore, and Remote.Read on source remote. Additionally, remove_vanished requires Datastore.Prune, and any owner other than the user themselves requires Datastore.Modify",
+        description: "User needs Datastore.Backup on target datastore, and Remote.Read on source remote. Additionally, remove_vanished requires Datastore.Prune, and any owner other than the user themselves requires Datastore.Modify. Push jobs require Datastore.Read on the source datastore and Remote.Modify on the target remote instead",
         permission: &Permission::Anybody,
     },
 )]
@@ -121,8 +123,42 @@ pub fn run_sync_job(
     Ok(upid_str)
 }
 
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            }
+        }
+    },
+    returns: {
+        type: JobHistory,
+    },
+    access: {
+        description: "Limited to sync jobs where user has Datastore.Audit on target datastore, and Remote.Audit on source remote.",
+        permission: &Permission::Anybody,
+    },
+)]
+/// Returns the recorded runs of a sync job with aggregated statistics.
+pub fn sync_job_history(id: String, rpcenv: &mut dyn RpcEnvironment) -> Result<JobHistory, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, _digest) = sync::config()?;
+    let sync_job: SyncJobConfig = config.lookup("sync", &id)?;
+
+    if !check_sync_job_read_access(&user_info, &auth_id, &sync_job) {
+        bail!("permission check failed");
+    }
+
+    read_job_history("syncjob", &id)
+}
+
 #[sortable]
-const SYNC_INFO_SUBDIRS: SubdirMap = &[("run", &Router::new().post(&API_METHOD_RUN_SYNC_JOB))];
+const SYNC_INFO_SUBDIRS: SubdirMap = &sorted!([
+    ("history", &Router::new().get(&API_METHOD_SYNC_JOB_HISTORY)),
+    ("run", &Router::new().post(&API_METHOD_RUN_SYNC_JOB)),
+]);
 
 const SYNC_INFO_ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SYNC_INFO_SUBDIRS))
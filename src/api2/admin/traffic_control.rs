@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use proxmox_router::{Permission, Router, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::{TrafficControlRule, PRIV_SYS_AUDIT};
+use pbs_api_types::{HumanByte, TrafficControlRule, PRIV_SYS_AUDIT};
 
 use crate::traffic_control_cache::TRAFFIC_CONTROL_CACHE;
 
@@ -25,6 +25,24 @@ pub struct TrafficControlCurrentRate {
     cur_rate_in: u64,
     /// Current egress rate in bytes/second
     cur_rate_out: u64,
+    /// Number of connections currently limited by the rule
+    connections: u64,
+    /// Ingress rate per limited connection, relative to the configured rate (0 - 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection_ratio_in: Option<f64>,
+    /// Egress rate per limited connection, relative to the configured rate (0 - 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection_ratio_out: Option<f64>,
+}
+
+// Ratio of the current rate to the configured limit. Rules which are not shared limit each of
+// their connections separately, so the rate is averaged over those connections.
+fn connection_ratio(cur_rate: u64, rate: Option<HumanByte>, limits: u64) -> Option<f64> {
+    let limit = rate?.as_f64() * (limits.max(1) as f64);
+    if limit <= 0.0 {
+        return None;
+    }
+    Some((cur_rate as f64 / limit).min(1.0))
 }
 
 #[api(
@@ -55,14 +73,25 @@ pub fn show_current_traffic(
     let mut list = Vec::new();
 
     for config in rules {
-        let (cur_rate_in, cur_rate_out) = match cache.current_rate_map().get(&config.name) {
-            None => (0, 0),
-            Some(state) => (state.rate_in, state.rate_out),
+        let (cur_rate_in, cur_rate_out, connections) =
+            match cache.current_rate_map().get(&config.name) {
+                None => (0, 0, 0),
+                Some(state) => (state.rate_in, state.rate_out, state.connections),
+            };
+        let limits = if config.shared.unwrap_or(true) {
+            1
+        } else {
+            connections
         };
+        let connection_ratio_in = connection_ratio(cur_rate_in, config.limit.rate_in, limits);
+        let connection_ratio_out = connection_ratio(cur_rate_out, config.limit.rate_out, limits);
         list.push(TrafficControlCurrentRate {
             config,
             cur_rate_in,
             cur_rate_out,
+            connections,
+            connection_ratio_in,
+            connection_ratio_out,
         });
     }
 
@@ -0,0 +1,59 @@
+//! Per-client transfer accounting
+
+use anyhow::Error;
+
+use proxmox_router::{Permission, Router};
+use proxmox_schema::api;
+
+use pbs_api_types::{Authid, ClientTransferStats, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY};
+
+use crate::server::transfer_accounting;
+
+#[api(
+    input: {
+        properties: {
+            "auth-id": {
+                type: Authid,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "Transferred bytes per client.",
+        type: Array,
+        items: {
+            type: ClientTransferStats,
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List the bytes uploaded and downloaded by each client, grouped by Authid and source IP.
+pub fn list_transfer_accounting(
+    auth_id: Option<Authid>,
+) -> Result<Vec<ClientTransferStats>, Error> {
+    let mut list = transfer_accounting::read_transfer_accounting()?;
+
+    if let Some(auth_id) = auth_id {
+        list.retain(|entry| entry.auth_id == auth_id);
+    }
+
+    list.sort_by(|a, b| (b.uploaded + b.downloaded).cmp(&(a.uploaded + a.downloaded)));
+
+    Ok(list)
+}
+
+#[api(
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Reset the transfer accounting of all clients.
+pub fn reset_transfer_accounting() -> Result<(), Error> {
+    transfer_accounting::reset_transfer_accounting()
+}
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_TRANSFER_ACCOUNTING)
+    .delete(&API_METHOD_RESET_TRANSFER_ACCOUNTING);
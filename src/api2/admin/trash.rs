@@ -0,0 +1,145 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use pbs_config::CachedUserInfo;
+use proxmox_router::{Permission, Router, RpcEnvironment, SubdirMap};
+use proxmox_schema::*;
+
+use pbs_api_types::{
+    Authid, BackupNamespace, Operation, TrashListItem, DATASTORE_SCHEMA, PRIV_DATASTORE_AUDIT,
+    PRIV_DATASTORE_MODIFY,
+};
+
+use pbs_datastore::DataStore;
+
+use crate::backup::check_ns_privs;
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+        },
+    },
+    returns: pbs_api_types::ADMIN_DATASTORE_LIST_TRASH_RETURN_TYPE,
+    access: {
+        permission: &Permission::Anybody,
+        description: "Only lists snapshots of namespaces with DATASTORE_AUDIT or DATASTORE_MODIFY \
+            on /datastore/{store}[/{namespace}]",
+    },
+)]
+/// List the removed snapshots in the trash of a datastore.
+pub fn list_trash(
+    store: String,
+    ns: Option<BackupNamespace>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<TrashListItem>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    let list = datastore
+        .list_trash()?
+        .into_iter()
+        .filter(|item| match &ns {
+            Some(ns) => &item.ns == ns,
+            None => true,
+        })
+        .filter(|item| {
+            let privs = user_info.lookup_privs(&auth_id, &item.ns.acl_path(&store));
+            privs & (PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_MODIFY) != 0
+        })
+        .collect();
+
+    Ok(list)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires DATASTORE_MODIFY on /datastore/{store}[/{namespace}]",
+    },
+)]
+/// Restore a removed snapshot from the trash.
+pub fn restore_from_trash(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    check_ns_privs(&store, &ns, &auth_id, PRIV_DATASTORE_MODIFY)?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+    datastore.restore_from_trash(&ns, &backup_dir)?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires DATASTORE_MODIFY on /datastore/{store}[/{namespace}]",
+    },
+)]
+/// Permanently remove a snapshot from the trash.
+pub fn purge_from_trash(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    check_ns_privs(&store, &ns, &auth_id, PRIV_DATASTORE_MODIFY)?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+
+    datastore.purge_from_trash(&ns, &backup_dir)?;
+
+    Ok(Value::Null)
+}
+
+const SUBDIRS: SubdirMap = &[(
+    "restore",
+    &Router::new().post(&API_METHOD_RESTORE_FROM_TRASH),
+)];
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_TRASH)
+    .delete(&API_METHOD_PURGE_FROM_TRASH)
+    .subdirs(SUBDIRS);
@@ -11,15 +11,15 @@ use proxmox_schema::api;
 use proxmox_sys::sortable;
 
 use pbs_api_types::{
-    Authid, VerificationJobConfig, VerificationJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA,
-    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_VERIFY,
+    Authid, JobHistory, VerificationJobConfig, VerificationJobStatus, DATASTORE_SCHEMA,
+    JOB_ID_SCHEMA, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_VERIFY,
 };
 use pbs_config::verify;
 use pbs_config::CachedUserInfo;
 
 use crate::server::{
     do_verification_job,
-    jobstate::{compute_schedule_status, Job, JobState},
+    jobstate::{compute_schedule_status, read_job_history, Job, JobState},
 };
 
 #[api(
@@ -129,9 +129,51 @@ pub fn run_verification_job(
     Ok(upid_str)
 }
 
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            }
+        }
+    },
+    returns: {
+        type: JobHistory,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit or Datastore.Verify on job's datastore.",
+    },
+)]
+/// Returns the recorded runs of a verification job with aggregated statistics.
+pub fn verification_job_history(
+    id: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<JobHistory, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, _digest) = verify::config()?;
+    let verification_job: VerificationJobConfig = config.lookup("verification", &id)?;
+
+    user_info.check_privs(
+        &auth_id,
+        &verification_job.acl_path(),
+        PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_VERIFY,
+        true,
+    )?;
+
+    read_job_history("verificationjob", &id)
+}
+
 #[sortable]
-const VERIFICATION_INFO_SUBDIRS: SubdirMap =
-    &[("run", &Router::new().post(&API_METHOD_RUN_VERIFICATION_JOB))];
+const VERIFICATION_INFO_SUBDIRS: SubdirMap = &sorted!([
+    (
+        "history",
+        &Router::new().get(&API_METHOD_VERIFICATION_JOB_HISTORY)
+    ),
+    ("run", &Router::new().post(&API_METHOD_RUN_VERIFICATION_JOB)),
+]);
 
 const VERIFICATION_INFO_ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(VERIFICATION_INFO_SUBDIRS))
@@ -1,22 +1,32 @@
 use anyhow::{bail, format_err, Error};
 use nix::dir::Dir;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use ::serde::Serialize;
 use serde_json::{json, Value};
 
 use proxmox_router::{RpcEnvironment, RpcEnvironmentType};
-use proxmox_sys::fs::{replace_file, CreateOptions};
+use proxmox_sys::fs::{file_get_optional_contents, replace_file, CreateOptions};
 
 use pbs_api_types::Authid;
 use pbs_datastore::backup_info::{BackupDir, BackupInfo};
 use pbs_datastore::dynamic_index::DynamicIndexWriter;
 use pbs_datastore::fixed_index::FixedIndexWriter;
+use pbs_datastore::protocol::{
+    decode_partial_index, encode_partial_index, encode_resume_chunks, partial_index_prefix,
+    strip_incomplete_record, ChunkCompression, ChunkIntegrity, PARTIAL_INDEX_EXTENSION,
+    PARTIAL_INDEX_RECORD_SIZE, RESUME_CHUNKS_NAME,
+};
 use pbs_datastore::{DataBlob, DataStore};
 use proxmox_rest_server::{formatter::*, WorkerTask};
 
 use crate::backup::verify_backup_dir_with_lock;
+use crate::server::transfer_accounting::{record_transfer, TransferSession};
 
 use hyper::{Body, Response};
 
@@ -58,6 +68,8 @@ struct DynamicWriterState {
     offset: u64,
     chunk_count: u64,
     upload_stat: UploadStatistic,
+    partial: Option<File>,         // partial index of a resumable backup
+    resumed: Vec<(u64, [u8; 32])>, // entries kept from the interrupted backup
 }
 
 struct FixedWriterState {
@@ -69,6 +81,8 @@ struct FixedWriterState {
     small_chunk_count: usize, // allow 0..1 small chunks (last chunk may be smaller)
     upload_stat: UploadStatistic,
     incremental: bool,
+    partial: Option<File>,         // partial index of a resumable backup
+    resumed: Vec<(u64, [u8; 32])>, // entries kept from the interrupted backup
 }
 
 // key=digest, value=length
@@ -83,6 +97,8 @@ struct SharedBackupState {
     known_chunks: KnownChunksMap,
     backup_size: u64, // sums up size of all files
     backup_stat: UploadStatistic,
+    transferred_bytes: u64,      // bytes received from the client
+    resume_chunks: Option<File>, // list of uploaded chunks of a resumable backup
 }
 
 impl SharedBackupState {
@@ -107,12 +123,16 @@ pub struct BackupEnvironment {
     env_type: RpcEnvironmentType,
     result_attributes: Value,
     auth_id: Authid,
+    pub client_ip: Option<IpAddr>,
     pub debug: bool,
     pub formatter: &'static dyn OutputFormatter,
     pub worker: Arc<WorkerTask>,
     pub datastore: Arc<DataStore>,
     pub backup_dir: BackupDir,
     pub last_backup: Option<BackupInfo>,
+    pub chunk_integrity: Option<ChunkIntegrity>,
+    pub chunk_compression: Vec<ChunkCompression>,
+    pub resumable: bool,
     state: Arc<Mutex<SharedBackupState>>,
 }
 
@@ -133,18 +153,24 @@ impl BackupEnvironment {
             known_chunks: HashMap::new(),
             backup_size: 0,
             backup_stat: UploadStatistic::new(),
+            transferred_bytes: 0,
+            resume_chunks: None,
         };
 
         Self {
             result_attributes: json!({}),
             env_type,
             auth_id,
+            client_ip: None,
             worker,
             datastore,
             debug: false,
             formatter: JSON_FORMATTER,
             backup_dir,
             last_backup: None,
+            chunk_integrity: None,
+            chunk_compression: ChunkCompression::LEGACY.to_vec(),
+            resumable: false,
             state: Arc::new(Mutex::new(state)),
         }
     }
@@ -163,6 +189,58 @@ impl BackupEnvironment {
         Ok(())
     }
 
+    /// Path of the list of chunks uploaded so far, if the backup is resumable.
+    pub fn resume_chunks_path(&self) -> PathBuf {
+        let mut path = self.backup_dir.full_path();
+        path.push(RESUME_CHUNKS_NAME);
+        path
+    }
+
+    fn partial_index_path(&self, name: &str) -> PathBuf {
+        let mut path = self.backup_dir.full_path();
+        path.push(format!("{}{}", name, PARTIAL_INDEX_EXTENSION));
+        path
+    }
+
+    /// Make the backup resumable, registering the `chunks` uploaded by the interrupted backup it
+    /// resumes, as far as they still exist.
+    ///
+    /// Chunks uploaded from now on are appended to the list, so that it is complete even if the
+    /// backup or the whole server process dies. Returns the number of registered chunks.
+    pub fn start_resumable(&self, chunks: Vec<([u8; 32], u32)>) -> Result<usize, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        // an interrupted server leaves temporary index files behind
+        remove_unresumable_files(&self.backup_dir.full_path())?;
+
+        for (digest, size) in chunks {
+            // touching the chunk also protects it from a running garbage collection
+            if self.datastore.cond_touch_chunk(&digest, false)? {
+                state.known_chunks.insert(digest, size);
+            }
+        }
+
+        let path = self.resume_chunks_path();
+        let data = encode_resume_chunks(&state.known_chunks);
+        state.resume_chunks = Some(rewrite_record_file(&path, &data)?);
+
+        Ok(state.known_chunks.len())
+    }
+
+    /// Returns the entries of the partial index of writer `wid` which were kept from the
+    /// interrupted backup, encoded as stored in the partial index file.
+    pub fn resumed_index_entries(&self, wid: usize) -> Result<Vec<u8>, Error> {
+        let state = self.state.lock().unwrap();
+
+        if let Some(data) = state.dynamic_writers.get(&wid) {
+            Ok(encode_partial_index(&data.resumed))
+        } else if let Some(data) = state.fixed_writers.get(&wid) {
+            Ok(encode_partial_index(&data.resumed))
+        } else {
+            bail!("writer '{}' not registered", wid);
+        }
+    }
+
     /// Register fixed length chunks after upload.
     ///
     /// Like `register_chunk()`, but additionally record statistics for
@@ -211,9 +289,16 @@ impl BackupEnvironment {
             data.upload_stat.duplicates += 1;
         }
 
+        state.transferred_bytes += compressed_size as u64;
+
         // register chunk
         state.known_chunks.insert(digest, size);
 
+        if let Some(file) = state.resume_chunks.as_mut() {
+            file.write_all(&encode_resume_chunks([(&digest, &size)]))
+                .map_err(|err| format_err!("unable to record uploaded chunk - {}", err))?;
+        }
+
         Ok(())
     }
 
@@ -246,9 +331,16 @@ impl BackupEnvironment {
             data.upload_stat.duplicates += 1;
         }
 
+        state.transferred_bytes += compressed_size as u64;
+
         // register chunk
         state.known_chunks.insert(digest, size);
 
+        if let Some(file) = state.resume_chunks.as_mut() {
+            file.write_all(&encode_resume_chunks([(&digest, &size)]))
+                .map_err(|err| format_err!("unable to record uploaded chunk - {}", err))?;
+        }
+
         Ok(())
     }
 
@@ -261,7 +353,7 @@ impl BackupEnvironment {
     /// Store the writer with an unique ID
     pub fn register_dynamic_writer(
         &self,
-        index: DynamicIndexWriter,
+        mut index: DynamicIndexWriter,
         name: String,
     ) -> Result<usize, Error> {
         let mut state = self.state.lock().unwrap();
@@ -270,14 +362,39 @@ impl BackupEnvironment {
 
         let uid = state.next_uid();
 
+        let mut offset = 0;
+        let (partial, resumed) = if self.resumable {
+            let path = self.partial_index_path(&name);
+            let resumed = load_partial_index(&path, None, &state.known_chunks)?;
+            for (end, digest) in resumed.iter() {
+                index.add_chunk(*end, digest)?;
+                offset = *end;
+            }
+            let file = rewrite_record_file(&path, &encode_partial_index(&resumed))?;
+            (Some(file), resumed)
+        } else {
+            (None, Vec::new())
+        };
+
+        if !resumed.is_empty() {
+            self.log(format!(
+                "resuming dynamic index '{}' with {} chunks ({} bytes)",
+                name,
+                resumed.len(),
+                offset
+            ));
+        }
+
         state.dynamic_writers.insert(
             uid,
             DynamicWriterState {
                 index,
                 name,
-                offset: 0,
-                chunk_count: 0,
+                offset,
+                chunk_count: resumed.len() as u64,
                 upload_stat: UploadStatistic::new(),
+                partial,
+                resumed,
             },
         );
 
@@ -287,7 +404,7 @@ impl BackupEnvironment {
     /// Store the writer with an unique ID
     pub fn register_fixed_writer(
         &self,
-        index: FixedIndexWriter,
+        mut index: FixedIndexWriter,
         name: String,
         size: usize,
         chunk_size: u32,
@@ -299,17 +416,43 @@ impl BackupEnvironment {
 
         let uid = state.next_uid();
 
+        // incremental backups only upload changed chunks, there is no prefix to resume from
+        let (partial, resumed) = if self.resumable && !incremental {
+            let path = self.partial_index_path(&name);
+            let mut resumed =
+                load_partial_index(&path, Some(chunk_size as u64), &state.known_chunks)?;
+            resumed.retain(|(end, _digest)| *end <= size as u64);
+            for (end, digest) in resumed.iter() {
+                let idx = index.check_chunk_alignment(*end as usize, chunk_size as usize)?;
+                index.add_digest(idx, digest)?;
+            }
+            let file = rewrite_record_file(&path, &encode_partial_index(&resumed))?;
+            (Some(file), resumed)
+        } else {
+            (None, Vec::new())
+        };
+
+        if !resumed.is_empty() {
+            self.log(format!(
+                "resuming fixed index '{}' with {} chunks",
+                name,
+                resumed.len()
+            ));
+        }
+
         state.fixed_writers.insert(
             uid,
             FixedWriterState {
                 index,
                 name,
-                chunk_count: 0,
+                chunk_count: resumed.len() as u64,
                 size,
                 chunk_size,
                 small_chunk_count: 0,
                 upload_stat: UploadStatistic::new(),
                 incremental,
+                partial,
+                resumed,
             },
         );
 
@@ -347,6 +490,11 @@ impl BackupEnvironment {
 
         data.index.add_chunk(data.offset, digest)?;
 
+        if let Some(file) = data.partial.as_mut() {
+            file.write_all(&encode_partial_index(&[(data.offset, *digest)]))
+                .map_err(|err| format_err!("unable to record index entry - {}", err))?;
+        }
+
         Ok(())
     }
 
@@ -374,6 +522,11 @@ impl BackupEnvironment {
 
         data.index.add_digest(idx, digest)?;
 
+        if let Some(file) = data.partial.as_mut() {
+            file.write_all(&encode_partial_index(&[(end as u64, *digest)]))
+                .map_err(|err| format_err!("unable to record index entry - {}", err))?;
+        }
+
         Ok(())
     }
 
@@ -586,6 +739,7 @@ impl BackupEnvironment {
         state.file_counter += 1;
         state.backup_size += orig_len as u64;
         state.backup_stat.size += blob_len as u64;
+        state.transferred_bytes += blob_len as u64;
 
         Ok(())
     }
@@ -613,6 +767,9 @@ impl BackupEnvironment {
             })
             .map_err(|err| format_err!("unable to update manifest blob - {}", err))?;
 
+        self.datastore
+            .store_snapshot_in_backend(self.backup_dir.backup_ns(), self.backup_dir.as_ref())?;
+
         if let Some(base) = &self.last_backup {
             let path = base.backup_dir.full_path();
             if !path.exists() {
@@ -625,6 +782,12 @@ impl BackupEnvironment {
 
         self.datastore.try_ensure_sync_level()?;
 
+        if self.resumable {
+            state.resume_chunks = None;
+            remove_resume_files(&self.backup_dir.full_path())
+                .map_err(|err| format_err!("unable to remove resume files - {}", err))?;
+        }
+
         // marks the backup as successful
         state.finished = true;
 
@@ -707,6 +870,75 @@ impl BackupEnvironment {
         state.finished
     }
 
+    /// Add the bytes received in this session to the per-client transfer accounting
+    pub fn record_transfer_accounting(&self) {
+        let transferred = self.state.lock().unwrap().transferred_bytes;
+        if let Err(err) = record_transfer(
+            &self.auth_id,
+            self.client_ip,
+            TransferSession::Backup,
+            transferred,
+        ) {
+            self.log(format!("updating transfer accounting failed: {}", err));
+        }
+    }
+
+    /// Remove all files of the failed backup, except for the list of chunks uploaded so far and
+    /// the partial indexes, which allow to resume it.
+    pub fn keep_for_resume(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.finished = true;
+
+        // dropping the open writers removes their temporary files
+        state.dynamic_writers.clear();
+        state.fixed_writers.clear();
+        state.resume_chunks = None;
+
+        remove_unresumable_files(&self.backup_dir.full_path())
+    }
+
+    /// Remove unfinished snapshots of the group left behind by older resumable backups, which
+    /// were never resumed. Only call this after the backup finished.
+    pub fn remove_abandoned_snapshots(&self) {
+        let group = self.datastore.backup_group(
+            self.backup_dir.backup_ns().clone(),
+            self.backup_dir.group().clone(),
+        );
+
+        let list = match group.list_backups() {
+            Ok(list) => list,
+            Err(err) => {
+                self.log(format!("unable to list snapshots of group - {}", err));
+                return;
+            }
+        };
+
+        for info in list {
+            if info.is_finished()
+                || info.backup_dir.backup_time() >= self.backup_dir.backup_time()
+                || !info
+                    .backup_dir
+                    .full_path()
+                    .join(RESUME_CHUNKS_NAME)
+                    .exists()
+            {
+                continue;
+            }
+
+            self.log(format!(
+                "removing abandoned resumable snapshot {}",
+                info.backup_dir.dir()
+            ));
+            if let Err(err) = info.backup_dir.destroy(false) {
+                self.log(format!(
+                    "unable to remove snapshot {} - {}",
+                    info.backup_dir.dir(),
+                    err
+                ));
+            }
+        }
+    }
+
     /// Remove complete backup
     pub fn remove_backup(&self) -> Result<(), Error> {
         let mut state = self.state.lock().unwrap();
@@ -722,6 +954,73 @@ impl BackupEnvironment {
     }
 }
 
+fn is_resume_file(name: &std::ffi::OsStr) -> bool {
+    name == RESUME_CHUNKS_NAME
+        || name
+            .to_str()
+            .map(|name| name.ends_with(PARTIAL_INDEX_EXTENSION))
+            .unwrap_or(false)
+}
+
+// remove all files of a snapshot directory, except for the ones needed to resume the backup
+fn remove_unresumable_files(path: &Path) -> Result<(), Error> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && !is_resume_file(&entry.file_name()) {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_resume_files(path: &Path) -> Result<(), Error> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && is_resume_file(&entry.file_name()) {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+// atomically replace a list of records with `data` and open it for appending further records
+fn rewrite_record_file(path: &Path, data: &[u8]) -> Result<File, Error> {
+    replace_file(path, data, CreateOptions::new(), false)?;
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(|err| format_err!("unable to open {:?} - {}", path, err))
+}
+
+/// Load the entries of a partial index which a resumed backup can append to, stopping at the
+/// first entry whose chunk is not known or does not fit its offset.
+fn load_partial_index(
+    path: &Path,
+    chunk_size: Option<u64>,
+    known_chunks: &KnownChunksMap,
+) -> Result<Vec<(u64, [u8; 32])>, Error> {
+    let data = match file_get_optional_contents(path)? {
+        Some(data) => data,
+        None => return Ok(Vec::new()),
+    };
+
+    let entries = decode_partial_index(strip_incomplete_record(&data, PARTIAL_INDEX_RECORD_SIZE))?;
+
+    let mut start = 0;
+    let mut resumed = partial_index_prefix(entries, chunk_size);
+    let len = resumed
+        .iter()
+        .take_while(|(end, digest)| {
+            let expected = chunk_size.unwrap_or(end - start);
+            start = *end;
+            known_chunks.get(digest).map(|size| *size as u64) == Some(expected)
+        })
+        .count();
+    resumed.truncate(len);
+
+    Ok(resumed)
+}
+
 impl RpcEnvironment for BackupEnvironment {
     fn result_attrib_mut(&mut self) -> &mut Value {
         &mut self.result_attributes
@@ -755,3 +1054,58 @@ impl AsRef<BackupEnvironment> for Box<dyn RpcEnvironment> {
         self.as_any().downcast_ref::<BackupEnvironment>().unwrap()
     }
 }
+
+#[test]
+fn test_load_partial_index() -> Result<(), Error> {
+    let testdir = std::fs::canonicalize(".")?.join(".testdir-partial-index");
+    let _ = std::fs::remove_dir_all(&testdir);
+    std::fs::create_dir_all(&testdir)?;
+
+    let path = testdir.join("root.pxar.didx.partial");
+    let known_chunks: KnownChunksMap = [([1u8; 32], 100), ([2u8; 32], 50), ([4u8; 32], 10)]
+        .into_iter()
+        .collect();
+
+    // no partial index yet
+    assert!(load_partial_index(&path, None, &known_chunks)?.is_empty());
+
+    // entries are appended while uploading, the last one got cut off by a crash
+    let mut file = rewrite_record_file(&path, &[])?;
+    file.write_all(&encode_partial_index(&[(100, [1u8; 32]), (150, [2u8; 32])]))?;
+    file.write_all(&encode_partial_index(&[(160, [4u8; 32])])[..20])?;
+    assert_eq!(
+        load_partial_index(&path, None, &known_chunks)?,
+        vec![(100, [1u8; 32]), (150, [2u8; 32])],
+    );
+
+    // unknown chunks and chunks with a mismatching size end the usable prefix
+    let mut file = rewrite_record_file(&path, &[])?;
+    file.write_all(&encode_partial_index(&[
+        (100, [1u8; 32]),
+        (150, [3u8; 32]),
+        (160, [4u8; 32]),
+    ]))?;
+    assert_eq!(
+        load_partial_index(&path, None, &known_chunks)?,
+        vec![(100, [1u8; 32])],
+    );
+    assert!(load_partial_index(&path, Some(50), &known_chunks)?.is_empty());
+
+    // only the resume files are kept for a later resume, and removed after finishing
+    std::fs::write(testdir.join(RESUME_CHUNKS_NAME), b"")?;
+    std::fs::write(testdir.join("root.pxar.tmp_didx"), b"")?;
+    std::fs::write(testdir.join("index.json.blob"), b"")?;
+    remove_unresumable_files(&testdir)?;
+    let mut names: Vec<_> = std::fs::read_dir(&testdir)?
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, [RESUME_CHUNKS_NAME, "root.pxar.didx.partial"]);
+
+    remove_resume_files(&testdir)?;
+    assert_eq!(std::fs::read_dir(&testdir)?.count(), 0);
+
+    std::fs::remove_dir_all(&testdir)?;
+
+    Ok(())
+}
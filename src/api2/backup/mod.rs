@@ -3,7 +3,7 @@
 use anyhow::{bail, format_err, Error};
 use futures::*;
 use hex::FromHex;
-use hyper::header::{HeaderValue, UPGRADE};
+use hyper::header::{HeaderValue, CONTENT_TYPE, UPGRADE};
 use hyper::http::request::Parts;
 use hyper::{Body, Request, Response, StatusCode};
 use serde::Deserialize;
@@ -17,17 +17,25 @@ use proxmox_schema::*;
 use proxmox_sys::sortable;
 
 use pbs_api_types::{
-    Authid, BackupNamespace, BackupType, Operation, SnapshotVerifyState, VerifyState,
+    Authid, BackupNamespace, BackupType, CryptCipher, Operation, SnapshotVerifyState, VerifyState,
     BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
     BACKUP_TYPE_SCHEMA, CHUNK_DIGEST_SCHEMA, DATASTORE_SCHEMA, PRIV_DATASTORE_BACKUP,
 };
 use pbs_config::CachedUserInfo;
 use pbs_datastore::index::IndexFile;
-use pbs_datastore::manifest::{archive_type, ArchiveType};
+use pbs_datastore::manifest::{archive_type, ArchiveType, PayloadCompression};
+use pbs_datastore::protocol::{
+    decode_resume_chunks, feature_offer, negotiate_feature, negotiate_feature_set,
+    strip_incomplete_record, ChunkCompression, ChunkIntegrity, ClientLog, CHUNK_COMPRESSION_HEADER,
+    CHUNK_COMPRESSION_LEVEL_HEADER, CHUNK_INTEGRITY_HEADER, CIPHER_HEADER, CLIENT_LOG_HEADER,
+    PAYLOAD_COMPRESSION_HEADER, RESUME_CHUNKS_NAME, RESUME_CHUNK_RECORD_SIZE,
+};
 use pbs_datastore::{DataStore, PROXMOX_BACKUP_PROTOCOL_ID_V1};
 use pbs_tools::json::{required_array_param, required_integer_param, required_string_param};
 use proxmox_rest_server::{H2Service, WorkerTask};
-use proxmox_sys::fs::lock_dir_noblock_shared;
+use proxmox_sys::fs::{file_get_optional_contents, lock_dir_noblock_shared};
+
+use crate::traffic_control_cache::register_peer_session;
 
 mod environment;
 use environment::*;
@@ -50,6 +58,9 @@ pub const API_METHOD_UPGRADE_BACKUP: ApiMethod = ApiMethod::new(
             ("backup-time", false, &BACKUP_TIME_SCHEMA),
             ("debug", true, &BooleanSchema::new("Enable verbose debug logging.").schema()),
             ("benchmark", true, &BooleanSchema::new("Job is a benchmark (do not keep data).").schema()),
+            ("resume", true, &BooleanSchema::new(
+                "Keep the uploaded chunks if the backup fails, and resume an interrupted backup of \
+                the same snapshot.").schema()),
         ]),
     )
 ).access(
@@ -76,6 +87,7 @@ fn upgrade_to_backup_protocol(
     async move {
         let debug = param["debug"].as_bool().unwrap_or(false);
         let benchmark = param["benchmark"].as_bool().unwrap_or(false);
+        let resume = param["resume"].as_bool().unwrap_or(false);
 
         let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
 
@@ -96,6 +108,10 @@ fn upgrade_to_backup_protocol(
 
         let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
 
+        if !benchmark {
+            datastore.check_quota()?;
+        }
+
         let protocols = parts
             .headers
             .get("UPGRADE")
@@ -106,6 +122,37 @@ fn upgrade_to_backup_protocol(
             bail!("invalid protocol name");
         }
 
+        let chunk_integrity = match parts.headers.get(CHUNK_INTEGRITY_HEADER) {
+            Some(offer) => negotiate_feature::<ChunkIntegrity>(offer.to_str()?),
+            None => None,
+        };
+
+        // the datastore tuning is only a hint for clients taking part in the negotiation, already
+        // compressed chunks (for example pushed from another datastore) are accepted anyway
+        let compression_level = datastore.chunk_compression_level();
+        let chunk_compression = match parts.headers.get(CHUNK_COMPRESSION_HEADER) {
+            Some(offer) => Some(negotiate_feature_set::<ChunkCompression>(offer.to_str()?)),
+            None => None,
+        };
+
+        // we only need to confirm these, readers have to offer them again
+        let payload_compression = match parts.headers.get(PAYLOAD_COMPRESSION_HEADER) {
+            Some(offer) => Some(negotiate_feature_set::<PayloadCompression>(offer.to_str()?)),
+            None => None,
+        };
+
+        // blobs encrypted with any of the supported ciphers are accepted, just confirm them
+        let ciphers = match parts.headers.get(CIPHER_HEADER) {
+            Some(offer) => Some(negotiate_feature_set::<CryptCipher>(offer.to_str()?)),
+            None => None,
+        };
+
+        // a log uploaded after the backup gets merged into the archive statistics
+        let client_log = match parts.headers.get(CLIENT_LOG_HEADER) {
+            Some(offer) => negotiate_feature::<ClientLog>(offer.to_str()?),
+            None => None,
+        };
+
         if parts.version >= http::version::Version::HTTP_2 {
             bail!(
                 "unexpected http version '{:?}' (expected version < 2)",
@@ -121,6 +168,8 @@ fn upgrade_to_backup_protocol(
         let worker_id = format!("{}:{}/{}", store, backup_dir_arg.ty(), backup_dir_arg.id());
 
         let env_type = rpcenv.env_type();
+        let peer = rpcenv.get_client_ip();
+        let client_ip = peer.map(|addr| addr.ip());
 
         let backup_group = datastore.backup_group(backup_ns, backup_dir_arg.group.clone());
 
@@ -138,6 +187,10 @@ fn upgrade_to_backup_protocol(
             "backup"
         };
 
+        if benchmark && resume {
+            bail!("unable to resume a benchmark");
+        }
+
         // lock backup group to only allow one backup per group at a time
         let (owner, _group_guard) = datastore.create_locked_backup_group(
             backup_group.backup_ns(),
@@ -193,9 +246,23 @@ fn upgrade_to_backup_protocol(
 
         let (path, is_new, snap_guard) =
             datastore.create_locked_backup_dir(backup_dir.backup_ns(), backup_dir.as_ref())?;
-        if !is_new {
-            bail!("backup directory already exists.");
-        }
+
+        let resume_chunks = if is_new {
+            Vec::new()
+        } else {
+            let mut resume_path = backup_dir.full_path();
+            resume_path.push(RESUME_CHUNKS_NAME);
+            match file_get_optional_contents(&resume_path)? {
+                // the list is appended to while uploading, and may end with a partial record
+                Some(data) if resume => {
+                    decode_resume_chunks(strip_incomplete_record(&data, RESUME_CHUNK_RECORD_SIZE))?
+                }
+                _ => bail!("backup directory already exists."),
+            }
+        };
+
+        // also confirmed in the response below
+        let worker_chunk_compression = chunk_compression.clone();
 
         WorkerTask::spawn(
             worker_type,
@@ -203,6 +270,9 @@ fn upgrade_to_backup_protocol(
             auth_id.to_string(),
             true,
             move |worker| {
+                // apply datastore and user specific traffic control rules to this connection
+                let tc_guard = peer.map(|peer| register_peer_session(peer, &store, &auth_id));
+
                 let mut env = BackupEnvironment::new(
                     env_type,
                     auth_id,
@@ -213,11 +283,42 @@ fn upgrade_to_backup_protocol(
 
                 env.debug = debug;
                 env.last_backup = last_backup;
+                env.client_ip = client_ip;
+                env.chunk_integrity = chunk_integrity;
+                env.resumable = resume;
+                if let Some(chunk_compression) = worker_chunk_compression {
+                    env.chunk_compression = chunk_compression;
+                }
 
-                env.log(format!(
-                    "starting new {} on datastore '{}': {:?}",
-                    worker_type, store, path
-                ));
+                if let Some(chunk_integrity) = chunk_integrity {
+                    env.debug(format!(
+                        "verifying chunk integrity with {}",
+                        chunk_integrity
+                    ));
+                }
+
+                if is_new {
+                    env.log(format!(
+                        "starting new {} on datastore '{}': {:?}",
+                        worker_type, store, path
+                    ));
+                } else {
+                    env.log(format!(
+                        "resuming {} on datastore '{}': {:?}",
+                        worker_type, store, path
+                    ));
+                }
+
+                if resume {
+                    let total = resume_chunks.len();
+                    let registered = env.start_resumable(resume_chunks)?;
+                    if !is_new {
+                        env.log(format!(
+                            "reusing {} of {} chunks uploaded by the interrupted backup",
+                            registered, total
+                        ));
+                    }
+                }
 
                 let service =
                     H2Service::new(env.clone(), worker.clone(), &BACKUP_API_ROUTER, debug);
@@ -264,11 +365,14 @@ fn upgrade_to_backup_protocol(
                     let _group_guard = _group_guard;
                     let snap_guard = snap_guard;
                     let _last_guard = _last_guard;
+                    let _tc_guard = tc_guard;
 
                     let res = select! {
                         req = req_fut => req,
                         abrt = abort_future => abrt,
                     };
+                    proxmox_async::runtime::block_in_place(|| env.record_transfer_accounting());
+
                     if benchmark {
                         env.log("benchmark finished successfully");
                         proxmox_async::runtime::block_in_place(|| env.remove_backup())?;
@@ -287,25 +391,45 @@ fn upgrade_to_backup_protocol(
                     match (res, env.ensure_finished()) {
                         (Ok(_), Ok(())) => {
                             env.log("backup finished successfully");
+                            if env.resumable {
+                                proxmox_async::runtime::block_in_place(|| {
+                                    env.remove_abandoned_snapshots()
+                                });
+                            }
                             verify(env);
                             Ok(())
                         }
                         (Err(err), Ok(())) => {
                             // ignore errors after finish
                             env.log(format!("backup had errors but finished: {}", err));
+                            if env.resumable {
+                                proxmox_async::runtime::block_in_place(|| {
+                                    env.remove_abandoned_snapshots()
+                                });
+                            }
                             verify(env);
                             Ok(())
                         }
                         (Ok(_), Err(err)) => {
                             env.log(format!("backup ended and finish failed: {}", err));
-                            env.log("removing unfinished backup");
-                            proxmox_async::runtime::block_in_place(|| env.remove_backup())?;
+                            if env.resumable {
+                                env.log("keeping uploaded chunks to resume the backup");
+                                proxmox_async::runtime::block_in_place(|| env.keep_for_resume())?;
+                            } else {
+                                env.log("removing unfinished backup");
+                                proxmox_async::runtime::block_in_place(|| env.remove_backup())?;
+                            }
                             Err(err)
                         }
                         (Err(err), Err(_)) => {
                             env.log(format!("backup failed: {}", err));
-                            env.log("removing failed backup");
-                            proxmox_async::runtime::block_in_place(|| env.remove_backup())?;
+                            if env.resumable {
+                                env.log("keeping uploaded chunks to resume the backup");
+                                proxmox_async::runtime::block_in_place(|| env.keep_for_resume())?;
+                            } else {
+                                env.log("removing failed backup");
+                                proxmox_async::runtime::block_in_place(|| env.remove_backup())?;
+                            }
                             Err(err)
                         }
                     }
@@ -313,13 +437,56 @@ fn upgrade_to_backup_protocol(
             },
         )?;
 
-        let response = Response::builder()
+        let mut response = Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
             .header(
                 UPGRADE,
                 HeaderValue::from_static(PROXMOX_BACKUP_PROTOCOL_ID_V1!()),
-            )
-            .body(Body::empty())?;
+            );
+
+        if let Some(chunk_integrity) = chunk_integrity {
+            response = response.header(
+                CHUNK_INTEGRITY_HEADER,
+                HeaderValue::from_static(chunk_integrity.as_str()),
+            );
+        }
+
+        if let Some(chunk_compression) = &chunk_compression {
+            response = response.header(
+                CHUNK_COMPRESSION_HEADER,
+                HeaderValue::from_str(&feature_offer(chunk_compression))?,
+            );
+            if chunk_compression.contains(&ChunkCompression::Zstd) {
+                let level = match compression_level {
+                    Some(level) => level.to_string(),
+                    None => String::from("none"),
+                };
+                response = response.header(CHUNK_COMPRESSION_LEVEL_HEADER, level);
+            }
+        }
+
+        if let Some(payload_compression) = &payload_compression {
+            response = response.header(
+                PAYLOAD_COMPRESSION_HEADER,
+                HeaderValue::from_str(&feature_offer(payload_compression))?,
+            );
+        }
+
+        if let Some(ciphers) = &ciphers {
+            response = response.header(
+                CIPHER_HEADER,
+                HeaderValue::from_str(&feature_offer(ciphers))?,
+            );
+        }
+
+        if let Some(client_log) = client_log {
+            response = response.header(
+                CLIENT_LOG_HEADER,
+                HeaderValue::from_static(client_log.as_str()),
+            );
+        }
+
+        let response = response.body(Body::empty())?;
 
         Ok(response)
     }
@@ -363,6 +530,10 @@ const BACKUP_API_SUBDIRS: SubdirMap = &[
             .post(&API_METHOD_CREATE_FIXED_INDEX)
             .put(&API_METHOD_FIXED_APPEND),
     ),
+    (
+        "partial_index",
+        &Router::new().download(&API_METHOD_DOWNLOAD_PARTIAL_INDEX),
+    ),
     (
         "previous",
         &Router::new().download(&API_METHOD_DOWNLOAD_PREVIOUS),
@@ -371,6 +542,10 @@ const BACKUP_API_SUBDIRS: SubdirMap = &[
         "previous_backup_time",
         &Router::new().get(&API_METHOD_GET_PREVIOUS_BACKUP_TIME),
     ),
+    (
+        "resume_chunks",
+        &Router::new().download(&API_METHOD_DOWNLOAD_RESUME_CHUNKS),
+    ),
     (
         "speedtest",
         &Router::new().upload(&API_METHOD_UPLOAD_SPEEDTEST),
@@ -865,3 +1040,73 @@ fn download_previous(
     }
     .boxed()
 }
+
+#[sortable]
+pub const API_METHOD_DOWNLOAD_RESUME_CHUNKS: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&download_resume_chunks),
+    &ObjectSchema::new(
+        "Download the list of chunks which are already known to a resumed backup.",
+        &[],
+    ),
+);
+
+fn download_resume_chunks(
+    _parts: Parts,
+    _req_body: Body,
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let env: &BackupEnvironment = rpcenv.as_ref();
+
+        if !env.resumable {
+            bail!("backup is not resumable");
+        }
+
+        crate::api2::helpers::create_download_response(env.resume_chunks_path()).await
+    }
+    .boxed()
+}
+
+#[sortable]
+pub const API_METHOD_DOWNLOAD_PARTIAL_INDEX: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&download_partial_index),
+    &ObjectSchema::new(
+        "Download the index entries a resumed backup kept from the interrupted one.",
+        &sorted!([(
+            "wid",
+            false,
+            &IntegerSchema::new("Writer ID.")
+                .minimum(1)
+                .maximum(256)
+                .schema()
+        )]),
+    ),
+);
+
+fn download_partial_index(
+    _parts: Parts,
+    _req_body: Body,
+    param: Value,
+    _info: &ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let env: &BackupEnvironment = rpcenv.as_ref();
+
+        if !env.resumable {
+            bail!("backup is not resumable");
+        }
+
+        let wid = required_integer_param(&param, "wid")? as usize;
+        let data = env.resumed_index_entries(wid)?;
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(data))
+            .unwrap())
+    }
+    .boxed()
+}
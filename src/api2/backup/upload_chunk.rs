@@ -15,6 +15,7 @@ use proxmox_sys::sortable;
 
 use pbs_api_types::{BACKUP_ARCHIVE_NAME_SCHEMA, CHUNK_DIGEST_SCHEMA};
 use pbs_datastore::file_formats::{DataBlobHeader, EncryptedDataBlobHeader};
+use pbs_datastore::protocol::{ChunkCompression, ChunkIntegrity};
 use pbs_datastore::{DataBlob, DataStore};
 use pbs_tools::json::{required_integer_param, required_string_param};
 
@@ -26,6 +27,8 @@ pub struct UploadChunk {
     digest: [u8; 32],
     size: u32,
     encoded_size: u32,
+    chunk_integrity: Option<ChunkIntegrity>,
+    chunk_compression: Vec<ChunkCompression>,
     raw_data: Option<Vec<u8>>,
 }
 
@@ -36,12 +39,16 @@ impl UploadChunk {
         digest: [u8; 32],
         size: u32,
         encoded_size: u32,
+        chunk_integrity: Option<ChunkIntegrity>,
+        chunk_compression: Vec<ChunkCompression>,
     ) -> Self {
         Self {
             stream,
             store,
             size,
             encoded_size,
+            chunk_integrity,
+            chunk_compression,
             raw_data: Some(vec![]),
             digest,
         }
@@ -76,7 +83,22 @@ impl Future for UploadChunk {
                         let (is_duplicate, compressed_size) = match proxmox_lang::try_block! {
                             let mut chunk = DataBlob::from_raw(raw_data)?;
 
+                            if let Some(compression) = ChunkCompression::from_magic(chunk.magic()) {
+                                if !this.chunk_compression.contains(&compression) {
+                                    bail!(
+                                        "chunk uses {} compression, which was not negotiated",
+                                        compression
+                                    );
+                                }
+                            }
+
                             proxmox_async::runtime::block_in_place(|| {
+                                if this.chunk_integrity == Some(ChunkIntegrity::Crc32) {
+                                    chunk.verify_crc().map_err(|err| {
+                                        format_err!("chunk corrupted during upload - {}", err)
+                                    })?;
+                                }
+
                                 chunk.verify_unencrypted(this.size as usize, &this.digest)?;
 
                                 // always comput CRC at server side
@@ -161,8 +183,16 @@ fn upload_fixed_chunk(
 
         let env: &BackupEnvironment = rpcenv.as_ref();
 
-        let (digest, size, compressed_size, is_duplicate) =
-            UploadChunk::new(req_body, env.datastore.clone(), digest, size, encoded_size).await?;
+        let (digest, size, compressed_size, is_duplicate) = UploadChunk::new(
+            req_body,
+            env.datastore.clone(),
+            digest,
+            size,
+            encoded_size,
+            env.chunk_integrity,
+            env.chunk_compression.clone(),
+        )
+        .await?;
 
         env.register_fixed_chunk(wid, digest, size, compressed_size, is_duplicate)?;
         let digest_str = hex::encode(&digest);
@@ -230,8 +260,16 @@ fn upload_dynamic_chunk(
 
         let env: &BackupEnvironment = rpcenv.as_ref();
 
-        let (digest, size, compressed_size, is_duplicate) =
-            UploadChunk::new(req_body, env.datastore.clone(), digest, size, encoded_size).await?;
+        let (digest, size, compressed_size, is_duplicate) = UploadChunk::new(
+            req_body,
+            env.datastore.clone(),
+            digest,
+            size,
+            encoded_size,
+            env.chunk_integrity,
+            env.chunk_compression.clone(),
+        )
+        .await?;
 
         env.register_dynamic_chunk(wid, digest, size, compressed_size, is_duplicate)?;
         let digest_str = hex::encode(&digest);
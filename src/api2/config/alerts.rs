@@ -0,0 +1,300 @@
+use ::serde::{Deserialize, Serialize};
+use anyhow::Error;
+use hex::FromHex;
+use serde_json::Value;
+
+use proxmox_router::{http_bail, ApiMethod, Permission, Router, RpcEnvironment};
+use proxmox_schema::{api, param_bail};
+
+use pbs_api_types::{
+    AlertRule, AlertRuleType, AlertRuleUpdater, ALERT_RULE_ID_SCHEMA, PRIV_SYS_AUDIT,
+    PRIV_SYS_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA,
+};
+
+fn check_alert_rule(rule: &AlertRule) -> Result<(), Error> {
+    match rule.ty {
+        AlertRuleType::DatastoreUsage => {
+            if rule.store.is_none() {
+                param_bail!("store", "datastore-usage rules require a datastore");
+            }
+            if rule.threshold > 100 {
+                param_bail!("threshold", "usage threshold must not exceed 100 percent");
+            }
+        }
+        AlertRuleType::BackupAge => {
+            if rule.store.is_none() {
+                param_bail!("store", "backup-age rules require a datastore");
+            }
+        }
+        AlertRuleType::JobFailures => {
+            if rule.job_id.is_some() && rule.job_type.is_none() {
+                param_bail!("job-type", "a job ID requires a job type");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "The list of configured alert rules (with config digest).",
+        type: Array,
+        items: { type: AlertRule },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List alert rules
+pub fn list_alert_rules(
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<AlertRule>, Error> {
+    let (config, digest) = pbs_config::alerts::config()?;
+
+    let list: Vec<AlertRule> = config.convert_to_typed_array("rule")?;
+
+    rpcenv["digest"] = hex::encode(&digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: AlertRule,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Create new alert rule.
+pub fn create_alert_rule(config: AlertRule) -> Result<(), Error> {
+    let _lock = pbs_config::alerts::lock_config()?;
+
+    let (mut section_config, _digest) = pbs_config::alerts::config()?;
+
+    if section_config.sections.get(&config.id).is_some() {
+        param_bail!("id", "alert rule '{}' already exists.", config.id);
+    }
+
+    check_alert_rule(&config)?;
+
+    section_config.set_data(&config.id, "rule", &config)?;
+
+    pbs_config::alerts::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: ALERT_RULE_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: AlertRule },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    }
+)]
+/// Read an alert rule.
+pub fn read_alert_rule(
+    id: String,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<AlertRule, Error> {
+    let (config, digest) = pbs_config::alerts::config()?;
+    let data: AlertRule = config.lookup("rule", &id)?;
+    rpcenv["digest"] = hex::encode(&digest).into();
+    Ok(data)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the store property.
+    Store,
+    /// Delete the ns property.
+    Ns,
+    /// Delete the group property.
+    Group,
+    /// Delete the job-type property.
+    JobType,
+    /// Delete the job-id property.
+    JobId,
+    /// Delete the notify-user property.
+    NotifyUser,
+    /// Delete the disable property.
+    Disable,
+    /// Delete the comment property.
+    Comment,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: ALERT_RULE_ID_SCHEMA,
+            },
+            update: {
+                type: AlertRuleUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Update an alert rule.
+pub fn update_alert_rule(
+    id: String,
+    update: AlertRuleUpdater,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+) -> Result<(), Error> {
+    let _lock = pbs_config::alerts::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::alerts::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut data: AlertRule = config.lookup("rule", &id)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Store => data.store = None,
+                DeletableProperty::Ns => data.ns = None,
+                DeletableProperty::Group => data.group = None,
+                DeletableProperty::JobType => data.job_type = None,
+                DeletableProperty::JobId => data.job_id = None,
+                DeletableProperty::NotifyUser => data.notify_user = None,
+                DeletableProperty::Disable => data.disable = None,
+                DeletableProperty::Comment => data.comment = None,
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            data.comment = None;
+        } else {
+            data.comment = Some(comment);
+        }
+    }
+
+    if let Some(threshold) = update.threshold {
+        data.threshold = threshold;
+    }
+    if update.store.is_some() {
+        data.store = update.store;
+    }
+    if update.ns.is_some() {
+        data.ns = update.ns;
+    }
+    if update.group.is_some() {
+        data.group = update.group;
+    }
+    if update.job_type.is_some() {
+        data.job_type = update.job_type;
+    }
+    if update.job_id.is_some() {
+        data.job_id = update.job_id;
+    }
+    if update.notify_user.is_some() {
+        data.notify_user = update.notify_user;
+    }
+    if update.disable.is_some() {
+        data.disable = update.disable;
+    }
+
+    check_alert_rule(&data)?;
+
+    config.set_data(&id, "rule", &data)?;
+
+    pbs_config::alerts::save_config(&config)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            id: {
+                schema: ALERT_RULE_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Remove an alert rule from the configuration file.
+pub fn delete_alert_rule(id: String, digest: Option<String>) -> Result<(), Error> {
+    let _lock = pbs_config::alerts::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::alerts::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    match config.sections.get(&id) {
+        Some(_) => {
+            config.sections.remove(&id);
+        }
+        None => http_bail!(NOT_FOUND, "alert rule '{}' does not exist.", id),
+    }
+
+    pbs_config::alerts::save_config(&config)?;
+
+    Ok(())
+}
+
+const ITEM_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_ALERT_RULE)
+    .put(&API_METHOD_UPDATE_ALERT_RULE)
+    .delete(&API_METHOD_DELETE_ALERT_RULE);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_ALERT_RULES)
+    .post(&API_METHOD_CREATE_ALERT_RULE)
+    .match_all("id", &ITEM_ROUTER);
@@ -1,27 +1,35 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use ::serde::{Deserialize, Serialize};
-use anyhow::Error;
+use anyhow::{bail, format_err, Error};
 use hex::FromHex;
-use serde_json::Value;
+use serde_json::{json, Value};
 
-use proxmox_router::{http_bail, Permission, Router, RpcEnvironment, RpcEnvironmentType};
+use proxmox_router::{
+    http_bail, Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+};
 use proxmox_schema::{api, param_bail, ApiType};
 use proxmox_section_config::SectionConfigData;
-use proxmox_sys::WorkerTaskContext;
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
 
 use pbs_api_types::{
-    Authid, DataStoreConfig, DataStoreConfigUpdater, DatastoreNotify, DatastoreTuning,
-    DATASTORE_SCHEMA, PRIV_DATASTORE_ALLOCATE, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY,
-    PROXMOX_CONFIG_DIGEST_SCHEMA,
+    Authid, DataStoreConfig, DataStoreConfigUpdater, DatastoreBackendType, DatastoreNotify,
+    DatastoreTuning, ZfsCompressionType, ZfsDatasetProperties, DATASTORE_SCHEMA,
+    PRIV_DATASTORE_ALLOCATE, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY, PRIV_SYS_MODIFY,
+    PROXMOX_CONFIG_DIGEST_SCHEMA, UPID_SCHEMA, ZFS_DATASET_SCHEMA,
 };
 use pbs_config::BackupLockGuard;
 use pbs_datastore::chunk_store::ChunkStore;
+use pbs_datastore::s3::S3Client;
+use pbs_datastore::{task_tracking, DataStore};
 
 use crate::api2::admin::{sync::list_sync_jobs, verify::list_verification_jobs};
 use crate::api2::config::sync::delete_sync_job;
 use crate::api2::config::tape_backup_job::{delete_tape_backup_job, list_tape_backup_jobs};
 use crate::api2::config::verify::delete_verification_job;
+use crate::tools::disks::{
+    check_datastore_zfs_properties, zfs_dataset_for_path, zfs_dataset_properties,
+};
 use pbs_config::CachedUserInfo;
 
 use proxmox_rest_server::WorkerTask;
@@ -59,13 +67,21 @@ pub fn list_datastores(
         (user_privs & PRIV_DATASTORE_AUDIT) != 0
     };
 
-    Ok(list.into_iter().filter(filter_by_privs).collect())
+    Ok(list
+        .into_iter()
+        .filter(filter_by_privs)
+        .map(|mut store| {
+            // we do not want to return the secret key
+            store.s3.s3_secret_key = None;
+            store
+        })
+        .collect())
 }
 
 pub(crate) fn do_create_datastore(
     _lock: BackupLockGuard,
     mut config: SectionConfigData,
-    datastore: DataStoreConfig,
+    mut datastore: DataStoreConfig,
     worker: Option<&dyn WorkerTaskContext>,
 ) -> Result<(), Error> {
     let path: PathBuf = datastore.path.clone().into();
@@ -74,6 +90,12 @@ pub(crate) fn do_create_datastore(
         DatastoreTuning::API_SCHEMA
             .parse_property_string(datastore.tuning.as_deref().unwrap_or(""))?,
     )?;
+    if datastore.backend.unwrap_or_default() == DatastoreBackendType::S3 {
+        let client = S3Client::new(&datastore.s3)?;
+        proxmox_async::runtime::block_on(client.check_bucket())
+            .map_err(|err| format_err!("unable to access S3 bucket - {}", err))?;
+    }
+
     let backup_user = pbs_config::backup_user()?;
     let _store = ChunkStore::create(
         &datastore.name,
@@ -84,6 +106,11 @@ pub(crate) fn do_create_datastore(
         tuning.sync_level.unwrap_or_default(),
     )?;
 
+    // keep the secret key out of the datastore configuration
+    if let Some(secret_key) = datastore.s3.s3_secret_key.take() {
+        pbs_config::s3_shadow::set_secret_key(&datastore.name, &secret_key)?;
+    }
+
     config.set_data(&datastore.name, "datastore", &datastore)?;
 
     pbs_config::datastore::save_config(&config)?;
@@ -94,6 +121,58 @@ pub(crate) fn do_create_datastore(
     Ok(())
 }
 
+// Create a ZFS dataset with the recommended properties for a datastore, mounted at `path`.
+// Explicitly passed `properties` take precedence.
+fn create_zfs_dataset(
+    worker: &dyn WorkerTaskContext,
+    dataset: &str,
+    path: &Path,
+    properties: ZfsDatasetProperties,
+) -> Result<(), Error> {
+    if let Ok(mut entries) = std::fs::read_dir(path) {
+        if entries.next().is_some() {
+            bail!(
+                "mount point {:?} for dataset '{}' is not empty",
+                path,
+                dataset
+            );
+        }
+    }
+
+    let properties = ZfsDatasetProperties {
+        // garbage collection relies on the access time of chunks
+        atime: Some(true),
+        relatime: Some(properties.relatime.unwrap_or(true)),
+        compression: Some(properties.compression.unwrap_or(ZfsCompressionType::On)),
+        ..properties
+    };
+
+    let mut command = std::process::Command::new("zfs");
+    command.arg("create");
+    for option in properties.to_zfs_options()? {
+        command.args(&["-o", &option]);
+    }
+    command.args(&["-o", "xattr=sa"]);
+    command.args(&["-o", &format!("mountpoint={}", path.display())]);
+    command.arg(dataset);
+
+    task_log!(worker, "# {:?}", command);
+    let output = proxmox_sys::command::run_command(command, None)?;
+    task_log!(worker, "{}", output);
+
+    Ok(())
+}
+
+fn destroy_zfs_dataset(worker: &dyn WorkerTaskContext, dataset: &str) {
+    let mut command = std::process::Command::new("zfs");
+    command.args(&["destroy", dataset]);
+
+    task_log!(worker, "# {:?}", command);
+    if let Err(err) = proxmox_sys::command::run_command(command, None) {
+        task_warn!(worker, "unable to remove dataset '{}' - {}", dataset, err);
+    }
+}
+
 #[api(
     protected: true,
     input: {
@@ -102,15 +181,27 @@ pub(crate) fn do_create_datastore(
                 type: DataStoreConfig,
                 flatten: true,
             },
+            zfs: {
+                schema: ZFS_DATASET_SCHEMA,
+                optional: true,
+                description: "Create this ZFS dataset, mounted at the datastore path.",
+            },
+            "zfs-properties": {
+                type: ZfsDatasetProperties,
+                flatten: true,
+            },
         },
     },
     access: {
         permission: &Permission::Privilege(&["datastore"], PRIV_DATASTORE_ALLOCATE, false),
+        description: "Creating a ZFS dataset additionally requires Sys.Modify on '/system/disks'.",
     },
 )]
 /// Create new datastore config.
 pub fn create_datastore(
     config: DataStoreConfig,
+    zfs: Option<String>,
+    zfs_properties: ZfsDatasetProperties,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
     let lock = pbs_config::datastore::lock_config()?;
@@ -124,12 +215,35 @@ pub fn create_datastore(
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
 
+    if zfs.is_some() {
+        let user_info = CachedUserInfo::new()?;
+        user_info.check_privs(&auth_id, &["system", "disks"], PRIV_SYS_MODIFY, false)?;
+        check_datastore_zfs_properties(&zfs_properties)?;
+        // fail early on invalid values, not only in the worker
+        zfs_properties.to_zfs_options()?;
+    } else if zfs_properties != ZfsDatasetProperties::default() {
+        bail!("ZFS properties can only be set when creating a ZFS dataset");
+    }
+
     WorkerTask::new_thread(
         "create-datastore",
         Some(config.name.to_string()),
         auth_id.to_string(),
         to_stdout,
-        move |worker| do_create_datastore(lock, section_config, config, Some(&worker)),
+        move |worker| {
+            let dataset = match zfs {
+                Some(dataset) => dataset,
+                None => return do_create_datastore(lock, section_config, config, Some(&worker)),
+            };
+
+            create_zfs_dataset(&*worker, &dataset, Path::new(&config.path), zfs_properties)?;
+
+            let result = do_create_datastore(lock, section_config, config, Some(&worker));
+            if result.is_err() {
+                destroy_zfs_dataset(&*worker, &dataset);
+            }
+            result
+        },
     )
 }
 
@@ -153,9 +267,12 @@ pub fn read_datastore(
 ) -> Result<DataStoreConfig, Error> {
     let (config, digest) = pbs_config::datastore::config()?;
 
-    let store_config = config.lookup("datastore", &name)?;
+    let mut store_config: DataStoreConfig = config.lookup("datastore", &name)?;
     rpcenv["digest"] = hex::encode(&digest).into();
 
+    // we do not want to return the secret key
+    store_config.s3.s3_secret_key = None;
+
     Ok(store_config)
 }
 
@@ -193,6 +310,14 @@ pub enum DeletableProperty {
     tuning,
     /// Delete the maintenance-mode property
     maintenance_mode,
+    /// Delete the trash-retention property
+    trash_retention,
+    /// Delete the quota property
+    quota,
+    /// Delete the s3-region property
+    s3_region,
+    /// Delete the s3-path-style property
+    s3_path_style,
 }
 
 #[api(
@@ -288,6 +413,18 @@ pub fn update_datastore(
                 DeletableProperty::maintenance_mode => {
                     data.maintenance_mode = None;
                 }
+                DeletableProperty::trash_retention => {
+                    data.trash_retention = None;
+                }
+                DeletableProperty::quota => {
+                    data.quota = None;
+                }
+                DeletableProperty::s3_region => {
+                    data.s3.s3_region = None;
+                }
+                DeletableProperty::s3_path_style => {
+                    data.s3.s3_path_style = None;
+                }
             }
         }
     }
@@ -337,6 +474,7 @@ pub fn update_datastore(
             verify: None,
             sync: None,
             prune: None,
+            tape: None,
         } = notify
         {
             data.notify = None;
@@ -360,6 +498,33 @@ pub fn update_datastore(
         data.maintenance_mode = update.maintenance_mode;
     }
 
+    if update.trash_retention.is_some() {
+        data.trash_retention = update.trash_retention;
+    }
+
+    if update.quota.is_some() {
+        data.quota = update.quota;
+    }
+
+    if update.s3.s3_region.is_some() {
+        data.s3.s3_region = update.s3.s3_region;
+    }
+    if update.s3.s3_access_key.is_some() {
+        data.s3.s3_access_key = update.s3.s3_access_key;
+    }
+    if let Some(secret_key) = update.s3.s3_secret_key {
+        pbs_config::s3_shadow::set_secret_key(&name, &secret_key)?;
+    }
+    // also moves a secret key still stored in the configuration by older versions
+    if let Some(secret_key) = data.s3.s3_secret_key.take() {
+        if pbs_config::s3_shadow::get_secret_key(&name)?.is_none() {
+            pbs_config::s3_shadow::set_secret_key(&name, &secret_key)?;
+        }
+    }
+    if update.s3.s3_path_style.is_some() {
+        data.s3.s3_path_style = update.s3.s3_path_style;
+    }
+
     config.set_data(&name, "datastore", &data)?;
 
     pbs_config::datastore::save_config(&config)?;
@@ -386,24 +551,38 @@ pub fn update_datastore(
                 optional: true,
                 default: false,
             },
+            "destroy-data": {
+                description: "Delete all data of the datastore, including its chunks, backup \
+                    snapshots and namespaces. Cannot be undone!",
+                type: bool,
+                optional: true,
+                default: false,
+            },
             digest: {
                 optional: true,
                 schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
             },
         },
     },
+    returns: {
+        schema: UPID_SCHEMA,
+        optional: true,
+    },
     access: {
         permission: &Permission::Privilege(&["datastore", "{name}"], PRIV_DATASTORE_ALLOCATE, false),
     },
 )]
-/// Remove a datastore configuration.
+/// Remove a datastore configuration and optionally all of its data.
+///
+/// Only returns the UPID of a worker task if the data gets destroyed.
 pub async fn delete_datastore(
     name: String,
     keep_job_configs: bool,
+    destroy_data: bool,
     digest: Option<String>,
     rpcenv: &mut dyn RpcEnvironment,
-) -> Result<(), Error> {
-    let _lock = pbs_config::datastore::lock_config()?;
+) -> Result<Value, Error> {
+    let lock = pbs_config::datastore::lock_config()?;
 
     let (mut config, expected_digest) = pbs_config::datastore::config()?;
 
@@ -412,11 +591,33 @@ pub async fn delete_datastore(
         crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
     }
 
-    match config.sections.get(&name) {
-        Some(_) => {
-            config.sections.remove(&name);
-        }
+    let store_config: DataStoreConfig = match config.sections.get(&name) {
+        Some(_) => config.lookup("datastore", &name)?,
         None => http_bail!(NOT_FOUND, "datastore '{}' does not exist.", name),
+    };
+    config.sections.remove(&name);
+
+    if destroy_data {
+        // the objects in the bucket would stay, with no datastore left to access them
+        if store_config.backend.unwrap_or_default() == DatastoreBackendType::S3 {
+            bail!(
+                "destroying the data of datastore '{}' on S3 is not supported, remove the \
+                datastore without it and delete the objects from the bucket instead",
+                name
+            );
+        }
+
+        check_datastore_path_overlap(&config, &store_config.path)?;
+
+        let active_operations = task_tracking::get_active_operations(&name)?;
+        if active_operations.read > 0 || active_operations.write > 0 {
+            bail!(
+                "datastore '{}' is still in use ({} read, {} write operations)",
+                name,
+                active_operations.read,
+                active_operations.write,
+            );
+        }
     }
 
     if !keep_job_configs {
@@ -437,22 +638,186 @@ pub async fn delete_datastore(
     }
 
     pbs_config::datastore::save_config(&config)?;
+    drop(lock);
 
     // ignore errors
     let _ = jobstate::remove_state_file("prune", &name);
     let _ = jobstate::remove_state_file("garbage_collection", &name);
+    let _ = pbs_config::s3_shadow::delete_secret_key(&name);
 
     crate::server::notify_datastore_removed().await?;
 
+    if !destroy_data {
+        return Ok(Value::Null);
+    }
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "delete-datastore",
+        Some(name.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            task_log!(
+                worker,
+                "destroying data of removed datastore '{}' at {:?}",
+                name,
+                store_config.path
+            );
+            DataStore::destroy_data(Path::new(&store_config.path), &*worker)
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+// Refuses to destroy the data at `path` if any of the datastores still configured in `config`
+// uses the same directory, or one nested in it or containing it. Symlinks are resolved for paths
+// which exist.
+fn check_datastore_path_overlap(config: &SectionConfigData, path: &str) -> Result<(), Error> {
+    let resolve = |path: &str| std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+
+    let path = resolve(path);
+    let stores: Vec<DataStoreConfig> = config.convert_to_typed_array("datastore")?;
+
+    for store in stores {
+        let other = resolve(&store.path);
+        if other.starts_with(&path) || path.starts_with(&other) {
+            bail!(
+                "datastore '{}' at {:?} overlaps with {:?}, refusing to destroy data",
+                store.name,
+                other,
+                path,
+            );
+        }
+    }
+
     Ok(())
 }
 
+// Returns the ZFS dataset the datastore `name` is located on.
+fn datastore_zfs_dataset(name: &str) -> Result<String, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let store_config: DataStoreConfig = config.lookup("datastore", name)?;
+
+    match zfs_dataset_for_path(Path::new(&store_config.path))? {
+        Some(dataset) => Ok(dataset),
+        None => bail!("datastore '{}' is not located on a ZFS dataset", name),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: { type: ZfsDatasetProperties },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{name}"], PRIV_DATASTORE_AUDIT, false),
+    },
+)]
+/// Read the properties of the ZFS dataset a datastore is located on.
+pub fn read_datastore_zfs_properties(name: String) -> Result<ZfsDatasetProperties, Error> {
+    let dataset = datastore_zfs_dataset(&name)?;
+    zfs_dataset_properties(&dataset)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            properties: {
+                type: ZfsDatasetProperties,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{name}"], PRIV_DATASTORE_MODIFY, false),
+        description: "Additionally requires Sys.Modify on '/system/disks'.",
+    },
+)]
+/// Update the properties of the ZFS dataset a datastore is located on. This affects all data on
+/// the dataset, not only the datastore.
+pub fn update_datastore_zfs_properties(
+    name: String,
+    properties: ZfsDatasetProperties,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+    user_info.check_privs(&auth_id, &["system", "disks"], PRIV_SYS_MODIFY, false)?;
+
+    check_datastore_zfs_properties(&properties)?;
+    let options = properties.to_zfs_options()?;
+    if options.is_empty() {
+        return Ok(());
+    }
+
+    let dataset = datastore_zfs_dataset(&name)?;
+
+    let mut command = std::process::Command::new("zfs");
+    command.arg("set");
+    command.args(&options);
+    command.arg(&dataset);
+    proxmox_sys::command::run_command(command, None)?;
+
+    Ok(())
+}
+
+const SUBDIRS: SubdirMap = &[(
+    "zfs",
+    &Router::new()
+        .get(&API_METHOD_READ_DATASTORE_ZFS_PROPERTIES)
+        .put(&API_METHOD_UPDATE_DATASTORE_ZFS_PROPERTIES),
+)];
+
 const ITEM_ROUTER: Router = Router::new()
     .get(&API_METHOD_READ_DATASTORE)
     .put(&API_METHOD_UPDATE_DATASTORE)
-    .delete(&API_METHOD_DELETE_DATASTORE);
+    .delete(&API_METHOD_DELETE_DATASTORE)
+    .subdirs(SUBDIRS);
 
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_LIST_DATASTORES)
     .post(&API_METHOD_CREATE_DATASTORE)
     .match_all("name", &ITEM_ROUTER);
+
+#[test]
+fn datastore_path_overlap_test() -> Result<(), Error> {
+    let config = pbs_config::datastore::CONFIG.parse(
+        "datastore.cfg",
+        r###"
+datastore: store1
+	path /backup/store1
+
+datastore: nested
+	path /backup/store2/nested
+
+"###,
+    )?;
+
+    // unrelated and sibling paths are fine
+    check_datastore_path_overlap(&config, "/backup/store3")?;
+    check_datastore_path_overlap(&config, "/backup/store10")?;
+    check_datastore_path_overlap(&config, "/backup/store2/other")?;
+
+    // same path, with and without trailing slash
+    assert!(check_datastore_path_overlap(&config, "/backup/store1").is_err());
+    assert!(check_datastore_path_overlap(&config, "/backup/store1/").is_err());
+    // another datastore is nested in it
+    assert!(check_datastore_path_overlap(&config, "/backup/store2").is_err());
+    assert!(check_datastore_path_overlap(&config, "/backup").is_err());
+    // nested in another datastore
+    assert!(check_datastore_path_overlap(&config, "/backup/store1/sub").is_err());
+
+    Ok(())
+}
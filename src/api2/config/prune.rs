@@ -157,6 +157,10 @@ pub enum DeletableProperty {
     KeepMonthly,
     /// Delete number of yearly backups to keep.
     KeepYearly,
+    /// Use the datastore's notification setting again.
+    Notify,
+    /// Prune all backup groups again.
+    GroupFilter,
 }
 
 #[api(
@@ -248,6 +252,12 @@ pub fn update_prune_job(
                 DeletableProperty::KeepYearly => {
                     data.options.keep.keep_yearly = None;
                 }
+                DeletableProperty::Notify => {
+                    data.notify = None;
+                }
+                DeletableProperty::GroupFilter => {
+                    data.options.group_filter = None;
+                }
             }
         }
     }
@@ -286,6 +296,12 @@ pub fn update_prune_job(
     if let Some(value) = update.comment {
         data.comment = Some(value);
     }
+    if let Some(value) = update.notify {
+        data.notify = Some(value);
+    }
+    if let Some(value) = update.options.group_filter {
+        data.options.group_filter = Some(value);
+    }
     if let Some(value) = update.options.keep.keep_last {
         data.options.keep.keep_last = Some(value);
     }
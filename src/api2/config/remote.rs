@@ -1,6 +1,8 @@
 use ::serde::{Deserialize, Serialize};
 use anyhow::{bail, format_err, Error};
 use hex::FromHex;
+use openssl::ssl::SslVersion;
+use openssl::x509::X509;
 use pbs_api_types::BackupNamespace;
 use pbs_api_types::NamespaceListItem;
 use proxmox_router::list_subdirs_api_method;
@@ -13,8 +15,9 @@ use proxmox_schema::{api, param_bail};
 
 use pbs_api_types::{
     Authid, DataStoreListItem, GroupListItem, RateLimitConfig, Remote, RemoteConfig,
-    RemoteConfigUpdater, RemoteWithoutPassword, SyncJobConfig, DATASTORE_SCHEMA, PRIV_REMOTE_AUDIT,
-    PRIV_REMOTE_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA, REMOTE_ID_SCHEMA, REMOTE_PASSWORD_SCHEMA,
+    RemoteConfigUpdater, RemoteTestResult, RemoteWithoutPassword, SyncJobConfig, TlsVersion,
+    DATASTORE_SCHEMA, PRIV_REMOTE_AUDIT, PRIV_REMOTE_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    REMOTE_CA_CERT_SCHEMA, REMOTE_ID_SCHEMA, REMOTE_PASSWORD_SCHEMA,
 };
 use pbs_client::{HttpClient, HttpClientOptions};
 use pbs_config::sync;
@@ -22,6 +25,16 @@ use pbs_config::sync;
 use pbs_config::CachedUserInfo;
 use serde_json::json;
 
+// the certificates are passed and stored as content, so the check never needs to read a path
+// chosen by the caller
+fn check_ca_cert(pem: &str) -> Result<(), Error> {
+    match X509::stack_from_pem(pem.as_bytes()) {
+        Ok(certs) if !certs.is_empty() => Ok(()),
+        Ok(_) => param_bail!("ca-cert", "no PEM encoded certificate found"),
+        Err(err) => param_bail!("ca-cert", "unable to parse CA certificate - {}", err),
+    }
+}
+
 #[api(
     input: {
         properties: {},
@@ -77,6 +90,10 @@ pub fn list_remotes(
                 // We expect the plain password here (not base64 encoded)
                 schema: REMOTE_PASSWORD_SCHEMA,
             },
+            "ca-cert": {
+                optional: true,
+                schema: REMOTE_CA_CERT_SCHEMA,
+            },
         },
     },
     access: {
@@ -84,7 +101,12 @@ pub fn list_remotes(
     },
 )]
 /// Create new remote.
-pub fn create_remote(name: String, config: RemoteConfig, password: String) -> Result<(), Error> {
+pub fn create_remote(
+    name: String,
+    config: RemoteConfig,
+    password: String,
+    ca_cert: Option<String>,
+) -> Result<(), Error> {
     let _lock = pbs_config::remote::lock_config()?;
 
     let (mut section_config, _digest) = pbs_config::remote::config()?;
@@ -93,6 +115,10 @@ pub fn create_remote(name: String, config: RemoteConfig, password: String) -> Re
         param_bail!("name", "remote '{}' already exists.", name);
     }
 
+    if let Some(ca_cert) = &ca_cert {
+        check_ca_cert(ca_cert)?;
+    }
+
     let remote = Remote {
         name: name.clone(),
         config,
@@ -101,6 +127,12 @@ pub fn create_remote(name: String, config: RemoteConfig, password: String) -> Re
 
     section_config.set_data(&name, "remote", &remote)?;
 
+    // do not pick up certificates left behind by an earlier remote with the same name
+    match ca_cert {
+        Some(ca_cert) => pbs_config::remote::save_ca_cert(&name, &ca_cert)?,
+        None => pbs_config::remote::remove_ca_cert(&name)?,
+    }
+
     pbs_config::remote::save_config(&section_config)?;
 
     Ok(())
@@ -142,6 +174,15 @@ pub enum DeletableProperty {
     fingerprint,
     /// Delete the port property.
     port,
+    /// Delete the CA certificate(s).
+    #[serde(rename = "ca-cert")]
+    ca_cert,
+    /// Delete the verify-hostname property.
+    #[serde(rename = "verify-hostname")]
+    verify_hostname,
+    /// Delete the min-tls-version property.
+    #[serde(rename = "min-tls-version")]
+    min_tls_version,
 }
 
 #[api(
@@ -160,6 +201,10 @@ pub enum DeletableProperty {
                 optional: true,
                 schema: REMOTE_PASSWORD_SCHEMA,
             },
+            "ca-cert": {
+                optional: true,
+                schema: REMOTE_CA_CERT_SCHEMA,
+            },
             delete: {
                 description: "List of properties to delete.",
                 type: Array,
@@ -183,6 +228,7 @@ pub fn update_remote(
     name: String,
     update: RemoteConfigUpdater,
     password: Option<String>,
+    ca_cert: Option<String>,
     delete: Option<Vec<DeletableProperty>>,
     digest: Option<String>,
 ) -> Result<(), Error> {
@@ -197,6 +243,11 @@ pub fn update_remote(
 
     let mut data: Remote = config.lookup("remote", &name)?;
 
+    if let Some(ca_cert) = &ca_cert {
+        check_ca_cert(ca_cert)?;
+    }
+    let mut delete_ca_cert = false;
+
     if let Some(delete) = delete {
         for delete_prop in delete {
             match delete_prop {
@@ -209,6 +260,15 @@ pub fn update_remote(
                 DeletableProperty::port => {
                     data.config.port = None;
                 }
+                DeletableProperty::ca_cert => {
+                    delete_ca_cert = true;
+                }
+                DeletableProperty::verify_hostname => {
+                    data.config.verify_hostname = None;
+                }
+                DeletableProperty::min_tls_version => {
+                    data.config.min_tls_version = None;
+                }
             }
         }
     }
@@ -237,11 +297,24 @@ pub fn update_remote(
     if update.fingerprint.is_some() {
         data.config.fingerprint = update.fingerprint;
     }
+    if update.verify_hostname.is_some() {
+        data.config.verify_hostname = update.verify_hostname;
+    }
+    if update.min_tls_version.is_some() {
+        data.config.min_tls_version = update.min_tls_version;
+    }
 
     config.set_data(&name, "remote", &data)?;
 
     pbs_config::remote::save_config(&config)?;
 
+    // only touch the certificate once the configuration is saved, so both stay in sync
+    match ca_cert {
+        Some(ca_cert) => pbs_config::remote::save_ca_cert(&name, &ca_cert)?,
+        None if delete_ca_cert => pbs_config::remote::remove_ca_cert(&name)?,
+        None => (),
+    }
+
     Ok(())
 }
 
@@ -296,10 +369,32 @@ pub fn delete_remote(name: String, digest: Option<String>) -> Result<(), Error>
     }
 
     pbs_config::remote::save_config(&config)?;
+    pbs_config::remote::remove_ca_cert(&name)?;
 
     Ok(())
 }
 
+/// Apply the TLS settings of a remote.cfg entry to the client options
+pub fn remote_tls_options(remote: &Remote, options: HttpClientOptions) -> HttpClientOptions {
+    let config = &remote.config;
+    let min_tls_version = config.min_tls_version.map(|version| match version {
+        TlsVersion::Tls1_2 => SslVersion::TLS1_2,
+        TlsVersion::Tls1_3 => SslVersion::TLS1_3,
+    });
+
+    let ca_file = pbs_config::remote::ca_cert_path(&remote.name);
+    let ca_file = if ca_file.exists() {
+        Some(ca_file.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    options
+        .ca_file(ca_file)
+        .verify_hostname(config.verify_hostname.unwrap_or(true))
+        .min_tls_version(min_tls_version)
+}
+
 /// Helper to get client for remote.cfg entry
 pub async fn remote_client(
     remote: &Remote,
@@ -314,6 +409,8 @@ pub async fn remote_client(
         options = options.rate_limit(limit);
     }
 
+    options = remote_tls_options(remote, options);
+
     let client = HttpClient::new(
         &remote.config.host,
         remote.config.port.unwrap_or(8007),
@@ -498,6 +595,72 @@ pub async fn scan_remote_groups(
     }
 }
 
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: REMOTE_ID_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["remote", "{name}"], PRIV_REMOTE_AUDIT, false),
+    },
+    returns: {
+        type: RemoteTestResult,
+    },
+)]
+/// Test the connection to a remote.cfg entry.
+///
+/// Connects to the remote, verifies its certificate and the configured credentials, and returns
+/// the version of the remote and the datastores visible to it.
+pub async fn test_remote(name: String) -> Result<RemoteTestResult, Error> {
+    let (remote_config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = remote_config.lookup("remote", &name)?;
+
+    let map_remote_err = |api_err| {
+        http_err!(
+            INTERNAL_SERVER_ERROR,
+            "connection test to remote '{}' failed - {}",
+            &name,
+            api_err
+        )
+    };
+
+    let client = remote_client(&remote, None).await.map_err(map_remote_err)?;
+
+    let mut version = client
+        .get("api2/json/version", None)
+        .await
+        .map_err(map_remote_err)?;
+    let version = version["data"].take();
+    let get_version_field = |field: &str| match version[field].as_str() {
+        Some(value) => Ok(value.to_string()),
+        None => bail!("remote '{}' did not return its {}", name, field),
+    };
+
+    let mut datastores = client
+        .get("api2/json/admin/datastore", None)
+        .await
+        .map_err(map_remote_err)?;
+    let datastores: Vec<DataStoreListItem> = serde_json::from_value(datastores["data"].take())
+        .map_err(|err| {
+            format_err!(
+                "failed to parse datastore list of remote '{}' - {}",
+                name,
+                err
+            )
+        })?;
+
+    Ok(RemoteTestResult {
+        version: get_version_field("version")?,
+        release: get_version_field("release")?,
+        repoid: get_version_field("repoid")?,
+        fingerprint: client.fingerprint(),
+        datastores,
+    })
+}
+
 #[sortable]
 const DATASTORE_SCAN_SUBDIRS: SubdirMap = &sorted!([
     ("groups", &Router::new().get(&API_METHOD_SCAN_REMOTE_GROUPS)),
@@ -519,7 +682,10 @@ const ITEM_ROUTER: Router = Router::new()
     .get(&API_METHOD_READ_REMOTE)
     .put(&API_METHOD_UPDATE_REMOTE)
     .delete(&API_METHOD_DELETE_REMOTE)
-    .subdirs(&[("scan", &SCAN_ROUTER)]);
+    .subdirs(&[
+        ("scan", &SCAN_ROUTER),
+        ("test", &Router::new().post(&API_METHOD_TEST_REMOTE)),
+    ]);
 
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_LIST_REMOTES)
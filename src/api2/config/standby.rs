@@ -0,0 +1,131 @@
+//! Configuration replication to a standby node
+
+use std::collections::HashMap;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_router::{
+    list_subdirs_api_method, Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+};
+use proxmox_schema::api;
+use proxmox_sys::sortable;
+
+use pbs_api_types::{Authid, PRIV_SYS_AUDIT, UPID_SCHEMA};
+
+use crate::server::jobstate::Job;
+
+#[api]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Configuration replication status of this node.
+pub struct StandbyStatus {
+    /// Remote of the standby node this node replicates its configuration to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub standby: Option<String>,
+    /// Node name of the primary node the staged configuration was received from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Time the staged configuration was received (seconds since the Epoch).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received: Option<i64>,
+}
+
+#[api(
+    returns: {
+        type: StandbyStatus,
+    },
+    access: {
+        permission: &Permission::Privilege(&["system"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Get the configuration replication status.
+pub fn get_status() -> Result<StandbyStatus, Error> {
+    let (config, _digest) = crate::config::node::config()?;
+    let standby = config.standby_config().transpose()?;
+
+    let staged = crate::server::staged_config()?;
+
+    Ok(StandbyStatus {
+        standby: standby.map(|standby| standby.remote),
+        source: staged.as_ref().map(|staged| staged.source.clone()),
+        received: staged.map(|staged| staged.received),
+    })
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            source: {
+                type: String,
+                description: "Node name of the primary node.",
+            },
+            configs: {
+                type: String,
+                description: "JSON encoded map of the replicated configuration files by name.",
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// Stage the configuration replicated from a primary node. It only gets applied once this node
+/// is promoted.
+pub fn receive_config(source: String, configs: String) -> Result<(), Error> {
+    let configs: HashMap<String, String> = serde_json::from_str(&configs)
+        .map_err(|_| format_err!("unable to parse replicated configuration"))?;
+
+    crate::server::receive_configs(source, configs)
+}
+
+#[api(
+    protected: true,
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// Promote this standby node, replacing its user, password, second factor, ACL, remote and job configuration with the one
+/// received from the primary node.
+pub fn promote() -> Result<(), Error> {
+    crate::server::promote_standby()
+}
+
+#[api(
+    protected: true,
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// Replicate the configuration to the standby node now.
+pub fn push(rpcenv: &mut dyn RpcEnvironment) -> Result<String, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let (config, _digest) = crate::config::node::config()?;
+    let standby = match config.standby_config() {
+        Some(standby) => standby?,
+        None => bail!("no standby node configured"),
+    };
+
+    let job = Job::new("standby-push", "standby")?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    crate::server::do_standby_push_job(job, standby, &auth_id, None, to_stdout)
+}
+
+#[sortable]
+const SUBDIRS: SubdirMap = &sorted!([
+    ("promote", &Router::new().post(&API_METHOD_PROMOTE)),
+    ("push", &Router::new().post(&API_METHOD_PUSH)),
+    ("status", &Router::new().get(&API_METHOD_GET_STATUS)),
+]);
+
+pub const ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(SUBDIRS))
+    .put(&API_METHOD_RECEIVE_CONFIG)
+    .subdirs(SUBDIRS);
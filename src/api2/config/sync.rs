@@ -7,9 +7,10 @@ use proxmox_router::{http_bail, Permission, Router, RpcEnvironment};
 use proxmox_schema::{api, param_bail};
 
 use pbs_api_types::{
-    Authid, SyncJobConfig, SyncJobConfigUpdater, JOB_ID_SCHEMA, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_REMOTE_AUDIT,
-    PRIV_REMOTE_READ, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    Authid, SyncDirection, SyncJobConfig, SyncJobConfigUpdater, JOB_ID_SCHEMA,
+    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE,
+    PRIV_DATASTORE_READ, PRIV_REMOTE_AUDIT, PRIV_REMOTE_MODIFY, PRIV_REMOTE_READ,
+    PROXMOX_CONFIG_DIGEST_SCHEMA,
 };
 use pbs_config::sync;
 
@@ -39,6 +40,17 @@ pub fn check_sync_job_modify_access(
     job: &SyncJobConfig,
 ) -> bool {
     let ns_anchor_privs = user_info.lookup_privs(auth_id, &job.acl_path());
+
+    if job.sync_direction == Some(SyncDirection::Push) {
+        // the remote side checks the privileges of the remote's user for writing
+        if ns_anchor_privs & PRIV_DATASTORE_READ == 0 {
+            return false;
+        }
+        let remote_privs =
+            user_info.lookup_privs(auth_id, &["remote", &job.remote, &job.remote_store]);
+        return remote_privs & PRIV_REMOTE_MODIFY != 0;
+    }
+
     if ns_anchor_privs & PRIV_DATASTORE_BACKUP == 0 {
         return false;
     }
@@ -216,6 +228,14 @@ pub enum DeletableProperty {
     remote_ns,
     /// Delete the max_depth property,
     max_depth,
+    /// Delete the parallel_downloads property,
+    parallel_downloads,
+    /// Delete the sync_direction property,
+    sync_direction,
+    /// Delete the verify_after_sync property,
+    verify_after_sync,
+    /// Delete the notify property,
+    notify,
 }
 
 #[api(
@@ -310,6 +330,18 @@ pub fn update_sync_job(
                 DeletableProperty::max_depth => {
                     data.max_depth = None;
                 }
+                DeletableProperty::parallel_downloads => {
+                    data.parallel_downloads = None;
+                }
+                DeletableProperty::sync_direction => {
+                    data.sync_direction = None;
+                }
+                DeletableProperty::verify_after_sync => {
+                    data.verify_after_sync = None;
+                }
+                DeletableProperty::notify => {
+                    data.notify = None;
+                }
             }
         }
     }
@@ -344,6 +376,15 @@ pub fn update_sync_job(
     if let Some(group_filter) = update.group_filter {
         data.group_filter = Some(group_filter);
     }
+    if let Some(parallel_downloads) = update.parallel_downloads {
+        data.parallel_downloads = Some(parallel_downloads);
+    }
+    if let Some(sync_direction) = update.sync_direction {
+        data.sync_direction = Some(sync_direction);
+    }
+    if let Some(verify_after_sync) = update.verify_after_sync {
+        data.verify_after_sync = Some(verify_after_sync);
+    }
 
     if update.limit.rate_in.is_some() {
         data.limit.rate_in = update.limit.rate_in;
@@ -371,6 +412,9 @@ pub fn update_sync_job(
     if let Some(max_depth) = update.max_depth {
         data.max_depth = Some(max_depth);
     }
+    if update.notify.is_some() {
+        data.notify = update.notify;
+    }
 
     if let Some(max_depth) = data.max_depth {
         if let Some(ref ns) = data.ns {
@@ -482,6 +526,7 @@ acl:1:/datastore/localstore2:write@pbs:DatastorePowerUser
 acl:1:/datastore/localstore3:write@pbs:DatastoreAdmin
 acl:1:/remote/remote1:read@pbs,write@pbs:RemoteAudit
 acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
+acl:1:/remote/remote1/remotestore2:write@pbs:RemoteAdmin
 "###,
     )
     .expect("test acl.cfg is not parsable");
@@ -508,6 +553,10 @@ acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
         group_filter: None,
         schedule: None,
         limit: pbs_api_types::RateLimitConfig::default(), // no limit
+        parallel_downloads: None,
+        sync_direction: None,
+        verify_after_sync: None,
+        notify: None,
     };
 
     // should work without ACLs
@@ -637,5 +686,27 @@ acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
         &job
     ));
 
+    // pushing requires Remote.Modify on the remote datastore
+    job.sync_direction = Some(SyncDirection::Push);
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+    job.remote_store = "remotestore2".to_string();
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+
+    // and Datastore.Read on the local datastore
+    job.store = "localstore1".to_string();
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+
     Ok(())
 }
@@ -138,6 +138,8 @@ pub enum DeletableProperty {
     MaxDepth,
     /// Delete the 'ns' property
     Ns,
+    /// Delete the 'notify' property
+    Notify,
 }
 
 #[api(
@@ -217,6 +219,9 @@ pub fn update_tape_backup_job(
                 DeletableProperty::Ns => {
                     data.setup.ns = None;
                 }
+                DeletableProperty::Notify => {
+                    data.setup.notify = None;
+                }
             }
         }
     }
@@ -252,6 +257,9 @@ pub fn update_tape_backup_job(
     if update.setup.max_depth.is_some() {
         data.setup.max_depth = update.setup.max_depth;
     }
+    if update.setup.notify.is_some() {
+        data.setup.notify = update.setup.notify;
+    }
 
     let schedule_changed = data.schedule != update.schedule;
     if update.schedule.is_some() {
@@ -117,6 +117,12 @@ pub enum DeletableProperty {
     comment,
     /// Delete the timeframe property
     timeframe,
+    /// Delete the datastore property
+    datastore,
+    /// Delete the shared property
+    shared,
+    /// Delete the auth-id property
+    auth_id,
 }
 
 // fixme: use  TrafficControlUpdater
@@ -188,6 +194,15 @@ pub fn update_traffic_control(
                 DeletableProperty::timeframe => {
                     data.timeframe = None;
                 }
+                DeletableProperty::datastore => {
+                    data.datastore = None;
+                }
+                DeletableProperty::shared => {
+                    data.shared = None;
+                }
+                DeletableProperty::auth_id => {
+                    data.auth_id = None;
+                }
             }
         }
     }
@@ -223,6 +238,15 @@ pub fn update_traffic_control(
     if update.timeframe.is_some() {
         data.timeframe = update.timeframe;
     }
+    if update.datastore.is_some() {
+        data.datastore = update.datastore;
+    }
+    if update.shared.is_some() {
+        data.shared = update.shared;
+    }
+    if update.auth_id.is_some() {
+        data.auth_id = update.auth_id;
+    }
 
     config.set_data(&name, "rule", &data)?;
 
@@ -1,4 +1,5 @@
 use anyhow::{bail, format_err, Error};
+use serde::Serialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::os::unix::prelude::OsStrExt;
@@ -429,6 +430,52 @@ pub fn get_versions() -> Result<Vec<APTUpdateInfo>, Error> {
     Ok(packages)
 }
 
+#[api(
+    properties: {
+        files: {
+            description: "List of parsed repository files.",
+            type: Array,
+            items: {
+                type: APTRepositoryFile,
+            },
+        },
+        errors: {
+            description: "List of problematic files.",
+            type: Array,
+            items: {
+                type: APTRepositoryFileError,
+            },
+        },
+        digest: {
+            schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+        },
+        infos: {
+            description: "List of additional information/warnings about the repositories.",
+            type: Array,
+            items: {
+                type: APTRepositoryInfo,
+            },
+        },
+        "standard-repos": {
+            description: "List of standard repositories and their configuration status.",
+            type: Array,
+            items: {
+                type: APTStandardRepository,
+            },
+        },
+    },
+)]
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Result from parsing the APT repository files in /etc/apt/.
+pub struct APTRepositoriesResult {
+    pub files: Vec<APTRepositoryFile>,
+    pub errors: Vec<APTRepositoryFileError>,
+    pub digest: String,
+    pub infos: Vec<APTRepositoryInfo>,
+    pub standard_repos: Vec<APTStandardRepository>,
+}
+
 #[api(
     input: {
         properties: {
@@ -438,46 +485,14 @@ pub fn get_versions() -> Result<Vec<APTUpdateInfo>, Error> {
         },
     },
     returns: {
-        type: Object,
-        description: "Result from parsing the APT repository files in /etc/apt/.",
-        properties: {
-            files: {
-                description: "List of parsed repository files.",
-                type: Array,
-                items: {
-                    type: APTRepositoryFile,
-                },
-            },
-            errors: {
-                description: "List of problematic files.",
-                type: Array,
-                items: {
-                    type: APTRepositoryFileError,
-                },
-            },
-            digest: {
-                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
-            },
-            infos: {
-                description: "List of additional information/warnings about the repositories.",
-                items: {
-                    type: APTRepositoryInfo,
-                },
-            },
-            "standard-repos": {
-                description: "List of standard repositories and their configuration status.",
-                items: {
-                    type: APTStandardRepository,
-                },
-            },
-        },
+        type: APTRepositoriesResult,
     },
     access: {
         permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
     },
 )]
 /// Get APT repository information.
-pub fn get_repositories() -> Result<Value, Error> {
+pub fn get_repositories() -> Result<APTRepositoriesResult, Error> {
     let (files, errors, digest) = proxmox_apt::repositories::repositories()?;
     let digest = hex::encode(&digest);
 
@@ -486,13 +501,13 @@ pub fn get_repositories() -> Result<Value, Error> {
     let infos = proxmox_apt::repositories::check_repositories(&files, suite);
     let standard_repos = proxmox_apt::repositories::standard_repositories(&files, "pbs", suite);
 
-    Ok(json!({
-        "files": files,
-        "errors": errors,
-        "digest": digest,
-        "infos": infos,
-        "standard-repos": standard_repos,
-    }))
+    Ok(APTRepositoriesResult {
+        files,
+        errors,
+        digest,
+        infos,
+        standard_repos,
+    })
 }
 
 #[api(
@@ -646,6 +661,71 @@ pub fn change_repository(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            handle: {
+                type: APTRepositoryHandle,
+            },
+            enabled: {
+                description: "Whether the repository should be enabled or not.",
+                type: bool,
+            },
+            digest: {
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    protected: true,
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Enable or disable the standard repository identified by the `handle`, for example the
+/// enterprise or no-subscription repository.
+///
+/// Enabling a repository which is not yet configured adds it. Disabling a repository which is not
+/// configured is a no-op.
+///
+/// The `digest` parameter asserts that the configuration has not been modified.
+pub fn set_standard_repository(
+    handle: APTRepositoryHandle,
+    enabled: bool,
+    digest: Option<String>,
+) -> Result<(), Error> {
+    if enabled {
+        return add_repository(handle, digest);
+    }
+
+    let (mut files, _errors, current_digest) = proxmox_apt::repositories::repositories()?;
+
+    let suite = proxmox_apt::repositories::get_current_release_codename()?;
+
+    if let Some(expected_digest) = digest {
+        let current_digest = hex::encode(&current_digest);
+        crate::tools::assert_if_modified(&expected_digest, &current_digest)?;
+    }
+
+    for file in files.iter_mut() {
+        let mut modified = false;
+        for repo in file.repositories.iter_mut() {
+            if repo.enabled && repo.is_referenced_repository(handle, "pbs", &suite.to_string()) {
+                repo.set_enabled(false);
+                modified = true;
+            }
+        }
+        if modified {
+            file.write()?;
+        }
+    }
+
+    Ok(())
+}
+
 const SUBDIRS: SubdirMap = &[
     (
         "changelog",
@@ -656,7 +736,11 @@ const SUBDIRS: SubdirMap = &[
         &Router::new()
             .get(&API_METHOD_GET_REPOSITORIES)
             .post(&API_METHOD_CHANGE_REPOSITORY)
-            .put(&API_METHOD_ADD_REPOSITORY),
+            .put(&API_METHOD_ADD_REPOSITORY)
+            .subdirs(&[(
+                "standard",
+                &Router::new().post(&API_METHOD_SET_STANDARD_REPOSITORY),
+            )]),
     ),
     (
         "update",
@@ -68,6 +68,14 @@ pub enum DeletableProperty {
     description,
     /// Delete the task-log-max-days property
     task_log_max_days,
+    /// Delete the snmp property, disabling SNMP traps.
+    snmp,
+    /// Delete the digest property, disabling the digest email.
+    digest,
+    /// Delete the standby property, disabling the configuration replication.
+    standby,
+    /// Delete the user-expiry-warning property, resetting it to the default.
+    user_expiry_warning,
 }
 
 #[api(
@@ -156,6 +164,18 @@ pub fn update_node_config(
                 DeletableProperty::task_log_max_days => {
                     config.task_log_max_days = None;
                 }
+                DeletableProperty::snmp => {
+                    config.snmp = None;
+                }
+                DeletableProperty::digest => {
+                    config.digest = None;
+                }
+                DeletableProperty::standby => {
+                    config.standby = None;
+                }
+                DeletableProperty::user_expiry_warning => {
+                    config.user_expiry_warning = None;
+                }
             }
         }
     }
@@ -199,6 +219,18 @@ pub fn update_node_config(
     if update.task_log_max_days.is_some() {
         config.task_log_max_days = update.task_log_max_days;
     }
+    if update.snmp.is_some() {
+        config.snmp = update.snmp;
+    }
+    if update.digest.is_some() {
+        config.digest = update.digest;
+    }
+    if update.standby.is_some() {
+        config.standby = update.standby;
+    }
+    if update.user_expiry_warning.is_some() {
+        config.user_expiry_warning = update.user_expiry_warning;
+    }
 
     crate::config::node::save_config(&config)?;
 
@@ -1,18 +1,20 @@
 use anyhow::{bail, Error};
 use serde_json::{json, Value};
 
-use proxmox_router::{Permission, Router, RpcEnvironment, RpcEnvironmentType};
+use proxmox_router::{Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap};
 use proxmox_schema::api;
-use proxmox_sys::task_log;
+use proxmox_sys::{sortable, task_log, task_warn, WorkerTaskContext};
 
 use pbs_api_types::{
-    DataStoreConfig, ZfsCompressionType, ZfsRaidLevel, ZpoolListItem, DATASTORE_SCHEMA,
-    DISK_ARRAY_SCHEMA, DISK_LIST_SCHEMA, NODE_SCHEMA, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY, UPID_SCHEMA,
-    ZFS_ASHIFT_SCHEMA, ZPOOL_NAME_SCHEMA,
+    DataStoreConfig, ZfsDatasetProperties, ZfsRaidLevel, ZpoolListItem, BLOCKDEVICE_NAME_SCHEMA,
+    DATASTORE_SCHEMA, DISK_ARRAY_SCHEMA, DISK_LIST_SCHEMA, NODE_SCHEMA, PRIV_SYS_AUDIT,
+    PRIV_SYS_MODIFY, UPID_SCHEMA, ZFS_ASHIFT_SCHEMA, ZPOOL_NAME_SCHEMA,
 };
 
 use crate::tools::disks::{
-    parse_zpool_status_config_tree, vdev_list_to_tree, zpool_list, zpool_status, DiskUsageType,
+    check_datastore_zfs_properties, parse_zpool_status_config_tree, resilver_state,
+    vdev_list_to_tree, zpool_list, zpool_spares, zpool_status, DiskUsageType, ResilverState,
+    ZFSPoolVDevState,
 };
 
 use proxmox_rest_server::WorkerTask;
@@ -109,6 +111,40 @@ pub fn zpool_details(name: String) -> Result<Value, Error> {
     Ok(tree)
 }
 
+// Parse a disk list and check that all disks exist and are unused.
+fn parse_unused_disks(devices: &str) -> Result<Vec<String>, Error> {
+    let devices = DISK_ARRAY_SCHEMA.parse_property_string(devices)?;
+    let devices: Vec<String> = devices
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_str().unwrap().to_string())
+        .collect();
+
+    let disk_map = crate::tools::disks::DiskUsageQuery::new().query()?;
+    for disk in devices.iter() {
+        match disk_map.get(disk) {
+            Some(info) => {
+                if info.used != DiskUsageType::Unused {
+                    bail!("disk '{}' is already in use.", disk);
+                }
+            }
+            None => {
+                bail!("no such disk '{}'", disk);
+            }
+        }
+    }
+
+    Ok(devices)
+}
+
+fn zpool_vdev_list(pool: &str) -> Result<Vec<ZFSPoolVDevState>, Error> {
+    match zpool_status(pool)?.into_iter().find(|(k, _)| k == "config") {
+        Some((_, config)) => parse_zpool_status_config_tree(&config),
+        None => bail!("got zpool status without config key"),
+    }
+}
+
 #[api(
     protected: true,
     input: {
@@ -129,9 +165,9 @@ pub fn zpool_details(name: String) -> Result<Value, Error> {
                 schema: ZFS_ASHIFT_SCHEMA,
                 optional: true,
             },
-            compression: {
-                type: ZfsCompressionType,
-                optional: true,
+            properties: {
+                type: ZfsDatasetProperties,
+                flatten: true,
             },
             "add-datastore": {
                 description: "Configure a datastore using the zpool.",
@@ -152,7 +188,7 @@ pub fn create_zpool(
     name: String,
     devices: String,
     raidlevel: ZfsRaidLevel,
-    compression: Option<String>,
+    properties: ZfsDatasetProperties,
     ashift: Option<usize>,
     add_datastore: Option<bool>,
     rpcenv: &mut dyn RpcEnvironment,
@@ -165,28 +201,16 @@ pub fn create_zpool(
 
     let ashift = ashift.unwrap_or(12);
 
-    let devices_text = devices.clone();
-    let devices = DISK_ARRAY_SCHEMA.parse_property_string(&devices)?;
-    let devices: Vec<String> = devices
-        .as_array()
-        .unwrap()
-        .iter()
-        .map(|v| v.as_str().unwrap().to_string())
-        .collect();
-
-    let disk_map = crate::tools::disks::DiskUsageQuery::new().query()?;
-    for disk in devices.iter() {
-        match disk_map.get(disk) {
-            Some(info) => {
-                if info.used != DiskUsageType::Unused {
-                    bail!("disk '{}' is already in use.", disk);
-                }
-            }
-            None => {
-                bail!("no such disk '{}'", disk);
-            }
-        }
+    if add_datastore {
+        check_datastore_zfs_properties(&properties)?;
     }
+    let mut zfs_options = properties.to_zfs_options()?;
+    if properties.relatime.is_none() {
+        zfs_options.push("relatime=on".to_string());
+    }
+
+    let devices_text = devices.clone();
+    let devices = parse_unused_disks(&devices)?;
 
     let min_disks = match raidlevel {
         ZfsRaidLevel::Single => 1,
@@ -290,10 +314,8 @@ pub fn create_zpool(
 
             let mut command = std::process::Command::new("zfs");
             command.arg("set");
-            if let Some(compression) = compression {
-                command.arg(&format!("compression={}", compression));
-            }
-            command.args(&["relatime=on", &name]);
+            command.args(&zfs_options);
+            command.arg(&name);
             task_log!(worker, "# {:?}", command);
             let output = proxmox_sys::command::run_command(command, None)?;
             task_log!(worker, "{}", output);
@@ -324,7 +346,252 @@ pub fn create_zpool(
     Ok(upid_str)
 }
 
-pub const POOL_ROUTER: Router = Router::new().get(&API_METHOD_ZPOOL_DETAILS);
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            name: {
+                schema: ZPOOL_NAME_SCHEMA,
+            },
+            devices: {
+                schema: DISK_LIST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "disks"], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Add unused disks as hot spares to a ZFS pool.
+pub fn add_zpool_spares(name: String, devices: String) -> Result<(), Error> {
+    let devices = parse_unused_disks(&devices)?;
+
+    let mut command = std::process::Command::new("zpool");
+    command.args(&["add", &name, "spare"]);
+    command.args(&devices);
+    proxmox_sys::command::run_command(command, None)?;
+
+    Ok(())
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            name: {
+                schema: ZPOOL_NAME_SCHEMA,
+            },
+            device: {
+                description: "Path of the hot spare, as shown in the pool status.",
+                type: String,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "disks"], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Remove an available hot spare from a ZFS pool.
+pub fn remove_zpool_spare(name: String, device: String) -> Result<(), Error> {
+    let spares = zpool_spares(&zpool_vdev_list(&name)?);
+
+    match spares.iter().find(|(spare, _)| *spare == device) {
+        Some((_, Some(state))) if state == "AVAIL" => {}
+        Some(_) => bail!("hot spare '{}' is in use", device),
+        None => bail!("'{}' is not a hot spare of pool '{}'", device, name),
+    }
+
+    let mut command = std::process::Command::new("zpool");
+    command.args(&["remove", &name, &device]);
+    proxmox_sys::command::run_command(command, None)?;
+
+    Ok(())
+}
+
+// Returns the `scan` field of 'zpool status', describing the last scrub or resilver.
+fn zpool_scan(pool: &str) -> Result<String, Error> {
+    Ok(zpool_status(pool)?
+        .into_iter()
+        .find(|(k, _)| k == "scan")
+        .map(|(_, v)| v)
+        .unwrap_or_default())
+}
+
+// Wait for the resilver of `pool` to finish, logging its progress. `previous_scan` is the scan
+// field from before the resilver got started, so that an earlier resilver is not mistaken for it.
+fn wait_for_resilver(worker: &WorkerTask, pool: &str, previous_scan: &str) -> Result<(), Error> {
+    // zpool may need a moment until it reports the resilver it started
+    const MAX_IDLE_POLLS: usize = 6;
+
+    let mut last_progress = String::new();
+    let mut idle_polls = 0;
+
+    loop {
+        let scan = zpool_scan(pool)?;
+
+        match resilver_state(&scan) {
+            ResilverState::Running(progress) => {
+                let progress = progress.unwrap_or("starting");
+                if progress != last_progress {
+                    task_log!(worker, "resilver: {}", progress);
+                    last_progress = progress.to_string();
+                }
+            }
+            ResilverState::Finished if scan != previous_scan => {
+                task_log!(worker, "{}", scan);
+                return Ok(());
+            }
+            ResilverState::Finished | ResilverState::Idle => {
+                idle_polls += 1;
+                if idle_polls >= MAX_IDLE_POLLS {
+                    task_warn!(worker, "no resilver reported for pool '{}'", pool);
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Err(err) = worker.check_abort() {
+            task_warn!(worker, "resilver continues in the background");
+            return Err(err);
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(10));
+    }
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            name: {
+                schema: ZPOOL_NAME_SCHEMA,
+            },
+            device: {
+                description: "Path or GUID of the device to replace, as shown in the pool status.",
+                type: String,
+            },
+            "new-device": {
+                schema: BLOCKDEVICE_NAME_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "disks"], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Replace a (failed) device of a ZFS pool with an unused disk, or with an available hot spare if
+/// no new device is given. The worker takes the old device offline, replaces it and waits for the
+/// resilver to finish.
+pub fn replace_zpool_device(
+    name: String,
+    device: String,
+    new_device: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<String, Error> {
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let auth_id = rpcenv.get_auth_id().unwrap();
+
+    let vdev_list = zpool_vdev_list(&name)?;
+
+    let old_state = match vdev_list.iter().find(|vdev| vdev.name == device) {
+        Some(vdev) => vdev.state.clone(),
+        None => bail!("no device '{}' in pool '{}'", device, name),
+    };
+
+    let (new_device, is_spare) = match new_device {
+        Some(new_device) => {
+            parse_unused_disks(&new_device)?;
+            (new_device, false)
+        }
+        None => {
+            let spare = zpool_spares(&vdev_list)
+                .into_iter()
+                .find(|(_, state)| state.as_deref() == Some("AVAIL"));
+            match spare {
+                Some((spare, _)) => (spare, true),
+                None => bail!("no new device given and no hot spare available"),
+            }
+        }
+    };
+
+    let upid_str = WorkerTask::new_thread(
+        "zfsreplace",
+        Some(name.clone()),
+        auth_id,
+        to_stdout,
+        move |worker| {
+            task_log!(
+                worker,
+                "replace device '{}' of zpool '{}' with '{}'",
+                device,
+                name,
+                new_device
+            );
+
+            // failed devices cannot be taken offline, and do not need to be
+            if matches!(old_state.as_deref(), Some("ONLINE") | Some("DEGRADED")) {
+                let mut command = std::process::Command::new("zpool");
+                command.args(&["offline", &name, &device]);
+                task_log!(worker, "# {:?}", command);
+                proxmox_sys::command::run_command(command, None)?;
+            }
+
+            let previous_scan = zpool_scan(&name)?;
+
+            let mut command = std::process::Command::new("zpool");
+            command.args(&["replace", &name, &device, &new_device]);
+            task_log!(worker, "# {:?}", command);
+            let output = proxmox_sys::command::run_command(command, None)?;
+            task_log!(worker, "{}", output);
+
+            wait_for_resilver(&worker, &name, &previous_scan)?;
+
+            if is_spare {
+                // make the spare a permanent member of the pool
+                let mut command = std::process::Command::new("zpool");
+                command.args(&["detach", &name, &device]);
+                task_log!(worker, "# {:?}", command);
+                proxmox_sys::command::run_command(command, None)?;
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(upid_str)
+}
+
+#[sortable]
+const POOL_SUBDIRS: SubdirMap = &sorted!([
+    (
+        "replace",
+        &Router::new().post(&API_METHOD_REPLACE_ZPOOL_DEVICE)
+    ),
+    (
+        "spare",
+        &Router::new()
+            .post(&API_METHOD_ADD_ZPOOL_SPARES)
+            .delete(&API_METHOD_REMOVE_ZPOOL_SPARE)
+    ),
+]);
+
+pub const POOL_ROUTER: Router = Router::new()
+    .get(&API_METHOD_ZPOOL_DETAILS)
+    .subdirs(POOL_SUBDIRS);
 
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_LIST_ZPOOLS)
@@ -5,14 +5,16 @@ use std::collections::BTreeMap;
 use proxmox_router::{Permission, Router};
 use proxmox_schema::api;
 
-use pbs_api_types::{RRDMode, RRDTimeFrame, NODE_SCHEMA, PRIV_SYS_AUDIT};
+use pbs_api_types::{
+    RRDMode, RRDTimeFrame, NODE_SCHEMA, PRIV_SYS_AUDIT, RRD_END_TIME_SCHEMA, RRD_START_TIME_SCHEMA,
+};
 
-use crate::rrd_cache::extract_rrd_data;
+use crate::rrd_cache::{extract_rrd_data_range, RRDTimeRange};
 
 pub fn create_value_from_rrd(
     basedir: &str,
     list: &[&str],
-    timeframe: RRDTimeFrame,
+    range: RRDTimeRange,
     mode: RRDMode,
 ) -> Result<Value, Error> {
     let mut result: Vec<Value> = Vec::new();
@@ -22,7 +24,7 @@ pub fn create_value_from_rrd(
     let mut last_resolution = None;
 
     for name in list {
-        let (start, reso, data) = match extract_rrd_data(basedir, name, timeframe, mode)? {
+        let (start, reso, data) = match extract_rrd_data_range(basedir, name, range, mode)? {
             Some(result) => result.into(),
             None => continue,
         };
@@ -65,9 +67,19 @@ pub fn create_value_from_rrd(
             },
             timeframe: {
                 type: RRDTimeFrame,
+                optional: true,
+            },
+            start: {
+                schema: RRD_START_TIME_SCHEMA,
+                optional: true,
+            },
+            end: {
+                schema: RRD_END_TIME_SCHEMA,
+                optional: true,
             },
             cf: {
                 type: RRDMode,
+                optional: true,
             },
         },
     },
@@ -75,8 +87,16 @@ pub fn create_value_from_rrd(
         permission: &Permission::Privilege(&["system", "status"], PRIV_SYS_AUDIT, false),
     },
 )]
-/// Read node stats
-fn get_node_stats(timeframe: RRDTimeFrame, cf: RRDMode, _param: Value) -> Result<Value, Error> {
+/// Read node stats, either for a fixed time frame or a custom start/end range
+fn get_node_stats(
+    timeframe: Option<RRDTimeFrame>,
+    start: Option<u64>,
+    end: Option<u64>,
+    cf: Option<RRDMode>,
+    _param: Value,
+) -> Result<Value, Error> {
+    let range = RRDTimeRange::from_params(timeframe, start, end)?;
+
     create_value_from_rrd(
         "host",
         &[
@@ -97,8 +117,8 @@ fn get_node_stats(timeframe: RRDTimeFrame, cf: RRDMode, _param: Value) -> Result
             "write_bytes",
             "io_ticks",
         ],
-        timeframe,
-        cf,
+        range,
+        cf.unwrap_or_default(),
     )
 }
 
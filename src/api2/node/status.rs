@@ -80,6 +80,7 @@ async fn get_status(
     );
 
     let disk = crate::tools::fs::fs_info_static(proxmox_lang::c_str!("/")).await?;
+    let (total_inodes, used_inodes) = crate::tools::fs::fs_inode_info("/".into()).await?;
 
     Ok(NodeStatus {
         memory,
@@ -88,6 +89,9 @@ async fn get_status(
             total: disk.total,
             used: disk.used,
             avail: disk.available,
+            total_inodes: Some(total_inodes),
+            used_inodes: Some(used_inodes),
+            ..Default::default()
         },
         uptime: procfs::read_proc_uptime()?.0 as u64,
         loadavg,
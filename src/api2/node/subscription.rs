@@ -3,7 +3,7 @@ use serde_json::Value;
 
 use proxmox_http::client::Client;
 use proxmox_http::HttpOptions;
-use proxmox_router::{Permission, Router, RpcEnvironment};
+use proxmox_router::{Permission, Router, RpcEnvironment, SubdirMap};
 use proxmox_schema::api;
 use proxmox_subscription::{SubscriptionInfo, SubscriptionStatus};
 use proxmox_sys::fs::CreateOptions;
@@ -205,6 +205,60 @@ pub fn set_subscription(key: String) -> Result<(), Error> {
     check_and_write_subscription(key, server_id)
 }
 
+#[api(
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            data: {
+                type: String,
+                description: "Base64 encoded, signed subscription info.",
+            },
+        },
+    },
+    protected: true,
+    access: {
+        permission: &Permission::Privilege(&["system"], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Activate a subscription with a signed offline key, for servers without internet access.
+pub fn set_offline_subscription_key(data: String) -> Result<(), Error> {
+    let data = base64::decode(data.trim())
+        .map_err(|err| format_err!("offline key is not valid base64 - {}", err))?;
+    let mut info: SubscriptionInfo = serde_json::from_slice(&data)
+        .map_err(|err| format_err!("unable to parse offline key - {}", err))?;
+
+    if !info.is_signed() {
+        bail!("Offline subscription key must be signed!");
+    }
+    info.check_signature(&[proxmox_subscription::files::DEFAULT_SIGNING_KEY]);
+    info.check_age(false);
+    info.check_server_id();
+
+    if info.status != SubscriptionStatus::Active {
+        bail!(
+            "offline subscription key is not valid - {}",
+            info.message.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    proxmox_subscription::files::write_subscription(
+        PROXMOX_BACKUP_SUBSCRIPTION_FN,
+        subscription_file_opts()?,
+        &info,
+    )
+    .map_err(|e| format_err!("Error writing subscription status - {}", e))?;
+
+    proxmox_subscription::files::update_apt_auth(
+        APT_AUTH_FN,
+        apt_auth_file_opts(),
+        APT_AUTH_URL,
+        info.key,
+        info.serverid,
+    )
+}
+
 #[api(
     input: {
         properties: {
@@ -234,8 +288,14 @@ pub fn delete_subscription() -> Result<(), Error> {
     Ok(())
 }
 
+const SUBDIRS: SubdirMap = &[(
+    "offline-key",
+    &Router::new().put(&API_METHOD_SET_OFFLINE_SUBSCRIPTION_KEY),
+)];
+
 pub const ROUTER: Router = Router::new()
     .post(&API_METHOD_CHECK_SUBSCRIPTION)
     .put(&API_METHOD_SET_SUBSCRIPTION)
     .delete(&API_METHOD_DELETE_SUBSCRIPTION)
-    .get(&API_METHOD_GET_SUBSCRIPTION);
+    .get(&API_METHOD_GET_SUBSCRIPTION)
+    .subdirs(SUBDIRS);
@@ -105,6 +105,11 @@ fn check_job_store(upid: &UPID, store: &str) -> bool {
         ("prune", Some(workerid))
         | ("prunejob", Some(workerid))
         | ("backup", Some(workerid))
+        | ("reader", Some(workerid))
+        | ("sync", Some(workerid))
+        | ("tape-backup", Some(workerid))
+        | ("create-datastore", Some(workerid))
+        | ("delete-datastore", Some(workerid))
         | ("garbage_collection", Some(workerid)) => {
             return workerid == store || workerid.starts_with(&format!("{}:", store));
         }
@@ -451,6 +456,12 @@ fn stop_task(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Err
                     type: TaskStateType,
                 },
             },
+            "exact-total": {
+                type: bool,
+                description: "Count all matching tasks for 'total', even after the requested page.",
+                optional: true,
+                default: false,
+            },
         },
     },
     returns: pbs_api_types::NODE_TASKS_LIST_TASKS_RETURN_TYPE,
@@ -460,6 +471,11 @@ fn stop_task(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Err
     },
 )]
 /// List tasks.
+///
+/// By default, the `total` attribute of the result is one more than the number of tasks up to the
+/// end of the page if there are more tasks after it, to avoid reading the whole task archive. With
+/// `exact-total`, it contains the number of all tasks matching the filters, which requires
+/// reading the whole task archive.
 #[allow(clippy::too_many_arguments)]
 pub fn list_tasks(
     start: u64,
@@ -471,6 +487,7 @@ pub fn list_tasks(
     until: Option<i64>,
     typefilter: Option<String>,
     statusfilter: Option<Vec<TaskStateType>>,
+    exact_total: bool,
     param: Value,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Vec<TaskListItem>, Error> {
@@ -489,7 +506,8 @@ pub fn list_tasks(
         usize::MAX
     };
 
-    let mut skipped = 0;
+    // number of tasks matching the filters, for pagination
+    let mut count = 0;
     let mut result: Vec<TaskListItem> = Vec::new();
 
     for info in list {
@@ -551,22 +569,20 @@ pub fn list_tasks(
             _ => {}
         }
 
-        if skipped < start as usize {
-            skipped += 1;
+        count += 1;
+        if count <= start as usize {
             continue;
         }
 
-        result.push(into_task_list_item(info));
-
         if result.len() >= limit {
+            if exact_total {
+                continue;
+            }
+            // the page is full, counting this task tells the client that there are more
             break;
         }
-    }
 
-    let mut count = result.len() + start as usize;
-    if !result.is_empty() && result.len() >= limit {
-        // we have a 'virtual' entry as long as we have any new
-        count += 1;
+        result.push(into_task_list_item(info));
     }
 
     rpcenv["total"] = Value::from(count);
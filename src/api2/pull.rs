@@ -1,5 +1,7 @@
 //! Sync datastore from remote server
 use std::convert::TryFrom;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
 use anyhow::{format_err, Error};
 use futures::{future::FutureExt, select};
@@ -9,15 +11,17 @@ use proxmox_schema::api;
 use proxmox_sys::task_log;
 
 use pbs_api_types::{
-    Authid, BackupNamespace, GroupFilter, RateLimitConfig, SyncJobConfig, DATASTORE_SCHEMA,
-    GROUP_FILTER_LIST_SCHEMA, NS_MAX_DEPTH_REDUCED_SCHEMA, PRIV_DATASTORE_BACKUP,
+    Authid, BackupNamespace, GroupFilter, RateLimitConfig, SyncDirection, SyncJobConfig,
+    DATASTORE_SCHEMA, GROUP_FILTER_LIST_SCHEMA, NS_MAX_DEPTH_REDUCED_SCHEMA, PRIV_DATASTORE_BACKUP,
     PRIV_DATASTORE_PRUNE, PRIV_REMOTE_READ, REMOTE_ID_SCHEMA, REMOVE_VANISHED_BACKUPS_SCHEMA,
+    SYNC_PARALLEL_DOWNLOADS_SCHEMA, SYNC_VERIFY_SCHEMA,
 };
 use pbs_config::CachedUserInfo;
 use proxmox_rest_server::WorkerTask;
 
 use crate::server::jobstate::Job;
-use crate::server::pull::{pull_store, PullParameters};
+use crate::server::pull::{pull_store, PullParameters, PullStats};
+use crate::server::push::{push_store, PushParameters};
 
 pub fn check_pull_privs(
     auth_id: &Authid,
@@ -78,6 +82,8 @@ impl TryFrom<&SyncJobConfig> for PullParameters {
             sync_job.max_depth,
             sync_job.group_filter.clone(),
             sync_job.limit.clone(),
+            sync_job.parallel_downloads,
+            sync_job.verify_after_sync,
         )
     }
 }
@@ -111,24 +117,45 @@ pub fn do_sync_job(
 
             let worker2 = worker.clone();
             let sync_job2 = sync_job.clone();
+            let stats = Arc::new(PullStats::default());
+            let stats2 = Arc::clone(&stats);
 
             let worker_future = async move {
-                let pull_params = PullParameters::try_from(&sync_job)?;
-                let client = pull_params.client().await?;
-
                 task_log!(worker, "Starting datastore sync job '{}'", job_id);
                 if let Some(event_str) = schedule {
                     task_log!(worker, "task triggered by schedule '{}'", event_str);
                 }
-                task_log!(
-                    worker,
-                    "sync datastore '{}' from '{}/{}'",
-                    sync_job.store,
-                    sync_job.remote,
-                    sync_job.remote_store,
-                );
 
-                pull_store(&worker, &client, pull_params).await?;
+                match sync_job.sync_direction.unwrap_or_default() {
+                    SyncDirection::Pull => {
+                        let pull_params = PullParameters::try_from(&sync_job)?.with_stats(stats2);
+                        let client = pull_params.client().await?;
+
+                        task_log!(
+                            worker,
+                            "sync datastore '{}' from '{}/{}'",
+                            sync_job.store,
+                            sync_job.remote,
+                            sync_job.remote_store,
+                        );
+
+                        pull_store(&worker, &client, pull_params).await?;
+                    }
+                    SyncDirection::Push => {
+                        let push_params = PushParameters::try_from(&sync_job)?.with_stats(stats2);
+                        let client = push_params.client().await?;
+
+                        task_log!(
+                            worker,
+                            "sync datastore '{}' to '{}/{}'",
+                            sync_job.store,
+                            sync_job.remote,
+                            sync_job.remote_store,
+                        );
+
+                        push_store(&worker, &client, push_params).await?;
+                    }
+                }
 
                 task_log!(worker, "sync job '{}' end", &job_id);
 
@@ -146,6 +173,11 @@ pub fn do_sync_job(
 
             let status = worker2.create_state(&result);
 
+            job.set_run_statistics(
+                Some(stats.transferred_bytes.load(Ordering::SeqCst)),
+                Some(stats.snapshots.load(Ordering::SeqCst)),
+            );
+
             match job.finish(status) {
                 Ok(_) => {}
                 Err(err) => {
@@ -203,7 +235,15 @@ pub fn do_sync_job(
             limit: {
                 type: RateLimitConfig,
                 flatten: true,
-            }
+            },
+            "parallel-downloads": {
+                schema: SYNC_PARALLEL_DOWNLOADS_SCHEMA,
+                optional: true,
+            },
+            "verify-after-sync": {
+                schema: SYNC_VERIFY_SCHEMA,
+                optional: true,
+            },
         },
     },
     access: {
@@ -227,6 +267,8 @@ async fn pull(
     max_depth: Option<usize>,
     group_filter: Option<Vec<GroupFilter>>,
     limit: RateLimitConfig,
+    parallel_downloads: Option<usize>,
+    verify_after_sync: Option<bool>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
@@ -259,6 +301,8 @@ async fn pull(
         max_depth,
         group_filter,
         limit,
+        parallel_downloads,
+        verify_after_sync,
     )?;
     let client = pull_params.client().await?;
 
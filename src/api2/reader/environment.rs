@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 use serde_json::{json, Value};
@@ -7,22 +9,30 @@ use proxmox_router::{RpcEnvironment, RpcEnvironmentType};
 
 use pbs_api_types::Authid;
 use pbs_datastore::backup_info::BackupDir;
+use pbs_datastore::manifest::PayloadCompression;
+use pbs_datastore::protocol::ChunkCompression;
 use pbs_datastore::DataStore;
 use proxmox_rest_server::formatter::*;
 use proxmox_rest_server::WorkerTask;
 
+use crate::server::transfer_accounting::{record_transfer, TransferSession};
+
 /// `RpcEnvironmet` implementation for backup reader service
 #[derive(Clone)]
 pub struct ReaderEnvironment {
     env_type: RpcEnvironmentType,
     result_attributes: Value,
     auth_id: Authid,
+    pub client_ip: Option<IpAddr>,
     pub debug: bool,
     pub formatter: &'static dyn OutputFormatter,
     pub worker: Arc<WorkerTask>,
     pub datastore: Arc<DataStore>,
     pub backup_dir: BackupDir,
+    pub chunk_compression: Vec<ChunkCompression>,
+    pub payload_compression: Vec<PayloadCompression>,
     allowed_chunks: Arc<RwLock<HashSet<[u8; 32]>>>,
+    downloaded_bytes: Arc<AtomicU64>,
 }
 
 impl ReaderEnvironment {
@@ -37,12 +47,16 @@ impl ReaderEnvironment {
             result_attributes: json!({}),
             env_type,
             auth_id,
+            client_ip: None,
             worker,
             datastore,
             debug: false,
             formatter: JSON_FORMATTER,
             backup_dir,
+            chunk_compression: ChunkCompression::LEGACY.to_vec(),
+            payload_compression: Vec::new(),
             allowed_chunks: Arc::new(RwLock::new(HashSet::new())),
+            downloaded_bytes: Arc::new(AtomicU64::new(0)),
         }
     }
 
@@ -64,6 +78,23 @@ impl ReaderEnvironment {
     pub fn check_chunk_access(&self, digest: [u8; 32]) -> bool {
         self.allowed_chunks.read().unwrap().contains(&digest)
     }
+
+    pub fn add_downloaded_bytes(&self, bytes: u64) {
+        self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Add the bytes sent in this session to the per-client transfer accounting
+    pub fn record_transfer_accounting(&self) {
+        let transferred = self.downloaded_bytes.load(Ordering::Relaxed);
+        if let Err(err) = record_transfer(
+            &self.auth_id,
+            self.client_ip,
+            TransferSession::Reader,
+            transferred,
+        ) {
+            self.log(format!("updating transfer accounting failed: {}", err));
+        }
+    }
 }
 
 impl RpcEnvironment for ReaderEnvironment {
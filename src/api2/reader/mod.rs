@@ -23,7 +23,11 @@ use pbs_api_types::{
 };
 use pbs_config::CachedUserInfo;
 use pbs_datastore::index::IndexFile;
-use pbs_datastore::manifest::{archive_type, ArchiveType};
+use pbs_datastore::manifest::{archive_type, ArchiveType, PayloadCompression};
+use pbs_datastore::protocol::{
+    feature_offer, negotiate_feature_set, ChunkCompression, CHUNK_COMPRESSION_HEADER,
+    PAYLOAD_COMPRESSION_HEADER,
+};
 use pbs_datastore::{DataStore, PROXMOX_BACKUP_READER_PROTOCOL_ID_V1};
 use pbs_tools::json::required_string_param;
 use proxmox_rest_server::{H2Service, WorkerTask};
@@ -31,6 +35,7 @@ use proxmox_sys::fs::lock_dir_noblock_shared;
 
 use crate::api2::backup::optional_ns_param;
 use crate::api2::helpers;
+use crate::traffic_control_cache::register_peer_session;
 
 mod environment;
 use environment::*;
@@ -106,6 +111,16 @@ fn upgrade_to_backup_reader_protocol(
             bail!("invalid protocol name");
         }
 
+        let chunk_compression = match parts.headers.get(CHUNK_COMPRESSION_HEADER) {
+            Some(offer) => Some(negotiate_feature_set::<ChunkCompression>(offer.to_str()?)),
+            None => None,
+        };
+
+        let payload_compression = match parts.headers.get(PAYLOAD_COMPRESSION_HEADER) {
+            Some(offer) => Some(negotiate_feature_set::<PayloadCompression>(offer.to_str()?)),
+            None => None,
+        };
+
         if parts.version >= http::version::Version::HTTP_2 {
             bail!(
                 "unexpected http version '{:?}' (expected version < 2)",
@@ -114,6 +129,8 @@ fn upgrade_to_backup_reader_protocol(
         }
 
         let env_type = rpcenv.env_type();
+        let peer = rpcenv.get_client_ip();
+        let client_ip = peer.map(|addr| addr.ip());
 
         let backup_dir = datastore.backup_dir(backup_ns, backup_dir)?;
         if !priv_read {
@@ -144,6 +161,10 @@ fn upgrade_to_backup_reader_protocol(
             backup_dir.backup_time(),
         );
 
+        // also confirmed in the response below
+        let worker_chunk_compression = chunk_compression.clone();
+        let worker_payload_compression = payload_compression.clone();
+
         WorkerTask::spawn(
             "reader",
             Some(worker_id),
@@ -152,6 +173,9 @@ fn upgrade_to_backup_reader_protocol(
             move |worker| async move {
                 let _guard = _guard;
 
+                // apply datastore and user specific traffic control rules to this connection
+                let _tc_guard = peer.map(|peer| register_peer_session(peer, &store, &auth_id));
+
                 let mut env = ReaderEnvironment::new(
                     env_type,
                     auth_id,
@@ -161,6 +185,13 @@ fn upgrade_to_backup_reader_protocol(
                 );
 
                 env.debug = debug;
+                env.client_ip = client_ip;
+                if let Some(chunk_compression) = worker_chunk_compression {
+                    env.chunk_compression = chunk_compression;
+                }
+                if let Some(payload_compression) = worker_payload_compression {
+                    env.payload_compression = payload_compression;
+                }
 
                 env.log(format!(
                     "starting new backup reader datastore '{}': {:?}",
@@ -192,24 +223,43 @@ fn upgrade_to_backup_reader_protocol(
                         .await
                 };
 
-                futures::select! {
-                    req = req_fut.fuse() => req?,
-                    abort = abort_future => abort?,
+                let res = futures::select! {
+                    req = req_fut.fuse() => req,
+                    abort = abort_future => abort,
                 };
 
+                proxmox_async::runtime::block_in_place(|| env.record_transfer_accounting());
+
+                res?;
+
                 env.log("reader finished successfully");
 
                 Ok(())
             },
         )?;
 
-        let response = Response::builder()
+        let mut response = Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
             .header(
                 UPGRADE,
                 HeaderValue::from_static(PROXMOX_BACKUP_READER_PROTOCOL_ID_V1!()),
-            )
-            .body(Body::empty())?;
+            );
+
+        if let Some(chunk_compression) = &chunk_compression {
+            response = response.header(
+                CHUNK_COMPRESSION_HEADER,
+                HeaderValue::from_str(&feature_offer(chunk_compression))?,
+            );
+        }
+
+        if let Some(payload_compression) = &payload_compression {
+            response = response.header(
+                PAYLOAD_COMPRESSION_HEADER,
+                HeaderValue::from_str(&feature_offer(payload_compression))?,
+            );
+        }
+
+        let response = response.body(Body::empty())?;
 
         Ok(response)
     }
@@ -262,6 +312,7 @@ fn download_file(
                 Some(Box::new(index))
             }
             ArchiveType::DynamicIndex => {
+                check_payload_compression(env, &file_name)?;
                 let index = env.datastore.open_dynamic_reader(&path)?;
                 Some(Box::new(index))
             }
@@ -280,11 +331,35 @@ fn download_file(
             }
         }
 
-        helpers::create_download_response(path).await
+        let size = std::fs::metadata(&path).map(|stat| stat.len()).unwrap_or(0);
+        let response = helpers::create_download_response(path).await?;
+        env.add_downloaded_bytes(size);
+
+        Ok(response)
     }
     .boxed()
 }
 
+/// Refuse to hand out archives with a compressed payload the client did not offer to decode, as
+/// older clients would treat it like a plain archive.
+fn check_payload_compression(env: &ReaderEnvironment, file_name: &str) -> Result<(), Error> {
+    let (manifest, _) = env.backup_dir.load_manifest()?;
+    let compression = match manifest.lookup_file_info(file_name) {
+        Ok(info) => info.payload_compression,
+        Err(_) => None,
+    };
+
+    match compression {
+        Some(compression) if !env.payload_compression.contains(&compression) => bail!(
+            "archive '{}' uses '{}' payload compression, which this client does not support - \
+             please upgrade the client",
+            file_name,
+            compression
+        ),
+        _ => Ok(()),
+    }
+}
+
 #[sortable]
 pub const API_METHOD_DOWNLOAD_CHUNK: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&download_chunk),
@@ -319,15 +394,27 @@ fn download_chunk(
             ));
         }
 
-        let (path, _) = env.datastore.chunk_path(&digest);
-        let path2 = path.clone();
-
-        env.debug(format!("download chunk {:?}", path));
+        env.debug(format!("download chunk {}", digest_str));
+
+        let data = env
+            .datastore
+            .load_chunk_async(&digest)
+            .await
+            .map_err(|err| http_err!(BAD_REQUEST, "{}", err))?
+            .into_inner();
+
+        if let Some(compression) = ChunkCompression::from_magic(&data[..data.len().min(8)]) {
+            if !env.chunk_compression.contains(&compression) {
+                return Err(http_err!(
+                    BAD_REQUEST,
+                    "chunk {} uses {} compression, which the client does not support",
+                    digest_str,
+                    compression
+                ));
+            }
+        }
 
-        let data =
-            proxmox_async::runtime::block_in_place(|| std::fs::read(path)).map_err(move |err| {
-                http_err!(BAD_REQUEST, "reading file {:?} failed: {}", path2, err)
-            })?;
+        env.add_downloaded_bytes(data.len() as u64);
 
         let body = Body::from(data);
 
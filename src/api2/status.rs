@@ -6,10 +6,11 @@ use serde_json::Value;
 use proxmox_router::list_subdirs_api_method;
 use proxmox_router::{ApiMethod, Permission, Router, RpcEnvironment, SubdirMap};
 use proxmox_schema::api;
+use proxmox_sys::sortable;
 
 use pbs_api_types::{
-    Authid, DataStoreStatusListItem, Operation, RRDMode, RRDTimeFrame, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_BACKUP,
+    Authid, DataStoreForecast, DataStoreStatusListItem, Operation, RRDMode, RRDTimeFrame,
+    ZpoolHealth, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP,
 };
 
 use pbs_config::CachedUserInfo;
@@ -77,17 +78,68 @@ pub async fn datastore_status(
             estimated_full_date: None,
             error: None,
             gc_status: Some(datastore.last_gc_status()),
+            zpool: datastore_zpool_health(datastore.base_path()).await,
         };
 
-        let rrd_dir = format!("datastore/{}", store);
+        if let Some(usage) = datastore_usage_history(store)? {
+            entry.history_start = Some(usage.start);
+            entry.history_delta = Some(usage.resolution);
+            entry.history = Some(usage.history);
+            entry.estimated_full_date = usage.estimated_full_date;
+        }
+
+        list.push(entry);
+    }
+
+    Ok(list)
+}
+
+/// Returns the health of the ZFS pool a datastore is located on, logging errors.
+pub(crate) async fn datastore_zpool_health(path: std::path::PathBuf) -> Option<ZpoolHealth> {
+    match crate::tools::fs::zpool_health_for_path(path).await {
+        Ok(health) => health,
+        Err(err) => {
+            log::error!("could not get zpool health: {}", err);
+            None
+        }
+    }
+}
+
+/// Usage history of a datastore over the last month.
+pub(crate) struct DataStoreUsageHistory {
+    /// History start time (epoch)
+    pub start: u64,
+    /// History resolution (seconds)
+    pub resolution: u64,
+    /// Usage between 0.0 and 1.0 for each time slot, if known
+    pub history: Vec<Option<f64>>,
+    /// Estimated time the datastore is full, see [DataStoreStatusListItem]
+    pub estimated_full_date: Option<i64>,
+}
 
-        let get_rrd =
-            |what: &str| extract_rrd_data(&rrd_dir, what, RRDTimeFrame::Month, RRDMode::Average);
+impl DataStoreUsageHistory {
+    /// Days until the datastore is estimated to be full, `None` if usage is not growing.
+    pub fn days_until_full(&self, now: i64) -> Option<f64> {
+        match self.estimated_full_date {
+            Some(estimate) if estimate > now => Some((estimate - now) as f64 / 86400.0),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the usage history of the last month from the RRD data of `store` and fits a linear
+/// trend to estimate when it will be full.
+pub(crate) fn datastore_usage_history(store: &str) -> Result<Option<DataStoreUsageHistory>, Error> {
+    let rrd_dir = format!("datastore/{}", store);
 
-        let total_res = get_rrd("total")?;
-        let used_res = get_rrd("used")?;
+    let get_rrd =
+        |what: &str| extract_rrd_data(&rrd_dir, what, RRDTimeFrame::Month, RRDMode::Average);
 
-        if let (
+    let total_res = get_rrd("total")?;
+    let used_res = get_rrd("used")?;
+
+    let (start, resolution, total_list, used_list) = match (total_res, used_res) {
+        (
             Some(proxmox_rrd::Entry {
                 start,
                 resolution,
@@ -96,54 +148,104 @@ pub async fn datastore_status(
             Some(proxmox_rrd::Entry {
                 data: used_list, ..
             }),
-        ) = (total_res, used_res)
-        {
-            let mut usage_list: Vec<f64> = Vec::new();
-            let mut time_list: Vec<u64> = Vec::new();
-            let mut history = Vec::new();
-
-            for (idx, used) in used_list.iter().enumerate() {
-                let total = if idx < total_list.len() {
-                    total_list[idx]
-                } else {
-                    None
-                };
-
-                match (total, used) {
-                    (Some(total), Some(used)) if total != 0.0 => {
-                        time_list.push(start + (idx as u64) * resolution);
-                        let usage = used / total;
-                        usage_list.push(usage);
-                        history.push(Some(usage));
-                    }
-                    _ => history.push(None),
-                }
-            }
+        ) => (start, resolution, total_list, used_list),
+        _ => return Ok(None),
+    };
+
+    let mut usage_list: Vec<f64> = Vec::new();
+    let mut time_list: Vec<u64> = Vec::new();
+    let mut history = Vec::new();
+
+    for (idx, used) in used_list.iter().enumerate() {
+        let total = if idx < total_list.len() {
+            total_list[idx]
+        } else {
+            None
+        };
 
-            entry.history_start = Some(start);
-            entry.history_delta = Some(resolution);
-            entry.history = Some(history);
-
-            // we skip the calculation for datastores with not enough data
-            if usage_list.len() >= 7 {
-                entry.estimated_full_date = match linear_regression(&time_list, &usage_list) {
-                    Some((a, b)) if b != 0.0 => Some(((1.0 - a) / b).floor() as i64),
-                    Some((_, b)) if b == 0.0 => Some(0), // infinite estimate, set to past for gui to detect
-                    _ => None,
-                };
+        match (total, used) {
+            (Some(total), Some(used)) if total != 0.0 => {
+                time_list.push(start + (idx as u64) * resolution);
+                let usage = used / total;
+                usage_list.push(usage);
+                history.push(Some(usage));
             }
+            _ => history.push(None),
         }
+    }
 
-        list.push(entry);
+    let mut estimated_full_date = None;
+
+    // we skip the calculation for datastores with not enough data
+    if usage_list.len() >= 7 {
+        estimated_full_date = match linear_regression(&time_list, &usage_list) {
+            Some((a, b)) if b != 0.0 => Some(((1.0 - a) / b).floor() as i64),
+            Some((_, b)) if b == 0.0 => Some(0), // infinite estimate, set to past for gui to detect
+            _ => None,
+        };
+    }
+
+    Ok(Some(DataStoreUsageHistory {
+        start,
+        resolution,
+        history,
+        estimated_full_date,
+    }))
+}
+
+#[api(
+    returns: {
+        description: "Capacity forecast of the datastores.",
+        type: Array,
+        items: {
+            type: DataStoreForecast,
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Only lists datastores with Datastore.Audit or Datastore.Backup privileges.",
+    },
+)]
+/// Estimate for each datastore when it will be full, based on the usage of the last month.
+pub fn datastore_forecast(
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<DataStoreForecast>, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let now = proxmox_time::epoch_i64();
+    let mut list = Vec::new();
+
+    for store in config.sections.keys() {
+        let user_privs = user_info.lookup_privs(&auth_id, &["datastore", store]);
+        if (user_privs & (PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_BACKUP)) == 0 {
+            continue;
+        }
+
+        let usage = datastore_usage_history(store)?;
+        list.push(DataStoreForecast {
+            store: store.clone(),
+            estimated_full_date: usage.as_ref().and_then(|usage| usage.estimated_full_date),
+            days_until_full: usage.and_then(|usage| usage.days_until_full(now)),
+        });
     }
 
     Ok(list)
 }
 
-const SUBDIRS: SubdirMap = &[(
-    "datastore-usage",
-    &Router::new().get(&API_METHOD_DATASTORE_STATUS),
-)];
+#[sortable]
+const SUBDIRS: SubdirMap = &sorted!([
+    (
+        "datastore-forecast",
+        &Router::new().get(&API_METHOD_DATASTORE_FORECAST)
+    ),
+    (
+        "datastore-usage",
+        &Router::new().get(&API_METHOD_DATASTORE_STATUS)
+    ),
+]);
 
 pub const ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SUBDIRS))
@@ -4,13 +4,15 @@ use anyhow::{bail, format_err, Error};
 use serde_json::Value;
 
 use proxmox_lang::try_block;
-use proxmox_router::{Permission, Router, RpcEnvironment, RpcEnvironmentType};
+use proxmox_router::{
+    list_subdirs_api_method, Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+};
 use proxmox_schema::api;
-use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+use proxmox_sys::{sortable, task_log, task_warn, WorkerTaskContext};
 
 use pbs_api_types::{
-    print_ns_and_snapshot, print_store_and_ns, Authid, GroupFilter, MediaPoolConfig, Operation,
-    TapeBackupJobConfig, TapeBackupJobSetup, TapeBackupJobStatus, Userid, JOB_ID_SCHEMA,
+    print_ns_and_snapshot, print_store_and_ns, Authid, GroupFilter, JobHistory, MediaPoolConfig,
+    Operation, TapeBackupJobConfig, TapeBackupJobSetup, TapeBackupJobStatus, Userid, JOB_ID_SCHEMA,
     PRIV_DATASTORE_READ, PRIV_TAPE_AUDIT, PRIV_TAPE_WRITE, UPID_SCHEMA,
 };
 
@@ -21,7 +23,7 @@ use proxmox_rest_server::WorkerTask;
 
 use crate::{
     server::{
-        jobstate::{compute_schedule_status, Job, JobState},
+        jobstate::{compute_schedule_status, read_job_history, Job, JobState},
         lookup_user_email, TapeBackupJobSummary,
     },
     tape::{
@@ -31,7 +33,16 @@ use crate::{
     },
 };
 
-const TAPE_BACKUP_JOB_ROUTER: Router = Router::new().post(&API_METHOD_RUN_TAPE_BACKUP_JOB);
+#[sortable]
+const TAPE_BACKUP_JOB_SUBDIRS: SubdirMap = &sorted!([(
+    "history",
+    &Router::new().get(&API_METHOD_TAPE_BACKUP_JOB_HISTORY)
+),]);
+
+const TAPE_BACKUP_JOB_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(TAPE_BACKUP_JOB_SUBDIRS))
+    .post(&API_METHOD_RUN_TAPE_BACKUP_JOB)
+    .subdirs(TAPE_BACKUP_JOB_SUBDIRS);
 
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_LIST_TAPE_BACKUP_JOBS)
@@ -214,6 +225,8 @@ pub fn do_tape_backup_job(
 
             let status = worker.create_state(&job_result);
 
+            job.set_run_statistics(None, Some(summary.snapshot_list.len() as u64));
+
             if let Some(email) = email {
                 if let Err(err) = crate::server::send_tape_backup_status(
                     &email,
@@ -279,6 +292,29 @@ pub fn run_tape_backup_job(id: String, rpcenv: &mut dyn RpcEnvironment) -> Resul
     Ok(upid_str)
 }
 
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        type: JobHistory,
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "job", "{id}"], PRIV_TAPE_AUDIT, false),
+    },
+)]
+/// Returns the recorded runs of a tape backup job with aggregated statistics.
+pub fn tape_backup_job_history(id: String) -> Result<JobHistory, Error> {
+    let (config, _digest) = pbs_config::tape_job::config()?;
+    let _job: TapeBackupJobConfig = config.lookup("backup", &id)?;
+
+    read_job_history("tape-backup-job", &id)
+}
+
 #[api(
     input: {
         properties: {
@@ -27,7 +27,7 @@ use pbs_config::CachedUserInfo;
 use pbs_tape::{
     linux_list_drives::{lookup_device_identification, lto_tape_device_list, open_lto_tape_device},
     sg_tape::tape_alert_flags_critical,
-    BlockReadError,
+    BlockReadError, ElementStatus,
 };
 use proxmox_rest_server::WorkerTask;
 
@@ -1212,7 +1212,34 @@ pub async fn status(drive: String) -> Result<LtoDriveAndMediaStatus, Error> {
 
             let mut handle = LtoTapeHandle::new(file)?;
 
-            handle.get_drive_and_media_status()
+            let mut status = handle.get_drive_and_media_status()?;
+
+            // the changer knows the barcode, even if the library does not write it to the MAM
+            if let Some((mut changer, _)) = media_changer(&config, &drive)? {
+                let drive_num = changer.drive_number() as usize;
+                match changer.status() {
+                    Ok(changer_status) => {
+                        if let Some(drive_status) = changer_status.drives.get(drive_num) {
+                            if let ElementStatus::VolumeTag(ref tag) = drive_status.status {
+                                status.label_text = Some(tag.clone());
+                            }
+                        }
+                    }
+                    Err(err) => eprintln!("unable to read changer status - {}", err), // best-effort only
+                }
+            }
+
+            if let Some(ref label_text) = status.label_text {
+                let inventory = Inventory::load(TAPE_STATUS_DIR)?;
+                if let Some(media_id) = inventory.find_media_by_label_text(label_text) {
+                    status.pool = media_id
+                        .media_set_label
+                        .as_ref()
+                        .map(|set| set.pool.clone());
+                }
+            }
+
+            Ok(status)
         },
     )
     .await
@@ -76,6 +76,25 @@ struct PBS();
 
 const SHADOW_CONFIG_FILENAME: &str = configdir!("/shadow.json");
 
+/// Read the password hashes of the `pbs` realm users.
+pub fn read_shadow_config() -> Result<serde_json::Value, Error> {
+    proxmox_sys::fs::file_get_json(SHADOW_CONFIG_FILENAME, Some(json!({})))
+}
+
+/// Replace the password hashes of the `pbs` realm users.
+pub fn save_shadow_config(data: &serde_json::Value) -> Result<(), Error> {
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
+    let options = proxmox_sys::fs::CreateOptions::new()
+        .perm(mode)
+        .owner(nix::unistd::ROOT)
+        .group(nix::unistd::Gid::from_raw(0));
+
+    let data = serde_json::to_vec_pretty(data)?;
+    proxmox_sys::fs::replace_file(SHADOW_CONFIG_FILENAME, &data, options, true)?;
+
+    Ok(())
+}
+
 impl ProxmoxAuthenticator for PBS {
     fn authenticate_user(&self, username: &UsernameRef, password: &str) -> Result<(), Error> {
         let data = proxmox_sys::fs::file_get_json(SHADOW_CONFIG_FILENAME, Some(json!({})))?;
@@ -91,16 +110,7 @@ impl ProxmoxAuthenticator for PBS {
         let mut data = proxmox_sys::fs::file_get_json(SHADOW_CONFIG_FILENAME, Some(json!({})))?;
         data[username.as_str()] = enc_password.into();
 
-        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
-        let options = proxmox_sys::fs::CreateOptions::new()
-            .perm(mode)
-            .owner(nix::unistd::ROOT)
-            .group(nix::unistd::Gid::from_raw(0));
-
-        let data = serde_json::to_vec_pretty(&data)?;
-        proxmox_sys::fs::replace_file(SHADOW_CONFIG_FILENAME, &data, options, true)?;
-
-        Ok(())
+        save_shadow_config(&data)
     }
 
     fn remove_password(&self, username: &UsernameRef) -> Result<(), Error> {
@@ -109,16 +119,7 @@ impl ProxmoxAuthenticator for PBS {
             map.remove(username.as_str());
         }
 
-        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
-        let options = proxmox_sys::fs::CreateOptions::new()
-            .perm(mode)
-            .owner(nix::unistd::ROOT)
-            .group(nix::unistd::Gid::from_raw(0));
-
-        let data = serde_json::to_vec_pretty(&data)?;
-        proxmox_sys::fs::replace_file(SHADOW_CONFIG_FILENAME, &data, options, true)?;
-
-        Ok(())
+        save_shadow_config(&data)
     }
 }
 
@@ -1,6 +1,6 @@
 use nix::dir::Dir;
 use std::collections::HashSet;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
@@ -10,7 +10,8 @@ use proxmox_sys::{task_log, WorkerTaskContext};
 
 use pbs_api_types::{
     print_ns_and_snapshot, print_store_and_ns, Authid, BackupNamespace, BackupType, CryptMode,
-    SnapshotVerifyState, VerifyState, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_VERIFY, UPID,
+    DatastoreBackendType, SnapshotVerifyState, VerifyState, PRIV_DATASTORE_BACKUP,
+    PRIV_DATASTORE_VERIFY, UPID,
 };
 use pbs_datastore::backup_info::{BackupDir, BackupGroup, BackupInfo};
 use pbs_datastore::index::IndexFile;
@@ -29,6 +30,9 @@ pub struct VerifyWorker {
     datastore: Arc<DataStore>,
     verified_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
     corrupt_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    quarantined_chunks: Arc<AtomicUsize>,
+    refetched_chunks: Arc<AtomicUsize>,
+    verified_snapshots: Arc<AtomicUsize>,
 }
 
 impl VerifyWorker {
@@ -41,6 +45,34 @@ impl VerifyWorker {
             verified_chunks: Arc::new(Mutex::new(HashSet::with_capacity(16 * 1024))),
             // start with 64 chunks since we assume there are few corrupt ones
             corrupt_chunks: Arc::new(Mutex::new(HashSet::with_capacity(64))),
+            quarantined_chunks: Arc::new(AtomicUsize::new(0)),
+            refetched_chunks: Arc::new(AtomicUsize::new(0)),
+            verified_snapshots: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the chunks found to be corrupt so far and forgets about them, so that they get
+    /// checked again when they are referenced after being replaced, for example by a sync.
+    pub fn take_corrupt_chunks(&self) -> HashSet<[u8; 32]> {
+        std::mem::take(&mut *self.corrupt_chunks.lock().unwrap())
+    }
+
+    /// Returns the number of snapshots checked so far, not counting those skipped by a filter.
+    pub fn verified_snapshot_count(&self) -> usize {
+        self.verified_snapshots.load(Ordering::SeqCst)
+    }
+
+    /// Logs how many corrupt chunks were quarantined and downloaded again from a remote.
+    pub fn log_chunk_summary(&self) {
+        let quarantined = self.quarantined_chunks.load(Ordering::SeqCst);
+        let refetched = self.refetched_chunks.load(Ordering::SeqCst);
+        if quarantined > 0 || refetched > 0 {
+            task_log!(
+                self.worker,
+                "quarantined {} corrupt chunks, downloaded {} chunks again from remotes",
+                quarantined,
+                refetched,
+            );
         }
     }
 }
@@ -69,63 +101,135 @@ fn verify_blob(backup_dir: &BackupDir, info: &FileInfo) -> Result<(), Error> {
     }
 }
 
-fn rename_corrupted_chunk(
-    datastore: Arc<DataStore>,
+fn quarantine_corrupt_chunk(
+    datastore: &DataStore,
     digest: &[u8; 32],
     worker: &dyn WorkerTaskContext,
+    quarantined: &AtomicUsize,
 ) {
-    let (path, digest_str) = datastore.chunk_path(digest);
-
-    let mut counter = 0;
-    let mut new_path = path.clone();
-    loop {
-        new_path.set_file_name(format!("{}.{}.bad", digest_str, counter));
-        if new_path.exists() && counter < 9 {
-            counter += 1;
-        } else {
-            break;
+    if datastore.backend_type() == DatastoreBackendType::S3 {
+        // objects cannot be moved, and a copy would not be kept by garbage collection anyway
+        match datastore.remove_chunk(digest) {
+            Ok(()) => {
+                quarantined.fetch_add(1, Ordering::SeqCst);
+                task_log!(worker, "corrupted chunk {} removed", hex::encode(digest));
+            }
+            Err(err) => task_log!(
+                worker,
+                "could not remove corrupted chunk {} - {}",
+                hex::encode(digest),
+                err
+            ),
         }
+        return;
     }
 
-    match std::fs::rename(&path, &new_path) {
-        Ok(_) => {
-            task_log!(worker, "corrupted chunk renamed to {:?}", &new_path);
+    match datastore.quarantine_chunk(digest) {
+        Ok(Some(path)) => {
+            quarantined.fetch_add(1, Ordering::SeqCst);
+            task_log!(worker, "corrupted chunk moved to {:?}", path);
         }
-        Err(err) => {
-            match err.kind() {
-                std::io::ErrorKind::NotFound => { /* ignored */ }
-                _ => task_log!(
-                    worker,
-                    "could not rename corrupted chunk {:?} - {}",
-                    &path,
-                    err
-                ),
+        Ok(None) => (), // missing, or replaced by a correct chunk in the meantime
+        Err(err) => task_log!(
+            worker,
+            "could not quarantine corrupted chunk {} - {}",
+            hex::encode(digest),
+            err
+        ),
+    }
+}
+
+// download the `broken` chunks of the archive `file_name` again from the remote of a sync job
+// pulling into the snapshot's namespace, returns the digests of the downloaded chunks
+//
+// Only the snapshot being verified is marked verified afterwards. Other snapshots which failed on
+// the same chunks are not tracked, they only pass once they get verified again.
+fn refetch_corrupt_chunks(
+    verify_worker: &VerifyWorker,
+    backup_dir: &BackupDir,
+    file_name: &str,
+    broken: &[([u8; 32], u64)],
+) -> Vec<[u8; 32]> {
+    let worker = &*verify_worker.worker;
+    let datastore = &verify_worker.datastore;
+
+    let sync_job =
+        match crate::server::lookup_refetch_sync_job(datastore.name(), backup_dir.backup_ns()) {
+            Ok(Some(sync_job)) => sync_job,
+            Ok(None) => return Vec::new(),
+            Err(err) => {
+                task_log!(worker, "could not look up sync jobs - {}", err);
+                return Vec::new();
             }
+        };
+
+    task_log!(
+        worker,
+        "downloading {} corrupt chunks again from remote '{}', datastore '{}'",
+        broken.len(),
+        sync_job.remote,
+        sync_job.remote_store,
+    );
+
+    let refetched = match proxmox_async::runtime::block_on(crate::server::refetch_snapshot_chunks(
+        worker, datastore, &sync_job, backup_dir, file_name, broken,
+    )) {
+        Ok(refetched) => refetched,
+        Err(err) => {
+            task_log!(worker, "could not download corrupt chunks again - {}", err);
+            return Vec::new();
         }
     };
+
+    for digest in &refetched {
+        verify_worker.corrupt_chunks.lock().unwrap().remove(digest);
+        verify_worker
+            .verified_chunks
+            .lock()
+            .unwrap()
+            .insert(*digest);
+    }
+    verify_worker
+        .refetched_chunks
+        .fetch_add(refetched.len(), Ordering::SeqCst);
+
+    task_log!(
+        worker,
+        "downloaded {} of {} corrupt chunks again",
+        refetched.len(),
+        broken.len()
+    );
+
+    refetched
 }
 
 fn verify_index_chunks(
     verify_worker: &VerifyWorker,
+    backup_dir: &BackupDir,
+    file_name: &str,
     index: Box<dyn IndexFile + Send>,
     crypt_mode: CryptMode,
 ) -> Result<(), Error> {
     let errors = Arc::new(AtomicUsize::new(0));
+    // digests of the chunks found corrupt in this index, once per error
+    let broken = Arc::new(Mutex::new(Vec::new()));
 
     let start_time = Instant::now();
 
-    let mut read_bytes = 0;
-    let mut decoded_bytes = 0;
+    let read_bytes = Arc::new(AtomicU64::new(0));
+    let decoded_bytes = Arc::new(AtomicU64::new(0));
 
     let worker2 = Arc::clone(&verify_worker.worker);
     let datastore2 = Arc::clone(&verify_worker.datastore);
     let corrupt_chunks2 = Arc::clone(&verify_worker.corrupt_chunks);
     let verified_chunks2 = Arc::clone(&verify_worker.verified_chunks);
+    let quarantined2 = Arc::clone(&verify_worker.quarantined_chunks);
+    let broken2 = Arc::clone(&broken);
     let errors2 = Arc::clone(&errors);
 
     let decoder_pool = ParallelHandler::new(
         "verify chunk decoder",
-        4,
+        verify_worker.datastore.verify_threads(),
         move |(chunk, digest, size): (DataBlob, [u8; 32], u64)| {
             let chunk_crypt_mode = match chunk.crypt_mode() {
                 Err(err) => {
@@ -149,9 +253,10 @@ fn verify_index_chunks(
 
             if let Err(err) = chunk.verify_unencrypted(size as usize, &digest) {
                 corrupt_chunks2.lock().unwrap().insert(digest);
+                broken2.lock().unwrap().push(digest);
                 task_log!(worker2, "{}", err);
                 errors2.fetch_add(1, Ordering::SeqCst);
-                rename_corrupted_chunk(datastore2.clone(), &digest, &worker2);
+                quarantine_corrupt_chunk(&datastore2, &digest, &*worker2, &quarantined2);
             } else {
                 verified_chunks2.lock().unwrap().insert(digest);
             }
@@ -160,6 +265,43 @@ fn verify_index_chunks(
         },
     );
 
+    let worker2 = Arc::clone(&verify_worker.worker);
+    let datastore2 = Arc::clone(&verify_worker.datastore);
+    let corrupt_chunks2 = Arc::clone(&verify_worker.corrupt_chunks);
+    let quarantined2 = Arc::clone(&verify_worker.quarantined_chunks);
+    let broken2 = Arc::clone(&broken);
+    let errors2 = Arc::clone(&errors);
+    let read_bytes2 = Arc::clone(&read_bytes);
+    let decoded_bytes2 = Arc::clone(&decoded_bytes);
+    let decoder_channel = decoder_pool.channel();
+
+    // loading chunks with multiple threads keeps several reads in flight, which helps with
+    // storage that only reaches its full throughput with concurrent requests
+    let reader_pool = ParallelHandler::new(
+        "verify chunk reader",
+        verify_worker.datastore.verify_read_threads(),
+        move |(digest, size): ([u8; 32], u64)| {
+            match datastore2.load_chunk(&digest) {
+                Err(err) => {
+                    corrupt_chunks2.lock().unwrap().insert(digest);
+                    broken2.lock().unwrap().push(digest);
+                    task_log!(worker2, "can't verify chunk, load failed - {}", err);
+                    errors2.fetch_add(1, Ordering::SeqCst);
+                    // with an object storage, the request may just have failed
+                    if datastore2.backend_type() == DatastoreBackendType::Filesystem {
+                        quarantine_corrupt_chunk(&datastore2, &digest, &*worker2, &quarantined2);
+                    }
+                }
+                Ok(chunk) => {
+                    read_bytes2.fetch_add(chunk.raw_size(), Ordering::SeqCst);
+                    decoder_channel.send((chunk, digest, size))?;
+                    decoded_bytes2.fetch_add(size, Ordering::SeqCst);
+                }
+            }
+            Ok(())
+        },
+    );
+
     let skip_chunk = |digest: &[u8; 32]| -> bool {
         if verify_worker
             .verified_chunks
@@ -180,6 +322,7 @@ fn verify_index_chunks(
                 "chunk {} was marked as corrupt",
                 digest_str
             );
+            broken.lock().unwrap().push(*digest);
             errors.fetch_add(1, Ordering::SeqCst);
             true
         } else {
@@ -211,40 +354,34 @@ fn verify_index_chunks(
             continue; // already verified or marked corrupt
         }
 
-        match verify_worker.datastore.load_chunk(&info.digest) {
-            Err(err) => {
-                verify_worker
-                    .corrupt_chunks
-                    .lock()
-                    .unwrap()
-                    .insert(info.digest);
-                task_log!(
-                    verify_worker.worker,
-                    "can't verify chunk, load failed - {}",
-                    err
-                );
-                errors.fetch_add(1, Ordering::SeqCst);
-                rename_corrupted_chunk(
-                    verify_worker.datastore.clone(),
-                    &info.digest,
-                    &verify_worker.worker,
-                );
-            }
-            Ok(chunk) => {
-                let size = info.size();
-                read_bytes += chunk.raw_size();
-                decoder_pool.send((chunk, info.digest, size))?;
-                decoded_bytes += size;
-            }
-        }
+        reader_pool.send((info.digest, info.size()))?;
     }
 
+    reader_pool.complete()?;
     decoder_pool.complete()?;
 
+    let broken = std::mem::take(&mut *broken.lock().unwrap());
+    if !broken.is_empty() {
+        let mut digests: HashSet<[u8; 32]> = broken.iter().copied().collect();
+        let chunks: Vec<([u8; 32], u64)> = (0..index.index_count())
+            .filter_map(|pos| index.chunk_info(pos))
+            .filter(|info| digests.remove(&info.digest))
+            .map(|info| (info.digest, info.size()))
+            .collect();
+
+        let refetched = refetch_corrupt_chunks(verify_worker, backup_dir, file_name, &chunks);
+        // the downloaded chunks were verified before inserting them
+        let fixed = broken
+            .iter()
+            .filter(|digest| refetched.contains(digest))
+            .count();
+        errors.fetch_sub(fixed, Ordering::SeqCst);
+    }
+
     let elapsed = start_time.elapsed().as_secs_f64();
 
-    let read_bytes_mib = (read_bytes as f64) / (1024.0 * 1024.0);
-    let decoded_bytes_mib = (decoded_bytes as f64) / (1024.0 * 1024.0);
+    let read_bytes_mib = (read_bytes.load(Ordering::SeqCst) as f64) / (1024.0 * 1024.0);
+    let decoded_bytes_mib = (decoded_bytes.load(Ordering::SeqCst) as f64) / (1024.0 * 1024.0);
 
     let read_speed = read_bytes_mib / elapsed;
     let decode_speed = decoded_bytes_mib / elapsed;
@@ -288,7 +425,13 @@ fn verify_fixed_index(
         bail!("wrong index checksum");
     }
 
-    verify_index_chunks(verify_worker, Box::new(index), info.chunk_crypt_mode())
+    verify_index_chunks(
+        verify_worker,
+        backup_dir,
+        &info.filename,
+        Box::new(index),
+        info.chunk_crypt_mode(),
+    )
 }
 
 fn verify_dynamic_index(
@@ -310,7 +453,13 @@ fn verify_dynamic_index(
         bail!("wrong index checksum");
     }
 
-    verify_index_chunks(verify_worker, Box::new(index), info.chunk_crypt_mode())
+    verify_index_chunks(
+        verify_worker,
+        backup_dir,
+        &info.filename,
+        Box::new(index),
+        info.chunk_crypt_mode(),
+    )
 }
 
 /// Verify a single backup snapshot
@@ -390,6 +539,9 @@ pub fn verify_backup_dir_with_lock(
         verify_worker.datastore.name(),
         backup_dir.dir()
     );
+    verify_worker
+        .verified_snapshots
+        .fetch_add(1, Ordering::SeqCst);
 
     let mut error_count = 0;
 
@@ -1,12 +1,13 @@
 use std::future::Future;
 use std::pin::Pin;
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 use futures::*;
 use http::request::Parts;
 use http::HeaderMap;
 use http::Response;
 use hyper::{Body, Method, StatusCode};
+use serde_json::Value;
 
 use proxmox_lang::try_block;
 use proxmox_router::{RpcEnvironmentType, UserInformation};
@@ -131,6 +132,15 @@ async fn run() -> Result<(), Error> {
         &mut commando_sock,
     )?;
 
+    // the proxy's scheduler cannot read all replicated configuration files itself
+    commando_sock.register_command("standby-push".to_string(), |args| {
+        let schedule = args
+            .and_then(|args| args["schedule"].as_str())
+            .ok_or_else(|| format_err!("missing schedule"))?;
+        let upid = proxmox_backup::server::start_scheduled_standby_push(schedule.to_string())?;
+        Ok(Value::from(upid))
+    })?;
+
     let rest_server = RestServer::new(config);
     proxmox_rest_server::init_worker_tasks(
         pbs_buildcfg::PROXMOX_BACKUP_LOG_DIR_M!().into(),
@@ -5,7 +5,7 @@ use std::str::FromStr;
 use anyhow::{Error, format_err};
 use serde_json::{json, Value};
 
-use proxmox_router::{cli::*, RpcEnvironment};
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 use proxmox_sys::fs::CreateOptions;
 
@@ -13,10 +13,10 @@ use pbs_api_types::percent_encoding::percent_encode_component;
 use pbs_api_types::{
     BackupNamespace, GroupFilter, RateLimitConfig, SyncJobConfig, DATASTORE_SCHEMA,
     GROUP_FILTER_LIST_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA, NS_MAX_DEPTH_SCHEMA,
-    REMOTE_ID_SCHEMA, REMOVE_VANISHED_BACKUPS_SCHEMA, UPID_SCHEMA,
-    VERIFICATION_OUTDATED_AFTER_SCHEMA,
+    REMOTE_ID_SCHEMA, REMOVE_VANISHED_BACKUPS_SCHEMA, SYNC_PARALLEL_DOWNLOADS_SCHEMA,
+    SYNC_VERIFY_SCHEMA, UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
 };
-use pbs_client::{display_task_log, view_task_result};
+use pbs_client::{display_task_log_full, view_task_result};
 use pbs_config::sync;
 use pbs_tools::json::required_string_param;
 
@@ -93,8 +93,41 @@ async fn garbage_collection_status(param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
+#[api(
+   input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// List the garbage collection jobs of all datastores.
+fn list_gc_jobs(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::admin::gc::API_METHOD_LIST_GC_JOBS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("store"))
+        .column(ColumnConfig::new("schedule"))
+        .column(ColumnConfig::new("last-run-state"))
+        .column(ColumnConfig::new("last-run-endtime").renderer(pbs_tools::format::render_epoch))
+        .column(ColumnConfig::new("next-run").renderer(pbs_tools::format::render_epoch));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
 fn garbage_collection_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_GC_JOBS))
         .insert(
             "status",
             CliCommand::new(&API_METHOD_GARBAGE_COLLECTION_STATUS)
@@ -180,16 +213,23 @@ async fn task_list(param: Value) -> Result<Value, Error> {
             upid: {
                 schema: UPID_SCHEMA,
             },
+            "no-follow": {
+                description: "Do not wait for new lines of a running task.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
         }
     }
 )]
 /// Display the task log.
 async fn task_log(param: Value) -> Result<Value, Error> {
     let upid = required_string_param(&param, "upid")?;
+    let follow = !param["no-follow"].as_bool().unwrap_or(false);
 
     let client = connect_to_localhost()?;
 
-    display_task_log(&client, upid, true).await?;
+    display_task_log_full(&client, upid, true, follow).await?;
 
     Ok(Value::Null)
 }
@@ -268,6 +308,14 @@ fn task_mgmt_cli() -> CommandLineInterface {
                 type: RateLimitConfig,
                 flatten: true,
             },
+            "parallel-downloads": {
+                schema: SYNC_PARALLEL_DOWNLOADS_SCHEMA,
+                optional: true,
+            },
+            "verify-after-sync": {
+                schema: SYNC_VERIFY_SCHEMA,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -287,6 +335,8 @@ async fn pull_datastore(
     max_depth: Option<usize>,
     group_filter: Option<Vec<GroupFilter>>,
     limit: RateLimitConfig,
+    parallel_downloads: Option<usize>,
+    verify_after_sync: Option<bool>,
     param: Value,
 ) -> Result<Value, Error> {
     let output_format = get_output_format(&param);
@@ -315,6 +365,14 @@ async fn pull_datastore(
         args["group-filter"] = json!(group_filter);
     }
 
+    if let Some(parallel_downloads) = parallel_downloads {
+        args["parallel-downloads"] = json!(parallel_downloads);
+    }
+
+    if let Some(verify_after_sync) = verify_after_sync {
+        args["verify-after-sync"] = Value::from(verify_after_sync);
+    }
+
     if let Some(remove_vanished) = remove_vanished {
         args["remove-vanished"] = Value::from(remove_vanished);
     }
@@ -336,6 +394,108 @@ async fn pull_datastore(
     Ok(Value::Null)
 }
 
+// fixme: avoid API redefinition
+#[api(
+   input: {
+        properties: {
+            "store": {
+                schema: DATASTORE_SCHEMA,
+            },
+            "ns": {
+                type: BackupNamespace,
+                optional: true,
+            },
+            remote: {
+                schema: REMOTE_ID_SCHEMA,
+            },
+            "remote-store": {
+                schema: DATASTORE_SCHEMA,
+            },
+            "remote-ns": {
+                type: BackupNamespace,
+                optional: true,
+            },
+            "remove-vanished": {
+                schema: REMOVE_VANISHED_BACKUPS_SCHEMA,
+                optional: true,
+            },
+            "max-depth": {
+                schema: NS_MAX_DEPTH_SCHEMA,
+                optional: true,
+            },
+            "group-filter": {
+                schema: GROUP_FILTER_LIST_SCHEMA,
+                optional: true,
+            },
+            limit: {
+                type: RateLimitConfig,
+                flatten: true,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// Sync datastore to another repository
+#[allow(clippy::too_many_arguments)]
+async fn push_datastore(
+    store: String,
+    ns: Option<BackupNamespace>,
+    remote: String,
+    remote_store: String,
+    remote_ns: Option<BackupNamespace>,
+    remove_vanished: Option<bool>,
+    max_depth: Option<usize>,
+    group_filter: Option<Vec<GroupFilter>>,
+    limit: RateLimitConfig,
+    param: Value,
+) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let client = connect_to_localhost()?;
+
+    let mut args = json!({
+        "store": store,
+        "remote": remote,
+        "remote-store": remote_store,
+    });
+
+    if remote_ns.is_some() {
+        args["remote-ns"] = json!(remote_ns);
+    }
+
+    if ns.is_some() {
+        args["ns"] = json!(ns);
+    }
+
+    if max_depth.is_some() {
+        args["max-depth"] = json!(max_depth);
+    }
+
+    if group_filter.is_some() {
+        args["group-filter"] = json!(group_filter);
+    }
+
+    if let Some(remove_vanished) = remove_vanished {
+        args["remove-vanished"] = Value::from(remove_vanished);
+    }
+
+    let mut limit_json = json!(limit);
+    let limit_map = limit_json
+        .as_object_mut()
+        .ok_or_else(|| format_err!("limit is not an Object"))?;
+
+    args.as_object_mut().unwrap().append(limit_map);
+
+    let result = client.post("api2/json/push", Some(args)).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
 #[api(
    input: {
         properties: {
@@ -427,6 +587,7 @@ async fn run() -> Result<(), Error> {
 
     let cmd_def = CliCommandMap::new()
         .insert("acl", acl_commands())
+        .insert("alert", alert_commands())
         .insert("datastore", datastore_commands())
         .insert("disk", disk_commands())
         .insert("dns", dns_commands())
@@ -435,6 +596,7 @@ async fn run() -> Result<(), Error> {
         .insert("user", user_commands())
         .insert("openid", openid_commands())
         .insert("remote", remote_commands())
+        .insert("standby", standby_commands())
         .insert("traffic-control", traffic_control_commands())
         .insert("garbage-collection", garbage_collection_commands())
         .insert("acme", acme_mgmt_cli())
@@ -455,6 +617,16 @@ async fn run() -> Result<(), Error> {
                 .completion_cb("group-filter", complete_remote_datastore_group_filter)
                 .completion_cb("remote-ns", complete_remote_datastore_namespace),
         )
+        .insert(
+            "push",
+            CliCommand::new(&API_METHOD_PUSH_DATASTORE)
+                .arg_param(&["store", "remote", "remote-store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name)
+                .completion_cb("ns", complete_sync_local_datastore_namespace)
+                .completion_cb("remote", pbs_config::remote::complete_remote_name)
+                .completion_cb("remote-store", complete_remote_datastore_name)
+                .completion_cb("remote-ns", complete_remote_datastore_namespace),
+        )
         .insert(
             "verify",
             CliCommand::new(&API_METHOD_VERIFY)
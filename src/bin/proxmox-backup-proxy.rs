@@ -53,7 +53,7 @@ use pbs_buildcfg::configdir;
 use proxmox_time::CalendarEvent;
 
 use pbs_api_types::{
-    Authid, DataStoreConfig, Operation, PruneJobConfig, SyncJobConfig, TapeBackupJobConfig,
+    Authid, DataStoreConfig, Operation, PruneJobConfig, SyncJobConfig, TapeBackupJobConfig, Userid,
     VerificationJobConfig,
 };
 
@@ -255,6 +255,14 @@ async fn run() -> Result<(), Error> {
         .owner(backup_user.uid)
         .group(backup_user.gid);
 
+    // only documentation, not worth failing the start of the proxy for
+    if let Err(err) =
+        proxmox_backup::tools::apidoc::create_api_viewer(dir_opts.clone(), file_opts.clone())
+    {
+        log::error!("could not create the API viewer: {err}");
+    }
+    config.add_alias("api-viewer", proxmox_backup::tools::apidoc::API_VIEWER_DIR);
+
     config.enable_access_log(
         pbs_buildcfg::API_ACCESS_LOG_FN,
         Some(dir_opts.clone()),
@@ -553,10 +561,157 @@ async fn schedule_tasks() -> Result<(), Error> {
     schedule_datastore_verify_jobs().await;
     schedule_tape_backup_jobs().await;
     schedule_task_log_rotate().await;
+    schedule_digest();
+    schedule_standby_push();
+    schedule_alert_checks();
+    schedule_zpool_health_check();
 
     Ok(())
 }
 
+fn schedule_digest() {
+    let digest = match proxmox_backup::config::node::config() {
+        Ok((config, _digest)) => config.digest_config(),
+        Err(err) => {
+            eprintln!("unable to read node config - {err}");
+            return;
+        }
+    };
+
+    let digest = match digest {
+        Some(Ok(digest)) => digest,
+        Some(Err(err)) => {
+            eprintln!("unable to parse digest config - {err}");
+            return;
+        }
+        None => return,
+    };
+
+    let worker_type = "digest";
+    let job_id = "digest";
+
+    if !check_schedule(worker_type, &digest.schedule, job_id) {
+        return;
+    }
+
+    // on the first run, include the failed tasks of the last day
+    let since = match jobstate::JobState::load(worker_type, job_id) {
+        Ok(jobstate::JobState::Created { .. }) => proxmox_time::epoch_i64() - 24 * 3600,
+        _ => match jobstate::last_run_time(worker_type, job_id) {
+            Ok(time) => time,
+            Err(err) => {
+                eprintln!("could not get last run time of digest job - {err}");
+                return;
+            }
+        },
+    };
+
+    let job = match Job::new(worker_type, job_id) {
+        Ok(job) => job,
+        Err(_) => return, // could not get lock
+    };
+
+    let notify_user = digest
+        .notify_user
+        .unwrap_or_else(|| Userid::root_userid().clone());
+
+    if let Err(err) = server::do_digest_job(job, since, notify_user, Some(digest.schedule), false) {
+        eprintln!("unable to start digest job - {err}");
+    }
+}
+
+fn schedule_standby_push() {
+    let standby = match proxmox_backup::config::node::config() {
+        Ok((config, _digest)) => config.standby_config(),
+        Err(err) => {
+            eprintln!("unable to read node config - {err}");
+            return;
+        }
+    };
+
+    let standby = match standby {
+        Some(Ok(standby)) => standby,
+        Some(Err(err)) => {
+            eprintln!("unable to parse standby config - {err}");
+            return;
+        }
+        None => return,
+    };
+
+    let event_str = match standby.schedule {
+        Some(ref event_str) => event_str.clone(),
+        None => return,
+    };
+
+    let worker_type = "standby-push";
+    let job_id = "standby";
+
+    if !check_schedule(worker_type, &event_str, job_id) {
+        return;
+    }
+
+    // reading the password hashes and second factors needs root
+    tokio::spawn(async move {
+        if let Err(err) = server::request_scheduled_standby_push(&event_str).await {
+            eprintln!("unable to start standby push job - {err}");
+        }
+    });
+}
+
+fn schedule_zpool_health_check() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+
+    // skip this round if the previous check is still running
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let guard = CheckRunningGuard(&RUNNING);
+    tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        if let Err(err) = server::check_zpool_health() {
+            eprintln!("checking zpool health failed - {err}");
+        }
+    });
+}
+
+// check alert rules every 5 minutes
+const ALERT_CHECK_INTERVAL: i64 = 5 * 60;
+
+// clears the flag marking a periodic check as running once dropped, even if the check panicked
+struct CheckRunningGuard(&'static std::sync::atomic::AtomicBool);
+
+impl Drop for CheckRunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+fn schedule_alert_checks() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+
+    if proxmox_time::epoch_i64() / 60 % (ALERT_CHECK_INTERVAL / 60) != 0 {
+        return;
+    }
+
+    // skip this round if the previous check is still running
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let guard = CheckRunningGuard(&RUNNING);
+    tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        if let Err(err) = server::check_alert_rules() {
+            eprintln!("checking alert rules failed - {err}");
+        }
+    });
+}
+
 async fn schedule_datastore_garbage_collection() {
     let config = match pbs_config::datastore::config() {
         Err(err) => {
@@ -691,6 +846,7 @@ async fn schedule_datastore_prune_jobs() {
                 job,
                 job_config.options,
                 job_config.store,
+                job_config.notify,
                 &auth_id,
                 Some(job_config.schedule),
             ) {
@@ -1128,6 +1284,12 @@ impl DiskStat {
             value["write_ios"] = Value::from(dev.write_ios);
             value["write_bytes"] = Value::from(dev.write_sectors * 512);
             value["io_ticks"] = Value::from(dev.io_ticks / 1000);
+            if let Some(read_ticks) = dev.read_ticks {
+                value["read_ticks"] = Value::from(read_ticks);
+            }
+            if let Some(write_ticks) = dev.write_ticks {
+                value["write_ticks"] = Value::from(write_ticks);
+            }
         }
         value
     }
@@ -1247,6 +1409,17 @@ fn rrd_update_host_stats_sync(host: &HostStats, hostdisk: &DiskStat, datastores:
         let rrd_prefix = format!("datastore/{}", stat.name);
         rrd_update_disk_stat(stat, &rrd_prefix);
     }
+
+    let usage: Vec<(String, u64, u64)> = datastores
+        .iter()
+        .filter_map(|stat| {
+            let usage = stat.usage.as_ref()?;
+            Some((stat.name.clone(), usage.used, usage.total))
+        })
+        .collect();
+    if let Err(err) = server::check_datastore_full_traps(&usage) {
+        eprintln!("sending datastore full SNMP traps failed - {err}");
+    }
 }
 
 fn rrd_update_disk_stat(disk: &DiskStat, rrd_prefix: &str) {
@@ -1270,6 +1443,16 @@ fn rrd_update_disk_stat(disk: &DiskStat, rrd_prefix: &str) {
 
         let rrd_key = format!("{}/io_ticks", rrd_prefix);
         rrd_update_derive(&rrd_key, (stat.io_ticks as f64) / 1000.0);
+
+        // milliseconds spent per second, divided by the IOPS this gives the average latency
+        if let Some(read_ticks) = stat.read_ticks {
+            let rrd_key = format!("{}/read_ticks", rrd_prefix);
+            rrd_update_derive(&rrd_key, read_ticks as f64);
+        }
+        if let Some(write_ticks) = stat.write_ticks {
+            let rrd_key = format!("{}/write_ticks", rrd_prefix);
+            rrd_update_derive(&rrd_key, write_ticks as f64);
+        }
     }
 }
 
@@ -62,6 +62,14 @@ async fn do_update(rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
         }
     }
 
+    if let Err(err) = proxmox_backup::server::check_certificate_expiry() {
+        log::error!("error checking certificate expiry: {}", err);
+    }
+
+    if let Err(err) = proxmox_backup::server::check_user_expiry() {
+        log::error!("error checking user expiry: {}", err);
+    }
+
     // TODO: cleanup tasks like in PVE?
 
     Ok(())
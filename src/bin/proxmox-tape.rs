@@ -754,11 +754,14 @@ async fn status(mut param: Value) -> Result<(), Error> {
     };
 
     let options = default_table_format_options()
+        .column(ColumnConfig::new("label-text"))
+        .column(ColumnConfig::new("pool"))
         .column(ColumnConfig::new("blocksize"))
         .column(ColumnConfig::new("density"))
         .column(ColumnConfig::new("compression"))
         .column(ColumnConfig::new("buffer-mode"))
         .column(ColumnConfig::new("write-protect"))
+        .column(ColumnConfig::new("encryption"))
         .column(ColumnConfig::new("alert-flags"))
         .column(ColumnConfig::new("file-number"))
         .column(ColumnConfig::new("block-number"))
@@ -1,9 +1,15 @@
-use anyhow::{bail, Error};
+use std::collections::BTreeSet;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
+use pbs_api_types::{AclListItem, Authid};
+use pbs_config::acl::{AclTree, AclTreeNode, ROLE_NAMES};
+
 use proxmox_backup::api2;
 
 #[api(
@@ -53,9 +59,298 @@ fn list_acls(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Err
     Ok(Value::Null)
 }
 
+#[api()]
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// File format for exporting and importing ACLs.
+enum AclFileFormat {
+    /// List of ACL entries, as returned by 'acl list'.
+    Json,
+    /// One ACL entry per line, with the columns path, ugid_type, ugid, roleid and propagate.
+    Csv,
+}
+
+const CSV_HEADER: &str = "path,ugid_type,ugid,roleid,propagate";
+
+// ACL entries of `node` and all its children, sorted by path
+fn collect_acl_entries(node: &AclTreeNode, path: &str, list: &mut Vec<AclListItem>) {
+    let acl_path = if path.is_empty() { "/" } else { path };
+
+    let mut entries = Vec::new();
+    for (auth_id, roles) in &node.users {
+        for (role, propagate) in roles {
+            entries.push(("user", auth_id.to_string(), role, *propagate));
+        }
+    }
+    for (group, roles) in &node.groups {
+        for (role, propagate) in roles {
+            entries.push(("group", group.to_string(), role, *propagate));
+        }
+    }
+    entries.sort();
+
+    for (ugid_type, ugid, role, propagate) in entries {
+        list.push(AclListItem {
+            path: acl_path.to_string(),
+            ugid,
+            ugid_type: ugid_type.to_string(),
+            propagate,
+            roleid: role.to_string(),
+        });
+    }
+
+    for (comp, child) in &node.children {
+        collect_acl_entries(child, &format!("{}/{}", path, comp), list);
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(|c: char| c == ',' || c == '"') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_acl_csv_line(entry: &AclListItem) -> String {
+    format!(
+        "{},{},{},{},{}",
+        csv_field(&entry.path),
+        entry.ugid_type,
+        csv_field(&entry.ugid),
+        entry.roleid,
+        u8::from(entry.propagate),
+    )
+}
+
+fn split_csv_line(line: &str) -> Result<Vec<String>, Error> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    fields.push(field);
+
+    Ok(fields)
+}
+
+fn parse_acl_csv(raw: &str) -> Result<Vec<AclListItem>, Error> {
+    let mut list = Vec::new();
+
+    for (linenr, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line == CSV_HEADER {
+            continue;
+        }
+
+        let fields =
+            split_csv_line(line).map_err(|err| format_err!("line {} - {}", linenr + 1, err))?;
+        if fields.len() != 5 {
+            bail!(
+                "line {} - expected 5 columns, got {}",
+                linenr + 1,
+                fields.len()
+            );
+        }
+
+        let propagate = match fields[4].as_str() {
+            "1" | "true" => true,
+            "0" | "false" => false,
+            other => bail!("line {} - invalid propagate flag '{}'", linenr + 1, other),
+        };
+
+        list.push(AclListItem {
+            path: fields[0].clone(),
+            ugid_type: fields[1].clone(),
+            ugid: fields[2].clone(),
+            roleid: fields[3].clone(),
+            propagate,
+        });
+    }
+
+    Ok(list)
+}
+
+fn acl_entry_key(entry: &AclListItem) -> (String, String, String, String, bool) {
+    (
+        entry.path.clone(),
+        entry.ugid_type.clone(),
+        entry.ugid.clone(),
+        entry.roleid.clone(),
+        entry.propagate,
+    )
+}
+
+#[api(
+    input: {
+        properties: {
+            format: {
+                type: AclFileFormat,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Export all ACL entries (default format: json).
+fn export_acls(format: Option<AclFileFormat>) -> Result<Value, Error> {
+    let (tree, _digest) = pbs_config::acl::config()?;
+
+    let mut list = Vec::new();
+    collect_acl_entries(&tree.root, "", &mut list);
+
+    match format.unwrap_or(AclFileFormat::Json) {
+        AclFileFormat::Json => println!("{}", serde_json::to_string_pretty(&list)?),
+        AclFileFormat::Csv => {
+            println!("{}", CSV_HEADER);
+            for entry in list {
+                println!("{}", format_acl_csv_line(&entry));
+            }
+        }
+    }
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            file: {
+                description: "File with exported ACL entries.",
+                type: String,
+            },
+            format: {
+                type: AclFileFormat,
+                optional: true,
+            },
+            "dry-run": {
+                description: "Only show the changes, without saving them.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+        }
+    }
+)]
+/// Replace all ACL entries with the ones from an export file.
+///
+/// All entries are checked before anything is changed, so the ACL is either replaced completely
+/// or not at all. The default format is derived from the file extension.
+fn import_acls(file: String, format: Option<AclFileFormat>, dry_run: bool) -> Result<Value, Error> {
+    let raw = std::fs::read_to_string(&file)
+        .map_err(|err| format_err!("unable to read {:?} - {}", file, err))?;
+
+    let format = match format {
+        Some(format) => format,
+        None if file.ends_with(".csv") => AclFileFormat::Csv,
+        None => AclFileFormat::Json,
+    };
+
+    let entries: Vec<AclListItem> = match format {
+        AclFileFormat::Json => serde_json::from_str(&raw)?,
+        AclFileFormat::Csv => parse_acl_csv(&raw)?,
+    };
+
+    let (user_cfg, _digest) = pbs_config::user::config()?;
+
+    let mut new_tree = AclTree::new();
+    for entry in &entries {
+        pbs_config::acl::check_acl_path(&entry.path)?;
+        if let Err(err) = pbs_config::acl::check_acl_path_objects(&entry.path) {
+            eprintln!("WARNING: {}", err);
+        }
+
+        if !ROLE_NAMES.contains_key(entry.roleid.as_str()) {
+            bail!("unknown role '{}'", entry.roleid);
+        }
+
+        match entry.ugid_type.as_str() {
+            "user" => {
+                let auth_id: Authid = entry.ugid.parse()?;
+                if user_cfg.sections.get(&entry.ugid).is_none() {
+                    bail!("no such user or API token '{}'", auth_id);
+                }
+                new_tree.insert_user_role(&entry.path, &auth_id, &entry.roleid, entry.propagate);
+            }
+            "group" => bail!(
+                "unable to import ACL of group '{}' - groups are currently not supported",
+                entry.ugid
+            ),
+            other => bail!("unknown ugid_type '{}'", other),
+        }
+    }
+
+    let _lock = pbs_config::acl::lock_config()?;
+
+    let (old_tree, _digest) = pbs_config::acl::config()?;
+
+    let mut old_list = Vec::new();
+    collect_acl_entries(&old_tree.root, "", &mut old_list);
+    let mut new_list = Vec::new();
+    collect_acl_entries(&new_tree.root, "", &mut new_list);
+
+    let old_set: BTreeSet<_> = old_list.iter().map(acl_entry_key).collect();
+    let new_set: BTreeSet<_> = new_list.iter().map(acl_entry_key).collect();
+
+    let removed: Vec<_> = old_set.difference(&new_set).collect();
+    let added: Vec<_> = new_set.difference(&old_set).collect();
+
+    if removed.is_empty() && added.is_empty() {
+        println!("no changes");
+        return Ok(Value::Null);
+    }
+
+    for (prefix, list) in [("-", removed), ("+", added)] {
+        for (path, ugid_type, ugid, roleid, propagate) in list {
+            println!(
+                "{} {} {} {} {}{}",
+                prefix,
+                path,
+                ugid_type,
+                ugid,
+                roleid,
+                if *propagate { " (propagate)" } else { "" },
+            );
+        }
+    }
+
+    if dry_run {
+        println!("dry run - no changes saved");
+        return Ok(Value::Null);
+    }
+
+    pbs_config::acl::save_config(&new_tree)?;
+
+    Ok(Value::Null)
+}
+
 pub fn acl_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_ACLS))
+        .insert("export", CliCommand::new(&API_METHOD_EXPORT_ACLS))
+        .insert(
+            "import",
+            CliCommand::new(&API_METHOD_IMPORT_ACLS)
+                .arg_param(&["file"])
+                .completion_cb("file", complete_file_name),
+        )
         .insert(
             "update",
             CliCommand::new(&api2::access::acl::API_METHOD_UPDATE_ACL)
@@ -66,3 +361,112 @@ pub fn acl_commands() -> CommandLineInterface {
 
     cmd_def.into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        path: &str,
+        ugid_type: &str,
+        ugid: &str,
+        roleid: &str,
+        propagate: bool,
+    ) -> AclListItem {
+        AclListItem {
+            path: path.to_string(),
+            ugid_type: ugid_type.to_string(),
+            ugid: ugid.to_string(),
+            roleid: roleid.to_string(),
+            propagate,
+        }
+    }
+
+    #[test]
+    fn test_split_csv_line() -> Result<(), Error> {
+        assert_eq!(split_csv_line("a,b,,c")?, ["a", "b", "", "c"]);
+        assert_eq!(split_csv_line("")?, [""]);
+        assert_eq!(split_csv_line("\"a,b\",c")?, ["a,b", "c"]);
+        assert_eq!(split_csv_line("\"say \"\"hi\"\"\",x")?, ["say \"hi\"", "x"]);
+        assert_eq!(split_csv_line("\"\",x")?, ["", "x"]);
+        // quotes inside an unquoted field are kept as they are
+        assert_eq!(split_csv_line("a\"b,c")?, ["a\"b", "c"]);
+
+        assert!(split_csv_line("\"unterminated,x").is_err());
+        assert!(split_csv_line("a,\"b\"\"").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_acl_csv() -> Result<(), Error> {
+        let raw = format!(
+            "{}\n/datastore/store1,user,user@pbs,DatastoreAdmin,1\n\n  /,group,admins,Admin,false  \n/remote,user,user@pbs!sync,RemoteSyncOperator,0\n",
+            CSV_HEADER
+        );
+        let list = parse_acl_csv(&raw)?;
+        assert_eq!(list.len(), 3);
+        assert_eq!(
+            acl_entry_key(&list[0]),
+            acl_entry_key(&entry(
+                "/datastore/store1",
+                "user",
+                "user@pbs",
+                "DatastoreAdmin",
+                true
+            ))
+        );
+        assert_eq!(
+            acl_entry_key(&list[1]),
+            acl_entry_key(&entry("/", "group", "admins", "Admin", false))
+        );
+        assert_eq!(
+            acl_entry_key(&list[2]),
+            acl_entry_key(&entry(
+                "/remote",
+                "user",
+                "user@pbs!sync",
+                "RemoteSyncOperator",
+                false
+            ))
+        );
+
+        assert!(parse_acl_csv("/,user,user@pbs,Admin").is_err());
+        assert!(parse_acl_csv("/,user,user@pbs,Admin,1,extra").is_err());
+        assert!(parse_acl_csv("/,user,user@pbs,Admin,yes").is_err());
+        assert!(parse_acl_csv("\"/,user,user@pbs,Admin,1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_csv_roundtrip() -> Result<(), Error> {
+        let list = vec![
+            entry("/", "user", "root@pam", "Admin", true),
+            // API tokens are listed as users, with the token name in the auth id
+            entry(
+                "/datastore/store1",
+                "user",
+                "user@pbs!backup",
+                "DatastoreBackup",
+                false,
+            ),
+            entry("/remote/a,b", "user", "we\"ird@pbs", "RemoteAudit", true),
+            entry("/system/\"quoted\"", "group", "a,\"b\",c", "Audit", false),
+        ];
+
+        let mut raw = format!("{}\n", CSV_HEADER);
+        for item in &list {
+            raw.push_str(&format_acl_csv_line(item));
+            raw.push('\n');
+        }
+
+        let parsed = parse_acl_csv(&raw)?;
+        assert_eq!(
+            parsed.iter().map(acl_entry_key).collect::<Vec<_>>(),
+            list.iter().map(acl_entry_key).collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+}
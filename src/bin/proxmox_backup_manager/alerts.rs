@@ -0,0 +1,152 @@
+use anyhow::Error;
+use serde_json::{json, Value};
+
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
+use proxmox_schema::api;
+
+use pbs_api_types::{AlertRule, ALERT_RULE_ID_SCHEMA};
+
+use proxmox_backup::api2;
+use proxmox_backup::server::check_alert_rule;
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// List alert rules.
+fn list_alert_rules(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::alerts::API_METHOD_LIST_ALERT_RULES;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("id"))
+        .column(ColumnConfig::new("type"))
+        .column(ColumnConfig::new("threshold"))
+        .column(ColumnConfig::new("store"))
+        .column(ColumnConfig::new("disable"))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: ALERT_RULE_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show alert rule configuration.
+fn show_alert_rule(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::alerts::API_METHOD_READ_ALERT_RULE;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options();
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: ALERT_RULE_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Check an alert rule now and print all currently matching alerts, without sending
+/// notifications.
+fn check_alert(id: String, param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let (config, _digest) = pbs_config::alerts::config()?;
+    let rule: AlertRule = config.lookup("rule", &id)?;
+
+    let alerts = check_alert_rule(&rule)?;
+
+    if output_format != "text" {
+        let list: Vec<Value> = alerts
+            .into_iter()
+            .map(|alert| json!({ "subject": alert.subject, "message": alert.message }))
+            .collect();
+        format_and_print_result(&Value::from(list), &output_format);
+        return Ok(Value::Null);
+    }
+
+    if alerts.is_empty() {
+        println!("no alerts");
+    }
+    for alert in alerts {
+        println!("{}", alert.message);
+    }
+
+    Ok(Value::Null)
+}
+
+pub fn alert_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_ALERT_RULES))
+        .insert(
+            "show",
+            CliCommand::new(&API_METHOD_SHOW_ALERT_RULE)
+                .arg_param(&["id"])
+                .completion_cb("id", pbs_config::alerts::complete_alert_rule_id),
+        )
+        .insert(
+            "check",
+            CliCommand::new(&API_METHOD_CHECK_ALERT)
+                .arg_param(&["id"])
+                .completion_cb("id", pbs_config::alerts::complete_alert_rule_id),
+        )
+        .insert(
+            "create",
+            CliCommand::new(&api2::config::alerts::API_METHOD_CREATE_ALERT_RULE)
+                .arg_param(&["id"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "update",
+            CliCommand::new(&api2::config::alerts::API_METHOD_UPDATE_ALERT_RULE)
+                .arg_param(&["id"])
+                .completion_cb("id", pbs_config::alerts::complete_alert_rule_id)
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(&api2::config::alerts::API_METHOD_DELETE_ALERT_RULE)
+                .arg_param(&["id"])
+                .completion_cb("id", pbs_config::alerts::complete_alert_rule_id),
+        );
+
+    cmd_def.into()
+}
@@ -1,14 +1,33 @@
 use anyhow::{bail, Error};
+use serde_json::Value;
 
 use proxmox_router::cli::*;
 use proxmox_schema::api;
 
+use proxmox_backup::api2;
 use proxmox_backup::auth_helpers::*;
 use proxmox_backup::config;
 
-#[api]
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
 /// Display node certificate information.
-fn cert_info() -> Result<(), Error> {
+fn cert_info(param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+
+    if output_format != "text" {
+        let info = api2::node::certificates::get_info()?;
+        format_and_print_result(&serde_json::to_value(info)?, &output_format);
+        return Ok(());
+    }
+
     let cert = proxmox_backup::cert_info()?;
 
     println!("Subject: {}", cert.subject_name()?);
@@ -4,8 +4,11 @@ use serde_json::Value;
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::{DataStoreConfig, DATASTORE_SCHEMA};
+use pbs_api_types::{
+    DataStoreConfig, ZfsDatasetProperties, DATASTORE_SCHEMA, JOB_ID_SCHEMA, ZFS_DATASET_SCHEMA,
+};
 use pbs_client::view_task_result;
+use pbs_tools::json::required_string_param;
 
 use proxmox_backup::api2;
 use proxmox_backup::client_helpers::connect_to_localhost;
@@ -69,6 +72,35 @@ fn show_datastore(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show the properties of the ZFS dataset a datastore is located on.
+fn show_zfs_properties(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::datastore::API_METHOD_READ_DATASTORE_ZFS_PROPERTIES;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options();
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
 #[api(
     protected: true,
     input: {
@@ -77,6 +109,15 @@ fn show_datastore(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value
                 type: DataStoreConfig,
                 flatten: true,
             },
+            zfs: {
+                schema: ZFS_DATASET_SCHEMA,
+                optional: true,
+                description: "Create this ZFS dataset, mounted at the datastore path.",
+            },
+            "zfs-properties": {
+                type: ZfsDatasetProperties,
+                flatten: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -99,6 +140,149 @@ async fn create_datastore(mut param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "keep-job-configs": {
+                description: "If enabled, the job configurations related to this datastore will be kept.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "destroy-data": {
+                description: "Delete all data of the datastore, including its chunks, backup \
+                    snapshots and namespaces. Cannot be undone!",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Remove a datastore configuration and optionally all of its data.
+async fn delete_datastore(mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let name = required_string_param(&param, "name")?.to_string();
+    param.as_object_mut().unwrap().remove("name");
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/config/datastore/{}", name);
+    let result = client.delete(&path, Some(param)).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            repair: {
+                description: "Move broken chunks to the '.corrupt' directory and misplaced chunks to their directory.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "sync-job": {
+                schema: JOB_ID_SCHEMA,
+                optional: true,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Check the chunk store of a datastore for empty, misplaced and corrupt chunks, and optionally
+/// download broken chunks again from the remote of a sync job.
+async fn check_datastore(mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let name = required_string_param(&param, "name")?.to_string();
+    param.as_object_mut().unwrap().remove("name");
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/check-chunks", name);
+    let result = client.post(&path, Some(param)).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Rewrite the unencrypted chunks of a datastore with its configured compression.
+async fn recompress_chunks(mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let name = required_string_param(&param, "name")?.to_string();
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/recompress-chunks", name);
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Restore the snapshot files missing below the datastore path from the copies kept in its S3
+/// bucket.
+async fn restore_from_backend(mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let name = required_string_param(&param, "name")?.to_string();
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/restore-backend", name);
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
 pub fn datastore_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_DATASTORES))
@@ -126,9 +310,40 @@ pub fn datastore_commands() -> CommandLineInterface {
                     pbs_config::datastore::complete_calendar_event,
                 ),
         )
+        .insert(
+            "show-zfs-properties",
+            CliCommand::new(&API_METHOD_SHOW_ZFS_PROPERTIES)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "update-zfs-properties",
+            CliCommand::new(&api2::config::datastore::API_METHOD_UPDATE_DATASTORE_ZFS_PROPERTIES)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::datastore::complete_datastore_name),
+        )
         .insert(
             "remove",
-            CliCommand::new(&api2::config::datastore::API_METHOD_DELETE_DATASTORE)
+            CliCommand::new(&API_METHOD_DELETE_DATASTORE)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "check",
+            CliCommand::new(&API_METHOD_CHECK_DATASTORE)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::datastore::complete_datastore_name)
+                .completion_cb("sync-job", pbs_config::sync::complete_sync_job_id),
+        )
+        .insert(
+            "recompress",
+            CliCommand::new(&API_METHOD_RECOMPRESS_CHUNKS)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "restore-from-backend",
+            CliCommand::new(&API_METHOD_RESTORE_FROM_BACKEND)
                 .arg_param(&["name"])
                 .completion_cb("name", pbs_config::datastore::complete_datastore_name),
         );
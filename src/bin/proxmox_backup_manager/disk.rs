@@ -5,8 +5,8 @@ use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
 use pbs_api_types::{
-    ZfsCompressionType, ZfsRaidLevel, BLOCKDEVICE_NAME_SCHEMA, DATASTORE_SCHEMA, DISK_LIST_SCHEMA,
-    ZFS_ASHIFT_SCHEMA,
+    ZfsDatasetProperties, ZfsRaidLevel, BLOCKDEVICE_NAME_SCHEMA, DATASTORE_SCHEMA,
+    DISK_LIST_SCHEMA, ZFS_ASHIFT_SCHEMA, ZPOOL_NAME_SCHEMA,
 };
 use proxmox_backup::tools::disks::{complete_disk_name, FileSystemType, SmartAttribute};
 
@@ -153,9 +153,9 @@ async fn initialize_disk(
                 schema: ZFS_ASHIFT_SCHEMA,
                 optional: true,
             },
-            compression: {
-                type: ZfsCompressionType,
-                optional: true,
+            properties: {
+                type: ZfsDatasetProperties,
+                flatten: true,
             },
             "add-datastore": {
                 description: "Configure a datastore using the zpool.",
@@ -229,6 +229,92 @@ fn list_zpools(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Valu
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: ZPOOL_NAME_SCHEMA,
+            },
+            devices: {
+                schema: DISK_LIST_SCHEMA,
+            },
+        },
+    },
+)]
+/// Add unused disks as hot spares to a zfs pool.
+fn add_zpool_spares(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    param["node"] = "localhost".into();
+
+    let info = &api2::node::disks::zfs::API_METHOD_ADD_ZPOOL_SPARES;
+    match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: ZPOOL_NAME_SCHEMA,
+            },
+            device: {
+                description: "Path of the hot spare, as shown in the pool status.",
+                type: String,
+            },
+        },
+    },
+)]
+/// Remove an available hot spare from a zfs pool.
+fn remove_zpool_spare(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    param["node"] = "localhost".into();
+
+    let info = &api2::node::disks::zfs::API_METHOD_REMOVE_ZPOOL_SPARE;
+    match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: ZPOOL_NAME_SCHEMA,
+            },
+            device: {
+                description: "Path or GUID of the device to replace, as shown in the pool status.",
+                type: String,
+            },
+            "new-device": {
+                schema: BLOCKDEVICE_NAME_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Replace a device of a zfs pool and wait for the resilver to finish.
+async fn replace_zpool_device(
+    mut param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    param["node"] = "localhost".into();
+
+    let info = &api2::node::disks::zfs::API_METHOD_REPLACE_ZPOOL_DEVICE;
+    let result = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    crate::wait_for_local_worker(result.as_str().unwrap()).await?;
+
+    Ok(Value::Null)
+}
+
 pub fn zpool_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_ZPOOLS))
@@ -237,6 +323,22 @@ pub fn zpool_commands() -> CommandLineInterface {
             CliCommand::new(&API_METHOD_CREATE_ZPOOL)
                 .arg_param(&["name"])
                 .completion_cb("devices", complete_disk_name), // fixme: complete the list
+        )
+        .insert(
+            "add-spare",
+            CliCommand::new(&API_METHOD_ADD_ZPOOL_SPARES)
+                .arg_param(&["name"])
+                .completion_cb("devices", complete_disk_name),
+        )
+        .insert(
+            "remove-spare",
+            CliCommand::new(&API_METHOD_REMOVE_ZPOOL_SPARE).arg_param(&["name", "device"]),
+        )
+        .insert(
+            "replace",
+            CliCommand::new(&API_METHOD_REPLACE_ZPOOL_DEVICE)
+                .arg_param(&["name", "device"])
+                .completion_cb("new-device", complete_disk_name),
         );
 
     cmd_def.into()
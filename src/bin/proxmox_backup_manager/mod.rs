@@ -14,6 +14,8 @@ mod prune;
 pub use prune::*;
 mod remote;
 pub use remote::*;
+mod standby;
+pub use standby::*;
 mod sync;
 pub use sync::*;
 mod verify;
@@ -30,3 +32,5 @@ mod openid;
 pub use openid::*;
 mod traffic_control;
 pub use traffic_control::*;
+mod alerts;
+pub use alerts::*;
@@ -108,12 +108,23 @@ fn list_network_devices(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Re
     Ok(Value::Null)
 }
 
-#[api()]
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
 /// Show pending configuration changes (diff)
 fn pending_network_changes(
     mut param: Value,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
     param["node"] = "localhost".into();
 
     let info = &api2::node::network::API_METHOD_LIST_NETWORK_DEVICES;
@@ -122,8 +133,12 @@ fn pending_network_changes(
         _ => unreachable!(),
     };
 
-    if let Value::String(ref diff) = rpcenv["changes"] {
-        println!("{}", diff);
+    if output_format == "text" {
+        if let Value::String(ref diff) = rpcenv["changes"] {
+            println!("{}", diff);
+        }
+    } else {
+        format_and_print_result(&rpcenv["changes"], &output_format);
     }
 
     Ok(Value::Null)
@@ -223,6 +223,7 @@ pub(crate) fn update_to_prune_jobs_config() -> Result<(), Error> {
             comment: None,
             schedule,
             options,
+            notify: None,
         };
 
         let prune_config = serde_json::to_value(prune_config)?;
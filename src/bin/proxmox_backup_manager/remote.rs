@@ -4,7 +4,7 @@ use serde_json::Value;
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::REMOTE_ID_SCHEMA;
+use pbs_api_types::{RemoteTestResult, REMOTE_ID_SCHEMA};
 
 use proxmox_backup::api2;
 
@@ -69,6 +69,56 @@ fn show_remote(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, E
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: REMOTE_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Test the connection and credentials of a remote.
+async fn test_remote(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::remote::API_METHOD_TEST_REMOTE;
+    let data = match info.handler {
+        ApiHandler::Async(handler) => (handler)(param, info, rpcenv).await?,
+        _ => unreachable!(),
+    };
+
+    if output_format != "text" {
+        format_and_print_result(&data, &output_format);
+        return Ok(Value::Null);
+    }
+
+    let result: RemoteTestResult = serde_json::from_value(data)?;
+
+    println!(
+        "connected to proxmox-backup-server {}-{} ({})",
+        result.version, result.release, result.repoid
+    );
+    if let Some(fingerprint) = result.fingerprint {
+        println!("certificate fingerprint: {}", fingerprint);
+    }
+    if result.datastores.is_empty() {
+        println!("no datastores visible with the configured credentials");
+    }
+    for store in result.datastores {
+        match store.comment {
+            Some(comment) => println!("datastore: {} ({})", store.store, comment),
+            None => println!("datastore: {}", store.store),
+        }
+    }
+
+    Ok(Value::Null)
+}
+
 pub fn remote_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_REMOTES))
@@ -78,6 +128,12 @@ pub fn remote_commands() -> CommandLineInterface {
                 .arg_param(&["name"])
                 .completion_cb("name", pbs_config::remote::complete_remote_name),
         )
+        .insert(
+            "test",
+            CliCommand::new(&API_METHOD_TEST_REMOTE)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::remote::complete_remote_name),
+        )
         .insert(
             "create",
             // fixme: howto handle password parameter?
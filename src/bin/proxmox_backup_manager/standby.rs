@@ -0,0 +1,75 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
+use proxmox_schema::api;
+
+use pbs_client::view_task_result;
+
+use proxmox_backup::api2;
+use proxmox_backup::client_helpers::connect_to_localhost;
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show the configuration replication status.
+fn show_status(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::standby::API_METHOD_GET_STATUS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("standby"))
+        .column(ColumnConfig::new("source"))
+        .column(ColumnConfig::new("received").renderer(pbs_tools::format::render_epoch));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Replicate the configuration to the standby node now.
+async fn push_config(param: Value) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let client = connect_to_localhost()?;
+
+    let result = client.post("api2/json/config/standby/push", None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+pub fn standby_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert("status", CliCommand::new(&API_METHOD_SHOW_STATUS))
+        .insert("push", CliCommand::new(&API_METHOD_PUSH_CONFIG))
+        .insert(
+            "promote",
+            CliCommand::new(&api2::config::standby::API_METHOD_PROMOTE),
+        );
+
+    cmd_def.into()
+}
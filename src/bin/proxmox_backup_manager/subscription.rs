@@ -1,13 +1,10 @@
 use anyhow::{bail, Error};
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
-use proxmox_subscription::SubscriptionInfo;
 
-use proxmox_backup::api2::{self, node::subscription::subscription_file_opts};
-
-use pbs_buildcfg::PROXMOX_BACKUP_SUBSCRIPTION_FN;
+use proxmox_backup::api2;
 
 #[api(
     input: {
@@ -38,28 +35,42 @@ fn get(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
 #[api(
     input: {
         properties: {
-            "data": {
+            data: {
+                type: String,
+                description: "Base64 encoded, signed subscription info.",
+                optional: true,
+            },
+            file: {
                 type: String,
-                description: "base64-encoded signed subscription info"
+                description: "Read the signed subscription info from this file.",
+                optional: true,
             },
         }
     }
 )]
-/// (Internal use only!) Set a signed subscription info blob as offline key
-pub fn set_offline_subscription_key(data: String) -> Result<(), Error> {
-    let mut info: SubscriptionInfo = serde_json::from_slice(&base64::decode(data)?)?;
-    if !info.is_signed() {
-        bail!("Offline subscription key must be signed!");
-    }
-    info.check_signature(&[proxmox_subscription::files::DEFAULT_SIGNING_KEY]);
-    info.check_age(false);
-    info.check_server_id();
-    proxmox_subscription::files::write_subscription(
-        PROXMOX_BACKUP_SUBSCRIPTION_FN,
-        subscription_file_opts()?,
-        &info,
-    )?;
-    Ok(())
+/// Activate the subscription with a signed offline key, for servers without internet access.
+fn set_offline_key(
+    data: Option<String>,
+    file: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let data = match (data, file) {
+        (Some(data), None) => data,
+        (None, Some(file)) => proxmox_sys::fs::file_read_string(&file)?,
+        _ => bail!("please specify either the key data or a file to read it from"),
+    };
+
+    let param = json!({ "node": "localhost", "data": data });
+
+    let info = &api2::node::subscription::API_METHOD_SET_OFFLINE_SUBSCRIPTION_KEY;
+    match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    println!("offline subscription key activated");
+
+    Ok(Value::Null)
 }
 
 pub fn subscription_commands() -> CommandLineInterface {
@@ -73,7 +84,9 @@ pub fn subscription_commands() -> CommandLineInterface {
         )
         .insert(
             "set-offline-key",
-            CliCommand::new(&API_METHOD_SET_OFFLINE_SUBSCRIPTION_KEY).arg_param(&["data"]),
+            CliCommand::new(&API_METHOD_SET_OFFLINE_KEY)
+                .arg_param(&["data"])
+                .completion_cb("file", complete_file_name),
         )
         .insert(
             "update",
@@ -4,8 +4,8 @@ use serde_json::Value;
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::TRAFFIC_CONTROL_ID_SCHEMA;
-use pbs_tools::format::render_bytes_human_readable;
+use pbs_api_types::{Authid, TRAFFIC_CONTROL_ID_SCHEMA};
+use pbs_tools::format::{render_bytes_human_readable, render_epoch};
 
 use proxmox_backup::api2;
 use proxmox_backup::client_helpers::connect_to_localhost;
@@ -38,6 +38,7 @@ fn list_traffic_controls(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Resul
         .column(ColumnConfig::new("burst-out"))
         .column(ColumnConfig::new("network"))
         .column(ColumnConfig::new("timeframe"))
+        .column(ColumnConfig::new("datastore"))
         .column(ColumnConfig::new("comment"));
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
@@ -98,8 +99,56 @@ async fn show_current_traffic(param: Value) -> Result<Value, Error> {
 
     let options = default_table_format_options()
         .column(ColumnConfig::new("name"))
+        .column(ColumnConfig::new("connections"))
         .column(ColumnConfig::new("cur-rate-in").renderer(render_bytes_human_readable))
-        .column(ColumnConfig::new("cur-rate-out").renderer(render_bytes_human_readable));
+        .column(ColumnConfig::new("connection-ratio-in").renderer(render_ratio))
+        .column(ColumnConfig::new("cur-rate-out").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("connection-ratio-out").renderer(render_ratio));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+fn render_ratio(value: &Value, _record: &Value) -> Result<String, Error> {
+    match value.as_f64() {
+        Some(ratio) => Ok(format!("{:.1}%", ratio * 100.0)),
+        None => Ok(String::new()),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            "auth-id": {
+                type: Authid,
+                optional: true,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show transferred bytes per client.
+fn show_transfer_accounting(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::admin::transfer_accounting::API_METHOD_LIST_TRANSFER_ACCOUNTING;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("auth-id"))
+        .column(ColumnConfig::new("client-ip"))
+        .column(ColumnConfig::new("uploaded").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("downloaded").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("backup-sessions"))
+        .column(ColumnConfig::new("reader-sessions"))
+        .column(ColumnConfig::new("last-seen").renderer(render_epoch));
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
 
@@ -110,6 +159,10 @@ pub fn traffic_control_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_TRAFFIC_CONTROLS))
         .insert("traffic", CliCommand::new(&API_METHOD_SHOW_CURRENT_TRAFFIC))
+        .insert(
+            "accounting",
+            CliCommand::new(&API_METHOD_SHOW_TRANSFER_ACCOUNTING),
+        )
         .insert(
             "show",
             CliCommand::new(&API_METHOD_SHOW_TRAFFIC_CONTROL)
@@ -122,7 +175,8 @@ pub fn traffic_control_commands() -> CommandLineInterface {
         .insert(
             "create",
             CliCommand::new(&api2::config::traffic_control::API_METHOD_CREATE_TRAFFIC_CONTROL)
-                .arg_param(&["name"]),
+                .arg_param(&["name"])
+                .completion_cb("datastore", pbs_config::datastore::complete_datastore_name),
         )
         .insert(
             "update",
@@ -131,7 +185,8 @@ pub fn traffic_control_commands() -> CommandLineInterface {
                 .completion_cb(
                     "name",
                     pbs_config::traffic_control::complete_traffic_control_name,
-                ),
+                )
+                .completion_cb("datastore", pbs_config::datastore::complete_datastore_name),
         )
         .insert(
             "remove",
@@ -278,11 +278,17 @@ async fn restore_key(
                 min_length: 1,
                 max_length: 32,
             },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
         },
     },
 )]
 /// Create key (read password from stdin)
 fn create_key(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+
     if !tty::stdin_isatty() {
         bail!("no password input mechanism available");
     }
@@ -297,7 +303,11 @@ fn create_key(mut param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), E
         _ => unreachable!(),
     };
 
-    println!("{}", fingerprint);
+    if output_format == "text" {
+        println!("{}", fingerprint);
+    } else {
+        format_and_print_result(&fingerprint, &output_format);
+    }
 
     Ok(())
 }
@@ -9,8 +9,8 @@ use proxmox_schema::{api, ApiStringFormat, ApiType, Updater};
 use proxmox_http::ProxyConfig;
 
 use pbs_api_types::{
-    EMAIL_SCHEMA, MULTI_LINE_COMMENT_SCHEMA, OPENSSL_CIPHERS_TLS_1_2_SCHEMA,
-    OPENSSL_CIPHERS_TLS_1_3_SCHEMA,
+    Userid, DNS_NAME_OR_IP_SCHEMA, EMAIL_SCHEMA, MULTI_LINE_COMMENT_SCHEMA,
+    OPENSSL_CIPHERS_TLS_1_2_SCHEMA, OPENSSL_CIPHERS_TLS_1_3_SCHEMA, REMOTE_ID_SCHEMA,
 };
 
 use pbs_buildcfg::configdir;
@@ -24,6 +24,9 @@ use crate::api2::types::{
 const CONF_FILE: &str = configdir!("/node.cfg");
 const LOCK_FILE: &str = configdir!("/.node.lck");
 
+/// Default of the `user-expiry-warning` property, in days
+pub const DEFAULT_USER_EXPIRY_WARNING: u64 = 14;
+
 pub fn lock() -> Result<BackupLockGuard, Error> {
     open_backup_lockfile(LOCK_FILE, None, true)
 }
@@ -61,6 +64,93 @@ pub struct AcmeConfig {
     account: AcmeAccountName,
 }
 
+#[api(
+    properties: {
+        target: {
+            schema: DNS_NAME_OR_IP_SCHEMA,
+        },
+        port: {
+            type: u16,
+            optional: true,
+            default: 162,
+        },
+        community: {
+            type: String,
+            optional: true,
+            default: "public",
+        },
+        "datastore-full-threshold": {
+            type: u8,
+            optional: true,
+            minimum: 1,
+            maximum: 100,
+            default: 95,
+        },
+    }
+)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The SNMP trap configuration.
+pub struct SnmpConfig {
+    /// Host to send SNMP traps to.
+    pub target: String,
+    /// UDP port of the trap receiver.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// SNMPv2c community used for the traps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub community: Option<String>,
+    /// Datastore usage in percent above which a datastore-full trap is sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datastore_full_threshold: Option<u8>,
+}
+
+#[api(
+    properties: {
+        schedule: {
+            type: String,
+            format: &ApiStringFormat::VerifyFn(proxmox_time::verify_calendar_event),
+        },
+        "notify-user": {
+            type: Userid,
+            optional: true,
+        },
+    }
+)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The digest email configuration.
+pub struct DigestConfig {
+    /// When to send the digest, for example 'daily' or 'weekly'.
+    pub schedule: String,
+    /// User to send the digest to, root@pam if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify_user: Option<Userid>,
+}
+
+#[api(
+    properties: {
+        remote: {
+            schema: REMOTE_ID_SCHEMA,
+        },
+        schedule: {
+            type: String,
+            optional: true,
+            format: &ApiStringFormat::VerifyFn(proxmox_time::verify_calendar_event),
+        },
+    }
+)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The standby node configuration.
+pub struct StandbyConfig {
+    /// Remote of the standby node to replicate the configuration to.
+    pub remote: String,
+    /// When to replicate the configuration, only on request if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+}
+
 /// All available languages in Proxmox. Taken from proxmox-i18n repository.
 /// pt_BR, zh_CN, and zh_TW use the same case in the translation files.
 // TODO: auto-generate from available translations
@@ -175,7 +265,28 @@ pub enum Translation {
         "description" : {
             optional: true,
             schema: MULTI_LINE_COMMENT_SCHEMA,
-        }
+        },
+        snmp: {
+            optional: true,
+            type: String,
+            format: &ApiStringFormat::PropertyString(&SnmpConfig::API_SCHEMA),
+        },
+        digest: {
+            optional: true,
+            type: String,
+            format: &ApiStringFormat::PropertyString(&DigestConfig::API_SCHEMA),
+        },
+        standby: {
+            optional: true,
+            type: String,
+            format: &ApiStringFormat::PropertyString(&StandbyConfig::API_SCHEMA),
+        },
+        "user-expiry-warning": {
+            optional: true,
+            type: Integer,
+            minimum: 0,
+            default: 14,
+        },
     },
 )]
 #[derive(Deserialize, Serialize, Updater)]
@@ -226,6 +337,23 @@ pub struct NodeConfig {
     /// Maximum days to keep Task logs
     #[serde(skip_serializing_if = "Option::is_none")]
     pub task_log_max_days: Option<usize>,
+
+    /// Send SNMP traps for critical events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snmp: Option<String>,
+
+    /// Send a periodic digest of failed tasks and pending issues.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+
+    /// Replicate the configuration to a standby node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub standby: Option<String>,
+
+    /// Warn users this many days before their account or API tokens expire ('0' disables the
+    /// warning).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_expiry_warning: Option<u64>,
 }
 
 impl NodeConfig {
@@ -244,6 +372,35 @@ impl NodeConfig {
         AcmeClient::load(&account).await
     }
 
+    pub fn snmp_config(&self) -> Option<Result<SnmpConfig, Error>> {
+        self.snmp.as_deref().map(|config| -> Result<_, Error> {
+            crate::tools::config::from_property_string(config, &SnmpConfig::API_SCHEMA)
+        })
+    }
+
+    pub fn digest_config(&self) -> Option<Result<DigestConfig, Error>> {
+        self.digest.as_deref().map(|config| -> Result<_, Error> {
+            crate::tools::config::from_property_string(config, &DigestConfig::API_SCHEMA)
+        })
+    }
+
+    pub fn standby_config(&self) -> Option<Result<StandbyConfig, Error>> {
+        self.standby.as_deref().map(|config| -> Result<_, Error> {
+            crate::tools::config::from_property_string(config, &StandbyConfig::API_SCHEMA)
+        })
+    }
+
+    /// Days before the expiration of an account to warn its user, or `None` if disabled.
+    pub fn user_expiry_warning(&self) -> Option<u64> {
+        match self
+            .user_expiry_warning
+            .unwrap_or(DEFAULT_USER_EXPIRY_WARNING)
+        {
+            0 => None,
+            days => Some(days),
+        }
+    }
+
     pub fn acme_domains(&self) -> AcmeDomainIter {
         AcmeDomainIter::new(self)
     }
@@ -281,6 +438,15 @@ impl NodeConfig {
         if let Some(ciphers) = self.ciphers_tls_1_2.as_deref() {
             dummy_acceptor.set_cipher_list(ciphers)?;
         }
+        if let Some(snmp) = self.snmp_config() {
+            snmp?;
+        }
+        if let Some(digest) = self.digest_config() {
+            digest?;
+        }
+        if let Some(standby) = self.standby_config() {
+            standby?;
+        }
 
         Ok(())
     }
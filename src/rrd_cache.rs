@@ -6,7 +6,7 @@
 
 use std::path::Path;
 
-use anyhow::{format_err, Error};
+use anyhow::{bail, format_err, Error};
 use once_cell::sync::OnceCell;
 
 use proxmox_rrd::rrd::{CF, DST, RRD};
@@ -74,6 +74,85 @@ fn load_callback(path: &Path, _rel_path: &str, dst: DST) -> RRD {
     }
 }
 
+/// Time range and resolution used to extract data from the RRD cache
+#[derive(Copy, Clone)]
+pub struct RRDTimeRange {
+    pub start: u64,
+    pub end: u64,
+    pub resolution: u64,
+}
+
+impl RRDTimeRange {
+    /// Range covering one of the fixed time frames, ending now.
+    pub fn from_timeframe(timeframe: RRDTimeFrame) -> Self {
+        let end = proxmox_time::epoch_f64() as u64;
+
+        let (start, resolution) = match timeframe {
+            RRDTimeFrame::Hour => (end - 3600, 60),
+            RRDTimeFrame::Day => (end - 3600 * 24, 60),
+            RRDTimeFrame::Week => (end - 3600 * 24 * 7, 30 * 60),
+            RRDTimeFrame::Month => (end - 3600 * 24 * 30, 30 * 60),
+            RRDTimeFrame::Year => (end - 3600 * 24 * 365, 6 * 60 * 60),
+            RRDTimeFrame::Decade => (end - 10 * 3600 * 24 * 366, 7 * 86400),
+        };
+
+        Self {
+            start,
+            end,
+            resolution,
+        }
+    }
+
+    /// Custom range, using the finest resolution which still covers `start`.
+    pub fn from_start_end(start: u64, end: Option<u64>) -> Result<Self, Error> {
+        let now = proxmox_time::epoch_f64() as u64;
+        let end = end.unwrap_or(now);
+
+        if start >= end {
+            bail!("invalid RRD time range - start must be before end");
+        }
+
+        // must match the RRAs from RRDCache::create_proxmox_backup_default_rrd
+        let age = now.saturating_sub(start);
+        let resolution = if age <= 3600 * 24 {
+            60
+        } else if age <= 3600 * 24 * 30 {
+            30 * 60
+        } else if age <= 3600 * 24 * 360 {
+            6 * 60 * 60
+        } else {
+            7 * 86400
+        };
+
+        Ok(Self {
+            start,
+            end,
+            resolution,
+        })
+    }
+
+    /// Range from API parameters, either a fixed `timeframe` or a custom `start`/`end`.
+    pub fn from_params(
+        timeframe: Option<RRDTimeFrame>,
+        start: Option<u64>,
+        end: Option<u64>,
+    ) -> Result<Self, Error> {
+        match (timeframe, start) {
+            (Some(_), Some(_)) => {
+                bail!("parameters 'timeframe' and 'start' are mutually exclusive")
+            }
+            (Some(timeframe), None) => {
+                if end.is_some() {
+                    bail!("parameter 'end' can only be used together with 'start'");
+                }
+                Ok(Self::from_timeframe(timeframe))
+            }
+            (None, Some(start)) => Self::from_start_end(start, end),
+            (None, None) => bail!("either 'timeframe' or 'start' is required"),
+        }
+    }
+}
+
 /// Extracts data for the specified time frame from from RRD cache
 pub fn extract_rrd_data(
     basedir: &str,
@@ -81,17 +160,16 @@ pub fn extract_rrd_data(
     timeframe: RRDTimeFrame,
     mode: RRDMode,
 ) -> Result<Option<proxmox_rrd::Entry>, Error> {
-    let end = proxmox_time::epoch_f64() as u64;
-
-    let (start, resolution) = match timeframe {
-        RRDTimeFrame::Hour => (end - 3600, 60),
-        RRDTimeFrame::Day => (end - 3600 * 24, 60),
-        RRDTimeFrame::Week => (end - 3600 * 24 * 7, 30 * 60),
-        RRDTimeFrame::Month => (end - 3600 * 24 * 30, 30 * 60),
-        RRDTimeFrame::Year => (end - 3600 * 24 * 365, 6 * 60 * 60),
-        RRDTimeFrame::Decade => (end - 10 * 3600 * 24 * 366, 7 * 86400),
-    };
+    extract_rrd_data_range(basedir, name, RRDTimeRange::from_timeframe(timeframe), mode)
+}
 
+/// Extracts data for the specified time range from from RRD cache
+pub fn extract_rrd_data_range(
+    basedir: &str,
+    name: &str,
+    range: RRDTimeRange,
+    mode: RRDMode,
+) -> Result<Option<proxmox_rrd::Entry>, Error> {
     let cf = match mode {
         RRDMode::Max => CF::Maximum,
         RRDMode::Average => CF::Average,
@@ -99,7 +177,14 @@ pub fn extract_rrd_data(
 
     let rrd_cache = get_rrd_cache()?;
 
-    rrd_cache.extract_cached_data(basedir, name, cf, resolution, Some(start), Some(end))
+    rrd_cache.extract_cached_data(
+        basedir,
+        name,
+        cf,
+        range.resolution,
+        Some(range.start),
+        Some(range.end),
+    )
 }
 
 /// Sync/Flush the RRD journal
@@ -0,0 +1,313 @@
+//! Threshold based alert rules
+//!
+//! The proxy daemon periodically evaluates all configured alert rules. A notification is sent
+//! once a rule starts to match for a subject (a datastore, job or backup group), and only again
+//! after the condition cleared in between.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{format_err, Error};
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{AlertJobType, AlertRule, AlertRuleType, BackupGroup, JobHistory, Operation};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_datastore::DataStore;
+
+use crate::server::jobstate;
+use crate::server::send_alert_notification;
+
+const ALERT_STATE_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/alert-state.json");
+
+/// A subject matching an alert rule
+pub struct Alert {
+    /// Identifies the datastore, job or backup group the alert is about
+    pub subject: String,
+    /// Human readable description of the alert
+    pub message: String,
+}
+
+// rule ID => subjects which matched on the last check
+type AlertState = HashMap<String, HashSet<String>>;
+
+fn load_state() -> Result<AlertState, Error> {
+    match file_read_optional_string(ALERT_STATE_FN)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn save_state(state: &AlertState) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(
+        ALERT_STATE_FN,
+        serde_json::to_string(state)?.as_bytes(),
+        options,
+        false,
+    )
+}
+
+fn required_store(rule: &AlertRule) -> Result<&str, Error> {
+    rule.store
+        .as_deref()
+        .ok_or_else(|| format_err!("alert rule '{}' has no datastore configured", rule.id))
+}
+
+fn check_datastore_usage(rule: &AlertRule) -> Result<Vec<Alert>, Error> {
+    let store = required_store(rule)?;
+    let datastore = DataStore::lookup_datastore(store, Some(Operation::Lookup))?;
+    let status = proxmox_sys::fs::fs_info(&datastore.base_path())?;
+
+    let mut alerts = Vec::new();
+    if status.total == 0 {
+        return Ok(alerts);
+    }
+
+    let usage = status.used * 100 / status.total;
+    if usage >= rule.threshold {
+        alerts.push(Alert {
+            subject: store.to_string(),
+            message: format!(
+                "datastore '{}' is {}% full (threshold {}%)",
+                store, usage, rule.threshold
+            ),
+        });
+    }
+
+    Ok(alerts)
+}
+
+fn check_job_failures(rule: &AlertRule) -> Result<Vec<Alert>, Error> {
+    let job_types = match rule.job_type {
+        Some(job_type) => vec![job_type],
+        None => AlertJobType::ALL.to_vec(),
+    };
+
+    let mut alerts = Vec::new();
+
+    for job_type in job_types {
+        let job_type = job_type.as_str();
+        let names = match &rule.job_id {
+            Some(id) => vec![id.clone()],
+            None => jobstate::list_job_history_names(job_type)?,
+        };
+
+        for name in names {
+            let history = jobstate::read_job_history(job_type, &name)?;
+            alerts.extend(job_failure_alert(job_type, &name, &history, rule.threshold));
+        }
+    }
+
+    Ok(alerts)
+}
+
+// alert if the last `threshold` runs of the job all failed
+fn job_failure_alert(
+    job_type: &str,
+    name: &str,
+    history: &JobHistory,
+    threshold: u64,
+) -> Option<Alert> {
+    let last_run = history.runs.first()?;
+    let failures = history.runs.iter().take_while(|run| !run.success).count() as u64;
+    if failures < threshold.max(1) {
+        return None;
+    }
+
+    Some(Alert {
+        subject: format!("{}/{}", job_type, name),
+        message: format!(
+            "{} '{}' failed {} times in a row, last state: {}",
+            job_type, name, failures, last_run.state
+        ),
+    })
+}
+
+fn check_backup_age(rule: &AlertRule) -> Result<Vec<Alert>, Error> {
+    let store = required_store(rule)?;
+    let datastore = DataStore::lookup_datastore(store, Some(Operation::Read))?;
+    let ns = rule.ns.clone().unwrap_or_default();
+
+    let groups = match &rule.group {
+        Some(group) => vec![datastore.backup_group(ns.clone(), group.parse::<BackupGroup>()?)],
+        None => datastore.iter_backup_groups_ok(ns.clone())?.collect(),
+    };
+
+    let limit = proxmox_time::epoch_i64() - (rule.threshold as i64) * 24 * 3600;
+
+    let mut alerts = Vec::new();
+
+    for group in groups {
+        let last = group.last_successful_backup()?;
+        if matches!(last, Some(time) if time >= limit) {
+            continue;
+        }
+
+        let name = if ns.is_root() {
+            group.group().to_string()
+        } else {
+            format!("{}/{}", ns.display_as_path(), group.group())
+        };
+        let last = match last.map(proxmox_time::epoch_to_rfc3339_utc) {
+            Some(Ok(time)) => time,
+            _ => "never".to_string(),
+        };
+
+        alerts.push(Alert {
+            message: format!(
+                "backup group '{}' on datastore '{}' has no backup for {} days, last backup: {}",
+                name, store, rule.threshold, last
+            ),
+            subject: name,
+        });
+    }
+
+    Ok(alerts)
+}
+
+/// Returns all subjects currently matching `rule`.
+pub fn check_alert_rule(rule: &AlertRule) -> Result<Vec<Alert>, Error> {
+    match rule.ty {
+        AlertRuleType::DatastoreUsage => check_datastore_usage(rule),
+        AlertRuleType::JobFailures => check_job_failures(rule),
+        AlertRuleType::BackupAge => check_backup_age(rule),
+    }
+}
+
+// returns the alerts for subjects which did not match on the previous check
+fn new_alerts<'a>(alerts: &'a [Alert], previous: &HashSet<String>) -> Vec<&'a Alert> {
+    alerts
+        .iter()
+        .filter(|alert| !previous.contains(&alert.subject))
+        .collect()
+}
+
+// returns the subjects to remember for the next check - if the notification failed, the new
+// alerts are left out, so that they get retried
+fn matched_subjects(
+    alerts: &[Alert],
+    previous: &HashSet<String>,
+    notified: bool,
+) -> HashSet<String> {
+    alerts
+        .iter()
+        .map(|alert| &alert.subject)
+        .filter(|subject| notified || previous.contains(*subject))
+        .cloned()
+        .collect()
+}
+
+/// Evaluate all enabled alert rules and send notifications for new alerts.
+pub fn check_alert_rules() -> Result<(), Error> {
+    let (config, _digest) = pbs_config::alerts::config()?;
+    let rules: Vec<AlertRule> = config.convert_to_typed_array("rule")?;
+
+    let mut old_state = load_state()?;
+    let mut state = AlertState::new();
+
+    for rule in rules {
+        if rule.disable.unwrap_or(false) {
+            continue;
+        }
+
+        let previous = old_state.remove(&rule.id).unwrap_or_default();
+
+        let alerts = match check_alert_rule(&rule) {
+            Ok(alerts) => alerts,
+            Err(err) => {
+                eprintln!("checking alert rule '{}' failed - {}", rule.id, err);
+                state.insert(rule.id, previous);
+                continue;
+            }
+        };
+
+        let new_alerts = new_alerts(&alerts, &previous);
+
+        let mut notified = true;
+        if !new_alerts.is_empty() {
+            if let Err(err) = send_alert_notification(&rule, &new_alerts) {
+                eprintln!(
+                    "sending notification for alert rule '{}' failed - {}",
+                    rule.id, err
+                );
+                notified = false;
+            }
+        }
+
+        state.insert(rule.id, matched_subjects(&alerts, &previous, notified));
+    }
+
+    save_state(&state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::JobRunResult;
+
+    fn alert(subject: &str) -> Alert {
+        Alert {
+            subject: subject.to_string(),
+            message: format!("{subject} matched"),
+        }
+    }
+
+    fn history(results: &[bool]) -> JobHistory {
+        let runs = results
+            .iter()
+            .enumerate()
+            .map(|(i, success)| JobRunResult {
+                upid: format!("run{i}"),
+                starttime: 0,
+                endtime: 0,
+                state: if *success { "OK" } else { "ERROR: failed" }.to_string(),
+                success: *success,
+                transferred_bytes: None,
+                snapshot_count: None,
+            })
+            .collect();
+        JobHistory::from_runs(runs)
+    }
+
+    #[test]
+    fn test_job_failure_alert() {
+        // runs are ordered newest first
+        let found = job_failure_alert("syncjob", "job1", &history(&[false, false, true]), 2)
+            .expect("two failures should match");
+        assert_eq!(found.subject, "syncjob/job1");
+        assert!(found.message.contains("failed 2 times in a row"));
+
+        assert!(job_failure_alert("syncjob", "job1", &history(&[false, true, false]), 2).is_none());
+        assert!(job_failure_alert("syncjob", "job1", &history(&[true, false, false]), 1).is_none());
+        assert!(job_failure_alert("syncjob", "job1", &history(&[]), 0).is_none());
+        assert!(job_failure_alert("syncjob", "job1", &history(&[false]), 1).is_some());
+    }
+
+    #[test]
+    fn test_alert_state() {
+        let previous: HashSet<String> = ["store1".to_string(), "store2".to_string()].into();
+        let alerts = vec![alert("store1"), alert("store3")];
+
+        // only alerts which did not match before get sent
+        let new: Vec<&str> = new_alerts(&alerts, &previous)
+            .iter()
+            .map(|alert| alert.subject.as_str())
+            .collect();
+        assert_eq!(new, ["store3"]);
+
+        // cleared alerts get dropped, so that they are sent again once they match again
+        let expected: HashSet<String> = ["store1".to_string(), "store3".to_string()].into();
+        assert_eq!(matched_subjects(&alerts, &previous, true), expected);
+
+        // new alerts are retried if the notification failed
+        let expected: HashSet<String> = ["store1".to_string()].into();
+        assert_eq!(matched_subjects(&alerts, &previous, false), expected);
+    }
+}
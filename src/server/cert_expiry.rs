@@ -0,0 +1,214 @@
+//! Certificate expiry monitoring
+//!
+//! The daily update checks the expiration date of all configured certificates, that is the proxy
+//! certificate (including its chain) and the CA certificates of remotes, and warns the
+//! administrator if they expire soon. ACME certificates get renewed 30 days before they expire,
+//! so a warning for them means that the renewal has been failing.
+//!
+//! Every certificate gets warned about once before and once after it expired.
+
+use std::collections::HashMap;
+
+use anyhow::{format_err, Error};
+
+use proxmox_sys::fs::{
+    file_get_contents, file_get_optional_contents, file_read_optional_string, replace_file,
+    CreateOptions,
+};
+
+use pbs_buildcfg::{configdir, PROXMOX_BACKUP_STATE_DIR_M};
+use pbs_tools::cert::CertInfo;
+
+use crate::server::send_certificate_expiry_mail;
+
+const CERT_EXPIRY_STATE_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/cert-expiry.json");
+
+/// Warn this many days before the certificate expires
+const CERT_EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// A configured certificate which expires soon
+pub struct ExpiringCertificate {
+    /// Where the certificate is configured
+    pub description: String,
+    pub subject: String,
+    pub fingerprint: String,
+    pub expire: i64,
+    /// Whether the certificate is part of the proxy certificate, which might be managed by ACME
+    pub proxy: bool,
+}
+
+// certificate fingerprint => whether the warning was sent after the certificate expired
+type CertExpiryState = HashMap<String, bool>;
+
+fn load_state() -> Result<CertExpiryState, Error> {
+    match file_read_optional_string(CERT_EXPIRY_STATE_FN)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn save_state(state: &CertExpiryState) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(
+        CERT_EXPIRY_STATE_FN,
+        serde_json::to_string(state)?.as_bytes(),
+        options,
+        false,
+    )
+}
+
+fn expiring_from_pem(
+    list: &mut Vec<ExpiringCertificate>,
+    pem: &[u8],
+    description: &str,
+    proxy: bool,
+    until: i64,
+) -> Result<(), Error> {
+    for cert in CertInfo::stack_from_pem(pem)? {
+        let expire = cert.not_after_unix()?;
+        if expire > until {
+            continue;
+        }
+        list.push(ExpiringCertificate {
+            description: description.to_string(),
+            subject: cert.subject_name()?,
+            fingerprint: cert.fingerprint()?,
+            expire,
+            proxy,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the configured certificates which expire within the next `days` days, ordered by
+/// their expiration date.
+pub fn expiring_certificates(days: i64) -> Result<Vec<ExpiringCertificate>, Error> {
+    let until = proxmox_time::epoch_i64() + days * 24 * 3600;
+
+    let mut list = Vec::new();
+
+    let proxy_pem = file_get_contents(configdir!("/proxy.pem"))?;
+    expiring_from_pem(&mut list, &proxy_pem, "proxy certificate", true, until)
+        .map_err(|err| format_err!("failed to check proxy certificate - {}", err))?;
+
+    let (config, _digest) = pbs_config::remote::config()?;
+    for name in config.sections.keys() {
+        let description = format!("CA certificate of remote '{}'", name);
+        let path = pbs_config::remote::ca_cert_path(name);
+        let result = file_get_optional_contents(path).and_then(|pem| match pem {
+            Some(pem) => expiring_from_pem(&mut list, &pem, &description, false, until),
+            None => Ok(()),
+        });
+        if let Err(err) = result {
+            // a broken remote CA file must not prevent checking the others
+            log::error!("failed to check {} - {}", description, err);
+        }
+    }
+
+    list.sort_by_key(|cert| cert.expire);
+
+    Ok(list)
+}
+
+// returns the certificates which were not warned about yet, or which expired since the last
+// warning - the state of already warned about certificates is carried over to `state`
+fn pending_certificates<'a>(
+    certificates: &'a [ExpiringCertificate],
+    old_state: &CertExpiryState,
+    state: &mut CertExpiryState,
+    now: i64,
+) -> Vec<&'a ExpiringCertificate> {
+    let mut pending = Vec::new();
+    for cert in certificates {
+        let expired = cert.expire <= now;
+        match old_state.get(&cert.fingerprint) {
+            Some(warned_expired) if *warned_expired || !expired => {
+                state.insert(cert.fingerprint.clone(), *warned_expired);
+            }
+            _ => pending.push(cert),
+        }
+    }
+    pending
+}
+
+/// Check the expiration date of all configured certificates and warn if they expire soon.
+pub fn check_certificate_expiry() -> Result<(), Error> {
+    let certificates = expiring_certificates(CERT_EXPIRY_WARNING_DAYS)?;
+
+    let now = proxmox_time::epoch_i64();
+    let old_state = load_state()?;
+    let mut state = CertExpiryState::new();
+
+    let pending = pending_certificates(&certificates, &old_state, &mut state, now);
+
+    if !pending.is_empty() {
+        let (config, _digest) = crate::config::node::config()?;
+        let acme = config.acme_domains().next().is_some();
+
+        match send_certificate_expiry_mail(&pending, acme) {
+            Ok(()) => {
+                for cert in pending {
+                    state.insert(cert.fingerprint.clone(), cert.expire <= now);
+                }
+            }
+            // try again on the next check
+            Err(err) => log::error!("sending certificate expiry warning failed - {}", err),
+        }
+    }
+
+    save_state(&state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cert(fingerprint: &str, expire: i64) -> ExpiringCertificate {
+        ExpiringCertificate {
+            description: "proxy certificate".to_string(),
+            subject: format!("CN = {fingerprint}"),
+            fingerprint: fingerprint.to_string(),
+            expire,
+            proxy: true,
+        }
+    }
+
+    #[test]
+    fn test_pending_certificates() {
+        let now = 1000;
+        let certificates = vec![
+            cert("new", now + 10),
+            cert("warned", now + 10),
+            cert("expired-since", now - 10),
+            cert("warned-expired", now - 10),
+        ];
+        let old_state: CertExpiryState = [
+            ("warned".to_string(), false),
+            ("expired-since".to_string(), false),
+            ("warned-expired".to_string(), true),
+            ("removed".to_string(), false),
+        ]
+        .into();
+
+        let mut state = CertExpiryState::new();
+        let pending: Vec<&str> = pending_certificates(&certificates, &old_state, &mut state, now)
+            .iter()
+            .map(|cert| cert.fingerprint.as_str())
+            .collect();
+        assert_eq!(pending, ["new", "expired-since"]);
+
+        // already warned about certificates are remembered, removed ones are dropped
+        let expected: CertExpiryState = [
+            ("warned".to_string(), false),
+            ("warned-expired".to_string(), true),
+        ]
+        .into();
+        assert_eq!(state, expected);
+    }
+}
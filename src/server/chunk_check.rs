@@ -0,0 +1,342 @@
+//! Check the chunk store of a datastore, and repair broken chunks
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{bail, Error};
+
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::{Authid, BackupNamespace, Remote, SyncDirection, SyncJobConfig};
+use pbs_client::BackupReader;
+use pbs_datastore::index::IndexFile;
+use pbs_datastore::manifest::{archive_type, ArchiveType};
+use pbs_datastore::{BackupDir, DataBlob, DataStore};
+use proxmox_rest_server::WorkerTask;
+
+/// Checks the chunk store of a datastore for empty, misplaced and corrupt chunks.
+///
+/// With `repair`, broken chunks are moved to the `.corrupt` directory and misplaced ones are moved
+/// to their directory. If a `sync_job` is given, the broken chunks are downloaded again from its remote.
+pub fn do_chunkstore_check(
+    datastore: Arc<DataStore>,
+    repair: bool,
+    sync_job: Option<SyncJobConfig>,
+    auth_id: &Authid,
+    to_stdout: bool,
+) -> Result<String, Error> {
+    let store = datastore.name().to_string();
+
+    let upid_str = WorkerTask::spawn(
+        "chunkstore-check",
+        Some(store.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| async move {
+            task_log!(worker, "checking chunks of datastore {}", store);
+
+            let status = proxmox_async::runtime::block_in_place(|| {
+                datastore.check_chunkstore(repair, &*worker)
+            })?;
+
+            task_log!(worker, "checked chunks: {}", status.checked_chunks);
+            task_log!(worker, "broken chunks: {}", status.broken_chunks.len());
+            task_log!(worker, "misplaced chunks: {}", status.misplaced_chunks);
+            task_log!(worker, "unknown files: {}", status.unknown_files);
+
+            if let Some(sync_job) = sync_job {
+                if !status.broken_chunks.is_empty() {
+                    let broken = status.broken_chunks.into_iter().collect();
+                    refetch_chunks(&worker, &datastore, &sync_job, broken).await?;
+                }
+            } else if !repair && !status.broken_chunks.is_empty() {
+                bail!("found {} broken chunks", status.broken_chunks.len());
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(upid_str)
+}
+
+// download the `broken` chunks again from the remote of `sync_job`, the local snapshots which
+// reference them tell which remote snapshots should contain them
+async fn refetch_chunks(
+    worker: &WorkerTask,
+    datastore: &Arc<DataStore>,
+    sync_job: &SyncJobConfig,
+    mut broken: HashSet<[u8; 32]>,
+) -> Result<(), Error> {
+    task_log!(
+        worker,
+        "downloading broken chunks from remote '{}', datastore '{}'",
+        sync_job.remote,
+        sync_job.remote_store,
+    );
+
+    let (remote_config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = remote_config.lookup("remote", &sync_job.remote)?;
+
+    let local_ns = sync_job.ns.clone().unwrap_or_default();
+    let remote_ns = sync_job.remote_ns.clone().unwrap_or_default();
+
+    let mut refetched = 0;
+
+    'namespaces: for ns in datastore.recursive_iter_backup_ns_ok(local_ns.clone(), None)? {
+        let source_ns = ns.map_prefix(&local_ns, &remote_ns)?;
+
+        for group in datastore.iter_backup_groups_ok(ns)? {
+            for info in group.list_backups()? {
+                if broken.is_empty() {
+                    break 'namespaces;
+                }
+
+                let snapshot = info.backup_dir;
+
+                // archive name and digests and sizes of the broken chunks it references
+                let mut archives = Vec::new();
+                for file in info.files {
+                    let mut path = snapshot.relative_path();
+                    path.push(&file);
+                    let index: Box<dyn IndexFile> = match archive_type(&file)? {
+                        ArchiveType::FixedIndex => Box::new(datastore.open_fixed_reader(&path)?),
+                        ArchiveType::DynamicIndex => {
+                            Box::new(datastore.open_dynamic_reader(&path)?)
+                        }
+                        ArchiveType::Blob => continue,
+                    };
+
+                    let chunks: Vec<([u8; 32], u64)> = (0..index.index_count())
+                        .filter_map(|pos| index.chunk_info(pos))
+                        .filter(|chunk| broken.contains(&chunk.digest))
+                        .map(|chunk| (chunk.digest, chunk.size()))
+                        .collect();
+                    if !chunks.is_empty() {
+                        archives.push((file, chunks));
+                    }
+                }
+
+                if archives.is_empty() {
+                    continue;
+                }
+
+                let client = crate::api2::config::remote::remote_client(
+                    &remote,
+                    Some(sync_job.limit.clone()),
+                )
+                .await?;
+                let reader = match BackupReader::start(
+                    client,
+                    None,
+                    &sync_job.remote_store,
+                    &source_ns,
+                    snapshot.as_ref(),
+                    false,
+                )
+                .await
+                {
+                    Ok(reader) => reader,
+                    Err(err) => {
+                        task_warn!(
+                            worker,
+                            "snapshot {} not available on remote - {}",
+                            snapshot.dir(),
+                            err
+                        );
+                        continue;
+                    }
+                };
+
+                for (file, chunks) in archives {
+                    // the remote only hands out chunks of downloaded index files
+                    if let Err(err) = reader.download(&file, Vec::new()).await {
+                        task_warn!(worker, "could not download {file} - {err}");
+                        continue;
+                    }
+
+                    for (digest, size) in chunks {
+                        if !broken.contains(&digest) {
+                            continue;
+                        }
+                        match refetch_chunk(&reader, datastore, &digest, size).await {
+                            Ok(()) => {
+                                broken.remove(&digest);
+                                refetched += 1;
+                            }
+                            Err(err) => task_warn!(
+                                worker,
+                                "could not download chunk {} - {}",
+                                hex::encode(digest),
+                                err
+                            ),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    task_log!(worker, "downloaded {} chunks again", refetched);
+
+    if !broken.is_empty() {
+        bail!(
+            "{} broken chunks could not be downloaded again",
+            broken.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the pull sync job of the datastore `store` whose target namespace is the closest parent
+/// of `ns`, which chunks of the snapshots in `ns` can be downloaded again from.
+pub fn lookup_refetch_sync_job(
+    store: &str,
+    ns: &BackupNamespace,
+) -> Result<Option<SyncJobConfig>, Error> {
+    let (config, _digest) = pbs_config::sync::config()?;
+    let jobs: Vec<SyncJobConfig> = config.convert_to_typed_array("sync")?;
+
+    Ok(select_refetch_sync_job(jobs, store, ns))
+}
+
+fn select_refetch_sync_job(
+    jobs: Vec<SyncJobConfig>,
+    store: &str,
+    ns: &BackupNamespace,
+) -> Option<SyncJobConfig> {
+    jobs.into_iter()
+        .filter(|job| job.store == store && job.sync_direction != Some(SyncDirection::Push))
+        .filter_map(|job| {
+            let depth = job.ns.clone().unwrap_or_default().contains(ns)?;
+            match job.max_depth {
+                Some(max_depth) if depth > max_depth => None,
+                _ => Some((depth, job)),
+            }
+        })
+        .min_by_key(|(depth, _job)| *depth)
+        .map(|(_depth, job)| job)
+}
+
+/// Download the `chunks` (digest and size) of the archive `file` of `snapshot` again from the
+/// same snapshot on the remote of `sync_job`. Returns the digests of the downloaded chunks.
+pub async fn refetch_snapshot_chunks(
+    worker: &dyn WorkerTaskContext,
+    datastore: &DataStore,
+    sync_job: &SyncJobConfig,
+    snapshot: &BackupDir,
+    file: &str,
+    chunks: &[([u8; 32], u64)],
+) -> Result<Vec<[u8; 32]>, Error> {
+    let (remote_config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = remote_config.lookup("remote", &sync_job.remote)?;
+
+    let source_ns = snapshot.backup_ns().map_prefix(
+        &sync_job.ns.clone().unwrap_or_default(),
+        &sync_job.remote_ns.clone().unwrap_or_default(),
+    )?;
+
+    let client =
+        crate::api2::config::remote::remote_client(&remote, Some(sync_job.limit.clone())).await?;
+    let reader = BackupReader::start(
+        client,
+        None,
+        &sync_job.remote_store,
+        &source_ns,
+        snapshot.as_ref(),
+        false,
+    )
+    .await?;
+
+    // the remote only hands out chunks of downloaded index files
+    reader.download(file, Vec::new()).await?;
+
+    let mut refetched = Vec::new();
+    for (digest, size) in chunks {
+        match refetch_chunk(&reader, datastore, digest, *size).await {
+            Ok(()) => refetched.push(*digest),
+            Err(err) => task_warn!(
+                worker,
+                "could not download chunk {} - {}",
+                hex::encode(digest),
+                err
+            ),
+        }
+    }
+
+    Ok(refetched)
+}
+
+async fn refetch_chunk(
+    reader: &BackupReader,
+    datastore: &DataStore,
+    digest: &[u8; 32],
+    size: u64,
+) -> Result<(), Error> {
+    let mut raw_data = Vec::with_capacity(size as usize);
+    reader.download_chunk(digest, &mut raw_data).await?;
+    let chunk = DataBlob::load_from_reader(&mut &raw_data[..])?;
+    chunk.verify_unencrypted(size as usize, digest)?;
+
+    // a broken chunk still in place would be kept by inserting, as it might have the same size
+    proxmox_async::runtime::block_in_place(|| datastore.replace_chunk(&chunk, digest))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sync_job(id: &str, store: &str, ns: &str, extra: serde_json::Value) -> SyncJobConfig {
+        let mut config = json!({
+            "id": id,
+            "store": store,
+            "ns": ns,
+            "remote": "remote1",
+            "remote-store": "store1",
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn selected(jobs: &[SyncJobConfig], ns: &str) -> Option<String> {
+        select_refetch_sync_job(jobs.to_vec(), "store1", &ns.parse().unwrap()).map(|job| job.id)
+    }
+
+    #[test]
+    fn test_select_refetch_sync_job() {
+        let jobs = vec![
+            sync_job("root", "store1", "", json!({})),
+            sync_job("a", "store1", "a", json!({})),
+            sync_job("a-b", "store1", "a/b", json!({ "max-depth": 0 })),
+            sync_job(
+                "push",
+                "store1",
+                "a/b/c",
+                json!({ "sync-direction": "push" }),
+            ),
+            sync_job("other-store", "store2", "a/b/c", json!({})),
+            sync_job("x", "store1", "x", json!({ "max-depth": 1 })),
+        ];
+
+        // the job with the closest parent namespace wins
+        assert_eq!(selected(&jobs, "a").as_deref(), Some("a"));
+        assert_eq!(selected(&jobs, "a/b").as_deref(), Some("a-b"));
+        assert_eq!(selected(&jobs, "b").as_deref(), Some("root"));
+
+        // the max-depth of "a-b" excludes sub namespaces, push jobs and other stores never match
+        assert_eq!(selected(&jobs, "a/b/c").as_deref(), Some("a"));
+
+        // "x" only covers one level, so deeper namespaces fall back to the root job
+        assert_eq!(selected(&jobs, "x/y").as_deref(), Some("x"));
+        assert_eq!(selected(&jobs, "x/y/z").as_deref(), Some("root"));
+
+        assert_eq!(selected(&jobs[1..], "b"), None);
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::Error;
+use serde::Serialize;
+
+use proxmox_lang::try_block;
+use proxmox_rest_server::{TaskListInfoIterator, TaskState, WorkerTask};
+use proxmox_sys::{task_log, task_warn};
+use proxmox_time::CalendarEvent;
+
+use pbs_api_types::{
+    Authid, BackupNamespace, DataStoreConfig, Operation, SnapshotVerifyState, SyncJobConfig,
+    Userid, VerifyState,
+};
+use pbs_datastore::DataStore;
+
+use crate::server::jobstate::{self, Job};
+
+/// Sync jobs are considered stale if their last successful run is older than one day after the
+/// next scheduled run.
+const SYNC_JOB_STALE_GRACE: i64 = 24 * 3600;
+
+/// A task which ended with an error
+#[derive(Serialize)]
+pub struct DigestFailedTask {
+    pub upid: String,
+    pub worker_type: String,
+    pub worker_id: Option<String>,
+    pub starttime: String,
+    pub error: String,
+}
+
+/// Snapshots of a datastore which were not verified or failed verification
+#[derive(Serialize)]
+pub struct DigestVerifyStatus {
+    pub store: String,
+    pub unverified: u64,
+    pub failed: u64,
+}
+
+/// A sync job which has not run successfully as scheduled
+#[derive(Serialize)]
+pub struct DigestStaleSyncJob {
+    pub id: String,
+    pub store: String,
+    pub remote: String,
+    pub last_success: Option<String>,
+}
+
+/// Failed tasks and pending issues collected for the digest email
+#[derive(Serialize)]
+pub struct Digest {
+    pub since: String,
+    pub failed_tasks: Vec<DigestFailedTask>,
+    pub verify_status: Vec<DigestVerifyStatus>,
+    pub stale_sync_jobs: Vec<DigestStaleSyncJob>,
+}
+
+fn format_time(epoch: i64) -> String {
+    proxmox_time::strftime_local("%F %T", epoch).unwrap_or_else(|_| epoch.to_string())
+}
+
+fn collect_failed_tasks(since: i64) -> Result<Vec<DigestFailedTask>, Error> {
+    let mut list = Vec::new();
+
+    for info in TaskListInfoIterator::new(false)? {
+        let info = info?;
+
+        let state = match info.state {
+            Some(state) => state,
+            None => continue,
+        };
+        if state.endtime() < since {
+            // tasks are ordered by end time, everything from here on is older
+            break;
+        }
+
+        if let TaskState::Error { message, .. } = state {
+            list.push(DigestFailedTask {
+                upid: info.upid_str,
+                worker_type: info.upid.worker_type,
+                worker_id: info.upid.worker_id,
+                starttime: format_time(info.upid.starttime),
+                error: message,
+            });
+        }
+    }
+
+    list.reverse();
+
+    Ok(list)
+}
+
+fn collect_verify_status(worker: &WorkerTask) -> Result<Vec<DigestVerifyStatus>, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let stores: Vec<DataStoreConfig> = config.convert_to_typed_array("datastore")?;
+
+    let mut list = Vec::new();
+
+    for store in stores {
+        let datastore = match DataStore::lookup_datastore(&store.name, Some(Operation::Read)) {
+            Ok(datastore) => datastore,
+            Err(err) => {
+                task_warn!(worker, "skipping datastore '{}' - {}", store.name, err);
+                continue;
+            }
+        };
+
+        let mut status = DigestVerifyStatus {
+            store: store.name,
+            unverified: 0,
+            failed: 0,
+        };
+
+        for ns in datastore.recursive_iter_backup_ns_ok(BackupNamespace::root(), None)? {
+            for group in datastore.iter_backup_groups_ok(ns)? {
+                for info in group.list_backups()? {
+                    if !info.is_finished() {
+                        continue;
+                    }
+                    let manifest = match info.backup_dir.load_manifest() {
+                        Ok((manifest, _)) => manifest,
+                        Err(_) => continue,
+                    };
+                    let verify = manifest.unprotected["verify_state"].clone();
+                    match serde_json::from_value::<SnapshotVerifyState>(verify) {
+                        Ok(verify) if verify.state == VerifyState::Failed => status.failed += 1,
+                        Ok(_) => (),
+                        Err(_) => status.unverified += 1,
+                    }
+                }
+            }
+        }
+
+        if status.unverified > 0 || status.failed > 0 {
+            list.push(status);
+        }
+    }
+
+    Ok(list)
+}
+
+fn collect_stale_sync_jobs(now: i64) -> Result<Vec<DigestStaleSyncJob>, Error> {
+    let (config, _digest) = pbs_config::sync::config()?;
+    let jobs: Vec<SyncJobConfig> = config.convert_to_typed_array("sync")?;
+
+    let mut list = Vec::new();
+
+    for job in jobs {
+        let schedule = match &job.schedule {
+            Some(schedule) => schedule,
+            None => continue,
+        };
+
+        let history = jobstate::read_job_history("syncjob", &job.id)?;
+        let last_success = history
+            .runs
+            .iter()
+            .find(|run| run.success)
+            .map(|run| run.starttime);
+
+        let stale = match last_success {
+            Some(last) => {
+                let event: CalendarEvent = schedule.parse()?;
+                match event.compute_next_event(last)? {
+                    Some(next) => next + SYNC_JOB_STALE_GRACE < now,
+                    None => false,
+                }
+            }
+            // only report jobs which ran, but never successfully
+            None => !history.runs.is_empty(),
+        };
+
+        if stale {
+            list.push(DigestStaleSyncJob {
+                id: job.id,
+                store: job.store,
+                remote: job.remote,
+                last_success: last_success.map(format_time),
+            });
+        }
+    }
+
+    Ok(list)
+}
+
+/// Collect the failed tasks since `since` and all currently pending issues.
+pub fn collect_digest(worker: &WorkerTask, since: i64) -> Result<Digest, Error> {
+    let now = proxmox_time::epoch_i64();
+
+    Ok(Digest {
+        since: format_time(since),
+        failed_tasks: collect_failed_tasks(since)?,
+        verify_status: collect_verify_status(worker)?,
+        stale_sync_jobs: collect_stale_sync_jobs(now)?,
+    })
+}
+
+/// Runs the digest job, sending a single email about all failed tasks since the last digest and
+/// pending issues.
+pub fn do_digest_job(
+    mut job: Job,
+    since: i64,
+    notify_user: Userid,
+    schedule: Option<String>,
+    to_stdout: bool,
+) -> Result<String, Error> {
+    let worker_type = job.jobtype().to_string();
+    let upid_str = WorkerTask::new_thread(
+        &worker_type,
+        None,
+        Authid::root_auth_id().to_string(),
+        to_stdout,
+        move |worker| {
+            job.start(&worker.upid().to_string())?;
+
+            if let Some(event_str) = schedule {
+                task_log!(worker, "task triggered by schedule '{}'", event_str);
+            }
+
+            let result = try_block!({
+                let email = match crate::server::lookup_user_email(&notify_user) {
+                    Some(email) => email,
+                    None => anyhow::bail!("no email address configured for '{}'", notify_user),
+                };
+
+                task_log!(
+                    worker,
+                    "collecting failed tasks since {}",
+                    format_time(since)
+                );
+                let digest = collect_digest(&worker, since)?;
+
+                task_log!(
+                    worker,
+                    "found {} failed tasks, {} datastores with unverified snapshots, {} stale sync jobs",
+                    digest.failed_tasks.len(),
+                    digest.verify_status.len(),
+                    digest.stale_sync_jobs.len(),
+                );
+
+                crate::server::send_digest_mail(&email, &digest)?;
+                task_log!(worker, "sent digest to {}", email);
+
+                Ok(())
+            });
+
+            let status = worker.create_state(&result);
+
+            if let Err(err) = job.finish(status) {
+                eprintln!("could not finish job state for {}: {}", job.jobtype(), err);
+            }
+
+            result
+        },
+    )?;
+
+    Ok(upid_str)
+}
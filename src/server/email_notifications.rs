@@ -1,5 +1,5 @@
-use anyhow::Error;
-use serde_json::json;
+use anyhow::{bail, Error};
+use serde_json::{json, Value};
 
 use handlebars::{
     Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError, TemplateError,
@@ -10,10 +10,13 @@ use proxmox_schema::ApiType;
 use proxmox_sys::email::sendmail;
 
 use pbs_api_types::{
-    APTUpdateInfo, DataStoreConfig, DatastoreNotify, GarbageCollectionStatus, HumanByte, Notify,
-    SyncJobConfig, TapeBackupJobSetup, User, Userid, VerificationJobConfig,
+    APTUpdateInfo, AlertRule, DataStoreConfig, DatastoreNotify, ExpiringAccount,
+    GarbageCollectionStatus, HumanByte, Notify, SyncJobConfig, TapeBackupJobSetup, User, Userid,
+    VerificationJobConfig, ZpoolHealth,
 };
 
+use crate::server::{Alert, Digest, ExpiringCertificate};
+
 const GC_OK_TEMPLATE: &str = r###"
 
 Datastore:            {{datastore}}
@@ -24,7 +27,7 @@ Removed garbage:      {{human-bytes status.removed-bytes}}
 Removed chunks:       {{status.removed-chunks}}
 Removed bad chunks:   {{status.removed-bad}}
 
-Leftover bad chunks:  {{status.still-bad}}
+Leftover bad chunks:  {{status.still-bad}} ({{human-bytes status.still-bad-bytes}})
 Pending removals:     {{human-bytes status.pending-bytes}} (in {{status.pending-chunks}} chunks)
 
 Original Data usage:  {{human-bytes status.index-data-bytes}}
@@ -34,6 +37,7 @@ On-Disk chunks:       {{status.disk-chunks}}
 Deduplication Factor: {{deduplication-factor}}
 
 Garbage collection successful.
+{{> full_estimate_partial}}
 
 
 Please visit the web interface for further details:
@@ -47,6 +51,7 @@ const GC_ERR_TEMPLATE: &str = r###"
 Datastore: {{datastore}}
 
 Garbage collection failed: {{error}}
+{{> full_estimate_partial}}
 
 
 Please visit the web interface for further details:
@@ -95,6 +100,7 @@ Remote:       {{job.remote}}
 Remote Store: {{job.remote-store}}
 
 Synchronization successful.
+{{> full_estimate_partial}}
 
 
 Please visit the web interface for further details:
@@ -111,6 +117,7 @@ Remote:       {{job.remote}}
 Remote Store: {{job.remote-store}}
 
 Synchronization failed: {{error}}
+{{> full_estimate_partial}}
 
 
 Please visit the web interface for further details:
@@ -222,6 +229,13 @@ Please visit the web interface for further details:
 
 "###;
 
+const FULL_ESTIMATE_PARTIAL: &str = r###"{{#if full-estimate}}
+Warning: the datastore is estimated to be full in {{full-estimate.days}} days ({{full-estimate.date}}).
+{{/if}}"###;
+
+/// Datastores estimated to be full in less days are mentioned in GC and sync notifications.
+const FULL_ESTIMATE_WARNING_DAYS: f64 = 30.0;
+
 const ACME_CERTIFICATE_ERR_RENEWAL: &str = r###"
 
 Proxmox Backup Server was not able to renew a TLS certificate.
@@ -234,6 +248,131 @@ Please visit the web interface for further details:
 
 "###;
 
+const CERTIFICATE_EXPIRY_TEMPLATE: &str = r###"
+
+The following TLS certificates configured on Proxmox Backup Server expire soon or expired:
+
+{{#each certificates}}
+{{description}}: {{subject}}
+  Fingerprint: {{fingerprint}}
+  {{#if expired}}Expired{{else}}Expires{{/if}} on {{expire}}
+
+{{/each}}
+{{#if proxy}}
+{{#if acme}}
+The proxy certificate is managed by ACME, but could not be renewed. Please check
+the log of the last certificate renewal task.
+{{else}}
+The proxy certificate is not managed by ACME. Please upload a new certificate or
+configure ACME to renew it automatically.
+{{/if}}
+
+Please visit the web interface for further details:
+
+<https://{{fqdn}}:{{port}}/#pbsCertificateConfiguration>
+
+{{/if}}
+{{#if remote}}
+The CA certificates of remotes are used to verify their TLS certificates. Please
+update them in the remote configuration:
+
+<https://{{fqdn}}:{{port}}/#pbsRemoteView>
+
+{{/if}}
+
+"###;
+
+const ALERT_TEMPLATE: &str = r###"
+
+Alert rule '{{rule}}' ({{type}}) matched:
+{{#each alerts}}
+  {{this~}}
+{{/each}}
+
+{{#if comment}}
+Rule comment: {{comment}}
+
+{{/if}}
+Please visit the web interface for further details:
+
+<https://{{fqdn}}:{{port}}/#pbsServerAdministration:tasks>
+
+"###;
+
+const ZPOOL_HEALTH_TEMPLATE: &str = r###"
+
+ZFS pool '{{pool}}' changed its state{{#if previous}} from {{previous}}{{/if}} to {{health}}.
+
+Affected datastores: {{#each datastores}}{{this}}{{#unless @last}}, {{/unless}}{{/each}}
+
+{{#if online}}
+The pool is fully operational again.
+{{else}}
+The pool lost redundancy or is not operational, check 'zpool status {{pool}}'
+for details.
+{{/if}}
+{{#if status}}
+
+Status: {{status}}
+{{/if}}
+
+Please visit the web interface for further details:
+
+<https://{{fqdn}}:{{port}}/#pbsStorageAndDiskPanel:zfsstorage>
+
+"###;
+
+const DIGEST_TEMPLATE: &str = r###"
+
+Summary since {{since}}:
+
+Failed tasks:              {{failed-task-count}}
+Datastores with unverified or failed snapshots: {{verify-count}}
+Stale sync jobs:           {{stale-sync-count}}
+{{#if failed-tasks}}
+
+Failed tasks:
+{{#each failed-tasks}}
+  {{starttime}} {{worker-type}}{{#if worker-id}} ({{worker-id}}){{/if}}: {{error}}
+{{/each}}
+{{/if}}
+{{#if verify-status}}
+
+Snapshot verification:
+{{#each verify-status}}
+  {{store}}: {{unverified}} unverified, {{failed}} failed
+{{/each}}
+{{/if}}
+{{#if stale-sync-jobs}}
+
+Stale sync jobs:
+{{#each stale-sync-jobs}}
+  {{id}} ({{remote}} -> {{store}}), last successful run: {{#if last-success}}{{last-success}}{{else}}never{{/if}}
+{{/each}}
+{{/if}}
+
+Please visit the web interface for further details:
+
+<https://{{fqdn}}:{{port}}/#pbsServerAdministration:tasks>
+
+"###;
+
+const USER_EXPIRY_TEMPLATE: &str = r###"
+
+The following accounts of user '{{userid}}' will expire soon:
+{{#each accounts}}
+  {{authid}}: {{expire}}
+{{/each}}
+
+Please contact your administrator to extend the expiration date if you still
+need access.
+
+Please visit the web interface for further details:
+
+<https://{{fqdn}}:{{port}}/#pbsAccessControlPanel:users>
+
+"###;
+
 lazy_static::lazy_static! {
 
     static ref HANDLEBARS: Handlebars<'static> = {
@@ -246,6 +385,8 @@ lazy_static::lazy_static! {
             hb.register_helper("human-bytes", Box::new(handlebars_humam_bytes_helper));
             hb.register_helper("relative-percentage", Box::new(handlebars_relative_percentage_helper));
 
+            hb.register_partial("full_estimate_partial", FULL_ESTIMATE_PARTIAL)?;
+
             hb.register_template_string("gc_ok_template", GC_OK_TEMPLATE)?;
             hb.register_template_string("gc_err_template", GC_ERR_TEMPLATE)?;
 
@@ -264,6 +405,15 @@ lazy_static::lazy_static! {
             hb.register_template_string("package_update_template", PACKAGE_UPDATES_TEMPLATE)?;
 
             hb.register_template_string("certificate_renewal_err_template", ACME_CERTIFICATE_ERR_RENEWAL)?;
+            hb.register_template_string("certificate_expiry_template", CERTIFICATE_EXPIRY_TEMPLATE)?;
+
+            hb.register_template_string("alert_template", ALERT_TEMPLATE)?;
+
+            hb.register_template_string("zpool_health_template", ZPOOL_HEALTH_TEMPLATE)?;
+
+            hb.register_template_string("digest_template", DIGEST_TEMPLATE)?;
+
+            hb.register_template_string("user_expiry_template", USER_EXPIRY_TEMPLATE)?;
 
             Ok(())
         });
@@ -321,13 +471,8 @@ pub fn send_gc_status(
     status: &GarbageCollectionStatus,
     result: &Result<(), Error>,
 ) -> Result<(), Error> {
-    match notify.gc {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
-            if notify == Notify::Never || (result.is_ok() && notify == Notify::Error) {
-                return Ok(());
-            }
-        }
+    if !Notify::should_notify(None, notify.gc, result.is_ok()) {
+        return Ok(());
     }
 
     let (fqdn, port) = get_server_url();
@@ -335,6 +480,7 @@ pub fn send_gc_status(
         "datastore": datastore,
         "fqdn": fqdn,
         "port": port,
+        "full-estimate": full_estimate(datastore),
     });
 
     let text = match result {
@@ -396,13 +542,8 @@ pub fn send_verify_status(
         }
     };
 
-    match notify.verify {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
-            if notify == Notify::Never || (result_is_ok && notify == Notify::Error) {
-                return Ok(());
-            }
-        }
+    if !Notify::should_notify(job.notify, notify.verify, result_is_ok) {
+        return Ok(());
     }
 
     let subject = match result {
@@ -418,6 +559,7 @@ pub fn send_verify_status(
 pub fn send_prune_status(
     store: &str,
     jobname: &str,
+    job_notify: Option<Notify>,
     result: &Result<(), Error>,
 ) -> Result<(), Error> {
     let (email, notify) = match lookup_datastore_notify_settings(store) {
@@ -425,13 +567,8 @@ pub fn send_prune_status(
         (None, _) => return Ok(()),
     };
 
-    match notify.prune {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
-            if notify == Notify::Never || (result.is_ok() && notify == Notify::Error) {
-                return Ok(());
-            }
-        }
+    if !Notify::should_notify(job_notify, notify.prune, result.is_ok()) {
+        return Ok(());
     }
 
     let (fqdn, port) = get_server_url();
@@ -466,13 +603,8 @@ pub fn send_sync_status(
     job: &SyncJobConfig,
     result: &Result<(), Error>,
 ) -> Result<(), Error> {
-    match notify.sync {
-        None => { /* send notifications by default */ }
-        Some(notify) => {
-            if notify == Notify::Never || (result.is_ok() && notify == Notify::Error) {
-                return Ok(());
-            }
-        }
+    if !Notify::should_notify(job.notify, notify.sync, result.is_ok()) {
+        return Ok(());
     }
 
     let (fqdn, port) = get_server_url();
@@ -480,6 +612,7 @@ pub fn send_sync_status(
         "job": job,
         "fqdn": fqdn,
         "port": port,
+        "full-estimate": full_estimate(&job.store),
     });
 
     let text = match result {
@@ -513,6 +646,11 @@ pub fn send_tape_backup_status(
     result: &Result<(), Error>,
     summary: TapeBackupJobSummary,
 ) -> Result<(), Error> {
+    let (_, notify) = lookup_datastore_notify_settings(&job.store);
+    if !Notify::should_notify(job.notify, notify.tape, result.is_ok()) {
+        return Ok(());
+    }
+
     let (fqdn, port) = get_server_url();
     let duration: proxmox_time::TimeSpan = summary.duration.into();
     let mut data = json!({
@@ -641,6 +779,260 @@ pub fn send_certificate_renewal_mail(result: &Result<(), Error>) -> Result<(), E
     Ok(())
 }
 
+/// send email to warn about the upcoming expiration of configured certificates
+pub fn send_certificate_expiry_mail(
+    certificates: &[&ExpiringCertificate],
+    acme: bool,
+) -> Result<(), Error> {
+    let email = match lookup_user_email(Userid::root_userid()) {
+        Some(email) => email,
+        None => bail!("no email address configured for user 'root@pam'"),
+    };
+
+    let (fqdn, port) = get_server_url();
+
+    let now = proxmox_time::epoch_i64();
+    let expired = certificates.iter().any(|cert| cert.expire <= now);
+
+    let list: Vec<Value> = certificates
+        .iter()
+        .map(|cert| {
+            let expire = proxmox_time::strftime_local("%F %T", cert.expire)
+                .unwrap_or_else(|_| cert.expire.to_string());
+            json!({
+                "description": cert.description,
+                "subject": cert.subject,
+                "fingerprint": cert.fingerprint,
+                "expire": expire,
+                "expired": cert.expire <= now,
+            })
+        })
+        .collect();
+
+    let text = HANDLEBARS.render(
+        "certificate_expiry_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "certificates": list,
+            "proxy": certificates.iter().any(|cert| cert.proxy),
+            "remote": certificates.iter().any(|cert| !cert.proxy),
+            "acme": acme,
+        }),
+    )?;
+
+    let subject = if expired {
+        "Certificate expired"
+    } else {
+        "Certificate expires soon"
+    };
+
+    send_job_status_mail(&email, subject, &text)
+}
+
+/// send email for alerts which started to match an alert rule
+pub fn send_alert_notification(rule: &AlertRule, alerts: &[&Alert]) -> Result<(), Error> {
+    let userid = rule
+        .notify_user
+        .as_ref()
+        .unwrap_or_else(Userid::root_userid);
+
+    let email = match lookup_user_email(userid) {
+        Some(email) => email,
+        None => bail!("no email address configured for user '{}'", userid),
+    };
+
+    let (fqdn, port) = get_server_url();
+
+    let rule_type = serde_json::to_value(rule.ty)?;
+
+    let text = HANDLEBARS.render(
+        "alert_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "rule": rule.id,
+            "type": rule_type,
+            "comment": rule.comment,
+            "alerts": alerts.iter().map(|alert| &alert.message).collect::<Vec<_>>(),
+        }),
+    )?;
+
+    let subject = if alerts.len() == 1 {
+        format!("Alert '{}': {}", rule.id, alerts[0].message)
+    } else {
+        format!("Alert '{}': {} new alerts", rule.id, alerts.len())
+    };
+
+    send_job_status_mail(&email, &subject, &text)
+}
+
+/// send email when the health of a ZFS pool backing datastores changed
+pub fn send_zpool_health_notification(
+    health: &ZpoolHealth,
+    previous: Option<&str>,
+    datastores: &[String],
+) -> Result<(), Error> {
+    let mut emails: Vec<String> = datastores
+        .iter()
+        .filter_map(|store| lookup_datastore_notify_settings(store).0)
+        .collect();
+    emails.sort();
+    emails.dedup();
+
+    if emails.is_empty() {
+        bail!(
+            "no email address configured for datastores on zpool '{}'",
+            health.pool
+        );
+    }
+
+    let (fqdn, port) = get_server_url();
+
+    let text = HANDLEBARS.render(
+        "zpool_health_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "pool": health.pool,
+            "health": health.health,
+            "previous": previous,
+            "status": health.status,
+            "online": health.is_online(),
+            "datastores": datastores,
+        }),
+    )?;
+
+    let subject = format!("ZFS pool '{}' is {}", health.pool, health.health);
+
+    for email in emails {
+        send_job_status_mail(&email, &subject, &text)?;
+    }
+
+    Ok(())
+}
+
+/// send digest email about failed tasks and pending issues
+pub fn send_digest_mail(email: &str, digest: &Digest) -> Result<(), Error> {
+    let (fqdn, port) = get_server_url();
+
+    let failed_tasks: Vec<Value> = digest
+        .failed_tasks
+        .iter()
+        .map(|task| {
+            json!({
+                "starttime": task.starttime,
+                "worker-type": task.worker_type,
+                "worker-id": task.worker_id,
+                "error": task.error,
+            })
+        })
+        .collect();
+
+    let stale_sync_jobs: Vec<Value> = digest
+        .stale_sync_jobs
+        .iter()
+        .map(|job| {
+            json!({
+                "id": job.id,
+                "store": job.store,
+                "remote": job.remote,
+                "last-success": job.last_success,
+            })
+        })
+        .collect();
+
+    let text = HANDLEBARS.render(
+        "digest_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "since": digest.since,
+            "failed-task-count": failed_tasks.len(),
+            "verify-count": digest.verify_status.len(),
+            "stale-sync-count": stale_sync_jobs.len(),
+            "failed-tasks": failed_tasks,
+            "verify-status": digest.verify_status,
+            "stale-sync-jobs": stale_sync_jobs,
+        }),
+    )?;
+
+    let issues =
+        digest.failed_tasks.len() + digest.verify_status.len() + digest.stale_sync_jobs.len();
+
+    let subject = if issues == 0 {
+        "Digest: no failed tasks or pending issues".to_string()
+    } else {
+        format!(
+            "Digest: {} failed tasks, {} pending issues",
+            digest.failed_tasks.len(),
+            digest.verify_status.len() + digest.stale_sync_jobs.len()
+        )
+    };
+
+    send_job_status_mail(email, &subject, &text)
+}
+
+/// send email to warn a user about the upcoming expiration of their account or API tokens
+pub fn send_user_expiry_notification(
+    userid: &Userid,
+    accounts: &[&ExpiringAccount],
+) -> Result<(), Error> {
+    let email = match lookup_user_email(userid) {
+        Some(email) => email,
+        None => bail!("no email address configured for user '{}'", userid),
+    };
+
+    let (fqdn, port) = get_server_url();
+
+    let accounts: Vec<Value> = accounts
+        .iter()
+        .map(|account| {
+            let expire = proxmox_time::strftime_local("%F %T", account.expire)
+                .unwrap_or_else(|_| account.expire.to_string());
+            json!({
+                "authid": account.authid,
+                "expire": expire,
+            })
+        })
+        .collect();
+
+    let text = HANDLEBARS.render(
+        "user_expiry_template",
+        &json!({
+            "fqdn": fqdn,
+            "port": port,
+            "userid": userid,
+            "accounts": accounts,
+        }),
+    )?;
+
+    let subject = format!("Accounts of user '{}' expire soon", userid);
+
+    send_job_status_mail(&email, &subject, &text)
+}
+
+/// Returns the capacity estimate of a datastore for notifications, if it will be full soon.
+fn full_estimate(store: &str) -> Value {
+    let usage = match crate::api2::status::datastore_usage_history(store) {
+        Ok(Some(usage)) => usage,
+        _ => return Value::Null,
+    };
+
+    match usage.days_until_full(proxmox_time::epoch_i64()) {
+        Some(days) if days < FULL_ESTIMATE_WARNING_DAYS => {
+            let date = usage
+                .estimated_full_date
+                .and_then(|time| proxmox_time::strftime_local("%F", time).ok());
+            json!({
+                "days": days as u64,
+                "date": date,
+            })
+        }
+        _ => Value::Null,
+    }
+}
+
 /// Lookup users email address
 pub fn lookup_user_email(userid: &Userid) -> Option<String> {
     if let Ok(user_config) = pbs_config::user::cached_config() {
@@ -661,6 +1053,7 @@ pub fn lookup_datastore_notify_settings(store: &str) -> (Option<String>, Datasto
         verify: None,
         sync: None,
         prune: None,
+        tape: None,
     };
 
     let (config, _digest) = match pbs_config::datastore::config() {
@@ -752,4 +1145,13 @@ fn test_template_register() {
     assert!(HANDLEBARS.has_template("package_update_template"));
 
     assert!(HANDLEBARS.has_template("certificate_renewal_err_template"));
+    assert!(HANDLEBARS.has_template("certificate_expiry_template"));
+
+    assert!(HANDLEBARS.has_template("alert_template"));
+
+    assert!(HANDLEBARS.has_template("zpool_health_template"));
+
+    assert!(HANDLEBARS.has_template("digest_template"));
+
+    assert!(HANDLEBARS.has_template("user_expiry_template"));
 }
@@ -46,12 +46,14 @@ use proxmox_sys::fs::{create_path, file_read_optional_string, replace_file, Crea
 
 use proxmox_time::CalendarEvent;
 
-use pbs_api_types::{JobScheduleStatus, UPID};
+use pbs_api_types::{JobHistory, JobRunResult, JobScheduleStatus, UPID};
 use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
 use pbs_config::{open_backup_lockfile, BackupLockGuard};
 
 use proxmox_rest_server::{upid_read_status, worker_is_active_local, TaskState};
 
+use crate::server::{send_snmp_trap, SnmpTrap};
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 /// Represents the State of a specific Job
@@ -74,11 +76,16 @@ pub struct Job {
     jobname: String,
     /// The State of the job
     pub state: JobState,
+    transferred_bytes: Option<u64>,
+    snapshot_count: Option<u64>,
     _lock: BackupLockGuard,
 }
 
 const JOB_STATE_BASEDIR: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/jobstates");
 
+/// How many runs are kept in the history file of a job
+const JOB_HISTORY_MAX_ENTRIES: usize = 100;
+
 /// Create jobstate stat dir with correct permission
 pub fn create_jobstate_dir() -> Result<(), Error> {
     let backup_user = pbs_config::backup_user()?;
@@ -99,6 +106,74 @@ fn get_path(jobtype: &str, jobname: &str) -> PathBuf {
     path
 }
 
+fn get_history_path(jobtype: &str, jobname: &str) -> PathBuf {
+    let mut path = PathBuf::from(JOB_STATE_BASEDIR);
+    path.push(format!("{}-{}.history", jobtype, jobname));
+    path
+}
+
+fn read_history_entries(path: &Path) -> Result<Vec<JobRunResult>, Error> {
+    match file_read_optional_string(path)? {
+        Some(data) => Ok(parse_history_entries(&data)),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_history_entries(data: &str) -> Vec<JobRunResult> {
+    // ignore unparsable lines instead of losing the whole history
+    data.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Serializes the runs one per line, keeping only the newest `JOB_HISTORY_MAX_ENTRIES`
+fn format_history_entries(runs: &[JobRunResult]) -> Result<String, Error> {
+    let skip = runs.len().saturating_sub(JOB_HISTORY_MAX_ENTRIES);
+
+    let mut data = String::new();
+    for run in runs.iter().skip(skip) {
+        data.push_str(&serde_json::to_string(run)?);
+        data.push('\n');
+    }
+    Ok(data)
+}
+
+/// Returns the recorded runs of a job, newest first, with aggregated statistics
+/// Note that this is not locked
+pub fn read_job_history(jobtype: &str, jobname: &str) -> Result<JobHistory, Error> {
+    let mut runs = read_history_entries(&get_history_path(jobtype, jobname))?;
+    runs.reverse();
+    Ok(JobHistory::from_runs(runs))
+}
+
+/// Returns the names of all jobs of `jobtype` with a recorded run history
+pub fn list_job_history_names(jobtype: &str) -> Result<Vec<String>, Error> {
+    let prefix = format!("{}-", jobtype);
+    let mut list = Vec::new();
+
+    let entries = match std::fs::read_dir(JOB_STATE_BASEDIR) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+        Err(err) => bail!("unable to read jobstate dir - {}", err),
+    };
+
+    for entry in entries {
+        let file_name = entry?.file_name();
+        let file_name = match file_name.to_str() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+        if let Some(name) = file_name
+            .strip_prefix(&prefix)
+            .and_then(|name| name.strip_suffix(".history"))
+        {
+            list.push(name.to_string());
+        }
+    }
+
+    Ok(list)
+}
+
 fn get_lock<P>(path: P) -> Result<BackupLockGuard, Error>
 where
     P: AsRef<Path>,
@@ -117,6 +192,11 @@ pub fn remove_state_file(jobtype: &str, jobname: &str) -> Result<(), Error> {
             bail!("cannot remove statefile for {jobtype} - {jobname}: {err}");
         }
     }
+    if let Err(err) = std::fs::remove_file(get_history_path(jobtype, jobname)) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            bail!("cannot remove history file for {jobtype} - {jobname}: {err}");
+        }
+    }
     path.set_extension("lck");
     if let Err(err) = std::fs::remove_file(&path) {
         if err.kind() != std::io::ErrorKind::NotFound {
@@ -237,6 +317,8 @@ impl Job {
             state: JobState::Created {
                 time: proxmox_time::epoch_i64(),
             },
+            transferred_bytes: None,
+            snapshot_count: None,
             _lock,
         })
     }
@@ -258,6 +340,23 @@ impl Job {
     /// Finish the job and update the statefile accordingly with the given taskstate
     /// Fails if the job was not yet started
     pub fn finish(&mut self, state: TaskState) -> Result<(), Error> {
+        if let TaskState::Error { message, .. } = &state {
+            let trap = SnmpTrap::JobFailed {
+                jobtype: &self.jobtype,
+                jobname: &self.jobname,
+                error: message,
+            };
+            if let Err(err) = send_snmp_trap(&trap) {
+                log::warn!("could not send SNMP trap: {}", err);
+            }
+        }
+
+        self.finish_without_trap(state)
+    }
+
+    /// Like [`Job::finish`], but does not send a job failure SNMP trap, for jobs which report
+    /// their failure with a more specific trap.
+    pub fn finish_without_trap(&mut self, state: TaskState) -> Result<(), Error> {
         let upid = match &self.state {
             JobState::Created { .. } => bail!("cannot finish when not started"),
             JobState::Started { upid } => upid,
@@ -265,6 +364,15 @@ impl Job {
         }
         .to_string();
 
+        if let Err(err) = self.append_history(&upid, &state) {
+            log::warn!(
+                "could not record run of {} {}: {}",
+                self.jobtype,
+                self.jobname,
+                err
+            );
+        }
+
         self.state = JobState::Finished {
             upid,
             state,
@@ -274,6 +382,16 @@ impl Job {
         self.write_state()
     }
 
+    /// Set job type specific statistics, which get recorded in the history on 'finish'
+    pub fn set_run_statistics(
+        &mut self,
+        transferred_bytes: Option<u64>,
+        snapshot_count: Option<u64>,
+    ) {
+        self.transferred_bytes = transferred_bytes;
+        self.snapshot_count = snapshot_count;
+    }
+
     pub fn jobtype(&self) -> &str {
         &self.jobtype
     }
@@ -282,20 +400,46 @@ impl Job {
         &self.jobname
     }
 
-    fn write_state(&mut self) -> Result<(), Error> {
-        let serialized = serde_json::to_string(&self.state)?;
-        let path = get_path(&self.jobtype, &self.jobname);
+    fn append_history(&self, upid: &str, state: &TaskState) -> Result<(), Error> {
+        let starttime = upid
+            .parse::<UPID>()
+            .map_err(|err| format_err!("could not parse upid: {}", err))?
+            .starttime;
 
+        let run = JobRunResult {
+            upid: upid.to_string(),
+            starttime,
+            endtime: state.endtime(),
+            state: state.to_string(),
+            success: matches!(state, TaskState::OK { .. } | TaskState::Warning { .. }),
+            transferred_bytes: self.transferred_bytes,
+            snapshot_count: self.snapshot_count,
+        };
+
+        let path = get_history_path(&self.jobtype, &self.jobname);
+        let mut runs = read_history_entries(&path)?;
+        runs.push(run);
+        let data = format_history_entries(&runs)?;
+
+        replace_file(path, data.as_bytes(), Self::file_options()?, false)
+    }
+
+    fn file_options() -> Result<CreateOptions, Error> {
         let backup_user = pbs_config::backup_user()?;
         let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
         // set the correct owner/group/permissions while saving file
         // owner(rw) = backup, group(r)= backup
-        let options = CreateOptions::new()
+        Ok(CreateOptions::new()
             .perm(mode)
             .owner(backup_user.uid)
-            .group(backup_user.gid);
+            .group(backup_user.gid))
+    }
 
-        replace_file(path, serialized.as_bytes(), options, false)
+    fn write_state(&mut self) -> Result<(), Error> {
+        let serialized = serde_json::to_string(&self.state)?;
+        let path = get_path(&self.jobtype, &self.jobname);
+
+        replace_file(path, serialized.as_bytes(), Self::file_options()?, false)
     }
 }
 
@@ -340,3 +484,65 @@ pub fn compute_schedule_status(
 
     Ok(status)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(starttime: i64, success: bool) -> JobRunResult {
+        JobRunResult {
+            upid: format!("run-{starttime}"),
+            starttime,
+            endtime: starttime + 10,
+            state: if success { "OK" } else { "some error" }.to_string(),
+            success,
+            transferred_bytes: None,
+            snapshot_count: Some(starttime as u64),
+        }
+    }
+
+    #[test]
+    fn test_history_roundtrip() -> Result<(), Error> {
+        let runs = vec![run(1, true), run(2, false)];
+        let data = format_history_entries(&runs)?;
+        assert_eq!(data.lines().count(), 2);
+
+        let parsed = parse_history_entries(&data);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].upid, "run-1");
+        assert!(parsed[0].success);
+        assert_eq!(parsed[1].upid, "run-2");
+        assert!(!parsed[1].success);
+        assert_eq!(parsed[1].snapshot_count, Some(2));
+        assert_eq!(parsed[1].transferred_bytes, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_skips_invalid_lines() -> Result<(), Error> {
+        let mut data = format_history_entries(&[run(1, true)])?;
+        data.push_str("{ truncated\n");
+        data.push_str(&format_history_entries(&[run(2, true)])?);
+
+        let parsed = parse_history_entries(&data);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].starttime, 1);
+        assert_eq!(parsed[1].starttime, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_keeps_newest_entries() -> Result<(), Error> {
+        let count = JOB_HISTORY_MAX_ENTRIES as i64 + 5;
+        let runs: Vec<_> = (0..count).map(|i| run(i, true)).collect();
+
+        let parsed = parse_history_entries(&format_history_entries(&runs)?);
+        assert_eq!(parsed.len(), JOB_HISTORY_MAX_ENTRIES);
+        assert_eq!(parsed[0].starttime, 5);
+        assert_eq!(parsed.last().unwrap().starttime, count - 1);
+
+        Ok(())
+    }
+}
@@ -22,9 +22,33 @@ pub use prune_job::*;
 mod gc_job;
 pub use gc_job::*;
 
+mod chunk_check;
+pub use chunk_check::*;
+
 mod email_notifications;
 pub use email_notifications::*;
 
+mod snmp;
+pub use snmp::*;
+
+mod alerts;
+pub use alerts::*;
+
+mod zpool_health;
+pub use zpool_health::*;
+
+mod digest_job;
+pub use digest_job::*;
+
+mod standby;
+pub use standby::*;
+
+mod user_expiry;
+pub use user_expiry::*;
+
+mod cert_expiry;
+pub use cert_expiry::*;
+
 mod report;
 pub use report::*;
 
@@ -32,7 +56,10 @@ pub mod ticket;
 
 pub mod auth;
 
+pub mod transfer_accounting;
+
 pub(crate) mod pull;
+pub(crate) mod push;
 
 pub(crate) async fn reload_proxy_certificate() -> Result<(), Error> {
     let proxy_pid = proxmox_rest_server::read_pid(pbs_buildcfg::PROXMOX_BACKUP_PROXY_PID_FN)?;
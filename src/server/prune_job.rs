@@ -5,8 +5,8 @@ use anyhow::Error;
 use proxmox_sys::{task_log, task_warn};
 
 use pbs_api_types::{
-    print_store_and_ns, Authid, KeepOptions, Operation, PruneJobOptions, MAX_NAMESPACE_DEPTH,
-    PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE,
+    print_store_and_ns, Authid, KeepOptions, Notify, Operation, PruneJobOptions,
+    MAX_NAMESPACE_DEPTH, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE,
 };
 use pbs_datastore::prune::compute_prune_info;
 use pbs_datastore::DataStore;
@@ -21,7 +21,7 @@ pub fn prune_datastore(
     prune_options: PruneJobOptions,
     datastore: Arc<DataStore>,
     dry_run: bool,
-) -> Result<(), Error> {
+) -> Result<u64, Error> {
     let store = &datastore.name();
     let max_depth = prune_options.max_depth.unwrap_or(MAX_NAMESPACE_DEPTH);
     let depth = match max_depth {
@@ -38,6 +38,7 @@ pub fn prune_datastore(
     }
 
     let keep_all = !prune_options.keeps_something();
+    let mut removed = 0;
 
     if keep_all {
         task_log!(worker, "No prune selection - keeping all files.");
@@ -55,6 +56,9 @@ pub fn prune_datastore(
         Some(&auth_id),
     )? {
         let group = group?;
+        if !prune_options.matches_group(group.group()) {
+            continue;
+        }
         let ns = group.backup_ns();
         let list = group.list_backups()?;
 
@@ -80,15 +84,18 @@ pub fn prune_datastore(
                 info.backup_dir.backup_time_string()
             );
             if !keep && !dry_run {
-                if let Err(err) = datastore.remove_backup_dir(ns, info.backup_dir.as_ref(), false) {
-                    let path = info.backup_dir.relative_path();
-                    task_warn!(worker, "failed to remove dir {path:?}: {err}");
+                match datastore.remove_backup_dir(ns, info.backup_dir.as_ref(), false) {
+                    Ok(()) => removed += 1,
+                    Err(err) => {
+                        let path = info.backup_dir.relative_path();
+                        task_warn!(worker, "failed to remove dir {path:?}: {err}");
+                    }
                 }
             }
         }
     }
 
-    Ok(())
+    Ok(removed)
 }
 
 pub(crate) fn cli_prune_options_string(options: &PruneJobOptions) -> String {
@@ -104,6 +111,12 @@ pub(crate) fn cli_prune_options_string(options: &PruneJobOptions) -> String {
         opts.push(format!("--max-depth {max_depth}"));
     }
 
+    if let Some(group_filter) = &options.group_filter {
+        for filter in group_filter {
+            opts.push(format!("--group-filter {filter}"));
+        }
+    }
+
     cli_keep_options(&mut opts, &options.keep);
 
     opts.join(" ")
@@ -129,6 +142,7 @@ pub fn do_prune_job(
     mut job: Job,
     prune_options: PruneJobOptions,
     store: String,
+    notify: Option<Notify>,
     auth_id: &Authid,
     schedule: Option<String>,
 ) -> Result<String, Error> {
@@ -156,7 +170,8 @@ pub fn do_prune_job(
                 task_log!(worker, "task triggered by schedule '{event_str}'");
             }
 
-            let result = prune_datastore(worker.clone(), auth_id, prune_options, datastore, false);
+            let result = prune_datastore(worker.clone(), auth_id, prune_options, datastore, false)
+                .map(|removed| job.set_run_statistics(None, Some(removed)));
 
             let status = worker.create_state(&result);
 
@@ -164,7 +179,9 @@ pub fn do_prune_job(
                 eprintln!("could not finish job state for {}: {err}", job.jobtype());
             }
 
-            if let Err(err) = crate::server::send_prune_status(&store, job.jobname(), &result) {
+            if let Err(err) =
+                crate::server::send_prune_status(&store, job.jobname(), notify, &result)
+            {
                 log::error!("send prune notification failed: {err}");
             }
             result
@@ -1,9 +1,9 @@
 //! Sync datastore from remote server
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::io::{Seek, SeekFrom};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
@@ -11,14 +11,16 @@ use anyhow::{bail, format_err, Error};
 use http::StatusCode;
 use pbs_config::CachedUserInfo;
 use serde_json::json;
+use tokio::sync::Semaphore;
 
 use proxmox_router::HttpError;
 use proxmox_sys::task_log;
 
 use pbs_api_types::{
-    print_store_and_ns, Authid, BackupNamespace, GroupFilter, GroupListItem, NamespaceListItem,
-    Operation, RateLimitConfig, Remote, SnapshotListItem, MAX_NAMESPACE_DEPTH,
-    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP,
+    print_store_and_ns, Authid, BackupContent, BackupNamespace, DatastoreBackendType, GroupFilter,
+    GroupListItem, NamespaceListItem, Operation, RateLimitConfig, Remote, SnapshotListItem,
+    DEFAULT_SYNC_PARALLEL_DOWNLOADS, MAX_NAMESPACE_DEPTH, PRIV_DATASTORE_AUDIT,
+    PRIV_DATASTORE_BACKUP,
 };
 
 use pbs_client::{
@@ -35,9 +37,14 @@ use pbs_datastore::{check_backup_owner, DataStore, StoreProgress};
 use pbs_tools::sha::sha256;
 use proxmox_rest_server::WorkerTask;
 
-use crate::backup::{check_ns_modification_privs, check_ns_privs};
+use crate::backup::{
+    check_ns_modification_privs, check_ns_privs, verify_backup_dir_with_lock, VerifyWorker,
+};
 use crate::tools::parallel_handler::ParallelHandler;
 
+/// Maximum number of archives of a snapshot which are pulled concurrently
+const MAX_PARALLEL_ARCHIVES: usize = 4;
+
 /// Parameters for a pull operation.
 pub(crate) struct PullParameters {
     /// Remote that is pulled from
@@ -60,6 +67,23 @@ pub(crate) struct PullParameters {
     group_filter: Option<Vec<GroupFilter>>,
     /// Rate limits for all transfers from `remote`
     limit: RateLimitConfig,
+    /// Number of chunks downloaded in parallel
+    parallel_downloads: usize,
+    /// Whether to verify newly synced snapshots
+    verify_after_sync: bool,
+    /// Statistics about the data transferred so far
+    stats: Arc<PullStats>,
+    /// Source datastore, if it is on this node and on the same file system as `store`
+    local_source: Option<Arc<DataStore>>,
+}
+
+#[derive(Default)]
+/// Statistics of a pull operation, updated while it is running.
+pub(crate) struct PullStats {
+    /// Bytes of chunk data downloaded from the remote
+    pub transferred_bytes: AtomicU64,
+    /// Number of snapshots synced successfully
+    pub snapshots: AtomicU64,
 }
 
 impl PullParameters {
@@ -79,6 +103,8 @@ impl PullParameters {
         max_depth: Option<usize>,
         group_filter: Option<Vec<GroupFilter>>,
         limit: RateLimitConfig,
+        parallel_downloads: Option<usize>,
+        verify_after_sync: Option<bool>,
     ) -> Result<Self, Error> {
         let store = DataStore::lookup_datastore(store, Some(Operation::Write))?;
 
@@ -91,6 +117,8 @@ impl PullParameters {
         let remote: Remote = remote_config.lookup("remote", remote)?;
 
         let remove_vanished = remove_vanished.unwrap_or(false);
+        let parallel_downloads = parallel_downloads.unwrap_or(DEFAULT_SYNC_PARALLEL_DOWNLOADS);
+        let verify_after_sync = verify_after_sync.unwrap_or(false);
 
         let source = BackupRepository::new(
             Some(remote.config.auth_id.clone()),
@@ -99,6 +127,8 @@ impl PullParameters {
             remote_store.to_string(),
         );
 
+        let local_source = local_source_datastore(&remote, remote_store, &store);
+
         Ok(Self {
             remote,
             remote_ns,
@@ -110,21 +140,105 @@ impl PullParameters {
             max_depth,
             group_filter,
             limit,
+            parallel_downloads,
+            verify_after_sync,
+            stats: Arc::new(PullStats::default()),
+            local_source,
         })
     }
 
+    /// Use `stats` to collect the statistics of the pull operation, e.g. to report them after
+    /// it has finished.
+    pub(crate) fn with_stats(mut self, stats: Arc<PullStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
     /// Creates a new [HttpClient] for accessing the [Remote] that is pulled from.
     pub async fn client(&self) -> Result<HttpClient, Error> {
         crate::api2::config::remote::remote_client(&self.remote, Some(self.limit.clone())).await
     }
 }
 
+/// Limits the number of chunks downloaded in parallel, shared by all archives which are pulled
+/// concurrently.
+#[derive(Clone)]
+struct DownloadLimit {
+    slots: Arc<Semaphore>,
+    max: usize,
+}
+
+impl DownloadLimit {
+    fn new(max: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+}
+
+fn remote_is_local(remote: &Remote) -> bool {
+    let host = remote.config.host.as_str();
+    if host == "localhost"
+        || host == "127.0.0.1"
+        || host == "::1"
+        || host == proxmox_sys::nodename()
+    {
+        return true;
+    }
+
+    // pinned to the certificate of this node
+    match (&remote.config.fingerprint, crate::cert_info()) {
+        (Some(fingerprint), Ok(cert)) => match cert.fingerprint() {
+            Ok(local) => fingerprint.eq_ignore_ascii_case(&local),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns the source datastore if `remote` is this node and the datastore is on the same file
+/// system as the `target`, so that chunks can be cloned instead of downloaded.
+///
+/// Only the chunks get cloned locally, the snapshots and their indices are still read via the
+/// API, so the privileges of the remote's user are still checked.
+fn local_source_datastore(
+    remote: &Remote,
+    remote_store: &str,
+    target: &DataStore,
+) -> Option<Arc<DataStore>> {
+    if !remote_is_local(remote) {
+        return None;
+    }
+
+    let source = DataStore::lookup_datastore(remote_store, Some(Operation::Read)).ok()?;
+
+    if source.backend_type() != DatastoreBackendType::Filesystem
+        || target.backend_type() != DatastoreBackendType::Filesystem
+    {
+        return None;
+    }
+
+    let source_dev = nix::sys::stat::stat(&source.base_path()).ok()?.st_dev;
+    let target_dev = nix::sys::stat::stat(&target.base_path()).ok()?.st_dev;
+
+    if source_dev != target_dev {
+        return None;
+    }
+
+    Some(source)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn pull_index_chunks<I: IndexFile>(
     worker: &WorkerTask,
     chunk_reader: RemoteChunkReader,
     target: Arc<DataStore>,
     index: I,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    local_source: Option<Arc<DataStore>>,
+    download_limit: &DownloadLimit,
+    stats: &PullStats,
 ) -> Result<(), Error> {
     use futures::stream::{self, StreamExt, TryStreamExt};
 
@@ -160,13 +274,17 @@ async fn pull_index_chunks<I: IndexFile>(
     let verify_and_write_channel = verify_pool.channel();
 
     let bytes = Arc::new(AtomicUsize::new(0));
+    let cloned_bytes = Arc::new(AtomicUsize::new(0));
 
     stream
         .map(|info| {
             let target = Arc::clone(&target);
             let chunk_reader = chunk_reader.clone();
             let bytes = Arc::clone(&bytes);
+            let cloned_bytes = Arc::clone(&cloned_bytes);
             let verify_and_write_channel = verify_and_write_channel.clone();
+            let local_source = local_source.clone();
+            let download_slots = Arc::clone(&download_limit.slots);
 
             Ok::<_, Error>(async move {
                 let chunk_exists = proxmox_async::runtime::block_in_place(|| {
@@ -176,6 +294,20 @@ async fn pull_index_chunks<I: IndexFile>(
                     //task_log!(worker, "chunk {} exists {}", pos, hex::encode(digest));
                     return Ok::<_, Error>(());
                 }
+
+                if let Some(source) = local_source {
+                    let (source_path, _digest_str) = source.chunk_path(&info.digest);
+                    // fall back to downloading the chunk, for example if it is missing
+                    if let Ok((_exists, size)) = proxmox_async::runtime::block_in_place(|| {
+                        target.insert_chunk_file(&source_path, &info.digest, info.size())
+                    }) {
+                        cloned_bytes.fetch_add(size as usize, Ordering::SeqCst);
+                        return Ok(());
+                    }
+                }
+
+                let _permit = download_slots.acquire().await?;
+
                 //task_log!(worker, "sync {} chunk {}", pos, hex::encode(digest));
                 let chunk = chunk_reader.read_raw_chunk(&info.digest).await?;
                 let raw_size = chunk.raw_size() as usize;
@@ -190,7 +322,7 @@ async fn pull_index_chunks<I: IndexFile>(
                 Ok(())
             })
         })
-        .try_buffer_unordered(20)
+        .try_buffer_unordered(download_limit.max)
         .try_for_each(|_res| futures::future::ok(()))
         .await?;
 
@@ -201,6 +333,9 @@ async fn pull_index_chunks<I: IndexFile>(
     let elapsed = start_time.elapsed()?.as_secs_f64();
 
     let bytes = bytes.load(Ordering::SeqCst);
+    stats
+        .transferred_bytes
+        .fetch_add(bytes as u64, Ordering::SeqCst);
 
     task_log!(
         worker,
@@ -209,6 +344,11 @@ async fn pull_index_chunks<I: IndexFile>(
         (bytes as f64) / (1024.0 * 1024.0 * elapsed)
     );
 
+    let cloned_bytes = cloned_bytes.load(Ordering::SeqCst);
+    if cloned_bytes > 0 {
+        task_log!(worker, "cloned {} bytes from local datastore", cloned_bytes);
+    }
+
     Ok(())
 }
 
@@ -257,6 +397,7 @@ fn verify_archive(info: &FileInfo, csum: &[u8; 32], size: u64) -> Result<(), Err
 /// - Verify tmp file checksum
 /// - if archive is an index, pull referenced chunks
 /// - Rename tmp file into real path
+#[allow(clippy::too_many_arguments)]
 async fn pull_single_archive(
     worker: &WorkerTask,
     reader: &BackupReader,
@@ -264,6 +405,9 @@ async fn pull_single_archive(
     snapshot: &pbs_datastore::BackupDir,
     archive_info: &FileInfo,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    local_source: Option<Arc<DataStore>>,
+    download_limit: &DownloadLimit,
+    stats: &PullStats,
 ) -> Result<(), Error> {
     let archive_name = &archive_info.filename;
     let mut path = snapshot.full_path();
@@ -296,6 +440,9 @@ async fn pull_single_archive(
                 snapshot.datastore().clone(),
                 index,
                 downloaded_chunks,
+                local_source,
+                download_limit,
+                stats,
             )
             .await?;
         }
@@ -312,6 +459,9 @@ async fn pull_single_archive(
                 snapshot.datastore().clone(),
                 index,
                 downloaded_chunks,
+                local_source,
+                download_limit,
+                stats,
             )
             .await?;
         }
@@ -328,7 +478,29 @@ async fn pull_single_archive(
 }
 
 // Note: The client.log.blob is uploaded after the backup, so it is
-// not mentioned in the manifest.
+// not mentioned in the manifest. A log which only holds the archive
+// statistics written by the backup client is downloaded again if its
+// size on the source differs, since the uploaded log may have been
+// merged into it in the meantime.
+fn client_log_outdated(
+    snapshot: &pbs_datastore::BackupDir,
+    path: &std::path::Path,
+    source_log: Option<&BackupContent>,
+) -> Result<bool, Error> {
+    let source_log = match source_log {
+        Some(source_log) => source_log,
+        None => return Ok(false),
+    };
+    if !path.exists() {
+        return Ok(true);
+    }
+    if snapshot.client_log_statistics()?.is_none() {
+        return Ok(false);
+    }
+    // older sources do not report the size
+    Ok(source_log.size != Some(std::fs::metadata(path)?.len()))
+}
+
 async fn try_client_log_download(
     worker: &WorkerTask,
     reader: Arc<BackupReader>,
@@ -361,14 +533,23 @@ async fn try_client_log_download(
 /// -- if it matches, only download log and treat snapshot as already synced
 /// - Iterate over referenced files
 /// -- if file already exists, verify contents
-/// -- if not, pull it from the remote
-/// - Download log if not already existing
+/// -- if not, pull it from the remote, concurrently with other archives
+/// - Download log if it is missing or changed on the source (`source_log`)
+///
+/// Returns whether any files of the snapshot were transferred.
+#[allow(clippy::too_many_arguments)]
 async fn pull_snapshot(
     worker: &WorkerTask,
     reader: Arc<BackupReader>,
     snapshot: &pbs_datastore::BackupDir,
+    source_log: Option<&BackupContent>,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
+    local_source: Option<Arc<DataStore>>,
+    download_limit: &DownloadLimit,
+    stats: &PullStats,
+) -> Result<bool, Error> {
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
     let mut manifest_name = snapshot.full_path();
     manifest_name.push(MANIFEST_BLOB_NAME);
 
@@ -390,7 +571,7 @@ async fn pull_snapshot(
                             "skipping snapshot {} - vanished since start of sync",
                             snapshot.dir(),
                         );
-                        return Ok(());
+                        return Ok(false);
                     }
                     _ => {
                         bail!("HTTP error {code} - {message}");
@@ -418,17 +599,18 @@ async fn pull_snapshot(
         })?;
 
         if manifest_blob.raw_data() == tmp_manifest_blob.raw_data() {
-            if !client_log_name.exists() {
+            if client_log_outdated(snapshot, &client_log_name, source_log)? {
                 try_client_log_download(worker, reader, &client_log_name).await?;
             }
             task_log!(worker, "no data changes");
             let _ = std::fs::remove_file(&tmp_manifest_name);
-            return Ok(()); // nothing changed
+            return Ok(false); // nothing changed
         }
     }
 
     let manifest = BackupManifest::try_from(tmp_manifest_blob)?;
 
+    let mut archives = Vec::new();
     for item in manifest.files() {
         let mut path = snapshot.full_path();
         path.push(&item.filename);
@@ -468,29 +650,46 @@ async fn pull_snapshot(
             }
         }
 
-        let mut chunk_reader = RemoteChunkReader::new(
-            reader.clone(),
-            None,
-            item.chunk_crypt_mode(),
-            HashMap::new(),
-        );
+        archives.push(item);
+    }
 
-        pull_single_archive(
-            worker,
-            &reader,
-            &mut chunk_reader,
-            snapshot,
-            item,
-            downloaded_chunks.clone(),
-        )
+    stream::iter(archives)
+        .map(|item| {
+            let reader = reader.clone();
+            let downloaded_chunks = downloaded_chunks.clone();
+            let local_source = local_source.clone();
+
+            async move {
+                let mut chunk_reader = RemoteChunkReader::new(
+                    reader.clone(),
+                    None,
+                    item.chunk_crypt_mode(),
+                    0, // only raw chunks are read, which are not cached
+                );
+
+                pull_single_archive(
+                    worker,
+                    &reader,
+                    &mut chunk_reader,
+                    snapshot,
+                    item,
+                    downloaded_chunks,
+                    local_source,
+                    download_limit,
+                    stats,
+                )
+                .await
+            }
+        })
+        .buffer_unordered(MAX_PARALLEL_ARCHIVES)
+        .try_for_each(|()| futures::future::ok(()))
         .await?;
-    }
 
     if let Err(err) = std::fs::rename(&tmp_manifest_name, &manifest_name) {
         bail!("Atomic rename file {:?} failed - {}", manifest_name, err);
     }
 
-    if !client_log_name.exists() {
+    if client_log_outdated(snapshot, &client_log_name, source_log)? {
         try_client_log_download(worker, reader, &client_log_name).await?;
     }
 
@@ -498,27 +697,48 @@ async fn pull_snapshot(
         .cleanup_unreferenced_files(&manifest)
         .map_err(|err| format_err!("failed to cleanup unreferenced files - {err}"))?;
 
-    Ok(())
+    snapshot
+        .datastore()
+        .store_snapshot_in_backend(snapshot.backup_ns(), snapshot.as_ref())?;
+
+    Ok(true)
 }
 
 /// Pulls a `snapshot`, removing newly created ones on error, but keeping existing ones in any case.
 ///
 /// The `reader` is configured to read from the remote / source namespace, while the `snapshot` is
 /// pointing to the local datastore and target namespace.
+#[allow(clippy::too_many_arguments)]
 async fn pull_snapshot_from(
     worker: &WorkerTask,
     reader: Arc<BackupReader>,
     snapshot: &pbs_datastore::BackupDir,
+    source_log: Option<&BackupContent>,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    local_source: Option<Arc<DataStore>>,
+    download_limit: &DownloadLimit,
+    stats: &PullStats,
+    verify_worker: Option<&VerifyWorker>,
 ) -> Result<(), Error> {
-    let (_path, is_new, _snap_lock) = snapshot
+    let (_path, is_new, snap_lock) = snapshot
         .datastore()
         .create_locked_backup_dir(snapshot.backup_ns(), snapshot.as_ref())?;
 
-    if is_new {
+    let transferred = if is_new {
         task_log!(worker, "sync snapshot {}", snapshot.dir());
 
-        if let Err(err) = pull_snapshot(worker, reader, snapshot, downloaded_chunks).await {
+        if let Err(err) = pull_snapshot(
+            worker,
+            reader,
+            snapshot,
+            source_log,
+            Arc::clone(&downloaded_chunks),
+            local_source,
+            download_limit,
+            stats,
+        )
+        .await
+        {
             if let Err(cleanup_err) = snapshot.datastore().remove_backup_dir(
                 snapshot.backup_ns(),
                 snapshot.as_ref(),
@@ -529,15 +749,95 @@ async fn pull_snapshot_from(
             return Err(err);
         }
         task_log!(worker, "sync snapshot {} done", snapshot.dir());
+        true
     } else {
         task_log!(worker, "re-sync snapshot {}", snapshot.dir());
-        pull_snapshot(worker, reader, snapshot, downloaded_chunks).await?;
+        let transferred = pull_snapshot(
+            worker,
+            reader,
+            snapshot,
+            source_log,
+            Arc::clone(&downloaded_chunks),
+            local_source,
+            download_limit,
+            stats,
+        )
+        .await?;
         task_log!(worker, "re-sync snapshot {} done", snapshot.dir());
+        transferred
+    };
+
+    // only verify what was transferred, not an unchanged, already existing snapshot
+    if let Some(verify_worker) = verify_worker.filter(|_| transferred) {
+        // keep the lock, the snapshot must not be accessed before it got verified
+        let verified = proxmox_async::runtime::block_in_place(|| {
+            verify_backup_dir_with_lock(
+                verify_worker,
+                snapshot,
+                worker.upid().clone(),
+                None,
+                snap_lock,
+            )
+        })?;
+
+        // corrupt chunks got quarantined, download them again if another snapshot needs them,
+        // and verify them again afterwards
+        let corrupt_chunks = verify_worker.take_corrupt_chunks();
+        if !corrupt_chunks.is_empty() {
+            let mut downloaded_chunks = downloaded_chunks.lock().unwrap();
+            for digest in &corrupt_chunks {
+                downloaded_chunks.remove(digest);
+            }
+        }
+
+        if !verified {
+            match failed_verify_cleanup(is_new, snapshot.is_protected()) {
+                FailedVerifyCleanup::Remove => {
+                    if let Err(cleanup_err) = snapshot.datastore().remove_backup_dir(
+                        snapshot.backup_ns(),
+                        snapshot.as_ref(),
+                        false,
+                    ) {
+                        task_log!(worker, "cleanup error - {}", cleanup_err);
+                    }
+                    bail!(
+                        "verification of synced snapshot {} failed, removed it again",
+                        snapshot.dir()
+                    );
+                }
+                FailedVerifyCleanup::KeepFailed => bail!(
+                    "verification of re-synced snapshot {} failed, keeping it marked as failed",
+                    snapshot.dir()
+                ),
+            }
+        }
     }
 
+    stats.snapshots.fetch_add(1, Ordering::SeqCst);
+
     Ok(())
 }
 
+#[derive(Debug, PartialEq)]
+enum FailedVerifyCleanup {
+    /// Remove the snapshot again.
+    Remove,
+    /// Keep the snapshot, its manifest records the failed verification.
+    KeepFailed,
+}
+
+/// Decide what happens to a synced snapshot which failed its verification.
+///
+/// Only snapshots created by this sync are removed again. Already existing snapshots, which were
+/// only re-synced, may hold data the source does not have anymore, so they are kept.
+fn failed_verify_cleanup(is_new: bool, protected: bool) -> FailedVerifyCleanup {
+    if is_new && !protected {
+        FailedVerifyCleanup::Remove
+    } else {
+        FailedVerifyCleanup::KeepFailed
+    }
+}
+
 struct SkipInfo {
     oldest: i64,
     newest: i64,
@@ -606,6 +906,7 @@ async fn pull_group(
     group: &pbs_api_types::BackupGroup,
     remote_ns: BackupNamespace,
     progress: &mut StoreProgress,
+    verify_worker: Option<&VerifyWorker>,
 ) -> Result<(), Error> {
     let path = format!(
         "api2/json/admin/datastore/{}/snapshots",
@@ -639,6 +940,8 @@ async fn pull_group(
     // start with 65536 chunks (up to 256 GiB)
     let downloaded_chunks = Arc::new(Mutex::new(HashSet::with_capacity(1024 * 64)));
 
+    let download_limit = DownloadLimit::new(params.parallel_downloads);
+
     progress.group_snapshots = list.len() as u64;
 
     let mut skip_info = SkipInfo {
@@ -675,6 +978,7 @@ async fn pull_group(
         let options =
             HttpClientOptions::new_non_interactive(auth_info.ticket.clone(), fingerprint.clone())
                 .rate_limit(params.limit.clone());
+        let options = crate::api2::config::remote::remote_tls_options(&params.remote, options);
 
         let new_client = HttpClient::new(
             params.source.host(),
@@ -694,8 +998,23 @@ async fn pull_group(
         .await?;
 
         let snapshot = params.store.backup_dir(target_ns.clone(), snapshot)?;
+        let source_log = item
+            .files
+            .iter()
+            .find(|file| file.filename == CLIENT_LOG_BLOB_NAME);
 
-        let result = pull_snapshot_from(worker, reader, &snapshot, downloaded_chunks.clone()).await;
+        let result = pull_snapshot_from(
+            worker,
+            reader,
+            &snapshot,
+            source_log,
+            downloaded_chunks.clone(),
+            params.local_source.clone(),
+            &download_limit,
+            &params.stats,
+            verify_worker,
+        )
+        .await;
 
         progress.done_snapshots = pos as u64 + 1;
         task_log!(worker, "percentage done: {}", progress);
@@ -897,7 +1216,7 @@ fn check_and_remove_vanished_ns(
 /// - creation and removal of sub-NS checked here
 /// - access to sub-NS checked here
 pub(crate) async fn pull_store(
-    worker: &WorkerTask,
+    worker: &Arc<WorkerTask>,
     client: &HttpClient,
     mut params: PullParameters,
 ) -> Result<(), Error> {
@@ -905,6 +1224,22 @@ pub(crate) async fn pull_store(
     let _shared_store_lock = params.store.try_shared_chunk_store_lock()?;
     let mut errors = false;
 
+    // shared by all snapshots, so that chunks are only verified once
+    let verify_worker = if params.verify_after_sync {
+        task_log!(worker, "verifying newly synced snapshots");
+        Some(VerifyWorker::new(worker.clone(), params.store.clone()))
+    } else {
+        None
+    };
+
+    if let Some(source) = &params.local_source {
+        task_log!(
+            worker,
+            "source datastore '{}' is local, cloning chunks instead of downloading them",
+            source.name()
+        );
+    }
+
     let old_max_depth = params.max_depth;
     let namespaces = if params.remote_ns.is_root() && params.max_depth == Some(0) {
         vec![params.remote_ns.clone()] // backwards compat - don't query remote namespaces!
@@ -948,7 +1283,16 @@ pub(crate) async fn pull_store(
             }
         }
 
-        match pull_ns(worker, client, &params, namespace.clone(), target_ns).await {
+        match pull_ns(
+            worker,
+            client,
+            &params,
+            namespace.clone(),
+            target_ns,
+            verify_worker.as_ref(),
+        )
+        .await
+        {
             Ok((ns_progress, ns_errors)) => {
                 errors |= ns_errors;
 
@@ -1005,6 +1349,7 @@ pub(crate) async fn pull_ns(
     params: &PullParameters,
     source_ns: BackupNamespace,
     target_ns: BackupNamespace,
+    verify_worker: Option<&VerifyWorker>,
 ) -> Result<(StoreProgress, bool), Error> {
     let path = format!("api2/json/admin/datastore/{}/groups", params.source.store());
 
@@ -1108,6 +1453,7 @@ pub(crate) async fn pull_ns(
             &group,
             source_ns.clone(),
             &mut progress,
+            verify_worker,
         )
         .await
         {
@@ -1159,3 +1505,29 @@ pub(crate) async fn pull_ns(
 
     Ok((progress, errors))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_failed_verify_cleanup() {
+        assert_eq!(
+            failed_verify_cleanup(true, false),
+            FailedVerifyCleanup::Remove
+        );
+        // re-synced snapshots were not created by this sync
+        assert_eq!(
+            failed_verify_cleanup(false, false),
+            FailedVerifyCleanup::KeepFailed
+        );
+        assert_eq!(
+            failed_verify_cleanup(false, true),
+            FailedVerifyCleanup::KeepFailed
+        );
+        assert_eq!(
+            failed_verify_cleanup(true, true),
+            FailedVerifyCleanup::KeepFailed
+        );
+    }
+}
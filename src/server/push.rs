@@ -0,0 +1,594 @@
+//! Sync datastore to remote server
+//!
+//! The counterpart of [pull](super::pull): the snapshots of a local datastore are uploaded to the
+//! remote with the backup protocol, so that the remote does not need to be able to reach this
+//! node, for example if it is behind a NAT. Chunks the remote already knows from the previous
+//! snapshot of a group are only referenced, not uploaded again.
+
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Error};
+use serde_json::json;
+
+use proxmox_sys::fs::lock_dir_noblock_shared;
+use proxmox_sys::task_log;
+
+use pbs_api_types::{
+    print_store_and_ns, BackupNamespace, GroupFilter, GroupListItem, NamespaceListItem, Operation,
+    RateLimitConfig, Remote, SnapshotListItem,
+};
+use pbs_client::{BackupRepository, BackupWriter, HttpClient, HttpClientOptions};
+use pbs_datastore::dynamic_index::DynamicIndexReader;
+use pbs_datastore::fixed_index::FixedIndexReader;
+use pbs_datastore::manifest::{
+    archive_type, ArchiveType, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME,
+};
+use pbs_datastore::{BackupDir, BackupGroup, BackupInfo, DataStore, LocalChunkReader};
+use proxmox_rest_server::WorkerTask;
+
+use crate::server::pull::PullStats;
+
+/// Parameters for a push operation.
+pub(crate) struct PushParameters {
+    /// Remote that is pushed to
+    remote: Remote,
+    /// Full specification of remote datastore
+    target: BackupRepository,
+    /// Local store that is pushed
+    store: Arc<DataStore>,
+    /// Remote namespace (anchor)
+    remote_ns: BackupNamespace,
+    /// Local namespace
+    ns: BackupNamespace,
+    /// Whether to remove groups and snapshots which exist on the remote end, but not locally
+    remove_vanished: bool,
+    /// How many levels of sub-namespaces to push (0 == no recursion, None == maximum recursion)
+    max_depth: Option<usize>,
+    /// Filters for reducing the push scope
+    group_filter: Option<Vec<GroupFilter>>,
+    /// Rate limits for all transfers to `remote`
+    limit: RateLimitConfig,
+    /// Statistics about the data transferred so far
+    stats: Arc<PullStats>,
+}
+
+impl PushParameters {
+    /// Creates a new instance of `PushParameters`.
+    ///
+    /// `remote` will be dereferenced via [pbs_api_types::RemoteConfig], and combined into a
+    /// [BackupRepository] with `remote_store`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        store: &str,
+        ns: BackupNamespace,
+        remote: &str,
+        remote_store: &str,
+        remote_ns: BackupNamespace,
+        remove_vanished: Option<bool>,
+        max_depth: Option<usize>,
+        group_filter: Option<Vec<GroupFilter>>,
+        limit: RateLimitConfig,
+    ) -> Result<Self, Error> {
+        let store = DataStore::lookup_datastore(store, Some(Operation::Read))?;
+
+        if let Some(max_depth) = max_depth {
+            ns.check_max_depth(max_depth)?;
+            remote_ns.check_max_depth(max_depth)?;
+        }
+
+        let (remote_config, _digest) = pbs_config::remote::config()?;
+        let remote: Remote = remote_config.lookup("remote", remote)?;
+
+        let target = BackupRepository::new(
+            Some(remote.config.auth_id.clone()),
+            Some(remote.config.host.clone()),
+            remote.config.port,
+            remote_store.to_string(),
+        );
+
+        Ok(Self {
+            remote,
+            target,
+            store,
+            remote_ns,
+            ns,
+            remove_vanished: remove_vanished.unwrap_or(false),
+            max_depth,
+            group_filter,
+            limit,
+            stats: Arc::new(PullStats::default()),
+        })
+    }
+
+    /// Use `stats` to collect the statistics of the push operation, e.g. to report them after
+    /// it has finished.
+    pub(crate) fn with_stats(mut self, stats: Arc<PullStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Creates a new [HttpClient] for accessing the [Remote] that is pushed to.
+    pub async fn client(&self) -> Result<HttpClient, Error> {
+        crate::api2::config::remote::remote_client(&self.remote, Some(self.limit.clone())).await
+    }
+
+    fn apply_filters(&self, group: &pbs_api_types::BackupGroup) -> bool {
+        match &self.group_filter {
+            Some(filters) => filters.iter().any(|filter| group.matches(filter)),
+            None => true,
+        }
+    }
+}
+
+// each backup writer needs its own connection, reuse the ticket of `client` for it
+async fn writer_client(client: &HttpClient, params: &PushParameters) -> Result<HttpClient, Error> {
+    let auth_info = client.login().await?;
+
+    let options = HttpClientOptions::new_non_interactive(auth_info.ticket, client.fingerprint())
+        .rate_limit(params.limit.clone());
+    let options = crate::api2::config::remote::remote_tls_options(&params.remote, options);
+
+    HttpClient::new(
+        params.target.host(),
+        params.target.port(),
+        params.target.auth_id(),
+        options,
+    )
+}
+
+async fn query_remote_namespaces(
+    client: &HttpClient,
+    params: &PushParameters,
+) -> Result<HashSet<BackupNamespace>, Error> {
+    let path = format!(
+        "api2/json/admin/datastore/{}/namespace",
+        params.target.store()
+    );
+    let mut data = json!({});
+    if !params.remote_ns.is_root() {
+        data["parent"] = json!(params.remote_ns);
+    }
+
+    let mut result = client
+        .get(&path, Some(data))
+        .await
+        .map_err(|err| format_err!("Querying remote namespaces failed - {}", err))?;
+    let list: Vec<NamespaceListItem> = serde_json::from_value(result["data"].take())?;
+
+    Ok(list.into_iter().map(|item| item.ns).collect())
+}
+
+async fn create_remote_ns(
+    client: &HttpClient,
+    params: &PushParameters,
+    ns: &BackupNamespace,
+) -> Result<(), Error> {
+    let path = format!(
+        "api2/json/admin/datastore/{}/namespace",
+        params.target.store()
+    );
+    let name = ns.components().last().unwrap_or_default();
+    let mut data = json!({ "name": name });
+    let parent = ns.parent();
+    if !parent.is_root() {
+        data["parent"] = json!(parent);
+    }
+
+    client.post(&path, Some(data)).await?;
+
+    Ok(())
+}
+
+/// Pushes the snapshot `snapshot` of the local datastore into `target_ns` on the remote.
+///
+/// Pushing a snapshot consists of the following steps:
+/// - Start a backup of the same snapshot on the remote
+/// - Download the indices of the previous remote snapshot, to only reference the chunks it
+///   already knows
+/// - Upload blobs and indices with their new chunks, and the manifest last
+/// - Upload the client log, if any
+async fn push_snapshot(
+    worker: &WorkerTask,
+    client: &HttpClient,
+    params: &PushParameters,
+    snapshot: &BackupDir,
+    target_ns: &BackupNamespace,
+) -> Result<(), Error> {
+    let _snap_lock = lock_dir_noblock_shared(
+        &snapshot.full_path(),
+        "snapshot",
+        "locked by another operation",
+    )?;
+
+    let (manifest, _size) = snapshot.load_manifest()?;
+
+    let writer = BackupWriter::start(
+        writer_client(client, params).await?,
+        None,
+        params.target.store(),
+        target_ns,
+        snapshot.dir(),
+        false,
+        false,
+        false,
+    )
+    .await?;
+
+    for item in manifest.files() {
+        if let Some(compression) = item.payload_compression {
+            if !writer.payload_compression().contains(&compression) {
+                bail!(
+                    "remote does not support '{}' payload compression of '{}'",
+                    compression,
+                    item.filename
+                );
+            }
+        }
+    }
+
+    let previous_manifest = match writer.previous_backup_time().await? {
+        Some(_) => writer.download_previous_manifest().await.ok(),
+        None => None,
+    };
+    let known_chunks = Arc::new(Mutex::new(HashSet::new()));
+
+    for item in manifest.files() {
+        let mut path = snapshot.full_path();
+        path.push(&item.filename);
+
+        let archive_type = archive_type(&item.filename)?;
+
+        if let Some(previous) = &previous_manifest {
+            if previous.lookup_file_info(&item.filename).is_ok() {
+                // just a bandwidth optimization, chunks which are not known get uploaded anyway
+                let _ = match archive_type {
+                    ArchiveType::FixedIndex => writer
+                        .download_previous_fixed_index(
+                            &item.filename,
+                            previous,
+                            known_chunks.clone(),
+                        )
+                        .await
+                        .map(drop),
+                    ArchiveType::DynamicIndex => writer
+                        .download_previous_dynamic_index(
+                            &item.filename,
+                            previous,
+                            known_chunks.clone(),
+                        )
+                        .await
+                        .map(drop),
+                    ArchiveType::Blob => Ok(()),
+                };
+            }
+        }
+
+        let chunk_reader =
+            LocalChunkReader::new(params.store.clone(), None, item.chunk_crypt_mode());
+
+        match archive_type {
+            ArchiveType::FixedIndex => {
+                let index = FixedIndexReader::open(&path)?;
+                writer
+                    .upload_index(&item.filename, &index, &chunk_reader, known_chunks.clone())
+                    .await?;
+            }
+            ArchiveType::DynamicIndex => {
+                let index = DynamicIndexReader::open(&path)?;
+                writer
+                    .upload_index(&item.filename, &index, &chunk_reader, known_chunks.clone())
+                    .await?;
+            }
+            ArchiveType::Blob => {
+                writer
+                    .upload_blob(std::fs::File::open(&path)?, &item.filename)
+                    .await?;
+            }
+        }
+    }
+
+    // uploaded last, the manifest is only accepted with all referenced files present
+    let mut manifest_path = snapshot.full_path();
+    manifest_path.push(MANIFEST_BLOB_NAME);
+    writer
+        .upload_blob(std::fs::File::open(&manifest_path)?, MANIFEST_BLOB_NAME)
+        .await?;
+
+    writer.finish().await?;
+
+    let mut client_log_path = snapshot.full_path();
+    client_log_path.push(CLIENT_LOG_BLOB_NAME);
+    if let Some(data) = proxmox_sys::fs::file_get_optional_contents(&client_log_path)? {
+        let path = format!(
+            "api2/json/admin/datastore/{}/upload-backup-log",
+            params.target.store()
+        );
+        let mut args = json!({
+            "backup-type": snapshot.backup_type(),
+            "backup-id": snapshot.backup_id(),
+            "backup-time": snapshot.backup_time(),
+        });
+        if !target_ns.is_root() {
+            args["ns"] = json!(target_ns);
+        }
+        client
+            .upload("application/octet-stream", data.into(), &path, Some(args))
+            .await?;
+        task_log!(
+            worker,
+            "uploaded backup log file {:?}",
+            CLIENT_LOG_BLOB_NAME
+        );
+    }
+
+    Ok(())
+}
+
+/// Pushes a group of the local datastore into `target_ns` on the remote.
+///
+/// Pushing a group consists of the following steps:
+/// - Query the list of snapshots of the group on the remote, if it exists there
+/// - Push each finished local snapshot which is newer than the last remote one
+/// - (remove_vanished) remove remote snapshots which no longer exist locally
+async fn push_group(
+    worker: &WorkerTask,
+    client: &HttpClient,
+    params: &PushParameters,
+    group: &BackupGroup,
+    target_ns: &BackupNamespace,
+    exists_on_remote: bool,
+) -> Result<(), Error> {
+    let mut remote_snapshots = HashSet::new();
+    let mut last_remote = None;
+
+    if exists_on_remote {
+        let path = format!(
+            "api2/json/admin/datastore/{}/snapshots",
+            params.target.store()
+        );
+        let mut args = json!({
+            "backup-type": group.group().ty,
+            "backup-id": group.group().id,
+        });
+        if !target_ns.is_root() {
+            args["ns"] = json!(target_ns);
+        }
+
+        let mut result = client.get(&path, Some(args)).await?;
+        let list: Vec<SnapshotListItem> = serde_json::from_value(result["data"].take())?;
+
+        for item in list {
+            // in-progress backups are not considered as synced
+            if item.size.is_none() {
+                continue;
+            }
+            last_remote = last_remote.max(Some(item.backup.time));
+            remote_snapshots.insert(item.backup.time);
+        }
+    }
+
+    let mut list = group.list_backups()?;
+    BackupInfo::sort_list(&mut list, true);
+
+    let mut local_snapshots = HashSet::with_capacity(list.len());
+    let mut skipped = 0;
+
+    for info in list {
+        let finished = info.is_finished();
+        let snapshot = info.backup_dir;
+        local_snapshots.insert(snapshot.backup_time());
+
+        if !finished {
+            task_log!(
+                worker,
+                "skipping snapshot {} - in-progress backup",
+                snapshot.dir()
+            );
+            continue;
+        }
+
+        if let Some(last_remote) = last_remote {
+            if snapshot.backup_time() <= last_remote {
+                skipped += 1;
+                continue;
+            }
+        }
+
+        task_log!(worker, "sync snapshot {}", snapshot.dir());
+        push_snapshot(worker, client, params, &snapshot, target_ns).await?; // stop on error
+        task_log!(worker, "sync snapshot {} done", snapshot.dir());
+
+        params.stats.snapshots.fetch_add(1, Ordering::SeqCst);
+    }
+
+    if skipped > 0 {
+        task_log!(
+            worker,
+            "skipped: {} snapshot(s) - older than the newest remote snapshot",
+            skipped
+        );
+    }
+
+    if params.remove_vanished {
+        let path = format!(
+            "api2/json/admin/datastore/{}/snapshots",
+            params.target.store()
+        );
+        for backup_time in remote_snapshots {
+            if local_snapshots.contains(&backup_time) {
+                continue;
+            }
+            let snapshot = pbs_api_types::BackupDir::from((group.group().clone(), backup_time));
+            task_log!(worker, "delete vanished remote snapshot {}", snapshot);
+
+            let mut args = json!({
+                "backup-type": snapshot.group.ty,
+                "backup-id": snapshot.group.id,
+                "backup-time": snapshot.time,
+            });
+            if !target_ns.is_root() {
+                args["ns"] = json!(target_ns);
+            }
+            client.delete(&path, Some(args)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Pushes a namespace of the local datastore into `target_ns` on the remote.
+///
+/// Returns whether errors occurred while pushing single groups.
+async fn push_ns(
+    worker: &WorkerTask,
+    client: &HttpClient,
+    params: &PushParameters,
+    ns: BackupNamespace,
+    target_ns: &BackupNamespace,
+) -> Result<bool, Error> {
+    let path = format!("api2/json/admin/datastore/{}/groups", params.target.store());
+    let args = if target_ns.is_root() {
+        None
+    } else {
+        Some(json!({ "ns": target_ns }))
+    };
+
+    let mut result = client.get(&path, args).await?;
+    let remote_groups: Vec<GroupListItem> = serde_json::from_value(result["data"].take())?;
+    let remote_groups: HashSet<pbs_api_types::BackupGroup> =
+        remote_groups.into_iter().map(|item| item.backup).collect();
+
+    let mut groups: Vec<BackupGroup> = params
+        .store
+        .iter_backup_groups_ok(ns)?
+        .filter(|group| params.apply_filters(group.group()))
+        .collect();
+    groups.sort_unstable_by(|a, b| {
+        let (a, b) = (a.group(), b.group());
+        a.ty.cmp(&b.ty).then_with(|| a.id.cmp(&b.id))
+    });
+
+    let mut errors = false;
+    let mut local_groups = HashSet::with_capacity(groups.len());
+
+    for group in groups {
+        local_groups.insert(group.group().clone());
+
+        let exists_on_remote = remote_groups.contains(group.group());
+        if let Err(err) =
+            push_group(worker, client, params, &group, target_ns, exists_on_remote).await
+        {
+            task_log!(worker, "sync group {} failed - {}", group.group(), err);
+            errors = true; // do not stop here, instead continue
+        }
+    }
+
+    if params.remove_vanished {
+        let path = format!("api2/json/admin/datastore/{}/groups", params.target.store());
+        for remote_group in remote_groups {
+            if local_groups.contains(&remote_group) || !params.apply_filters(&remote_group) {
+                continue;
+            }
+            task_log!(worker, "delete vanished remote group '{}'", remote_group);
+
+            let mut args = json!({
+                "backup-type": remote_group.ty,
+                "backup-id": remote_group.id,
+            });
+            if !target_ns.is_root() {
+                args["ns"] = json!(target_ns);
+            }
+            if let Err(err) = client.delete(&path, Some(args)).await {
+                task_log!(
+                    worker,
+                    "delete vanished remote group '{}' failed - {}",
+                    remote_group,
+                    err
+                );
+                errors = true;
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
+/// Pushes a store according to `params`.
+///
+/// Pushing a store consists of the following steps:
+/// - Query the list of namespaces on the remote
+/// - Iterate the local namespaces
+/// -- create the remote namespace if needed
+/// -- attempt to push each namespace in turn
+///
+/// Permission checks:
+/// - access to the local datastore, namespace anchor and remote entry need to be checked at call
+///   site
+/// - the remote checks the privileges of the remote's user for creating namespaces and backups
+pub(crate) async fn push_store(
+    worker: &WorkerTask,
+    client: &HttpClient,
+    params: PushParameters,
+) -> Result<(), Error> {
+    let mut errors = false;
+
+    let remote_namespaces = query_remote_namespaces(client, &params).await?;
+
+    let mut namespaces: Vec<BackupNamespace> = params
+        .store
+        .recursive_iter_backup_ns_ok(params.ns.clone(), params.max_depth)?
+        .collect();
+    // parents first
+    namespaces.sort_unstable_by_key(|ns| ns.name_len());
+
+    for namespace in namespaces {
+        let source_store_ns_str = print_store_and_ns(params.store.name(), &namespace);
+
+        let target_ns = namespace.map_prefix(&params.ns, &params.remote_ns)?;
+        let target_store_ns_str = print_store_and_ns(params.target.store(), &target_ns);
+
+        task_log!(worker, "----");
+        task_log!(
+            worker,
+            "Syncing {} into remote {}",
+            source_store_ns_str,
+            target_store_ns_str
+        );
+
+        if !target_ns.is_root() && !remote_namespaces.contains(&target_ns) {
+            if let Err(err) = create_remote_ns(client, &params, &target_ns).await {
+                task_log!(
+                    worker,
+                    "Cannot sync {} into remote {} - namespace creation failed: {}",
+                    source_store_ns_str,
+                    target_store_ns_str,
+                    err,
+                );
+                errors = true;
+                continue;
+            }
+            task_log!(worker, "Created remote namespace {}", target_ns);
+        }
+
+        match push_ns(worker, client, &params, namespace.clone(), &target_ns).await {
+            Ok(ns_errors) => errors |= ns_errors,
+            Err(err) => {
+                errors = true;
+                task_log!(
+                    worker,
+                    "Encountered errors while syncing namespace {} - {}",
+                    namespace,
+                    err,
+                );
+            }
+        }
+    }
+
+    if errors {
+        bail!("sync failed with some errors.");
+    }
+
+    Ok(())
+}
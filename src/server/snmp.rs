@@ -0,0 +1,255 @@
+//! SNMP trap notifications for critical events
+//!
+//! Traps are sent as SNMPv2c notifications using the `snmptrap` tool from the net-snmp tools, the
+//! notifications and their objects are described in `PROXMOX-BACKUP-MIB.txt`.
+
+use std::collections::HashSet;
+use std::net::Ipv6Addr;
+use std::process::Command;
+use std::sync::Mutex;
+
+use anyhow::{bail, Error};
+
+use proxmox_sys::fs::{create_path, replace_file, CreateOptions};
+
+/// Base OID of the PROXMOX-BACKUP-MIB module.
+///
+/// The module is placed below the net-snmp playpen arc (netSnmpPlaypen.1).
+const PBS_MIB_OID: &str = "1.3.6.1.4.1.8072.9999.9999.1";
+
+const SNMPTRAP_BIN_PATH: &str = "/usr/bin/snmptrap";
+
+// private net-snmp configuration directory, used to pass the community to snmptrap without
+// exposing it on the command line
+const SNMP_CONF_DIR: &str = pbs_buildcfg::rundir!("/snmp");
+
+lazy_static::lazy_static! {
+    // datastores for which a datastore-full trap was sent and usage did not drop since
+    static ref FULL_DATASTORES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// A critical event which can be reported via SNMP trap.
+pub enum SnmpTrap<'a> {
+    /// A scheduled or manually started job failed
+    JobFailed {
+        jobtype: &'a str,
+        jobname: &'a str,
+        error: &'a str,
+    },
+    /// A verification job found corrupt snapshots
+    VerificationFailed {
+        store: &'a str,
+        jobname: &'a str,
+        errors: usize,
+    },
+    /// The usage of a datastore exceeded the configured threshold
+    DatastoreFull { store: &'a str, usage: u64 },
+}
+
+enum VarBind {
+    String(String),
+    Unsigned(u64),
+}
+
+impl SnmpTrap<'_> {
+    fn notification(&self) -> u32 {
+        match self {
+            SnmpTrap::JobFailed { .. } => 1,
+            SnmpTrap::VerificationFailed { .. } => 2,
+            SnmpTrap::DatastoreFull { .. } => 3,
+        }
+    }
+
+    /// Returns the objects of the notification as (pbsObjects index, value) pairs.
+    fn objects(&self) -> Vec<(u32, VarBind)> {
+        let mut list = vec![(1, VarBind::String(proxmox_sys::nodename().to_string()))];
+        match self {
+            SnmpTrap::JobFailed {
+                jobtype,
+                jobname,
+                error,
+            } => {
+                list.push((2, VarBind::String(jobtype.to_string())));
+                list.push((3, VarBind::String(jobname.to_string())));
+                list.push((4, VarBind::String(error.to_string())));
+            }
+            SnmpTrap::VerificationFailed {
+                store,
+                jobname,
+                errors,
+            } => {
+                list.push((5, VarBind::String(store.to_string())));
+                list.push((3, VarBind::String(jobname.to_string())));
+                list.push((6, VarBind::Unsigned(*errors as u64)));
+            }
+            SnmpTrap::DatastoreFull { store, usage } => {
+                list.push((5, VarBind::String(store.to_string())));
+                list.push((7, VarBind::Unsigned(*usage)));
+            }
+        }
+        list
+    }
+}
+
+/// Returns the net-snmp transport address for the trap receiver `host` and `port`.
+fn snmp_target(host: &str, port: u16) -> String {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("udp6:[{}]:{}", host, port)
+    } else {
+        format!("udp:{}:{}", host, port)
+    }
+}
+
+/// Returns the snmptrap arguments following the target, which describe the notification.
+fn trap_arguments(trap: &SnmpTrap) -> Vec<String> {
+    // empty uptime, snmptrap fills in the system uptime
+    let mut args = vec![
+        String::new(),
+        format!("{}.0.{}", PBS_MIB_OID, trap.notification()),
+    ];
+
+    for (index, value) in trap.objects() {
+        args.push(format!("{}.1.{}", PBS_MIB_OID, index));
+        match value {
+            VarBind::String(value) => args.extend(["s".to_string(), value]),
+            VarBind::Unsigned(value) => args.extend(["u".to_string(), value.to_string()]),
+        }
+    }
+
+    args
+}
+
+// write the community to a private snmp.conf, command line arguments are visible to all users
+fn write_snmp_conf(community: &str) -> Result<(), Error> {
+    if community
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control())
+    {
+        bail!("invalid SNMP community - must not contain whitespace");
+    }
+
+    let backup_user = pbs_config::backup_user()?;
+    let dir_opts = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o700))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    create_path(SNMP_CONF_DIR, None, Some(dir_opts))?;
+
+    let file_opts = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o600))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        format!("{}/snmp.conf", SNMP_CONF_DIR),
+        format!("defVersion 2c\ndefCommunity {}\n", community).as_bytes(),
+        file_opts,
+        false,
+    )
+}
+
+/// Send an SNMP trap for `trap`, if SNMP traps are configured in the node config.
+///
+/// Callers may run on the async executor, so sending happens in blocking context.
+pub fn send_snmp_trap(trap: &SnmpTrap) -> Result<(), Error> {
+    let (config, _digest) = crate::config::node::config()?;
+    let snmp = match config.snmp_config().transpose()? {
+        Some(snmp) => snmp,
+        None => return Ok(()),
+    };
+
+    let target = snmp_target(&snmp.target, snmp.port.unwrap_or(162));
+    let args = trap_arguments(trap);
+
+    proxmox_async::runtime::block_in_place(move || {
+        write_snmp_conf(snmp.community.as_deref().unwrap_or("public"))?;
+
+        let mut command = Command::new(SNMPTRAP_BIN_PATH);
+        command.env("SNMPCONFPATH", SNMP_CONF_DIR);
+        command.args(["-v", "2c", &target]);
+        command.args(args);
+
+        proxmox_sys::command::run_command(command, None)?;
+
+        Ok(())
+    })
+}
+
+/// Send datastore-full traps for datastores whose usage exceeds the configured threshold.
+///
+/// `usage` contains the name, used and total bytes of each datastore. A trap is only sent once
+/// when a datastore crosses the threshold, not on every call.
+pub fn check_datastore_full_traps(usage: &[(String, u64, u64)]) -> Result<(), Error> {
+    let (config, _digest) = crate::config::node::config()?;
+    let threshold = match config.snmp_config().transpose()? {
+        Some(snmp) => u64::from(snmp.datastore_full_threshold.unwrap_or(95)),
+        None => return Ok(()),
+    };
+
+    let mut full_datastores = FULL_DATASTORES.lock().unwrap();
+
+    for (store, used, total) in usage {
+        if *total == 0 {
+            continue;
+        }
+        let percent = used * 100 / total;
+
+        if percent < threshold {
+            full_datastores.remove(store);
+        } else if full_datastores.insert(store.clone()) {
+            let trap = SnmpTrap::DatastoreFull {
+                store,
+                usage: percent,
+            };
+            if let Err(err) = send_snmp_trap(&trap) {
+                // try again next time
+                full_datastores.remove(store);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snmp_target() {
+        assert_eq!(snmp_target("192.0.2.1", 162), "udp:192.0.2.1:162");
+        assert_eq!(
+            snmp_target("trap.example.com", 1162),
+            "udp:trap.example.com:1162"
+        );
+        assert_eq!(snmp_target("2001:db8::1", 162), "udp6:[2001:db8::1]:162");
+        assert_eq!(snmp_target("[2001:db8::1]", 162), "udp6:[2001:db8::1]:162");
+    }
+
+    #[test]
+    fn test_trap_arguments() {
+        let trap = SnmpTrap::DatastoreFull {
+            store: "store1",
+            usage: 97,
+        };
+        let nodename = proxmox_sys::nodename().to_string();
+
+        assert_eq!(
+            trap_arguments(&trap),
+            vec![
+                "",
+                "1.3.6.1.4.1.8072.9999.9999.1.0.3",
+                "1.3.6.1.4.1.8072.9999.9999.1.1.1",
+                "s",
+                nodename.as_str(),
+                "1.3.6.1.4.1.8072.9999.9999.1.1.5",
+                "s",
+                "store1",
+                "1.3.6.1.4.1.8072.9999.9999.1.1.7",
+                "u",
+                "97",
+            ],
+        );
+    }
+}
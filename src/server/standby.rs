@@ -0,0 +1,368 @@
+//! Configuration replication to a standby node
+//!
+//! The user (including password hashes and second factors), ACL, remote (including their CA
+//! certificates) and job configuration (but no backup data) is pushed to the standby node over the
+//! API. Datastore, tape drive and media pool, traffic control and node configuration describe the
+//! storage and hardware of the node itself and are not replicated.
+//!
+//! Reading the password hashes and second factors needs root, so pushing always runs in the
+//! privileged API daemon. The standby node only stages the received configuration, so that it does not run
+//! the jobs of the primary node. Once it gets promoted, for example after the primary node failed,
+//! the staged configuration replaces its own.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use proxmox_rest_server::WorkerTask;
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+use proxmox_sys::task_log;
+
+use pbs_api_types::{Authid, Remote, REMOTE_ID_SCHEMA};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::acl::AclTree;
+
+use crate::config::node::StandbyConfig;
+use crate::config::tfa::TfaConfig;
+use crate::server::jobstate::Job;
+
+const STANDBY_CONFIG_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/standby-config.json");
+
+/// Names of the replicated configurations, in the order they get applied on promotion.
+pub const REPLICATED_CONFIGS: &[&str] = &[
+    "user",
+    "shadow",
+    "token-shadow",
+    "tfa",
+    "acl",
+    "remote",
+    "remote-ca",
+    "sync",
+    "verification",
+    "prune",
+    "tape-job",
+];
+
+/// Configuration received from the primary node
+#[derive(Serialize, Deserialize)]
+pub struct StagedConfig {
+    /// Node name of the primary node
+    pub source: String,
+    /// Time the configuration was received
+    pub received: i64,
+    /// Raw configuration files, by name
+    pub configs: HashMap<String, String>,
+}
+
+fn section_config_filename(name: &str) -> Option<&'static str> {
+    match name {
+        "user" => Some(pbs_config::user::USER_CFG_FILENAME),
+        "remote" => Some(pbs_config::remote::REMOTE_CFG_FILENAME),
+        "sync" => Some(pbs_config::sync::SYNC_CFG_FILENAME),
+        "verification" => Some(pbs_config::verify::VERIFICATION_CFG_FILENAME),
+        "prune" => Some(pbs_config::prune::PRUNE_CFG_FILENAME),
+        "tape-job" => Some(pbs_config::tape_job::TAPE_JOB_CFG_FILENAME),
+        _ => None,
+    }
+}
+
+fn read_config(name: &str) -> Result<String, Error> {
+    let filename = match name {
+        "token-shadow" => {
+            let secrets = pbs_config::token_shadow::hashed_secrets()?;
+            return Ok(serde_json::to_string(&secrets)?);
+        }
+        "shadow" => {
+            let shadow = crate::auth::read_shadow_config()?;
+            return Ok(serde_json::to_string(&shadow)?);
+        }
+        "tfa" => {
+            let _lock = crate::config::tfa::read_lock()?;
+            let tfa = crate::config::tfa::read()?;
+            return Ok(serde_json::to_string(&tfa)?);
+        }
+        "remote-ca" => {
+            let certs = pbs_config::remote::ca_certs()?;
+            return Ok(serde_json::to_string(&certs)?);
+        }
+        "acl" => pbs_config::acl::ACL_CFG_FILENAME,
+        name => section_config_filename(name)
+            .ok_or_else(|| format_err!("unknown configuration '{}'", name))?,
+    };
+
+    Ok(file_read_optional_string(filename)?.unwrap_or_default())
+}
+
+// parse all configurations before applying any, to not end up with a partially promoted node
+fn check_config(name: &str, raw: &str) -> Result<(), Error> {
+    let result = match name {
+        "token-shadow" => serde_json::from_str::<HashMap<Authid, String>>(raw)
+            .map(|_| ())
+            .map_err(|_| format_err!("unable to parse token secrets")), // don't leak secrets
+        "shadow" => serde_json::from_str::<HashMap<String, String>>(raw)
+            .map(|_| ())
+            .map_err(|_| format_err!("unable to parse password hashes")),
+        "tfa" => serde_json::from_str::<TfaConfig>(raw)
+            .map(|_| ())
+            .map_err(|_| format_err!("unable to parse second factors")),
+        "user" => pbs_config::user::CONFIG.parse(name, raw).map(|_| ()),
+        "remote" => pbs_config::remote::CONFIG.parse(name, raw).map(|_| ()),
+        "sync" => pbs_config::sync::CONFIG.parse(name, raw).map(|_| ()),
+        "verification" => pbs_config::verify::CONFIG.parse(name, raw).map(|_| ()),
+        "prune" => pbs_config::prune::CONFIG.parse(name, raw).map(|_| ()),
+        "tape-job" => pbs_config::tape_job::CONFIG.parse(name, raw).map(|_| ()),
+        "remote-ca" => check_ca_certs(raw),
+        "acl" => AclTree::from_raw(raw).map(|_| ()),
+        _ => bail!("unknown configuration '{}'", name),
+    };
+
+    result.map_err(|err| format_err!("invalid {} configuration - {}", name, err))
+}
+
+fn check_ca_certs(raw: &str) -> Result<(), Error> {
+    let certs: HashMap<String, String> = serde_json::from_str(raw)?;
+    for (remote, pem) in certs.iter() {
+        REMOTE_ID_SCHEMA
+            .parse_simple_value(remote)
+            .map_err(|err| format_err!("invalid remote name '{}' - {}", remote, err))?;
+        openssl::x509::X509::stack_from_pem(pem.as_bytes())
+            .map_err(|err| format_err!("invalid CA certificate of '{}' - {}", remote, err))?;
+    }
+    Ok(())
+}
+
+fn apply_ca_certs(raw: &str) -> Result<(), Error> {
+    let certs: HashMap<String, String> = serde_json::from_str(raw)?;
+
+    for remote in pbs_config::remote::ca_certs()?.keys() {
+        if !certs.contains_key(remote) {
+            pbs_config::remote::remove_ca_cert(remote)?;
+        }
+    }
+    for (remote, pem) in certs.iter() {
+        pbs_config::remote::save_ca_cert(remote, pem)?;
+    }
+    Ok(())
+}
+
+fn apply_config(name: &str, raw: &str) -> Result<(), Error> {
+    match name {
+        "token-shadow" => {
+            let secrets: HashMap<Authid, String> = serde_json::from_str(raw)
+                .map_err(|_| format_err!("unable to parse token secrets"))?;
+            pbs_config::token_shadow::replace_hashed_secrets(secrets)
+        }
+        "shadow" => {
+            let shadow: serde_json::Value = serde_json::from_str(raw)
+                .map_err(|_| format_err!("unable to parse password hashes"))?;
+            crate::auth::save_shadow_config(&shadow)
+        }
+        "tfa" => {
+            let _lock = crate::config::tfa::write_lock()?;
+            let tfa: TfaConfig = serde_json::from_str(raw)
+                .map_err(|_| format_err!("unable to parse second factors"))?;
+            crate::config::tfa::write(&tfa)
+        }
+        "user" => {
+            let _lock = pbs_config::user::lock_config()?;
+            let data = pbs_config::user::CONFIG.parse(name, raw)?;
+            pbs_config::user::save_config(&data)
+        }
+        "remote" => {
+            let _lock = pbs_config::remote::lock_config()?;
+            let data = pbs_config::remote::CONFIG.parse(name, raw)?;
+            pbs_config::remote::save_config(&data)
+        }
+        "sync" => {
+            let _lock = pbs_config::sync::lock_config()?;
+            let data = pbs_config::sync::CONFIG.parse(name, raw)?;
+            pbs_config::sync::save_config(&data)
+        }
+        "verification" => {
+            let _lock = pbs_config::verify::lock_config()?;
+            let data = pbs_config::verify::CONFIG.parse(name, raw)?;
+            pbs_config::verify::save_config(&data)
+        }
+        "prune" => {
+            let _lock = pbs_config::prune::lock_config()?;
+            let data = pbs_config::prune::CONFIG.parse(name, raw)?;
+            pbs_config::prune::save_config(&data)
+        }
+        "tape-job" => {
+            let _lock = pbs_config::tape_job::lock()?;
+            let data = pbs_config::tape_job::CONFIG.parse(name, raw)?;
+            pbs_config::tape_job::save_config(&data)
+        }
+        "remote-ca" => {
+            let _lock = pbs_config::remote::lock_config()?;
+            apply_ca_certs(raw)
+        }
+        "acl" => {
+            let _lock = pbs_config::acl::lock_config()?;
+            let tree = AclTree::from_raw(raw)?;
+            pbs_config::acl::save_config(&tree)
+        }
+        _ => bail!("unknown configuration '{}'", name),
+    }
+}
+
+/// Read all replicated configurations of this node.
+pub fn collect_configs() -> Result<HashMap<String, String>, Error> {
+    let mut configs = HashMap::new();
+    for name in REPLICATED_CONFIGS {
+        configs.insert(name.to_string(), read_config(name)?);
+    }
+    Ok(configs)
+}
+
+/// Returns the configuration received from the primary node, if any.
+pub fn staged_config() -> Result<Option<StagedConfig>, Error> {
+    match file_read_optional_string(STANDBY_CONFIG_FN)? {
+        Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/// Stage the configuration received from the primary node `source`.
+pub fn receive_configs(source: String, configs: HashMap<String, String>) -> Result<(), Error> {
+    for (name, raw) in configs.iter() {
+        check_config(name, raw)?;
+    }
+
+    let staged = StagedConfig {
+        source,
+        received: proxmox_time::epoch_i64(),
+        configs,
+    };
+
+    // contains secrets, like the remote passwords
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(nix::unistd::ROOT)
+        .group(nix::unistd::Gid::from_raw(0));
+
+    replace_file(
+        STANDBY_CONFIG_FN,
+        serde_json::to_string(&staged)?.as_bytes(),
+        options,
+        true,
+    )
+}
+
+/// Promote this node, replacing its configuration with the one received from the primary node.
+pub fn promote_standby() -> Result<(), Error> {
+    let staged = match staged_config()? {
+        Some(staged) => staged,
+        None => bail!("no configuration received from a primary node"),
+    };
+
+    for (name, raw) in staged.configs.iter() {
+        check_config(name, raw)?;
+    }
+
+    for name in REPLICATED_CONFIGS {
+        if let Some(raw) = staged.configs.get(*name) {
+            apply_config(name, raw)?;
+        }
+    }
+
+    // don't apply the (then outdated) configuration again on a later promotion
+    std::fs::remove_file(STANDBY_CONFIG_FN)?;
+
+    Ok(())
+}
+
+async fn push_configs(worker: &WorkerTask, remote: &str) -> Result<(), Error> {
+    let (config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = config.lookup("remote", remote)?;
+
+    let configs = collect_configs()?;
+
+    task_log!(
+        worker,
+        "replicating configuration ({}) to standby node '{}'",
+        REPLICATED_CONFIGS.join(", "),
+        remote.name,
+    );
+
+    let client = crate::api2::config::remote::remote_client(&remote, None).await?;
+    let param = json!({
+        "source": proxmox_sys::nodename(),
+        "configs": serde_json::to_string(&configs)?,
+    });
+    client
+        .put("api2/json/config/standby", Some(param))
+        .await
+        .map_err(|err| format_err!("standby node rejected the configuration - {}", err))?;
+
+    task_log!(worker, "configuration replicated");
+
+    Ok(())
+}
+
+/// Starts the scheduled standby push job.
+///
+/// Runs in the privileged API daemon, which gets asked to do so by the proxy's scheduler through
+/// its command socket, see [`request_scheduled_standby_push`].
+pub fn start_scheduled_standby_push(schedule: String) -> Result<String, Error> {
+    let (config, _digest) = crate::config::node::config()?;
+    let standby = match config.standby_config() {
+        Some(standby) => standby?,
+        None => bail!("no standby node configured"),
+    };
+
+    let job = Job::new("standby-push", "standby")?;
+
+    do_standby_push_job(job, standby, Authid::root_auth_id(), Some(schedule), false)
+}
+
+/// Ask the privileged API daemon to start the scheduled standby push job.
+pub async fn request_scheduled_standby_push(schedule: &str) -> Result<(), Error> {
+    let api_pid = proxmox_rest_server::read_pid(pbs_buildcfg::PROXMOX_BACKUP_API_PID_FN)?;
+    let sock = proxmox_rest_server::ctrl_sock_from_pid(api_pid);
+    let command = json!({
+        "command": "standby-push",
+        "args": { "schedule": schedule },
+    });
+    let _: Value = proxmox_rest_server::send_raw_command(sock, &format!("{}\n", command)).await?;
+    Ok(())
+}
+
+/// Runs the standby push job, replicating the configuration to the standby node.
+pub fn do_standby_push_job(
+    mut job: Job,
+    standby: StandbyConfig,
+    auth_id: &Authid,
+    schedule: Option<String>,
+    to_stdout: bool,
+) -> Result<String, Error> {
+    let worker_type = job.jobtype().to_string();
+    let upid_str = WorkerTask::spawn(
+        &worker_type,
+        Some(standby.remote.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| async move {
+            job.start(&worker.upid().to_string())?;
+
+            if let Some(event_str) = schedule {
+                task_log!(worker, "task triggered by schedule '{}'", event_str);
+            }
+
+            let result = push_configs(&worker, &standby.remote).await;
+
+            let status = worker.create_state(&result);
+
+            if let Err(err) = job.finish(status) {
+                eprintln!("could not finish job state for {}: {}", job.jobtype(), err);
+            }
+
+            result
+        },
+    )?;
+
+    Ok(upid_str)
+}
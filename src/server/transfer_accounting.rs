@@ -0,0 +1,114 @@
+//! Per-client transfer accounting
+//!
+//! The backup and reader protocol handlers record the transferred bytes of each session here,
+//! aggregated per `Authid` and source IP. The totals are kept in a state file, so they survive
+//! daemon restarts and can be queried from any process.
+
+use std::net::IpAddr;
+
+use anyhow::Error;
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{Authid, ClientTransferStats};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::{open_backup_lockfile, BackupLockGuard};
+
+const TRANSFER_ACCOUNTING_FN: &str =
+    concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/transfer-accounting.json");
+const TRANSFER_ACCOUNTING_LOCK_FN: &str =
+    concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/.transfer-accounting.lck");
+
+/// Kind of session the transferred bytes belong to.
+pub enum TransferSession {
+    Backup,
+    Reader,
+}
+
+fn lock_transfer_accounting() -> Result<BackupLockGuard, Error> {
+    open_backup_lockfile(TRANSFER_ACCOUNTING_LOCK_FN, None, true)
+}
+
+fn read_entries() -> Result<Vec<ClientTransferStats>, Error> {
+    match file_read_optional_string(TRANSFER_ACCOUNTING_FN)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Returns the accumulated transfer statistics of all clients.
+pub fn read_transfer_accounting() -> Result<Vec<ClientTransferStats>, Error> {
+    let _lock = lock_transfer_accounting()?;
+    read_entries()
+}
+
+/// Add the bytes transferred by a finished session to the totals of its client.
+pub fn record_transfer(
+    auth_id: &Authid,
+    client_ip: Option<IpAddr>,
+    session: TransferSession,
+    transferred: u64,
+) -> Result<(), Error> {
+    let _lock = lock_transfer_accounting()?;
+
+    let mut entries = read_entries()?;
+
+    let client_ip = client_ip.map(|ip| ip.to_string());
+
+    let pos = entries
+        .iter()
+        .position(|entry| &entry.auth_id == auth_id && entry.client_ip == client_ip);
+
+    let entry = match pos {
+        Some(pos) => &mut entries[pos],
+        None => {
+            entries.push(ClientTransferStats {
+                auth_id: auth_id.clone(),
+                client_ip,
+                uploaded: 0,
+                downloaded: 0,
+                backup_sessions: 0,
+                reader_sessions: 0,
+                last_seen: 0,
+            });
+            entries.last_mut().unwrap()
+        }
+    };
+
+    match session {
+        TransferSession::Backup => {
+            entry.uploaded += transferred;
+            entry.backup_sessions += 1;
+        }
+        TransferSession::Reader => {
+            entry.downloaded += transferred;
+            entry.reader_sessions += 1;
+        }
+    }
+    entry.last_seen = proxmox_time::epoch_i64();
+
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(
+        TRANSFER_ACCOUNTING_FN,
+        serde_json::to_string(&entries)?.as_bytes(),
+        options,
+        false,
+    )
+}
+
+/// Reset the accumulated transfer statistics of all clients.
+pub fn reset_transfer_accounting() -> Result<(), Error> {
+    let _lock = lock_transfer_accounting()?;
+    if let Err(err) = std::fs::remove_file(TRANSFER_ACCOUNTING_FN) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! Account expiry warnings
+//!
+//! The daily update checks the user configuration for users and API tokens which expire within
+//! the configured number of days, and warns the affected users by email. Every expiration date
+//! gets only warned about once, so changing it results in a new warning.
+
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{ApiToken, Authid, ExpiringAccount, User, Userid};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+
+use crate::server::send_user_expiry_notification;
+
+const USER_EXPIRY_STATE_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/user-expiry.json");
+
+// auth id => expiration date a warning was sent for
+type UserExpiryState = HashMap<Authid, i64>;
+
+fn load_state() -> Result<UserExpiryState, Error> {
+    match file_read_optional_string(USER_EXPIRY_STATE_FN)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn save_state(state: &UserExpiryState) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(
+        USER_EXPIRY_STATE_FN,
+        serde_json::to_string(state)?.as_bytes(),
+        options,
+        false,
+    )
+}
+
+// returns the expiration date of an enabled account which expires after `now`, within `days` days
+fn expires_within(enable: Option<bool>, expire: Option<i64>, now: i64, days: u64) -> Option<i64> {
+    let until = now + (days as i64) * 24 * 3600;
+    match expire {
+        Some(expire) if enable.unwrap_or(true) && expire > now && expire <= until => Some(expire),
+        _ => None,
+    }
+}
+
+/// Returns the enabled users and API tokens which expire within the next `days` days, ordered by
+/// their expiration date.
+pub fn expiring_accounts(days: u64) -> Result<Vec<ExpiringAccount>, Error> {
+    let (config, _digest) = pbs_config::user::config()?;
+
+    let now = proxmox_time::epoch_i64();
+    let expiring = |enable, expire| expires_within(enable, expire, now, days);
+
+    let mut list = Vec::new();
+
+    let users: Vec<User> = config.convert_to_typed_array("user")?;
+    for user in users {
+        if let Some(expire) = expiring(user.enable, user.expire) {
+            list.push(ExpiringAccount {
+                authid: user.userid.into(),
+                expire,
+            });
+        }
+    }
+
+    let tokens: Vec<ApiToken> = config.convert_to_typed_array("token")?;
+    for token in tokens {
+        if let Some(expire) = expiring(token.enable, token.expire) {
+            list.push(ExpiringAccount {
+                authid: token.tokenid,
+                expire,
+            });
+        }
+    }
+
+    list.sort_by_key(|account| account.expire);
+
+    Ok(list)
+}
+
+/// Warn the users whose account or API tokens expire soon.
+pub fn check_user_expiry() -> Result<(), Error> {
+    let (node_config, _digest) = crate::config::node::config()?;
+    let days = match node_config.user_expiry_warning() {
+        Some(days) => days,
+        None => return Ok(()),
+    };
+
+    let accounts = expiring_accounts(days)?;
+
+    let old_state = load_state()?;
+    let (mut state, pending) = pending_warnings(&accounts, &old_state);
+
+    for (userid, accounts) in pending {
+        if let Err(err) = send_user_expiry_notification(&userid, &accounts) {
+            // try again on the next check
+            log::error!("sending expiry warning to '{}' failed - {}", userid, err);
+            continue;
+        }
+        for account in accounts {
+            state.insert(account.authid.clone(), account.expire);
+        }
+    }
+
+    save_state(&state)
+}
+
+// splits the `accounts` into the ones already warned about with their current expiration date,
+// which are kept in the new state, and the ones to warn about, grouped by user
+fn pending_warnings<'a>(
+    accounts: &'a [ExpiringAccount],
+    old_state: &UserExpiryState,
+) -> (UserExpiryState, HashMap<Userid, Vec<&'a ExpiringAccount>>) {
+    let mut state = UserExpiryState::new();
+    let mut pending: HashMap<Userid, Vec<&ExpiringAccount>> = HashMap::new();
+
+    for account in accounts.iter() {
+        if old_state.get(&account.authid) == Some(&account.expire) {
+            state.insert(account.authid.clone(), account.expire);
+            continue;
+        }
+        pending
+            .entry(account.authid.user().clone())
+            .or_default()
+            .push(account);
+    }
+
+    (state, pending)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600;
+
+    #[test]
+    fn test_expires_within() {
+        let now = 1_700_000_000;
+
+        assert_eq!(
+            expires_within(None, Some(now + DAY), now, 7),
+            Some(now + DAY)
+        );
+        // the window includes its last second
+        assert_eq!(
+            expires_within(Some(true), Some(now + 7 * DAY), now, 7),
+            Some(now + 7 * DAY)
+        );
+        assert_eq!(expires_within(None, Some(now + 7 * DAY + 1), now, 7), None);
+        // already expired, never expiring and disabled accounts get no warning
+        assert_eq!(expires_within(None, Some(now), now, 7), None);
+        assert_eq!(expires_within(None, Some(now - DAY), now, 7), None);
+        assert_eq!(expires_within(None, None, now, 7), None);
+        assert_eq!(expires_within(Some(false), Some(now + DAY), now, 7), None);
+        // a window of zero days warns about nothing
+        assert_eq!(expires_within(None, Some(now + 1), now, 0), None);
+    }
+
+    #[test]
+    fn test_pending_warnings() {
+        let account = |authid: &str, expire| ExpiringAccount {
+            authid: authid.parse().unwrap(),
+            expire,
+        };
+        let accounts = vec![
+            account("user1@pbs", 1000),
+            account("user1@pbs!token1", 2000),
+            account("user2@pbs", 3000),
+        ];
+
+        let mut old_state = UserExpiryState::new();
+        // warned about before, with the same date
+        old_state.insert("user1@pbs".parse().unwrap(), 1000);
+        // warned about before, but the expiration date changed since
+        old_state.insert("user2@pbs".parse().unwrap(), 2500);
+        // no longer expiring soon
+        old_state.insert("user3@pbs".parse().unwrap(), 500);
+
+        let (state, pending) = pending_warnings(&accounts, &old_state);
+
+        assert_eq!(state.len(), 1);
+        assert_eq!(state.get(&"user1@pbs".parse().unwrap()), Some(&1000));
+
+        let user1: Userid = "user1@pbs".parse().unwrap();
+        let user2: Userid = "user2@pbs".parse().unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(
+            pending[&user1]
+                .iter()
+                .map(|account| account.expire)
+                .collect::<Vec<_>>(),
+            vec![2000]
+        );
+        assert_eq!(
+            pending[&user2]
+                .iter()
+                .map(|account| account.expire)
+                .collect::<Vec<_>>(),
+            vec![3000]
+        );
+    }
+}
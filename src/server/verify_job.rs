@@ -57,6 +57,8 @@ pub fn do_verification_job(
                     verify_filter(ignore_verified_snapshots, outdated_after, manifest)
                 }),
             );
+            verify_worker.log_chunk_summary();
+            job.set_run_statistics(None, Some(verify_worker.verified_snapshot_count() as u64));
             let job_result = match result {
                 Ok(ref failed_dirs) if failed_dirs.is_empty() => Ok(()),
                 Ok(ref failed_dirs) => {
@@ -74,7 +76,23 @@ pub fn do_verification_job(
 
             let status = worker.create_state(&job_result);
 
-            if let Err(err) = job.finish(status) {
+            // corrupt snapshots are reported with their own trap instead of a generic job failure
+            let finish_result = match result {
+                Ok(ref failed_dirs) if !failed_dirs.is_empty() => {
+                    let trap = crate::server::SnmpTrap::VerificationFailed {
+                        store: &verification_job.store,
+                        jobname: job.jobname(),
+                        errors: failed_dirs.len(),
+                    };
+                    if let Err(err) = crate::server::send_snmp_trap(&trap) {
+                        eprintln!("send verify SNMP trap failed: {}", err);
+                    }
+                    job.finish_without_trap(status)
+                }
+                _ => job.finish(status),
+            };
+
+            if let Err(err) = finish_result {
                 eprintln!("could not finish job state for {}: {}", job.jobtype(), err);
             }
 
@@ -0,0 +1,126 @@
+//! ZFS pool health monitoring
+//!
+//! The proxy daemon checks the health of all ZFS pools backing a datastore once a minute and
+//! sends a notification whenever a pool changes its state, for example when it loses redundancy
+//! or is back online.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Error;
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+
+use crate::server::{lookup_datastore_notify_settings, send_zpool_health_notification};
+use crate::tools::disks::{zpool_for_path, zpool_health};
+
+const ZPOOL_HEALTH_STATE_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/zpool-health.json");
+
+// pool name => health on the last check
+type ZpoolHealthState = HashMap<String, String>;
+
+fn load_state() -> Result<ZpoolHealthState, Error> {
+    match file_read_optional_string(ZPOOL_HEALTH_STATE_FN)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn save_state(state: &ZpoolHealthState) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(
+        ZPOOL_HEALTH_STATE_FN,
+        serde_json::to_string(state)?.as_bytes(),
+        options,
+        false,
+    )
+}
+
+/// Returns the ZFS pools backing datastores, with the names of the datastores on each pool.
+fn datastore_pools() -> Result<HashMap<String, Vec<String>>, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+
+    let mut pools: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (store, (_, store_config)) in config.sections {
+        let path = match store_config["path"].as_str() {
+            Some(path) => path,
+            None => continue,
+        };
+        match zpool_for_path(Path::new(path)) {
+            Ok(Some(pool)) => pools.entry(pool).or_default().push(store),
+            Ok(None) => (),
+            Err(err) => eprintln!("could not get zpool of datastore '{}' - {}", store, err),
+        }
+    }
+
+    Ok(pools)
+}
+
+/// Check the health of all ZFS pools backing a datastore and notify about state changes.
+///
+/// A pool which is not online on the first check is reported as well. Pools whose datastores have
+/// no email address to notify are skipped.
+pub fn check_zpool_health() -> Result<(), Error> {
+    let pools = datastore_pools()?;
+
+    let mut old_state = load_state()?;
+    let mut state = ZpoolHealthState::new();
+
+    for (pool, mut datastores) in pools {
+        let previous = old_state.remove(&pool);
+
+        // nobody to notify, no need to run 'zpool status'
+        if !datastores
+            .iter()
+            .any(|store| lookup_datastore_notify_settings(store).0.is_some())
+        {
+            continue;
+        }
+
+        let health = match zpool_health(pool.clone()) {
+            Ok(health) => health,
+            Err(err) => {
+                eprintln!("could not get health of zpool '{}' - {}", pool, err);
+                if let Some(previous) = previous {
+                    state.insert(pool, previous);
+                }
+                continue;
+            }
+        };
+
+        let changed = match &previous {
+            Some(previous) => previous != &health.health,
+            None => !health.is_online(),
+        };
+
+        if changed {
+            datastores.sort();
+            if let Err(err) =
+                send_zpool_health_notification(&health, previous.as_deref(), &datastores)
+            {
+                eprintln!(
+                    "sending zpool health notification for '{}' failed - {}",
+                    pool, err
+                );
+                // try again on the next check
+                if let Some(previous) = previous {
+                    state.insert(pool, previous);
+                }
+                continue;
+            }
+        }
+
+        state.insert(pool, health.health);
+    }
+
+    save_state(&state)
+}
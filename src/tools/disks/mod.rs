@@ -491,6 +491,8 @@ impl Disk {
                 write_ios: stat[4] + stat[11],     // write + discard
                 write_sectors: stat[6] + stat[13], // write + discard
                 io_ticks: stat[10],
+                read_ticks: Some(stat[3]),
+                write_ticks: Some(stat[7] + stat[14]), // write + discard
             }));
         }
         Ok(None)
@@ -554,6 +556,10 @@ pub struct BlockDevStat {
     pub write_ios: u64,
     pub write_sectors: u64,
     pub io_ticks: u64, // milliseconds
+    /// Total time spent waiting for reads in milliseconds, if available.
+    pub read_ticks: Option<u64>,
+    /// Total time spent waiting for writes (including discards) in milliseconds, if available.
+    pub write_ticks: Option<u64>,
 }
 
 /// Use lsblk to read partition type uuids and file system types.
@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Error};
@@ -8,6 +8,8 @@ use lazy_static::lazy_static;
 
 use proxmox_schema::const_regex;
 
+use pbs_api_types::{ZfsDatasetProperties, ZpoolHealth};
+
 use super::*;
 
 lazy_static! {
@@ -64,6 +66,9 @@ pub fn zfs_pool_stats(pool: &OsStr) -> Result<Option<BlockDevStat>, Error> {
         read_ios: stat[2],
         write_ios: stat[3],
         io_ticks: ticks,
+        // the pool kstats do not differentiate between read and write time
+        read_ticks: None,
+        write_ticks: None,
     };
 
     Ok(Some(stat))
@@ -134,6 +139,8 @@ fn parse_objset_stat(pool: &str, objset_id: &str) -> Result<(String, BlockDevSta
         read_ios: 0,
         write_ios: 0,
         io_ticks: 0,
+        read_ticks: None,
+        write_ticks: None,
     };
 
     for (i, line) in text.lines().enumerate() {
@@ -207,3 +214,122 @@ pub fn zfs_dataset_stats(dataset: &str) -> Result<BlockDevStat, Error> {
         }
     }
 }
+
+/// Returns the name of the ZFS dataset `path` is located on, or `None` if it is not on ZFS.
+pub fn zfs_dataset_for_path(path: &Path) -> Result<Option<String>, Error> {
+    match DiskManage::new().find_mounted_device(path)? {
+        Some((fs_type, _device, Some(source))) if fs_type == "zfs" => {
+            let dataset = source
+                .into_string()
+                .map_err(|source| format_err!("invalid dataset name {:?}", source))?;
+            Ok(Some(dataset))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Returns the name of the ZFS pool `path` is located on, or `None` if it is not on ZFS.
+pub fn zpool_for_path(path: &Path) -> Result<Option<String>, Error> {
+    Ok(zfs_dataset_for_path(path)?.map(|dataset| get_pool_from_dataset(&dataset).to_string()))
+}
+
+/// Returns the properties of a ZFS dataset, as reported by 'zfs get'.
+pub fn zfs_dataset_properties(dataset: &str) -> Result<ZfsDatasetProperties, Error> {
+    let mut command = std::process::Command::new("zfs");
+    command.args(&[
+        "get",
+        "-H",
+        "-p",
+        "-o",
+        "property,value",
+        "compression,atime,relatime,recordsize,special_small_blocks",
+        dataset,
+    ]);
+
+    let output = proxmox_sys::command::run_command(command, None)?;
+
+    let mut properties = ZfsDatasetProperties::default();
+
+    for line in output.lines() {
+        let (property, value) = match line.split_once('\t') {
+            Some(pair) => pair,
+            None => bail!("unable to parse 'zfs get' output line '{}'", line),
+        };
+        match property {
+            // levels like 'zstd-3' cannot be represented, so leave them out
+            "compression" => properties.compression = value.parse().ok(),
+            "atime" => properties.atime = Some(value == "on"),
+            "relatime" => properties.relatime = Some(value == "on"),
+            "recordsize" => properties.recordsize = Some(value.parse()?),
+            "special_small_blocks" => properties.special_small_blocks = Some(value.parse()?),
+            _ => (),
+        }
+    }
+
+    Ok(properties)
+}
+
+/// Checks that `properties` can be used for a dataset containing a datastore.
+pub fn check_datastore_zfs_properties(properties: &ZfsDatasetProperties) -> Result<(), Error> {
+    if properties.atime == Some(false) {
+        bail!("garbage collection relies on the access time of chunks, 'atime' cannot be disabled");
+    }
+    Ok(())
+}
+
+/// Returns the health of the ZFS pool `path` is located on, or `None` if it is not on ZFS.
+pub fn zpool_health_for_path(path: &Path) -> Result<Option<ZpoolHealth>, Error> {
+    match zpool_for_path(path)? {
+        Some(pool) => Ok(Some(zpool_health(pool)?)),
+        None => Ok(None),
+    }
+}
+
+// 'zpool status' can take a while, reuse its results for the datastore status API calls and the
+// health monitor for a short time
+const ZPOOL_HEALTH_CACHE_TIME: i64 = 30;
+
+lazy_static::lazy_static! {
+    static ref ZPOOL_HEALTH_CACHE: Mutex<HashMap<String, (i64, ZpoolHealth)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the health of a ZFS pool, as reported by 'zpool status'.
+///
+/// The result is cached for a few seconds.
+pub fn zpool_health(pool: String) -> Result<ZpoolHealth, Error> {
+    let now = proxmox_time::epoch_i64();
+
+    if let Some((time, health)) = ZPOOL_HEALTH_CACHE.lock().unwrap().get(&pool) {
+        if (now - time).abs() < ZPOOL_HEALTH_CACHE_TIME {
+            return Ok(health.clone());
+        }
+    }
+
+    let health = zpool_health_uncached(pool.clone())?;
+    ZPOOL_HEALTH_CACHE
+        .lock()
+        .unwrap()
+        .insert(pool, (now, health.clone()));
+
+    Ok(health)
+}
+
+fn zpool_health_uncached(pool: String) -> Result<ZpoolHealth, Error> {
+    let mut health = None;
+    let mut status = None;
+
+    for (key, value) in zpool_status(&pool)? {
+        match key.as_str() {
+            "state" => health = Some(value),
+            "status" => status = Some(value),
+            _ => (),
+        }
+    }
+
+    Ok(ZpoolHealth {
+        pool,
+        health: health.unwrap_or_else(|| "UNKNOWN".to_string()),
+        status,
+    })
+}
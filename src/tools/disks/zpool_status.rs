@@ -11,7 +11,7 @@ use pbs_tools::nom::{
 
 use nom::{
     bytes::complete::{tag, take_while, take_while1},
-    character::complete::line_ending,
+    character::complete::{line_ending, space1},
     combinator::opt,
     multi::{many0, many1},
     sequence::preceded,
@@ -66,8 +66,10 @@ fn parse_zpool_status_vdev(i: &str) -> IResult<&str, ZFSPoolVDevState> {
     }
 
     let (i, state) = preceded(multispace1, notspace1)(i)?;
-    if let Ok((n, _)) = preceded(multispace0, line_ending)(i) {
-        // spares
+    if preceded(space1, parse_u64)(i).is_err() {
+        // spares, with a message like "currently in use" if they are in use
+        let (i, msg) = opt(preceded(space1, take_while1(|c| c != '\n')))(i)?;
+        let (n, _) = preceded(multispace0, line_ending)(i)?;
         let vdev = ZFSPoolVDevState {
             name: vdev_name.to_string(),
             lvl: indent_level,
@@ -75,7 +77,7 @@ fn parse_zpool_status_vdev(i: &str) -> IResult<&str, ZFSPoolVDevState> {
             read: None,
             write: None,
             cksum: None,
-            msg: None,
+            msg: msg.map(String::from),
         };
         return Ok((n, vdev));
     }
@@ -197,6 +199,54 @@ fn parse_zpool_status(input: &str) -> Result<Vec<(String, String)>, Error> {
     parse_complete("zfs status output", input, many0(parse_zpool_status_field))
 }
 
+/// Returns the hot spares of a pool with their state (`AVAIL`, `INUSE`, ...).
+pub fn zpool_spares(vdev_list: &[ZFSPoolVDevState]) -> Vec<(String, Option<String>)> {
+    let mut spares = Vec::new();
+    let mut in_spares = false;
+
+    for vdev in vdev_list {
+        if vdev.lvl == 0 {
+            in_spares = vdev.name == "spares";
+        } else if in_spares {
+            spares.push((vdev.name.clone(), vdev.state.clone()));
+        }
+    }
+
+    spares
+}
+
+/// State of the last resilver of a pool
+#[derive(Debug, PartialEq)]
+pub enum ResilverState<'a> {
+    /// A resilver is running, with its progress (like `45.67% done`) once it is reported
+    Running(Option<&'a str>),
+    /// The last resilver finished
+    Finished,
+    /// No resilver was started, or the last scan was a scrub
+    Idle,
+}
+
+/// Returns the state of the last resilver, parsed from the `scan` field of 'zpool status'.
+pub fn resilver_state(scan: &str) -> ResilverState {
+    if scan.starts_with("resilvered") {
+        return ResilverState::Finished;
+    }
+    if !scan.starts_with("resilver in progress") {
+        return ResilverState::Idle;
+    }
+
+    let progress = scan.find("% done").map(|pos| {
+        let end = pos + "% done".len();
+        let start = scan[..pos]
+            .rfind(|c: char| c.is_whitespace() || c == ',')
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        &scan[start..end]
+    });
+
+    ResilverState::Running(progress)
+}
+
 pub fn vdev_list_to_tree(vdev_list: &[ZFSPoolVDevState]) -> Result<Value, Error> {
     indented_list_to_tree(vdev_list, |vdev| {
         let node = serde_json::to_value(vdev).unwrap();
@@ -496,3 +546,66 @@ errors: No known data errors
 
     test_parse(output)
 }
+
+#[test]
+fn test_zpool_spares() -> Result<(), Error> {
+    let output = r###"  pool: tank
+ state: DEGRADED
+  scan: resilvered 10737418240 in 00:01:00 with 0 errors on Fri Oct 16 10:01:00 2026
+config:
+
+	NAME             STATE     READ WRITE CKSUM
+	tank             DEGRADED     0     0     0
+	  mirror-0       DEGRADED     0     0     0
+	    /dev/sda1    ONLINE       0     0     0
+	    spare-1      DEGRADED     0     0     0
+	      /dev/sda2  FAULTED      0     0     0
+	      /dev/sdb   ONLINE       0     0     0
+	logs
+	  /dev/sda5      ONLINE       0     0     0
+	spares
+	  /dev/sdb       INUSE     currently in use
+	  /dev/sdc       AVAIL
+
+errors: No known data errors
+"###;
+
+    let config = parse_zpool_status(output)?
+        .into_iter()
+        .find(|(key, _)| key == "config")
+        .map(|(_, value)| value)
+        .unwrap();
+    let spares = zpool_spares(&parse_zpool_status_config_tree(&config)?);
+    assert_eq!(
+        spares,
+        vec![
+            ("/dev/sdb".to_string(), Some("INUSE".to_string())),
+            ("/dev/sdc".to_string(), Some("AVAIL".to_string())),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_resilver_state() {
+    let scan = "resilver in progress since Fri Oct 16 10:00:00 2026\n\
+        \t1610612736 scanned at 268435456/s, 1073741824 issued at 178956970/s, 10737418240 total\n\
+        \t1073741824 resilvered, 10.00% done, 00:00:53 to go";
+    assert_eq!(
+        resilver_state(scan),
+        ResilverState::Running(Some("10.00% done"))
+    );
+
+    // right after starting, zpool does not report any progress yet
+    let scan = "resilver in progress since Fri Oct 16 10:00:00 2026\n\
+        \t0B scanned at 0B/s, 0B issued at 0B/s, 10737418240 total";
+    assert_eq!(resilver_state(scan), ResilverState::Running(None));
+
+    let scan = "resilvered 10737418240 in 00:01:00 with 0 errors on Fri Oct 16 10:01:00 2026";
+    assert_eq!(resilver_state(scan), ResilverState::Finished);
+
+    let scan = "scrub repaired 0B in 00:10:00 with 0 errors on Sun Oct 11 00:34:01 2026";
+    assert_eq!(resilver_state(scan), ResilverState::Idle);
+    assert_eq!(resilver_state(""), ResilverState::Idle);
+}
@@ -4,6 +4,8 @@ use std::path::PathBuf;
 use anyhow::{format_err, Error};
 use tokio::task::spawn_blocking;
 
+use pbs_api_types::ZpoolHealth;
+
 /// `proxmox_sys::fs::fs_into` wrapped in a `spawn_blocking` call.
 pub async fn fs_info(path: PathBuf) -> Result<proxmox_sys::fs::FileSystemInformation, Error> {
     Ok(spawn_blocking(move || proxmox_sys::fs::fs_info(&path))
@@ -22,3 +24,22 @@ pub async fn fs_info_static(
         .await
         .map_err(|err| format_err!("error waiting for fs_info call: {err}"))??)
 }
+
+/// Returns the total and used inode counts of the file system containing `path`.
+pub async fn fs_inode_info(path: PathBuf) -> Result<(u64, u64), Error> {
+    spawn_blocking(move || {
+        let stat = nix::sys::statvfs::statvfs(&path)?;
+        let total = stat.files() as u64;
+        let used = total.saturating_sub(stat.files_free() as u64);
+        Ok((total, used))
+    })
+    .await
+    .map_err(|err| format_err!("error waiting for statvfs call: {err}"))?
+}
+
+/// `zpool_health_for_path` wrapped in a `spawn_blocking` call.
+pub async fn zpool_health_for_path(path: PathBuf) -> Result<Option<ZpoolHealth>, Error> {
+    spawn_blocking(move || crate::tools::disks::zpool_health_for_path(&path))
+        .await
+        .map_err(|err| format_err!("error waiting for zpool status call: {err}"))?
+}
@@ -7,6 +7,7 @@ use anyhow::{bail, Error};
 
 use proxmox_http::{client::Client, HttpOptions, ProxyConfig};
 
+pub mod apidoc;
 pub mod apt;
 pub mod config;
 pub mod disks;
@@ -12,9 +12,7 @@ use cidr::IpInet;
 use proxmox_http::client::{RateLimiter, ShareableRateLimit};
 use proxmox_section_config::SectionConfigData;
 
-use proxmox_time::{parse_daily_duration, DailyDuration, TmEditor};
-
-use pbs_api_types::TrafficControlRule;
+use pbs_api_types::{Authid, HumanByte, TrafficControlRule, TrafficControlTimeframe};
 
 use pbs_config::ConfigVersionCache;
 
@@ -29,9 +27,11 @@ lazy_static::lazy_static! {
 }
 
 struct ParsedTcRule {
-    config: TrafficControlRule,    // original rule config
-    networks: Vec<IpInet>,         // parsed networks
-    timeframe: Vec<DailyDuration>, // parsed timeframe
+    config: TrafficControlRule,              // original rule config
+    networks: Vec<IpInet>,                   // parsed networks
+    timeframe: Vec<TrafficControlTimeframe>, // parsed timeframe
+    datastores: Vec<String>,                 // matching datastores, any if empty
+    auth_ids: Vec<Authid>,                   // matching users and tokens, any if empty
 }
 
 /// Traffic control statistics
@@ -44,6 +44,8 @@ pub struct TrafficStat {
     pub traffic_out: u64,
     /// Outgoing data rate (bytes/second)
     pub rate_out: u64,
+    /// Number of connections currently limited by the rule
+    pub connections: u64,
 }
 
 /// Cache rules from `/etc/proxmox-backup/traffic-control.cfg`
@@ -57,17 +59,79 @@ pub struct TrafficControlCache {
     last_traffic_control_generation: usize,
     rules: Vec<ParsedTcRule>,
     limiter_map: HashMap<String, (Option<SharedRateLimit>, Option<SharedRateLimit>)>,
+    // limiters of rules which are not shared, per connection
+    connection_limiter_map:
+        HashMap<(String, SocketAddr), (Option<SharedRateLimit>, Option<SharedRateLimit>)>,
+    // traffic of closed connections, for rules which are not shared
+    closed_traffic_map: HashMap<String, (u64, u64)>,
+    // backup or reader session on a connection
+    peer_session_map: HashMap<SocketAddr, PeerSession>,
     use_utc: bool, // currently only used for testing
 }
 
-fn timeframe_match(duration_list: &[DailyDuration], now: &TmEditor) -> bool {
-    if duration_list.is_empty() {
+struct PeerSession {
+    datastore: String,
+    auth_id: Authid,
+}
+
+/// Registers the datastore and user of a session on a client connection, so that datastore and
+/// user specific rules apply to it. The registration is removed when the guard is dropped.
+pub struct PeerSessionGuard {
+    peer: SocketAddr,
+}
+
+impl Drop for PeerSessionGuard {
+    fn drop(&mut self) {
+        TRAFFIC_CONTROL_CACHE
+            .lock()
+            .unwrap()
+            .peer_session_map
+            .remove(&self.peer);
+    }
+}
+
+/// Register that the connection from `peer` is used by `auth_id` to access `store`.
+pub fn register_peer_session(peer: SocketAddr, store: &str, auth_id: &Authid) -> PeerSessionGuard {
+    TRAFFIC_CONTROL_CACHE
+        .lock()
+        .unwrap()
+        .peer_session_map
+        .insert(
+            peer,
+            PeerSession {
+                datastore: store.to_string(),
+                auth_id: auth_id.clone(),
+            },
+        );
+
+    PeerSessionGuard { peer }
+}
+
+// Returns the match priority, an exact match wins over a match of the token's user.
+fn auth_id_match(auth_ids: &[Authid], auth_id: &Authid) -> Option<u8> {
+    if auth_ids.contains(auth_id) {
+        return Some(2);
+    }
+    // a rule for a user also matches the user's API tokens
+    if auth_ids
+        .iter()
+        .any(|rule_auth_id| !rule_auth_id.is_token() && rule_auth_id.user() == auth_id.user())
+    {
+        return Some(1);
+    }
+    None
+}
+
+fn timeframe_match(timeframe_list: &[TrafficControlTimeframe], now: i64, use_utc: bool) -> bool {
+    if timeframe_list.is_empty() {
         return true;
     }
 
-    for duration in duration_list.iter() {
-        if duration.time_match_with_tm_editor(now) {
-            return true;
+    for timeframe in timeframe_list.iter() {
+        match timeframe.epoch_match(now, use_utc) {
+            Ok(true) => return true,
+            Ok(false) => (),
+            Err(err) => log::error!("timeframe_match: unable to convert time - {}", err),
         }
     }
 
@@ -120,12 +184,45 @@ fn create_limiter(
     }
 }
 
+fn create_connection_limiter(
+    rate: Option<HumanByte>,
+    burst: Option<HumanByte>,
+) -> Option<SharedRateLimit> {
+    rate.map(|rate| -> SharedRateLimit {
+        let burst = burst.unwrap_or(rate);
+        Arc::new(Mutex::new(RateLimiter::new(rate.as_u64(), burst.as_u64())))
+    })
+}
+
+// Returns false if the limiter cannot be updated in place and needs to be recreated.
+fn update_connection_limiter(
+    limiter: &Option<SharedRateLimit>,
+    rate: Option<HumanByte>,
+    burst: Option<HumanByte>,
+) -> bool {
+    match (limiter, rate) {
+        (Some(limiter), Some(rate)) => {
+            limiter.update_rate(rate.as_u64(), burst.unwrap_or(rate).as_u64());
+            true
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn limiter_traffic(limiter: &Option<SharedRateLimit>) -> u64 {
+    limiter.as_ref().map(|l| l.traffic()).unwrap_or(0)
+}
+
 impl TrafficControlCache {
     fn new() -> Self {
         Self {
             use_shared_memory: true,
             rules: Vec::new(),
             limiter_map: HashMap::new(),
+            connection_limiter_map: HashMap::new(),
+            closed_traffic_map: HashMap::new(),
+            peer_session_map: HashMap::new(),
             last_traffic_control_generation: 0,
             last_update: 0,
             use_utc: false,
@@ -188,11 +285,35 @@ impl TrafficControlCache {
             return;
         } // not enough data
 
+        self.remove_closed_connections();
+
+        // traffic of rules which are not shared
+        let mut connection_traffic = self.closed_traffic_map.clone();
+        let mut connection_count: HashMap<&str, u64> = HashMap::new();
+        for ((rule, _peer), (read_limit, write_limit)) in self.connection_limiter_map.iter() {
+            let traffic = connection_traffic.entry(rule.clone()).or_default();
+            traffic.0 += limiter_traffic(read_limit);
+            traffic.1 += limiter_traffic(write_limit);
+            *connection_count.entry(rule).or_default() += 1;
+        }
+
         let mut new_rate_map = HashMap::new();
 
         for (rule, (read_limit, write_limit)) in self.limiter_map.iter() {
-            let traffic_in = read_limit.as_ref().map(|l| l.traffic()).unwrap_or(0);
-            let traffic_out = write_limit.as_ref().map(|l| l.traffic()).unwrap_or(0);
+            let (connection_in, connection_out) =
+                connection_traffic.get(rule).copied().unwrap_or_default();
+            let traffic_in = limiter_traffic(read_limit) + connection_in;
+            let traffic_out = limiter_traffic(write_limit) + connection_out;
+
+            // each connection using a shared limiter holds a reference to it
+            let shared_connections = [read_limit, write_limit]
+                .iter()
+                .filter_map(|limit| limit.as_ref())
+                .map(|limit| Arc::strong_count(limit) as u64 - 1)
+                .max()
+                .unwrap_or(0);
+            let connections =
+                shared_connections + connection_count.get(rule.as_str()).copied().unwrap_or(0);
 
             let traffic_diff_in;
             let traffic_diff_out;
@@ -213,6 +334,7 @@ impl TrafficControlCache {
                 traffic_out,
                 rate_in: rate_in.try_into().unwrap_or(u64::MAX),
                 rate_out: rate_out.try_into().unwrap_or(u64::MAX),
+                connections,
             };
             new_rate_map.insert(rule.clone(), stat);
         }
@@ -222,6 +344,56 @@ impl TrafficControlCache {
         self.last_rate_compute = Instant::now()
     }
 
+    // Remove the limiters of connections which are closed, i.e. only referenced by the cache.
+    fn remove_closed_connections(&mut self) {
+        let closed_traffic_map = &mut self.closed_traffic_map;
+
+        self.connection_limiter_map
+            .retain(|(rule, _peer), (read_limit, write_limit)| {
+                let in_use = [&*read_limit, &*write_limit]
+                    .iter()
+                    .any(|limit| matches!(limit, Some(limit) if Arc::strong_count(limit) > 1));
+                if !in_use {
+                    let traffic = closed_traffic_map.entry(rule.clone()).or_default();
+                    traffic.0 += limiter_traffic(read_limit);
+                    traffic.1 += limiter_traffic(write_limit);
+                }
+                in_use
+            });
+    }
+
+    // Apply configuration changes to the limiters of rules which are not shared. Limiters
+    // which cannot be updated in place are removed and recreated on the next lookup.
+    fn update_connection_limiters(&mut self) {
+        let rules = &self.rules;
+        let closed_traffic_map = &mut self.closed_traffic_map;
+
+        self.connection_limiter_map
+            .retain(|(name, _peer), (read_limit, write_limit)| {
+                let keep = match rules.iter().find(|rule| &rule.config.name == name) {
+                    Some(rule) if !rule.config.shared.unwrap_or(true) => {
+                        let limit = &rule.config.limit;
+                        update_connection_limiter(read_limit, limit.rate_in, limit.burst_in)
+                            && update_connection_limiter(
+                                write_limit,
+                                limit.rate_out,
+                                limit.burst_out,
+                            )
+                    }
+                    _ => false,
+                };
+                if !keep {
+                    let traffic = closed_traffic_map.entry(name.clone()).or_default();
+                    traffic.0 += limiter_traffic(read_limit);
+                    traffic.1 += limiter_traffic(write_limit);
+                }
+                keep
+            });
+
+        self.closed_traffic_map
+            .retain(|name, _| rules.iter().any(|rule| &rule.config.name == name));
+    }
+
     /// Returns current [TrafficStat] for each configured rule.
     pub fn current_rate_map(&self) -> &HashMap<String, TrafficStat> {
         &self.current_rate_map
@@ -236,69 +408,89 @@ impl TrafficControlCache {
         let mut active_rules = Vec::new();
 
         for rule in rules {
+            let mut timeframe = Vec::new();
+            let mut invalid_timeframe = false;
+
+            for timeframe_str in rule.timeframe.iter().flatten() {
+                match timeframe_str.parse() {
+                    Ok(parsed) => timeframe.push(parsed),
+                    Err(err) => {
+                        log::error!(
+                            "skipping traffic control rule '{}' - invalid timeframe '{}' - {}",
+                            rule.name,
+                            timeframe_str,
+                            err
+                        );
+                        invalid_timeframe = true;
+                        break;
+                    }
+                }
+            }
+
+            // one broken rule must not disable all the others
+            if invalid_timeframe {
+                continue;
+            }
+
             let entry = self
                 .limiter_map
                 .entry(rule.name.clone())
                 .or_insert((None, None));
             let limit = &rule.limit;
 
-            match entry.0 {
-                Some(ref read_limiter) => match limit.rate_in {
-                    Some(rate_in) => {
-                        read_limiter.update_rate(
-                            rate_in.as_u64(),
-                            limit.burst_in.unwrap_or(rate_in).as_u64(),
-                        );
-                    }
-                    None => entry.0 = None,
-                },
-                None => {
-                    if let Some(rate_in) = limit.rate_in {
-                        let name = format!("{}.in", rule.name);
-                        let limiter = create_limiter(
-                            self.use_shared_memory,
-                            &name,
-                            rate_in.as_u64(),
-                            limit.burst_in.unwrap_or(rate_in).as_u64(),
-                        )?;
-                        entry.0 = Some(limiter);
+            if !rule.shared.unwrap_or(true) {
+                // limiters are created per connection on lookup
+                *entry = (None, None);
+            } else {
+                match entry.0 {
+                    Some(ref read_limiter) => match limit.rate_in {
+                        Some(rate_in) => {
+                            read_limiter.update_rate(
+                                rate_in.as_u64(),
+                                limit.burst_in.unwrap_or(rate_in).as_u64(),
+                            );
+                        }
+                        None => entry.0 = None,
+                    },
+                    None => {
+                        if let Some(rate_in) = limit.rate_in {
+                            let name = format!("{}.in", rule.name);
+                            let limiter = create_limiter(
+                                self.use_shared_memory,
+                                &name,
+                                rate_in.as_u64(),
+                                limit.burst_in.unwrap_or(rate_in).as_u64(),
+                            )?;
+                            entry.0 = Some(limiter);
+                        }
                     }
                 }
-            }
 
-            match entry.1 {
-                Some(ref write_limiter) => match limit.rate_out {
-                    Some(rate_out) => {
-                        write_limiter.update_rate(
-                            rate_out.as_u64(),
-                            limit.burst_out.unwrap_or(rate_out).as_u64(),
-                        );
-                    }
-                    None => entry.1 = None,
-                },
-                None => {
-                    if let Some(rate_out) = limit.rate_out {
-                        let name = format!("{}.out", rule.name);
-                        let limiter = create_limiter(
-                            self.use_shared_memory,
-                            &name,
-                            rate_out.as_u64(),
-                            limit.burst_out.unwrap_or(rate_out).as_u64(),
-                        )?;
-                        entry.1 = Some(limiter);
+                match entry.1 {
+                    Some(ref write_limiter) => match limit.rate_out {
+                        Some(rate_out) => {
+                            write_limiter.update_rate(
+                                rate_out.as_u64(),
+                                limit.burst_out.unwrap_or(rate_out).as_u64(),
+                            );
+                        }
+                        None => entry.1 = None,
+                    },
+                    None => {
+                        if let Some(rate_out) = limit.rate_out {
+                            let name = format!("{}.out", rule.name);
+                            let limiter = create_limiter(
+                                self.use_shared_memory,
+                                &name,
+                                rate_out.as_u64(),
+                                limit.burst_out.unwrap_or(rate_out).as_u64(),
+                            )?;
+                            entry.1 = Some(limiter);
+                        }
                     }
                 }
             }
 
-            let mut timeframe = Vec::new();
-
-            if let Some(ref timefram_list) = rule.timeframe {
-                for duration_str in timefram_list {
-                    let duration = parse_daily_duration(duration_str)?;
-                    timeframe.push(duration);
-                }
-            }
-
             let mut networks = Vec::new();
 
             for network in rule.network.iter() {
@@ -312,27 +504,44 @@ impl TrafficControlCache {
                 networks.push(cidr);
             }
 
+            let datastores = rule.datastore.clone().unwrap_or_default();
+            let auth_ids = rule.auth_id.clone().unwrap_or_default();
+
             active_rules.push(ParsedTcRule {
                 config: rule,
                 networks,
                 timeframe,
+                datastores,
+                auth_ids,
             });
         }
 
         self.rules = active_rules;
 
+        self.update_connection_limiters();
+
         Ok(())
     }
 
     /// Returns the rate limiter (if any) for the specified peer address.
     ///
     /// - Rules where timeframe does not match are skipped.
+    /// - Rules limited to datastores are skipped, unless the peer
+    ///   accesses one of them.
+    /// - Rules limited to users or API tokens are skipped, unless
+    ///   the peer authenticated as one of them.
+    /// - Rules matching the user or API token have higher priority,
+    ///   an exact match of an API token wins over a match of its user.
+    /// - Rules matching the accessed datastore have higher priority.
     /// - Rules with smaller network size have higher priority.
     ///
     /// Behavior is undefined if more than one rule matches after
     /// above selection.
+    ///
+    /// Rules which are not shared return a separate rate limiter for
+    /// each connection.
     pub fn lookup_rate_limiter(
-        &self,
+        &mut self,
         peer: SocketAddr,
         now: i64,
     ) -> (&str, Option<SharedRateLimit>, Option<SharedRateLimit>) {
@@ -340,45 +549,74 @@ impl TrafficControlCache {
 
         log::debug!("lookup_rate_limiter: {:?}", peer_ip);
 
-        let now = match TmEditor::with_epoch(now, self.use_utc) {
-            Ok(now) => now,
-            Err(err) => {
-                log::error!("lookup_rate_limiter: TmEditor::with_epoch failed - {}", err);
-                return ("", None, None);
-            }
-        };
+        let session = self.peer_session_map.get(&peer);
 
         let mut last_rule_match = None;
 
         for rule in self.rules.iter() {
-            if !timeframe_match(&rule.timeframe, &now) {
+            if !timeframe_match(&rule.timeframe, now, self.use_utc) {
+                continue;
+            }
+
+            let datastore_match = !rule.datastores.is_empty();
+            if datastore_match
+                && !matches!(session, Some(session) if rule.datastores.contains(&session.datastore))
+            {
                 continue;
             }
 
+            let auth_id_priority = if rule.auth_ids.is_empty() {
+                0
+            } else {
+                match session.and_then(|session| auth_id_match(&rule.auth_ids, &session.auth_id)) {
+                    Some(priority) => priority,
+                    None => continue,
+                }
+            };
+
             if let Some(match_len) = network_match_len(&rule.networks, &peer_ip) {
+                let priority = (auth_id_priority, datastore_match, match_len);
                 match last_rule_match {
-                    None => last_rule_match = Some((rule, match_len)),
-                    Some((_, last_len)) => {
-                        if match_len > last_len {
-                            last_rule_match = Some((rule, match_len));
+                    None => last_rule_match = Some((rule, priority)),
+                    Some((_, last_priority)) => {
+                        if priority > last_priority {
+                            last_rule_match = Some((rule, priority));
                         }
                     }
                 }
             }
         }
 
-        match last_rule_match {
-            Some((rule, _)) => {
-                match self.limiter_map.get(&rule.config.name) {
-                    Some((read_limiter, write_limiter)) => (
-                        &rule.config.name,
-                        read_limiter.clone(),
-                        write_limiter.clone(),
-                    ),
-                    None => ("", None, None), // should never happen
-                }
-            }
-            None => ("", None, None),
+        let rule = match last_rule_match {
+            Some((rule, _)) => rule,
+            None => return ("", None, None),
+        };
+
+        if !rule.config.shared.unwrap_or(true) {
+            let limit = &rule.config.limit;
+            let (read_limiter, write_limiter) = self
+                .connection_limiter_map
+                .entry((rule.config.name.clone(), peer))
+                .or_insert_with(|| {
+                    (
+                        create_connection_limiter(limit.rate_in, limit.burst_in),
+                        create_connection_limiter(limit.rate_out, limit.burst_out),
+                    )
+                });
+            return (
+                &rule.config.name,
+                read_limiter.clone(),
+                write_limiter.clone(),
+            );
+        }
+
+        match self.limiter_map.get(&rule.config.name) {
+            Some((read_limiter, write_limiter)) => (
+                &rule.config.name,
+                read_limiter.clone(),
+                write_limiter.clone(),
+            ),
+            None => ("", None, None), // should never happen
         }
     }
 }
@@ -387,6 +625,8 @@ impl TrafficControlCache {
 mod test {
     use super::*;
 
+    use pbs_api_types::TimeframeTimezone;
+
     const fn make_test_time(mday: i32, hour: i32, min: i32) -> i64 {
         (mday * 3600 * 24 + hour * 3600 + min * 60) as i64
     }
@@ -419,6 +659,119 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_timeframe_parse() -> Result<(), Error> {
+        const MON: u32 = 1;
+        const FRI: u32 = 5;
+        const SAT: u32 = 6;
+
+        let tf: TrafficControlTimeframe = "mon..fri 8-17".parse()?;
+        assert_eq!(tf.timezone(), TimeframeTimezone::Local);
+        assert!(tf.time_match(MON, 8 * 60));
+        assert!(!tf.time_match(MON, 17 * 60));
+        assert!(!tf.time_match(SAT, 12 * 60));
+
+        // crosses midnight
+        let tf: TrafficControlTimeframe = "fri 22-6".parse()?;
+        assert!(tf.time_match(FRI, 23 * 60));
+        assert!(tf.time_match(SAT, 5 * 60 + 59));
+        assert!(!tf.time_match(SAT, 23 * 60));
+        assert!(!tf.time_match(FRI, 5 * 60));
+
+        let tf: TrafficControlTimeframe = "sat..sun 14:30-18".parse()?;
+        assert!(tf.time_match(SAT, 15 * 60));
+        assert!(!tf.time_match(FRI, 15 * 60));
+
+        let tf: TrafficControlTimeframe = "8-12 utc".parse()?;
+        assert_eq!(tf.timezone(), TimeframeTimezone::Offset(0));
+
+        // thursday 16:00 UTC is 08:00 at -08:00
+        let tf: TrafficControlTimeframe = "thu 8-17 -08:00".parse()?;
+        assert_eq!(tf.timezone(), TimeframeTimezone::Offset(-8 * 3600));
+        assert!(tf.epoch_match(make_test_time(0, 16, 0), false)?);
+        assert!(!tf.epoch_match(make_test_time(0, 15, 59), false)?);
+
+        let tf: TrafficControlTimeframe = "mon..fri 9-17 +05:30".parse()?;
+        assert_eq!(tf.timezone(), TimeframeTimezone::Offset(5 * 3600 + 30 * 60));
+
+        assert!("8-8".parse::<TrafficControlTimeframe>().is_err());
+        assert!("24-8".parse::<TrafficControlTimeframe>().is_err());
+        assert!("mon..foo 8-12".parse::<TrafficControlTimeframe>().is_err());
+        assert!("8-12 Europe/Vienna"
+            .parse::<TrafficControlTimeframe>()
+            .is_err());
+        assert!("mon 8-12 utc extra"
+            .parse::<TrafficControlTimeframe>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeframe_compat() -> Result<(), Error> {
+        // timeframes accepted by the daily duration parser keep their meaning
+        for timeframe in [
+            "8-18",
+            "MON..FRI 8-18",
+            "mon..fri 8-12",
+            "mon,wed,fri 7:30-18:45",
+            "Sat,sun 0:00-23:59",
+            "tue..thu 14:30-18",
+        ] {
+            let tf: TrafficControlTimeframe = timeframe.parse()?;
+            let duration = proxmox_time::parse_daily_duration(&timeframe.to_lowercase())?;
+
+            for day in 0..7 {
+                for minute in (0..24 * 60).step_by(15) {
+                    // 1970-01-04 was a sunday
+                    let epoch = make_test_time(3 + day, minute / 60, minute % 60);
+                    assert_eq!(
+                        tf.epoch_match(epoch, true)?,
+                        duration.time_match(epoch, true)?,
+                        "timeframe '{}' differs at {}",
+                        timeframe,
+                        epoch
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_rule_skipped() -> Result<(), Error> {
+        let config_data = "
+rule: valid
+	network 10.0.0.0/8
+	rate-in 10000000
+	rate-out 10000000
+";
+        let mut config = pbs_config::traffic_control::CONFIG.parse("testconfig", config_data)?;
+        // for example written by an older version with a different timeframe syntax
+        config.set_data(
+            "broken",
+            "rule",
+            serde_json::json!({
+                "name": "broken",
+                "network": ["10.1.0.0/16"],
+                "timeframe": ["someday 8-12"],
+            }),
+        )?;
+
+        let mut cache = TrafficControlCache::new();
+        cache.use_utc = true;
+        cache.use_shared_memory = false; // avoid permission problems in test environment
+
+        cache.update_config(&config)?;
+
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 1, 1, 1)), 1234);
+        let (rule, _, _) = cache.lookup_rate_limiter(remote, make_test_time(0, 10, 0));
+        assert_eq!(rule, "valid");
+
+        Ok(())
+    }
+
     #[test]
     fn test_rule_match() -> Result<(), Error> {
         let config_data = "
@@ -441,6 +794,37 @@ rule: somewhere
 	network 0.0.0.0/0
 	rate-in 100000000
 	rate-out 100000000
+
+rule: replication
+	network 0.0.0.0/0
+	datastore store1
+	rate-in 10000000
+	rate-out 10000000
+
+rule: noisy-token
+	network 0.0.0.0/0
+	auth-id automation@pbs!noisy
+	rate-in 1000000
+	rate-out 1000000
+
+rule: automation
+	network 0.0.0.0/0
+	auth-id automation@pbs
+	rate-in 5000000
+	rate-out 5000000
+
+rule: per-connection
+	network 172.16.0.0/12
+	shared false
+	rate-in 10000000
+	rate-out 10000000
+
+rule: regions
+	network 10.0.0.0/8
+	rate-in 10000000
+	rate-out 10000000
+	timeframe mon..fri 8-17 +01:00
+	timeframe mon..fri 8-17 -05:00
 ";
         let config = pbs_config::traffic_control::CONFIG.parse("testconfig", config_data)?;
 
@@ -488,6 +872,69 @@ rule: somewhere
         assert!(read_limiter.is_some());
         assert!(write_limiter.is_some());
 
+        let session = |store: &str, auth_id: &str| PeerSession {
+            datastore: store.to_string(),
+            auth_id: auth_id.parse().unwrap(),
+        };
+
+        cache
+            .peer_session_map
+            .insert(gateway, session("store2", "backup@pbs"));
+        let (rule, _, _) = cache.lookup_rate_limiter(gateway, THURSDAY_15_00);
+        assert_eq!(rule, "rule1");
+
+        cache
+            .peer_session_map
+            .insert(gateway, session("store1", "backup@pbs"));
+        let (rule, read_limiter, write_limiter) =
+            cache.lookup_rate_limiter(gateway, THURSDAY_15_00);
+        assert_eq!(rule, "replication");
+        assert!(read_limiter.is_some());
+        assert!(write_limiter.is_some());
+
+        // user and token rules match regardless of network and datastore
+        cache
+            .peer_session_map
+            .insert(gateway, session("store1", "automation@pbs!noisy"));
+        let (rule, _, _) = cache.lookup_rate_limiter(gateway, THURSDAY_15_00);
+        assert_eq!(rule, "noisy-token");
+        cache
+            .peer_session_map
+            .insert(gateway, session("store1", "automation@pbs!other"));
+        let (rule, _, _) = cache.lookup_rate_limiter(gateway, THURSDAY_15_00);
+        assert_eq!(rule, "automation");
+        cache.peer_session_map.remove(&gateway);
+
+        // business hours in either region
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 1, 1, 1)), 1234);
+        let (rule, _, _) = cache.lookup_rate_limiter(remote, make_test_time(0, 8, 0));
+        assert_eq!(rule, "regions");
+        let (rule, _, _) = cache.lookup_rate_limiter(remote, make_test_time(0, 21, 0));
+        assert_eq!(rule, "regions");
+        let (rule, _, _) = cache.lookup_rate_limiter(remote, make_test_time(0, 23, 0));
+        assert_eq!(rule, "somewhere");
+
+        // rules which are not shared use a limiter per connection
+        let conn1 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(172, 16, 1, 1)), 1234);
+        let conn2 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(172, 16, 1, 1)), 1235);
+        let (rule, read_limiter1, write_limiter1) =
+            cache.lookup_rate_limiter(conn1, THURSDAY_15_00);
+        assert_eq!(rule, "per-connection");
+        assert!(read_limiter1.is_some());
+        assert!(write_limiter1.is_some());
+        let (rule, _read_limiter2, _write_limiter2) =
+            cache.lookup_rate_limiter(conn2, THURSDAY_15_00);
+        assert_eq!(rule, "per-connection");
+        assert_eq!(cache.connection_limiter_map.len(), 2);
+        let (rule, _, _) = cache.lookup_rate_limiter(conn1, THURSDAY_19_00);
+        assert_eq!(rule, "per-connection");
+        assert_eq!(cache.connection_limiter_map.len(), 2);
+        assert!(cache.limiter_map["per-connection"].0.is_none());
+
+        drop((read_limiter1, write_limiter1));
+        cache.remove_closed_connections();
+        assert_eq!(cache.connection_limiter_map.len(), 1);
+
         Ok(())
     }
 }
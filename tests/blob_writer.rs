@@ -5,7 +5,8 @@ use std::sync::Arc;
 use anyhow::{bail, Error};
 use lazy_static::lazy_static;
 
-use pbs_datastore::{DataBlob, DataBlobReader, DataBlobWriter};
+use pbs_api_types::CryptCipher;
+use pbs_datastore::{ChunkDigestMismatch, DataBlob, DataBlobReader, DataBlobWriter};
 use pbs_tools::crypt_config::CryptConfig;
 
 lazy_static! {
@@ -22,6 +23,14 @@ lazy_static! {
         let key = [1u8; 32];
         Arc::new(CryptConfig::new(key).unwrap())
     };
+    static ref CHACHA_CRYPT_CONFIG: Arc<CryptConfig> = {
+        let key = [1u8; 32];
+        Arc::new(
+            CryptConfig::new(key)
+                .unwrap()
+                .with_cipher(CryptCipher::ChaCha20Poly1305),
+        )
+    };
     static ref TEST_DIGEST_PLAIN: [u8; 32] = [
         83, 154, 96, 195, 167, 204, 38, 142, 204, 224, 130, 201, 24, 71, 2, 188, 130, 155, 177, 6,
         162, 100, 61, 238, 38, 219, 63, 240, 191, 132, 87, 238
@@ -103,3 +112,42 @@ fn test_encrypted_compressed_blob_writer() -> Result<(), Error> {
 
     verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_ENC)
 }
+
+#[test]
+fn test_chacha_encrypted_blob_writer() -> Result<(), Error> {
+    let tmp = Cursor::new(Vec::<u8>::new());
+    let mut blob_writer = DataBlobWriter::new_encrypted(tmp, CHACHA_CRYPT_CONFIG.clone())?;
+    blob_writer.write_all(&TEST_DATA)?;
+
+    verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_ENC)
+}
+
+#[test]
+fn test_chacha_encrypted_compressed_blob_writer() -> Result<(), Error> {
+    let tmp = Cursor::new(Vec::<u8>::new());
+    let mut blob_writer =
+        DataBlobWriter::new_encrypted_compressed(tmp, CHACHA_CRYPT_CONFIG.clone())?;
+    blob_writer.write_all(&TEST_DATA)?;
+
+    verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_ENC)
+}
+
+#[test]
+fn test_blob_digest_mismatch() -> Result<(), Error> {
+    let tmp = Cursor::new(Vec::<u8>::new());
+    let mut blob_writer = DataBlobWriter::new_compressed(tmp)?;
+    blob_writer.write_all(&TEST_DATA)?;
+
+    let raw_data = blob_writer.finish()?.into_inner();
+    let blob = DataBlob::load_from_reader(&mut &raw_data[..])?;
+
+    let wrong_digest = [0u8; 32];
+    let err = match blob.decode(None, Some(&wrong_digest)) {
+        Ok(_) => bail!("decoding with a wrong digest succeeded"),
+        Err(err) => err,
+    };
+    match err.downcast_ref::<ChunkDigestMismatch>() {
+        Some(mismatch) if mismatch.computed == *TEST_DIGEST_PLAIN => Ok(()),
+        _ => bail!("unexpected error - {}", err),
+    }
+}
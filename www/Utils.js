@@ -300,7 +300,11 @@ Ext.define('PBS.Utils', {
 	}
 
 	let duration = Proxmox.Utils.format_duration_human(timespan);
-	return Ext.String.format(gettext("in {0}"), duration);
+	let text = Ext.String.format(gettext("in {0}"), duration);
+	if (timespan < 30*24*60*60) {
+	    return `<i class="fa fa-exclamation-triangle warning"></i> ${text}`;
+	}
+	return text;
     },
 
     // FIXME: depreacted by Proxmox.Utils.render_size_usage ?!
@@ -394,8 +398,11 @@ Ext.define('PBS.Utils', {
 	    backup: (type, id) => PBS.Utils.render_datastore_worker_id(id, gettext('Backup')),
 	    'barcode-label-media': [gettext('Drive'), gettext('Barcode-Label Media')],
 	    'catalog-media': [gettext('Drive'), gettext('Catalog Media')],
+	    'chunkstore-check': [gettext('Datastore'), gettext('Check Chunks')],
+	    'copy-snapshot': ['Snapshot', gettext('Copy')],
 	    'delete-datastore': [gettext('Datastore'), gettext('Remove Datastore')],
 	    'delete-namespace': [gettext('Namespace'), gettext('Remove Namespace')],
+	    digest: [null, gettext('Digest Email')],
 	    dircreate: [gettext('Directory Storage'), gettext('Create')],
 	    dirremove: [gettext('Directory'), gettext('Remove')],
 	    'eject-media': [gettext('Drive'), gettext('Eject Media')],
@@ -406,10 +413,14 @@ Ext.define('PBS.Utils', {
 	    'label-media': [gettext('Drive'), gettext('Label Media')],
 	    'load-media': (type, id) => PBS.Utils.render_drive_load_media_id(id, gettext('Load Media')),
 	    logrotate: [null, gettext('Log Rotation')],
+	    'move-snapshot': ['Snapshot', gettext('Move')],
 	    prune: (type, id) => PBS.Utils.render_datastore_worker_id(id, gettext('Prune')),
 	    prunejob: (type, id) => PBS.Utils.render_prune_job_worker_id(id, gettext('Prune Job')),
+	    'recompress-chunks': [gettext('Datastore'), gettext('Recompress Chunks')],
+	    'restore-backend': [gettext('Datastore'), gettext('Restore from Backend')],
 	    reader: (type, id) => PBS.Utils.render_datastore_worker_id(id, gettext('Read Objects')),
 	    'rewind-media': [gettext('Drive'), gettext('Rewind Media')],
+	    'standby-push': [gettext('Remote'), gettext('Replicate Configuration')],
 	    sync: ['Datastore', gettext('Remote Sync')],
 	    syncjob: [gettext('Sync Job'), gettext('Remote Sync')],
 	    'tape-backup': (type, id) => PBS.Utils.render_tape_backup_id(id, gettext('Tape Backup')),
@@ -421,6 +432,7 @@ Ext.define('PBS.Utils', {
 	    verify_group: ['Group', gettext('Verification')],
 	    verify_snapshot: ['Snapshot', gettext('Verification')],
 	    zfscreate: [gettext('ZFS Storage'), gettext('Create')],
+	    zfsreplace: [gettext('ZFS Storage'), gettext('Replace Disk')],
 	});
 
 	Proxmox.Schema.overrideAuthDomains({
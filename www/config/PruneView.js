@@ -1,7 +1,7 @@
 Ext.define('pbs-prune-jobs-status', {
     extend: 'Ext.data.Model',
     fields: [
-	'id', 'disable', 'store', 'ns', 'max-depth', 'schedule',
+	'id', 'disable', 'store', 'ns', 'max-depth', 'group-filter', 'schedule',
 	'keep-last', 'keep-hourly', 'keep-daily', 'keep-weekly', 'keep-monthly', 'keep-yearly',
 	'next-run', 'last-run-upid', 'last-run-state', 'last-run-endtime',
 	{
@@ -200,6 +200,12 @@ Ext.define('PBS.config.PruneJobView', {
 	    width: 90,
 	    sortable: true,
 	},
+	{
+	    header: gettext('Backup Groups'),
+	    dataIndex: 'group-filter',
+	    renderer: v => v ? Ext.String.htmlEncode(v) : gettext('All'),
+	    width: 80,
+	},
 	{
 	    header: gettext('Schedule'),
 	    dataIndex: 'schedule',
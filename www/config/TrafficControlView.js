@@ -2,7 +2,7 @@ Ext.define('pmx-traffic-control', {
     extend: 'Ext.data.Model',
     fields: [
 	'name', 'rate-in', 'rate-out', 'burst-in', 'burst-out', 'network',
-	'timeframe', 'comment', 'cur-rate-in', 'cur-rate-out',
+	'timeframe', 'datastore', 'auth-id', 'comment', 'cur-rate-in', 'cur-rate-out',
 	{
 	    name: 'rateInUsed',
 	    calculate: d => Proxmox.Utils.size_unit_ratios(d['cur-rate-in'], d['rate-in']),
@@ -176,6 +176,20 @@ Ext.define('PBS.config.TrafficControlView', {
 	    renderer: Ext.String.htmlEncode,
 	    dataIndex: 'network',
 	},
+	{
+	    header: gettext('Datastores'),
+	    flex: 2,
+	    sortable: true,
+	    renderer: ds => ds ? Ext.String.htmlEncode(ds.join(', ')) : gettext('All'),
+	    dataIndex: 'datastore',
+	},
+	{
+	    header: gettext('Users/Tokens'),
+	    flex: 2,
+	    sortable: true,
+	    renderer: ids => ids ? Ext.String.htmlEncode(ids.join(', ')) : gettext('All'),
+	    dataIndex: 'auth-id',
+	},
 	{
 	    header: gettext('Timeframes'),
 	    sortable: false,
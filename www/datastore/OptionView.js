@@ -51,7 +51,26 @@ Ext.define('PBS.Datastore.Options', {
 		item: {
 		    id: datastore,
 		},
-		note: gettext('Configuration change only, no data will be deleted.'),
+		note: gettext('Only the configuration is removed, unless the data gets destroyed too.'),
+		params: {},
+		additionalItems: [
+		    {
+			xtype: 'proxmoxcheckbox',
+			name: 'destroy-data',
+			boxLabel: gettext('Destroy all data (cannot be undone!)'),
+			value: false,
+			listeners: {
+			    change: function(field, value) {
+				let win = field.up('proxmoxSafeDestroy');
+				if (value) {
+				    win.params['destroy-data'] = value;
+				} else {
+				    delete win.params['destroy-data'];
+				}
+			    },
+			},
+		    },
+		],
 		autoShow: true,
 		taskName: 'delete-datastore',
 		apiCallDone: (success) => {
@@ -110,7 +129,7 @@ Ext.define('PBS.Datastore.Options', {
 	    renderer: (value) => {
 		let notify = PBS.Utils.parsePropertyString(value);
 		let res = [];
-		for (const k of ['Verify', 'Sync', 'GC', 'Prune']) {
+		for (const k of ['Verify', 'Sync', 'GC', 'Prune', 'Tape']) {
 		    let v = Ext.String.capitalize(notify[k.toLowerCase()]) || 'Always';
 		    res.push(`${k}=${v}`);
 		}
@@ -147,6 +166,43 @@ Ext.define('PBS.Datastore.Options', {
 		},
 	    },
 	},
+	"trash-retention": {
+	    required: true,
+	    header: gettext('Trash Retention'),
+	    renderer: (v) => v ? `${v} ${gettext('days')}` : gettext('Disabled'),
+	    editor: {
+		xtype: 'proxmoxWindowEdit',
+		title: gettext('Trash Retention'),
+		width: 350,
+		items: {
+		    xtype: 'proxmoxintegerfield',
+		    name: 'trash-retention',
+		    fieldLabel: gettext('Days'),
+		    minValue: 1,
+		    emptyText: gettext('Disabled'),
+		    deleteEmpty: true,
+		},
+	    },
+	},
+	"quota": {
+	    required: true,
+	    header: gettext('Quota'),
+	    renderer: (v) => v || gettext('Unlimited'),
+	    editor: {
+		xtype: 'proxmoxWindowEdit',
+		title: gettext('Quota'),
+		width: 350,
+		items: {
+		    xtype: 'pmxSizeField',
+		    name: 'quota',
+		    fieldLabel: gettext('Quota'),
+		    unit: 'GiB',
+		    emptyText: gettext('Unlimited'),
+		    submitAutoScaledSizeUnit: true,
+		    deleteEmpty: true,
+		},
+	    },
+	},
 	"maintenance-mode": {
 	    required: true,
 	    header: gettext('Maintenance mode'),
@@ -8,6 +8,8 @@ Ext.define('pve-rrd-datastore', {
 	'write_ios',
 	'write_bytes',
 	'io_ticks',
+	'read_latency',
+	'write_latency',
 	{
 	    name: 'io_delay', calculate: function(data) {
 		let ios = 0;
@@ -35,6 +37,11 @@ Ext.define('PBS.DataStoreInfo', {
 	    usage: {},
 	    stillbad: 0,
 	    mountpoint: "",
+	    zpooltext: '',
+	    zpoolhealthy: true,
+	    gcrunning: false,
+	    gcprogress: 0,
+	    gcprogresstext: '',
 	},
     },
 
@@ -84,6 +91,30 @@ Ext.define('PBS.DataStoreInfo', {
 		vm.set('stillbad', gcstatus['still-bad']);
 	    }
 
+	    let gcprogress = gcstatus?.progress;
+	    if (gcprogress) {
+		let phase = gcprogress.phase === 'mark'
+		    ? gettext('Marking used chunks')
+		    : gettext('Removing unused chunks');
+		let text = `${phase}: ${gcprogress.percentage}%`;
+		if (gcprogress.eta !== undefined) {
+		    let eta = Proxmox.Utils.format_duration_human(gcprogress.eta);
+		    text += ` (${Ext.String.format(gettext('about {0} remaining'), eta)})`;
+		}
+		vm.set('gcprogress', gcprogress.percentage / 100);
+		vm.set('gcprogresstext', text);
+	    }
+	    vm.set('gcrunning', !!gcprogress);
+
+	    let zpool = store.getById('zpool')?.data.value;
+	    if (zpool) {
+		vm.set('zpooltext', `${zpool.pool}: ${zpool.health}`);
+		vm.set('zpoolhealthy', zpool.health === 'ONLINE');
+	    } else {
+		vm.set('zpooltext', '');
+		vm.set('zpoolhealthy', true);
+	    }
+
 	    vm.set('ctcount', countstext(counts.ct));
 	    vm.set('vmcount', countstext(counts.vm));
 	    vm.set('hostcount', countstext(counts.host));
@@ -126,6 +157,39 @@ Ext.define('PBS.DataStoreInfo', {
 		},
 	    },
 	},
+	{
+	    iconCls: 'fa fa-fw fa-th-large',
+	    title: gettext('ZFS Pool'),
+	    printBar: false,
+	    bind: {
+		data: {
+		    text: '{zpooltext}',
+		},
+		visible: '{zpooltext && zpoolhealthy}',
+	    },
+	},
+	{
+	    iconCls: 'fa critical fa-fw fa-exclamation-triangle',
+	    title: gettext('ZFS Pool'),
+	    printBar: false,
+	    bind: {
+		data: {
+		    text: '{zpooltext}',
+		},
+		visible: '{!zpoolhealthy}',
+	    },
+	},
+	{
+	    iconCls: 'fa fa-fw fa-trash-o',
+	    title: gettext('Garbage Collection'),
+	    bind: {
+		data: {
+		    usage: '{gcprogress}',
+		    text: '{gcprogresstext}',
+		},
+		visible: '{gcrunning}',
+	    },
+	},
 	{
 	    xtype: 'box',
 	    html: `<b>${gettext('Backup Count')}</b>`,
@@ -259,6 +323,14 @@ Ext.define('PBS.DataStoreSummary', {
 	    fields: ['io_delay'],
 	    fieldTitles: [gettext('IO Delay')],
 	},
+	{
+	    xtype: 'proxmoxRRDChart',
+	    itemId: 'latencyChart',
+	    hidden: true,
+	    title: gettext('Latency (ms)'),
+	    fields: ['read_latency', 'write_latency'],
+	    fieldTitles: [gettext('Read'), gettext('Write')],
+	},
     ],
 
     listeners: {
@@ -312,6 +384,8 @@ Ext.define('PBS.DataStoreSummary', {
 	me.mon(me.rrdstore, 'load', function(store, records, success) {
 	    let hasIoTicks = records?.some((rec) => rec?.data?.io_ticks !== undefined);
 	    me.down('#ioDelayChart').setVisible(!success || hasIoTicks);
+	    let hasLatency = records?.some((rec) => rec?.data?.read_latency !== undefined);
+	    me.down('#latencyChart').setVisible(hasLatency);
 	}, undefined, { single: true });
 
 	me.query('proxmoxRRDChart').forEach((chart) => {
@@ -323,6 +323,12 @@ Ext.define('PBS.TapeManagement.DriveStatusGrid', {
     title: gettext('Status'),
 
     rows: {
+	'label-text': {
+	    header: gettext('Label'),
+	},
+	'pool': {
+	    header: gettext('Pool'),
+	},
 	'density': {
 	    required: true,
 	    header: gettext('Tape Density'),
@@ -348,6 +354,9 @@ Ext.define('PBS.TapeManagement.DriveStatusGrid', {
 	    header: gettext('Compression'),
 	    renderer: Proxmox.Utils.format_boolean,
 	},
+	'encryption': {
+	    header: gettext('Encryption'),
+	},
 	'file-number': {
 	    header: gettext('Tape Position'),
 	    renderer: function(value, mD, r, rI, cI, store) {
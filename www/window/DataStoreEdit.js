@@ -99,6 +99,77 @@ Ext.define('PBS.DataStoreEdit', {
 		},
 		onlineHelp: 'backup_pruning',
 	    },
+	    {
+		title: gettext('Backend'),
+		xtype: 'inputpanel',
+		onlineHelp: 'storage_datastore_s3',
+		column1: [
+		    {
+			xtype: 'proxmoxKVComboBox',
+			name: 'backend',
+			fieldLabel: gettext('Backend'),
+			comboItems: [
+			    ['__default__', Proxmox.Utils.defaultText + ' (' + gettext('Filesystem') + ')'],
+			    ['filesystem', gettext('Filesystem')],
+			    ['s3', 'S3'],
+			],
+			value: '__default__',
+			cbind: {
+			    disabled: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxtextfield',
+			name: 's3-endpoint',
+			fieldLabel: gettext('Endpoint'),
+			emptyText: 'https://s3.amazonaws.com',
+			cbind: {
+			    disabled: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxtextfield',
+			name: 's3-bucket',
+			fieldLabel: gettext('Bucket'),
+			cbind: {
+			    disabled: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxtextfield',
+			name: 's3-region',
+			fieldLabel: gettext('Region'),
+			emptyText: 'us-east-1',
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		],
+		column2: [
+		    {
+			xtype: 'proxmoxtextfield',
+			name: 's3-access-key',
+			fieldLabel: gettext('Access Key'),
+		    },
+		    {
+			xtype: 'proxmoxtextfield',
+			inputType: 'password',
+			name: 's3-secret-key',
+			fieldLabel: gettext('Secret Key'),
+			cbind: {
+			    emptyText: (get) => get('isCreate') ? '' : gettext('Unchanged'),
+			},
+		    },
+		    {
+			xtype: 'proxmoxcheckbox',
+			name: 's3-path-style',
+			fieldLabel: gettext('Path Style'),
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		],
+	    },
 	],
     },
 });
@@ -36,7 +36,7 @@ Ext.define('PBS.window.NotifyOptions', {
 	xtype: 'inputpanel',
 	onGetValues: function(values) {
 	    let notify = {};
-	    for (const k of ['verify', 'sync', 'gc', 'prune']) {
+	    for (const k of ['verify', 'sync', 'gc', 'prune', 'tape']) {
 		notify[k] = values[k];
 		delete values[k];
 	    }
@@ -86,6 +86,13 @@ Ext.define('PBS.window.NotifyOptions', {
 		value: '__default__',
 		deleteEmpty: false,
 	    },
+	    {
+		xtype: 'pbsNotifyType',
+		name: 'tape',
+		fieldLabel: gettext('Tape Backup Jobs'),
+		value: '__default__',
+		deleteEmpty: false,
+	    },
 	],
     },
     setValues: function(values) {
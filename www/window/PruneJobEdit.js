@@ -13,6 +13,8 @@ Ext.define('PBS.window.PruneJobEdit', {
 
     defaultFocus: 'proxmoxtextfield[name=comment]',
 
+    bodyPadding: 0,
+
     cbindData: function(initialConfig) {
 	let me = this;
 
@@ -40,121 +42,159 @@ Ext.define('PBS.window.PruneJobEdit', {
 	    let view = this.getView();
 	    let nsSelector = view.down('pbsNamespaceSelector[name=ns]');
 	    nsSelector.setDatastore(value);
+	    view.down('pbsGroupFilter').setLocalDatastore(value);
 	},
-    },
 
-    items: {
-	xtype: 'inputpanel',
-	onGetValues: function(values) {
-	    let me = this;
-
-	    if (!values.id && me.up('pbsPruneJobEdit').isCreate) {
-		values.id = 's-' + Ext.data.identifier.Uuid.Global.generate().slice(0, 13);
-	    }
-	    if (!me.isCreate) {
-		if (typeof values.delete === 'string') {
-		    values.delete = values.delete.split(',');
-		}
+	init: function(view) {
+	    if (view.datastore) {
+		view.down('pbsGroupFilter').setLocalDatastore(view.datastore);
 	    }
-	    values.disable = !values.enable;
-	    delete values.enable;
-
-	    return values;
 	},
-	onSetValues: function(values) {
-	    let me = this;
-	    values.enable = !values.disable;
-	    delete values.disable;
+    },
 
-	    return values;
-	},
-	column1: [
-	    {
-		xtype: 'pmxDisplayEditField',
-		fieldLabel: gettext('Datastore'),
-		name: 'store',
-		submitValue: true,
-		cbind: {
-		    editable: '{editDatastore}',
-		    value: '{datastore}',
-		},
-		editConfig: {
-		    xtype: 'pbsDataStoreSelector',
-		    allowBlank: false,
-		},
-	    },
+    items: {
+	xtype: 'tabpanel',
+	bodyPadding: 10,
+	border: 0,
+	items: [
 	    {
-		xtype: 'pbsNamespaceSelector',
-		fieldLabel: gettext('Namespace'),
-		name: 'ns',
-		cbind: {
-		    datastore: '{datastore}',
-		},
-		listeners: {
-		    change: function(field, localNs) {
-			let me = this;
-			let view = me.up('pbsPruneJobEdit');
-
-			let maxDepthField = view.down('field[name=max-depth]');
-			maxDepthField.setLimit(localNs);
-			maxDepthField.validate();
-		    },
+		title: gettext('Options'),
+		xtype: 'inputpanel',
+		onGetValues: function(values) {
+		    let me = this;
+
+		    if (!values.id && me.up('pbsPruneJobEdit').isCreate) {
+			values.id = 's-' + Ext.data.identifier.Uuid.Global.generate().slice(0, 13);
+		    }
+		    if (!me.isCreate) {
+			if (typeof values.delete === 'string') {
+			    values.delete = values.delete.split(',');
+			}
+		    }
+		    values.disable = !values.enable;
+		    delete values.enable;
+
+		    return values;
 		},
-	    },
-	    {
-		xtype: 'pbsNamespaceMaxDepthReduced',
-		name: 'max-depth',
-		fieldLabel: gettext('Max. Depth'),
-		deleteEmpty: true,
-	    },
-	],
+		onSetValues: function(values) {
+		    let me = this;
+		    values.enable = !values.disable;
+		    delete values.disable;
 
-	column2: [
-	    {
-		fieldLabel: gettext('Prune Schedule'),
-		xtype: 'pbsCalendarEvent',
-		name: 'schedule',
-		emptyText: gettext('none (disabled)'),
-		cbind: {
-			deleteEmpty: '{!isCreate}',
-			value: '{scheduleValue}',
+		    return values;
 		},
-	    },
-	    {
-		xtype: 'proxmoxcheckbox',
-		fieldLabel: gettext('Enabled'),
-		name: 'enable',
-		uncheckedValue: 0,
-		defaultValue: 1,
-		checked: true,
-	    },
-	],
+		column1: [
+		    {
+			xtype: 'pmxDisplayEditField',
+			fieldLabel: gettext('Datastore'),
+			name: 'store',
+			submitValue: true,
+			cbind: {
+			    editable: '{editDatastore}',
+			    value: '{datastore}',
+			},
+			editConfig: {
+			    xtype: 'pbsDataStoreSelector',
+			    allowBlank: false,
+			},
+		    },
+		    {
+			xtype: 'pbsNamespaceSelector',
+			fieldLabel: gettext('Namespace'),
+			name: 'ns',
+			cbind: {
+			    datastore: '{datastore}',
+			},
+			listeners: {
+			    change: function(field, localNs) {
+				let me = this;
+				let view = me.up('pbsPruneJobEdit');
+
+				let maxDepthField = view.down('field[name=max-depth]');
+				maxDepthField.setLimit(localNs);
+				maxDepthField.validate();
+			    },
+			},
+		    },
+		    {
+			xtype: 'pbsNamespaceMaxDepthReduced',
+			name: 'max-depth',
+			fieldLabel: gettext('Max. Depth'),
+			deleteEmpty: true,
+		    },
+		],
+
+		column2: [
+		    {
+			fieldLabel: gettext('Prune Schedule'),
+			xtype: 'pbsCalendarEvent',
+			name: 'schedule',
+			emptyText: gettext('none (disabled)'),
+			cbind: {
+				deleteEmpty: '{!isCreate}',
+				value: '{scheduleValue}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxcheckbox',
+			fieldLabel: gettext('Enabled'),
+			name: 'enable',
+			uncheckedValue: 0,
+			defaultValue: 1,
+			checked: true,
+		    },
+		],
 
-	columnB: [
-	    {
-		xtype: 'pbsPruneInputPanel',
-		getValues: () => ({}), // let that handle our inputpanel here
-	    },
-	    {
-		fieldLabel: gettext('Comment'),
-		xtype: 'proxmoxtextfield',
-		name: 'comment',
-		cbind: {
-		    deleteEmpty: '{!isCreate}',
-		},
+		columnB: [
+		    {
+			xtype: 'pbsPruneInputPanel',
+			getValues: () => ({}), // let that handle our inputpanel here
+		    },
+		    {
+			fieldLabel: gettext('Comment'),
+			xtype: 'proxmoxtextfield',
+			name: 'comment',
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		],
+		advancedColumn1: [
+		    {
+			xtype: 'pmxDisplayEditField',
+			fieldLabel: gettext('Job ID'),
+			emptyText: gettext('Autogenerate'),
+			name: 'id',
+			allowBlank: true,
+			regex: PBS.Utils.SAFE_ID_RE,
+			cbind: {
+			    editable: '{isCreate}',
+			},
+		    },
+		],
 	    },
-	],
-	advancedColumn1: [
 	    {
-		xtype: 'pmxDisplayEditField',
-		fieldLabel: gettext('Job ID'),
-		emptyText: gettext('Autogenerate'),
-		name: 'id',
-		allowBlank: true,
-		regex: PBS.Utils.SAFE_ID_RE,
-		cbind: {
-		    editable: '{isCreate}',
+		xtype: 'inputpanel',
+		onGetValues: function(values) {
+		    PBS.Utils.delete_if_default(values, 'group-filter');
+		    if (Ext.isArray(values['group-filter'])) {
+			if (values['group-filter'].length === 0) {
+			    delete values['group-filter'];
+			    values.delete = 'group-filter';
+			} else {
+			    // merge duplicates
+			    values['group-filter'] = [...new Set(values['group-filter'])];
+			}
+		    }
+		    return values;
 		},
+		title: gettext('Group Filter'),
+		items: [
+		    {
+			xtype: 'pbsGroupFilter',
+			name: 'group-filter',
+		    },
+		],
 	    },
 	],
     },
@@ -64,6 +64,7 @@ Ext.define('PBS.window.SyncJobEdit', {
 		    }
 		    if (!me.isCreate) {
 			PBS.Utils.delete_if_default(values, 'rate-in');
+			PBS.Utils.delete_if_default(values, 'rate-out');
 			if (typeof values.delete === 'string') {
 			    values.delete = values.delete.split(',');
 			}
@@ -126,11 +127,44 @@ Ext.define('PBS.window.SyncJobEdit', {
 		    {
 			xtype: 'pmxBandwidthField',
 			name: 'rate-in',
-			fieldLabel: gettext('Rate Limit'),
+			fieldLabel: gettext('Rate In'),
 			emptyText: gettext('Unlimited'),
 			submitAutoScaledSizeUnit: true,
 			// NOTE: handle deleteEmpty in onGetValues due to bandwidth field having a cbind too
 		    },
+		    {
+			xtype: 'pmxBandwidthField',
+			name: 'rate-out',
+			fieldLabel: gettext('Rate Out'),
+			emptyText: gettext('Unlimited'),
+			submitAutoScaledSizeUnit: true,
+		    },
+		    {
+			xtype: 'proxmoxintegerfield',
+			name: 'parallel-downloads',
+			fieldLabel: gettext('Parallel Downloads'),
+			minValue: 1,
+			maxValue: 256,
+			emptyText: '20',
+			submitEmpty: false,
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
+		    {
+			xtype: 'proxmoxKVComboBox',
+			name: 'sync-direction',
+			fieldLabel: gettext('Direction'),
+			comboItems: [
+			    ['__default__', Proxmox.Utils.defaultText + ' (' + gettext('Pull') + ')'],
+			    ['pull', gettext('Pull')],
+			    ['push', gettext('Push')],
+			],
+			value: '__default__',
+			cbind: {
+			    deleteEmpty: '{!isCreate}',
+			},
+		    },
 		],
 
 		column2: [
@@ -208,6 +242,17 @@ Ext.define('PBS.window.SyncJobEdit', {
 			uncheckedValue: false,
 			value: false,
 		    },
+		    {
+			fieldLabel: gettext('Verify synced'),
+			xtype: 'proxmoxcheckbox',
+			name: 'verify-after-sync',
+			autoEl: {
+			    tag: 'div',
+			    'data-qtip': gettext('Verify newly synced snapshots and remove them again if the verification fails.'),
+			},
+			uncheckedValue: false,
+			value: false,
+		    },
 		],
 
 		columnB: [
@@ -63,6 +63,19 @@ Ext.define('PBS.window.TrafficControlEdit', {
 	    me.updateTimeframeField();
 	},
 
+	timezoneChanged: function(field, value) {
+	    let me = this;
+	    let record = field.getWidgetRecord();
+	    if (record === undefined) {
+		// this is sometimes called before a record/column is initialized
+		return;
+	    }
+	    record.set('timezone', value);
+	    record.commit();
+
+	    me.updateTimeframeField();
+	},
+
 	addTimeframe: function() {
 	    let me = this;
 	    me.lookup('timeframes').getStore().add({
@@ -90,9 +103,12 @@ Ext.define('PBS.window.TrafficControlEdit', {
 		if (days.length < 7 && days.length > 0) {
 		    timeframe += days.join(',') + ' ';
 		}
-		let { start, end } = rec.data;
+		let { start, end, timezone } = rec.data;
 
 		timeframe += `${start}-${end}`;
+		if (timezone) {
+		    timeframe += ` ${timezone}`;
+		}
 		timeframes.push(timeframe);
 	    });
 
@@ -116,7 +132,8 @@ Ext.define('PBS.window.TrafficControlEdit', {
 
 	parseTimeframe: function(timeframe) {
 	    let me = this;
-	    let [, days, start, end] = /^(?:(\S*)\s+)?([0-9:]+)-([0-9:]+)$/.exec(timeframe) || [];
+	    let [, days, start, end, timezone] =
+		/^(?:([a-z.,]+)\s+)?([0-9:]+)-([0-9:]+)(?:\s+(\S+))?$/.exec(timeframe) || [];
 
 	    if (start === '0') {
 		start = "00:00";
@@ -125,6 +142,7 @@ Ext.define('PBS.window.TrafficControlEdit', {
 	    let record = {
 		start,
 		end,
+		timezone,
 	    };
 
 	    if (!days) {
@@ -179,6 +197,9 @@ Ext.define('PBS.window.TrafficControlEdit', {
 	    'grid timefield': {
 		change: 'timeChanged',
 	    },
+	    'grid textfield[isTimezone]': {
+		change: 'timezoneChanged',
+	    },
 	    'grid button': {
 		click: 'removeTimeFrame',
 	    },
@@ -200,6 +221,14 @@ Ext.define('PBS.window.TrafficControlEdit', {
 		values.network = [...new Set(values.network.split(/\s*,\s*/))];
 	    }
 
+	    if (values.datastore) {
+		values.datastore = [...new Set(values.datastore.split(/\s*,\s*/))];
+	    }
+
+	    if (values['auth-id']) {
+		values['auth-id'] = [...new Set(values['auth-id'].split(/\s*,\s*/))];
+	    }
+
 	    if ('timeframe' in values && !values.timeframe) {
 		delete values.timeframe;
 	    }
@@ -271,6 +300,21 @@ Ext.define('PBS.window.TrafficControlEdit', {
 		emptyText: gettext('Same as Rate'),
 		submitAutoScaledSizeUnit: true,
 	    },
+	    {
+		xtype: 'proxmoxcheckbox',
+		name: 'shared',
+		fieldLabel: gettext('Shared Limit'),
+		checked: true,
+		defaultValue: true,
+		deleteDefaultValue: true,
+		cbind: {
+		    deleteEmpty: '{!isCreate}',
+		},
+		autoEl: {
+		    tag: 'div',
+		    'data-qtip': gettext('Share the limit across all matching connections, otherwise it applies to each connection separately.'),
+		},
+	    },
 	],
 
 	columnB: [
@@ -284,6 +328,32 @@ Ext.define('PBS.window.TrafficControlEdit', {
 		    'data-qtip': gettext('A comma-separated list of networks to apply the (shared) limit.'),
 		},
 	    },
+	    {
+		xtype: 'proxmoxtextfield',
+		fieldLabel: gettext('Datastore(s)'),
+		name: 'datastore',
+		emptyText: gettext('Apply on all Datastores'),
+		cbind: {
+		    deleteEmpty: '{!isCreate}',
+		},
+		autoEl: {
+		    tag: 'div',
+		    'data-qtip': gettext('A comma-separated list of datastores, the rule then only applies to backup and restore sessions on them.'),
+		},
+	    },
+	    {
+		xtype: 'proxmoxtextfield',
+		fieldLabel: gettext('User(s)/Token(s)'),
+		name: 'auth-id',
+		emptyText: gettext('Apply on all Users'),
+		cbind: {
+		    deleteEmpty: '{!isCreate}',
+		},
+		autoEl: {
+		    tag: 'div',
+		    'data-qtip': gettext('A comma-separated list of users or API tokens, the rule then only applies to their backup and restore sessions. A user also matches its API tokens.'),
+		},
+	    },
 	    {
 		xtype: 'displayfield',
 		fieldLabel: gettext('Timeframes'),
@@ -300,7 +370,7 @@ Ext.define('PBS.window.TrafficControlEdit', {
 			    emptyText: gettext('Apply Always'),
 			},
 			store: {
-			    fields: ['start', 'end', 'mon', 'tue', 'wed', 'thu', 'fri', 'sat', 'sun'],
+			    fields: ['start', 'end', 'timezone', 'mon', 'tue', 'wed', 'thu', 'fri', 'sat', 'sun'],
 			    data: [],
 			},
 			columns: [
@@ -329,6 +399,20 @@ Ext.define('PBS.window.TrafficControlEdit', {
 				},
 				flex: 1,
 			    },
+			    {
+				text: gettext('Timezone'),
+				xtype: 'widgetcolumn',
+				dataIndex: 'timezone',
+				widget: {
+				    xtype: 'textfield',
+				    isFormField: false,
+				    isTimezone: true,
+				    emptyText: 'local',
+				    regex: /^(local|utc|UTC|[+-]\d{1,2}(:\d{2})?)$/,
+				    regexText: gettext("'local', 'utc' or an offset like '+01:00'"),
+				},
+				flex: 1,
+			    },
 			    {
 				text: gettext('Mon'),
 				xtype: 'widgetcolumn',